@@ -33,6 +33,7 @@ docker build \
   -t $DOCKER_IMG \
   -f "$DOCKERFILE" \
   --build-arg KERNEL_VERSION=${KERNEL_VERSION} \
+  --build-arg SNPGUEST_VERSION=${SNPGUEST_VERSION} \
   $SCRIPT_PATH/../../
 
 # Run the container. This will run and stop it immediately since it does nothing by default.
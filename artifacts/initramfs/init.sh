@@ -23,7 +23,8 @@ mkdir /dev/pts
 mount -t devpts -o noexec,nosuid,gid=5,mode=0620 devpts /dev/pts || true
 
 MNT_DIR=/root
-BOOT_PROOF_DIR=$MNT_DIR/var/lib/nilcc-boot
+# /var is an absolute symlink to the state disk so it can't be followed from here.
+BOOT_PROOF_DIR=$MNT_DIR/media/state/var/lib/nilcc-boot
 
 # Parse command line options
 for x in $(cat /proc/cmdline); do
@@ -67,26 +68,6 @@ veritysetup open $ROOT root $VERITY_DISK $VERITY_ROOT_HASH
 log "Mounting disk on $MNT_DIR"
 mount -o ro /dev/mapper/root $MNT_DIR
 
-# Generate a random password and use LUKS to encrypt the state disk with it.
-STATE_PASSWORD=$(head -c 64 /dev/random | base64 -w 0)
-log "Setting state disk $STATE_DISK"
-echo "$STATE_PASSWORD" | cryptsetup luksFormat "$STATE_DISK"
-
-# Now open the disk and format it using ext4
-echo "$STATE_PASSWORD" | cryptsetup luksOpen "$STATE_DISK" state
-mkfs.ext4 /dev/mapper/state
-unset STATE_PASSWORD
-
-# Mount the now encrypted and formatted state disk
-mount /dev/mapper/state "${MNT_DIR}/media/state"
-
-# Create the directory for /tmp
-mkdir -p "${MNT_DIR}/media/state/tmp"
-chmod 777 "${MNT_DIR}/media/state/tmp"
-
-# Now copy over the original /var into the new one.
-cp -r "${MNT_DIR}/ro/var" "${MNT_DIR}/media/state/"
-
 # Mount the ISO that contains the docker compose file into the path where cvm-agent will look it up.
 log "Validating docker compose file"
 mount -o loop "$DOCKER_COMPOSE_DISK" "$MNT_DIR/media/cvm-agent-entrypoint"
@@ -107,6 +88,79 @@ fi
 
 log "Docker compose hash matches expected one: ${ACTUAL_HASH}"
 
+# Derive the state disk key inside the CVM if the workload asked for a sealed state disk. The key is bound to this
+# CVM's measurement and policy, so the same key is derived every time a CVM with the same measurement boots and the
+# state disk can be unlocked across restarts. Otherwise, use a random key that's thrown away once the disk is opened.
+METADATA_PATH="${MNT_DIR}/media/cvm-agent-entrypoint/metadata.json"
+if [ "$(jq -r '.sealed_state_disk // false' "$METADATA_PATH")" = "true" ]; then
+  log "Deriving sealed key for state disk $STATE_DISK"
+  modprobe sev-guest
+  STATE_KEY_PATH=/tmp/state.key
+  snpguest key "$STATE_KEY_PATH" vcek --guest_field_select 001001
+
+  if cryptsetup isLuks "$STATE_DISK"; then
+    # Never format a disk that was already sealed: if it can't be unlocked, e.g. because the measurement changed, fail
+    # instead so its contents aren't destroyed.
+    if ! cryptsetup luksOpen --key-file "$STATE_KEY_PATH" "$STATE_DISK" state; then
+      rm -f "$STATE_KEY_PATH"
+      log "Failed to unlock sealed state disk $STATE_DISK"
+      exit 1
+    fi
+    log "Unlocked existing state disk"
+  else
+    log "Formatting state disk $STATE_DISK"
+    cryptsetup luksFormat --batch-mode --key-file "$STATE_KEY_PATH" "$STATE_DISK"
+    cryptsetup luksOpen --key-file "$STATE_KEY_PATH" "$STATE_DISK" state
+    mkfs.ext4 /dev/mapper/state
+    FORMAT_STATE_DISK=1
+  fi
+  rm -f "$STATE_KEY_PATH"
+else
+  # Generate a random password and use LUKS to encrypt the state disk with it.
+  STATE_PASSWORD=$(head -c 64 /dev/random | base64 -w 0)
+  log "Setting state disk $STATE_DISK"
+  echo "$STATE_PASSWORD" | cryptsetup luksFormat "$STATE_DISK"
+
+  # Now open the disk and format it using ext4
+  echo "$STATE_PASSWORD" | cryptsetup luksOpen "$STATE_DISK" state
+  mkfs.ext4 /dev/mapper/state
+  unset STATE_PASSWORD
+  FORMAT_STATE_DISK=1
+fi
+
+# Mount the now encrypted and formatted state disk
+mount /dev/mapper/state "${MNT_DIR}/media/state"
+
+# Create the directory for /tmp
+mkdir -p "${MNT_DIR}/media/state/tmp"
+chmod 777 "${MNT_DIR}/media/state/tmp"
+
+# Now copy over the original /var into the new one, unless it's a sealed state disk that already has it.
+if [ "${FORMAT_STATE_DISK}" = "1" ]; then
+  cp -r "${MNT_DIR}/ro/var" "${MNT_DIR}/media/state/"
+fi
+
+# Write the boot log so nilcc-attester can bind it into its reports. This has to happen after the state disk is
+# mounted and /var is copied into it.
+log "Writing boot log"
+VERITY_STATUS=$(veritysetup status root | awk '/status:/ { print $2 }')
+DOCKER_COMPOSE_DISK_HASH=$(sha256sum "$DOCKER_COMPOSE_DISK" | awk '{{ print $1 }}')
+mkdir -p "$BOOT_PROOF_DIR"
+cat >"${BOOT_PROOF_DIR}/boot-log.json" <<EOF
+{
+  "kernel_cmdline": "$(cat /proc/cmdline)",
+  "verity": { "root_hash": "${VERITY_ROOT_HASH}", "status": "${VERITY_STATUS}" },
+  "docker_compose_hash": "${ACTUAL_HASH}",
+  "mounts": [
+    {
+      "device": "${DOCKER_COMPOSE_DISK}",
+      "mount_point": "/media/cvm-agent-entrypoint",
+      "sha256": "${DOCKER_COMPOSE_DISK_HASH}"
+    }
+  ]
+}
+EOF
+
 if [ "${DEBUG_MODE}" = "1" ]; then
   tmp_dir="${MNT_DIR}/media/state/tmp"
   mkdir "${tmp_dir}/etc"
@@ -0,0 +1,18 @@
+#!/usr/bin/env bash
+# This script creates the detached signature for the artifacts metadata.
+
+set -euo pipefail
+
+if [ -z "${METADATA_SIGNING_KEY:-}" ]; then
+  echo "METADATA_SIGNING_KEY must contain the PEM encoded ed25519 private key to sign with"
+  exit 1
+fi
+
+SCRIPT_PATH=$(dirname $(realpath $0))
+METADATA="$SCRIPT_PATH/dist/metadata.json"
+KEY_FILE=$(mktemp)
+trap 'rm -f "$KEY_FILE"' EXIT
+
+echo "$METADATA_SIGNING_KEY" >"$KEY_FILE"
+openssl pkeyutl -sign -rawin -inkey "$KEY_FILE" -in "$METADATA" | xxd -p -c 64 >"$METADATA.sig"
+echo "Signed metadata with public key $(openssl pkey -in "$KEY_FILE" -pubout -outform DER | tail -c 32 | xxd -p -c 32)"
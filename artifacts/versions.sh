@@ -1 +1,2 @@
 export KERNEL_VERSION=6.8.0-85
+export SNPGUEST_VERSION=0.9.1
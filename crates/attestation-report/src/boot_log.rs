@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A log of what was verified and mounted while booting a CVM.
+///
+/// This is generated by the initrd and can be bound into a report's `report_data` by hashing its raw contents.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootLog {
+    /// The kernel command line the CVM was booted with.
+    pub kernel_cmdline: String,
+
+    /// The result of opening the root filesystem's dm-verity device.
+    pub verity: VerityResult,
+
+    /// The docker compose hash the workload's docker compose file was checked against, as hex.
+    pub docker_compose_hash: String,
+
+    /// The devices that were mounted during boot.
+    pub mounts: Vec<MountedDevice>,
+}
+
+impl BootLog {
+    /// Hash the raw contents of a boot log.
+    pub fn hash(raw: &[u8]) -> [u8; 32] {
+        Sha256::digest(raw).into()
+    }
+}
+
+/// The result of opening a dm-verity device.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerityResult {
+    /// The root hash the device was opened with, as hex.
+    pub root_hash: String,
+
+    /// The status reported by `veritysetup` once the device was opened, e.g. `verified`.
+    pub status: String,
+}
+
+/// A device that was mounted during boot.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MountedDevice {
+    /// The device path.
+    pub device: String,
+
+    /// The path the device was mounted on.
+    pub mount_point: String,
+
+    /// The sha256 hash of the device's contents, as hex.
+    pub sha256: String,
+}
@@ -0,0 +1,60 @@
+use crate::report_data::WorkloadIdentity;
+use serde::{Deserialize, Serialize};
+
+/// The issuer set in every identity token.
+pub const IDENTITY_TOKEN_ISSUER: &str = "nilcc";
+
+/// The JWT algorithm identity tokens are signed with.
+pub const IDENTITY_TOKEN_ALGORITHM: &str = "EdDSA";
+
+/// The header of an identity token.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityTokenHeader {
+    /// The signing algorithm, always [IDENTITY_TOKEN_ALGORITHM].
+    pub alg: String,
+
+    /// The token type, always `JWT`.
+    pub typ: String,
+}
+
+impl Default for IdentityTokenHeader {
+    fn default() -> Self {
+        Self { alg: IDENTITY_TOKEN_ALGORITHM.into(), typ: "JWT".into() }
+    }
+}
+
+/// The claims in an identity token.
+///
+/// Identity tokens are JWTs minted inside a CVM and signed with an Ed25519 key whose public part is bound into the
+/// CVM's attestation report, so they carry the identity of the workload running in it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityTokenClaims {
+    /// The issuer, always [IDENTITY_TOKEN_ISSUER].
+    pub iss: String,
+
+    /// The workload id.
+    pub sub: String,
+
+    /// The id of the agent running the workload.
+    pub agent_id: String,
+
+    /// The audience the token is meant for.
+    pub aud: String,
+
+    /// The unix timestamp when the token was issued.
+    pub iat: u64,
+
+    /// The unix timestamp before which the token must not be accepted, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<u64>,
+
+    /// The unix timestamp when the token expires.
+    pub exp: u64,
+}
+
+impl IdentityTokenClaims {
+    /// The identity of the workload this token was minted for.
+    pub fn workload_identity(&self) -> WorkloadIdentity {
+        WorkloadIdentity { workload_id: self.sub.clone(), agent_id: self.agent_id.clone() }
+    }
+}
@@ -1 +1,5 @@
+pub mod boot_log;
+pub mod identity_token;
+pub mod platform_claims;
+pub mod report_data;
 pub mod v2;
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{hex::Hex, serde_as};
+use sha2::{Digest, Sha256};
+
+/// The domain separator prepended to the claims before hashing them.
+const DOMAIN_SEPARATOR: &[u8] = b"nilcc-platform-claims-v1";
+
+/// Information about the platform a CVM runs on, as reported by the nilcc-agent that launched it.
+///
+/// These can't be measured by the CVM itself, so they're only as trustworthy as the agent that signs them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlatformClaims {
+    /// The workload id.
+    pub workload_id: String,
+
+    /// The id of the metal instance the CVM runs on.
+    pub metal_instance_id: String,
+
+    /// The region the metal instance is located in.
+    pub region: String,
+
+    /// The version of the agent that launched the CVM.
+    pub agent_version: String,
+}
+
+impl PlatformClaims {
+    /// The digest the agent signs.
+    ///
+    /// Every field is length prefixed so that different claims can't produce the same input.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(DOMAIN_SEPARATOR);
+        for field in [&self.workload_id, &self.metal_instance_id, &self.region, &self.agent_version] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// Platform claims along with the agent's signature over them.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedPlatformClaims {
+    /// The claims.
+    pub claims: PlatformClaims,
+
+    /// The agent's compressed secp256k1 public key.
+    #[serde_as(as = "Hex")]
+    pub public_key: Vec<u8>,
+
+    /// The compact ECDSA signature over the claims' digest.
+    #[serde_as(as = "Hex")]
+    pub signature: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_claims() -> PlatformClaims {
+        PlatformClaims {
+            workload_id: "workload".into(),
+            metal_instance_id: "instance".into(),
+            region: "us-east-1".into(),
+            agent_version: "1.0.0".into(),
+        }
+    }
+
+    #[test]
+    fn distinct_digests() {
+        let digest = make_claims().digest();
+        assert_ne!(digest, PlatformClaims { region: "eu-west-1".into(), ..make_claims() }.digest());
+        assert_ne!(
+            PlatformClaims { workload_id: "ab".into(), metal_instance_id: "c".into(), ..make_claims() }.digest(),
+            PlatformClaims { workload_id: "a".into(), metal_instance_id: "bc".into(), ..make_claims() }.digest()
+        );
+    }
+
+    #[test]
+    fn serde() {
+        let claims = SignedPlatformClaims { claims: make_claims(), public_key: vec![2, 171], signature: vec![1, 2, 3] };
+        let serialized = serde_json::to_string(&claims).expect("failed to serialize");
+        assert!(serialized.contains(r#""public_key":"02ab""#), "{serialized}");
+        let deserialized: SignedPlatformClaims = serde_json::from_str(&serialized).expect("failed to deserialize");
+        assert_eq!(deserialized, claims);
+    }
+}
@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The data that's bound into the `report_data` field of a CVM's attestation report.
+///
+/// The layout is:
+///
+/// * Byte 0: the layout version.
+/// * Bytes 1..33: the sha256 hash of the TLS certificate's public key.
+/// * Bytes 33..64: the first 31 bytes of the [WorkloadIdentity] hash, if any.
+///
+/// Version 0 reports don't bind an identity and leave the last 31 bytes zeroed. Version 2 reports bind a boot log and
+/// use the first 31 bytes of `sha256(identity_hash || boot_log_hash)` as the last 31 bytes instead, where the identity
+/// hash is zeroed if there's no identity. Version 3 reports bind the public key identity tokens are signed with and use
+/// the first 31 bytes of `sha256(identity_hash || boot_log_hash || token_key)`, where the identity and boot log hashes
+/// are zeroed if they're not bound.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportData {
+    /// The sha256 hash of the TLS certificate's public key.
+    pub tls_fingerprint: [u8; 32],
+
+    /// The identity of the workload running in the CVM.
+    pub identity: Option<WorkloadIdentity>,
+
+    /// The sha256 hash of the boot log generated while booting the CVM.
+    pub boot_log_hash: Option<[u8; 32]>,
+
+    /// The Ed25519 public key the CVM signs identity tokens with.
+    pub token_key: Option<[u8; 32]>,
+}
+
+impl ReportData {
+    /// The version used when the report doesn't bind a workload identity.
+    pub const UNBOUND_VERSION: u8 = 0;
+
+    /// The version used when the report binds a workload identity.
+    pub const IDENTITY_VERSION: u8 = 1;
+
+    /// The version used when the report binds a boot log.
+    pub const BOOT_LOG_VERSION: u8 = 2;
+
+    /// The version used when the report binds the identity token key.
+    pub const TOKEN_KEY_VERSION: u8 = 3;
+
+    /// Encode this into the 64 bytes that go in the report.
+    pub fn encode(&self) -> [u8; 64] {
+        let mut data = [0; 64];
+        data[1..33].copy_from_slice(&self.tls_fingerprint);
+        match (&self.identity, &self.boot_log_hash, &self.token_key) {
+            (identity, boot_log_hash, Some(token_key)) => {
+                let identity_hash = identity.as_ref().map(WorkloadIdentity::hash).unwrap_or_default();
+                let hash: [u8; 32] = Sha256::new()
+                    .chain_update(identity_hash)
+                    .chain_update(boot_log_hash.unwrap_or_default())
+                    .chain_update(token_key)
+                    .finalize()
+                    .into();
+                data[0] = Self::TOKEN_KEY_VERSION;
+                data[33..].copy_from_slice(&hash[..31]);
+            }
+            (identity, Some(boot_log_hash), None) => {
+                let identity_hash = identity.as_ref().map(WorkloadIdentity::hash).unwrap_or_default();
+                let hash: [u8; 32] =
+                    Sha256::new().chain_update(identity_hash).chain_update(boot_log_hash).finalize().into();
+                data[0] = Self::BOOT_LOG_VERSION;
+                data[33..].copy_from_slice(&hash[..31]);
+            }
+            (Some(identity), None, None) => {
+                data[0] = Self::IDENTITY_VERSION;
+                data[33..].copy_from_slice(&identity.hash()[..31]);
+            }
+            (None, None, None) => data[0] = Self::UNBOUND_VERSION,
+        };
+        data
+    }
+}
+
+/// The identity of a workload, as assigned by the nilcc-agent that runs it.
+///
+/// The agent launches the CVM with this identity's hash as its SNP host data. Unlike the report data, the host data is
+/// fixed when the CVM is launched and can't be changed by anyone afterwards, so reports that bind an identity are only
+/// valid if their host data is that identity's hash.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkloadIdentity {
+    /// The workload id.
+    pub workload_id: String,
+
+    /// The id of the agent running the workload.
+    pub agent_id: String,
+}
+
+impl WorkloadIdentity {
+    /// Hash this identity.
+    ///
+    /// Every field is length prefixed so that different identities can't produce the same input.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for field in [&self.workload_id, &self.agent_id] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_identity(workload_id: &str, agent_id: &str) -> WorkloadIdentity {
+        WorkloadIdentity { workload_id: workload_id.into(), agent_id: agent_id.into() }
+    }
+
+    #[test]
+    fn unbound() {
+        let data =
+            ReportData { tls_fingerprint: [1; 32], identity: None, boot_log_hash: None, token_key: None }.encode();
+        let mut expected = [0; 64];
+        expected[1..33].copy_from_slice(&[1; 32]);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn bound() {
+        let identity = make_identity("workload", "agent");
+        let hash = identity.hash();
+        let data =
+            ReportData { tls_fingerprint: [1; 32], identity: Some(identity), boot_log_hash: None, token_key: None }
+                .encode();
+        assert_eq!(data[0], ReportData::IDENTITY_VERSION);
+        assert_eq!(data[1..33], [1; 32]);
+        assert_eq!(data[33..], hash[..31]);
+    }
+
+    #[test]
+    fn distinct_identities() {
+        let encode = |identity| {
+            ReportData { tls_fingerprint: [1; 32], identity: Some(identity), boot_log_hash: None, token_key: None }
+                .encode()
+        };
+        let data = encode(make_identity("a", "b"));
+        assert_ne!(data, encode(make_identity("c", "b")));
+        assert_ne!(data, encode(make_identity("a", "c")));
+        assert_ne!(encode(make_identity("ab", "c")), encode(make_identity("a", "bc")));
+    }
+
+    #[test]
+    fn boot_log() {
+        let encode = |identity, boot_log_hash| {
+            ReportData { tls_fingerprint: [1; 32], identity, boot_log_hash, token_key: None }.encode()
+        };
+        let data = encode(None, Some([2; 32]));
+        assert_eq!(data[0], ReportData::BOOT_LOG_VERSION);
+        assert_eq!(data[1..33], [1; 32]);
+        assert_ne!(data, encode(None, Some([3; 32])));
+        assert_ne!(data, encode(Some(make_identity("a", "b")), Some([2; 32])));
+    }
+
+    #[test]
+    fn token_key() {
+        let encode = |boot_log_hash, token_key| {
+            let identity = Some(make_identity("a", "b"));
+            ReportData { tls_fingerprint: [1; 32], identity, boot_log_hash, token_key }.encode()
+        };
+        let data = encode(None, Some([2; 32]));
+        assert_eq!(data[0], ReportData::TOKEN_KEY_VERSION);
+        assert_eq!(data[1..33], [1; 32]);
+        assert_ne!(data, encode(None, Some([3; 32])));
+        assert_ne!(data, encode(Some([4; 32]), Some([2; 32])));
+        assert_ne!(data, encode(None, None));
+    }
+
+    #[test]
+    fn golden_vectors() {
+        for vector in nilcc_test_vectors::report_data() {
+            let tls_fingerprint =
+                hex::decode(&vector.tls_fingerprint).expect("invalid hex").try_into().expect("invalid length");
+            let identity = match (&vector.workload_id, &vector.agent_id) {
+                (Some(workload_id), Some(agent_id)) => Some(make_identity(workload_id, agent_id)),
+                _ => None,
+            };
+            let boot_log_hash = vector
+                .boot_log_hash
+                .as_ref()
+                .map(|hash| hex::decode(hash).expect("invalid hex").try_into().expect("invalid length"));
+            let token_key = vector
+                .token_key
+                .as_ref()
+                .map(|key| hex::decode(key).expect("invalid hex").try_into().expect("invalid length"));
+            let data = ReportData { tls_fingerprint, identity, boot_log_hash, token_key }.encode();
+            assert_eq!(hex::encode(data), vector.expected, "vector {}", vector.name);
+        }
+    }
+}
@@ -265,4 +265,15 @@ mod tests {
         let report2: AttestationReport = serde_json::from_str(&serialized).expect("deserialization failed");
         assert_eq!(report1, report2);
     }
+
+    #[test]
+    fn serde_vectors() {
+        for vector in nilcc_test_vectors::reports() {
+            let report1: AttestationReport =
+                serde_json::from_value(vector.report.clone()).expect("deserialization failed");
+            let serialized = serde_json::to_string(&report1).expect("serialization failed");
+            let report2: AttestationReport = serde_json::from_str(&serialized).expect("deserialization failed");
+            assert_eq!(report1, report2, "vector {}", vector.name);
+        }
+    }
 }
@@ -0,0 +1,231 @@
+use crate::certs::CacheLock;
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tracing::info;
+
+/// The file in every cached version whose modification time is the last time the version was used.
+const LAST_USED_FILE: &str = ".last-used";
+
+/// How long a version is kept after it's used even if the cache is over its maximum size.
+///
+/// Versions are only locked while they're being downloaded, so this keeps them around for runs that are still
+/// generating a measurement from them.
+const MIN_EVICTION_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// A directory where artifacts are cached, with one subdirectory per version.
+///
+/// Every version is downloaded while holding a lock file next to its directory, so any number of verifier processes
+/// sharing the same cache only download each version once. The lock file is removed along with the version. If a
+/// maximum size is set, the least recently used versions are evicted once the cache grows past it.
+#[derive(Clone, Debug)]
+pub struct ArtifactCache {
+    path: PathBuf,
+    max_size: Option<u64>,
+}
+
+impl ArtifactCache {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, max_size: None }
+    }
+
+    /// Evict the least recently used versions once the cache takes up more than this many bytes.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// The path where a version is cached.
+    pub fn version_path(&self, version: &str) -> PathBuf {
+        self.path.join(version)
+    }
+
+    /// Lock a version so it can be downloaded, marking it as the most recently used one.
+    pub async fn lock(&self, version: &str) -> io::Result<CachedVersion> {
+        fs::create_dir_all(&self.path)?;
+        let path = self.version_path(version);
+        let lock = CacheLock::acquire(&path).await?;
+        fs::create_dir_all(&path)?;
+        File::create(path.join(LAST_USED_FILE))?;
+        Ok(CachedVersion { path, _lock: lock })
+    }
+
+    /// Evict the least recently used versions until the cache fits in its maximum size, returning the evicted ones.
+    ///
+    /// Versions that are locked or that were used recently are never evicted.
+    pub fn evict(&self) -> io::Result<Vec<String>> {
+        let Some(max_size) = self.max_size else {
+            return Ok(Vec::new());
+        };
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|entry| entry.last_used);
+        let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+        let now = SystemTime::now();
+        let mut evicted = Vec::new();
+        for entry in entries {
+            let age = now.duration_since(entry.last_used).unwrap_or_default();
+            if size <= max_size || age < MIN_EVICTION_AGE {
+                break;
+            }
+            if self.remove(&entry.version)? {
+                size = size.saturating_sub(entry.size);
+                evicted.push(entry.version);
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Remove every version that isn't locked, returning the removed ones.
+    pub fn purge(&self) -> io::Result<Vec<String>> {
+        let mut purged = Vec::new();
+        for entry in self.entries()? {
+            if self.remove(&entry.version)? {
+                purged.push(entry.version);
+            }
+        }
+        Ok(purged)
+    }
+
+    /// List the cached versions.
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let dir = match fs::read_dir(&self.path) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for entry in dir {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let Ok(version) = entry.file_name().into_string() else {
+                continue;
+            };
+            let path = entry.path();
+            // Versions cached before usage was tracked are treated as the least recently used ones.
+            let last_used = fs::metadata(path.join(LAST_USED_FILE))
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push(CacheEntry { version, size: directory_size(&path)?, last_used });
+        }
+        Ok(entries)
+    }
+
+    fn remove(&self, version: &str) -> io::Result<bool> {
+        let path = self.version_path(version);
+        let Some(lock) = CacheLock::try_acquire(&path)? else {
+            info!("Not removing cached artifacts version {version} because it's in use");
+            return Ok(false);
+        };
+        info!("Removing cached artifacts version {version}");
+        fs::remove_dir_all(&path)?;
+        lock.remove()?;
+        Ok(true)
+    }
+}
+
+/// A version in the cache, locked for as long as this is alive.
+pub struct CachedVersion {
+    path: PathBuf,
+    _lock: CacheLock,
+}
+
+impl CachedVersion {
+    /// The directory the version is cached in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// A version in the cache.
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    /// The artifacts version.
+    pub version: String,
+
+    /// The space the version takes up, in bytes.
+    pub size: u64,
+
+    /// The last time the version was used.
+    pub last_used: SystemTime,
+}
+
+fn directory_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += match metadata.is_dir() {
+            true => directory_size(&entry.path())?,
+            false => metadata.len(),
+        };
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    async fn cache_version(cache: &ArtifactCache, version: &str, size: usize, last_used_ago: Duration) {
+        let cached = cache.lock(version).await.expect("failed to lock");
+        fs::create_dir_all(cached.path().join("vm")).expect("failed to create dir");
+        fs::write(cached.path().join("vm/kernel"), vec![0; size]).expect("failed to write");
+        let marker = File::options().write(true).open(cached.path().join(LAST_USED_FILE)).expect("no marker");
+        marker.set_modified(SystemTime::now() - last_used_ago).expect("failed to set time");
+    }
+
+    fn versions(cache: &ArtifactCache) -> Vec<String> {
+        let mut versions: Vec<_> = cache.entries().expect("failed to list").into_iter().map(|e| e.version).collect();
+        versions.sort();
+        versions
+    }
+
+    #[tokio::test]
+    async fn evict_least_recently_used() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let cache = ArtifactCache::new(dir.path().join("artifacts")).with_max_size(25);
+        let hour = Duration::from_secs(3600);
+        cache_version(&cache, "0.1.0", 10, hour * 3).await;
+        cache_version(&cache, "0.2.0", 10, hour).await;
+        cache_version(&cache, "0.3.0", 10, hour * 2).await;
+        cache_version(&cache, "0.4.0", 10, Duration::ZERO).await;
+
+        let entry = cache.entries().expect("failed to list").into_iter().find(|e| e.version == "0.1.0");
+        assert_eq!(entry.expect("version not found").size, 10);
+
+        // 0.3.0 is locked so 0.2.0 goes instead, and 0.4.0 was just used.
+        let _lock = CacheLock::acquire(&cache.version_path("0.3.0")).await.expect("failed to lock");
+        let evicted = cache.evict().expect("failed to evict");
+        assert_eq!(evicted, &["0.1.0", "0.2.0"]);
+        assert_eq!(versions(&cache), &["0.3.0", "0.4.0"]);
+        assert!(!dir.path().join("artifacts/0.1.0.lock").exists());
+        assert!(!dir.path().join("artifacts/0.2.0.lock").exists());
+        assert!(dir.path().join("artifacts/0.3.0.lock").exists());
+    }
+
+    #[tokio::test]
+    async fn evict_without_max_size() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let cache = ArtifactCache::new(dir.path().into());
+        cache_version(&cache, "0.1.0", 10, Duration::from_secs(3600)).await;
+        assert!(cache.evict().expect("failed to evict").is_empty());
+    }
+
+    #[tokio::test]
+    async fn purge() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let cache = ArtifactCache::new(dir.path().into());
+        cache_version(&cache, "0.1.0", 10, Duration::ZERO).await;
+        cache_version(&cache, "0.2.0", 10, Duration::ZERO).await;
+
+        let _lock = cache.lock("0.2.0").await.expect("failed to lock");
+        assert_eq!(cache.purge().expect("failed to purge"), &["0.1.0"]);
+        assert_eq!(versions(&cache), &["0.2.0"]);
+    }
+}
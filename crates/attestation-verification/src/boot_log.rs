@@ -0,0 +1,133 @@
+use crate::measurement::MeasurementGenerator;
+use attestation_report::boot_log::BootLog;
+use nilcc_artifacts::metadata::MissingCommandLineParameter;
+
+/// The status `veritysetup` reports for a device whose integrity was verified.
+const VERITY_VERIFIED_STATUS: &str = "verified";
+
+/// The parameter OVMF appends to the kernel command line when booting a kernel with an initrd.
+const OVMF_INITRD_PARAMETER: &str = " initrd=initrd";
+
+/// Checks that a boot log is consistent with the inputs of a measurement.
+///
+/// Because every input checked here is part of the measurement, a boot log that's consistent with a measurement that
+/// matches the one in the report describes what the CVM actually booted.
+pub struct BootLogVerifier<'a> {
+    pub generator: &'a MeasurementGenerator,
+}
+
+impl BootLogVerifier<'_> {
+    pub fn verify(&self, boot_log: &BootLog) -> Result<(), BootLogError> {
+        let expected_cmdline = self.generator.kernel_command_line()?;
+        let cmdline = boot_log.kernel_cmdline.strip_suffix(OVMF_INITRD_PARAMETER).unwrap_or(&boot_log.kernel_cmdline);
+        if cmdline != expected_cmdline {
+            return Err(BootLogError::KernelCommandLine {
+                expected: expected_cmdline,
+                actual: boot_log.kernel_cmdline.clone(),
+            });
+        }
+        let expected_root_hash = hex::encode(self.generator.filesystem_root_hash);
+        if boot_log.verity.root_hash != expected_root_hash {
+            return Err(BootLogError::VerityRootHash {
+                expected: expected_root_hash,
+                actual: boot_log.verity.root_hash.clone(),
+            });
+        }
+        if boot_log.verity.status != VERITY_VERIFIED_STATUS {
+            return Err(BootLogError::VerityStatus(boot_log.verity.status.clone()));
+        }
+        let expected_compose_hash = hex::encode(self.generator.docker_compose_hash);
+        if boot_log.docker_compose_hash != expected_compose_hash {
+            return Err(BootLogError::DockerComposeHash {
+                expected: expected_compose_hash,
+                actual: boot_log.docker_compose_hash.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BootLogError {
+    #[error(transparent)]
+    KernelArgs(#[from] MissingCommandLineParameter),
+
+    #[error("boot log kernel command line is '{actual}', expected '{expected}'")]
+    KernelCommandLine { expected: String, actual: String },
+
+    #[error("boot log verity root hash is {actual}, expected {expected}")]
+    VerityRootHash { expected: String, actual: String },
+
+    #[error("boot log verity status is '{0}', expected '{VERITY_VERIFIED_STATUS}'")]
+    VerityStatus(String),
+
+    #[error("boot log docker compose hash is {actual}, expected {expected}")]
+    DockerComposeHash { expected: String, actual: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use attestation_report::boot_log::{MountedDevice, VerityResult};
+    use nilcc_artifacts::metadata::KernelCommandLine;
+
+    fn make_generator() -> MeasurementGenerator {
+        MeasurementGenerator {
+            vcpus: 1,
+            ovmf: "ovmf".into(),
+            kernel: "kernel".into(),
+            initrd: "initrd".into(),
+            docker_compose_hash: [1; 32],
+            filesystem_root_hash: [2; 32],
+            kernel_args: KernelCommandLine("root={VERITY_ROOT_HASH} compose={DOCKER_COMPOSE_HASH}".into()),
+            cpu_model: Default::default(),
+        }
+    }
+
+    fn make_boot_log(generator: &MeasurementGenerator) -> BootLog {
+        BootLog {
+            kernel_cmdline: generator.kernel_command_line().expect("failed to render"),
+            verity: VerityResult { root_hash: hex::encode([2; 32]), status: "verified".into() },
+            docker_compose_hash: hex::encode([1; 32]),
+            mounts: vec![MountedDevice {
+                device: "/dev/sr0".into(),
+                mount_point: "/media/cvm-agent-entrypoint".into(),
+                sha256: hex::encode([3; 32]),
+            }],
+        }
+    }
+
+    #[test]
+    fn consistent() {
+        let generator = make_generator();
+        let boot_log = make_boot_log(&generator);
+        BootLogVerifier { generator: &generator }.verify(&boot_log).expect("verification failed");
+    }
+
+    #[test]
+    fn ovmf_initrd_parameter() {
+        let generator = make_generator();
+        let mut boot_log = make_boot_log(&generator);
+        boot_log.kernel_cmdline.push_str(" initrd=initrd");
+        BootLogVerifier { generator: &generator }.verify(&boot_log).expect("verification failed");
+    }
+
+    #[test]
+    fn inconsistent() {
+        let generator = make_generator();
+        let verifier = BootLogVerifier { generator: &generator };
+
+        let boot_log = BootLog { kernel_cmdline: "root=foo".into(), ..make_boot_log(&generator) };
+        let err = verifier.verify(&boot_log).expect_err("verification succeeded");
+        assert!(matches!(err, BootLogError::KernelCommandLine { .. }), "{err}");
+
+        let mut boot_log = make_boot_log(&generator);
+        boot_log.verity.status = "corrupted".into();
+        let err = verifier.verify(&boot_log).expect_err("verification succeeded");
+        assert!(matches!(err, BootLogError::VerityStatus(_)), "{err}");
+
+        let boot_log = BootLog { docker_compose_hash: hex::encode([4; 32]), ..make_boot_log(&generator) };
+        let err = verifier.verify(&boot_log).expect_err("verification succeeded");
+        assert!(matches!(err, BootLogError::DockerComposeHash { .. }), "{err}");
+    }
+}
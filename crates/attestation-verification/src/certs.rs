@@ -1,19 +1,31 @@
 use crate::verify::Processor;
 use async_trait::async_trait;
-use reqwest::get;
+use reqwest::{
+    Client, StatusCode,
+    header::{HeaderMap, RETRY_AFTER},
+};
 use sev::{
     certs::snp::{Certificate, ca::Chain},
     firmware::guest::AttestationReport,
 };
 use std::{
-    fs::{self, File},
+    ffi::OsString,
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
     io::{self, Read},
+    iter,
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
+    time::Duration,
 };
-use tracing::{error, info};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
 
 const KDS_DOMAIN: &str = "kdsintf.amd.com";
 
+/// How often a cache lock held by someone else is checked.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// The set of certificates needed to validate a report.
 pub struct Certs {
     /// The certificate chain, which includes the ARK and ASK.
@@ -30,16 +42,54 @@ pub trait CertificateFetcher: Send + Sync + 'static {
     async fn fetch_certs(&self, processor: &Processor, report: &AttestationReport) -> Result<Certs, FetcherError>;
 }
 
+/// How requests to the KDS are retried when they're rate limited or fail.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts made against each endpoint.
+    pub max_attempts: u32,
+
+    /// The delay before the first retry, which doubles after every attempt.
+    pub base_delay: Duration,
+
+    /// The maximum delay between attempts, including the ones requested via a `Retry-After` header.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = || self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        retry_after.unwrap_or_else(backoff).min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_attempts: 5, base_delay: Duration::from_secs(1), max_delay: Duration::from_secs(60) }
+    }
+}
+
 /// A default implementation of the certificate fetcher.
+///
+/// Certificates are cached on disk. Downloads are serialized through a lock file next to each cached certificate so
+/// that any number of verifier processes sharing the same cache directory only download each certificate once.
 pub struct DefaultCertificateFetcher {
     cache_path: PathBuf,
     processor_cert_domain: String,
+    mirror_url: Option<String>,
+    retry_policy: RetryPolicy,
+    client: Client,
 }
 
 impl DefaultCertificateFetcher {
     pub fn new(cache_path: PathBuf) -> io::Result<Self> {
         fs::create_dir_all(&cache_path)?;
-        Ok(Self { cache_path, processor_cert_domain: KDS_DOMAIN.to_string() })
+        Ok(Self {
+            cache_path,
+            processor_cert_domain: KDS_DOMAIN.to_string(),
+            mirror_url: None,
+            retry_policy: Default::default(),
+            client: Client::new(),
+        })
     }
 
     pub fn with_processor_cert_domain(mut self, domain: String) -> Self {
@@ -47,29 +97,36 @@ impl DefaultCertificateFetcher {
         self
     }
 
+    /// Fetch VCEK certificates from a KDS mirror, e.g. `https://kds.example.com`, before falling back to AMD's KDS.
+    ///
+    /// The mirror must serve certificates under the same paths as the KDS does. Certificate chains are always fetched
+    /// from AMD's KDS so a mirror can't hand out a chain rooted somewhere else.
+    pub fn with_mirror_url(mut self, url: String) -> Self {
+        self.mirror_url = Some(url.trim_end_matches('/').to_string());
+        self
+    }
+
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
     async fn fetch_vcek(&self, processor: &Processor, report: &AttestationReport) -> Result<Certificate, FetcherError> {
         let identifier = ProcessorVcekIdentifier::new(processor.clone(), report)?;
         let cache_file_name = self.cache_path.join(identifier.cache_file_name());
-        match self.load_cache_file(&cache_file_name)? {
-            Some(cert) => match Certificate::from_bytes(&cert) {
-                Ok(cert) => {
-                    info!("Using cached VCEK certificate {}", cache_file_name.display());
-                    return Ok(cert);
-                }
-                Err(e) => {
-                    error!("Downloading VCEK because cached file {} is corrupted: {e}", cache_file_name.display());
-                }
-            },
-            None => {
-                info!("VCEK not found, downloading it");
-            }
-        };
-
-        let url = identifier.kds_url(&self.processor_cert_domain);
-        info!("Fetching VCEK from {url}");
+        let load = || self.load_cached(&cache_file_name, "VCEK certificate", Certificate::from_bytes);
+        if let Some(cert) = load()? {
+            return Ok(cert);
+        }
+        let _lock = CacheLock::acquire(&cache_file_name).await.map_err(FetcherError::LockCache)?;
+        // Someone else may have downloaded it while we were waiting for the lock.
+        if let Some(cert) = load()? {
+            return Ok(cert);
+        }
 
-        let response = get(url).await.and_then(|r| r.error_for_status()).map_err(FetcherError::FetchingVcek)?;
-        let bytes = response.bytes().await.map_err(FetcherError::FetchingVcek)?.to_vec();
+        info!("VCEK not found, downloading it");
+        let urls = self.urls(&self.processor_cert_domain, &identifier.kds_path());
+        let bytes = self.download(&urls).await.map_err(FetcherError::FetchingVcek)?;
         let cert = Certificate::from_bytes(&bytes).map_err(FetcherError::ParsingVcek)?;
         self.cache_file(&cache_file_name, &bytes)?;
         Ok(cert)
@@ -77,34 +134,96 @@ impl DefaultCertificateFetcher {
 
     async fn fetch_cert_chain(&self, processor: &Processor) -> Result<Chain, FetcherError> {
         let cache_file_name = self.cache_path.join(format!("{processor:?}.cert"));
-        match self.load_cache_file(&cache_file_name)? {
-            Some(chain) => match Chain::from_pem_bytes(&chain) {
-                Ok(chain) => {
-                    info!("Using cached certificate chain file {}", cache_file_name.display());
-                    return Ok(chain);
-                }
-                Err(e) => {
-                    error!(
-                        "Downloading cert chain for processor {processor:?} because cached file {} is corrupted: {e}",
-                        cache_file_name.display()
-                    );
-                }
-            },
-            None => {
-                info!("Cert chain file for processor {processor:?} not found, downloading it");
-            }
-        };
+        let load = || self.load_cached(&cache_file_name, "certificate chain", Chain::from_pem_bytes);
+        if let Some(chain) = load()? {
+            return Ok(chain);
+        }
+        let _lock = CacheLock::acquire(&cache_file_name).await.map_err(FetcherError::LockCache)?;
+        if let Some(chain) = load()? {
+            return Ok(chain);
+        }
 
+        info!("Cert chain file for processor {processor:?} not found, downloading it");
         let url = format!("https://{KDS_DOMAIN}/vcek/v1/{}/cert_chain", processor.to_kds_url());
-        info!("Fetching CA chain from {url}");
-
-        let response = get(url).await.and_then(|r| r.error_for_status()).map_err(FetcherError::FetchingCertChain)?;
-        let bytes = response.bytes().await.map_err(FetcherError::FetchingCertChain)?.to_vec();
+        let bytes = self.download(&[url]).await.map_err(FetcherError::FetchingCertChain)?;
         let certificates = Chain::from_pem_bytes(&bytes).map_err(FetcherError::ParsingCertChain)?;
         self.cache_file(&cache_file_name, &bytes)?;
         Ok(certificates)
     }
 
+    /// The URLs a KDS path can be downloaded from, in the order they should be tried.
+    fn urls(&self, domain: &str, path: &str) -> Vec<String> {
+        let mirror = self.mirror_url.iter().map(|url| format!("{url}{path}"));
+        mirror.chain(iter::once(format!("https://{domain}{path}"))).collect()
+    }
+
+    /// Download a file from the first URL that serves it.
+    async fn download(&self, urls: &[String]) -> Result<Vec<u8>, reqwest::Error> {
+        let mut last_error = None;
+        for url in urls {
+            match self.download_with_retries(url).await {
+                Ok(bytes) => return Ok(bytes),
+                Err(e) => {
+                    warn!("Failed to fetch {url}: {e}");
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.expect("no URLs to download from"))
+    }
+
+    async fn download_with_retries(&self, url: &str) -> Result<Vec<u8>, reqwest::Error> {
+        let mut attempt = 1;
+        loop {
+            info!("Fetching {url}");
+            let (error, retry_after) = match self.client.get(url).send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = parse_retry_after(response.headers());
+                    match response.error_for_status() {
+                        Ok(response) => return Ok(response.bytes().await?.to_vec()),
+                        Err(e) if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() => {
+                            (e, retry_after)
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Err(e) => (e, None),
+            };
+            if attempt >= self.retry_policy.max_attempts {
+                return Err(error);
+            }
+            let delay = self.retry_policy.delay(attempt, retry_after);
+            warn!("Attempt {attempt} to fetch {url} failed, retrying in {delay:?}: {error}");
+            sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    fn load_cached<T, E>(
+        &self,
+        path: &Path,
+        description: &str,
+        parse: impl Fn(&[u8]) -> Result<T, E>,
+    ) -> Result<Option<T>, FetcherError>
+    where
+        E: fmt::Display,
+    {
+        let Some(contents) = self.load_cache_file(path)? else {
+            return Ok(None);
+        };
+        match parse(&contents) {
+            Ok(value) => {
+                info!("Using cached {description} {}", path.display());
+                Ok(Some(value))
+            }
+            Err(e) => {
+                error!("Downloading {description} because cached file {} is corrupted: {e}", path.display());
+                Ok(None)
+            }
+        }
+    }
+
     fn load_cache_file(&self, path: &Path) -> Result<Option<Vec<u8>>, FetcherError> {
         match File::open(path) {
             Ok(mut file) => {
@@ -118,7 +237,10 @@ impl DefaultCertificateFetcher {
     }
 
     fn cache_file(&self, path: &Path, contents: &[u8]) -> Result<(), FetcherError> {
-        fs::write(path, contents).map_err(FetcherError::WriteCachedCert)?;
+        // Write it somewhere else and move it in place so readers that don't hold the lock never see partial files.
+        let temp_path = suffixed_path(path, ".tmp");
+        fs::write(&temp_path, contents).map_err(FetcherError::WriteCachedCert)?;
+        fs::rename(&temp_path, path).map_err(FetcherError::WriteCachedCert)?;
         Ok(())
     }
 }
@@ -126,12 +248,71 @@ impl DefaultCertificateFetcher {
 #[async_trait]
 impl CertificateFetcher for DefaultCertificateFetcher {
     async fn fetch_certs(&self, processor: &Processor, report: &AttestationReport) -> Result<Certs, FetcherError> {
-        let chain = self.fetch_cert_chain(processor).await?;
-        let vcek = self.fetch_vcek(processor, report).await?;
+        let (chain, vcek) = tokio::try_join!(self.fetch_cert_chain(processor), self.fetch_vcek(processor, report))?;
         Ok(Certs { chain, vcek })
     }
 }
 
+/// An exclusive lock on a cached file, held across processes via a `.lock` file next to it.
+pub(crate) struct CacheLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl CacheLock {
+    pub(crate) async fn acquire(path: &Path) -> io::Result<Self> {
+        loop {
+            match Self::try_acquire(path)? {
+                Some(lock) => return Ok(lock),
+                None => sleep(LOCK_POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Acquire the lock if nobody else holds it.
+    pub(crate) fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
+        let path = suffixed_path(path, ".lock");
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => return Err(e),
+        };
+        // The lock file may have been removed by whoever held it before we locked it, in which case someone else may
+        // have created and locked a new one at the same path.
+        let current = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let locked = file.metadata()?;
+        if (current.dev(), current.ino()) != (locked.dev(), locked.ino()) {
+            return Ok(None);
+        }
+        Ok(Some(Self { file, path }))
+    }
+
+    /// Release the lock and remove its lock file.
+    pub(crate) fn remove(self) -> io::Result<()> {
+        // Removing it while still holding the lock makes anyone waiting on it retry with a new lock file.
+        fs::remove_file(&self.path)?;
+        drop(self.file);
+        Ok(())
+    }
+}
+
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+/// Parse a `Retry-After` header, which the KDS sets as a number of seconds.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
 struct ProcessorVcekIdentifier {
     processor: Processor,
     fmc: Option<u8>,
@@ -171,7 +352,7 @@ impl ProcessorVcekIdentifier {
         })
     }
 
-    fn kds_url(&self, vcek_domain: &str) -> String {
+    fn kds_path(&self) -> String {
         let Self { processor, fmc, bootloader, tee, snp, microcode, hw_id } = self;
         let fmc_param = match fmc {
             Some(fmc) => format!("&fmcSPL={fmc:02}"),
@@ -179,7 +360,7 @@ impl ProcessorVcekIdentifier {
         };
         let processor = processor.to_kds_url();
         format!(
-            "https://{vcek_domain}/vcek/v1/{processor}/{hw_id}?blSPL={bootloader:02}&teeSPL={tee:02}&snpSPL={snp:02}&ucodeSPL={microcode:02}{fmc_param}"
+            "/vcek/v1/{processor}/{hw_id}?blSPL={bootloader:02}&teeSPL={tee:02}&snpSPL={snp:02}&ucodeSPL={microcode:02}{fmc_param}"
         )
     }
 
@@ -203,6 +384,9 @@ pub enum FetcherError {
     #[error("writing cached cert: {0}")]
     WriteCachedCert(io::Error),
 
+    #[error("locking cached cert: {0}")]
+    LockCache(io::Error),
+
     #[error("fetching AMD VCEK certificate: {0}")]
     FetchingVcek(reqwest::Error),
 
@@ -214,4 +398,124 @@ pub enum FetcherError {
 
     #[error("parsing AMD cert chain: {0}")]
     ParsingCertChain(io::Error),
+
+    #[error("encoding certificate: {0}")]
+    EncodeCert(io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::{io::Write, net::TcpListener, thread};
+    use tempfile::tempdir;
+
+    const NOT_FOUND: &str = "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+    const RATE_LIMITED: &str =
+        "HTTP/1.1 429 Too Many Requests\r\nretry-after: 0\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+    const CERT: &str = "HTTP/1.1 200 OK\r\ncontent-length: 4\r\nconnection: close\r\n\r\ncert";
+
+    /// Serve the given responses, one per connection, and return the server's URL.
+    fn serve(responses: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+        let url = format!("http://{}", listener.local_addr().expect("no local address"));
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().expect("failed to accept");
+                let mut buffer = [0; 4096];
+                let _ = stream.read(&mut buffer).expect("failed to read request");
+                stream.write_all(response.as_bytes()).expect("failed to write response");
+            }
+        });
+        url
+    }
+
+    #[rstest]
+    #[case::first_retry(1, None, 1)]
+    #[case::backoff(3, None, 4)]
+    #[case::retry_after(3, Some(10), 10)]
+    #[case::capped_backoff(10, None, 60)]
+    #[case::capped_retry_after(1, Some(3600), 60)]
+    fn retry_delay(#[case] attempt: u32, #[case] retry_after: Option<u64>, #[case] expected: u64) {
+        let delay = RetryPolicy::default().delay(attempt, retry_after.map(Duration::from_secs));
+        assert_eq!(delay, Duration::from_secs(expected));
+    }
+
+    #[test]
+    fn mirror_urls() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let fetcher = DefaultCertificateFetcher::new(dir.path().into())
+            .expect("failed to create fetcher")
+            .with_mirror_url("https://mirror.example.com/".into());
+        let urls = fetcher.urls("kds.example.com", "/vcek/v1/Milan/abcd");
+        assert_eq!(
+            urls,
+            &["https://mirror.example.com/vcek/v1/Milan/abcd", "https://kds.example.com/vcek/v1/Milan/abcd"]
+        );
+    }
+
+    #[tokio::test]
+    async fn download_rate_limited() {
+        let url = serve(vec![RATE_LIMITED, RATE_LIMITED, CERT]);
+        let dir = tempdir().expect("failed to create tempdir");
+        let fetcher = DefaultCertificateFetcher::new(dir.path().into()).expect("failed to create fetcher");
+        let bytes = fetcher.download(&[url]).await.expect("download failed");
+        assert_eq!(bytes, b"cert");
+    }
+
+    #[tokio::test]
+    async fn download_gives_up() {
+        let url = serve(vec![RATE_LIMITED, RATE_LIMITED]);
+        let dir = tempdir().expect("failed to create tempdir");
+        let policy = RetryPolicy { max_attempts: 2, ..Default::default() };
+        let fetcher = DefaultCertificateFetcher::new(dir.path().into())
+            .expect("failed to create fetcher")
+            .with_retry_policy(policy);
+        let error = fetcher.download(&[url]).await.expect_err("download succeeded");
+        assert_eq!(error.status(), Some(StatusCode::TOO_MANY_REQUESTS));
+    }
+
+    #[tokio::test]
+    async fn download_falls_back() {
+        let mirror = serve(vec![NOT_FOUND]);
+        let kds = serve(vec![CERT]);
+        let dir = tempdir().expect("failed to create tempdir");
+        let fetcher = DefaultCertificateFetcher::new(dir.path().into()).expect("failed to create fetcher");
+        let bytes = fetcher.download(&[mirror, kds]).await.expect("download failed");
+        assert_eq!(bytes, b"cert");
+    }
+
+    #[tokio::test]
+    async fn cache_lock() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("cert");
+        let lock = CacheLock::acquire(&path).await.expect("failed to lock");
+        let waiter = tokio::spawn({
+            let path = path.clone();
+            async move { CacheLock::acquire(&path).await.map(drop) }
+        });
+        sleep(LOCK_POLL_INTERVAL * 3).await;
+        assert!(!waiter.is_finished());
+
+        drop(lock);
+        waiter.await.expect("task panicked").expect("failed to lock");
+    }
+
+    #[tokio::test]
+    async fn removed_cache_lock() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("cert");
+        let lock = CacheLock::acquire(&path).await.expect("failed to lock");
+        let waiter = tokio::spawn({
+            let path = path.clone();
+            async move { CacheLock::acquire(&path).await }
+        });
+        sleep(LOCK_POLL_INTERVAL * 3).await;
+        lock.remove().expect("failed to remove lock");
+
+        // The waiter locks a new lock file rather than the removed one, so it excludes anyone else.
+        let _lock = waiter.await.expect("task panicked").expect("failed to lock");
+        assert!(dir.path().join("cert.lock").exists());
+        assert!(CacheLock::try_acquire(&path).expect("failed to lock").is_none());
+    }
 }
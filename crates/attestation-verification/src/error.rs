@@ -1,5 +1,6 @@
 use crate::{
-    certs::FetcherError, measurement::MeasurementHashError, report::ReportBundleError, verify::VerificationError,
+    boot_log::BootLogError, certs::FetcherError, measurement::MeasurementHashError,
+    platform_claims::PlatformClaimsError, report::ReportBundleError, verify::VerificationError,
 };
 use nilcc_artifacts::downloader::DownloadError;
 use serde::Serialize;
@@ -13,6 +14,12 @@ pub enum ValidateError {
     #[error("creating cert cache directories: {0}")]
     CertCacheDirectories(io::Error),
 
+    #[error("report belongs to workload {actual}, expected {expected}")]
+    WorkloadMismatch { expected: String, actual: String },
+
+    #[error("report is not bound to a workload identity")]
+    UnboundWorkload,
+
     #[error("fetching report bundle: {0}")]
     ReportBundle(#[from] ReportBundleError),
 
@@ -21,6 +28,15 @@ pub enum ValidateError {
 
     #[error("verifying report: {0}")]
     VerifyReports(#[from] VerificationError),
+
+    #[error("verifying boot log: {0}")]
+    BootLog(#[from] BootLogError),
+
+    #[error("the CVM did not return any platform claims")]
+    MissingPlatformClaims,
+
+    #[error("verifying platform claims: {0}")]
+    PlatformClaims(#[from] PlatformClaimsError),
 }
 
 #[derive(Debug, Serialize)]
@@ -28,8 +44,11 @@ pub enum ValidateError {
 pub enum ErrorCode {
     InvalidDockerComposeHash,
     InvalidTlsFingerprint,
+    InvalidWorkloadIdentity,
     InvalidArtifacts,
     InvalidReport,
+    InvalidBootLog,
+    InvalidPlatformClaims,
     InvalidAmdCerts,
     Filesystem,
     Request,
@@ -42,9 +61,15 @@ impl From<ValidateError> for ErrorCode {
         match e {
             ValidateError::DockerComposeHash => InvalidDockerComposeHash,
             ValidateError::CertCacheDirectories(_) => Filesystem,
+            ValidateError::WorkloadMismatch { .. } | ValidateError::UnboundWorkload => InvalidWorkloadIdentity,
             ValidateError::ReportBundle(e) => match e {
                 ReportBundleError::TlsFingerprint { .. } => InvalidTlsFingerprint,
+                ReportBundleError::WorkloadIdentity { .. } | ReportBundleError::HostData { .. } => {
+                    InvalidWorkloadIdentity
+                }
+                ReportBundleError::MissingBootLog | ReportBundleError::MalformedBootLog(_) => InvalidBootLog,
                 ReportBundleError::HttpClient(_) => Internal,
+                ReportBundleError::LockArtifacts(_) => Filesystem,
                 ReportBundleError::FetchAttestation(_)
                 | ReportBundleError::NoTlsInfo
                 | ReportBundleError::TlsCertificate(_)
@@ -54,7 +79,9 @@ impl From<ValidateError> for ErrorCode {
                 ReportBundleError::DownloadArtifacts(e) => match e {
                     DownloadError::NoParent => Internal,
                     DownloadError::TargetDirectory(_) | DownloadError::TargetFile(_) => Filesystem,
-                    DownloadError::DecodeMetadata(_) => InvalidArtifacts,
+                    DownloadError::DecodeMetadata(_)
+                    | DownloadError::MissingSignature
+                    | DownloadError::Signature(_) => InvalidArtifacts,
                     DownloadError::Download(_) => Request,
                 },
             },
@@ -62,9 +89,12 @@ impl From<ValidateError> for ErrorCode {
             ValidateError::VerifyReports(e) => match e {
                 VerificationError::FetchCerts(e) => match e {
                     FetcherError::TurinFmc | FetcherError::ZeroHardwareId => InvalidReport,
-                    FetcherError::ReadCachedCert(_) | FetcherError::WriteCachedCert(_) => Filesystem,
+                    FetcherError::ReadCachedCert(_) | FetcherError::WriteCachedCert(_) | FetcherError::LockCache(_) => {
+                        Filesystem
+                    }
                     FetcherError::FetchingVcek(_) | FetcherError::FetchingCertChain(_) => Request,
                     FetcherError::ParsingVcek(_) | FetcherError::ParsingCertChain(_) => InvalidAmdCerts,
+                    FetcherError::EncodeCert(_) => Internal,
                 },
                 VerificationError::CertVerification(_)
                 | VerificationError::MalformedCertificate(_)
@@ -74,9 +104,17 @@ impl From<ValidateError> for ErrorCode {
                 | VerificationError::InvalidVcekPubKey
                 | VerificationError::MalformedReportSignature
                 | VerificationError::InvalidSignature
-                | VerificationError::DebugAllowed => InvalidReport,
+                | VerificationError::WeakGuestPolicy { .. } => InvalidReport,
                 VerificationError::SerializeReport(_) => Internal,
             },
+            ValidateError::BootLog(e) => match e {
+                BootLogError::KernelArgs(_) => Internal,
+                BootLogError::KernelCommandLine { .. }
+                | BootLogError::VerityRootHash { .. }
+                | BootLogError::VerityStatus(_)
+                | BootLogError::DockerComposeHash { .. } => InvalidBootLog,
+            },
+            ValidateError::MissingPlatformClaims | ValidateError::PlatformClaims(_) => InvalidPlatformClaims,
         }
     }
 }
@@ -0,0 +1,181 @@
+use crate::measurement::{MeasurementGenerator, MeasurementHashError};
+use nilcc_artifacts::{VmType, metadata::ArtifactsMetadata};
+use sha2::{Digest, Sha256};
+use std::{fmt, fs::File, io, path::Path};
+use tracing::info;
+
+/// The highest vCPU count tried when looking for the one a measurement was generated with.
+const MAX_VCPUS: u32 = 64;
+
+/// Breaks down the inputs of a measurement to figure out why it doesn't match the one in a report.
+pub struct MeasurementExplainer<'a> {
+    pub generator: MeasurementGenerator,
+    pub metadata: &'a ArtifactsMetadata,
+    pub vm_type: VmType,
+}
+
+impl MeasurementExplainer<'_> {
+    /// Explain why the `expected` measurement doesn't match the `reported` one.
+    pub fn explain(self, expected: &[u8], reported: &[u8]) -> Result<MeasurementExplanation, MeasurementHashError> {
+        let Self { generator, metadata, vm_type } = self;
+        let artifacts = [
+            ("OVMF", &generator.ovmf, metadata.ovmf.sha256),
+            ("kernel", &generator.kernel, metadata.cvm.images.resolve(vm_type).kernel.sha256),
+            ("initrd", &generator.initrd, metadata.initrd.sha256),
+        ];
+        let mut components = Vec::new();
+        for (name, path, expected_sha256) in artifacts {
+            let sha256 = hash_file(path).map_err(|e| MeasurementHashError::ReadArtifact(path.clone(), e))?;
+            components.push(ArtifactComponent { name, sha256, expected_sha256 });
+        }
+        let kernel_command_line = generator.kernel_command_line()?;
+
+        let divergence = match components.iter().find(|c| !c.matches()) {
+            Some(component) => Divergence::Artifact(component.name),
+            None => match Self::find_vcpus(&generator, reported)? {
+                Some(vcpus) => Divergence::Vcpus { reported: generator.vcpus, actual: vcpus },
+                None => Divergence::DockerComposeHash,
+            },
+        };
+        Ok(MeasurementExplanation {
+            expected_measurement: expected.to_vec(),
+            reported_measurement: reported.to_vec(),
+            vcpus: generator.vcpus,
+            components,
+            filesystem_root_hash: generator.filesystem_root_hash,
+            docker_compose_hash: generator.docker_compose_hash,
+            kernel_command_line,
+            divergence,
+        })
+    }
+
+    fn find_vcpus(generator: &MeasurementGenerator, reported: &[u8]) -> Result<Option<u32>, MeasurementHashError> {
+        info!("Looking for a vCPU count that reproduces the reported measurement");
+        for vcpus in (1..=MAX_VCPUS).filter(|vcpus| *vcpus != generator.vcpus) {
+            let measurement = MeasurementGenerator { vcpus, ..generator.clone() }.generate()?;
+            if measurement == reported {
+                return Ok(Some(vcpus));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// An artifact that's measured when the CVM boots.
+#[derive(Debug)]
+pub struct ArtifactComponent {
+    pub name: &'static str,
+    pub sha256: [u8; 32],
+    pub expected_sha256: [u8; 32],
+}
+
+impl ArtifactComponent {
+    /// Whether the local file matches the artifacts metadata.
+    pub fn matches(&self) -> bool {
+        self.sha256 == self.expected_sha256
+    }
+}
+
+/// The input that most likely caused a measurement mismatch.
+#[derive(Debug, PartialEq)]
+pub enum Divergence {
+    /// A local artifact doesn't match the one in the artifacts metadata.
+    Artifact(&'static str),
+
+    /// The measurement matches when using a different number of vCPUs than the one the CVM reports.
+    Vcpus { reported: u32, actual: u32 },
+
+    /// Every other input checks out, so the CVM is running a different docker compose file.
+    DockerComposeHash,
+}
+
+/// A per component breakdown of a measurement.
+#[derive(Debug)]
+pub struct MeasurementExplanation {
+    pub expected_measurement: Vec<u8>,
+    pub reported_measurement: Vec<u8>,
+    pub vcpus: u32,
+    pub components: Vec<ArtifactComponent>,
+    pub filesystem_root_hash: [u8; 32],
+    pub docker_compose_hash: [u8; 32],
+    pub kernel_command_line: String,
+    pub divergence: Divergence,
+}
+
+impl fmt::Display for MeasurementExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Measurement mismatch")?;
+        writeln!(f, "  expected: {}", hex::encode(&self.expected_measurement))?;
+        writeln!(f, "  reported: {}", hex::encode(&self.reported_measurement))?;
+        writeln!(f)?;
+        writeln!(f, "Inputs")?;
+        writeln!(f, "  vCPUs: {}", self.vcpus)?;
+        for component in &self.components {
+            let status = match component.matches() {
+                true => "matches artifacts metadata".to_string(),
+                false => format!("artifacts metadata has {}", hex::encode(component.expected_sha256)),
+            };
+            writeln!(f, "  {} sha256: {} ({status})", component.name, hex::encode(component.sha256))?;
+        }
+        writeln!(f, "  filesystem root hash: {}", hex::encode(self.filesystem_root_hash))?;
+        writeln!(f, "  docker compose hash: {}", hex::encode(self.docker_compose_hash))?;
+        writeln!(f, "  kernel command line: {}", self.kernel_command_line)?;
+        writeln!(f)?;
+        match &self.divergence {
+            Divergence::Artifact(name) => {
+                write!(f, "The local {name} doesn't match the artifacts metadata, try removing the artifacts cache")
+            }
+            Divergence::Vcpus { reported, actual } => {
+                write!(f, "The measurement matches when using {actual} vCPUs but the CVM reports having {reported}")
+            }
+            Divergence::DockerComposeHash => {
+                write!(f, "Every other input checks out, the CVM is likely running a different docker compose file")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nilcc_artifacts::metadata::KernelCommandLine;
+    use std::fs;
+
+    #[test]
+    fn artifact_mismatch() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let mut metadata: ArtifactsMetadata =
+            serde_json::from_str(nilcc_test_vectors::metadata()[0].contents).expect("invalid metadata");
+        let write_artifact = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).expect("failed to write");
+            path
+        };
+        let generator = MeasurementGenerator {
+            vcpus: 2,
+            ovmf: write_artifact("OVMF.fd", b"ovmf"),
+            kernel: write_artifact("kernel", b"kernel"),
+            initrd: write_artifact("initrd", b"initrd"),
+            docker_compose_hash: [1; 32],
+            filesystem_root_hash: [2; 32],
+            kernel_args: KernelCommandLine("root={VERITY_ROOT_HASH} compose={DOCKER_COMPOSE_HASH}".into()),
+            cpu_model: Default::default(),
+        };
+        metadata.ovmf.sha256 = Sha256::digest(b"ovmf").into();
+        metadata.cvm.images.cpu.kernel.sha256 = Sha256::digest(b"kernel").into();
+
+        let explainer = MeasurementExplainer { generator, metadata: &metadata, vm_type: VmType::Cpu };
+        let explanation = explainer.explain(&[1; 48], &[2; 48]).expect("failed to explain");
+        assert_eq!(explanation.divergence, Divergence::Artifact("initrd"));
+        assert_eq!(explanation.kernel_command_line, format!("root={} compose={}", "02".repeat(32), "01".repeat(32)));
+        let matches: Vec<_> = explanation.components.iter().map(|c| (c.name, c.matches())).collect();
+        assert_eq!(matches, &[("OVMF", true), ("kernel", true), ("initrd", false)]);
+    }
+}
@@ -0,0 +1,253 @@
+use attestation_report::{
+    identity_token::{IDENTITY_TOKEN_ALGORITHM, IDENTITY_TOKEN_ISSUER, IdentityTokenClaims, IdentityTokenHeader},
+    report_data::ReportData,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use openssl::{
+    pkey::{Id, PKey},
+    sign::Verifier,
+};
+use serde::de::DeserializeOwned;
+
+/// How far ahead of the verifier's clock a token's issue and not before times can be, to account for clock skew
+/// between the CVM and the verifier.
+const MAX_CLOCK_SKEW_SECONDS: u64 = 60;
+
+/// Verifies identity tokens minted by a CVM.
+///
+/// The report data and host data must come from an attestation report that was already verified, and the report data
+/// must bind the token key along with the boot log hash if the report that was fetched binds one. A token that passes
+/// verification was minted by the CVM that report belongs to, for the workload identity the CVM was launched with.
+pub struct IdentityTokenVerifier<'a> {
+    pub report_data: &'a [u8; 64],
+    pub host_data: &'a [u8; 32],
+    pub token_key: [u8; 32],
+    pub boot_log_hash: Option<[u8; 32]>,
+}
+
+impl IdentityTokenVerifier<'_> {
+    /// Verify a token meant for the given audience, as of the given unix timestamp.
+    pub fn verify(&self, token: &str, audience: &str, now: u64) -> Result<IdentityTokenClaims, IdentityTokenError> {
+        let (message, signature) = token.rsplit_once('.').ok_or(IdentityTokenError::Malformed)?;
+        let (header, claims) = message.split_once('.').ok_or(IdentityTokenError::Malformed)?;
+        let header: IdentityTokenHeader = decode_part(header)?;
+        if header.alg != IDENTITY_TOKEN_ALGORITHM {
+            return Err(IdentityTokenError::Algorithm(header.alg));
+        }
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| IdentityTokenError::Malformed)?;
+        let key = PKey::public_key_from_raw_bytes(&self.token_key, Id::ED25519).map_err(IdentityTokenError::Key)?;
+        let mut verifier = Verifier::new_without_digest(&key).map_err(IdentityTokenError::Key)?;
+        if !verifier.verify_oneshot(&signature, message.as_bytes()).unwrap_or(false) {
+            return Err(IdentityTokenError::Signature);
+        }
+
+        let claims: IdentityTokenClaims = decode_part(claims)?;
+        if claims.iss != IDENTITY_TOKEN_ISSUER {
+            return Err(IdentityTokenError::Issuer(claims.iss));
+        }
+        if claims.aud != audience {
+            return Err(IdentityTokenError::Audience(claims.aud));
+        }
+        if claims.exp <= now {
+            return Err(IdentityTokenError::Expired);
+        }
+        let valid_from = claims.nbf.unwrap_or_default().max(claims.iat);
+        if valid_from > now + MAX_CLOCK_SKEW_SECONDS {
+            return Err(IdentityTokenError::NotYetValid);
+        }
+        let identity = claims.workload_identity();
+        if &identity.hash() != self.host_data {
+            return Err(IdentityTokenError::HostData);
+        }
+        let tls_fingerprint = self.report_data[1..33].try_into().expect("invalid slice length");
+        let expected = ReportData {
+            tls_fingerprint,
+            identity: Some(identity),
+            boot_log_hash: self.boot_log_hash,
+            token_key: Some(self.token_key),
+        }
+        .encode();
+        if &expected != self.report_data {
+            return Err(IdentityTokenError::ReportData);
+        }
+        Ok(claims)
+    }
+}
+
+fn decode_part<T: DeserializeOwned>(part: &str) -> Result<T, IdentityTokenError> {
+    let part = URL_SAFE_NO_PAD.decode(part).map_err(|_| IdentityTokenError::Malformed)?;
+    serde_json::from_slice(&part).map_err(|_| IdentityTokenError::Malformed)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IdentityTokenError {
+    #[error("malformed identity token")]
+    Malformed,
+
+    #[error("identity token algorithm is '{0}', expected '{IDENTITY_TOKEN_ALGORITHM}'")]
+    Algorithm(String),
+
+    #[error("invalid identity token key: {0}")]
+    Key(openssl::error::ErrorStack),
+
+    #[error("invalid identity token signature")]
+    Signature,
+
+    #[error("identity token issuer is '{0}', expected '{IDENTITY_TOKEN_ISSUER}'")]
+    Issuer(String),
+
+    #[error("identity token is meant for audience '{0}'")]
+    Audience(String),
+
+    #[error("identity token expired")]
+    Expired,
+
+    #[error("identity token is not valid yet")]
+    NotYetValid,
+
+    #[error("identity token is not bound to the attestation report")]
+    ReportData,
+
+    #[error("identity token is for a workload the CVM wasn't launched for")]
+    HostData,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{pkey::Private, sign::Signer};
+
+    const AUDIENCE: &str = "https://example.com";
+
+    fn make_claims() -> IdentityTokenClaims {
+        IdentityTokenClaims {
+            iss: IDENTITY_TOKEN_ISSUER.into(),
+            sub: "workload".into(),
+            agent_id: "agent".into(),
+            aud: AUDIENCE.into(),
+            iat: 1000,
+            nbf: None,
+            exp: 1300,
+        }
+    }
+
+    fn encode_part<T: serde::Serialize>(part: &T) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(part).expect("failed to serialize"))
+    }
+
+    fn mint(key: &PKey<Private>, claims: &IdentityTokenClaims) -> String {
+        let message = format!("{}.{}", encode_part(&IdentityTokenHeader::default()), encode_part(claims));
+        let mut signer = Signer::new_without_digest(key).expect("failed to create signer");
+        let signature = signer.sign_oneshot_to_vec(message.as_bytes()).expect("failed to sign");
+        format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    struct Fixture {
+        key: PKey<Private>,
+        token_key: [u8; 32],
+        report_data: [u8; 64],
+        host_data: [u8; 32],
+    }
+
+    impl Fixture {
+        fn new(boot_log_hash: Option<[u8; 32]>) -> Self {
+            let key = PKey::generate_ed25519().expect("failed to generate key");
+            let token_key = key.raw_public_key().expect("no public key").try_into().expect("invalid key length");
+            let report_data = ReportData {
+                tls_fingerprint: [1; 32],
+                identity: Some(make_claims().workload_identity()),
+                boot_log_hash,
+                token_key: Some(token_key),
+            }
+            .encode();
+            let host_data = make_claims().workload_identity().hash();
+            Self { key, token_key, report_data, host_data }
+        }
+
+        fn verifier(&self, boot_log_hash: Option<[u8; 32]>) -> IdentityTokenVerifier<'_> {
+            IdentityTokenVerifier {
+                report_data: &self.report_data,
+                host_data: &self.host_data,
+                token_key: self.token_key,
+                boot_log_hash,
+            }
+        }
+    }
+
+    #[test]
+    fn valid() {
+        let fixture = Fixture::new(None);
+        let token = mint(&fixture.key, &make_claims());
+        let claims = fixture.verifier(None).verify(&token, AUDIENCE, 1200).expect("verification failed");
+        assert_eq!(claims, make_claims());
+
+        let fixture = Fixture::new(Some([2; 32]));
+        let token = mint(&fixture.key, &make_claims());
+        fixture.verifier(Some([2; 32])).verify(&token, AUDIENCE, 1200).expect("verification failed");
+    }
+
+    #[test]
+    fn invalid_claims() {
+        let fixture = Fixture::new(None);
+        let verifier = fixture.verifier(None);
+        let token = mint(&fixture.key, &make_claims());
+
+        let err = verifier.verify(&token, "https://other.com", 1200).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::Audience(_)), "{err}");
+
+        let err = verifier.verify(&token, AUDIENCE, 1300).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::Expired), "{err}");
+
+        let claims = IdentityTokenClaims { sub: "other".into(), ..make_claims() };
+        let token = mint(&fixture.key, &claims);
+        let err = verifier.verify(&token, AUDIENCE, 1200).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::HostData), "{err}");
+
+        let token = mint(&fixture.key, &make_claims());
+        let err = fixture.verifier(Some([2; 32])).verify(&token, AUDIENCE, 1200).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::ReportData), "{err}");
+    }
+
+    #[test]
+    fn not_yet_valid() {
+        let fixture = Fixture::new(None);
+        let verifier = fixture.verifier(None);
+
+        let claims = IdentityTokenClaims { iat: 1300, exp: 1600, ..make_claims() };
+        let token = mint(&fixture.key, &claims);
+        let err = verifier.verify(&token, AUDIENCE, 1200).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::NotYetValid), "{err}");
+
+        let claims = IdentityTokenClaims { nbf: Some(1300), ..make_claims() };
+        let token = mint(&fixture.key, &claims);
+        let err = verifier.verify(&token, AUDIENCE, 1200).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::NotYetValid), "{err}");
+
+        // Tokens issued slightly in the future are accepted to account for clock skew.
+        let claims = IdentityTokenClaims { iat: 1230, nbf: Some(1230), exp: 1600, ..make_claims() };
+        let token = mint(&fixture.key, &claims);
+        verifier.verify(&token, AUDIENCE, 1200).expect("verification failed");
+    }
+
+    #[test]
+    fn report_data_with_other_host_data() {
+        // The report binds the identity in the token, but the CVM was launched for another workload.
+        let mut fixture = Fixture::new(None);
+        fixture.host_data = [3; 32];
+        let token = mint(&fixture.key, &make_claims());
+        let err = fixture.verifier(None).verify(&token, AUDIENCE, 1200).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::HostData), "{err}");
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let fixture = Fixture::new(None);
+        let other = Fixture::new(None);
+        let token = mint(&other.key, &make_claims());
+        let err = fixture.verifier(None).verify(&token, AUDIENCE, 1200).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::Signature), "{err}");
+
+        let err = fixture.verifier(None).verify("foo.bar", AUDIENCE, 1200).expect_err("verification succeeded");
+        assert!(matches!(err, IdentityTokenError::Malformed), "{err}");
+    }
+}
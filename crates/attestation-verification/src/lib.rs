@@ -1,12 +1,24 @@
+pub mod artifact_cache;
+pub mod boot_log;
 pub mod certs;
 pub mod error;
+pub mod explain;
+pub mod identity_token;
 pub mod measurement;
+pub mod platform_claims;
+pub mod proof;
 pub mod report;
 pub mod verify;
 
-pub use certs::{CertificateFetcher, Certs, DefaultCertificateFetcher, FetcherError};
+pub use artifact_cache::ArtifactCache;
+pub use boot_log::{BootLogError, BootLogVerifier};
+pub use certs::{CertificateFetcher, Certs, DefaultCertificateFetcher, FetcherError, RetryPolicy};
 pub use error::{ErrorCode, ValidateError};
+pub use explain::{MeasurementExplainer, MeasurementExplanation};
+pub use identity_token::{IdentityTokenError, IdentityTokenVerifier};
 pub use measurement::{MeasurementGenerator, MeasurementHashError};
+pub use platform_claims::{PlatformClaimsError, PlatformClaimsVerifier};
+pub use proof::{ProofBundle, ProofCerts, ProofError, RecordingCertificateFetcher};
 pub use report::{EnvironmentSpec, ReportBundle, ReportBundleError, ReportFetcher, ReportResponse, VmType};
 pub use verify::{ReportVerifier, VerificationError};
 
@@ -1,6 +1,6 @@
 use nilcc_artifacts::{
     VmType,
-    metadata::{ArtifactsMetadata, KernelArgs, KernelCommandLine, MissingCommandLineParameter},
+    metadata::{ArtifactsMetadata, CpuModel, KernelArgs, KernelCommandLine, MissingCommandLineParameter},
 };
 use sev::{
     error::MeasurementError,
@@ -10,10 +10,14 @@ use sev::{
         vmsa::{GuestFeatures, VMMType},
     },
 };
-use std::path::{Path, PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 use tracing::info;
 
 /// Generates the measurement that a CVM should have generated given the parameters.
+#[derive(Clone)]
 pub struct MeasurementGenerator {
     pub vcpus: u32,
     pub ovmf: PathBuf,
@@ -22,6 +26,7 @@ pub struct MeasurementGenerator {
     pub docker_compose_hash: [u8; 32],
     pub filesystem_root_hash: [u8; 32],
     pub kernel_args: KernelCommandLine,
+    pub cpu_model: CpuModel,
 }
 
 impl MeasurementGenerator {
@@ -41,21 +46,27 @@ impl MeasurementGenerator {
             docker_compose_hash,
             filesystem_root_hash: vm_type_metadata.verity.root_hash,
             kernel_args: metadata.cvm.cmdline.clone(),
+            cpu_model: metadata.cpu.model,
         }
     }
 
-    pub fn generate(self) -> Result<Vec<u8>, MeasurementHashError> {
-        let Self { ovmf, kernel, initrd, docker_compose_hash, filesystem_root_hash, vcpus, kernel_args } = self;
-        let docker_compose_hash = hex::encode(docker_compose_hash);
-        let cmdline = kernel_args.render(KernelArgs {
+    /// Render the kernel command line the CVM is booted with.
+    pub fn kernel_command_line(&self) -> Result<String, MissingCommandLineParameter> {
+        let docker_compose_hash = hex::encode(self.docker_compose_hash);
+        self.kernel_args.render(KernelArgs {
             docker_compose_hash: &docker_compose_hash,
-            filesystem_root_hash: &filesystem_root_hash,
-        })?;
+            filesystem_root_hash: &self.filesystem_root_hash,
+        })
+    }
+
+    pub fn generate(self) -> Result<Vec<u8>, MeasurementHashError> {
+        let cmdline = self.kernel_command_line()?;
+        let Self { ovmf, kernel, initrd, vcpus, cpu_model, .. } = self;
         info!("Using kernel parameters for measurement: {cmdline}");
         let guest_features = GuestFeatures(0x01);
         let args = SnpMeasurementArgs {
             vcpus,
-            vcpu_type: CpuType::EpycV4,
+            vcpu_type: vcpu_type(cpu_model),
             ovmf_file: ovmf,
             guest_features,
             kernel_file: Some(kernel),
@@ -69,6 +80,17 @@ impl MeasurementGenerator {
     }
 }
 
+/// The CPU type a model's VMSAs are measured with.
+fn vcpu_type(model: CpuModel) -> CpuType {
+    match model {
+        CpuModel::EpycV4 => CpuType::EpycV4,
+        CpuModel::EpycMilan => CpuType::EpycMilan,
+        CpuModel::EpycMilanV2 => CpuType::EpycMilanV2,
+        CpuModel::EpycGenoa => CpuType::EpycGenoa,
+        CpuModel::EpycGenoaV1 => CpuType::EpycGenoaV1,
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum MeasurementHashError {
     #[error("generating measurement hash: {0}")]
@@ -76,4 +98,7 @@ pub enum MeasurementHashError {
 
     #[error(transparent)]
     KernelArgs(#[from] MissingCommandLineParameter),
+
+    #[error("reading artifact {0}: {1}")]
+    ReadArtifact(PathBuf, io::Error),
 }
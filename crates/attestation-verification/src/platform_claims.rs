@@ -0,0 +1,164 @@
+use attestation_report::{
+    platform_claims::{PlatformClaims, SignedPlatformClaims},
+    report_data::WorkloadIdentity,
+};
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey, EcPoint},
+    ecdsa::EcdsaSig,
+    nid::Nid,
+};
+
+/// Verifies the platform claims the nilcc-agent that launched a CVM signed.
+///
+/// Claims aren't bound into the attestation report, so they're only trusted if they're signed by one of the trusted
+/// agent keys and were issued for the workload identity that a verified report is bound to.
+pub struct PlatformClaimsVerifier<'a> {
+    pub trusted_keys: &'a [Vec<u8>],
+    pub identity: Option<&'a WorkloadIdentity>,
+}
+
+impl PlatformClaimsVerifier<'_> {
+    /// Verify signed claims, returning the claims themselves if they're valid.
+    pub fn verify<'b>(&self, signed: &'b SignedPlatformClaims) -> Result<&'b PlatformClaims, PlatformClaimsError> {
+        let SignedPlatformClaims { claims, public_key, signature } = signed;
+        if !self.trusted_keys.contains(public_key) {
+            return Err(PlatformClaimsError::UntrustedKey(hex::encode(public_key)));
+        }
+        if signature.len() != 64 {
+            return Err(PlatformClaimsError::MalformedSignature);
+        }
+        let group = EcGroup::from_curve_name(Nid::SECP256K1).map_err(PlatformClaimsError::Key)?;
+        let mut context = BigNumContext::new().map_err(PlatformClaimsError::Key)?;
+        let point = EcPoint::from_bytes(&group, public_key, &mut context).map_err(PlatformClaimsError::Key)?;
+        let key = EcKey::from_public_key(&group, &point).map_err(PlatformClaimsError::Key)?;
+        let r = BigNum::from_slice(&signature[..32]).map_err(|_| PlatformClaimsError::MalformedSignature)?;
+        let s = BigNum::from_slice(&signature[32..]).map_err(|_| PlatformClaimsError::MalformedSignature)?;
+        let signature = EcdsaSig::from_private_components(r, s).map_err(|_| PlatformClaimsError::MalformedSignature)?;
+        if !signature.verify(&claims.digest(), &key).unwrap_or(false) {
+            return Err(PlatformClaimsError::Signature);
+        }
+
+        let identity = self.identity.ok_or(PlatformClaimsError::Unbound)?;
+        if claims.workload_id != identity.workload_id || claims.metal_instance_id != identity.agent_id {
+            return Err(PlatformClaimsError::Identity {
+                workload_id: claims.workload_id.clone(),
+                metal_instance_id: claims.metal_instance_id.clone(),
+            });
+        }
+        Ok(claims)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlatformClaimsError {
+    #[error("platform claims are signed by untrusted key {0}")]
+    UntrustedKey(String),
+
+    #[error("invalid platform claims key: {0}")]
+    Key(openssl::error::ErrorStack),
+
+    #[error("malformed platform claims signature")]
+    MalformedSignature,
+
+    #[error("invalid platform claims signature")]
+    Signature,
+
+    #[error("report is not bound to a workload identity so platform claims can't be checked against it")]
+    Unbound,
+
+    #[error("platform claims are for workload {workload_id} on metal instance {metal_instance_id}")]
+    Identity { workload_id: String, metal_instance_id: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{ec::PointConversionForm, pkey::Private};
+
+    fn make_identity() -> WorkloadIdentity {
+        WorkloadIdentity { workload_id: "workload".into(), agent_id: "agent".into() }
+    }
+
+    fn make_claims() -> PlatformClaims {
+        PlatformClaims {
+            workload_id: "workload".into(),
+            metal_instance_id: "agent".into(),
+            region: "us-east-1".into(),
+            agent_version: "1.0.0".into(),
+        }
+    }
+
+    struct Fixture {
+        key: EcKey<Private>,
+        public_key: Vec<u8>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let group = EcGroup::from_curve_name(Nid::SECP256K1).expect("no curve");
+            let key = EcKey::generate(&group).expect("failed to generate key");
+            let mut context = BigNumContext::new().expect("failed to create context");
+            let public_key = key
+                .public_key()
+                .to_bytes(&group, PointConversionForm::COMPRESSED, &mut context)
+                .expect("failed to serialize key");
+            Self { key, public_key }
+        }
+
+        fn sign(&self, claims: PlatformClaims) -> SignedPlatformClaims {
+            let signature = EcdsaSig::sign(&claims.digest(), &self.key).expect("failed to sign");
+            let mut serialized = signature.r().to_vec_padded(32).expect("invalid r");
+            serialized.extend(signature.s().to_vec_padded(32).expect("invalid s"));
+            SignedPlatformClaims { claims, public_key: self.public_key.clone(), signature: serialized }
+        }
+    }
+
+    #[test]
+    fn valid() {
+        let fixture = Fixture::new();
+        let identity = make_identity();
+        let trusted_keys = [fixture.public_key.clone()];
+        let verifier = PlatformClaimsVerifier { trusted_keys: &trusted_keys, identity: Some(&identity) };
+        let signed = fixture.sign(make_claims());
+        let claims = verifier.verify(&signed).expect("verification failed");
+        assert_eq!(claims, &make_claims());
+    }
+
+    #[test]
+    fn untrusted_key() {
+        let fixture = Fixture::new();
+        let other = Fixture::new();
+        let identity = make_identity();
+        let trusted_keys = [other.public_key.clone()];
+        let verifier = PlatformClaimsVerifier { trusted_keys: &trusted_keys, identity: Some(&identity) };
+        let err = verifier.verify(&fixture.sign(make_claims())).expect_err("verification succeeded");
+        assert!(matches!(err, PlatformClaimsError::UntrustedKey(_)), "{err}");
+
+        let mut signed = fixture.sign(make_claims());
+        signed.public_key = other.public_key.clone();
+        let err = verifier.verify(&signed).expect_err("verification succeeded");
+        assert!(matches!(err, PlatformClaimsError::Signature), "{err}");
+    }
+
+    #[test]
+    fn invalid_claims() {
+        let fixture = Fixture::new();
+        let identity = make_identity();
+        let trusted_keys = [fixture.public_key.clone()];
+        let verifier = PlatformClaimsVerifier { trusted_keys: &trusted_keys, identity: Some(&identity) };
+
+        let mut signed = fixture.sign(make_claims());
+        signed.claims.region = "eu-west-1".into();
+        let err = verifier.verify(&signed).expect_err("verification succeeded");
+        assert!(matches!(err, PlatformClaimsError::Signature), "{err}");
+
+        let signed = fixture.sign(PlatformClaims { workload_id: "other".into(), ..make_claims() });
+        let err = verifier.verify(&signed).expect_err("verification succeeded");
+        assert!(matches!(err, PlatformClaimsError::Identity { .. }), "{err}");
+
+        let verifier = PlatformClaimsVerifier { trusted_keys: &trusted_keys, identity: None };
+        let err = verifier.verify(&fixture.sign(make_claims())).expect_err("verification succeeded");
+        assert!(matches!(err, PlatformClaimsError::Unbound), "{err}");
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{
+    certs::{CertificateFetcher, Certs, FetcherError},
+    measurement::{MeasurementGenerator, MeasurementHashError},
+    report::{ReportBundle, VmType},
+    verify::{Processor, ReportVerifier, VerificationError},
+};
+use async_trait::async_trait;
+use attestation_report::report_data::{ReportData, WorkloadIdentity};
+use nilcc_artifacts::metadata::{ArtifactsMetadata, GuestPolicy};
+use serde::{Deserialize, Serialize};
+use sev::{
+    certs::snp::{Certificate, ca::Chain},
+    firmware::guest::AttestationReport,
+    parser::ByteParser,
+};
+use std::{
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tracing::info;
+
+/// The current proof bundle format version.
+pub const PROOF_BUNDLE_VERSION: u32 = 1;
+
+/// Everything needed to re-verify an attestation offline.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProofBundle {
+    /// The proof bundle format version.
+    pub version: u32,
+
+    /// The endpoint the report was fetched from.
+    pub endpoint: String,
+
+    /// The raw attestation report.
+    #[serde(with = "hex::serde")]
+    pub report: Vec<u8>,
+
+    /// The AMD certificates used to verify the report. The ARK is checked against AMD's root key when verifying.
+    pub certs: ProofCerts,
+
+    /// The fingerprint of the public key in the CVM's TLS certificate, which the report is bound to.
+    pub tls_fingerprint: String,
+
+    /// The identity of the workload the report is bound to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<WorkloadIdentity>,
+
+    /// The artifacts the CVM was booted with.
+    pub artifacts: ProofArtifacts,
+
+    /// The inputs used to generate the expected measurement.
+    pub measurement: MeasurementInputs,
+}
+
+impl ProofBundle {
+    /// Build a proof bundle out of a report bundle that was already verified.
+    pub fn new(
+        endpoint: String,
+        bundle: ReportBundle,
+        certs: ProofCerts,
+        generator: &MeasurementGenerator,
+        measurement: Vec<u8>,
+    ) -> Result<Self, ProofError> {
+        let ReportBundle { report, metadata, metadata_hash, tls_fingerprint, nilcc_version, vm_type, identity, .. } =
+            bundle;
+        let report = report.to_bytes().map_err(ProofError::SerializeReport)?;
+        let measurement = MeasurementInputs {
+            measurement,
+            vm_type,
+            vcpus: generator.vcpus,
+            docker_compose_hash: generator.docker_compose_hash,
+            filesystem_root_hash: generator.filesystem_root_hash,
+            kernel_command_line: generator.kernel_command_line().map_err(MeasurementHashError::from)?,
+        };
+        Ok(Self {
+            version: PROOF_BUNDLE_VERSION,
+            endpoint,
+            report,
+            certs,
+            tls_fingerprint,
+            identity,
+            artifacts: ProofArtifacts { version: nilcc_version, metadata_hash, metadata },
+            measurement,
+        })
+    }
+
+    /// Verify this proof without any network access.
+    ///
+    /// If `artifacts_path` is set, the measurement is regenerated using the artifacts in it rather than trusting the
+    /// one in the bundle. The guest policy in the bundled artifacts metadata is not trusted, the report is instead
+    /// checked against `required_policy`.
+    pub async fn verify(&self, artifacts_path: Option<&Path>, required_policy: &GuestPolicy) -> Result<(), ProofError> {
+        if self.version != PROOF_BUNDLE_VERSION {
+            return Err(ProofError::UnsupportedVersion(self.version));
+        }
+        let report = AttestationReport::from_bytes(&self.report).map_err(ProofError::MalformedReport)?;
+
+        let mut tls_fingerprint = [0; 32];
+        hex::decode_to_slice(&self.tls_fingerprint, &mut tls_fingerprint)
+            .map_err(|_| ProofError::MalformedTlsFingerprint)?;
+        let expected_report_data =
+            ReportData { tls_fingerprint, identity: self.identity.clone(), boot_log_hash: None, token_key: None }
+                .encode();
+        if report.report_data.as_slice() != expected_report_data {
+            return Err(ProofError::ReportData {
+                expected: hex::encode(expected_report_data),
+                actual: hex::encode(report.report_data),
+            });
+        }
+        // Like when fetching reports, the identity is only trusted if the CVM was launched with it as its host data.
+        if let Some(identity) = &self.identity
+            && report.host_data != identity.hash()
+        {
+            return Err(ProofError::HostData {
+                expected: hex::encode(identity.hash()),
+                actual: hex::encode(report.host_data),
+            });
+        }
+
+        let measurement = match artifacts_path {
+            Some(path) => {
+                let inputs = &self.measurement;
+                info!("Regenerating measurement using artifacts in {}", path.display());
+                let generator = MeasurementGenerator::new(
+                    inputs.docker_compose_hash,
+                    inputs.vcpus,
+                    inputs.vm_type.into(),
+                    &self.artifacts.metadata,
+                    path,
+                );
+                let measurement = generator.generate()?;
+                if measurement != inputs.measurement {
+                    return Err(ProofError::MeasurementMismatch {
+                        expected: hex::encode(&inputs.measurement),
+                        actual: hex::encode(measurement),
+                    });
+                }
+                measurement
+            }
+            None => self.measurement.measurement.clone(),
+        };
+
+        let certs = self.certs.decode()?;
+        let verifier = ReportVerifier::new(Arc::new(BundledCertificateFetcher(certs)));
+        verifier.verify_report(&report, &measurement, required_policy).await?;
+        Ok(())
+    }
+}
+
+/// The AMD certificates used to verify a report, DER encoded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProofCerts {
+    #[serde(with = "hex::serde")]
+    pub ark: Vec<u8>,
+
+    #[serde(with = "hex::serde")]
+    pub ask: Vec<u8>,
+
+    #[serde(with = "hex::serde")]
+    pub vcek: Vec<u8>,
+}
+
+impl ProofCerts {
+    fn encode(certs: &Certs) -> io::Result<Self> {
+        Ok(Self { ark: certs.chain.ark.to_der()?, ask: certs.chain.ask.to_der()?, vcek: certs.vcek.to_der()? })
+    }
+
+    fn decode(&self) -> Result<Certs, ProofError> {
+        let parse = |name, bytes: &[u8]| Certificate::from_der(bytes).map_err(|e| ProofError::MalformedCert(name, e));
+        let chain = Chain { ark: parse("ARK", &self.ark)?, ask: parse("ASK", &self.ask)? };
+        Ok(Certs { chain, vcek: parse("VCEK", &self.vcek)? })
+    }
+}
+
+/// The artifacts a CVM was booted with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProofArtifacts {
+    /// The artifacts version.
+    pub version: String,
+
+    /// The hash of the artifacts metadata file.
+    #[serde(with = "hex::serde")]
+    pub metadata_hash: [u8; 32],
+
+    /// The artifacts metadata.
+    pub metadata: ArtifactsMetadata,
+}
+
+/// The inputs that went into a measurement.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeasurementInputs {
+    /// The expected measurement.
+    #[serde(with = "hex::serde")]
+    pub measurement: Vec<u8>,
+
+    /// The type of VM the CVM runs as.
+    pub vm_type: VmType,
+
+    /// The number of vCPUs the CVM has.
+    pub vcpus: u32,
+
+    /// The hash of the docker compose file the CVM runs.
+    #[serde(with = "hex::serde")]
+    pub docker_compose_hash: [u8; 32],
+
+    /// The root hash of the CVM's filesystem.
+    #[serde(with = "hex::serde")]
+    pub filesystem_root_hash: [u8; 32],
+
+    /// The rendered kernel command line.
+    pub kernel_command_line: String,
+}
+
+/// A certificate fetcher that keeps a copy of the certificates fetched by another one.
+///
+/// This is used when exporting proofs so the exact same certificates used during verification end up in the bundle.
+pub struct RecordingCertificateFetcher {
+    inner: Arc<dyn CertificateFetcher>,
+    certs: Mutex<Option<ProofCerts>>,
+}
+
+impl RecordingCertificateFetcher {
+    pub fn new(inner: Arc<dyn CertificateFetcher>) -> Self {
+        Self { inner, certs: Default::default() }
+    }
+
+    /// Take the last certificates that were fetched.
+    pub fn take_certs(&self) -> Option<ProofCerts> {
+        self.certs.lock().expect("lock poisoned").take()
+    }
+}
+
+#[async_trait]
+impl CertificateFetcher for RecordingCertificateFetcher {
+    async fn fetch_certs(&self, processor: &Processor, report: &AttestationReport) -> Result<Certs, FetcherError> {
+        let certs = self.inner.fetch_certs(processor, report).await?;
+        let encoded = ProofCerts::encode(&certs).map_err(FetcherError::EncodeCert)?;
+        *self.certs.lock().expect("lock poisoned") = Some(encoded);
+        Ok(certs)
+    }
+}
+
+struct BundledCertificateFetcher(Certs);
+
+#[async_trait]
+impl CertificateFetcher for BundledCertificateFetcher {
+    async fn fetch_certs(&self, _processor: &Processor, _report: &AttestationReport) -> Result<Certs, FetcherError> {
+        Ok(Certs { chain: self.0.chain.clone(), vcek: self.0.vcek.clone() })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProofError {
+    #[error("unsupported proof bundle version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("serializing report: {0}")]
+    SerializeReport(io::Error),
+
+    #[error("malformed report: {0}")]
+    MalformedReport(io::Error),
+
+    #[error("malformed {0} certificate: {1}")]
+    MalformedCert(&'static str, io::Error),
+
+    #[error("malformed TLS fingerprint")]
+    MalformedTlsFingerprint,
+
+    #[error("report data does not match the bundled TLS fingerprint and identity, expected {expected}, got {actual}")]
+    ReportData { expected: String, actual: String },
+
+    #[error("CVM wasn't launched with the workload identity it reports, expected host data {expected}, got {actual}")]
+    HostData { expected: String, actual: String },
+
+    #[error("measurement generated from artifacts does not match the bundled one, expected {expected}, got {actual}")]
+    MeasurementMismatch { expected: String, actual: String },
+
+    #[error(transparent)]
+    MeasurementHash(#[from] MeasurementHashError),
+
+    #[error("verifying report: {0}")]
+    VerifyReport(#[from] VerificationError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::CertificateValidationError;
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::{PKey, Private},
+        x509::X509,
+    };
+
+    fn make_bundle() -> ProofBundle {
+        let vector = nilcc_test_vectors::report("genoa");
+        let report: AttestationReport =
+            serde_json::from_value::<attestation_report::v2::AttestationReport>(vector.report.clone())
+                .expect("failed to parse")
+                .into();
+        let metadata = serde_json::from_str(nilcc_test_vectors::metadata()[0].contents).expect("invalid metadata");
+        ProofBundle {
+            version: PROOF_BUNDLE_VERSION,
+            endpoint: "https://example.com".into(),
+            report: report.to_bytes().expect("failed to serialize"),
+            certs: ProofCerts { ark: vec![], ask: vec![], vcek: vec![] },
+            tls_fingerprint: vector.tls_fingerprint.clone(),
+            identity: None,
+            artifacts: ProofArtifacts { version: "0.1.0".into(), metadata_hash: [1; 32], metadata },
+            measurement: MeasurementInputs {
+                measurement: report.measurement.to_vec(),
+                vm_type: VmType::Cpu,
+                vcpus: 1,
+                docker_compose_hash: [2; 32],
+                filesystem_root_hash: [3; 32],
+                kernel_command_line: "console=ttyS0".into(),
+            },
+        }
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let bundle = make_bundle();
+        let serialized = serde_json::to_string(&bundle).expect("failed to serialize");
+        let deserialized: ProofBundle = serde_json::from_str(&serialized).expect("failed to deserialize");
+        assert_eq!(deserialized.report, bundle.report);
+        assert_eq!(deserialized.measurement.docker_compose_hash, bundle.measurement.docker_compose_hash);
+    }
+
+    #[tokio::test]
+    async fn unsupported_version() {
+        let bundle = ProofBundle { version: PROOF_BUNDLE_VERSION + 1, ..make_bundle() };
+        let err = bundle.verify(None, &GuestPolicy::default()).await.expect_err("verification succeeded");
+        assert!(matches!(err, ProofError::UnsupportedVersion(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn tls_fingerprint_mismatch() {
+        let bundle = ProofBundle { tls_fingerprint: hex::encode([0; 32]), ..make_bundle() };
+        let err = bundle.verify(None, &GuestPolicy::default()).await.expect_err("verification succeeded");
+        assert!(matches!(err, ProofError::ReportData { .. }), "{err}");
+    }
+
+    #[tokio::test]
+    async fn host_data_mismatch() {
+        let bundle = make_bundle();
+        let identity = WorkloadIdentity { workload_id: "workload".into(), agent_id: "agent".into() };
+        // Bind the report data to the identity so only the host data gives it away.
+        let mut report = AttestationReport::from_bytes(&bundle.report).expect("failed to parse");
+        let mut tls_fingerprint = [0; 32];
+        hex::decode_to_slice(&bundle.tls_fingerprint, &mut tls_fingerprint).expect("invalid fingerprint");
+        let report_data =
+            ReportData { tls_fingerprint, identity: Some(identity.clone()), boot_log_hash: None, token_key: None };
+        report.report_data.copy_from_slice(&report_data.encode());
+        let report = report.to_bytes().expect("failed to serialize");
+        let bundle = ProofBundle { report, identity: Some(identity), ..bundle };
+        let err = bundle.verify(None, &GuestPolicy::default()).await.expect_err("verification succeeded");
+        assert!(matches!(err, ProofError::HostData { .. }), "{err}");
+    }
+
+    #[tokio::test]
+    async fn malformed_certs() {
+        let err = make_bundle().verify(None, &GuestPolicy::default()).await.expect_err("verification succeeded");
+        assert!(matches!(err, ProofError::MalformedCert("ARK", _)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn forged_certs() {
+        let make_key = || {
+            let group = EcGroup::from_curve_name(Nid::SECP384R1).expect("invalid curve name");
+            PKey::try_from(EcKey::generate(&group).expect("failed to generate key")).expect("failed to convert key")
+        };
+        let make_cert = |signer: &PKey<Private>, owner: &PKey<Private>| {
+            let mut builder = X509::builder().expect("failed to create builder");
+            builder.set_pubkey(owner).expect("failed to set pubkey");
+            builder.sign(signer, MessageDigest::sha384()).expect("failed to sign");
+            builder.build().to_der().expect("failed to encode")
+        };
+        let (ark, ask, vcek) = (make_key(), make_key(), make_key());
+        let certs = ProofCerts { ark: make_cert(&ark, &ark), ask: make_cert(&ark, &ask), vcek: make_cert(&ask, &vcek) };
+        let bundle = ProofBundle { certs, ..make_bundle() };
+        let err = bundle.verify(None, &GuestPolicy::default()).await.expect_err("verification succeeded");
+        assert!(
+            matches!(
+                err,
+                ProofError::VerifyReport(VerificationError::CertVerification(
+                    CertificateValidationError::UntrustedArk(_)
+                ))
+            ),
+            "{err}"
+        );
+    }
+}
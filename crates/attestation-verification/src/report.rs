@@ -1,19 +1,23 @@
+use crate::artifact_cache::ArtifactCache;
 use async_trait::async_trait;
+use attestation_report::{
+    boot_log::BootLog,
+    platform_claims::SignedPlatformClaims,
+    report_data::{ReportData, WorkloadIdentity},
+};
 use clap::ValueEnum;
 use nilcc_artifacts::{
     Artifacts,
     downloader::{ArtifactsDownloader, DownloadError},
     metadata::ArtifactsMetadata,
+    signature::SigningKey,
 };
 use reqwest::{ClientBuilder, Url, tls::TlsInfo};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sev::firmware::guest::AttestationReport;
 use sha2::{Digest, Sha256};
-use std::{
-    path::{Path, PathBuf},
-    time::Duration,
-};
-use tracing::info;
+use std::{io, path::Path, time::Duration};
+use tracing::{info, warn};
 use x509_parser::parse_x509_certificate;
 
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
@@ -22,6 +26,12 @@ const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 pub struct ReportResponse {
     pub report: attestation_report::v2::AttestationReport,
     pub environment: EnvironmentSpec,
+    #[serde(default)]
+    pub gpu_token: Option<String>,
+    #[serde(default)]
+    pub boot_log: Option<String>,
+    #[serde(default)]
+    pub platform_claims: Option<SignedPlatformClaims>,
 }
 
 #[derive(Deserialize)]
@@ -29,9 +39,22 @@ pub struct EnvironmentSpec {
     pub nilcc_version: String,
     pub vm_type: VmType,
     pub cpu_count: u32,
+    #[serde(default)]
+    pub workload_id: Option<String>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, ValueEnum)]
+impl EnvironmentSpec {
+    /// The identity of the workload, if the CVM reports one.
+    pub fn workload_identity(&self) -> Option<WorkloadIdentity> {
+        let workload_id = self.workload_id.clone()?;
+        let agent_id = self.agent_id.clone()?;
+        Some(WorkloadIdentity { workload_id, agent_id })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, ValueEnum)]
 #[serde(rename_all = "snake_case")]
 pub enum VmType {
     Gpu,
@@ -48,18 +71,25 @@ impl From<VmType> for nilcc_artifacts::VmType {
 }
 
 pub struct ReportFetcher {
-    cache_path: PathBuf,
+    cache: ArtifactCache,
     artifacts_url: String,
     artifacts_downloader: Box<dyn ReportArtifactsDownloader>,
+    include_boot_log: bool,
 }
 
 impl ReportFetcher {
     pub fn new(
-        cache_path: PathBuf,
+        cache: ArtifactCache,
         artifacts_url: String,
         artifacts_downloader: Box<dyn ReportArtifactsDownloader>,
     ) -> Self {
-        Self { cache_path, artifacts_url, artifacts_downloader }
+        Self { cache, artifacts_url, artifacts_downloader, include_boot_log: false }
+    }
+
+    /// Request a report that binds the CVM's boot log.
+    pub fn with_boot_log(mut self) -> Self {
+        self.include_boot_log = true;
+        self
     }
 
     pub async fn fetch_report(&self, base_url: &str) -> Result<ReportBundle, ReportBundleError> {
@@ -69,7 +99,7 @@ impl ReportFetcher {
             return Err(ReportBundleError::NotHttpsScheme);
         }
         url.set_path("/nilcc/api/v2/report");
-        url.set_query(None);
+        url.set_query(self.include_boot_log.then_some("include_boot_log=true"));
 
         info!("Fetching report from {url}");
         let response =
@@ -79,41 +109,92 @@ impl ReportFetcher {
         let cert = info.peer_certificate().ok_or(ReportBundleError::NoTlsInfo)?;
         let (_, cert) = parse_x509_certificate(cert).map_err(ReportBundleError::TlsCertificate)?;
         let pubkey = cert.tbs_certificate.subject_pki;
-        let cert_fingerprint = Sha256::digest(pubkey.raw);
-        let mut expected_report_data: [u8; 64] = [0; 64];
-        expected_report_data[1..33].copy_from_slice(&cert_fingerprint);
+        let cert_fingerprint: [u8; 32] = Sha256::digest(pubkey.raw).into();
 
-        let ReportResponse { report, environment } =
+        let ReportResponse { report, environment, gpu_token, boot_log, platform_claims } =
             response.json().await.map_err(ReportBundleError::MalformedPayload)?;
         let report = AttestationReport::from(report);
-        if report.report_data.as_slice() != expected_report_data {
+        let identity = environment.workload_identity();
+        let boot_log_hash = match (self.include_boot_log, &boot_log) {
+            (true, Some(boot_log)) => Some(BootLog::hash(boot_log.as_bytes())),
+            (true, None) => return Err(ReportBundleError::MissingBootLog),
+            (false, _) => None,
+        };
+        let expected_report_data = ReportData {
+            tls_fingerprint: cert_fingerprint,
+            identity: identity.clone(),
+            boot_log_hash,
+            token_key: None,
+        }
+        .encode();
+        if report.report_data[1..33] != cert_fingerprint {
             return Err(ReportBundleError::TlsFingerprint {
                 expected: hex::encode(expected_report_data),
                 actual: hex::encode(report.report_data),
             });
         }
         info!("Report contains expected TLS fingerprint: {}", hex::encode(cert_fingerprint));
+        // This also catches a CVM that claims a different identity than the one its report was generated for.
+        if report.report_data.as_slice() != expected_report_data {
+            return Err(ReportBundleError::WorkloadIdentity {
+                expected: hex::encode(expected_report_data),
+                actual: hex::encode(report.report_data),
+            });
+        }
+        // The report data is whatever the host asked for at bootstrap, so the identity is only trusted if the CVM was
+        // also launched with it as its host data, which can't be changed afterwards.
+        if let Some(identity) = &identity
+            && report.host_data != identity.hash()
+        {
+            return Err(ReportBundleError::HostData {
+                expected: hex::encode(identity.hash()),
+                actual: hex::encode(report.host_data),
+            });
+        }
+        match &identity {
+            Some(WorkloadIdentity { workload_id, agent_id }) => {
+                info!("Report is bound to workload {workload_id} running on agent {agent_id}")
+            }
+            None => info!("Report is not bound to a workload identity"),
+        };
+        let boot_log = match boot_log_hash.and(boot_log) {
+            Some(boot_log) => {
+                info!("Report is bound to a boot log");
+                Some(serde_json::from_str(&boot_log).map_err(ReportBundleError::MalformedBootLog)?)
+            }
+            None => None,
+        };
 
-        let EnvironmentSpec { nilcc_version, vm_type, cpu_count } = environment;
+        let EnvironmentSpec { nilcc_version, vm_type, cpu_count, .. } = environment;
         info!("CVM is running nilcc-version {nilcc_version}, using VM type '{vm_type:?}' and has {cpu_count} CPUs");
 
-        // Create the cache directory if it doesn't exist already.
-        let download_path = self.cache_path.join(&nilcc_version);
-
-        info!("Downloading artifacts, using {} as cache", self.cache_path.display());
+        // Other processes sharing the cache may be downloading the same version.
+        let cached = self.cache.lock(&nilcc_version).await.map_err(ReportBundleError::LockArtifacts)?;
+        info!("Downloading artifacts, using {} as cache", cached.path().display());
         let artifacts = self
             .artifacts_downloader
-            .download(nilcc_version.clone(), vm_type, self.artifacts_url.clone(), &download_path)
+            .download(nilcc_version.clone(), vm_type, self.artifacts_url.clone(), cached.path())
             .await?;
-        let Artifacts { metadata, metadata_hash } = artifacts;
+        match self.cache.evict() {
+            Ok(evicted) if !evicted.is_empty() => info!("Evicted cached artifacts versions {evicted:?}"),
+            Ok(_) => (),
+            Err(e) => warn!("Failed to evict cached artifacts: {e}"),
+        };
+        drop(cached);
+        let Artifacts { metadata, metadata_hash, signer } = artifacts;
         Ok(ReportBundle {
             report,
             metadata,
             metadata_hash,
+            metadata_signer: signer,
             cpu_count,
             tls_fingerprint: hex::encode(cert_fingerprint),
             nilcc_version,
             vm_type,
+            identity,
+            gpu_token,
+            boot_log,
+            platform_claims,
         })
     }
 }
@@ -129,7 +210,17 @@ pub trait ReportArtifactsDownloader: Send + Sync + 'static {
     ) -> Result<Artifacts, DownloadError>;
 }
 
-pub struct DefaultReportArtifactsDownloader;
+#[derive(Default)]
+pub struct DefaultReportArtifactsDownloader {
+    signing_keys: Vec<SigningKey>,
+}
+
+impl DefaultReportArtifactsDownloader {
+    /// Construct a downloader that requires the artifacts metadata to be signed by one of the given keys.
+    pub fn new(signing_keys: Vec<SigningKey>) -> Self {
+        Self { signing_keys }
+    }
+}
 
 #[async_trait]
 impl ReportArtifactsDownloader for DefaultReportArtifactsDownloader {
@@ -143,7 +234,8 @@ impl ReportArtifactsDownloader for DefaultReportArtifactsDownloader {
         let downloader = ArtifactsDownloader::new(nilcc_version, vec![vm_type.into()])
             .without_disk_images()
             .without_artifact_overwrite()
-            .with_artifacts_url(artifacts_url);
+            .with_artifacts_url(artifacts_url)
+            .with_signing_keys(self.signing_keys.clone());
         downloader.download(download_path).await
     }
 }
@@ -171,9 +263,24 @@ pub enum ReportBundleError {
     #[error("invalid TLS fingerprint, expected {expected}, got {actual}")]
     TlsFingerprint { expected: String, actual: String },
 
+    #[error("report is not bound to the workload identity the CVM reports, expected {expected}, got {actual}")]
+    WorkloadIdentity { expected: String, actual: String },
+
+    #[error("CVM wasn't launched with the workload identity it reports, expected host data {expected}, got {actual}")]
+    HostData { expected: String, actual: String },
+
     #[error("malformed JSON payload: {0}")]
     MalformedPayload(reqwest::Error),
 
+    #[error("the CVM did not return a boot log")]
+    MissingBootLog,
+
+    #[error("malformed boot log: {0}")]
+    MalformedBootLog(serde_json::Error),
+
+    #[error("failed to lock cached artifacts: {0}")]
+    LockArtifacts(io::Error),
+
     #[error("failed to download artifacts: {0}")]
     DownloadArtifacts(#[from] DownloadError),
 }
@@ -184,7 +291,12 @@ pub struct ReportBundle {
     pub metadata: ArtifactsMetadata,
     pub cpu_count: u32,
     pub metadata_hash: [u8; 32],
+    pub metadata_signer: Option<String>,
     pub tls_fingerprint: String,
     pub nilcc_version: String,
     pub vm_type: VmType,
+    pub identity: Option<WorkloadIdentity>,
+    pub gpu_token: Option<String>,
+    pub boot_log: Option<BootLog>,
+    pub platform_claims: Option<SignedPlatformClaims>,
 }
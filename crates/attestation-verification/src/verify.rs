@@ -1,9 +1,10 @@
 use crate::certs::{CertificateFetcher, Certs, FetcherError};
 use clap::ValueEnum;
+use nilcc_artifacts::metadata::GuestPolicy;
 use openssl::{ecdsa::EcdsaSig, sha::Sha384};
 use serde::Deserialize;
 use sev::{
-    certs::snp::{Certificate, Verifiable},
+    certs::snp::{Certificate, Verifiable, builtin},
     firmware::{guest::AttestationReport, host::CertType},
     parser::ByteParser,
 };
@@ -26,11 +27,19 @@ impl ReportVerifier {
         Self { fetcher }
     }
 
-    pub async fn verify_report(&self, report: &AttestationReport, measurement: &[u8]) -> Result<(), VerificationError> {
+    /// Verify a report, ensuring it matches the expected measurement and the guest wasn't launched with a policy weaker
+    /// than the required one.
+    pub async fn verify_report(
+        &self,
+        report: &AttestationReport,
+        measurement: &[u8],
+        required_policy: &GuestPolicy,
+    ) -> Result<(), VerificationError> {
         let processor = Self::detect_processor(report)?;
         info!("Using processor model {processor:?} for verification");
 
         let certs = self.fetcher.fetch_certs(&processor, report).await?;
+        Self::verify_ark(&processor, &certs.chain.ark)?;
         Self::verify_certs(&certs)?;
 
         if report.measurement.as_slice() != measurement {
@@ -43,13 +52,20 @@ impl ReportVerifier {
 
         Self::verify_report_signature(&certs.vcek, report)?;
         Self::verify_attestation_tcb(&certs.vcek, report, &processor)?;
-        if report.policy.debug_allowed() {
-            return Err(VerificationError::DebugAllowed);
-        }
+        Self::verify_guest_policy(report, required_policy)?;
         info!("Verification successful");
         Ok(())
     }
 
+    fn verify_guest_policy(report: &AttestationReport, required: &GuestPolicy) -> Result<(), VerificationError> {
+        let actual = GuestPolicy::from_bits(report.policy.0);
+        if let Some(property) = actual.weaker_than(required) {
+            return Err(VerificationError::WeakGuestPolicy { property, expected: *required, actual });
+        }
+        info!("Guest policy {actual} satisfies required policy {required}");
+        Ok(())
+    }
+
     fn detect_processor(report: &AttestationReport) -> Result<Processor, VerificationError> {
         info!("Detecting processor type based on attestation report");
         match Processor::try_from(report) {
@@ -62,6 +78,16 @@ impl ReportVerifier {
         }
     }
 
+    /// Ensure the ARK is AMD's root key for this processor, regardless of where the certificates were fetched from.
+    fn verify_ark(processor: &Processor, ark: &Certificate) -> Result<(), CertificateValidationError> {
+        let failure = |e: io::Error| CertificateValidationError::VerificationFailure("ARK", e.to_string());
+        let trusted = processor.trusted_ark().map_err(failure)?;
+        if ark.to_der().map_err(failure)? != trusted.to_der().map_err(failure)? {
+            return Err(CertificateValidationError::UntrustedArk(processor.clone()));
+        }
+        Ok(())
+    }
+
     fn verify_certs(certs: &Certs) -> Result<(), CertificateValidationError> {
         let ark = &certs.chain.ark;
         let ask = &certs.chain.ask;
@@ -265,6 +291,15 @@ impl Processor {
             Processor::Turin => "Turin",
         }
     }
+
+    /// The AMD root key certificate for this processor's family.
+    pub(crate) fn trusted_ark(&self) -> io::Result<Certificate> {
+        match self {
+            Processor::Genoa | Processor::Siena | Processor::Bergamo => builtin::genoa::ark(),
+            Processor::Milan => builtin::milan::ark(),
+            Processor::Turin => builtin::turin::ark(),
+        }
+    }
 }
 
 impl TryFrom<&AttestationReport> for Processor {
@@ -334,8 +369,8 @@ pub enum VerificationError {
     #[error("invalid AMD certificate: {0}")]
     InvalidCertificate(&'static str),
 
-    #[error("debug mode allowed")]
-    DebugAllowed,
+    #[error("guest policy {actual} is weaker than required policy {expected}: '{property}' is allowed")]
+    WeakGuestPolicy { property: &'static str, expected: GuestPolicy, actual: GuestPolicy },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -383,6 +418,9 @@ impl SnpOid {
 
 #[derive(thiserror::Error, Debug, PartialEq)]
 pub enum CertificateValidationError {
+    #[error("ARK is not AMD's root key for {0:?} processors")]
+    UntrustedArk(Processor),
+
     #[error("ARK is not self signed")]
     ArkNotSelfSigned,
 
@@ -502,6 +540,32 @@ mod tests {
         assert_eq!(err, expected_error);
     }
 
+    #[rstest]
+    #[case::milan(Processor::Milan)]
+    #[case::genoa(Processor::Genoa)]
+    #[case::turin(Processor::Turin)]
+    fn trusted_ark(#[case] processor: Processor) {
+        let ark = processor.trusted_ark().expect("failed to load ARK");
+        ReportVerifier::verify_ark(&processor, &ark).expect("verification failed");
+    }
+
+    #[test]
+    fn forged_chain() {
+        // A self signed chain is internally consistent but must still be rejected.
+        let certs = CertsBuilder::new_valid();
+        ReportVerifier::verify_certs(&certs).expect("verification failed");
+
+        let err = ReportVerifier::verify_ark(&Processor::Genoa, &certs.chain.ark).expect_err("verification succeeded");
+        assert_eq!(err, CertificateValidationError::UntrustedArk(Processor::Genoa));
+    }
+
+    #[test]
+    fn other_processor_ark() {
+        let ark = Processor::Milan.trusted_ark().expect("failed to load ARK");
+        let err = ReportVerifier::verify_ark(&Processor::Genoa, &ark).expect_err("verification succeeded");
+        assert_eq!(err, CertificateValidationError::UntrustedArk(Processor::Genoa));
+    }
+
     #[test]
     fn valid_signature_verification() {
         let vcek = CertsBuilder::make_key();
@@ -525,6 +589,22 @@ mod tests {
         ReportVerifier::verify_report_signature(&vcek_cert, &report).expect("signature verification failed");
     }
 
+    #[rstest]
+    #[case::milan("synthetic-milan")]
+    #[case::genoa("genoa")]
+    #[case::turin("synthetic-turin")]
+    fn processor_detection(#[case] name: &str) {
+        let vector = nilcc_test_vectors::report(name);
+        let report: AttestationReport =
+            serde_json::from_value::<attestation_report::v2::AttestationReport>(vector.report.clone())
+                .expect("failed to parse")
+                .into();
+        let expected: Processor =
+            serde_json::from_value(serde_json::Value::String(vector.processor.clone())).expect("invalid processor");
+        let processor = Processor::try_from(&report).expect("failed to detect processor");
+        assert_eq!(processor, expected);
+    }
+
     #[test]
     fn invalid_signature_verification() {
         let vcek = CertsBuilder::new_valid().vcek;
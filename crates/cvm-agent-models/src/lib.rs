@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_with::DurationMilliSeconds;
 use serde_with::DurationSeconds;
 use serde_with::hex::Hex;
 use serde_with::serde_as;
@@ -17,7 +18,7 @@ pub mod bootstrap {
     pub const CADDY_ACME_EAB_MAC_KEY: &str = "CADDY_ACME_EAB_MAC_KEY";
 
     /// A request to bootstrap the CVM.
-    #[derive(Deserialize, Serialize)]
+    #[derive(Clone, Deserialize, Serialize)]
     pub struct BootstrapRequest {
         /// The ACME credentials.
         pub acme: AcmeCredentials,
@@ -33,10 +34,34 @@ pub mod bootstrap {
 
         /// The workload identifier.
         pub workload_id: Option<Uuid>,
+
+        /// The identifier of the agent running the workload.
+        #[serde(default)]
+        pub agent_id: Option<Uuid>,
+
+        /// How container logs are rotated.
+        #[serde(default)]
+        pub log_rotation: Option<LogRotationConfig>,
+
+        /// The servers used to get a trusted time.
+        #[serde(default)]
+        pub time_sync: Option<TimeSyncConfig>,
+
+        /// The private PKI to get TLS certificates from instead of ZeroSSL.
+        #[serde(default)]
+        pub private_pki: Option<PrivatePki>,
+
+        /// The docker registry mirrors to pull images through.
+        #[serde(default)]
+        pub registry_mirrors: Vec<String>,
+
+        /// The JSON encoded platform claims signed by the agent, which are handed to the attester as-is.
+        #[serde(default)]
+        pub platform_claims: Option<String>,
     }
 
     /// The ACME credentials.
-    #[derive(Deserialize, Serialize)]
+    #[derive(Clone, Deserialize, Serialize)]
     pub struct AcmeCredentials {
         /// The ACME EAB key id.
         pub eab_key_id: String,
@@ -45,6 +70,34 @@ pub mod bootstrap {
         pub eab_mac_key: String,
     }
 
+    /// A private PKI, used for internal domains that public CAs won't issue certificates for.
+    #[derive(Clone, Deserialize, Serialize)]
+    #[serde(tag = "kind", rename_all = "kebab-case")]
+    pub enum PrivatePki {
+        /// Issue certificates from an internal CA inside the CVM.
+        ///
+        /// The proxy generates its own private keys and only uses the CA to sign them, so the host never learns the
+        /// key behind the TLS fingerprint that's bound into attestation reports.
+        Ca {
+            /// The PEM encoded CA certificate.
+            ca_cert_pem: String,
+
+            /// The PEM encoded CA private key.
+            ca_key_pem: String,
+        },
+
+        /// Request certificates from an internal ACME directory.
+        Acme {
+            /// The ACME directory URL.
+            directory_url: String,
+
+            /// The PEM encoded root CA the ACME directory's own TLS certificate is signed by, if it's not publicly
+            /// trusted.
+            #[serde(default)]
+            root_ca_pem: Option<String>,
+        },
+    }
+
     /// A set of docker credentials to use.
     #[derive(Clone, Deserialize, Serialize)]
     pub struct DockerCredentials {
@@ -58,6 +111,16 @@ pub mod bootstrap {
         pub server: Option<String>,
     }
 
+    impl std::fmt::Debug for DockerCredentials {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            // Don't print the password
+            f.debug_struct("DockerCredentials")
+                .field("username", &self.username)
+                .field("server", &self.server)
+                .finish_non_exhaustive()
+        }
+    }
+
     /// The heartbeat configuration.
     #[serde_as]
     #[derive(Clone, Deserialize, Serialize)]
@@ -82,6 +145,110 @@ pub mod bootstrap {
         /// The URL where measurement hashes for this workload are published.
         pub measurement_hash_url: String,
     }
+
+    /// The log rotation configuration for the docker json-file log driver.
+    #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct LogRotationConfig {
+        /// The size a container's log file can grow to before it's rotated, in megabytes.
+        pub max_size_mb: u32,
+
+        /// The number of log files kept per container.
+        pub max_files: u32,
+    }
+
+    /// The trusted time synchronization configuration.
+    #[serde_as]
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct TimeSyncConfig {
+        /// The Roughtime servers to query.
+        pub servers: Vec<RoughtimeServer>,
+
+        /// The interval at which the servers are queried.
+        #[serde_as(as = "DurationSeconds")]
+        pub interval: Duration,
+
+        /// The clock skew above which a warning event is emitted.
+        #[serde_as(as = "DurationMilliSeconds")]
+        pub max_skew: Duration,
+    }
+
+    /// A Roughtime server.
+    #[serde_as]
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct RoughtimeServer {
+        /// The server's address, as a `host:port` pair.
+        pub address: String,
+
+        /// The server's long term Ed25519 public key.
+        #[serde_as(as = "Hex")]
+        pub public_key: Vec<u8>,
+    }
+
+    /// A step in the bootstrap process.
+    ///
+    /// Steps are executed in the order they're defined in.
+    #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "kebab-case")]
+    pub enum BootstrapStep {
+        /// No bootstrap request has been received yet.
+        Pending,
+
+        /// Configuring docker registry mirrors.
+        ConfigureRegistryMirrors,
+
+        /// Logging in to docker registries.
+        DockerLogin,
+
+        /// Pulling docker images.
+        PullImages,
+
+        /// Configuring container log rotation.
+        ConfigureLogging,
+
+        /// Running the workload's one-shot jobs to completion.
+        RunJobs,
+
+        /// Starting the docker compose containers.
+        StartContainers,
+
+        /// Setting up heartbeats.
+        Heartbeats,
+
+        /// Bootstrap is complete.
+        Completed,
+    }
+
+    impl BootstrapStep {
+        /// The step that follows this one.
+        pub fn next(self) -> Self {
+            match self {
+                Self::Pending => Self::ConfigureRegistryMirrors,
+                Self::ConfigureRegistryMirrors => Self::DockerLogin,
+                Self::DockerLogin => Self::PullImages,
+                Self::PullImages => Self::ConfigureLogging,
+                Self::ConfigureLogging => Self::RunJobs,
+                Self::RunJobs => Self::StartContainers,
+                Self::StartContainers => Self::Heartbeats,
+                Self::Heartbeats | Self::Completed => Self::Completed,
+            }
+        }
+    }
+
+    /// The status of the bootstrap process.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct BootstrapStatus {
+        /// The step the bootstrap process is at.
+        pub step: BootstrapStep,
+
+        /// Whether the bootstrap process is currently running.
+        pub running: bool,
+
+        /// The error that caused the current step to fail, if any.
+        pub error: Option<String>,
+    }
 }
 
 pub mod config {
@@ -95,6 +262,20 @@ pub mod config {
         #[serde_as(as = "DurationSeconds")]
         pub interval: Duration,
     }
+
+    /// A request to change the domains the CVM is served on.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct DomainsConfigRequest {
+        /// The domains to serve, the first one being the workload's primary domain.
+        pub domains: Vec<String>,
+    }
+
+    /// A request to log in to docker registries using new credentials.
+    #[derive(Clone, Deserialize, Serialize)]
+    pub struct DockerCredentialsConfigRequest {
+        /// The credentials to log in with.
+        pub credentials: Vec<super::bootstrap::DockerCredentials>,
+    }
 }
 
 pub mod container {
@@ -102,6 +283,7 @@ pub mod container {
 
     /// A container.
     #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct Container {
         /// The names for this container.
@@ -116,6 +298,215 @@ pub mod container {
         /// The state of this container.
         pub state: String,
     }
+
+    /// A request to restart the containers for a docker compose service.
+    #[derive(Deserialize, Serialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct RestartContainerRequest {
+        /// The name of the service in the docker compose file.
+        #[validate(length(min = 1))]
+        pub service: String,
+    }
+
+    /// The label a docker compose service must set to `"true"` to allow running commands in it on demand.
+    pub const RUNNABLE_SERVICE_LABEL: &str = "nilcc.runnable";
+
+    /// The label a docker compose service must set to `"true"` to be run as a one-shot job before any other service is
+    /// started.
+    pub const JOB_SERVICE_LABEL: &str = "nilcc.job";
+
+    /// A request to run a command in a new container for a docker compose service, like `docker compose run` does.
+    #[derive(Deserialize, Serialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct RunContainerRequest {
+        /// The name of the service in the docker compose file.
+        #[validate(length(min = 1))]
+        pub service: String,
+
+        /// The command to run, the service's own command is run if this is empty.
+        #[serde(default)]
+        pub command: Vec<String>,
+    }
+
+    /// An event emitted while running a command, streamed back as a line of JSON.
+    #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(tag = "type", rename_all = "camelCase")]
+    pub enum RunContainerEvent {
+        /// The command wrote a line.
+        Output {
+            /// The stream the line was written to.
+            stream: super::logs::OutputStream,
+
+            /// The line.
+            line: String,
+        },
+
+        /// The command exited.
+        #[serde(rename_all = "camelCase")]
+        Exited {
+            /// The command's exit code, if it wasn't killed by a signal.
+            exit_code: Option<i32>,
+        },
+    }
+
+    /// The protocol port forwarding connections are upgraded to, which carries raw TCP traffic.
+    pub const PORT_FORWARD_PROTOCOL: &str = "tcp";
+
+    /// The label a docker compose service must set to a comma separated list of ports to allow forwarding connections
+    /// to those ports in its containers.
+    pub const PORT_FORWARD_LABEL: &str = "nilcc.port-forward";
+
+    /// A request to forward connections to a port in a container.
+    #[derive(Clone, Deserialize, Serialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+    #[serde(rename_all = "camelCase")]
+    pub struct PortForwardRequest {
+        /// The container to forward connections to.
+        #[validate(length(min = 1))]
+        pub container: String,
+
+        /// The port within the container to forward connections to.
+        #[validate(range(min = 1))]
+        pub port: u16,
+    }
+
+    /// The aggregated state of the docker compose deployment.
+    #[derive(Clone, Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ComposeStateResponse {
+        /// Whether every service is in its desired state and none of them is unhealthy.
+        pub converged: bool,
+
+        /// The state of every service, sorted by name.
+        pub services: Vec<ComposeServiceState>,
+
+        /// The last error encountered while launching or monitoring the deployment.
+        pub last_error: Option<super::health::LastEvent>,
+    }
+
+    /// The state of a docker compose service.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ComposeServiceState {
+        /// The name of the service in the docker compose file.
+        pub service: String,
+
+        /// The state the service should be in.
+        pub desired_state: ServiceState,
+
+        /// The state the service's containers are actually in.
+        pub actual_state: ServiceState,
+
+        /// The number of times the service's containers were restarted.
+        pub restart_count: u64,
+
+        /// The health reported by the service's healthcheck, if it has one.
+        pub health: Option<String>,
+
+        /// The last error reported for the service's containers.
+        pub error: Option<String>,
+    }
+
+    /// The state of a docker compose service.
+    #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "kebab-case")]
+    pub enum ServiceState {
+        /// All of the service's containers are running.
+        Running,
+
+        /// At least one of the service's containers is being restarted.
+        Restarting,
+
+        /// The service's containers exist but are not running.
+        Stopped,
+
+        /// The service has no containers.
+        Missing,
+    }
+}
+
+pub mod encryption {
+    use super::*;
+
+    /// A payload encrypted to the workload's log encryption public key.
+    ///
+    /// The payload is encrypted by performing an X25519 key agreement between an ephemeral key and the workload's
+    /// public key, deriving an AES-256-GCM key via HKDF-SHA256 using the ephemeral public key as salt and
+    /// [ENCRYPTION_INFO] as info, and encrypting the JSON serialized plaintext with it.
+    #[serde_as]
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct EncryptedPayload {
+        /// The ephemeral X25519 public key used in the key agreement.
+        #[serde_as(as = "Hex")]
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        pub ephemeral_public_key: Vec<u8>,
+
+        /// The AES-256-GCM nonce.
+        #[serde_as(as = "Hex")]
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        pub nonce: Vec<u8>,
+
+        /// The ciphertext, including the authentication tag.
+        #[serde_as(as = "Hex")]
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        pub ciphertext: Vec<u8>,
+    }
+
+    /// The HKDF info used when deriving the payload encryption key.
+    pub const ENCRYPTION_INFO: &[u8] = b"nilcc-log-encryption-v1";
+
+    /// The top level docker compose extension that holds the hex encoded X25519 public key to encrypt logs and stats
+    /// to. This is the only place the CVM takes the key from since the compose file is part of its measurement.
+    pub const LOG_ENCRYPTION_KEY_EXTENSION: &str = "x-nilcc-log-encryption-key";
+
+    /// A payload that may be encrypted, depending on whether the workload has a log encryption key.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(untagged)]
+    pub enum MaybeEncrypted<T> {
+        /// The payload is encrypted.
+        Encrypted {
+            /// The encrypted payload.
+            encrypted: EncryptedPayload,
+        },
+
+        /// The payload is in plaintext.
+        Plaintext(T),
+    }
+}
+
+pub mod errors {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// The body of an error response.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ErrorResponse {
+        /// A descriptive message about the error that was encountered.
+        pub message: String,
+
+        /// The error code.
+        pub error_code: String,
+
+        /// Whether the error was caused by a transient condition, meaning the same request may succeed if retried
+        /// later.
+        #[serde(default)]
+        pub retryable: bool,
+
+        /// Structured information about the error, e.g. which container was missing.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub details: BTreeMap<String, String>,
+    }
 }
 
 pub mod health {
@@ -123,6 +514,7 @@ pub mod health {
 
     /// A response to a health request.
     #[derive(Deserialize, Serialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct HealthResponse {
         /// Whether HTTPS is configured and available.
@@ -133,9 +525,73 @@ pub mod health {
 
         /// The last event encountered.
         pub last_event: Option<LastEvent>,
+
+        /// The status of the bootstrap process.
+        ///
+        /// This is only set by CVM agents that support resuming a failed bootstrap.
+        #[serde(default)]
+        pub bootstrap: Option<super::bootstrap::BootstrapStatus>,
+
+        /// The status of the TLS certificate served by the proxy, once it's been checked.
+        #[serde(default)]
+        pub certificate: Option<CertificateStatus>,
+
+        /// Whether the CVM's vCPUs are paused.
+        ///
+        /// This is only set by nilcc-agent, which answers on the CVM's behalf while it's paused since the CVM agent
+        /// can't respond. Every other field is unset in that case.
+        #[serde(default)]
+        pub paused: bool,
+
+        /// Whether the running containers match the measured docker compose, once they've been checked.
+        #[serde(default)]
+        pub drift: Option<ComposeDrift>,
+    }
+
+    /// How the running containers diverge from the measured docker compose.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ComposeDrift {
+        /// The timestamp of the last check.
+        pub checked_at: DateTime<Utc>,
+
+        /// The differences found in the last check, which is empty if every container matches.
+        pub findings: Vec<DriftFinding>,
+    }
+
+    /// A container that diverges from the measured docker compose.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct DriftFinding {
+        /// The container's name.
+        pub container: String,
+
+        /// The docker compose service the container belongs to, if any.
+        pub service: Option<String>,
+
+        /// What diverges.
+        pub reason: String,
+    }
+
+    /// The status of the TLS certificate served by the proxy.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct CertificateStatus {
+        /// The timestamp when the certificate expires.
+        pub expires_at: DateTime<Utc>,
+
+        /// The number of whole days left until the certificate expires.
+        pub days_to_expiry: i64,
+
+        /// The last error caddy reported when renewing the certificate, if it hasn't succeeded since.
+        pub renewal_error: Option<String>,
     }
 
     #[derive(Clone, Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct LastEvent {
         /// An incremental id for every event found.
@@ -152,17 +608,149 @@ pub mod health {
     }
 
     #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     pub enum EventKind {
         Error,
         Warning,
     }
 }
 
+pub mod heartbeat {
+    use super::*;
+
+    /// The status of the CVM's verifier heartbeats.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    pub struct HeartbeatStatusResponse {
+        /// The address of the wallet heartbeats are submitted from.
+        pub wallet_address: String,
+
+        /// The wallet's balance, if it was fetched already.
+        pub balance: Option<WalletBalance>,
+
+        /// When the last heartbeat was successfully submitted.
+        pub last_heartbeat_at: Option<DateTime<Utc>>,
+    }
+
+    /// The balance of a heartbeat wallet.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    pub struct WalletBalance {
+        /// The ETH balance, in ETH.
+        pub eth: String,
+
+        /// The NIL balance, in NIL.
+        pub nil: String,
+
+        /// When this balance was fetched.
+        pub observed_at: DateTime<Utc>,
+    }
+}
+
+pub mod identity {
+    use super::*;
+
+    /// The default lifetime of an identity token.
+    pub const DEFAULT_IDENTITY_TOKEN_TTL: Duration = Duration::from_secs(300);
+
+    /// A request to mint an identity token.
+    #[serde_as]
+    #[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+    #[serde(rename_all = "camelCase")]
+    pub struct IdentityTokenRequest {
+        /// The audience the token is meant for, e.g. the URL of the service it will be presented to.
+        #[validate(length(min = 1, max = 256))]
+        pub audience: String,
+
+        /// How long the token is valid for.
+        #[serde_as(as = "DurationSeconds")]
+        #[serde(default = "default_identity_token_ttl")]
+        pub ttl_seconds: Duration,
+    }
+
+    /// A minted identity token.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct IdentityTokenResponse {
+        /// The token, as a JWT.
+        pub token: String,
+
+        /// When the token expires.
+        pub expires_at: DateTime<Utc>,
+    }
+
+    fn default_identity_token_ttl() -> Duration {
+        DEFAULT_IDENTITY_TOKEN_TTL
+    }
+}
+
+pub mod jobs {
+    use super::*;
+    use crate::logs::OutputStream;
+
+    /// The status of a one-shot job.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct JobStatus {
+        /// The name of the job's service in the docker compose file.
+        pub name: String,
+
+        /// The state the job is in.
+        pub state: JobState,
+
+        /// The code the job's container exited with, if it already ran.
+        pub exit_code: Option<i64>,
+
+        /// The error reported for the job's container, if any.
+        pub error: Option<String>,
+    }
+
+    /// The state of a one-shot job.
+    #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "kebab-case")]
+    pub enum JobState {
+        /// The job hasn't been started yet.
+        Pending,
+
+        /// The job is running.
+        Running,
+
+        /// The job ran to completion and exited successfully.
+        Succeeded,
+
+        /// The job exited with an error.
+        Failed,
+    }
+
+    /// A request to get the logs for a job.
+    #[derive(Deserialize, Serialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+    #[serde(rename_all = "camelCase")]
+    pub struct JobLogsRequest {
+        /// The name of the job's service in the docker compose file.
+        #[validate(length(min = 1))]
+        pub job: String,
+
+        /// Whether to pull logs from the tail of the stream.
+        pub tail: bool,
+
+        /// The stream to take logs out of.
+        pub stream: OutputStream,
+
+        /// The maximum number of log lines to be returned.
+        #[validate(range(max = 1000))]
+        pub max_lines: usize,
+    }
+}
+
 pub mod logs {
     use super::*;
 
     /// A request to get the logs for a container.
     #[derive(Deserialize, Serialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
     #[serde(rename_all = "camelCase")]
     pub struct ContainerLogsRequest {
         /// The container that we're pulling logs out of.
@@ -180,7 +768,8 @@ pub mod logs {
     }
 
     /// The stream to take logs out of.
-    #[derive(Deserialize, Serialize)]
+    #[derive(Clone, Copy, Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub enum OutputStream {
         /// Standard output.
@@ -192,6 +781,7 @@ pub mod logs {
 
     /// The container logs response.
     #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     pub struct ContainerLogsResponse {
         /// The log lines.
         pub lines: Vec<String>,
@@ -199,6 +789,7 @@ pub mod logs {
 
     /// A request to get the system logs.
     #[derive(Deserialize, Serialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
     #[serde(rename_all = "camelCase")]
     pub struct SystemLogsRequest {
         /// The log source to fetch.
@@ -214,14 +805,25 @@ pub mod logs {
 
     /// The source for system logs.
     #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "kebab-case")]
     pub enum SystemLogsSource {
         /// Get the cvm-agent logs.
         CvmAgent,
+
+        /// Get the access logs of the proxy that sits in front of the workload, one JSON object per line.
+        Proxy,
+
+        /// Get the kernel logs, e.g. device mapper or GPU driver errors.
+        Kernel,
+
+        /// Get the docker daemon logs.
+        DockerDaemon,
     }
 
     /// The system logs response.
     #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     pub struct SystemLogsResponse {
         /// The log lines.
         pub lines: Vec<String>,
@@ -234,6 +836,7 @@ pub mod stats {
 
     /// The stats response.
     #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct SystemStatsResponse {
         /// Stats about the memory usage.
@@ -245,10 +848,63 @@ pub mod stats {
         /// Stats about every disk.
         #[serde(default)]
         pub disks: Vec<DiskStats>,
+
+        /// The disk space used by container logs, in bytes.
+        #[serde(default)]
+        pub log_disk_usage: u64,
+
+        /// The skew of the CVM's clock against trusted time, if time synchronization is enabled.
+        #[serde(default)]
+        pub clock_skew: Option<ClockSkew>,
+
+        /// Stats about the GPUs, if the CVM has any.
+        #[serde(default)]
+        pub gpus: Option<GpusStats>,
+
+        /// The most recent processes killed by the kernel because the CVM or their container ran out of memory.
+        #[serde(default)]
+        pub oom_kills: Vec<OomKill>,
+    }
+
+    /// A process that was killed by the kernel's OOM killer.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct OomKill {
+        /// The name of the process that was killed.
+        pub process: String,
+
+        /// The process id.
+        pub pid: u32,
+
+        /// The container the process was running in, if any.
+        pub container: Option<String>,
+
+        /// When the kill was detected.
+        pub detected_at: DateTime<Utc>,
+    }
+
+    /// The skew of the CVM's clock against trusted time.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ClockSkew {
+        /// How far ahead the CVM's clock is, in milliseconds. This is negative if the clock is behind.
+        pub skew_ms: i64,
+
+        /// The uncertainty of the measurement, in milliseconds.
+        pub uncertainty_ms: u64,
+
+        /// The number of servers that were successfully queried.
+        pub servers: usize,
+
+        /// When the skew was measured.
+        pub measured_at: DateTime<Utc>,
     }
 
     /// Memory stats.
     #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct MemoryStats {
         /// The total memory in the CVM, in bytes.
@@ -256,10 +912,45 @@ pub mod stats {
 
         /// The total used memory, in bytes.
         pub used: u64,
+
+        /// The memory pressure, if the kernel exposes pressure stall information.
+        #[serde(default)]
+        pub pressure: Option<MemoryPressure>,
+    }
+
+    /// The memory pressure stall information, as reported in `/proc/pressure/memory`.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct MemoryPressure {
+        /// The time in which at least one process was stalled waiting for memory.
+        pub some: PressureStats,
+
+        /// The time in which all non-idle processes were stalled waiting for memory at the same time.
+        pub full: PressureStats,
+    }
+
+    /// Pressure stall stats.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct PressureStats {
+        /// The percentage of time stalled over the last 10 seconds.
+        pub avg10: f32,
+
+        /// The percentage of time stalled over the last 60 seconds.
+        pub avg60: f32,
+
+        /// The percentage of time stalled over the last 300 seconds.
+        pub avg300: f32,
+
+        /// The total time stalled, in microseconds.
+        pub total: u64,
     }
 
     /// CPU stats.
     #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct CpuStats {
         /// The CPU name.
@@ -272,14 +963,52 @@ pub mod stats {
         pub frequency: u64,
     }
 
+    /// Stats about the GPUs attached to the CVM.
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct GpusStats {
+        /// The confidential computing mode the GPUs are in, e.g. `ON`, if it could be determined.
+        pub cc_mode: Option<String>,
+
+        /// Stats about every GPU.
+        pub devices: Vec<GpuStats>,
+    }
+
+    /// GPU stats.
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct GpuStats {
+        /// The GPU index.
+        pub index: u32,
+
+        /// The GPU name.
+        pub name: String,
+
+        /// The GPU utilization, as a percentage between 0-100.
+        pub utilization: u32,
+
+        /// The used memory, in bytes.
+        pub memory_used: u64,
+
+        /// The total memory, in bytes.
+        pub memory_total: u64,
+
+        /// The temperature, in degrees celsius.
+        pub temperature: u32,
+    }
+
     /// Disk stats.
     #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct DiskStats {
         /// The name of this disk.
         pub name: String,
 
         /// The path where the filesystem is mounted.
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
         pub mount_point: PathBuf,
 
         /// The type of filesystem.
@@ -291,4 +1020,90 @@ pub mod stats {
         /// The used space this disk, in bytes.
         pub used: u64,
     }
+
+    /// The containers stats response.
+    #[derive(Deserialize, Serialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ContainersStatsResponse {
+        /// Stats about every running container.
+        pub containers: Vec<ContainerStats>,
+    }
+
+    /// Stats about a running container.
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ContainerStats {
+        /// The container name.
+        pub name: String,
+
+        /// The docker compose service this container belongs to, if any.
+        pub service: Option<String>,
+
+        /// The CPU usage, as a percentage of a single CPU. This can be above 100 if the container uses multiple CPUs.
+        pub cpu_usage: f64,
+
+        /// The used memory, excluding the page cache, in bytes.
+        pub memory_used: u64,
+
+        /// The memory limit, in bytes.
+        pub memory_limit: u64,
+
+        /// The bytes received over every network interface.
+        pub network_rx: u64,
+
+        /// The bytes sent over every network interface.
+        pub network_tx: u64,
+
+        /// The bytes read from block devices.
+        pub block_read: u64,
+
+        /// The bytes written to block devices.
+        pub block_write: u64,
+    }
+}
+
+pub mod status {
+    use super::*;
+
+    /// The public status of a CVM, as seen by the workload's end users.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct PublicStatusResponse {
+        /// Whether the CVM finished bootstrapping.
+        pub bootstrapped: bool,
+
+        /// Whether the application's container is running and not reported as unhealthy.
+        pub app_responding: bool,
+
+        /// Whether attestation reports can be fetched.
+        pub attestation_available: bool,
+    }
+}
+
+pub mod tls {
+    use super::*;
+
+    /// The TLS certificate the CVM's proxy is serving.
+    #[serde_as]
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct TlsInfoResponse {
+        /// The domain the certificate is served for.
+        pub domain: String,
+
+        /// The SHA256 hash of the certificate's public key.
+        ///
+        /// This is the fingerprint attestation reports are bound to, so clients can pin it after validating a report
+        /// once.
+        #[serde_as(as = "Hex")]
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        pub fingerprint: [u8; 32],
+
+        /// When the CVM first saw the proxy serve a certificate with this fingerprint.
+        pub observed_at: DateTime<Utc>,
+    }
 }
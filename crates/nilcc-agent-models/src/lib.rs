@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use serde_with::base64::Base64;
 use serde_with::hex::Hex;
 use serde_with::serde_as;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::LazyLock;
 use uuid::Uuid;
 use validator::{Validate, ValidationError};
@@ -19,6 +19,7 @@ pub mod system {
 
     /// A request to install an artifacts version.
     #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct InstallArtifactVersionRequest {
         // The version to install.
@@ -28,6 +29,7 @@ pub mod system {
 
     /// A request to upgrade the version.
     #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct UpgradeRequest {
         // The version to upgrade to.
@@ -36,6 +38,7 @@ pub mod system {
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct AgentVersionResponse {
         // The version we are running.
@@ -47,6 +50,7 @@ pub mod system {
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct ArtifactVersionsResponse {
         // The versions supported.
@@ -58,6 +62,7 @@ pub mod system {
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct LastUpgrade {
         /// The last upgrade's target version.
@@ -68,9 +73,14 @@ pub mod system {
 
         /// The state of the upgrade.
         pub state: UpgradeState,
+
+        /// The hex encoded SHA256 digest of the agent binary, once its signature has been verified.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub sha256: Option<String>,
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "kebab-case", tag = "state")]
     pub enum UpgradeState {
         InProgress,
@@ -79,13 +89,23 @@ pub mod system {
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct ArtifactsCleanupResponse {
         /// The versions that were deleted.
         pub versions_deleted: Vec<String>,
     }
 
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ArtifactsGcPreviewResponse {
+        /// The versions the garbage collection policy would delete.
+        pub versions: Vec<String>,
+    }
+
     #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct ArtifactChangelogResponse {
         /// The changelog entries.
@@ -93,6 +113,7 @@ pub mod system {
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct ArtifactChangelogEntry {
         /// The version that was installed/uninstalled.
@@ -113,6 +134,7 @@ pub mod system {
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "kebab-case")]
     pub enum ArtifactChangelogEntryOperation {
         Install,
@@ -120,6 +142,7 @@ pub mod system {
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "kebab-case", tag = "state")]
     pub enum ArtifactChangelogEntryState {
         Pending,
@@ -129,31 +152,351 @@ pub mod system {
 
     #[serde_as]
     #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct VerifierKey {
         /// The public key.
         #[serde_as(as = "Hex")]
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
         pub public_key: Vec<u8>,
 
         /// Whether this key is in use.
         pub active: bool,
+
+        /// Whether this key was retired, meaning it's never going to be handed out again.
+        #[serde(default)]
+        pub retired: bool,
+
+        /// The workload this key is assigned to.
+        #[serde(default)]
+        pub workload_id: Option<Uuid>,
+
+        /// The key's wallet balance, as last fetched by the CVM using it.
+        #[serde(default)]
+        pub balance: Option<VerifierKeyBalance>,
+
+        /// When the CVM using this key last submitted a heartbeat successfully.
+        #[serde(default)]
+        pub last_heartbeat_at: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct VerifierKeyBalance {
+        /// The ETH balance, in ETH.
+        pub eth: String,
+
+        /// The NIL balance, in NIL.
+        pub nil: String,
+
+        /// When this balance was fetched.
+        pub observed_at: DateTime<Utc>,
+    }
+
+    /// A request to rotate a verifier key.
+    #[serde_as]
+    #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct RotateVerifierKeyRequest {
+        /// The public key to rotate, either compressed or uncompressed.
+        #[serde_as(as = "Hex")]
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        pub public_key: Vec<u8>,
+    }
+
+    #[serde_as]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct RotateVerifierKeyResponse {
+        /// The workload that was moved over to a new key.
+        pub workload_id: Uuid,
+
+        /// The new key's uncompressed public key.
+        #[serde_as(as = "Hex")]
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        pub public_key: Vec<u8>,
+    }
+
+    /// A request to retire a verifier key.
+    #[serde_as]
+    #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct RetireVerifierKeyRequest {
+        /// The public key to retire, either compressed or uncompressed.
+        #[serde_as(as = "Hex")]
+        #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+        pub public_key: Vec<u8>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ZeroSslAccount {
+        /// The EAB key id that identifies this account.
+        pub eab_key_id: String,
+
+        /// The number of workloads bound to this account.
+        pub workloads: usize,
+
+        /// The number of certificates requested using this account since the agent started.
+        pub certificate_requests: u64,
+    }
+
+    /// The agent's build info and capabilities.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct SystemInfoResponse {
+        /// How the agent binary was built.
+        pub build: BuildInfo,
+
+        /// The VM types workloads can use, e.g. `cpu` or `gpu`.
+        pub vm_types: Vec<String>,
+
+        /// Whether the host's kernel has SEV-SNP enabled.
+        pub snp: bool,
+
+        /// The host's GPUs, if any.
+        #[serde(default)]
+        pub gpus: Option<GpuInfo>,
+
+        /// The installed artifacts versions.
+        pub artifacts_versions: Vec<String>,
+
+        /// The optional features this agent has enabled.
+        pub features: AgentFeatures,
+
+        /// The maximum resources a single workload can use.
+        pub resource_limits: ResourceLimits,
+    }
+
+    /// How the agent binary was built.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct BuildInfo {
+        /// The full agent version, as reported by the version endpoint.
+        pub version: String,
+
+        /// The crate version.
+        pub package_version: String,
+
+        /// The git commit the agent was built from.
+        pub git_commit: String,
+
+        /// When the agent was built.
+        pub built_at: DateTime<Utc>,
+    }
+
+    /// The GPUs in the host.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct GpuInfo {
+        /// The GPU vendor, e.g. `nvidia`.
+        pub vendor: String,
+
+        /// The GPU model, e.g. `H100`.
+        pub model: String,
+
+        /// The number of GPUs.
+        pub count: usize,
+
+        /// Whether the GPUs run in confidential computing mode.
+        ///
+        /// Workloads must set `allow_non_confidential_gpus` to use GPUs that don't.
+        pub confidential: bool,
+    }
+
+    /// The optional features an agent has enabled.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct AgentFeatures {
+        /// Whether workloads can submit verifier heartbeats.
+        pub heartbeats: bool,
+
+        /// Whether the agent's API is served over TLS.
+        pub tls: bool,
+
+        /// The default image vulnerability policy mode.
+        pub image_policy: crate::workloads::create::ImagePolicyMode,
+
+        /// Whether CVMs synchronize their clocks against trusted time servers.
+        pub time_sync: bool,
+
+        /// Whether CVMs get their TLS certificates from a private PKI instead of ZeroSSL.
+        pub private_pki: bool,
+
+        /// Whether workloads are also reachable over IPv6.
+        pub ipv6: bool,
+
+        /// Whether VMs are pinned to NUMA nodes.
+        pub numa_pinning: bool,
+
+        /// Whether the host's reserved resources are tuned automatically.
+        pub reservation_auto_tune: bool,
+
+        /// Whether workloads can be launched in debug mode with their serial console exposed.
+        pub debug_console: bool,
+    }
+
+    /// The maximum resources a single workload can use.
+    ///
+    /// A missing value means there's no limit other than the host's available resources.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct ResourceLimits {
+        /// The maximum number of CPUs.
+        pub cpus: Option<u32>,
+
+        /// The maximum memory, in megabytes.
+        pub memory_mb: Option<u32>,
+
+        /// The maximum disk space, in gigabytes.
+        pub disk_space_gb: Option<u32>,
+    }
+
+    /// The resources available for new workloads and the largest workloads they can fit.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct SystemCapacityResponse {
+        /// The resources that aren't used by any workload.
+        pub free: FreeResources,
+
+        /// The largest workload that can be created right now, if any can.
+        pub largest_workload: Option<WorkloadShape>,
+
+        /// The largest high priority workload that can be created by preempting every low priority workload, if any
+        /// can.
+        pub largest_preempting_workload: Option<WorkloadShape>,
+
+        /// The number of workloads that can still be created before running out of open ports.
+        pub workload_slots: u32,
+
+        /// The resource that prevents any workload from being created, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub exhausted_resource: Option<String>,
+    }
+
+    /// The resources that aren't used by any workload.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct FreeResources {
+        /// The number of CPUs.
+        pub cpus: u32,
+
+        /// The memory, in megabytes.
+        pub memory_mb: u32,
+
+        /// The disk space, in gigabytes.
+        pub disk_space_gb: u32,
+
+        /// The number of GPUs.
+        pub gpus: u32,
+
+        /// The number of open ports.
+        pub ports: u32,
+    }
+
+    /// The resources a single workload uses.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+    #[serde(rename_all = "camelCase")]
+    pub struct WorkloadShape {
+        /// The number of CPUs.
+        pub cpus: u32,
+
+        /// The memory, in megabytes.
+        pub memory_mb: u32,
+
+        /// The disk space, in gigabytes.
+        pub disk_space_gb: u32,
+
+        /// The number of GPUs.
+        pub gpus: u32,
     }
 }
 
 pub mod workloads {
     use super::*;
 
+    static DOMAIN_REGEX: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9-\.]+\.([a-zA-Z]{2,}|[a-zA-Z]{2,}\.[a-zA-Z]{2,})$").unwrap());
+    static LABEL_KEY_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9._/-]{1,63}$").unwrap());
+
+    /// The maximum number of labels a workload can have.
+    pub const MAX_LABELS: usize = 32;
+
+    /// The maximum length of a label's value.
+    pub const MAX_LABEL_VALUE_LENGTH: usize = 256;
+
+    fn validate_label(key: &str, value: &str) -> Result<(), ValidationError> {
+        if !LABEL_KEY_REGEX.is_match(key) {
+            return Err(ValidationError::new("invalid label key"));
+        }
+        if value.len() > MAX_LABEL_VALUE_LENGTH || value.contains(',') {
+            return Err(ValidationError::new("invalid label value"));
+        }
+        Ok(())
+    }
+
     pub mod create {
         use super::*;
 
         static FILENAME_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[\w/._-]+$").unwrap());
-        static DOMAIN_REGEX: LazyLock<Regex> =
-            LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9-\.]+\.([a-zA-Z]{2,}|[a-zA-Z]{2,}\.[a-zA-Z]{2,})$").unwrap());
+        static ENV_GROUP_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9_-]{1,64}$").unwrap());
+
+        fn validate_log_encryption_key(key: &[u8]) -> Result<(), ValidationError> {
+            if key.len() == 32 { Ok(()) } else { Err(ValidationError::new("must be a 32 byte X25519 public key")) }
+        }
 
-        fn validate_files(files: &HashMap<String, Vec<u8>>) -> Result<(), ValidationError> {
-            for key in files.keys() {
-                if !FILENAME_REGEX.is_match(key) {
-                    return Err(ValidationError::new("invalid filename"));
+        fn validate_file_names<'a>(mut names: impl Iterator<Item = &'a String>) -> Result<(), ValidationError> {
+            if names.all(|name| FILENAME_REGEX.is_match(name)) {
+                Ok(())
+            } else {
+                Err(ValidationError::new("invalid filename"))
+            }
+        }
+
+        pub(super) fn validate_files(files: &HashMap<String, Vec<u8>>) -> Result<(), ValidationError> {
+            validate_file_names(files.keys())
+        }
+
+        fn validate_uploaded_files(files: &HashMap<String, Uuid>) -> Result<(), ValidationError> {
+            validate_file_names(files.keys())
+        }
+
+        fn validate_registry_mirrors(mirrors: &[String]) -> Result<(), ValidationError> {
+            for mirror in mirrors {
+                if !mirror.starts_with("https://") {
+                    return Err(ValidationError::new("registry mirrors must be https URLs"));
+                }
+            }
+            Ok(())
+        }
+
+        fn validate_labels(labels: &HashMap<String, String>) -> Result<(), ValidationError> {
+            if labels.len() > MAX_LABELS {
+                return Err(ValidationError::new("too many labels"));
+            }
+            for (key, value) in labels {
+                validate_label(key, value)?;
+            }
+            Ok(())
+        }
+
+        fn validate_env_groups(groups: &[String]) -> Result<(), ValidationError> {
+            for group in groups {
+                if !ENV_GROUP_REGEX.is_match(group) {
+                    return Err(ValidationError::new("invalid env group name"));
                 }
             }
             Ok(())
@@ -161,6 +504,7 @@ pub mod workloads {
 
         #[serde_as]
         #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct CreateWorkloadRequest {
             pub id: Uuid,
@@ -170,11 +514,26 @@ pub mod workloads {
             #[serde(default)]
             pub env_vars: HashMap<String, String>,
 
+            /// The named environment variable groups to import from the control plane.
+            ///
+            /// Groups are applied in order and the workload's own environment variables take precedence over them.
+            #[serde(default)]
+            #[validate(custom(function = "validate_env_groups"))]
+            pub env_groups: Vec<String>,
+
             #[serde_as(as = "HashMap<_, Base64>")]
             #[serde(default)]
+            #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, String>))]
             #[validate(custom(function = "validate_files"))]
             pub files: HashMap<String, Vec<u8>>,
 
+            /// Files uploaded via the chunked upload endpoints, keyed by their path under `$FILES`.
+            ///
+            /// The uploads are consumed once the workload is created.
+            #[serde(default)]
+            #[validate(custom(function = "validate_uploaded_files"))]
+            pub uploaded_files: HashMap<String, Uuid>,
+
             #[serde(default)]
             pub docker_credentials: Vec<DockerCredentials>,
 
@@ -190,6 +549,19 @@ pub mod workloads {
 
             pub gpus: u16,
 
+            /// The GPU model the workload requires.
+            ///
+            /// When set, the workload is only created if the agent's GPUs are of this model.
+            #[serde(default)]
+            #[validate(length(min = 1))]
+            pub gpu_model: Option<String>,
+
+            /// Whether the workload can use GPUs that don't run in confidential computing mode.
+            ///
+            /// The memory of these GPUs isn't protected from the host, so workloads need to opt into using them.
+            #[serde(default)]
+            pub allow_non_confidential_gpus: bool,
+
             #[validate(range(min = 2))]
             pub disk_space_gb: u32,
 
@@ -197,15 +569,208 @@ pub mod workloads {
             pub domain: String,
 
             pub heartbeat: Option<CreateWorkloadHeartbeat>,
+
+            #[serde(default)]
+            pub priority: WorkloadPriority,
+
+            /// Which artifacts versions this workload follows once it's created.
+            #[serde(default)]
+            pub upgrade_channel: UpgradeChannel,
+
+            /// An X25519 public key to encrypt logs and stats to.
+            ///
+            /// When set, the CVM encrypts every log and stats response so that only the holder of the private key
+            /// can read them. The CVM takes the key from the docker compose file's `x-nilcc-log-encryption-key`
+            /// extension, so this must match it if set.
+            #[serde_as(as = "Option<Hex>")]
+            #[serde(default)]
+            #[validate(custom(function = "validate_log_encryption_key"))]
+            #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+            pub log_encryption_key: Option<Vec<u8>>,
+
+            /// Overrides the agent's image policy mode for this workload. Only admins can set this.
+            #[serde(default)]
+            pub image_policy: Option<ImagePolicyMode>,
+
+            /// How container logs are rotated inside the CVM.
+            ///
+            /// When not set, container logs are never rotated.
+            #[serde(default)]
+            #[validate(nested)]
+            pub log_rotation: Option<LogRotation>,
+
+            /// How the workload's state disk is stored.
+            ///
+            /// When not set, the agent's default state disk mode is used.
+            #[serde(default)]
+            pub state_disk: Option<StateDisk>,
+
+            /// The docker registry mirrors to pull images through.
+            ///
+            /// When not set, the agent's default mirrors are used. An empty list disables mirrors for this workload.
+            /// Mirrors must be https URLs and every image must be pinned by digest when any are used, since they're
+            /// picked by the host.
+            #[serde(default)]
+            #[validate(custom(function = "validate_registry_mirrors"))]
+            pub registry_mirrors: Option<Vec<String>>,
+
+            /// Free-form labels used to organize workloads.
+            ///
+            /// Label keys can contain alphanumeric characters, `.`, `_`, `/` and `-`. Values can't contain `,`.
+            #[serde(default)]
+            #[validate(custom(function = "validate_labels"))]
+            pub labels: HashMap<String, String>,
+
+            /// Whether to launch the workload in debug mode, exposing its serial console via the API.
+            ///
+            /// Debug workloads boot with a different kernel command line so they can't be attested.
+            #[serde(default)]
+            pub debug: bool,
+
+            /// Limits on the bandwidth of the workload's public traffic.
+            ///
+            /// When not set, the workload's bandwidth isn't limited.
+            #[serde(default)]
+            #[validate(nested)]
+            pub bandwidth_limits: Option<BandwidthLimits>,
+
+            /// Overrides for the timeouts the SNI proxy uses for the workload's traffic.
+            ///
+            /// When not set, the agent's default timeouts are used.
+            #[serde(default)]
+            #[validate(nested)]
+            pub proxy_timeouts: Option<ProxyTimeouts>,
+
+            /// Whether the workload needs exclusive use of the metal instance.
+            ///
+            /// Isolated workloads are only created if no other workload exists in the agent, and no other workload is
+            /// created for as long as they exist. This keeps them from sharing a host with workloads that could mount
+            /// side-channel attacks on them.
+            #[serde(default)]
+            pub isolated: bool,
+        }
+
+        /// The log rotation settings for the containers in a workload.
+        #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct LogRotation {
+            /// The size a container's log file can grow to before it's rotated, in megabytes.
+            #[validate(range(min = 1))]
+            pub max_size_mb: u32,
+
+            /// The number of log files kept per container, including the one being written to.
+            #[validate(range(min = 1))]
+            pub max_files: u32,
+        }
+
+        /// The bandwidth limits for a workload's public traffic.
+        #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct BandwidthLimits {
+            /// The maximum rate traffic is sent to the workload at, in megabits per second.
+            #[serde(default)]
+            #[validate(range(min = 1))]
+            pub ingress_mbps: Option<u32>,
+
+            /// The maximum rate the workload sends traffic at, in megabits per second.
+            #[serde(default)]
+            #[validate(range(min = 1))]
+            pub egress_mbps: Option<u32>,
+        }
+
+        /// Overrides for the timeouts the SNI proxy uses for a workload's traffic.
+        ///
+        /// Any timeout that isn't set uses the agent's default.
+        #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct ProxyTimeouts {
+            /// The time to wait for a connection to the workload to be established, in milliseconds.
+            #[serde(default)]
+            #[validate(range(min = 1))]
+            pub connect_ms: Option<u64>,
+
+            /// The time a client can stay inactive for, in milliseconds.
+            #[serde(default)]
+            #[validate(range(min = 1))]
+            pub client_ms: Option<u64>,
+
+            /// The time the workload can stay inactive for, in milliseconds.
+            #[serde(default)]
+            #[validate(range(min = 1))]
+            pub server_ms: Option<u64>,
+        }
+
+        /// What to do when a workload uses an image that has critical vulnerabilities.
+        #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "kebab-case")]
+        pub enum ImagePolicyMode {
+            /// Reject the workload.
+            #[default]
+            Enforce,
+
+            /// Log a warning and create the workload anyway.
+            Warn,
+
+            /// Don't check images at all.
+            Disabled,
+        }
+
+        /// The priority class for a workload.
+        ///
+        /// Low priority workloads can be preempted to make room for high priority ones.
+        #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "kebab-case")]
+        pub enum WorkloadPriority {
+            Low,
+            #[default]
+            Normal,
+            High,
+        }
+
+        /// The artifacts versions a workload follows.
+        #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "kebab-case")]
+        pub enum UpgradeChannel {
+            /// Stay on the artifacts version the workload was created with.
+            #[default]
+            Pinned,
+
+            /// Get recreated using the latest stable artifacts version whenever a newer one is installed.
+            LatestStable,
+        }
+
+        /// How a workload's state disk is stored on the host.
+        #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "kebab-case")]
+        pub enum StateDisk {
+            /// A raw disk that's encrypted using a random key generated on every boot.
+            ///
+            /// The workload's state is lost every time the CVM is restarted.
+            #[default]
+            Ephemeral,
+
+            /// A qcow2 disk encrypted using a key derived inside the CVM and sealed to its measurement.
+            ///
+            /// The workload's state survives restarts as long as the CVM's measurement doesn't change.
+            Sealed,
         }
 
         #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct CreateWorkloadHeartbeat {
             pub measurement_hash_url: String,
         }
 
         #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct DockerCredentials {
             /// The docker registry server.
@@ -218,10 +783,54 @@ pub mod workloads {
             pub password: String,
         }
 
+        /// The query parameters for a workload creation request.
+        #[derive(Clone, Debug, Default, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+        pub struct CreateWorkloadQuery {
+            /// Validate the workload and return what would be assigned to it without creating it.
+            #[serde(default)]
+            pub dry_run: bool,
+        }
+
         #[derive(Clone, Debug, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct CreateWorkloadResponse {
             pub id: Uuid,
+
+            /// What would be assigned to the workload, only set when doing a dry run.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub admission: Option<WorkloadAdmission>,
+        }
+
+        /// The resources that would be assigned to a workload if it was created.
+        #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct WorkloadAdmission {
+            /// The artifacts version the workload would run.
+            pub artifacts_version: String,
+
+            /// The number of CPUs.
+            pub cpus: u32,
+
+            /// The memory, in megabytes.
+            pub memory_mb: u32,
+
+            /// The disk space, in gigabytes.
+            pub disk_space_gb: u32,
+
+            /// The PCI addresses of the GPUs.
+            pub gpus: Vec<String>,
+
+            /// The host ports for the workload's HTTP, HTTPS and cvm-agent endpoints, in that order.
+            pub ports: Vec<u16>,
+
+            /// How the workload's state disk would be stored.
+            pub state_disk: StateDisk,
+
+            /// The low priority workloads that would be preempted to make room for this one.
+            pub preempted_workloads: Vec<Uuid>,
         }
     }
 
@@ -229,17 +838,153 @@ pub mod workloads {
         use super::*;
 
         #[derive(Clone, Debug, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct ListWorkloadsRequest {
             pub id: Uuid,
         }
 
+        fn validate_label_selector(selector: &str) -> Result<(), ValidationError> {
+            parse_label_selector(selector).map(|_| ())
+        }
+
+        /// Parse a label selector in the form `key1=value1,key2=value2`.
+        pub fn parse_label_selector(selector: &str) -> Result<HashMap<String, String>, ValidationError> {
+            let mut labels = HashMap::new();
+            for pair in selector.split(',') {
+                let Some((key, value)) = pair.split_once('=') else {
+                    return Err(ValidationError::new("labels must be in the form key=value"));
+                };
+                validate_label(key, value)?;
+                labels.insert(key.to_string(), value.to_string());
+            }
+            Ok(labels)
+        }
+
+        /// The query parameters for a workload list request.
+        #[derive(Clone, Debug, Default, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+        pub struct ListWorkloadsQuery {
+            /// Only list workloads that have all of these labels, in the form `key1=value1,key2=value2`.
+            #[serde(default)]
+            #[validate(custom(function = "validate_label_selector"))]
+            pub labels: Option<String>,
+        }
+
+        impl ListWorkloadsQuery {
+            /// Get the labels workloads must have to be listed.
+            pub fn label_selector(&self) -> HashMap<String, String> {
+                // An invalid selector is rejected during validation so we can ignore errors here.
+                self.labels.as_deref().and_then(|s| parse_label_selector(s).ok()).unwrap_or_default()
+            }
+        }
+
+        #[serde_as]
         #[derive(Clone, Debug, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct WorkloadSummary {
             pub id: Uuid,
             pub enabled: bool,
             pub domain: String,
+
+            /// The workload's priority class.
+            #[serde(default)]
+            pub priority: create::WorkloadPriority,
+
+            /// The artifacts version the workload is currently using.
+            #[serde(default)]
+            pub artifacts_version: String,
+
+            /// Which artifacts versions the workload follows.
+            #[serde(default)]
+            pub upgrade_channel: create::UpgradeChannel,
+
+            /// How the workload's state disk is stored.
+            #[serde(default)]
+            pub state_disk: create::StateDisk,
+
+            /// Whether this workload was stopped to make room for a higher priority one.
+            #[serde(default)]
+            pub preempted: bool,
+
+            /// Whether the workload's VM is paused.
+            #[serde(default)]
+            pub paused: bool,
+
+            /// The limits on the bandwidth of the workload's public traffic, if any.
+            #[serde(default)]
+            pub bandwidth_limits: Option<create::BandwidthLimits>,
+
+            /// The overrides for the SNI proxy's timeouts for the workload's traffic, if any.
+            #[serde(default)]
+            pub proxy_timeouts: Option<create::ProxyTimeouts>,
+
+            /// The name of the API token that owns the workload, if any.
+            #[serde(default)]
+            pub owner: Option<String>,
+
+            /// Whether the workload has exclusive use of the metal instance.
+            #[serde(default)]
+            pub isolated: bool,
+
+            /// Whether the workload's environment variables were updated but its VM wasn't restarted to pick them up.
+            #[serde(default)]
+            pub env_vars_restart_pending: bool,
+
+            /// The hash of the files in the workload's application ISO, excluding the ones that hold secrets.
+            ///
+            /// This is `None` if the ISO hasn't been built yet.
+            #[serde_as(as = "Option<Hex>")]
+            #[serde(default)]
+            #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+            pub iso_content_hash: Option<[u8; 32]>,
+
+            /// The workload's labels.
+            #[serde(default)]
+            pub labels: HashMap<String, String>,
+
+            /// The number of CPUs the workload uses.
+            #[serde(default)]
+            pub cpus: u32,
+
+            /// The memory the workload uses, in megabytes.
+            #[serde(default)]
+            pub memory_mb: u32,
+
+            /// The disk space the workload uses, in gigabytes.
+            #[serde(default)]
+            pub disk_space_gb: u32,
+
+            /// The number of GPUs the workload uses.
+            #[serde(default)]
+            pub gpus: u16,
+
+            /// The container requests are forwarded to.
+            #[serde(default)]
+            pub public_container_name: String,
+
+            /// The port in the container requests are forwarded to.
+            #[serde(default)]
+            pub public_container_port: u16,
+
+            /// The SHA256 hash of the workload's docker compose file.
+            #[serde_as(as = "Option<Hex>")]
+            #[serde(default)]
+            #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+            pub docker_compose_hash: Option<[u8; 32]>,
+
+            /// The SHA256 hash of the value of each of the workload's environment variables, by name.
+            #[serde_as(as = "HashMap<_, Hex>")]
+            #[serde(default)]
+            #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, String>))]
+            pub env_var_hashes: HashMap<String, [u8; 32]>,
+
+            /// The SHA256 hash of the contents of each of the workload's files, by name.
+            #[serde_as(as = "HashMap<_, Hex>")]
+            #[serde(default)]
+            #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, String>))]
+            pub file_hashes: HashMap<String, [u8; 32]>,
         }
     }
 
@@ -247,6 +992,7 @@ pub mod workloads {
         use super::*;
 
         #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct DeleteWorkloadRequest {
             pub id: Uuid,
@@ -257,6 +1003,7 @@ pub mod workloads {
         use super::*;
 
         #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct StartWorkloadRequest {
             pub id: Uuid,
@@ -267,16 +1014,157 @@ pub mod workloads {
         use super::*;
 
         #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct StopWorkloadRequest {
             pub id: Uuid,
         }
     }
 
+    pub mod pause {
+        use super::*;
+
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct PauseWorkloadRequest {
+            pub id: Uuid,
+        }
+    }
+
+    pub mod proxy_timeouts {
+        use super::*;
+        use crate::workloads::create::ProxyTimeouts;
+
+        /// A request to change the timeouts the SNI proxy uses for a workload's traffic.
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct UpdateProxyTimeoutsRequest {
+            /// The timeouts to use, or `null` to go back to the agent's default ones.
+            #[serde(default)]
+            #[validate(nested)]
+            pub proxy_timeouts: Option<ProxyTimeouts>,
+        }
+    }
+
+    pub mod resume {
+        use super::*;
+
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct ResumeWorkloadRequest {
+            pub id: Uuid,
+        }
+    }
+
+    pub mod wait {
+        use super::*;
+
+        /// The maximum time a single wait request blocks for, in seconds.
+        pub const MAX_WAIT_TIMEOUT_SECONDS: u64 = 300;
+
+        fn default_timeout_seconds() -> u64 {
+            60
+        }
+
+        /// A request to wait until a workload is ready.
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+        #[serde(rename_all = "camelCase")]
+        pub struct WaitWorkloadQuery {
+            /// How long to wait for, in seconds. This is capped at `MAX_WAIT_TIMEOUT_SECONDS`.
+            #[serde(default = "default_timeout_seconds")]
+            pub timeout_seconds: u64,
+        }
+
+        /// The readiness of a workload at the time a wait request returned.
+        #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct WaitWorkloadResponse {
+            /// Whether the workload is bootstrapped, serving HTTPS, and all of its services converged.
+            pub ready: bool,
+
+            /// Whether the CVM finished bootstrapping.
+            pub bootstrapped: bool,
+
+            /// Whether the CVM is serving HTTPS.
+            pub https: bool,
+
+            /// Whether every docker compose service is in its desired state and none of them is unhealthy.
+            pub converged: bool,
+
+            /// The error bootstrapping failed with, in which case the workload won't become ready without intervention.
+            pub bootstrap_error: Option<String>,
+
+            /// The last events reported by the CVM.
+            pub last_events: Vec<String>,
+        }
+    }
+
+    pub mod progress {
+        use super::*;
+        use chrono::{DateTime, Utc};
+
+        /// A step in the provisioning of a workload.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub enum ProvisioningStep {
+            /// The workload's application ISO was built.
+            IsoBuilt,
+
+            /// The workload's disks were created.
+            DisksCreated,
+
+            /// The workload's VM was started.
+            VmStarted,
+
+            /// The CVM finished bootstrapping.
+            CvmBootstrapped,
+
+            /// The CVM got its TLS certificate and is serving HTTPS.
+            CertificateIssued,
+
+            /// All of the workload's services converged.
+            Ready,
+        }
+
+        /// A provisioning step that was reached.
+        #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct ProvisioningEvent {
+            /// The step that was reached.
+            pub step: ProvisioningStep,
+
+            /// When the step was reached.
+            pub timestamp: DateTime<Utc>,
+        }
+
+        /// The provisioning progress of a workload.
+        #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct WorkloadProgressResponse {
+            /// The steps reached so far, in the order they were reached.
+            pub steps: Vec<ProvisioningEvent>,
+
+            /// Whether the workload finished provisioning and is ready.
+            pub ready: bool,
+
+            /// The error bootstrapping failed with, in which case the workload won't become ready without intervention.
+            pub error: Option<String>,
+        }
+    }
+
     pub mod restart {
         use super::*;
 
         #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
         #[serde(rename_all = "camelCase")]
         pub struct RestartWorkloadRequest {
             pub id: Uuid,
@@ -285,6 +1173,257 @@ pub mod workloads {
             pub env_vars: Option<HashMap<String, String>>,
         }
     }
+
+    pub mod docker_credentials {
+        use super::*;
+        use crate::workloads::create::DockerCredentials;
+
+        /// A request to replace the credentials a workload pulls its images with.
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct UpdateDockerCredentialsRequest {
+            /// The credentials to use, replacing the existing ones.
+            pub docker_credentials: Vec<DockerCredentials>,
+        }
+    }
+
+    pub mod env_vars {
+        use super::*;
+
+        fn validate_remove(request: &UpdateEnvVarsRequest) -> Result<(), ValidationError> {
+            match request.mode {
+                EnvVarsUpdateMode::Replace if !request.remove.is_empty() => {
+                    Err(ValidationError::new("'remove' can only be used when patching"))
+                }
+                _ => Ok(()),
+            }
+        }
+
+        /// A request to update a workload's environment variables.
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        #[validate(schema(function = "validate_remove"))]
+        pub struct UpdateEnvVarsRequest {
+            /// How the provided environment variables are applied.
+            pub mode: EnvVarsUpdateMode,
+
+            /// The environment variables to set.
+            #[serde(default)]
+            pub env_vars: HashMap<String, String>,
+
+            /// The environment variables to remove. Only allowed when patching.
+            #[serde(default)]
+            pub remove: Vec<String>,
+
+            /// Whether to restart the workload's VM so it picks up the changes right away.
+            #[serde(default)]
+            pub restart: bool,
+        }
+
+        /// How environment variables are updated.
+        #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "kebab-case")]
+        pub enum EnvVarsUpdateMode {
+            /// Replace all of the workload's environment variables with the provided ones.
+            Replace,
+
+            /// Add or overwrite the provided environment variables and remove the ones in `remove`, keeping the rest.
+            Patch,
+        }
+
+        /// The response to an environment variables update.
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct UpdateEnvVarsResponse {
+            /// The names of the environment variables that were added, changed, or removed.
+            pub changed: Vec<String>,
+
+            /// Whether the workload needs to be restarted for the changes to take effect.
+            pub restart_pending: bool,
+        }
+    }
+
+    pub mod files {
+        use super::*;
+
+        /// A request to add, replace, or remove the files bundled in a workload's application ISO.
+        #[serde_as]
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct UpdateFilesRequest {
+            /// The files to add or replace, keyed by their path under `$FILES`.
+            #[serde_as(as = "HashMap<_, Base64>")]
+            #[serde(default)]
+            #[cfg_attr(feature = "utoipa", schema(value_type = HashMap<String, String>))]
+            #[validate(custom(function = "create::validate_files"))]
+            pub files: HashMap<String, Vec<u8>>,
+
+            /// The files to remove.
+            #[serde(default)]
+            pub remove: Vec<String>,
+        }
+
+        /// The response to a files update.
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct UpdateFilesResponse {
+            /// The names of the files that were added, changed, or removed.
+            pub changed: Vec<String>,
+
+            /// Whether the workload's VM was restarted to pick up the changes.
+            pub restarted: bool,
+        }
+    }
+
+    pub mod uploads {
+        use super::*;
+
+        /// The maximum size of a chunk sent to the upload endpoint.
+        pub const MAX_UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+        /// A request to start uploading a file in chunks.
+        #[serde_as]
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct CreateUploadRequest {
+            /// The size of the file, in bytes.
+            pub size: u64,
+
+            /// The SHA256 hash of the file's contents, which is checked once every chunk is received.
+            #[serde_as(as = "Hex")]
+            #[cfg_attr(feature = "utoipa", schema(value_type = String))]
+            pub sha256: [u8; 32],
+        }
+
+        /// The response to a request to start an upload.
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct CreateUploadResponse {
+            /// The upload id, which is used to send chunks and to reference the file when creating a workload.
+            pub id: Uuid,
+
+            /// The maximum size of each chunk, in bytes.
+            pub max_chunk_size: u64,
+        }
+
+        /// The query parameters for a chunk upload.
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+        pub struct UploadChunkQuery {
+            /// The offset in the file this chunk starts at, which must match the number of bytes received so far.
+            pub offset: u64,
+        }
+
+        /// The state of an upload.
+        #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct UploadStatus {
+            /// The size of the file, in bytes.
+            pub size: u64,
+
+            /// The number of bytes received so far.
+            pub received: u64,
+
+            /// Whether every chunk was received and the file's hash matched the expected one.
+            pub complete: bool,
+        }
+    }
+
+    pub mod change_domain {
+        use super::*;
+
+        /// A request to change the domain a workload is served on.
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct ChangeWorkloadDomainRequest {
+            pub id: Uuid,
+
+            #[validate(regex(path  = DOMAIN_REGEX))]
+            pub domain: String,
+        }
+    }
+
+    pub mod usage {
+        use super::*;
+        use chrono::{DateTime, Utc};
+
+        fn validate_range(request: &WorkloadUsageRequest) -> Result<(), ValidationError> {
+            match (request.from, request.to) {
+                (Some(from), Some(to)) if from > to => Err(ValidationError::new("'from' can't be after 'to'")),
+                _ => Ok(()),
+            }
+        }
+
+        /// A request to get the usage of a workload.
+        #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+        #[serde(rename_all = "camelCase")]
+        #[validate(schema(function = "validate_range"))]
+        pub struct WorkloadUsageRequest {
+            /// Only include usage from this point on.
+            #[serde(default)]
+            pub from: Option<DateTime<Utc>>,
+
+            /// Only include usage before this point.
+            #[serde(default)]
+            pub to: Option<DateTime<Utc>>,
+        }
+
+        /// The resources a workload used within a time range.
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+        #[serde(rename_all = "camelCase")]
+        pub struct WorkloadUsageResponse {
+            /// The workload id.
+            pub id: Uuid,
+
+            /// The time the first sample in the range was taken, if any.
+            pub first_sample_at: Option<DateTime<Utc>>,
+
+            /// The time the last sample in the range was taken, if any.
+            pub last_sample_at: Option<DateTime<Utc>>,
+
+            /// The total time the workload was running for, in seconds.
+            pub running_seconds: u64,
+
+            /// The allocated CPU hours.
+            pub cpu_hours: f64,
+
+            /// The allocated GPU hours.
+            pub gpu_hours: f64,
+
+            /// The allocated memory, in GB hours.
+            pub memory_gb_hours: f64,
+
+            /// The allocated disk space, in GB hours.
+            pub disk_gb_hours: f64,
+        }
+    }
+}
+
+pub mod attestation {
+    use super::*;
+
+    /// A request to fetch a workload's attestation report through the agent.
+    #[derive(Clone, Debug, Default, Serialize, Deserialize, Validate)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+    pub struct AttestationReportQuery {
+        /// Whether to request a report that binds the CVM's boot log, along with the boot log itself.
+        ///
+        /// This is passed through as is to the workload's attester.
+        #[serde(default)]
+        pub include_boot_log: bool,
+    }
 }
 
 pub mod errors {
@@ -292,6 +1431,7 @@ pub mod errors {
 
     /// An error when handling a request.
     #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
     #[serde(rename_all = "camelCase")]
     pub struct RequestHandlerError {
         /// A descriptive message about the error that was encountered.
@@ -299,16 +1439,37 @@ pub mod errors {
 
         /// The error code.
         pub error_code: String,
+
+        /// Whether the error was caused by a transient condition, meaning the same request may succeed if retried
+        /// later.
+        #[serde(default)]
+        pub retryable: bool,
+
+        /// Structured information about the error, e.g. which resource was insufficient.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub details: BTreeMap<String, String>,
     }
 
     impl RequestHandlerError {
         pub fn new(message: impl Into<String>, error_code: impl AsRef<str>) -> Self {
             let error_code = error_code.as_ref().to_case(Case::UpperSnake);
-            Self { message: message.into(), error_code }
+            Self { message: message.into(), error_code, retryable: false, details: Default::default() }
         }
 
         pub fn internal() -> Self {
-            Self::new("internal server error", "INTERNAL")
+            Self::new("internal server error", "INTERNAL").with_retryable(true)
+        }
+
+        /// Set whether the request may succeed if retried later.
+        pub fn with_retryable(mut self, retryable: bool) -> Self {
+            self.retryable = retryable;
+            self
+        }
+
+        /// Add a detail about the error.
+        pub fn with_detail(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+            self.details.insert(key.into(), value.to_string());
+            self
         }
     }
 }
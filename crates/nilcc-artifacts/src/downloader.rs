@@ -1,9 +1,11 @@
 use crate::Artifacts;
 use crate::VmType;
-use crate::metadata::ArtifactsMetadata;
+use crate::metadata::{ArtifactsMetadata, CvmImage};
+use crate::signature::{METADATA_SIGNATURE_FILE, SignatureError, SigningKey, verify_signature};
 use futures_util::StreamExt;
 use sha2::Digest;
 use sha2::Sha256;
+use std::collections::HashMap;
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
@@ -13,6 +15,7 @@ use tokio::io::AsyncWriteExt;
 use tokio::io::BufWriter;
 use tracing::debug;
 use tracing::info;
+use tracing::warn;
 
 pub const S3_BUCKET_URL: &str = "https://nilcc.s3-accelerate.amazonaws.com";
 
@@ -23,11 +26,21 @@ pub struct ArtifactsDownloader {
     artifacts_url: String,
     disk_images: bool,
     always_download: bool,
+    reuse_dirs: Vec<PathBuf>,
+    signing_keys: Vec<SigningKey>,
 }
 
 impl ArtifactsDownloader {
     pub fn new(version: String, vm_types: Vec<VmType>) -> Self {
-        Self { version, vm_types, artifacts_url: S3_BUCKET_URL.into(), disk_images: true, always_download: true }
+        Self {
+            version,
+            vm_types,
+            artifacts_url: S3_BUCKET_URL.into(),
+            disk_images: true,
+            always_download: true,
+            reuse_dirs: Vec::new(),
+            signing_keys: Vec::new(),
+        }
     }
 
     pub fn with_artifacts_url(mut self, artifacts_url: String) -> Self {
@@ -45,6 +58,22 @@ impl ArtifactsDownloader {
         self
     }
 
+    /// Reuse files from artifacts already installed in these directories instead of downloading them.
+    ///
+    /// Files are only reused if the `metadata.json` in the directory says their hash matches the one being installed.
+    pub fn with_reuse_dirs(mut self, reuse_dirs: Vec<PathBuf>) -> Self {
+        self.reuse_dirs = reuse_dirs;
+        self
+    }
+
+    /// Require the metadata to be signed by one of these keys before trusting any of the paths and hashes in it.
+    ///
+    /// If no keys are set, the metadata's signature isn't checked.
+    pub fn with_signing_keys(mut self, signing_keys: Vec<SigningKey>) -> Self {
+        self.signing_keys = signing_keys;
+        self
+    }
+
     pub async fn validate_exists(&self) -> Result<(), DownloadError> {
         let Self { version, artifacts_url, .. } = self;
         let url = format!("{artifacts_url}/{version}/metadata.json");
@@ -57,48 +86,104 @@ impl ArtifactsDownloader {
         let artifact_metadata = self.fetch_metadata().await?;
         let metadata = &artifact_metadata.decoded;
         let metadata_path = target_dir.join("metadata.json");
-        self.download_artifact(&metadata.ovmf.path, target_dir).await?;
-        self.download_artifact(&metadata.initrd.path, target_dir).await?;
+        let reusable = ReusableArtifacts::load(&self.reuse_dirs, target_dir).await;
+        self.install_artifact(&metadata.ovmf.path, reusable.file(&metadata.ovmf.sha256), target_dir).await?;
+        self.install_artifact(&metadata.initrd.path, reusable.file(&metadata.initrd.sha256), target_dir).await?;
         for vm_type in &self.vm_types {
             let metadata = metadata.cvm.images.resolve(*vm_type);
-            self.download_artifact(&metadata.kernel.path, target_dir).await?;
+            self.install_artifact(&metadata.kernel.path, reusable.file(&metadata.kernel.sha256), target_dir).await?;
             if self.disk_images {
-                self.download_artifact(&metadata.disk.artifact.path, target_dir).await?;
-                self.download_artifact(&metadata.verity.disk.path, target_dir).await?;
+                let disk = &metadata.disk.artifact;
+                self.install_artifact(&disk.path, reusable.file(&disk.sha256), target_dir).await?;
+                self.install_artifact(&metadata.verity.disk.path, reusable.verity_disk(metadata), target_dir).await?;
             }
         }
-        fs::write(&metadata_path, artifact_metadata.raw).await.map_err(DownloadError::TargetFile)?;
-        Ok(Artifacts { metadata: artifact_metadata.decoded, metadata_hash: artifact_metadata.hash })
+        if let Some(signature) = &artifact_metadata.signature {
+            let signature_path = target_dir.join(METADATA_SIGNATURE_FILE);
+            write_file(&signature_path, signature.as_bytes()).await.map_err(DownloadError::TargetFile)?;
+        }
+        write_file(&metadata_path, artifact_metadata.raw.as_bytes()).await.map_err(DownloadError::TargetFile)?;
+        Ok(Artifacts {
+            metadata: artifact_metadata.decoded,
+            metadata_hash: artifact_metadata.hash,
+            signer: artifact_metadata.signer,
+        })
     }
 
-    async fn download_artifact(&self, artifact_name: &str, target_dir: &Path) -> Result<PathBuf, DownloadError> {
+    async fn install_artifact(
+        &self,
+        artifact_name: &str,
+        reusable_path: Option<&Path>,
+        target_dir: &Path,
+    ) -> Result<PathBuf, DownloadError> {
         let local_path = target_dir.join(artifact_name);
         if local_path.exists() {
             if self.always_download {
                 info!("Artifact {artifact_name} already exists, overwriting it");
+                // The existing file may be a hard link into another version, so unlink it rather than truncating it.
+                fs::remove_file(&local_path).await.map_err(DownloadError::TargetFile)?;
             } else {
                 info!("Not downloading {artifact_name} because it already exists in cache directory");
                 return Ok(local_path);
             }
         }
-        info!("Downloading {artifact_name} into {}", local_path.display());
         let parent = local_path.parent().ok_or_else(|| DownloadError::NoParent)?;
         fs::create_dir_all(parent).await.map_err(DownloadError::TargetDirectory)?;
+        if let Some(source) = reusable_path {
+            match Self::reuse_artifact(source, &local_path).await {
+                Ok(()) => return Ok(local_path),
+                Err(e) => warn!("Could not reuse {}, downloading {artifact_name} instead: {e}", source.display()),
+            }
+        }
 
+        info!("Downloading {artifact_name} into {}", local_path.display());
         let version = &self.version;
         let remote_path = format!("/{version}/{artifact_name}");
         self.download_object(&remote_path, &local_path).await?;
         Ok(local_path)
     }
 
+    async fn reuse_artifact(source: &Path, target: &Path) -> io::Result<()> {
+        match fs::hard_link(source, target).await {
+            Ok(()) => {
+                info!("Linked unchanged artifact {} into {}", source.display(), target.display());
+                Ok(())
+            }
+            Err(e) => {
+                // Hard links don't work across filesystems so fall back to a plain copy.
+                info!("Could not link {}, copying it instead: {e}", source.display());
+                fs::copy(source, target).await?;
+                Ok(())
+            }
+        }
+    }
+
     async fn fetch_metadata(&self) -> Result<Metadata, DownloadError> {
         let version = &self.version;
         let url = format!("{}/{version}/metadata.json", self.artifacts_url);
         let response = reqwest::get(url).await?.error_for_status()?;
         let raw_metadata = response.text().await?;
+        let (signature, signer) = self.verify_signature(&raw_metadata).await?.unzip();
         let metadata_hash = Sha256::digest(&raw_metadata).into();
         let metadata = serde_json::from_str(&raw_metadata).map_err(DownloadError::DecodeMetadata)?;
-        Ok(Metadata { raw: raw_metadata, decoded: metadata, hash: metadata_hash })
+        Ok(Metadata { raw: raw_metadata, decoded: metadata, hash: metadata_hash, signature, signer })
+    }
+
+    /// Verify the metadata's detached signature, returning it along with the name of the key that signed it.
+    async fn verify_signature(&self, raw_metadata: &str) -> Result<Option<(String, String)>, DownloadError> {
+        if self.signing_keys.is_empty() {
+            return Ok(None);
+        }
+        let version = &self.version;
+        let url = format!("{}/{version}/{METADATA_SIGNATURE_FILE}", self.artifacts_url);
+        let response = reqwest::get(url).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(DownloadError::MissingSignature);
+        }
+        let signature = response.error_for_status()?.text().await?;
+        let signer = verify_signature(&self.signing_keys, raw_metadata.as_bytes(), &signature)?;
+        info!("Artifacts metadata for version {version} is signed by {}", signer.name);
+        Ok(Some((signature, signer.name.clone())))
     }
 
     async fn download_object(&self, url_path: &str, target_path: &Path) -> Result<(), DownloadError> {
@@ -110,6 +195,74 @@ struct Metadata {
     raw: String,
     decoded: ArtifactsMetadata,
     hash: [u8; 32],
+    signature: Option<String>,
+    signer: Option<String>,
+}
+
+/// The files in already installed artifacts versions that can be reused, indexed by their hash.
+#[derive(Default)]
+struct ReusableArtifacts {
+    files: HashMap<[u8; 32], PathBuf>,
+
+    // Verity disks have no hash of their own so they're indexed by the disk's hash and their root hash.
+    verity_disks: HashMap<([u8; 32], [u8; 32]), PathBuf>,
+}
+
+impl ReusableArtifacts {
+    async fn load(dirs: &[PathBuf], target_dir: &Path) -> Self {
+        let mut artifacts = Self::default();
+        for dir in dirs {
+            if dir == target_dir {
+                continue;
+            }
+            let metadata_path = dir.join("metadata.json");
+            let metadata = match fs::read(&metadata_path).await {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    warn!("Not reusing artifacts in {}, could not read metadata: {e}", dir.display());
+                    continue;
+                }
+            };
+            match serde_json::from_slice::<ArtifactsMetadata>(&metadata) {
+                Ok(metadata) => artifacts.add(dir, &metadata),
+                Err(e) => warn!("Not reusing artifacts in {}, could not decode metadata: {e}", dir.display()),
+            };
+        }
+        artifacts
+    }
+
+    fn add(&mut self, dir: &Path, metadata: &ArtifactsMetadata) {
+        let mut add_file = |artifact_path: &str, sha256: [u8; 32]| {
+            let path = dir.join(artifact_path);
+            if path.is_file() {
+                self.files.entry(sha256).or_insert(path);
+            }
+        };
+        add_file(&metadata.ovmf.path, metadata.ovmf.sha256);
+        add_file(&metadata.initrd.path, metadata.initrd.sha256);
+        for image in [&metadata.cvm.images.cpu, &metadata.cvm.images.gpu] {
+            add_file(&image.kernel.path, image.kernel.sha256);
+            add_file(&image.disk.artifact.path, image.disk.artifact.sha256);
+        }
+        for image in [&metadata.cvm.images.cpu, &metadata.cvm.images.gpu] {
+            let path = dir.join(&image.verity.disk.path);
+            if path.is_file() {
+                self.verity_disks.entry(Self::verity_key(image)).or_insert(path);
+            }
+        }
+    }
+
+    fn file(&self, sha256: &[u8; 32]) -> Option<&Path> {
+        self.files.get(sha256).map(PathBuf::as_path)
+    }
+
+    fn verity_disk(&self, image: &CvmImage) -> Option<&Path> {
+        self.verity_disks.get(&Self::verity_key(image)).map(PathBuf::as_path)
+    }
+
+    fn verity_key(image: &CvmImage) -> ([u8; 32], [u8; 32]) {
+        (image.disk.artifact.sha256, image.verity.root_hash)
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -128,6 +281,12 @@ pub enum DownloadError {
 
     #[error("failed to decode metadata: {0}")]
     DecodeMetadata(serde_json::Error),
+
+    #[error("metadata is not signed")]
+    MissingSignature,
+
+    #[error("invalid metadata signature: {0}")]
+    Signature(#[from] SignatureError),
 }
 
 pub struct FileDownloader<'a> {
@@ -147,11 +306,30 @@ impl FileDownloader<'_> {
         Ok(())
     }
 
+    pub async fn fetch_text(&self, url_path: &str) -> Result<String, DownloadError> {
+        let url = format!("{}{url_path}", self.artifacts_url);
+        Ok(reqwest::get(url).await?.error_for_status()?.text().await?)
+    }
+
+    /// Download a file.
+    ///
+    /// The file is written next to the target path and only moved into place once it's complete, so an interrupted
+    /// download never leaves a partial file behind.
     pub async fn download(&self, url_path: &str, target_path: &Path) -> Result<(), DownloadError> {
         let url = format!("{}{url_path}", self.artifacts_url);
         let result = reqwest::get(url).await?.error_for_status()?;
-        let mut stream = result.bytes_stream();
-        let file = File::create(target_path).await.map_err(DownloadError::TargetFile)?;
+        let partial_path = partial_path(target_path);
+        if let Err(e) = Self::write_stream(result, &partial_path).await {
+            let _ = fs::remove_file(&partial_path).await;
+            return Err(e);
+        }
+        fs::rename(&partial_path, target_path).await.map_err(DownloadError::TargetFile)?;
+        Ok(())
+    }
+
+    async fn write_stream(response: reqwest::Response, path: &Path) -> Result<(), DownloadError> {
+        let mut stream = response.bytes_stream();
+        let file = File::create(path).await.map_err(DownloadError::TargetFile)?;
         let mut file = BufWriter::new(file);
         while let Some(bytes) = stream.next().await {
             let bytes = bytes?;
@@ -162,3 +340,105 @@ impl FileDownloader<'_> {
         Ok(())
     }
 }
+
+/// The path a file is written to before it's moved into place.
+///
+/// This is unique per process so that processes writing the same file don't clobber each other's partial writes.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(format!(".{}.part", std::process::id()));
+    partial_path.into()
+}
+
+/// Write a file so that readers either see its old contents or the new ones, but never a partial write.
+async fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let partial_path = partial_path(path);
+    fs::write(&partial_path, contents).await?;
+    fs::rename(&partial_path, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::KernelCommandLine;
+    use crate::packer::{ArtifactsPackSpec, ArtifactsPacker, CvmImageFiles, DEFAULT_KERNEL_COMMAND_LINE};
+    use std::os::unix::fs::MetadataExt;
+    use tempfile::tempdir;
+
+    async fn pack(target_dir: &Path) -> ArtifactsMetadata {
+        let sources = tempdir().expect("failed to create tempdir");
+        let write = |name: &str| {
+            let path = sources.path().join(name);
+            std::fs::write(&path, name).expect("failed to write file");
+            path
+        };
+        let image = |vm_type: &str| CvmImageFiles {
+            disk: write(&format!("{vm_type}.raw")),
+            verity_disk: write(&format!("{vm_type}-verity")),
+            verity_root_hash: [1; 32],
+            kernel: write(&format!("{vm_type}-vmlinuz")),
+        };
+        let spec = ArtifactsPackSpec {
+            ovmf: write("OVMF.fd"),
+            initrd: write("initrd"),
+            cmdline: KernelCommandLine(DEFAULT_KERNEL_COMMAND_LINE.into()),
+            cpu: image("cpu"),
+            gpu: image("gpu"),
+        };
+        ArtifactsPacker::new(spec).pack(target_dir).await.expect("failed to pack").metadata
+    }
+
+    #[tokio::test]
+    async fn index_installed_artifacts() {
+        let installed = tempdir().expect("failed to create tempdir");
+        let target = tempdir().expect("failed to create tempdir");
+        let metadata = pack(installed.path()).await;
+
+        let dirs = vec![installed.path().to_path_buf(), target.path().join("missing")];
+        let reusable = ReusableArtifacts::load(&dirs, target.path()).await;
+        assert_eq!(reusable.file(&metadata.ovmf.sha256), Some(installed.path().join(&metadata.ovmf.path).as_path()));
+        assert_eq!(reusable.file(&[0; 32]), None);
+
+        let mut image = metadata.cvm.images.gpu.clone();
+        let verity_disk = installed.path().join(&image.verity.disk.path);
+        assert_eq!(reusable.verity_disk(&image), Some(verity_disk.as_path()));
+
+        image.verity.root_hash = [2; 32];
+        assert_eq!(reusable.verity_disk(&image), None);
+    }
+
+    #[tokio::test]
+    async fn reuse_unchanged_artifact() {
+        let installed = tempdir().expect("failed to create tempdir");
+        let target = tempdir().expect("failed to create tempdir");
+        let metadata = pack(installed.path()).await;
+        let reusable = ReusableArtifacts::load(&[installed.path().to_path_buf()], target.path()).await;
+
+        // An unreachable URL makes sure nothing gets downloaded.
+        let downloader =
+            ArtifactsDownloader::new("1.0.0".into(), vec![VmType::Cpu]).with_artifacts_url("http://127.0.0.1:0".into());
+        let kernel = &metadata.cvm.images.cpu.kernel;
+        let path = downloader
+            .install_artifact(&kernel.path, reusable.file(&kernel.sha256), target.path())
+            .await
+            .expect("failed to install artifact");
+        assert_eq!(path, target.path().join(&kernel.path));
+        assert_eq!(std::fs::read(&path).expect("failed to read kernel"), b"cpu-vmlinuz");
+
+        let source = std::fs::metadata(installed.path().join(&kernel.path)).expect("no source");
+        let target = std::fs::metadata(&path).expect("no target");
+        assert_eq!(source.ino(), target.ino());
+    }
+
+    #[tokio::test]
+    async fn write_file_atomically() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("metadata.json");
+        write_file(&path, b"old").await.expect("failed to write");
+        write_file(&path, b"new").await.expect("failed to write");
+        assert_eq!(std::fs::read(&path).expect("failed to read"), b"new");
+
+        let files: Vec<_> = std::fs::read_dir(dir.path()).expect("failed to list").collect();
+        assert_eq!(files.len(), 1, "partial files left behind: {files:?}");
+    }
+}
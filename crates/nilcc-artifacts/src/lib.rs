@@ -3,6 +3,8 @@ use std::fmt;
 
 pub mod downloader;
 pub mod metadata;
+pub mod packer;
+pub mod signature;
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum VmType {
@@ -23,4 +25,7 @@ impl fmt::Display for VmType {
 pub struct Artifacts {
     pub metadata: ArtifactsMetadata,
     pub metadata_hash: [u8; 32],
+
+    /// The name of the key that signed the metadata, if its signature was verified.
+    pub signer: Option<String>,
 }
@@ -21,6 +21,16 @@ pub struct ArtifactsMetadata {
 
     /// Information about the CVM images.
     pub cvm: Cvm,
+
+    /// The SEV-SNP guest policy CVMs are launched with.
+    // Note: artifacts built before this was introduced use the default policy QEMU launches guests with.
+    #[serde(default)]
+    pub guest_policy: GuestPolicy,
+
+    /// The virtual CPU CVMs are launched with.
+    // Note: artifacts built before this was introduced use the EPYC-v4 model with the parameters used on Genoa hosts.
+    #[serde(default)]
+    pub cpu: CvmCpu,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -56,6 +66,127 @@ pub struct Cvm {
     pub images: CvmImages,
 }
 
+/// The SEV-SNP guest policy a CVM is launched with.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GuestPolicy {
+    /// Whether the host is allowed to debug the guest.
+    pub debug: bool,
+
+    /// Whether the guest is allowed to run on hosts that have SMT enabled.
+    pub smt: bool,
+
+    /// Whether a migration agent can be associated with the guest.
+    pub migrate_ma: bool,
+}
+
+impl GuestPolicy {
+    const SMT_BIT: u64 = 1 << 16;
+    const RESERVED_BIT: u64 = 1 << 17;
+    const MIGRATE_MA_BIT: u64 = 1 << 18;
+    const DEBUG_BIT: u64 = 1 << 19;
+
+    /// Parse a policy from its raw representation, as found in attestation reports.
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            debug: bits & Self::DEBUG_BIT != 0,
+            smt: bits & Self::SMT_BIT != 0,
+            migrate_ma: bits & Self::MIGRATE_MA_BIT != 0,
+        }
+    }
+
+    /// The raw representation of this policy, as passed to the hypervisor.
+    pub fn bits(&self) -> u64 {
+        let mut bits = Self::RESERVED_BIT;
+        for (enabled, bit) in
+            [(self.debug, Self::DEBUG_BIT), (self.smt, Self::SMT_BIT), (self.migrate_ma, Self::MIGRATE_MA_BIT)]
+        {
+            if enabled {
+                bits |= bit;
+            }
+        }
+        bits
+    }
+
+    /// Find the first property where this policy is weaker than the required one, if any.
+    pub fn weaker_than(&self, required: &Self) -> Option<&'static str> {
+        [
+            ("debug", self.debug, required.debug),
+            ("smt", self.smt, required.smt),
+            ("migrate_ma", self.migrate_ma, required.migrate_ma),
+        ]
+        .into_iter()
+        .find(|(_, allowed, required)| *allowed && !*required)
+        .map(|(name, _, _)| name)
+    }
+}
+
+impl Default for GuestPolicy {
+    /// The policy QEMU launches guests with by default: SMT is allowed, debugging and migration agents are not.
+    fn default() -> Self {
+        Self { debug: false, smt: true, migrate_ma: false }
+    }
+}
+
+impl fmt::Display for GuestPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.bits())
+    }
+}
+
+/// The virtual CPU a CVM is launched with.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct CvmCpu {
+    /// The CPU model, which is part of the CVM's measurement.
+    pub model: CpuModel,
+
+    /// The position of the C-bit in page table entries.
+    pub cbitpos: u8,
+
+    /// The number of physical address bits lost when memory encryption is enabled.
+    pub reduced_phys_bits: u8,
+}
+
+impl Default for CvmCpu {
+    fn default() -> Self {
+        Self { model: CpuModel::EpycV4, cbitpos: 51, reduced_phys_bits: 1 }
+    }
+}
+
+/// A CPU model that CVMs can be launched with.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CpuModel {
+    #[default]
+    #[serde(rename = "EPYC-v4")]
+    EpycV4,
+
+    #[serde(rename = "EPYC-Milan")]
+    EpycMilan,
+
+    #[serde(rename = "EPYC-Milan-v2")]
+    EpycMilanV2,
+
+    #[serde(rename = "EPYC-Genoa")]
+    EpycGenoa,
+
+    #[serde(rename = "EPYC-Genoa-v1")]
+    EpycGenoaV1,
+}
+
+impl fmt::Display for CpuModel {
+    /// The model's name, as passed to QEMU.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::EpycV4 => "EPYC-v4",
+            Self::EpycMilan => "EPYC-Milan",
+            Self::EpycMilanV2 => "EPYC-Milan-v2",
+            Self::EpycGenoa => "EPYC-Genoa",
+            Self::EpycGenoaV1 => "EPYC-Genoa-v1",
+        };
+        write!(f, "{s}")
+    }
+}
+
 pub struct KernelArgs<'a> {
     pub docker_compose_hash: &'a str,
     pub filesystem_root_hash: &'a [u8; 32],
@@ -171,13 +302,40 @@ impl fmt::Display for DiskFormat {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
+    use sha2::{Digest, Sha256};
 
     #[test]
     fn serde() {
-        let input = r#"{ "ovmf": { "path": "vm_images/ovmf/OVMF.fd", "sha256": "e842c3c58a54172592f6345ae7f44b1c7fe7c76af9578765932a77674e4475bb" }, "initrd": { "path": "initramfs/initramfs.cpio.gz", "sha256": "115c25046d4f357edae4506be45c5c6a79543a15db1b4ef2ec94bcfe70bd66ec" }, "cvm": { "cmdline": "panic=-1 root=/dev/sda2 verity_disk=/dev/sdb verity_roothash={VERITY_ROOT_HASH} state_disk=/dev/sdc docker_compose_disk=/dev/sr0 docker_compose_hash={DOCKER_COMPOSE_HASH}", "images": { "cpu": { "disk": { "path": "vm_images/cvm-cpu.qcow2", "format": "qcow2", "sha256": "1287785f6a6a2cb08f0c25b864f8976a76e7e0d2a7c906738e2bc374266b3708" }, "verity": { "disk": { "path": "vm_images/cvm-cpu-verity/verity-hash-dev", "format": "raw" }, "root_hash": "4324eabc4d0d9aa2aed99f7ac16bd118473f455ab97841674afd3bc318d755cb" }, "kernel": { "path": "vm_images/kernel/cpu-vmlinuz", "sha256": "92cefd4d94338ad808d3c1a1be3b1d166d92a654fcb52aecdbe8905e7970817e" } }, "gpu": { "disk": { "path": "vm_images/cvm-gpu.qcow2", "format": "qcow2", "sha256": "1287785f6a6a2cb08f0c25b864f8976a76e7e0d2a7c906738e2bc374266b3708" }, "verity": { "disk": { "path": "vm_images/cvm-gpu-verity/verity-hash-dev", "format": "raw" }, "root_hash": "4324eabc4d0d9aa2aed99f7ac16bd118473f455ab97841674afd3bc318d755cb" }, "kernel": { "path": "vm_images/kernel/gpu-vmlinuz", "sha256": "92cefd4d94338ad808d3c1a1be3b1d166d92a654fcb52aecdbe8905e7970817e" } } } } }"#;
-        let meta: ArtifactsMetadata = serde_json::from_str(input).expect("failed to deserialize");
-        let serialized = serde_json::to_string(&meta).expect("failed to serialize");
-        assert_eq!(serde_json::from_str::<ArtifactsMetadata>(&serialized).expect("failed ot parse"), meta);
+        for vector in nilcc_test_vectors::metadata() {
+            assert_eq!(hex::encode(Sha256::digest(vector.contents)), vector.sha256, "vector {}", vector.name);
+            let meta: ArtifactsMetadata = serde_json::from_str(vector.contents).expect("failed to deserialize");
+            let serialized = serde_json::to_string(&meta).expect("failed to serialize");
+            assert_eq!(serde_json::from_str::<ArtifactsMetadata>(&serialized).expect("failed ot parse"), meta);
+        }
+    }
+
+    #[test]
+    fn default_guest_policy_bits() {
+        let policy = GuestPolicy::default();
+        assert_eq!(policy.bits(), 0x30000);
+        assert_eq!(GuestPolicy::from_bits(policy.bits()), policy);
+    }
+
+    #[rstest]
+    #[case::same(GuestPolicy::default(), None)]
+    #[case::stricter(GuestPolicy { smt: false, ..Default::default() }, None)]
+    #[case::debug(GuestPolicy { debug: true, ..Default::default() }, Some("debug"))]
+    #[case::migrate_ma(GuestPolicy { migrate_ma: true, ..Default::default() }, Some("migrate_ma"))]
+    fn weaker_guest_policy(#[case] policy: GuestPolicy, #[case] expected: Option<&str>) {
+        assert_eq!(policy.weaker_than(&GuestPolicy::default()), expected);
+    }
+
+    #[test]
+    fn cpu_defaults() {
+        let cpu: CvmCpu = serde_json::from_str(r#"{"model":"EPYC-Genoa"}"#).expect("failed to deserialize");
+        assert_eq!(cpu, CvmCpu { model: CpuModel::EpycGenoa, ..Default::default() });
+        assert_eq!(cpu.model.to_string(), "EPYC-Genoa");
     }
 
     #[test]
@@ -190,4 +348,20 @@ mod tests {
         let expected = "panic=-1 root=/dev/sda2 verity_disk=/dev/sdb verity_roothash=0000000000000000000000000000000000000000000000000000000000000000 state_disk=/dev/sdc docker_compose_disk=/dev/sr0 docker_compose_hash=aaa";
         assert_eq!(rendered, expected);
     }
+
+    #[test]
+    fn render_golden_kernel_command_lines() {
+        for vector in nilcc_test_vectors::kernel_command_lines() {
+            let filesystem_root_hash: [u8; 32] =
+                hex::decode(&vector.filesystem_root_hash).expect("invalid hex").try_into().expect("invalid length");
+            let cmdline = KernelCommandLine(vector.template.clone());
+            let rendered = cmdline
+                .render(KernelArgs {
+                    docker_compose_hash: &vector.docker_compose_hash,
+                    filesystem_root_hash: &filesystem_root_hash,
+                })
+                .expect("failed to render");
+            assert_eq!(rendered, vector.expected, "vector {}", vector.name);
+        }
+    }
 }
@@ -0,0 +1,217 @@
+use crate::Artifacts;
+use crate::metadata::{
+    Artifact, ArtifactsMetadata, Cvm, CvmDisk, CvmImage, CvmImages, DiskFormat, GuestPolicy, KernelArgs,
+    KernelCommandLine, MissingCommandLineParameter, Verity, VerityDisk,
+};
+use sha2::Digest;
+use sha2::Sha256;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tracing::info;
+
+/// The default kernel command line used by CVMs.
+pub const DEFAULT_KERNEL_COMMAND_LINE: &str = "panic=-1 root=/dev/sda2 verity_disk=/dev/sdb verity_roothash={VERITY_ROOT_HASH} state_disk=/dev/sdc docker_compose_disk=/dev/sr0 docker_compose_hash={DOCKER_COMPOSE_HASH}";
+
+/// The locally built files that make up an artifacts version.
+#[derive(Clone, Debug)]
+pub struct ArtifactsPackSpec {
+    /// The path to the OVMF file.
+    pub ovmf: PathBuf,
+
+    /// The path to the initrd file.
+    pub initrd: PathBuf,
+
+    /// The kernel command line.
+    pub cmdline: KernelCommandLine,
+
+    /// The CPU CVM image files.
+    pub cpu: CvmImageFiles,
+
+    /// The GPU CVM image files.
+    pub gpu: CvmImageFiles,
+}
+
+/// The locally built files for a CVM image.
+#[derive(Clone, Debug)]
+pub struct CvmImageFiles {
+    /// The path to the CVM disk.
+    pub disk: PathBuf,
+
+    /// The path to the verity disk.
+    pub verity_disk: PathBuf,
+
+    /// The verity root hash.
+    pub verity_root_hash: [u8; 32],
+
+    /// The path to the kernel.
+    pub kernel: PathBuf,
+}
+
+/// Packs locally built artifacts into the same layout downloaded artifacts use.
+pub struct ArtifactsPacker {
+    spec: ArtifactsPackSpec,
+}
+
+impl ArtifactsPacker {
+    pub fn new(spec: ArtifactsPackSpec) -> Self {
+        Self { spec }
+    }
+
+    /// Copy all artifacts into the target directory and generate their `metadata.json` file.
+    pub async fn pack(&self, target_dir: &Path) -> Result<Artifacts, PackError> {
+        let ArtifactsPackSpec { ovmf, initrd, cmdline, cpu, gpu } = &self.spec;
+        // Make sure the command line can be rendered before copying anything.
+        cmdline.render(KernelArgs { docker_compose_hash: "", filesystem_root_hash: &[0; 32] })?;
+
+        info!("Packing artifacts into {}", target_dir.display());
+        let ovmf = Self::copy_artifact(ovmf, target_dir, "vm_images/ovmf/OVMF.fd").await?;
+        let initrd = Self::copy_artifact(initrd, target_dir, "initramfs/initramfs.cpio.gz").await?;
+        let cpu = Self::pack_image(cpu, target_dir, "cpu").await?;
+        let gpu = Self::pack_image(gpu, target_dir, "gpu").await?;
+        let metadata = ArtifactsMetadata {
+            build: None,
+            ovmf,
+            initrd,
+            cvm: Cvm { cmdline: cmdline.clone(), images: CvmImages { cpu, gpu } },
+            guest_policy: GuestPolicy::default(),
+            cpu: Default::default(),
+        };
+        let raw_metadata = serde_json::to_vec_pretty(&metadata).expect("failed to serialize metadata");
+        let metadata_hash = Sha256::digest(&raw_metadata).into();
+        let metadata_path = target_dir.join("metadata.json");
+        fs::write(&metadata_path, raw_metadata).await.map_err(|e| PackError::Write(metadata_path, e))?;
+        Ok(Artifacts { metadata, metadata_hash, signer: None })
+    }
+
+    async fn pack_image(files: &CvmImageFiles, target_dir: &Path, vm_type: &str) -> Result<CvmImage, PackError> {
+        let CvmImageFiles { disk, verity_disk, verity_root_hash, kernel } = files;
+        let format = match disk.extension().and_then(|e| e.to_str()) {
+            Some("qcow2") => DiskFormat::Qcow2,
+            _ => DiskFormat::Raw,
+        };
+        let disk_path = format!("vm_images/cvm-{vm_type}.{format}");
+        let verity_disk_path = format!("vm_images/cvm-{vm_type}-verity/verity-hash-dev");
+        let kernel_path = format!("vm_images/kernel/{vm_type}-vmlinuz");
+        let disk = Self::copy_artifact(disk, target_dir, &disk_path).await?;
+        // The verity disk's hash is implicitly checked via the verity root hash.
+        Self::copy_artifact(verity_disk, target_dir, &verity_disk_path).await?;
+        let kernel = Self::copy_artifact(kernel, target_dir, &kernel_path).await?;
+        Ok(CvmImage {
+            disk: CvmDisk { artifact: disk, format },
+            verity: Verity {
+                disk: VerityDisk { path: verity_disk_path, format: DiskFormat::Raw },
+                root_hash: *verity_root_hash,
+            },
+            kernel,
+        })
+    }
+
+    async fn copy_artifact(source: &Path, target_dir: &Path, artifact_path: &str) -> Result<Artifact, PackError> {
+        let target = target_dir.join(artifact_path);
+        info!("Copying {} into {}", source.display(), target.display());
+        let parent = target.parent().ok_or(PackError::NoParent)?;
+        fs::create_dir_all(parent).await.map_err(|e| PackError::Write(parent.into(), e))?;
+        fs::copy(source, &target).await.map_err(|e| PackError::Read(source.into(), e))?;
+        let sha256 = Self::hash_file(&target).await.map_err(|e| PackError::Read(target, e))?;
+        Ok(Artifact { path: artifact_path.into(), sha256 })
+    }
+
+    async fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+        let mut file = File::open(path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 1024 * 1024];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(hasher.finalize().into())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PackError {
+    #[error("no parent in target path")]
+    NoParent,
+
+    #[error("invalid kernel command line: {0}")]
+    CommandLine(#[from] MissingCommandLineParameter),
+
+    #[error("could not read {0}: {1}")]
+    Read(PathBuf, io::Error),
+
+    #[error("could not write {0}: {1}")]
+    Write(PathBuf, io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn pack() {
+        let sources = tempdir().expect("failed to create tempdir");
+        let target = tempdir().expect("failed to create tempdir");
+        let write = |name: &str| {
+            let path = sources.path().join(name);
+            std::fs::write(&path, name).expect("failed to write file");
+            path
+        };
+        let cpu = CvmImageFiles {
+            disk: write("cpu.qcow2"),
+            verity_disk: write("cpu-verity"),
+            verity_root_hash: [1; 32],
+            kernel: write("cpu-vmlinuz"),
+        };
+        let gpu = CvmImageFiles {
+            disk: write("gpu.raw"),
+            verity_disk: write("gpu-verity"),
+            verity_root_hash: [2; 32],
+            kernel: write("gpu-vmlinuz"),
+        };
+        let spec = ArtifactsPackSpec {
+            ovmf: write("OVMF.fd"),
+            initrd: write("initrd"),
+            cmdline: KernelCommandLine(DEFAULT_KERNEL_COMMAND_LINE.into()),
+            cpu,
+            gpu,
+        };
+        let artifacts = ArtifactsPacker::new(spec).pack(target.path()).await.expect("failed to pack");
+        let metadata = &artifacts.metadata;
+        assert_eq!(metadata.ovmf.sha256, <[u8; 32]>::from(Sha256::digest("OVMF.fd")));
+        assert_eq!(metadata.cvm.images.cpu.disk.artifact.path, "vm_images/cvm-cpu.qcow2");
+        assert_eq!(metadata.cvm.images.cpu.disk.format, DiskFormat::Qcow2);
+        assert_eq!(metadata.cvm.images.gpu.disk.artifact.path, "vm_images/cvm-gpu.raw");
+        assert_eq!(metadata.cvm.images.gpu.verity.root_hash, [2; 32]);
+
+        let kernel = std::fs::read(target.path().join(&metadata.cvm.images.gpu.kernel.path)).expect("no kernel");
+        assert_eq!(kernel, b"gpu-vmlinuz");
+
+        let raw_metadata = std::fs::read(target.path().join("metadata.json")).expect("no metadata");
+        assert_eq!(artifacts.metadata_hash, <[u8; 32]>::from(Sha256::digest(&raw_metadata)));
+        let decoded: ArtifactsMetadata = serde_json::from_slice(&raw_metadata).expect("invalid metadata");
+        assert_eq!(&decoded, metadata);
+    }
+
+    #[tokio::test]
+    async fn invalid_command_line() {
+        let target = tempdir().expect("failed to create tempdir");
+        let files =
+            CvmImageFiles { disk: "a".into(), verity_disk: "b".into(), verity_root_hash: [0; 32], kernel: "c".into() };
+        let spec = ArtifactsPackSpec {
+            ovmf: "ovmf".into(),
+            initrd: "initrd".into(),
+            cmdline: KernelCommandLine("panic=-1".into()),
+            cpu: files.clone(),
+            gpu: files,
+        };
+        let err = ArtifactsPacker::new(spec).pack(target.path()).await.expect_err("pack succeeded");
+        assert!(matches!(err, PackError::CommandLine(_)), "{err:?}");
+    }
+}
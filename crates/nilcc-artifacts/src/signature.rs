@@ -0,0 +1,126 @@
+use ring::signature::{ED25519, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_with::hex::Hex;
+use serde_with::serde_as;
+use std::fmt;
+use std::str::FromStr;
+
+/// The name of the file that holds the detached signature for `metadata.json`.
+///
+/// This contains the hex encoded ed25519 signature over the raw `metadata.json` contents.
+pub const METADATA_SIGNATURE_FILE: &str = "metadata.json.sig";
+
+/// A public key trusted to sign artifacts metadata.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SigningKey {
+    /// The identity of the signer, e.g. `nillion-release`.
+    pub name: String,
+
+    /// The signer's ed25519 public key.
+    #[serde_as(as = "Hex")]
+    pub public_key: [u8; 32],
+}
+
+impl SigningKey {
+    fn verifies(&self, message: &[u8], signature: &[u8]) -> bool {
+        UnparsedPublicKey::new(&ED25519, &self.public_key).verify(message, signature).is_ok()
+    }
+}
+
+impl fmt::Display for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.name, hex::encode(self.public_key))
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = ParseSigningKeyError;
+
+    /// Parse a key in the form `<name>:<hex public key>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, public_key) = s.rsplit_once(':').ok_or(ParseSigningKeyError::MissingName)?;
+        if name.is_empty() {
+            return Err(ParseSigningKeyError::MissingName);
+        }
+        let mut key = [0; 32];
+        hex::decode_to_slice(public_key, &mut key).map_err(|_| ParseSigningKeyError::PublicKey)?;
+        Ok(Self { name: name.into(), public_key: key })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ParseSigningKeyError {
+    #[error("expected a key in the form <name>:<hex public key>")]
+    MissingName,
+
+    #[error("public key is not a hex encoded 32 byte ed25519 key")]
+    PublicKey,
+}
+
+/// Verify a hex encoded detached signature over a message, returning the key that signed it.
+pub fn verify_signature<'a>(
+    keys: &'a [SigningKey],
+    message: &[u8],
+    signature: &str,
+) -> Result<&'a SigningKey, SignatureError> {
+    let signature = hex::decode(signature.trim()).map_err(|_| SignatureError::Malformed)?;
+    keys.iter().find(|key| key.verifies(message, &signature)).ok_or(SignatureError::Untrusted)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SignatureError {
+    #[error("signature is not hex encoded")]
+    Malformed,
+
+    #[error("not signed by any trusted key")]
+    Untrusted,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    fn make_key(name: &str) -> (Ed25519KeyPair, SigningKey) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).expect("failed to generate key");
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("invalid key");
+        let public_key = key_pair.public_key().as_ref().try_into().expect("invalid public key length");
+        (key_pair, SigningKey { name: name.into(), public_key })
+    }
+
+    #[test]
+    fn verify() {
+        let (release, release_key) = make_key("release");
+        let (_, other_key) = make_key("other");
+        let keys = [other_key, release_key];
+        let metadata = br#"{"ovmf":{}}"#;
+        let signature = format!("{}\n", hex::encode(release.sign(metadata)));
+
+        let signer = verify_signature(&keys, metadata, &signature).expect("signature not valid");
+        assert_eq!(signer.name, "release");
+
+        let result = verify_signature(&keys, br#"{"ovmf":null}"#, &signature);
+        assert!(matches!(result, Err(SignatureError::Untrusted)));
+
+        let result = verify_signature(&keys[..1], metadata, &signature);
+        assert!(matches!(result, Err(SignatureError::Untrusted)));
+
+        let result = verify_signature(&keys, metadata, "not hex");
+        assert!(matches!(result, Err(SignatureError::Malformed)));
+    }
+
+    #[test]
+    fn parse_key() {
+        let (_, key) = make_key("nillion-release");
+        let parsed: SigningKey = key.to_string().parse().expect("failed to parse");
+        assert_eq!(parsed, key);
+
+        assert!("deadbeef".parse::<SigningKey>().is_err());
+        assert!(":deadbeef".parse::<SigningKey>().is_err());
+        assert!("release:deadbeef".parse::<SigningKey>().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Golden test vectors for nilcc attestation verification.
+//!
+//! The raw vectors live under the `vectors` directory so they can be consumed from other languages as well. This
+//! crate embeds them and exposes them as typed values.
+
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+static REPORTS: LazyLock<Vec<ReportVector>> =
+    LazyLock::new(|| parse("reports.json", include_str!("../vectors/reports.json")));
+static REPORT_DATA: LazyLock<Vec<ReportDataVector>> =
+    LazyLock::new(|| parse("report_data.json", include_str!("../vectors/report_data.json")));
+static KERNEL_COMMAND_LINES: LazyLock<Vec<KernelCommandLineVector>> =
+    LazyLock::new(|| parse("kernel_cmdline.json", include_str!("../vectors/kernel_cmdline.json")));
+
+static METADATA: &[MetadataVector] = &[MetadataVector {
+    name: "release",
+    contents: include_str!("../vectors/metadata/release.json"),
+    sha256: "46cd45abb27a88325e913178bb138fb8fb0071ac22b47628277cf89cd09902e6",
+}];
+
+/// An attestation report along with the values a verifier is expected to derive from it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ReportVector {
+    /// The name of this vector.
+    pub name: String,
+
+    /// A description of where this report comes from.
+    pub description: String,
+
+    /// The processor a verifier should detect for this report, in snake case (e.g. `genoa`).
+    pub processor: String,
+
+    /// Whether this report was signed by real hardware.
+    ///
+    /// Synthetic reports have a zeroed signature and can only be used to test everything but signature verification.
+    pub hardware_signed: bool,
+
+    /// The TLS fingerprint bound into the report's `report_data`, as hex.
+    pub tls_fingerprint: String,
+
+    /// The report, in the JSON format used by `attestation_report::v2::AttestationReport`.
+    pub report: serde_json::Value,
+}
+
+/// The inputs to `report_data` encoding along with the expected output.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ReportDataVector {
+    /// The name of this vector.
+    pub name: String,
+
+    /// The TLS fingerprint, as hex.
+    pub tls_fingerprint: String,
+
+    /// The workload id, if the identity is bound.
+    pub workload_id: Option<String>,
+
+    /// The agent id, if the identity is bound.
+    pub agent_id: Option<String>,
+
+    /// The sha256 hash of the boot log, as hex, if one is bound.
+    #[serde(default)]
+    pub boot_log_hash: Option<String>,
+
+    /// The identity token public key, as hex, if one is bound.
+    #[serde(default)]
+    pub token_key: Option<String>,
+
+    /// The expected encoded `report_data`, as hex.
+    pub expected: String,
+}
+
+/// A kernel command line template along with the measured inputs and the expected rendered output.
+#[derive(Clone, Debug, Deserialize)]
+pub struct KernelCommandLineVector {
+    /// The name of this vector.
+    pub name: String,
+
+    /// The command line template, as found in an artifacts `metadata.json`.
+    pub template: String,
+
+    /// The docker compose hash, as hex.
+    pub docker_compose_hash: String,
+
+    /// The verity filesystem root hash, as hex.
+    pub filesystem_root_hash: String,
+
+    /// The expected rendered command line.
+    pub expected: String,
+}
+
+/// A sample artifacts `metadata.json` file.
+#[derive(Clone, Debug)]
+pub struct MetadataVector {
+    /// The name of this vector.
+    pub name: &'static str,
+
+    /// The raw contents of the file.
+    pub contents: &'static str,
+
+    /// The sha256 hash of the raw contents, as hex.
+    pub sha256: &'static str,
+}
+
+/// Get all attestation report vectors.
+pub fn reports() -> &'static [ReportVector] {
+    &REPORTS
+}
+
+/// Get an attestation report vector by name.
+pub fn report(name: &str) -> &'static ReportVector {
+    reports().iter().find(|vector| vector.name == name).unwrap_or_else(|| panic!("no report vector named {name}"))
+}
+
+/// Get all `report_data` encoding vectors.
+pub fn report_data() -> &'static [ReportDataVector] {
+    &REPORT_DATA
+}
+
+/// Get all kernel command line vectors.
+pub fn kernel_command_lines() -> &'static [KernelCommandLineVector] {
+    &KERNEL_COMMAND_LINES
+}
+
+/// Get all `metadata.json` samples.
+pub fn metadata() -> &'static [MetadataVector] {
+    METADATA
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(name: &str, contents: &str) -> T {
+    serde_json::from_str(contents).unwrap_or_else(|e| panic!("invalid test vector file {name}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectors_parse() {
+        assert!(!reports().is_empty());
+        assert!(!report_data().is_empty());
+        assert!(!kernel_command_lines().is_empty());
+        for vector in metadata() {
+            serde_json::from_str::<serde_json::Value>(vector.contents).expect("invalid metadata");
+        }
+    }
+}
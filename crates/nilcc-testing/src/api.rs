@@ -0,0 +1,139 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use nilcc_agent::{
+    clients::nilcc_api::{
+        EnvGroupResponse, HeartbeatResponse, HeartbeatWorkload, NilccApiClient, NilccApiError, VmEvent,
+    },
+    config::ApiConfig,
+    resources::{PublicIps, SystemResources},
+};
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+use tokio::{sync::Notify, time::timeout};
+use uuid::Uuid;
+
+/// An event reported to the nilcc API.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportedEvent {
+    /// The workload the event is for.
+    pub workload_id: Uuid,
+
+    /// The event.
+    pub event: VmEvent,
+
+    /// When the event happened.
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A [NilccApiClient] that records the events it's sent rather than sending them anywhere.
+///
+/// Heartbeats expect exactly the artifact versions that are already available and there are no environment variable
+/// groups.
+#[derive(Default)]
+pub struct FakeNilccApiClient {
+    events: Mutex<Vec<ReportedEvent>>,
+    event_reported: Notify,
+}
+
+impl FakeNilccApiClient {
+    /// The events reported so far, in the order they were reported.
+    pub fn events(&self) -> Vec<ReportedEvent> {
+        self.lock_events().clone()
+    }
+
+    /// The events reported so far for a workload.
+    pub fn workload_events(&self, workload_id: Uuid) -> Vec<VmEvent> {
+        self.lock_events().iter().filter(|e| e.workload_id == workload_id).map(|e| e.event.clone()).collect()
+    }
+
+    /// Wait until an event matching the given predicate is reported for a workload.
+    pub async fn wait_for_event<F>(
+        &self,
+        workload_id: Uuid,
+        max_wait: Duration,
+        predicate: F,
+    ) -> anyhow::Result<VmEvent>
+    where
+        F: Fn(&VmEvent) -> bool,
+    {
+        let find = || self.workload_events(workload_id).into_iter().find(|e| predicate(e));
+        let wait = async {
+            loop {
+                // Register before checking so an event reported in between isn't missed.
+                let notified = self.event_reported.notified();
+                if let Some(event) = find() {
+                    return event;
+                }
+                notified.await;
+            }
+        };
+        timeout(max_wait, wait).await.map_err(|_| {
+            let events = self.workload_events(workload_id);
+            anyhow::anyhow!("timed out waiting for event for workload {workload_id}, reported events: {events:?}")
+        })
+    }
+
+    fn lock_events(&self) -> MutexGuard<'_, Vec<ReportedEvent>> {
+        self.events.lock().expect("lock poisoned")
+    }
+}
+
+#[async_trait]
+impl NilccApiClient for FakeNilccApiClient {
+    async fn register(
+        &self,
+        _config: &ApiConfig,
+        _resources: &SystemResources,
+        _public_ips: PublicIps,
+    ) -> Result<(), NilccApiError> {
+        Ok(())
+    }
+
+    async fn report_vm_event(
+        &self,
+        workload_id: Uuid,
+        event: VmEvent,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), NilccApiError> {
+        self.lock_events().push(ReportedEvent { workload_id, event, timestamp });
+        self.event_reported.notify_waiters();
+        Ok(())
+    }
+
+    async fn heartbeat(
+        &self,
+        available_artifact_versions: Vec<String>,
+        _workloads: Vec<HeartbeatWorkload>,
+    ) -> Result<HeartbeatResponse, NilccApiError> {
+        Ok(HeartbeatResponse { expected_artifact_versions: available_artifact_versions })
+    }
+
+    async fn env_group(&self, name: &str) -> Result<EnvGroupResponse, NilccApiError> {
+        Err(NilccApiError::Api { status: StatusCode::NOT_FOUND, message: format!("env group {name} not found") })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn wait_for_event() {
+        let client = FakeNilccApiClient::default();
+        let workload_id = Uuid::new_v4();
+        let other_id = Uuid::new_v4();
+        client.report_vm_event(other_id, VmEvent::Running, Utc::now()).await.expect("failed to report");
+        client.report_vm_event(workload_id, VmEvent::Starting, Utc::now()).await.expect("failed to report");
+
+        let max_wait = Duration::from_millis(100);
+        let event = client
+            .wait_for_event(workload_id, max_wait, |e| matches!(e, VmEvent::Starting))
+            .await
+            .expect("event not reported");
+        assert_eq!(event, VmEvent::Starting);
+        assert!(client.wait_for_event(workload_id, max_wait, |e| matches!(e, VmEvent::Running)).await.is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    routing::{get, post},
+};
+use chrono::Utc;
+use cvm_agent_models::{
+    bootstrap::{BootstrapRequest, BootstrapStatus, BootstrapStep},
+    config::{DomainsConfigRequest, HeartbeatConfigRequest},
+    container::Container,
+    health::{EventKind, HealthResponse, LastEvent},
+};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tracing::error;
+
+#[derive(Default)]
+struct AgentState {
+    bootstrap_requests: Vec<BootstrapRequest>,
+    domains: Vec<String>,
+    heartbeat_interval: Option<Duration>,
+    last_event: Option<LastEvent>,
+}
+
+/// A handle to an in-process stand-in for the cvm-agent that runs inside every CVM.
+///
+/// Bootstrapping completes as soon as it's requested and the CVM's HTTPS endpoint is always reported as functional, so
+/// a workload is considered to be running as soon as it's bootstrapped.
+#[derive(Clone, Default)]
+pub struct FakeCvmAgent {
+    state: Arc<Mutex<AgentState>>,
+}
+
+impl FakeCvmAgent {
+    /// The bootstrap requests this agent received.
+    pub fn bootstrap_requests(&self) -> Vec<BootstrapRequest> {
+        self.state().bootstrap_requests.clone()
+    }
+
+    /// Whether this agent was bootstrapped.
+    pub fn bootstrapped(&self) -> bool {
+        !self.state().bootstrap_requests.is_empty()
+    }
+
+    /// The domains this agent was last told to serve.
+    pub fn domains(&self) -> Vec<String> {
+        self.state().domains.clone()
+    }
+
+    /// The heartbeat interval this agent was last configured with.
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        self.state().heartbeat_interval
+    }
+
+    /// Report an event the next time the agent's health is checked.
+    pub fn push_event(&self, kind: EventKind, message: impl Into<String>) {
+        let mut state = self.state();
+        let id = state.last_event.as_ref().map(|e| e.id + 1).unwrap_or_default();
+        state.last_event = Some(LastEvent { id, kind, message: message.into(), timestamp: Utc::now() });
+    }
+
+    fn state(&self) -> MutexGuard<'_, AgentState> {
+        self.state.lock().expect("lock poisoned")
+    }
+}
+
+/// A fake cvm-agent serving its API over HTTP.
+///
+/// The server is shut down when this is dropped.
+pub struct FakeCvmAgentServer {
+    agent: FakeCvmAgent,
+    address: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl FakeCvmAgentServer {
+    /// Start serving the cvm-agent API on the given address.
+    pub async fn spawn(address: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        let agent = FakeCvmAgent::default();
+        let router = Router::new()
+            .route("/api/v1/health", get(health))
+            .route("/api/v1/system/bootstrap", post(bootstrap))
+            .route("/api/v1/config/domains", post(set_domains))
+            .route("/api/v1/config/heartbeats", post(set_heartbeats))
+            .route("/api/v1/containers/list", get(list_containers))
+            .with_state(agent.clone());
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                error!("Fake cvm-agent server failed: {e}");
+            }
+        });
+        Ok(Self { agent, address, task })
+    }
+
+    /// The agent being served.
+    pub fn agent(&self) -> &FakeCvmAgent {
+        &self.agent
+    }
+
+    /// The address the agent is listening on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stop the server, waiting until its listening socket is closed.
+    pub async fn shutdown(mut self) {
+        self.task.abort();
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for FakeCvmAgentServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn health(State(agent): State<FakeCvmAgent>) -> Json<HealthResponse> {
+    let state = agent.state();
+    let bootstrapped = !state.bootstrap_requests.is_empty();
+    let step = if bootstrapped { BootstrapStep::Completed } else { BootstrapStep::Pending };
+    Json(HealthResponse {
+        https: true,
+        bootstrapped,
+        last_event: state.last_event.clone(),
+        bootstrap: Some(BootstrapStatus { step, running: false, error: None }),
+        certificate: None,
+        paused: false,
+        drift: None,
+    })
+}
+
+async fn bootstrap(State(agent): State<FakeCvmAgent>, Json(request): Json<BootstrapRequest>) {
+    let mut state = agent.state();
+    state.domains = vec![request.domain.clone()];
+    state.bootstrap_requests.push(request);
+}
+
+async fn set_domains(State(agent): State<FakeCvmAgent>, Json(request): Json<DomainsConfigRequest>) {
+    agent.state().domains = request.domains;
+}
+
+async fn set_heartbeats(State(agent): State<FakeCvmAgent>, Json(request): Json<HeartbeatConfigRequest>) {
+    agent.state().heartbeat_interval = Some(request.interval);
+}
+
+async fn list_containers() -> Json<Vec<Container>> {
+    Json(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nilcc_agent::clients::cvm_agent::{CvmAgentAuthKey, CvmAgentClient, DefaultCvmAgentClient};
+
+    #[tokio::test]
+    async fn api() {
+        let server = FakeCvmAgentServer::spawn(([127, 0, 0, 1], 0).into()).await.expect("failed to spawn");
+        let port = server.address().port();
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let auth_key = CvmAgentAuthKey::load_or_generate(&dir.path().join("key")).expect("failed to generate key");
+        let client = DefaultCvmAgentClient::new(auth_key).expect("failed to create client");
+
+        let health = client.check_health(port).await.expect("health check failed");
+        assert!(!health.bootstrapped);
+
+        server.agent().push_event(EventKind::Warning, "low disk");
+        let health = client.check_health(port).await.expect("health check failed");
+        assert_eq!(health.last_event.expect("no event").message, "low disk");
+
+        let domains = DomainsConfigRequest { domains: vec!["a.com".into(), "b.com".into()] };
+        client.set_domains_config(port, &domains).await.expect("failed to set domains");
+        assert_eq!(server.agent().domains(), domains.domains);
+        server.shutdown().await;
+    }
+}
@@ -0,0 +1,44 @@
+use async_trait::async_trait;
+use nilcc_agent::services::disk::{CreateIsoError, DiskService, IsoSpec, StoragePoolUsage};
+use nilcc_artifacts::metadata::DiskFormat;
+use std::path::Path;
+use tokio::fs;
+
+/// A [DiskService] that creates placeholder files rather than actual disks and ISOs.
+///
+/// Disks are created as empty files and ISOs contain the application's docker compose file, so tests can check which
+/// files exist without needing `qemu-img` or `mkisofs`.
+#[derive(Default)]
+pub struct FakeDiskService;
+
+#[async_trait]
+impl DiskService for FakeDiskService {
+    async fn create_disk(&self, path: &Path, _format: DiskFormat, _size_gib: u32) -> anyhow::Result<()> {
+        fs::write(path, b"").await?;
+        Ok(())
+    }
+
+    async fn create_qcow2_snapshot(&self, target: &Path, _origin: &Path) -> anyhow::Result<()> {
+        fs::write(target, b"").await?;
+        Ok(())
+    }
+
+    async fn resize_disk(&self, _path: &Path, _format: DiskFormat, _size_gib: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn delete_disk(&self, path: &Path) -> anyhow::Result<()> {
+        fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    async fn pool_usage(&self) -> anyhow::Result<Option<StoragePoolUsage>> {
+        Ok(None)
+    }
+
+    async fn create_application_iso(&self, path: &Path, spec: IsoSpec) -> Result<[u8; 32], CreateIsoError> {
+        let content_hash = spec.content_hash()?;
+        fs::write(path, spec.docker_compose_yaml).await.map_err(CreateIsoError::FilesWrite)?;
+        Ok(content_hash)
+    }
+}
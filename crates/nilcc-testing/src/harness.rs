@@ -0,0 +1,203 @@
+use crate::{
+    api::FakeNilccApiClient, cvm_agent::FakeCvmAgent, disk::FakeDiskService,
+    repositories::in_memory_repository_provider, vm::FakeVmClient, workloads::artifacts_metadata,
+};
+use nilcc_agent::{
+    clients::{
+        cvm_agent::{CvmAgentAuthKey, DefaultCvmAgentClient},
+        nilcc_api::VmEvent,
+    },
+    config::{DockerConfig, ZeroSslConfig},
+    repositories::{sqlite::RepositoryProvider, workload::Workload},
+    services::{
+        bandwidth::TcBandwidthLimiter,
+        vm::{DefaultVmService, VmService, VmServiceArgs},
+    },
+    workers::events::{EventWorker, EventWorkerArgs, WebhookDispatcher, WebhookDispatcherArgs},
+    zerossl::ZeroSslAccounts,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tempfile::TempDir;
+use uuid::Uuid;
+
+/// How long to wait for events by default.
+pub const DEFAULT_EVENT_WAIT: Duration = Duration::from_secs(30);
+
+/// A nilcc-agent wired up with fakes for everything that needs root, KVM, docker or the nilcc API.
+///
+/// Workloads are managed through the agent's actual [VmService] and events go through the actual event worker, so
+/// scenarios exercise the same code paths as a production agent up to the VM and the nilcc API.
+pub struct TestAgent {
+    /// The agent's id.
+    pub agent_id: Uuid,
+
+    /// The VM service managing workloads.
+    pub vm_service: DefaultVmService,
+
+    /// The VM client the VM service starts VMs through.
+    pub vm_client: Arc<FakeVmClient>,
+
+    /// The API client events are reported through.
+    pub api_client: Arc<FakeNilccApiClient>,
+
+    /// The repository provider backing this agent.
+    pub repository_provider: Arc<dyn RepositoryProvider>,
+
+    state_dir: TempDir,
+}
+
+impl TestAgent {
+    /// Create a new agent with an empty state.
+    pub async fn new() -> anyhow::Result<Self> {
+        let agent_id = Uuid::new_v4();
+        let state_dir = tempfile::tempdir()?;
+        let vm_client = Arc::new(FakeVmClient::default());
+        let api_client = Arc::new(FakeNilccApiClient::default());
+        let repository_provider: Arc<dyn RepositoryProvider> = Arc::new(in_memory_repository_provider().await?);
+        let cvm_agent_auth_key = CvmAgentAuthKey::load_or_generate(&state_dir.path().join("cvm-agent.key"))?;
+        let webhooks = WebhookDispatcher::new(WebhookDispatcherArgs {
+            agent_id,
+            clients: Vec::new(),
+            max_attempts: 1,
+            retry_interval: Duration::from_secs(1),
+            dead_letter_path: None,
+        });
+        let event_sender = EventWorker::spawn(EventWorkerArgs {
+            api_client: api_client.clone(),
+            repository_provider: repository_provider.clone(),
+            webhooks: Arc::new(webhooks),
+        });
+        let vm_service = DefaultVmService::new(VmServiceArgs {
+            agent_id,
+            state_path: state_dir.path().join("vms"),
+            vm_client: vm_client.clone(),
+            cvm_agent_client: Arc::new(DefaultCvmAgentClient::new(cvm_agent_auth_key.clone())?),
+            cvm_agent_auth_key,
+            disk_service: Arc::new(FakeDiskService),
+            cvm_artifacts_path: state_dir.path().join("artifacts"),
+            zerossl_accounts: ZeroSslAccounts::new(ZeroSslConfig {
+                eab_key_id: "eab-key-id".into(),
+                eab_mac_key: "eab-mac-key".into(),
+                pool: Vec::new(),
+            }),
+            docker_config: DockerConfig {
+                username: "nilcc".into(),
+                password: "password".into(),
+                registry_mirrors: Vec::new(),
+            },
+            event_sender,
+            repository_provider: repository_provider.clone(),
+            verifier_heartbeat_rpc: "http://127.0.0.1:8545".into(),
+            verifier_heartbeat_interval: Duration::from_secs(60),
+            verifier_contract_address: "0x0000000000000000000000000000000000000000".into(),
+            token_contract_address: "0x0000000000000000000000000000000000000000".into(),
+            ipv6: false,
+            time_sync: None,
+            private_pki: None,
+            platform_claims: None,
+            snp: Default::default(),
+            numa: None,
+            bandwidth_limiter: Arc::new(TcBandwidthLimiter::default()),
+        })
+        .await?;
+        Ok(Self { agent_id, vm_service, vm_client, api_client, repository_provider, state_dir })
+    }
+
+    /// The directory the agent keeps VM disks, ISOs and sockets in.
+    pub fn vms_path(&self) -> PathBuf {
+        self.state_dir.path().join("vms")
+    }
+
+    /// The path to the QEMU socket for a workload's VM.
+    pub fn socket_path(&self, workload_id: Uuid) -> PathBuf {
+        self.vms_path().join(format!("{workload_id}.sock"))
+    }
+
+    /// Store a workload along with its artifacts version and start its VM.
+    pub async fn create_workload(&self, workload: Workload) -> anyhow::Result<()> {
+        let mut artifacts = self.repository_provider.artifacts(Default::default()).await?;
+        if !artifacts.exists(&workload.artifacts_version).await? {
+            artifacts.create(&workload.artifacts_version, &artifacts_metadata()).await?;
+        }
+        artifacts.commit().await?;
+
+        let mut workloads = self.repository_provider.workloads(Default::default()).await?;
+        workloads.create(&workload).await?;
+        workloads.commit().await?;
+
+        self.vm_service.create_vm(workload, None).await?;
+        Ok(())
+    }
+
+    /// Stop a workload's VM, wait for its stop to be reported, and delete it.
+    pub async fn delete_workload(&self, workload_id: Uuid) -> anyhow::Result<()> {
+        self.vm_service.delete_vm(workload_id).await;
+        // Events for workloads that don't exist are dropped so only delete it once the event is out.
+        self.wait_for_event(workload_id, |e| matches!(e, VmEvent::Stopped)).await?;
+
+        let mut workloads = self.repository_provider.workloads(Default::default()).await?;
+        workloads.delete(workload_id).await?;
+        workloads.commit().await?;
+        Ok(())
+    }
+
+    /// Wait up to [DEFAULT_EVENT_WAIT] for an event matching the given predicate to be reported for a workload.
+    pub async fn wait_for_event<F>(&self, workload_id: Uuid, predicate: F) -> anyhow::Result<VmEvent>
+    where
+        F: Fn(&VmEvent) -> bool,
+    {
+        self.api_client.wait_for_event(workload_id, DEFAULT_EVENT_WAIT, predicate).await
+    }
+
+    /// The cvm-agent running inside a workload's VM, if the VM is running.
+    pub async fn cvm_agent(&self, workload: &Workload) -> Option<FakeCvmAgent> {
+        self.vm_client.cvm_agent(workload.cvm_agent_port()).await
+    }
+
+    /// Check whether a workload's VM is running.
+    pub async fn is_running(&self, workload_id: Uuid) -> bool {
+        self.vm_client.spec(&self.socket_path(workload_id)).await.is_some()
+    }
+
+    /// Check whether a file exists in the agent's VM directory.
+    pub fn vm_file_exists(&self, name: impl AsRef<Path>) -> bool {
+        self.vms_path().join(name).exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workloads::WorkloadBuilder;
+
+    #[tokio::test]
+    async fn create_bootstrap_delete() {
+        let agent = TestAgent::new().await.expect("failed to create agent");
+        let workload = WorkloadBuilder::new().registry_mirrors(vec!["https://mirror.nilcc.test".into()]).build();
+        let id = workload.id;
+        agent.create_workload(workload.clone()).await.expect("failed to create workload");
+
+        agent.wait_for_event(id, |e| matches!(e, VmEvent::Running)).await.expect("workload never ran");
+        assert!(agent.is_running(id).await);
+        assert!(agent.vm_file_exists(format!("{id}.iso")));
+
+        let cvm_agent = agent.cvm_agent(&workload).await.expect("no cvm agent");
+        let requests = cvm_agent.bootstrap_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].domain, workload.domain);
+        assert_eq!(requests[0].workload_id, Some(id));
+        assert_eq!(requests[0].registry_mirrors, vec!["https://mirror.nilcc.test".to_string()]);
+
+        agent.delete_workload(id).await.expect("failed to delete workload");
+        assert!(!agent.is_running(id).await);
+        assert!(!agent.vm_file_exists(format!("{id}.iso")));
+        assert_eq!(
+            agent.api_client.workload_events(id),
+            vec![VmEvent::Starting, VmEvent::AwaitingCert, VmEvent::Running, VmEvent::Stopped]
+        );
+    }
+}
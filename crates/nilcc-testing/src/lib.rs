@@ -0,0 +1,13 @@
+//! Fakes and helpers to run nilcc-agent scenarios end to end without root, KVM or docker.
+//!
+//! [TestAgent](harness::TestAgent) wires the agent's actual VM service and event worker to a fake VM client, whose VMs
+//! each run an in-process fake cvm-agent, a fake disk service, a fake nilcc API client and an in-memory database. This
+//! allows running full create, bootstrap, health check and delete flows in a regular test.
+
+pub mod api;
+pub mod cvm_agent;
+pub mod disk;
+pub mod harness;
+pub mod repositories;
+pub mod vm;
+pub mod workloads;
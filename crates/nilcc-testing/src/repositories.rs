@@ -0,0 +1,7 @@
+use nilcc_agent::repositories::sqlite::{SqliteDb, SqliteRepositoryProvider};
+
+/// Create a repository provider backed by an in-memory sqlite database with all migrations applied.
+pub async fn in_memory_repository_provider() -> anyhow::Result<SqliteRepositoryProvider> {
+    let db = SqliteDb::connect("sqlite://:memory:").await?;
+    Ok(SqliteRepositoryProvider::new(db))
+}
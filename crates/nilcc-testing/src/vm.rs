@@ -0,0 +1,165 @@
+use crate::cvm_agent::{FakeCvmAgent, FakeCvmAgentServer};
+use async_trait::async_trait;
+use nilcc_agent::{
+    clients::qemu::{QemuClientError, Result, VmClient, VmSpec},
+    services::vm::CVM_AGENT_PORT,
+};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+use tokio::sync::Mutex;
+
+struct FakeVm {
+    spec: VmSpec,
+    server: FakeCvmAgentServer,
+    paused: bool,
+}
+
+/// A [VmClient] that doesn't run any VMs.
+///
+/// Every VM started through this client gets a [FakeCvmAgent] listening on the host port that's forwarded to the
+/// cvm-agent's port, so the rest of the agent can talk to it like it would to a real CVM.
+#[derive(Default)]
+pub struct FakeVmClient {
+    vms: Mutex<HashMap<PathBuf, FakeVm>>,
+}
+
+impl FakeVmClient {
+    /// The number of VMs that are currently running.
+    pub async fn running_vms(&self) -> usize {
+        self.vms.lock().await.len()
+    }
+
+    /// The spec the VM behind the given socket was started with.
+    pub async fn spec(&self, socket_path: &Path) -> Option<VmSpec> {
+        self.vms.lock().await.get(socket_path).map(|vm| vm.spec.clone())
+    }
+
+    /// Whether the VM behind the given socket is paused, if it's running.
+    pub async fn is_paused(&self, socket_path: &Path) -> Option<bool> {
+        self.vms.lock().await.get(socket_path).map(|vm| vm.paused)
+    }
+
+    /// The cvm-agent of the running VM whose agent is reachable on the given host port.
+    pub async fn cvm_agent(&self, cvm_agent_port: u16) -> Option<FakeCvmAgent> {
+        let vms = self.vms.lock().await;
+        vms.values().find(|vm| vm.server.address().port() == cvm_agent_port).map(|vm| vm.server.agent().clone())
+    }
+
+    /// Simulate the VM behind the given socket crashing, returning whether it was running.
+    pub async fn crash_vm(&self, socket_path: &Path) -> bool {
+        let vm = self.vms.lock().await.remove(socket_path);
+        match vm {
+            Some(vm) => {
+                vm.server.shutdown().await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn spawn_cvm_agent(spec: &VmSpec) -> Result<FakeCvmAgentServer> {
+        let port = spec
+            .port_forwarding
+            .iter()
+            .find_map(|(host, guest)| (*guest == CVM_AGENT_PORT).then_some(*host))
+            .ok_or_else(|| QemuClientError::Qmp("cvm-agent port is not forwarded".into()))?;
+        let server = FakeCvmAgentServer::spawn(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await?;
+        Ok(server)
+    }
+}
+
+#[async_trait]
+impl VmClient for FakeVmClient {
+    fn build_start_vm_args(&self, _spec: &VmSpec, _socket_path: &Path) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn start_vm(&self, socket_path: &Path, spec: VmSpec) -> Result<()> {
+        let mut vms = self.vms.lock().await;
+        if vms.contains_key(socket_path) {
+            return Err(QemuClientError::VmAlreadyRunning);
+        }
+        let server = Self::spawn_cvm_agent(&spec).await?;
+        vms.insert(socket_path.into(), FakeVm { spec, server, paused: false });
+        Ok(())
+    }
+
+    async fn restart_vm(&self, socket_path: &Path) -> Result<()> {
+        let mut vms = self.vms.lock().await;
+        let vm = vms.remove(socket_path).ok_or(QemuClientError::VmNotRunning)?;
+        // A rebooted CVM starts off with a fresh cvm-agent.
+        vm.server.shutdown().await;
+        let server = Self::spawn_cvm_agent(&vm.spec).await?;
+        vms.insert(socket_path.into(), FakeVm { spec: vm.spec, server, paused: false });
+        Ok(())
+    }
+
+    async fn stop_vm(&self, socket_path: &Path, _force: bool) -> Result<()> {
+        let vm = self.vms.lock().await.remove(socket_path).ok_or(QemuClientError::VmNotRunning)?;
+        vm.server.shutdown().await;
+        Ok(())
+    }
+
+    async fn pause_vm(&self, socket_path: &Path) -> Result<()> {
+        let mut vms = self.vms.lock().await;
+        let vm = vms.get_mut(socket_path).ok_or(QemuClientError::VmNotRunning)?;
+        vm.paused = true;
+        Ok(())
+    }
+
+    async fn resume_vm(&self, socket_path: &Path) -> Result<()> {
+        let mut vms = self.vms.lock().await;
+        let vm = vms.get_mut(socket_path).ok_or(QemuClientError::VmNotRunning)?;
+        vm.paused = false;
+        Ok(())
+    }
+
+    async fn is_vm_running(&self, socket_path: &Path) -> bool {
+        self.vms.lock().await.contains_key(socket_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workloads::free_port;
+
+    fn make_spec(cvm_agent_port: u16) -> VmSpec {
+        VmSpec { port_forwarding: vec![(cvm_agent_port, CVM_AGENT_PORT)], ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn lifecycle() {
+        let client = FakeVmClient::default();
+        let socket_path = Path::new("/tmp/vm.sock");
+        let port = free_port();
+        client.start_vm(socket_path, make_spec(port)).await.expect("failed to start");
+        assert!(client.is_vm_running(socket_path).await);
+        assert!(client.cvm_agent(port).await.is_some());
+        assert!(matches!(client.start_vm(socket_path, make_spec(port)).await, Err(QemuClientError::VmAlreadyRunning)));
+
+        client.pause_vm(socket_path).await.expect("failed to pause");
+        assert_eq!(client.is_paused(socket_path).await, Some(true));
+        client.resume_vm(socket_path).await.expect("failed to resume");
+        assert_eq!(client.is_paused(socket_path).await, Some(false));
+
+        client.restart_vm(socket_path).await.expect("failed to restart");
+        assert!(client.cvm_agent(port).await.is_some());
+
+        client.stop_vm(socket_path, false).await.expect("failed to stop");
+        assert!(!client.is_vm_running(socket_path).await);
+        assert!(matches!(client.stop_vm(socket_path, false).await, Err(QemuClientError::VmNotRunning)));
+    }
+
+    #[tokio::test]
+    async fn missing_cvm_agent_port() {
+        let client = FakeVmClient::default();
+        let mut spec = make_spec(free_port());
+        spec.port_forwarding.clear();
+        assert!(client.start_vm(Path::new("/tmp/vm.sock"), spec).await.is_err());
+        assert_eq!(client.running_vms().await, 0);
+    }
+}
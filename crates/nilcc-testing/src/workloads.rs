@@ -0,0 +1,166 @@
+use nilcc_agent::repositories::workload::Workload;
+use nilcc_agent_models::workloads::create::{StateDisk, UpgradeChannel, WorkloadPriority};
+use nilcc_artifacts::metadata::{
+    Artifact, ArtifactsMetadata, Cvm, CvmDisk, CvmImage, CvmImages, DiskFormat, GuestPolicy, KernelCommandLine, Verity,
+    VerityDisk,
+};
+use std::{collections::HashMap, net::TcpListener};
+use uuid::Uuid;
+
+/// The artifacts version workloads are created with by default.
+pub const DEFAULT_ARTIFACTS_VERSION: &str = "0.1.0";
+
+const DEFAULT_DOCKER_COMPOSE: &str = r#"
+services:
+  api:
+    image: caddy:2.10
+    command: caddy respond --listen :80 "hello"
+"#;
+
+/// Find a port on localhost that's currently free.
+pub fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+    listener.local_addr().expect("no local address").port()
+}
+
+/// Artifacts metadata pointing to CPU and GPU images within the artifacts directory.
+///
+/// None of the files it points to need to exist when using a [FakeDiskService](crate::disk::FakeDiskService).
+pub fn artifacts_metadata() -> ArtifactsMetadata {
+    let image = |kind: &str| CvmImage {
+        disk: CvmDisk {
+            artifact: Artifact { path: format!("vm_images/cvm-{kind}.qcow2"), sha256: [0; 32] },
+            format: DiskFormat::Qcow2,
+        },
+        verity: Verity {
+            disk: VerityDisk { path: format!("vm_images/cvm-{kind}-verity/verity-hash-dev"), format: DiskFormat::Raw },
+            root_hash: [0; 32],
+        },
+        kernel: Artifact { path: format!("vm_images/kernel/{kind}-vmlinuz"), sha256: [0; 32] },
+    };
+    ArtifactsMetadata {
+        build: None,
+        ovmf: Artifact { path: "vm_images/ovmf/OVMF.fd".into(), sha256: [0; 32] },
+        initrd: Artifact { path: "initramfs/initramfs.cpio.gz".into(), sha256: [0; 32] },
+        cvm: Cvm {
+            cmdline: KernelCommandLine(
+                "panic=-1 root=/dev/sda2 verity_disk=/dev/sdb verity_roothash={VERITY_ROOT_HASH} state_disk=/dev/sdc \
+                 docker_compose_disk=/dev/sr0 docker_compose_hash={DOCKER_COMPOSE_HASH}"
+                    .into(),
+            ),
+            images: CvmImages { cpu: image("cpu"), gpu: image("gpu") },
+        },
+        guest_policy: GuestPolicy::default(),
+        cpu: Default::default(),
+    }
+}
+
+/// A builder for workloads.
+///
+/// Workloads get a random id and free ports on localhost so that several of them can run at once.
+pub struct WorkloadBuilder {
+    workload: Workload,
+}
+
+impl WorkloadBuilder {
+    pub fn new() -> Self {
+        let id = Uuid::new_v4();
+        let workload = Workload {
+            id,
+            docker_compose: DEFAULT_DOCKER_COMPOSE.trim_start().into(),
+            artifacts_version: DEFAULT_ARTIFACTS_VERSION.into(),
+            env_vars: Default::default(),
+            env_groups: Default::default(),
+            files: Default::default(),
+            docker_credentials: Default::default(),
+            public_container_name: "api".into(),
+            public_container_port: 80,
+            memory_mb: 1024,
+            cpus: 1,
+            enabled: true,
+            gpus: Default::default(),
+            disk_space_gb: 1,
+            ports: [free_port(), free_port(), free_port()],
+            domain: format!("{id}.workloads.nilcc.test"),
+            last_reported_event: None,
+            heartbeat: None,
+            priority: WorkloadPriority::default(),
+            preempted: false,
+            log_encryption_key: None,
+            upgrade_channel: UpgradeChannel::default(),
+            log_rotation: None,
+            state_disk: StateDisk::default(),
+            zerossl_account: None,
+            env_vars_restart_pending: false,
+            registry_mirrors: None,
+            labels: Default::default(),
+            paused: false,
+            debug: false,
+            bandwidth_limits: None,
+            proxy_timeouts: None,
+            owner: None,
+            isolated: false,
+        };
+        Self { workload }
+    }
+
+    /// Set the docker compose file and the container and port that exposes the workload's API.
+    pub fn docker_compose(mut self, docker_compose: impl Into<String>, container: &str, port: u16) -> Self {
+        self.workload.docker_compose = docker_compose.into();
+        self.workload.public_container_name = container.into();
+        self.workload.public_container_port = port;
+        self
+    }
+
+    /// Set the artifacts version.
+    pub fn artifacts_version(mut self, version: impl Into<String>) -> Self {
+        self.workload.artifacts_version = version.into();
+        self
+    }
+
+    /// Set the domain.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.workload.domain = domain.into();
+        self
+    }
+
+    /// Set the environment variables.
+    pub fn env_vars(mut self, env_vars: HashMap<String, String>) -> Self {
+        self.workload.env_vars = env_vars;
+        self
+    }
+
+    /// Add a file.
+    pub fn file(mut self, name: impl Into<String>, contents: impl Into<Vec<u8>>) -> Self {
+        self.workload.files.insert(name.into(), contents.into());
+        self
+    }
+
+    /// Set the registry mirrors.
+    pub fn registry_mirrors(mut self, mirrors: Vec<String>) -> Self {
+        self.workload.registry_mirrors = Some(mirrors);
+        self
+    }
+
+    /// Set the state disk type.
+    pub fn state_disk(mut self, state_disk: StateDisk) -> Self {
+        self.workload.state_disk = state_disk;
+        self
+    }
+
+    /// Set the priority.
+    pub fn priority(mut self, priority: WorkloadPriority) -> Self {
+        self.workload.priority = priority;
+        self
+    }
+
+    pub fn build(self) -> Workload {
+        self.workload
+    }
+}
+
+impl Default for WorkloadBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
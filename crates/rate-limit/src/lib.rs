@@ -0,0 +1,222 @@
+//! Rate limiters shared by the agents.
+
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The default maximum number of clients a [RateLimiter] tracks at once.
+pub const DEFAULT_MAX_CLIENTS: usize = 10_000;
+
+/// A fixed window rate limiter keyed by client, where each client's window starts with its first request.
+///
+/// Only a limited number of clients are tracked at once. Once that's reached, the clients whose window elapsed or, if
+/// there's none, the least recently seen one are forgotten to make room for new ones. This means a flood of distinct
+/// clients can reset someone else's limit but it can never lock new clients out.
+pub struct RateLimiter<K = String> {
+    max_requests: u32,
+    window: Duration,
+    max_clients: usize,
+    clients: Mutex<HashMap<K, ClientWindow>>,
+}
+
+struct ClientWindow {
+    started_at: Instant,
+    last_request: Instant,
+    requests: u32,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    /// Allow up to `max_requests` requests per client within every `window`.
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self { max_requests, window, max_clients: DEFAULT_MAX_CLIENTS, clients: Default::default() }
+    }
+
+    /// Set the maximum number of clients tracked at once.
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients.max(1);
+        self
+    }
+
+    /// Register a request for a client and check whether it's allowed.
+    pub fn allow<Q>(&self, client: &Q, now: Instant) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let mut clients = self.clients.lock().expect("lock poisoned");
+        if !clients.contains_key(client) {
+            if clients.len() >= self.max_clients {
+                self.evict(&mut clients, now);
+            }
+            clients.insert(client.to_owned(), ClientWindow { started_at: now, last_request: now, requests: 0 });
+        }
+        let window = clients.get_mut(client).expect("client not tracked");
+        if now.saturating_duration_since(window.started_at) >= self.window {
+            window.started_at = now;
+            window.requests = 0;
+        }
+        window.last_request = now;
+        if window.requests >= self.max_requests {
+            return false;
+        }
+        window.requests += 1;
+        true
+    }
+
+    /// The number of clients currently being tracked.
+    pub fn tracked_clients(&self) -> usize {
+        self.clients.lock().expect("lock poisoned").len()
+    }
+
+    fn evict(&self, clients: &mut HashMap<K, ClientWindow>, now: Instant) {
+        clients.retain(|_, window| now.saturating_duration_since(window.started_at) < self.window);
+        if clients.len() < self.max_clients {
+            return;
+        }
+        let oldest = clients.iter().min_by_key(|(_, window)| window.last_request).map(|(client, _)| client.clone());
+        if let Some(oldest) = oldest {
+            clients.remove(&oldest);
+        }
+    }
+}
+
+/// A token bucket that holds up to a burst of tokens and gets one back every interval.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    tokens: u32,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Create a bucket that has all of its tokens available.
+    pub fn full(burst: u32, now: Instant) -> Self {
+        Self { tokens: burst, refilled_at: now }
+    }
+
+    /// The number of tokens available.
+    pub fn tokens(&self) -> u32 {
+        self.tokens
+    }
+
+    /// The instant at which the next token is added back.
+    pub fn next_refill(&self, interval: Duration) -> Instant {
+        self.refilled_at + interval
+    }
+
+    /// Take a token, if there's any left.
+    pub fn take(&mut self) -> bool {
+        match self.tokens.checked_sub(1) {
+            Some(tokens) => {
+                self.tokens = tokens;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add back the tokens that were refilled since the last time this was called.
+    pub fn refill(&mut self, now: Instant, burst: u32, interval: Duration) {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        let refills = (elapsed.as_nanos() / interval.as_nanos().max(1)).min(burst as u128) as u32;
+        self.tokens = self.tokens.saturating_add(refills).min(burst);
+        // A full bucket doesn't accumulate refills, so the next token is only due an interval after it's taken.
+        if self.tokens >= burst {
+            self.refilled_at = now;
+        } else {
+            self.refilled_at += interval * refills;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit() {
+        let limiter = RateLimiter::<String>::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(limiter.allow("1.1.1.1", now));
+        assert!(limiter.allow("1.1.1.1", now));
+        assert!(!limiter.allow("1.1.1.1", now));
+
+        // Other clients have their own limits.
+        assert!(limiter.allow("2.2.2.2", now));
+
+        // Limits are reset once the window elapses.
+        assert!(limiter.allow("1.1.1.1", now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn rate_limit_clients() {
+        let limiter = RateLimiter::<String>::new(1, Duration::from_secs(60)).with_max_clients(3);
+        let now = Instant::now();
+        for (index, client) in ["a", "b", "c"].into_iter().enumerate() {
+            assert!(limiter.allow(client, now + Duration::from_secs(index as u64)));
+        }
+        // New clients are still allowed once the limit is reached, at the expense of the least recently seen one.
+        let now = now + Duration::from_secs(30);
+        assert!(limiter.allow("new", now));
+        assert!(!limiter.allow("new", now));
+        assert_eq!(limiter.tracked_clients(), 3);
+        assert!(!limiter.allow("b", now));
+        assert!(!limiter.allow("c", now));
+        assert!(limiter.allow("a", now));
+    }
+
+    #[test]
+    fn rate_limit_expired_clients() {
+        let limiter = RateLimiter::<String>::new(1, Duration::from_secs(60)).with_max_clients(3);
+        let now = Instant::now();
+        for client in ["a", "b", "c"] {
+            assert!(limiter.allow(client, now));
+        }
+        // Every client's window elapsed, so they're all dropped to make room.
+        assert!(limiter.allow("new", now + Duration::from_secs(60)));
+        assert_eq!(limiter.tracked_clients(), 1);
+    }
+
+    #[test]
+    fn token_bucket() {
+        let interval = Duration::from_secs(10);
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(2, now);
+        assert!(bucket.take());
+        assert!(bucket.take());
+        assert!(!bucket.take());
+        assert_eq!(bucket.next_refill(interval), now + interval);
+
+        bucket.refill(now + Duration::from_secs(15), 2, interval);
+        assert_eq!(bucket.tokens(), 1);
+        assert_eq!(bucket.next_refill(interval), now + interval * 2);
+
+        // Tokens never go over the burst.
+        bucket.refill(now + Duration::from_secs(100), 2, interval);
+        assert_eq!(bucket.tokens(), 2);
+    }
+
+    #[test]
+    fn idle_token_bucket() {
+        let interval = Duration::from_secs(10);
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(2, now);
+        assert!(bucket.take());
+
+        // After being idle for a long time the bucket is full, and its refills start over from then.
+        let now = now + Duration::from_secs(1000);
+        bucket.refill(now, 2, interval);
+        assert_eq!(bucket.tokens(), 2);
+        assert_eq!(bucket.next_refill(interval), now + interval);
+
+        assert!(bucket.take());
+        assert!(bucket.take());
+        bucket.refill(now + Duration::from_secs(5), 2, interval);
+        assert_eq!(bucket.tokens(), 0);
+        bucket.refill(now + interval, 2, interval);
+        assert_eq!(bucket.tokens(), 1);
+    }
+}
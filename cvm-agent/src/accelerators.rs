@@ -0,0 +1,123 @@
+use std::{fs, process::Command};
+use tracing::{info, warn};
+
+static NVIDIA_COMPOSE_DEPLOY: &str = r"
+    deploy:
+      resources:
+        reservations:
+          devices:
+            - driver: nvidia
+              capabilities: [gpu]";
+
+/// All the accelerators that can be passed through to the CVM.
+const ACCELERATORS: &[&dyn Accelerator] = &[&NvidiaAccelerator, &AmdInstinctAccelerator];
+
+/// A kind of accelerator, like a GPU, passed through to the CVM.
+pub(crate) trait Accelerator: Send + Sync {
+    /// The accelerator's vendor name.
+    fn vendor(&self) -> &'static str;
+
+    /// Count the number of devices available.
+    fn count_devices(&self) -> usize;
+
+    /// Prepare the devices before any workload uses them.
+    fn setup(&self, count: usize);
+
+    /// The `deploy` section added to the attester's service in the system docker compose, if it needs the devices.
+    fn compose_deploy(&self) -> Option<&'static str>;
+
+    /// Whether stats can be collected via `nvidia-smi`.
+    fn nvidia_smi(&self) -> bool;
+}
+
+/// Find the accelerator whose devices were passed through to the CVM, if any.
+pub(crate) fn detect() -> Option<&'static dyn Accelerator> {
+    ACCELERATORS.iter().find(|accelerator| accelerator.count_devices() > 0).copied()
+}
+
+/// NVIDIA GPUs running in confidential computing mode.
+pub(crate) struct NvidiaAccelerator;
+
+impl Accelerator for NvidiaAccelerator {
+    fn vendor(&self) -> &'static str {
+        "nvidia"
+    }
+
+    fn count_devices(&self) -> usize {
+        let mut id = 0;
+        loop {
+            let path = format!("/dev/nvidia{id}");
+            if !fs::exists(&path).unwrap_or_default() {
+                break;
+            }
+            id += 1;
+        }
+        id
+    }
+
+    fn setup(&self, count: usize) {
+        match count {
+            0 => info!("No GPUs detected"),
+            1 => {
+                info!("Detected a single GPU, setting confidential compute ready state");
+                Command::new("nvidia-smi")
+                    .args(["conf-compute", "-srs", "1"])
+                    .status()
+                    .expect("failed to run nvidia-smi");
+            }
+            _ => {
+                info!("Detected {count} GPUs, setting multiple GPU mode");
+                Command::new("nvidia-smi").args(["conf-compute", "-mgm"]).status().expect("failed to run nvidia-smi");
+            }
+        }
+    }
+
+    fn compose_deploy(&self) -> Option<&'static str> {
+        Some(NVIDIA_COMPOSE_DEPLOY)
+    }
+
+    fn nvidia_smi(&self) -> bool {
+        true
+    }
+}
+
+/// AMD Instinct accelerators, which are passed through without confidential computing.
+///
+/// These are exposed via the ROCm kernel driver, so workloads use them by mounting `/dev/kfd` and `/dev/dri`.
+pub(crate) struct AmdInstinctAccelerator;
+
+impl Accelerator for AmdInstinctAccelerator {
+    fn vendor(&self) -> &'static str {
+        "amd"
+    }
+
+    fn count_devices(&self) -> usize {
+        if !fs::exists("/dev/kfd").unwrap_or_default() {
+            return 0;
+        }
+        // Each device gets a render node, e.g. `/dev/dri/renderD128`.
+        match fs::read_dir("/dev/dri") {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("renderD"))
+                .count(),
+            Err(e) => {
+                warn!("Failed to list render nodes: {e}");
+                0
+            }
+        }
+    }
+
+    fn setup(&self, count: usize) {
+        // There's no confidential computing mode to set up so devices are ready to use as is.
+        info!("Detected {count} AMD Instinct GPUs");
+    }
+
+    fn compose_deploy(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn nvidia_smi(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,78 @@
+use crate::routes::SharedState;
+use axum::{
+    extract::Request,
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+    middleware::Next,
+    response::Response,
+};
+use std::{fs, io, path::Path};
+use tracing::{info, warn};
+
+/// The name of the file in the ISO that contains the token `nilcc-agent` authenticates to the API with.
+pub(crate) const API_TOKEN_FILE: &str = "api-token";
+
+/// Load the API token from the ISO.
+///
+/// ISOs created by older `nilcc-agent` versions don't have one, in which case the API is left unauthenticated.
+///
+/// The host builds the ISO so it knows this token: it only keeps other processes on the host from using the API.
+pub(crate) fn load_api_token(iso_mount_path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(iso_mount_path.join(API_TOKEN_FILE)) {
+        Ok(token) => {
+            info!("Loaded API token, requests will need to be authenticated");
+            Ok(Some(token.trim().to_string()))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            warn!("No API token found in ISO, requests won't be authenticated");
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Reject any request that doesn't carry the API token as a bearer token.
+pub(crate) async fn require_api_token(
+    state: SharedState,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if !is_authorized(state.api_token.as_deref(), request.headers()) {
+        warn!("Rejected {} {}: invalid or missing bearer token", request.method(), request.uri().path());
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(request).await)
+}
+
+fn is_authorized(expected: Option<&str>, headers: &HeaderMap) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token == expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn make_headers(authorization: Option<&'static str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(authorization) = authorization {
+            headers.insert(AUTHORIZATION, HeaderValue::from_static(authorization));
+        }
+        headers
+    }
+
+    #[test]
+    fn authorization() {
+        assert!(is_authorized(Some("secret"), &make_headers(Some("Bearer secret"))));
+        assert!(!is_authorized(Some("secret"), &make_headers(Some("Bearer other"))));
+        assert!(!is_authorized(Some("secret"), &make_headers(Some("secret"))));
+        assert!(!is_authorized(Some("secret"), &make_headers(None)));
+        assert!(is_authorized(None, &make_headers(None)));
+    }
+}
@@ -0,0 +1,368 @@
+use crate::routes::BootstrapContext;
+use anyhow::{Context, bail};
+use cvm_agent_models::{
+    bootstrap::{AcmeCredentials, CADDY_ACME_EAB_KEY_ID, CADDY_ACME_EAB_MAC_KEY, DockerCredentials},
+    container::RUNNABLE_SERVICE_LABEL,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path, process::Stdio};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    process::{Child, Command},
+};
+use tracing::info;
+use uuid::Uuid;
+
+pub(crate) const COMPOSE_PROJECT_NAME: &str = "cvm";
+
+/// The identity of the workload, which the attester binds into attestation reports.
+#[derive(Clone)]
+pub(crate) struct WorkloadIdentity {
+    pub(crate) workload_id: Option<Uuid>,
+    pub(crate) agent_id: Option<Uuid>,
+}
+
+/// The fully resolved docker compose configuration, as output by `docker compose config`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ComposeConfig {
+    pub(crate) services: HashMap<String, ServiceConfig>,
+}
+
+/// The parts of a service's configuration that the containers running it are checked against.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct ServiceConfig {
+    #[serde(default)]
+    pub(crate) image: Option<String>,
+
+    #[serde(default)]
+    pub(crate) command: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub(crate) entrypoint: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub(crate) volumes: Vec<VolumeConfig>,
+
+    #[serde(default)]
+    pub(crate) labels: HashMap<String, String>,
+}
+
+impl ServiceConfig {
+    /// Whether commands can be run on demand in new containers for this service.
+    pub(crate) fn runnable(&self) -> bool {
+        self.labels.get(RUNNABLE_SERVICE_LABEL).is_some_and(|value| value == "true")
+    }
+}
+
+/// A volume mounted into a service's containers.
+#[derive(Debug, Deserialize)]
+pub(crate) struct VolumeConfig {
+    #[serde(rename = "type")]
+    pub(crate) kind: String,
+
+    #[serde(default)]
+    pub(crate) source: Option<String>,
+
+    pub(crate) target: String,
+}
+
+/// Runs the docker compose related bootstrap steps.
+#[derive(Clone)]
+pub(crate) struct DockerCompose {
+    ctx: BootstrapContext,
+    acme: AcmeCredentials,
+    docker: Vec<DockerCredentials>,
+    domain: String,
+    identity: WorkloadIdentity,
+    platform_claims: Option<String>,
+}
+
+impl DockerCompose {
+    pub(crate) fn new(
+        ctx: BootstrapContext,
+        acme: AcmeCredentials,
+        docker: Vec<DockerCredentials>,
+        domain: String,
+        identity: WorkloadIdentity,
+        platform_claims: Option<String>,
+    ) -> Self {
+        Self { ctx, acme, docker, domain, identity, platform_claims }
+    }
+
+    /// Log in to every docker registry we have credentials for.
+    pub(crate) async fn login(&self) -> anyhow::Result<()> {
+        for credential in &self.docker {
+            docker_login(&self.ctx.docker_config, credential).await.context("Failed to docker login")?;
+        }
+        Ok(())
+    }
+
+    /// Start the docker compose containers.
+    ///
+    /// One-shot jobs are not started since they already ran to completion.
+    pub(crate) async fn start(&self) -> anyhow::Result<()> {
+        info!("Launching docker compose");
+        let mut command = self.base_docker_command();
+        command.arg("up").arg("-d").arg("--no-build");
+        if !self.ctx.jobs.is_empty() {
+            let services = Self::services(&self.ctx).await?;
+            // Every other service is listed explicitly so dependencies don't need to be pulled in, which would
+            // otherwise start any job a service depends on again.
+            command.arg("--no-deps").args(services.iter().filter(|service| !self.ctx.jobs.contains(*service)));
+        }
+        let output = command.output().await.context("Failed to run docker compose up")?;
+        if output.status.success() {
+            info!("docker compose is running");
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = Self::extract_stderr_message(&stderr);
+            bail!("docker compose execution failed: {message}")
+        }
+    }
+
+    /// Run a one-shot job, along with the services it depends on, and wait for it to exit.
+    pub(crate) async fn run_job(&self, job: &str) -> anyhow::Result<()> {
+        info!("Running job {job}");
+        let output = self
+            .base_docker_command()
+            .arg("up")
+            .arg("--no-build")
+            // this waits for the job to exit, stops the rest of the containers, and exits with the job's exit code
+            .arg("--exit-code-from")
+            .arg(job)
+            .arg(job)
+            .output()
+            .await
+            .context("Failed to run docker compose up")?;
+        if output.status.success() {
+            info!("Job {job} completed");
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match Self::extract_daemon_error(&stderr) {
+                Some(message) => bail!("job '{job}' failed: {message}"),
+                None => bail!("job '{job}' failed with {}", output.status),
+            }
+        }
+    }
+
+    /// Run a command in a new container for a service, which is removed once the command exits.
+    ///
+    /// The services it depends on aren't started since they're already running once the CVM is bootstrapped.
+    pub(crate) fn run(&self, service: &str, command: &[String]) -> anyhow::Result<Child> {
+        self.run_command(service, command).spawn().context("Failed to run docker compose run")
+    }
+
+    /// Build the command `run` spawns, with both stdout and stderr piped so they can be streamed.
+    pub(crate) fn run_command(&self, service: &str, command: &[String]) -> Command {
+        let mut docker = self.base_docker_command();
+        docker
+            .args(["run", "--rm", "--no-deps", "-T", service])
+            .args(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        docker
+    }
+
+    /// Pull the images for every service in the docker compose files.
+    pub(crate) async fn pull_images(&self) -> anyhow::Result<()> {
+        info!("Running docker compose pull");
+        let output = self
+            .base_docker_command()
+            .arg("pull")
+            .arg("-q")
+            .output()
+            .await
+            .context("Failed to run docker compose pull")?;
+        if output.status.success() {
+            if !self.docker.is_empty() {
+                info!("Removing docker config.json file");
+                // this isn't needed anymore because we already pulled images
+                fs::remove_file(&self.ctx.docker_config.join("config.json"))
+                    .await
+                    .context("Failed to remove docker config")?;
+            }
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = Self::extract_stderr_message(&stderr);
+            bail!("docker compose pull failed: {message}")
+        }
+    }
+
+    /// Get the resolved configuration of the docker compose files, with every variable interpolated.
+    pub(crate) async fn config(&self) -> anyhow::Result<ComposeConfig> {
+        let output = self
+            .base_docker_command()
+            .args(["config", "--format", "json"])
+            .output()
+            .await
+            .context("Failed to run docker compose config")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("docker compose config failed: {}", Self::extract_stderr_message(&stderr));
+        }
+        serde_json::from_slice(&output.stdout).context("Invalid docker compose config")
+    }
+
+    /// List the services defined across the docker compose files.
+    pub(crate) async fn services(ctx: &BootstrapContext) -> anyhow::Result<Vec<String>> {
+        // Variables aren't interpolated so this doesn't need any of the ones the compose files reference.
+        let output = Command::new("docker")
+            .env_clear()
+            .current_dir(&ctx.iso_mount)
+            .stderr(Stdio::piped())
+            .args(["compose", "-p", COMPOSE_PROJECT_NAME, "-f"])
+            .arg(&ctx.user_docker_compose)
+            .arg("-f")
+            .arg(&ctx.system_docker_compose)
+            .args(["config", "--services", "--no-interpolate"])
+            .output()
+            .await
+            .context("Failed to run docker compose config")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("docker compose config failed: {}", Self::extract_stderr_message(&stderr));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout.lines().map(str::trim).filter(|line| !line.is_empty()).map(ToString::to_string).collect())
+    }
+
+    fn extract_stderr_message(stderr: &str) -> &str {
+        Self::extract_daemon_error(stderr).unwrap_or(stderr)
+    }
+
+    fn extract_daemon_error(stderr: &str) -> Option<&str> {
+        // Try to grab the nicer error if we can
+        stderr.lines().find_map(|line| line.strip_prefix("Error response from daemon: "))
+    }
+
+    fn base_docker_command(&self) -> Command {
+        let mut command = Command::new("docker");
+        command
+            .env_clear()
+            .current_dir(&self.ctx.iso_mount)
+            // pass in `FILES` which points to `<iso>/files`
+            .env("FILES", self.ctx.external_files.as_os_str())
+            // pass in other env vars that are needed by our compose file
+            .env("CADDY_INPUT_FILE", self.ctx.caddy_config.as_os_str())
+            .env("CADDY_LOGS_DIR", self.ctx.proxy_logs.as_os_str())
+            .env("CADDY_TLS_DIR", self.ctx.proxy_tls.as_os_str())
+            .env("NILCC_VERSION", &self.ctx.version)
+            .env("NILCC_VM_TYPE", self.ctx.vm_type.to_string())
+            .env("NILCC_DOMAIN", &self.domain)
+            // these are left empty if the agent didn't provide them, which makes reports not be bound to the workload
+            .env("NILCC_WORKLOAD_ID", self.identity.workload_id.map(|id| id.to_string()).unwrap_or_default())
+            .env("NILCC_AGENT_ID", self.identity.agent_id.map(|id| id.to_string()).unwrap_or_default())
+            .env("NILCC_TOKEN_PUBLIC_KEY", &self.ctx.token_public_key)
+            .env("NILCC_PLATFORM_CLAIMS", self.platform_claims.as_deref().unwrap_or_default())
+            .env(CADDY_ACME_EAB_KEY_ID, &self.acme.eab_key_id)
+            .env(CADDY_ACME_EAB_MAC_KEY, &self.acme.eab_mac_key)
+            .stderr(Stdio::piped())
+            .arg("--config")
+            .arg(&self.ctx.docker_config)
+            .arg("compose")
+            // set a well defined project name, this is used as a prefix for container names
+            .arg("-p")
+            .arg(COMPOSE_PROJECT_NAME)
+            // point to the user provided compose file first
+            .arg("-f")
+            .arg(&self.ctx.user_docker_compose)
+            // then ours
+            .arg("-f")
+            .arg(&self.ctx.system_docker_compose);
+        command
+    }
+}
+
+/// Log in to a docker registry, storing the credentials in the given docker config directory.
+pub(crate) async fn docker_login(docker_config: &Path, credentials: &DockerCredentials) -> anyhow::Result<()> {
+    info!("Logging in to {}", registry_name(credentials));
+
+    let mut command = Command::new("docker");
+    let mut command = command
+        .arg("--config")
+        .arg(docker_config)
+        .arg("login")
+        .arg("-u")
+        .arg(&credentials.username)
+        .arg("--password-stdin")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(server) = &credentials.server {
+        command = command.arg(server);
+    }
+    let mut child = command.spawn().context("Failed to invoke docker login")?;
+    {
+        let mut stdin = child.stdin.take().expect("no stdin");
+        stdin.write_all(credentials.password.as_bytes()).await.context("Failed to write docker login password")?;
+    }
+    let output = child.wait_with_output().await.context("Failed to wait for docker login")?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = DockerCompose::extract_stderr_message(&stderr);
+        bail!("docker login failed: {message}")
+    }
+}
+
+/// The name of the registry a set of credentials is for.
+pub(crate) fn registry_name(credentials: &DockerCredentials) -> &str {
+    credentials.server.as_deref().unwrap_or("docker hub")
+}
+
+#[cfg(test)]
+pub(crate) fn make_docker_compose(iso_mount: &Path) -> DockerCompose {
+    use crate::routes::VmType;
+
+    let ctx = BootstrapContext {
+        system_docker_compose: iso_mount.join("docker-compose.yaml"),
+        user_docker_compose: iso_mount.join("user-docker-compose.yaml"),
+        user_docker_compose_sha256: Default::default(),
+        external_files: iso_mount.join("files"),
+        caddy_config: iso_mount.join("Caddyfile"),
+        proxy_logs: iso_mount.join("logs"),
+        proxy_tls: iso_mount.join("tls"),
+        docker_config: iso_mount.join("docker"),
+        version: "v1".into(),
+        vm_type: VmType::Cpu,
+        iso_mount: iso_mount.into(),
+        event_holder: Default::default(),
+        cpus: 1,
+        gpus: 0,
+        accelerator: None,
+        log_encryption_key: None,
+        jobs: Vec::new(),
+        token_public_key: Default::default(),
+    };
+    let acme = AcmeCredentials { eab_key_id: "key".into(), eab_mac_key: "mac".into() };
+    let identity = WorkloadIdentity { workload_id: None, agent_id: None };
+    DockerCompose::new(ctx, acme, Vec::new(), "example.com".into(), identity, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daemon_error_response() {
+        let stderr = r"[+] Pulling 2/2
+ ✘ other Error context canceled                                                                  1.2s
+ ✘ web Error   manifest for foo:bar not found: manifest unknown: manifest unknown                1.2s
+Error response from daemon: manifest for foo:bar not found: manifest unknown: manifest unknown";
+        let error = DockerCompose::extract_stderr_message(&stderr);
+        assert_eq!(error, "manifest for foo:bar not found: manifest unknown: manifest unknown");
+    }
+
+    #[test]
+    fn default_error_message() {
+        let stderr = r"foo
+bar";
+        let error = DockerCompose::extract_stderr_message(&stderr);
+        assert_eq!(error, stderr);
+    }
+}
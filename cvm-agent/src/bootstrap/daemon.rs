@@ -0,0 +1,36 @@
+use anyhow::{Context, bail};
+use std::{io, path::Path};
+use tokio::{fs, process::Command};
+
+/// The docker daemon configuration file.
+pub(crate) const DOCKER_DAEMON_CONFIG_PATH: &str = "/etc/docker/daemon.json";
+
+/// Read the docker daemon config, if it exists.
+///
+/// The daemon config may already exist, e.g. GPU images configure the nvidia runtime in it.
+pub(crate) async fn read_daemon_config(path: &Path) -> anyhow::Result<Option<String>> {
+    match fs::read_to_string(path).await {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context("Failed to read docker daemon config"),
+    }
+}
+
+/// Write the docker daemon config.
+pub(crate) async fn write_daemon_config(path: &Path, contents: String) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await.context("Failed to create docker config directory")?;
+    }
+    fs::write(path, contents).await.context("Failed to write docker daemon config")
+}
+
+/// Restart the docker daemon so changes to its config take effect.
+pub(crate) async fn restart_docker() -> anyhow::Result<()> {
+    let output =
+        Command::new("systemctl").arg("restart").arg("docker").output().await.context("Failed to run systemctl")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("failed to restart docker daemon: {}", stderr.trim());
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use crate::{
+    bootstrap::compose::COMPOSE_PROJECT_NAME,
+    routes::containers::{compose_state::COMPOSE_PROJECT_LABEL, restart::COMPOSE_SERVICE_LABEL},
+};
+use anyhow::Context;
+use bollard::{
+    Docker,
+    query_parameters::{InspectContainerOptions, ListContainersOptionsBuilder},
+    secret::ContainerStateStatusEnum,
+};
+use cvm_agent_models::{
+    container::JOB_SERVICE_LABEL,
+    jobs::{JobState, JobStatus},
+};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct ComposeServices {
+    services: Mapping,
+}
+
+/// Find the services in a docker compose file that are one-shot jobs, in the order they're declared in.
+///
+/// Jobs are taken from the compose file's labels since it's part of the CVM's measurement, unlike the application
+/// metadata.
+pub(crate) fn compose_jobs(docker_compose: &[u8]) -> anyhow::Result<Vec<String>> {
+    let compose: ComposeServices = serde_yaml::from_slice(docker_compose).context("malformed docker compose")?;
+    let mut jobs = Vec::new();
+    for (name, service) in compose.services {
+        let name = name.as_str().context("service name is not a string")?.to_string();
+        let is_job = match service.get("labels") {
+            Some(Value::Sequence(labels)) => labels
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|label| label.split_once('=') == Some((JOB_SERVICE_LABEL, "true"))),
+            Some(Value::Mapping(labels)) => labels.get(JOB_SERVICE_LABEL).and_then(Value::as_str) == Some("true"),
+            _ => false,
+        };
+        if is_job {
+            jobs.push(name);
+        }
+    }
+    Ok(jobs)
+}
+
+/// Find the id of the container a job ran in, if it was started at all.
+pub(crate) async fn job_container(docker: &Docker, job: &str) -> Result<Option<String>, bollard::errors::Error> {
+    let filters = HashMap::from([(
+        "label",
+        vec![format!("{COMPOSE_PROJECT_LABEL}={COMPOSE_PROJECT_NAME}"), format!("{COMPOSE_SERVICE_LABEL}={job}")],
+    )]);
+    let options = ListContainersOptionsBuilder::new().all(true).filters(&filters).build();
+    let containers = docker.list_containers(Some(options)).await?;
+    Ok(containers.into_iter().find_map(|c| c.id))
+}
+
+/// Get the status of a job by inspecting its container.
+pub(crate) async fn job_status(docker: &Docker, job: &str) -> Result<JobStatus, bollard::errors::Error> {
+    let Some(id) = job_container(docker, job).await? else {
+        return Ok(JobStatus { name: job.into(), state: JobState::Pending, exit_code: None, error: None });
+    };
+    let details = docker.inspect_container(&id, None::<InspectContainerOptions>).await?;
+    let container_state = details.state.unwrap_or_default();
+    let state = job_state(container_state.status, container_state.exit_code);
+    let exit_code = match state {
+        JobState::Succeeded | JobState::Failed => container_state.exit_code,
+        JobState::Pending | JobState::Running => None,
+    };
+    Ok(JobStatus { name: job.into(), state, exit_code, error: container_state.error.filter(|e| !e.is_empty()) })
+}
+
+fn job_state(status: Option<ContainerStateStatusEnum>, exit_code: Option<i64>) -> JobState {
+    use ContainerStateStatusEnum::*;
+    match status {
+        Some(RUNNING | RESTARTING | PAUSED | REMOVING) => JobState::Running,
+        Some(EXITED | DEAD) if exit_code == Some(0) => JobState::Succeeded,
+        Some(EXITED | DEAD) => JobState::Failed,
+        Some(CREATED | EMPTY) | None => JobState::Pending,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states() {
+        use ContainerStateStatusEnum::*;
+        let cases = [
+            (Some(CREATED), None, JobState::Pending),
+            (Some(RUNNING), None, JobState::Running),
+            (Some(EXITED), Some(0), JobState::Succeeded),
+            (Some(EXITED), Some(1), JobState::Failed),
+            (Some(DEAD), None, JobState::Failed),
+            (None, None, JobState::Pending),
+        ];
+        for (status, exit_code, expected) in cases {
+            assert_eq!(job_state(status, exit_code), expected, "unexpected state for {status:?}/{exit_code:?}");
+        }
+    }
+
+    #[test]
+    fn jobs_from_compose() {
+        let compose = r#"
+services:
+  api:
+    image: caddy:2
+  seed:
+    image: seed:1
+    labels:
+      - nilcc.job=true
+  migrate:
+    image: migrate:1
+    labels:
+      nilcc.job: "true"
+  worker:
+    image: worker:1
+    labels:
+      nilcc.job: "false"
+"#;
+        let jobs = compose_jobs(compose.as_bytes()).expect("failed to find jobs");
+        assert_eq!(jobs, &["seed", "migrate"]);
+    }
+
+    #[test]
+    fn malformed_compose() {
+        compose_jobs(b"services: 42").expect_err("jobs found");
+    }
+}
@@ -0,0 +1,116 @@
+use crate::bootstrap::daemon::{DOCKER_DAEMON_CONFIG_PATH, read_daemon_config, restart_docker, write_daemon_config};
+use anyhow::Context;
+use cvm_agent_models::bootstrap::LogRotationConfig;
+use serde_json::{Map, Value, json};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tracing::info;
+
+/// The directory where docker keeps per container state, including json-file logs.
+const DOCKER_CONTAINERS_PATH: &str = "/var/lib/docker/containers";
+
+/// Configures the docker json-file log driver so container logs don't grow unbounded.
+pub(crate) struct LogRotation {
+    daemon_config_path: PathBuf,
+    config: Option<LogRotationConfig>,
+}
+
+impl LogRotation {
+    pub(crate) fn new(config: Option<LogRotationConfig>) -> Self {
+        Self { daemon_config_path: DOCKER_DAEMON_CONFIG_PATH.into(), config }
+    }
+
+    /// Apply the log rotation config to the docker daemon.
+    ///
+    /// This needs to happen before containers are created since the daemon defaults only apply to new containers.
+    pub(crate) async fn apply(&self) -> anyhow::Result<()> {
+        let Some(config) = &self.config else {
+            info!("No log rotation configured, leaving docker daemon config untouched");
+            return Ok(());
+        };
+        let existing = read_daemon_config(&self.daemon_config_path).await?;
+        let contents = Self::merge_daemon_config(existing.as_deref(), config)?;
+        write_daemon_config(&self.daemon_config_path, contents).await?;
+
+        let LogRotationConfig { max_size_mb, max_files } = config;
+        info!("Restarting docker daemon to rotate logs every {max_size_mb}MB keeping {max_files} files");
+        restart_docker().await
+    }
+
+    fn merge_daemon_config(existing: Option<&str>, config: &LogRotationConfig) -> anyhow::Result<String> {
+        let mut daemon_config: Map<String, Value> = match existing {
+            Some(contents) => serde_json::from_str(contents).context("Invalid docker daemon config")?,
+            None => Map::new(),
+        };
+        daemon_config.insert("log-driver".into(), "json-file".into());
+        daemon_config.insert(
+            "log-opts".into(),
+            json!({
+                "max-size": format!("{}m", config.max_size_mb),
+                "max-file": config.max_files.to_string(),
+            }),
+        );
+        Ok(serde_json::to_string_pretty(&daemon_config)?)
+    }
+}
+
+/// Get the disk space used by container logs, in bytes.
+pub(crate) async fn container_logs_usage() -> u64 {
+    logs_usage(Path::new(DOCKER_CONTAINERS_PATH)).await
+}
+
+async fn logs_usage(containers_path: &Path) -> u64 {
+    let mut total = 0;
+    let Ok(mut containers) = fs::read_dir(containers_path).await else {
+        return 0;
+    };
+    while let Ok(Some(container)) = containers.next_entry().await {
+        let Ok(mut files) = fs::read_dir(container.path()).await else {
+            continue;
+        };
+        while let Ok(Some(file)) = files.next_entry().await {
+            // Rotated files are named `<id>-json.log.<n>`.
+            if !file.file_name().to_string_lossy().contains("-json.log") {
+                continue;
+            }
+            if let Ok(metadata) = file.metadata().await {
+                total += metadata.len();
+            }
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn merge_daemon_config() {
+        let config = LogRotationConfig { max_size_mb: 10, max_files: 3 };
+        let existing = r#"{"runtimes": {"nvidia": {"path": "nvidia-container-runtime"}}, "log-driver": "local"}"#;
+        let merged = LogRotation::merge_daemon_config(Some(existing), &config).expect("failed to merge");
+        let merged: Value = serde_json::from_str(&merged).expect("invalid json");
+        let expected = json!({
+            "runtimes": {"nvidia": {"path": "nvidia-container-runtime"}},
+            "log-driver": "json-file",
+            "log-opts": {"max-size": "10m", "max-file": "3"},
+        });
+        assert_eq!(merged, expected);
+    }
+
+    #[tokio::test]
+    async fn logs_disk_usage() {
+        let dir = tempdir().expect("failed to create tempdir");
+        for (container, file, size) in
+            [("a", "a-json.log", 10), ("a", "a-json.log.1", 20), ("a", "config.v2.json", 100), ("b", "b-json.log", 5)]
+        {
+            let path = dir.path().join(container);
+            std::fs::create_dir_all(&path).expect("failed to create dir");
+            std::fs::write(path.join(file), vec![0; size]).expect("failed to write");
+        }
+        assert_eq!(logs_usage(dir.path()).await, 35);
+        assert_eq!(logs_usage(&dir.path().join("missing")).await, 0);
+    }
+}
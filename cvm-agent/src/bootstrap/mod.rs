@@ -0,0 +1,331 @@
+use crate::{
+    bootstrap::{
+        compose::{DockerCompose, WorkloadIdentity},
+        logging::LogRotation,
+        registry::RegistryMirrors,
+        tls::ProxyTlsSetup,
+    },
+    heartbeat::{HeartbeatEmitter, HeartbeatEmitterArgs},
+    monitors::{caddy::CaddyStatus, drift::DriftMonitor},
+    resources::Resources,
+    routes::AppState,
+};
+use anyhow::Context;
+use cvm_agent_models::{
+    bootstrap::{BootstrapRequest, BootstrapStatus, BootstrapStep, HeartbeatConfig},
+    health::EventKind,
+    jobs::JobState,
+};
+use std::{io, path::PathBuf, sync::Arc};
+use tokio::fs;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub(crate) mod compose;
+pub(crate) mod daemon;
+pub(crate) mod jobs;
+pub(crate) mod logging;
+pub(crate) mod registry;
+pub(crate) mod tls;
+
+/// The bootstrap state machine.
+///
+/// The state is persisted on every transition so that a restarted cvm-agent can resume bootstrapping from the step
+/// it was at rather than starting over.
+pub(crate) struct BootstrapState {
+    path: PathBuf,
+    status: BootstrapStatus,
+}
+
+impl BootstrapState {
+    /// Load the state from the given path, starting from scratch if there's no persisted state.
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let status = match std::fs::read(&path) {
+            Ok(contents) => match serde_json::from_slice::<BootstrapStatus>(&contents) {
+                Ok(status) => {
+                    info!("Found persisted bootstrap state at step {:?}", status.step);
+                    BootstrapStatus { step: Self::resume_step(status.step), running: false, error: status.error }
+                }
+                Err(e) => {
+                    warn!("Ignoring invalid bootstrap state file: {e}");
+                    Self::initial_status()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::initial_status(),
+            Err(e) => {
+                warn!("Failed to read bootstrap state file: {e}");
+                Self::initial_status()
+            }
+        };
+        Self { path, status }
+    }
+
+    fn initial_status() -> BootstrapStatus {
+        BootstrapStatus { step: BootstrapStep::Pending, running: false, error: None }
+    }
+
+    // Docker credentials and heartbeats only live as long as the process that created them, so the steps that set
+    // them up need to run again after a restart.
+    fn resume_step(step: BootstrapStep) -> BootstrapStep {
+        match step {
+            BootstrapStep::PullImages => BootstrapStep::DockerLogin,
+            BootstrapStep::Completed => BootstrapStep::Heartbeats,
+            step => step,
+        }
+    }
+
+    /// The current bootstrap status.
+    pub(crate) fn status(&self) -> &BootstrapStatus {
+        &self.status
+    }
+
+    /// Whether the bootstrap process is either running or completed.
+    pub(crate) fn is_bootstrapped(&self) -> bool {
+        self.status.running || self.status.step == BootstrapStep::Completed
+    }
+
+    /// Whether a bootstrap request should start the bootstrap process.
+    pub(crate) fn can_start(&self) -> bool {
+        !self.is_bootstrapped()
+    }
+
+    /// Start, or resume, the bootstrap process.
+    pub(crate) async fn start(&mut self) {
+        if self.status.step == BootstrapStep::Pending {
+            self.status.step = self.status.step.next();
+        }
+        self.status.running = true;
+        self.status.error = None;
+        self.persist().await;
+    }
+
+    async fn advance(&mut self) {
+        self.status.step = self.status.step.next();
+        self.status.running = self.status.step != BootstrapStep::Completed;
+        self.persist().await;
+    }
+
+    async fn fail(&mut self, error: String) {
+        self.status.running = false;
+        self.status.error = Some(error);
+        self.persist().await;
+    }
+
+    async fn persist(&self) {
+        let contents = serde_json::to_vec(&self.status).expect("failed to serialize bootstrap status");
+        // Write to a temporary file and rename it so the state file is never left half written.
+        let tmp_path = self.path.with_extension("tmp");
+        let result = match fs::write(&tmp_path, contents).await {
+            Ok(()) => fs::rename(&tmp_path, &self.path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Failed to persist bootstrap state: {e}");
+        }
+    }
+}
+
+/// Runs the bootstrap steps in the background, starting from the step the bootstrap state is at.
+pub(crate) struct Bootstrapper {
+    state: Arc<AppState>,
+    compose: DockerCompose,
+    log_rotation: LogRotation,
+    registry_mirrors: RegistryMirrors,
+    proxy_tls: ProxyTlsSetup,
+    domain: String,
+    heartbeat: Option<(Uuid, HeartbeatConfig)>,
+    caddy_status: CaddyStatus,
+}
+
+impl Bootstrapper {
+    pub(crate) fn spawn(state: Arc<AppState>, request: BootstrapRequest, caddy_status: CaddyStatus) {
+        let BootstrapRequest {
+            acme,
+            docker,
+            domain,
+            heartbeat,
+            workload_id,
+            agent_id,
+            log_rotation,
+            time_sync: _,
+            private_pki,
+            registry_mirrors,
+            platform_claims,
+        } = request;
+        let identity = WorkloadIdentity { workload_id, agent_id };
+        let compose =
+            DockerCompose::new(state.context.clone(), acme, docker, domain.clone(), identity, platform_claims);
+        let log_rotation = LogRotation::new(log_rotation);
+        let registry_mirrors = RegistryMirrors::new(registry_mirrors, state.context.user_docker_compose.clone());
+        let proxy_tls = ProxyTlsSetup::new(state.context.proxy_tls.clone(), private_pki);
+        let heartbeat = workload_id.zip(heartbeat);
+        let bootstrapper =
+            Self { state, compose, log_rotation, registry_mirrors, proxy_tls, domain, heartbeat, caddy_status };
+        info!("Spawning bootstrapper");
+        tokio::spawn(async move {
+            bootstrapper.run().await;
+        });
+    }
+
+    async fn run(self) {
+        // The TLS files live in the state directory, which doesn't survive restarts, so this is done every time
+        // rather than as a resumable step.
+        if let Err(e) = self.configure_proxy_tls().await {
+            error!("Failed to configure proxy TLS: {e:#}");
+            self.state.context.event_holder.set(e.to_string(), EventKind::Error);
+            self.state.bootstrap.lock().await.fail(e.to_string()).await;
+            return;
+        }
+        loop {
+            let step = self.state.bootstrap.lock().await.status().step;
+            info!("Running bootstrap step {step:?}");
+            let result = match step {
+                BootstrapStep::Pending => Ok(()),
+                BootstrapStep::ConfigureRegistryMirrors => self.registry_mirrors.apply().await,
+                BootstrapStep::DockerLogin => self.compose.login().await,
+                BootstrapStep::PullImages => self.compose.pull_images().await,
+                BootstrapStep::ConfigureLogging => self.log_rotation.apply().await,
+                BootstrapStep::RunJobs => self.run_jobs().await,
+                BootstrapStep::StartContainers => self.compose.start().await,
+                BootstrapStep::Heartbeats => self.setup_heartbeats().await,
+                BootstrapStep::Completed => break,
+            };
+            let mut bootstrap = self.state.bootstrap.lock().await;
+            match result {
+                Ok(()) => bootstrap.advance().await,
+                Err(e) => {
+                    error!("Bootstrap step {step:?} failed: {e:#}");
+                    self.state.context.event_holder.set(e.to_string(), EventKind::Error);
+                    bootstrap.fail(e.to_string()).await;
+                    return;
+                }
+            }
+        }
+        info!("Bootstrap completed");
+        self.state.compose.lock().await.get_or_insert_with(|| self.compose.clone());
+        self.state
+            .drift_status
+            .lock()
+            .await
+            .get_or_insert_with(|| DriftMonitor::spawn(self.state.clone(), self.compose));
+    }
+
+    async fn configure_proxy_tls(&self) -> anyhow::Result<()> {
+        let tls = self.proxy_tls.apply().await?;
+        let mut proxy = self.state.proxy.lock().await;
+        proxy.tls = tls;
+        let caddyfile = Resources::render_caddyfile(&proxy);
+        fs::write(&self.state.context.caddy_config, caddyfile).await.context("Failed to write Caddyfile")?;
+        Ok(())
+    }
+
+    /// Run every job that hasn't succeeded yet, in the order they're defined in.
+    async fn run_jobs(&self) -> anyhow::Result<()> {
+        for job in &self.state.context.jobs {
+            let status = jobs::job_status(&self.state.docker, job).await.context("Failed to get job status")?;
+            if status.state == JobState::Succeeded {
+                info!("Job {job} already succeeded, not running it again");
+                continue;
+            }
+            self.compose.run_job(job).await?;
+        }
+        Ok(())
+    }
+
+    async fn setup_heartbeats(&self) -> anyhow::Result<()> {
+        let Some((workload_id, heartbeat)) = &self.heartbeat else {
+            info!("Not emitting heartbeats since the necessary config wasn't provided");
+            return Ok(());
+        };
+        let mut handle = self.state.heartbeat_handle.lock().await;
+        if handle.is_some() {
+            info!("Heartbeat emitter is already running");
+            return Ok(());
+        }
+        let ctx = &self.state.context;
+        let heartbeat = heartbeat.clone();
+        let args = HeartbeatEmitterArgs {
+            workload_id: *workload_id,
+            workload_domain: self.domain.clone(),
+            rpc_endpoint: heartbeat.rpc_endpoint,
+            heartbeat_contract_address: heartbeat.heartbeat_contract_address,
+            token_contract_address: heartbeat.token_contract_address,
+            wallet_private_key: heartbeat.wallet_private_key,
+            nilcc_version: ctx.version.clone(),
+            docker_compose_hash: ctx.user_docker_compose_sha256,
+            tick_interval: heartbeat.interval,
+            measurement_hash_url: heartbeat.measurement_hash_url,
+            cpu_count: ctx.cpus,
+            gpu_count: ctx.gpus,
+            caddy_status: self.caddy_status.clone(),
+        };
+        let emitter = HeartbeatEmitter::spawn(args).await.context("Failed setting up heartbeat emitter")?;
+        *handle = Some(emitter);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn state_transitions() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let mut state = BootstrapState::load(dir.path().join("bootstrap.json"));
+        assert_eq!(state.status().step, BootstrapStep::Pending);
+        assert!(state.can_start());
+
+        state.start().await;
+        assert_eq!(state.status().step, BootstrapStep::ConfigureRegistryMirrors);
+        assert!(!state.can_start());
+
+        state.advance().await;
+        assert_eq!(state.status().step, BootstrapStep::DockerLogin);
+        state.advance().await;
+        state.fail("pull failed".into()).await;
+        assert_eq!(
+            state.status(),
+            &BootstrapStatus { step: BootstrapStep::PullImages, running: false, error: Some("pull failed".into()) }
+        );
+        assert!(state.can_start());
+
+        // Resuming continues from the failed step.
+        state.start().await;
+        assert_eq!(state.status(), &BootstrapStatus { step: BootstrapStep::PullImages, running: true, error: None });
+
+        for _ in 0..5 {
+            state.advance().await;
+        }
+        assert_eq!(state.status(), &BootstrapStatus { step: BootstrapStep::Completed, running: false, error: None });
+        assert!(state.is_bootstrapped());
+        assert!(!state.can_start());
+    }
+
+    #[tokio::test]
+    async fn load_persisted() {
+        let cases = [
+            (BootstrapStep::Pending, BootstrapStep::Pending),
+            (BootstrapStep::ConfigureRegistryMirrors, BootstrapStep::ConfigureRegistryMirrors),
+            (BootstrapStep::DockerLogin, BootstrapStep::DockerLogin),
+            (BootstrapStep::PullImages, BootstrapStep::DockerLogin),
+            (BootstrapStep::ConfigureLogging, BootstrapStep::ConfigureLogging),
+            (BootstrapStep::RunJobs, BootstrapStep::RunJobs),
+            (BootstrapStep::StartContainers, BootstrapStep::StartContainers),
+            (BootstrapStep::Heartbeats, BootstrapStep::Heartbeats),
+            (BootstrapStep::Completed, BootstrapStep::Heartbeats),
+        ];
+        let dir = tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("bootstrap.json");
+        for (persisted, expected) in cases {
+            let mut state = BootstrapState::load(path.clone());
+            state.status = BootstrapStatus { step: persisted, running: true, error: None };
+            state.persist().await;
+
+            let state = BootstrapState::load(path.clone());
+            let expected = BootstrapStatus { step: expected, running: false, error: None };
+            assert_eq!(state.status(), &expected, "unexpected state after persisting {persisted:?}");
+        }
+    }
+}
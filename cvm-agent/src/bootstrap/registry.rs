@@ -0,0 +1,124 @@
+use crate::bootstrap::daemon::{DOCKER_DAEMON_CONFIG_PATH, read_daemon_config, restart_docker, write_daemon_config};
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use serde_yaml::Mapping;
+use std::path::PathBuf;
+use tokio::fs;
+use tracing::info;
+
+#[derive(Deserialize)]
+struct ComposeServices {
+    services: Mapping,
+}
+
+/// Configures docker registry mirrors so image pulls go through a pull-through cache.
+pub(crate) struct RegistryMirrors {
+    daemon_config_path: PathBuf,
+    user_docker_compose: PathBuf,
+    mirrors: Vec<String>,
+}
+
+impl RegistryMirrors {
+    pub(crate) fn new(mirrors: Vec<String>, user_docker_compose: PathBuf) -> Self {
+        Self { daemon_config_path: DOCKER_DAEMON_CONFIG_PATH.into(), user_docker_compose, mirrors }
+    }
+
+    /// Apply the registry mirrors to the docker daemon.
+    ///
+    /// This needs to happen before images are pulled, otherwise they'd be pulled from the upstream registry.
+    ///
+    /// Mirrors are picked by the host so they're only used over https and when every image in the user's docker
+    /// compose file is pinned by digest, which docker verifies no matter where the image is pulled from.
+    pub(crate) async fn apply(&self) -> anyhow::Result<()> {
+        if self.mirrors.is_empty() {
+            info!("No registry mirrors configured, leaving docker daemon config untouched");
+            return Ok(());
+        }
+        if let Some(mirror) = self.mirrors.iter().find(|mirror| !mirror.starts_with("https://")) {
+            bail!("registry mirror {mirror} is not an https URL");
+        }
+        let docker_compose = fs::read(&self.user_docker_compose).await.context("Failed to read docker compose")?;
+        let unpinned = unpinned_images(&docker_compose)?;
+        if !unpinned.is_empty() {
+            bail!("images must be pinned by digest when using registry mirrors: {}", unpinned.join(", "));
+        }
+        let existing = read_daemon_config(&self.daemon_config_path).await?;
+        let contents = Self::merge_daemon_config(existing.as_deref(), &self.mirrors)?;
+        write_daemon_config(&self.daemon_config_path, contents).await?;
+
+        info!("Restarting docker daemon to use registry mirrors {:?}", self.mirrors);
+        restart_docker().await
+    }
+
+    fn merge_daemon_config(existing: Option<&str>, mirrors: &[String]) -> anyhow::Result<String> {
+        let mut daemon_config: Map<String, Value> = match existing {
+            Some(contents) => serde_json::from_str(contents).context("Invalid docker daemon config")?,
+            None => Map::new(),
+        };
+        daemon_config.insert("registry-mirrors".into(), mirrors.into());
+        Ok(serde_json::to_string_pretty(&daemon_config)?)
+    }
+}
+
+/// Find the images in a docker compose file that aren't pinned by digest.
+fn unpinned_images(docker_compose: &[u8]) -> anyhow::Result<Vec<String>> {
+    let compose: ComposeServices = serde_yaml::from_slice(docker_compose).context("malformed docker compose")?;
+    let images = compose
+        .services
+        .values()
+        .filter_map(|service| service.get("image").and_then(serde_yaml::Value::as_str))
+        .filter(|image| !image.contains("@sha256:"))
+        .map(String::from)
+        .collect();
+    Ok(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_daemon_config() {
+        let mirrors = vec!["https://10.0.0.1:5000".to_string()];
+        let existing = r#"{"log-driver": "json-file", "registry-mirrors": ["https://old.mirror"]}"#;
+        let merged = RegistryMirrors::merge_daemon_config(Some(existing), &mirrors).expect("failed to merge");
+        let merged: Value = serde_json::from_str(&merged).expect("invalid json");
+        let expected = json!({
+            "log-driver": "json-file",
+            "registry-mirrors": ["https://10.0.0.1:5000"],
+        });
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn empty_daemon_config() {
+        let mirrors = vec!["https://10.0.0.1:5000".to_string()];
+        let merged = RegistryMirrors::merge_daemon_config(None, &mirrors).expect("failed to merge");
+        let merged: Value = serde_json::from_str(&merged).expect("invalid json");
+        assert_eq!(merged, json!({"registry-mirrors": ["https://10.0.0.1:5000"]}));
+    }
+
+    #[test]
+    fn unpinned() {
+        let compose = br#"
+services:
+  api:
+    image: caddy@sha256:abcd
+  worker:
+    image: ghcr.io/foo/worker:latest
+  built:
+    build: .
+"#;
+        let images = unpinned_images(compose).expect("failed to find images");
+        assert_eq!(images, &["ghcr.io/foo/worker:latest"]);
+    }
+
+    #[tokio::test]
+    async fn http_mirror() {
+        let mirrors = RegistryMirrors::new(vec!["http://10.0.0.1:5000".into()], "/does/not/exist".into());
+        let err = mirrors.apply().await.expect_err("http mirror accepted");
+        assert!(err.to_string().contains("not an https URL"), "{err}");
+    }
+}
@@ -0,0 +1,86 @@
+use crate::resources::{CA_CERT_FILE, CA_KEY_FILE, ProxyTls, ROOT_CA_FILE};
+use anyhow::Context;
+use cvm_agent_models::bootstrap::PrivatePki;
+use std::{fs::Permissions, os::unix::fs::PermissionsExt, path::PathBuf};
+use tokio::fs;
+use tracing::info;
+
+/// Sets up the files the proxy needs to get its TLS certificate from a private PKI.
+pub(crate) struct ProxyTlsSetup {
+    tls_dir: PathBuf,
+    private_pki: Option<PrivatePki>,
+}
+
+impl ProxyTlsSetup {
+    pub(crate) fn new(tls_dir: PathBuf, private_pki: Option<PrivatePki>) -> Self {
+        Self { tls_dir, private_pki }
+    }
+
+    /// Write the TLS files into the proxy's TLS directory and return the TLS configuration to render.
+    pub(crate) async fn apply(&self) -> anyhow::Result<ProxyTls> {
+        match &self.private_pki {
+            None => Ok(ProxyTls::ZeroSsl),
+            Some(PrivatePki::Ca { ca_cert_pem, ca_key_pem }) => {
+                info!("Issuing TLS certificates from the provided CA");
+                self.write(CA_CERT_FILE, ca_cert_pem, 0o644).await?;
+                self.write(CA_KEY_FILE, ca_key_pem, 0o600).await?;
+                Ok(ProxyTls::InternalCa)
+            }
+            Some(PrivatePki::Acme { directory_url, root_ca_pem }) => {
+                info!("Using ACME directory at {directory_url}");
+                if let Some(root_ca_pem) = root_ca_pem {
+                    self.write(ROOT_CA_FILE, root_ca_pem, 0o644).await?;
+                }
+                Ok(ProxyTls::Acme { directory_url: directory_url.clone(), trusted_root: root_ca_pem.is_some() })
+            }
+        }
+    }
+
+    async fn write(&self, name: &str, contents: &str, mode: u32) -> anyhow::Result<()> {
+        let path = self.tls_dir.join(name);
+        // Restrict permissions before writing so the contents are never readable by anyone else.
+        fs::write(&path, b"").await.with_context(|| format!("Failed to create {name}"))?;
+        fs::set_permissions(&path, Permissions::from_mode(mode))
+            .await
+            .with_context(|| format!("Failed to set {name} permissions"))?;
+        fs::write(&path, contents).await.with_context(|| format!("Failed to write {name}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn ca() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let pki = PrivatePki::Ca { ca_cert_pem: "cert".into(), ca_key_pem: "key".into() };
+        let tls = ProxyTlsSetup::new(dir.path().into(), Some(pki)).apply().await.expect("failed to apply");
+        assert_eq!(tls, ProxyTls::InternalCa);
+
+        assert_eq!(std::fs::read_to_string(dir.path().join(CA_CERT_FILE)).expect("no cert"), "cert");
+        let key_path = dir.path().join(CA_KEY_FILE);
+        assert_eq!(std::fs::read_to_string(&key_path).expect("no key"), "key");
+        let mode = std::fs::metadata(&key_path).expect("no metadata").permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn acme() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let pki = PrivatePki::Acme { directory_url: "https://acme.internal".into(), root_ca_pem: Some("root".into()) };
+        let tls = ProxyTlsSetup::new(dir.path().into(), Some(pki)).apply().await.expect("failed to apply");
+        assert_eq!(tls, ProxyTls::Acme { directory_url: "https://acme.internal".into(), trusted_root: true });
+        assert_eq!(std::fs::read_to_string(dir.path().join(ROOT_CA_FILE)).expect("no root"), "root");
+    }
+
+    #[tokio::test]
+    async fn zerossl() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let tls = ProxyTlsSetup::new(dir.path().into(), None).apply().await.expect("failed to apply");
+        assert_eq!(tls, ProxyTls::ZeroSsl);
+        assert!(std::fs::read_dir(dir.path()).expect("failed to read dir").next().is_none());
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{Context, anyhow, bail};
+use axum::http::StatusCode;
+use cvm_agent_models::encryption::{ENCRYPTION_INFO, EncryptedPayload, LOG_ENCRYPTION_KEY_EXTENSION, MaybeEncrypted};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    agreement::{EphemeralPrivateKey, UnparsedPublicKey, X25519, agree_ephemeral},
+    hkdf::{HKDF_SHA256, Salt},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::Serialize;
+use tracing::error;
+
+/// Get the X25519 public key to encrypt logs and stats to out of a docker compose file, if it declares one.
+///
+/// The key is taken from the compose file since it's part of the CVM's measurement, unlike the application metadata.
+pub(crate) fn compose_log_encryption_key(docker_compose: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let compose: serde_yaml::Mapping = serde_yaml::from_slice(docker_compose).context("malformed docker compose")?;
+    let Some(key) = compose.get(LOG_ENCRYPTION_KEY_EXTENSION) else {
+        return Ok(None);
+    };
+    let key = key.as_str().with_context(|| format!("'{LOG_ENCRYPTION_KEY_EXTENSION}' is not a string"))?;
+    let key = hex::decode(key).context("key is not hex encoded")?;
+    if key.len() != 32 {
+        bail!("key must be 32 bytes long");
+    }
+    Ok(Some(key))
+}
+
+/// Encrypt a payload so that it can only be decrypted by the owner of the given X25519 public key.
+pub(crate) fn encrypt<T: Serialize>(public_key: &[u8], payload: &T) -> anyhow::Result<EncryptedPayload> {
+    let rng = SystemRandom::new();
+    let private_key =
+        EphemeralPrivateKey::generate(&X25519, &rng).map_err(|_| anyhow!("failed to generate ephemeral key"))?;
+    let ephemeral_public_key =
+        private_key.compute_public_key().map_err(|_| anyhow!("failed to compute public key"))?.as_ref().to_vec();
+    let peer_public_key = UnparsedPublicKey::new(&X25519, public_key);
+    let key = agree_ephemeral(private_key, &peer_public_key, |shared_secret| {
+        let prk = Salt::new(HKDF_SHA256, &ephemeral_public_key).extract(shared_secret);
+        prk.expand(&[ENCRYPTION_INFO], &AES_256_GCM).map(UnboundKey::from)
+    })
+    .map_err(|_| anyhow!("key agreement failed"))?
+    .map_err(|_| anyhow!("key derivation failed"))?;
+
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill(&mut nonce).map_err(|_| anyhow!("failed to generate nonce"))?;
+    let mut ciphertext = serde_json::to_vec(payload).context("failed to serialize payload")?;
+    LessSafeKey::new(key)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut ciphertext)
+        .map_err(|_| anyhow!("encryption failed"))?;
+    Ok(EncryptedPayload { ephemeral_public_key, nonce: nonce.to_vec(), ciphertext })
+}
+
+/// Encrypt a response payload if a log encryption key is configured.
+pub(crate) fn maybe_encrypt<T: Serialize>(
+    public_key: Option<&[u8]>,
+    payload: T,
+) -> Result<MaybeEncrypted<T>, StatusCode> {
+    let Some(public_key) = public_key else {
+        return Ok(MaybeEncrypted::Plaintext(payload));
+    };
+    match encrypt(public_key, &payload) {
+        Ok(encrypted) => Ok(MaybeEncrypted::Encrypted { encrypted }),
+        Err(e) => {
+            error!("Failed to encrypt payload: {e:#}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_payload() {
+        let rng = SystemRandom::new();
+        let private_key = EphemeralPrivateKey::generate(&X25519, &rng).expect("failed to generate key");
+        let public_key = private_key.compute_public_key().expect("failed to compute public key");
+        let payload = vec!["hello".to_string()];
+        let plaintext = serde_json::to_vec(&payload).expect("failed to serialize");
+
+        let encrypted = encrypt(public_key.as_ref(), &payload).expect("failed to encrypt");
+        assert_eq!(encrypted.ephemeral_public_key.len(), 32);
+        assert_eq!(encrypted.nonce.len(), NONCE_LEN);
+        assert_eq!(encrypted.ciphertext.len(), plaintext.len() + AES_256_GCM.tag_len());
+        assert_ne!(&encrypted.ciphertext[..plaintext.len()], plaintext.as_slice());
+    }
+
+    #[test]
+    fn encrypt_decrypt() {
+        let rng = SystemRandom::new();
+        let private_key = EphemeralPrivateKey::generate(&X25519, &rng).expect("failed to generate key");
+        let public_key = private_key.compute_public_key().expect("failed to compute public key");
+        let payload = vec!["hello".to_string(), "world".to_string()];
+        let EncryptedPayload { ephemeral_public_key, nonce, mut ciphertext } =
+            encrypt(public_key.as_ref(), &payload).expect("failed to encrypt");
+
+        // Decrypt it the way the README describes.
+        let peer_public_key = UnparsedPublicKey::new(&X25519, &ephemeral_public_key);
+        let key = agree_ephemeral(private_key, &peer_public_key, |shared_secret| {
+            let prk = Salt::new(HKDF_SHA256, &ephemeral_public_key).extract(shared_secret);
+            prk.expand(&[ENCRYPTION_INFO], &AES_256_GCM).map(UnboundKey::from)
+        })
+        .expect("key agreement failed")
+        .expect("key derivation failed");
+        let nonce = Nonce::try_assume_unique_for_key(&nonce).expect("invalid nonce");
+        let plaintext =
+            LessSafeKey::new(key).open_in_place(nonce, Aad::empty(), &mut ciphertext).expect("decryption failed");
+        let decrypted: Vec<String> = serde_json::from_slice(plaintext).expect("invalid plaintext");
+        assert_eq!(decrypted, payload);
+    }
+
+    #[test]
+    fn compose_key() {
+        let compose = format!("{LOG_ENCRYPTION_KEY_EXTENSION}: {}\nservices: {{}}\n", hex::encode([1; 32]));
+        let key = compose_log_encryption_key(compose.as_bytes()).expect("invalid compose");
+        assert_eq!(key, Some(vec![1; 32]));
+
+        let key = compose_log_encryption_key(b"services: {}\n").expect("invalid compose");
+        assert_eq!(key, None);
+    }
+
+    #[test]
+    fn invalid_compose_key() {
+        let compose = format!("{LOG_ENCRYPTION_KEY_EXTENSION}: 0102\nservices: {{}}\n");
+        compose_log_encryption_key(compose.as_bytes()).expect_err("parsing succeeded");
+    }
+
+    #[test]
+    fn invalid_public_key() {
+        encrypt(&[1, 2, 3], &"hello").expect_err("encryption succeeded");
+    }
+
+    #[test]
+    fn plaintext_without_key() {
+        let payload = maybe_encrypt(None, "hello").expect("failed to encrypt");
+        assert!(matches!(payload, MaybeEncrypted::Plaintext("hello")));
+    }
+}
@@ -12,10 +12,15 @@ use alloy_contract::{CallBuilder, CallDecoder};
 use alloy_provider::{Provider, WsConnect};
 use anyhow::Context as _;
 use anyhow::anyhow;
+use chrono::Utc;
+use cvm_agent_models::heartbeat::{HeartbeatStatusResponse, WalletBalance};
 use serde::{Deserialize, Serialize};
 use serde_with::hex::Hex;
 use serde_with::serde_as;
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::{
     sync::mpsc::{Receiver, Sender, channel},
     time::{Interval, MissedTickBehavior, interval, sleep},
@@ -71,6 +76,7 @@ pub(crate) struct HeartbeatEmitter {
     measurement_hash_url: String,
     cpu_count: u64,
     gpu_count: u64,
+    status: Arc<Mutex<HeartbeatStatusResponse>>,
 }
 
 impl HeartbeatEmitter {
@@ -94,9 +100,14 @@ impl HeartbeatEmitter {
         let heartbeat_contract_address =
             heartbeat_contract_address.parse().context("Invalid heartbeat contract address")?;
         let token_contract_address = token_contract_address.parse().context("Invalid token contract address")?;
-        let attestation_url = format!("https://{workload_domain}{ATTESTATION_PATH}");
+        let attestation_url = Self::attestation_url(&workload_domain);
         let wallet = PrivateKeySigner::from_slice(&wallet_private_key).context("Invalid wallet private key")?;
         info!("Starting heartbeat emitter using wallet {}", wallet.address());
+        let status = Arc::new(Mutex::new(HeartbeatStatusResponse {
+            wallet_address: wallet.address().to_string(),
+            balance: None,
+            last_heartbeat_at: None,
+        }));
 
         let submitter = Self {
             workload_id,
@@ -111,13 +122,14 @@ impl HeartbeatEmitter {
             measurement_hash_url,
             cpu_count,
             gpu_count,
+            status: status.clone(),
         };
         tokio::spawn(async move { submitter.run(caddy_status, receiver).await });
-        let handle = HeartbeatEmitterHandle(sender);
+        let handle = HeartbeatEmitterHandle { sender, status };
         Ok(handle)
     }
 
-    async fn run(self, caddy_status: CaddyStatus, mut receiver: Receiver<HeartbeatEmitterCommand>) {
+    async fn run(mut self, caddy_status: CaddyStatus, mut receiver: Receiver<HeartbeatEmitterCommand>) {
         info!("Waiting for caddy to generate a TLS certificate before emitting heartbeats");
         caddy_status.wait_tls_certificate().await;
         info!("Starting heartbeat generation");
@@ -157,10 +169,14 @@ impl HeartbeatEmitter {
             if let Err(e) = self.submit_htx(&manager).await {
                 error!("Failed to submit HTX: {e}");
             }
-            ctx = Self::process_pending_commands(&mut receiver, ctx);
+            ctx = self.process_pending_commands(&mut receiver, ctx);
         }
     }
 
+    fn attestation_url(domain: &str) -> String {
+        format!("https://{domain}{ATTESTATION_PATH}")
+    }
+
     async fn connect(&self) -> anyhow::Result<impl Provider> {
         let ws = WsConnect::new(&self.rpc_endpoint).with_max_retries(u32::MAX);
         let provider = ProviderBuilder::new()
@@ -206,6 +222,8 @@ impl HeartbeatEmitter {
         let eth_balance = alloy::primitives::utils::format_ether(eth_balance);
         let nil_balance = alloy::primitives::utils::format_units(nil_balance, NIL_TOKEN_DECIMALS).unwrap_or_default();
         info!("Wallet {address} has {eth_balance} ETH and {nil_balance} NIL");
+        self.status.lock().expect("lock poisoned").balance =
+            Some(WalletBalance { eth: eth_balance, nil: nil_balance, observed_at: Utc::now() });
     }
 
     async fn submit_htx(&self, router: &HeartbeatManagerInstance<impl Provider>) -> anyhow::Result<()> {
@@ -237,10 +255,17 @@ impl HeartbeatEmitter {
         let tx_hash = receipt.transaction_hash;
         let status = if receipt.status() { "success" } else { "failure" };
         info!("HTX submitted in transaction {tx_hash} with status {status}");
+        if receipt.status() {
+            self.status.lock().expect("lock poisoned").last_heartbeat_at = Some(Utc::now());
+        }
         Ok(())
     }
 
-    fn process_pending_commands(receiver: &mut Receiver<HeartbeatEmitterCommand>, mut ctx: Context) -> Context {
+    fn process_pending_commands(
+        &mut self,
+        receiver: &mut Receiver<HeartbeatEmitterCommand>,
+        mut ctx: Context,
+    ) -> Context {
         while let Ok(command) = receiver.try_recv() {
             match command {
                 HeartbeatEmitterCommand::SetInterval(interval) => {
@@ -248,6 +273,9 @@ impl HeartbeatEmitter {
                     // Only tick once after the period, not right away
                     ctx.ticker.reset();
                 }
+                HeartbeatEmitterCommand::SetDomain(domain) => {
+                    self.attestation_url = Self::attestation_url(&domain);
+                }
             }
         }
         ctx
@@ -262,18 +290,32 @@ impl HeartbeatEmitter {
 }
 
 #[must_use]
-pub(crate) struct HeartbeatEmitterHandle(Sender<HeartbeatEmitterCommand>);
+pub(crate) struct HeartbeatEmitterHandle {
+    sender: Sender<HeartbeatEmitterCommand>,
+    status: Arc<Mutex<HeartbeatStatusResponse>>,
+}
 
 impl HeartbeatEmitterHandle {
     pub(crate) async fn set_interval(&self, interval: Duration) {
-        if self.0.send(HeartbeatEmitterCommand::SetInterval(interval)).await.is_err() {
+        if self.sender.send(HeartbeatEmitterCommand::SetInterval(interval)).await.is_err() {
             error!("Heartbeat emitter channel dropped");
         }
     }
+
+    pub(crate) async fn set_domain(&self, domain: String) {
+        if self.sender.send(HeartbeatEmitterCommand::SetDomain(domain)).await.is_err() {
+            error!("Heartbeat emitter channel dropped");
+        }
+    }
+
+    pub(crate) fn status(&self) -> HeartbeatStatusResponse {
+        self.status.lock().expect("lock poisoned").clone()
+    }
 }
 
 pub(crate) enum HeartbeatEmitterCommand {
     SetInterval(Duration),
+    SetDomain(String),
 }
 
 struct Context {
@@ -0,0 +1,96 @@
+use anyhow::{Context, anyhow};
+use attestation_report::identity_token::{IdentityTokenClaims, IdentityTokenHeader};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use std::{fs, io, path::Path};
+use tracing::info;
+
+/// Signs identity tokens using a key whose public part the attester binds into attestation reports.
+///
+/// The key is stored in the CVM's memory backed `/run` so that it survives `cvm-agent` restarts, which would otherwise
+/// make tokens stop matching the key bound into reports, but never leaves the CVM.
+pub struct IdentityTokenSigner {
+    key_pair: Ed25519KeyPair,
+}
+
+impl IdentityTokenSigner {
+    /// Load the signing key from a path, generating it if it doesn't exist yet.
+    pub fn load_or_generate(path: &Path) -> anyhow::Result<Self> {
+        let pkcs8 = match fs::read(path) {
+            Ok(pkcs8) => pkcs8,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!("Generating identity token key in {}", path.display());
+                let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                    .map_err(|_| anyhow!("Failed to generate key"))?;
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).context("Failed to create key directory")?;
+                }
+                fs::write(path, pkcs8.as_ref()).context("Failed to write key")?;
+                pkcs8.as_ref().to_vec()
+            }
+            Err(e) => return Err(e).context("Failed to read key"),
+        };
+        let key_pair = Ed25519KeyPair::from_pkcs8(&pkcs8).map_err(|e| anyhow!("Invalid key: {e}"))?;
+        Ok(Self { key_pair })
+    }
+
+    /// The public key tokens are signed with.
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key_pair.public_key().as_ref().try_into().expect("invalid public key length")
+    }
+
+    /// Mint a token carrying the given claims.
+    pub(crate) fn mint(&self, claims: &IdentityTokenClaims) -> anyhow::Result<String> {
+        let header = serde_json::to_vec(&IdentityTokenHeader::default()).context("Failed to serialize header")?;
+        let claims = serde_json::to_vec(claims).context("Failed to serialize claims")?;
+        let message = format!("{}.{}", URL_SAFE_NO_PAD.encode(header), URL_SAFE_NO_PAD.encode(claims));
+        let signature = self.key_pair.sign(message.as_bytes());
+        Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{ED25519, UnparsedPublicKey};
+    use tempfile::tempdir;
+
+    #[test]
+    fn persisted_key() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("keys/identity");
+        let signer = IdentityTokenSigner::load_or_generate(&path).expect("failed to generate key");
+        let loaded = IdentityTokenSigner::load_or_generate(&path).expect("failed to load key");
+        assert_eq!(signer.public_key(), loaded.public_key());
+    }
+
+    #[test]
+    fn mint() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let signer = IdentityTokenSigner::load_or_generate(&dir.path().join("identity")).expect("failed to create key");
+        let claims = IdentityTokenClaims {
+            iss: "nilcc".into(),
+            sub: "workload".into(),
+            agent_id: "agent".into(),
+            aud: "https://example.com".into(),
+            iat: 1000,
+            nbf: None,
+            exp: 1300,
+        };
+        let token = signer.mint(&claims).expect("failed to mint");
+        let (message, signature) = token.rsplit_once('.').expect("no signature");
+        let signature = URL_SAFE_NO_PAD.decode(signature).expect("invalid signature encoding");
+        UnparsedPublicKey::new(&ED25519, signer.public_key())
+            .verify(message.as_bytes(), &signature)
+            .expect("invalid signature");
+
+        let (_, encoded_claims) = message.split_once('.').expect("no claims");
+        let decoded: IdentityTokenClaims =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(encoded_claims).expect("invalid claims encoding"))
+                .expect("invalid claims");
+        assert_eq!(decoded, claims);
+    }
+}
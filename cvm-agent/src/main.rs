@@ -1,6 +1,8 @@
 use crate::{
-    resources::{ApplicationMetadata, Resources},
-    routes::{AppState, BootstrapContext, VmType, create_router},
+    bootstrap::{BootstrapState, jobs},
+    identity::IdentityTokenSigner,
+    resources::{ApplicationMetadata, ProxyConfig, Resources},
+    routes::{AppState, BootstrapContext, VmType, create_identity_router, create_public_router, create_router},
 };
 use alloy::signers::k256::sha2::{Digest, Sha256};
 use bollard::Docker;
@@ -19,11 +21,20 @@ use tokio::{
 use tracing::{error, info, level_filters::LevelFilter};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod accelerators;
+mod auth;
+mod bootstrap;
+mod encryption;
 mod heartbeat;
+mod identity;
 mod monitors;
 mod resources;
+mod roughtime;
 mod routes;
 
+/// The address of the host in docker's default bridge network, which `host.docker.internal` resolves to in containers.
+const DOCKER_BRIDGE_ADDRESS: Ipv4Addr = Ipv4Addr::new(172, 17, 0, 1);
+
 #[derive(Parser)]
 struct Cli {
     iso_mount_path: PathBuf,
@@ -39,6 +50,27 @@ struct Cli {
 
     #[clap(long, default_value_t = default_bind_endpoint())]
     bind_endpoint: SocketAddr,
+
+    /// The endpoint to serve the public endpoints the proxy exposes on.
+    ///
+    /// The proxy expects this to be listening on the default port. Only the proxy container needs to reach this, so
+    /// by default it's only bound to the docker bridge rather than every interface.
+    #[clap(long, default_value_t = default_public_bind_endpoint())]
+    public_bind_endpoint: SocketAddr,
+
+    /// The endpoint to serve the identity token endpoints on.
+    ///
+    /// Unlike the main endpoint this one isn't forwarded to the host. Only containers need to reach this, so by default
+    /// it's only bound to the docker bridge rather than every interface.
+    #[clap(long, default_value_t = default_identity_bind_endpoint())]
+    identity_bind_endpoint: SocketAddr,
+
+    /// The path to the key identity tokens are signed with, which is generated if it doesn't exist.
+    #[clap(long, default_value = default_identity_key_path().into_os_string())]
+    identity_key_path: PathBuf,
+
+    #[clap(long, default_value = default_bootstrap_state_path().into_os_string())]
+    bootstrap_state_path: PathBuf,
 }
 
 fn default_version_path() -> PathBuf {
@@ -53,17 +85,33 @@ fn default_log_file_path() -> PathBuf {
     "/var/log/cvm-agent.log".into()
 }
 
+fn default_bootstrap_state_path() -> PathBuf {
+    "/run/cvm-agent/bootstrap.json".into()
+}
+
+fn default_identity_key_path() -> PathBuf {
+    "/run/cvm-agent/identity-key".into()
+}
+
 fn default_bind_endpoint() -> SocketAddr {
     SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 59666).into()
 }
 
+fn default_public_bind_endpoint() -> SocketAddr {
+    SocketAddrV4::new(DOCKER_BRIDGE_ADDRESS, 59667).into()
+}
+
+fn default_identity_bind_endpoint() -> SocketAddr {
+    SocketAddrV4::new(DOCKER_BRIDGE_ADDRESS, 59668).into()
+}
+
 fn load_metadata(path: &Path) -> Result<ApplicationMetadata, Box<dyn std::error::Error>> {
     let metadata = fs::read_to_string(path)?;
     let metadata = serde_json::from_str(&metadata)?;
     Ok(metadata)
 }
 
-fn build_bootstrap_context(cli: &Cli) -> (TempDir, BootstrapContext) {
+fn build_bootstrap_context(cli: &Cli, token_public_key: [u8; 32]) -> (TempDir, BootstrapContext, ProxyConfig) {
     let metadata = match load_metadata(&cli.iso_mount_path.join("metadata.json")) {
         Ok(metadata) => metadata,
         Err(e) => {
@@ -77,38 +125,64 @@ fn build_bootstrap_context(cli: &Cli) -> (TempDir, BootstrapContext) {
         "gpu" => VmType::Gpu,
         _ => panic!("unknown vm type {vm_type}"),
     };
+    let accelerator = match vm_type {
+        VmType::Cpu => None,
+        VmType::Gpu => accelerators::detect(),
+    };
+    let gpus = accelerator.map(|accelerator| accelerator.count_devices()).unwrap_or_default();
     let state_dir = tempdir().expect("failed to create tempdir");
     println!("Writing state files to {}", state_dir.path().display());
 
-    let resources = Resources::render(&metadata, &vm_type);
+    let resources = Resources::render(&metadata, accelerator);
     let system_compose_path = state_dir.path().join("docker-compose.yaml");
     let caddy_path = state_dir.path().join("Caddyfile");
+    let proxy_logs_path = state_dir.path().join("caddy-logs");
+    let proxy_tls_path = state_dir.path().join("caddy-tls");
     let docker_config_path = state_dir.path().join("docker");
+    fs::create_dir_all(&proxy_logs_path).expect("failed to create proxy logs path");
+    fs::create_dir_all(&proxy_tls_path).expect("failed to create proxy tls path");
     fs::create_dir_all(&docker_config_path).expect("failed to create docker config path");
     fs::write(&system_compose_path, resources.docker_compose).expect("failed to write docker-compose.yaml");
     fs::write(&caddy_path, resources.caddyfile).expect("failed to write Caddyfile");
 
     let user_compose_path = cli.iso_mount_path.join("docker-compose.yaml");
-    let user_docker_compose_sha256 = {
-        let contents = fs::read(&user_compose_path).expect("failed to read user docker compose file");
-        Sha256::digest(contents).into()
+    let user_compose = fs::read(&user_compose_path).expect("failed to read user docker compose file");
+    let user_docker_compose_sha256 = Sha256::digest(&user_compose).into();
+    let log_encryption_key = match encryption::compose_log_encryption_key(&user_compose) {
+        Ok(key) => key,
+        Err(e) => {
+            Cli::command().error(ErrorKind::InvalidValue, format!("invalid log encryption key: {e:#}")).exit();
+        }
+    };
+    let jobs = match jobs::compose_jobs(&user_compose) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            Cli::command().error(ErrorKind::InvalidValue, format!("invalid jobs: {e:#}")).exit();
+        }
     };
     let external_files_path = cli.iso_mount_path.join("files");
+    let proxy = metadata.proxy_config();
     let context = BootstrapContext {
         system_docker_compose: system_compose_path,
         user_docker_compose: user_compose_path,
         user_docker_compose_sha256,
         external_files: external_files_path,
         caddy_config: caddy_path,
+        proxy_logs: proxy_logs_path,
+        proxy_tls: proxy_tls_path,
         docker_config: docker_config_path,
         version,
         vm_type,
         iso_mount: cli.iso_mount_path.clone(),
         event_holder: Default::default(),
         cpus: num_cpus::get() as u64,
-        gpus: count_gpus() as u64,
+        gpus: gpus as u64,
+        accelerator,
+        log_encryption_key,
+        jobs,
+        token_public_key: hex::encode(token_public_key),
     };
-    (state_dir, context)
+    (state_dir, context, proxy)
 }
 
 async fn shutdown_signal() {
@@ -130,38 +204,6 @@ async fn shutdown_signal() {
     }
 }
 
-fn count_gpus() -> usize {
-    let mut id = 0;
-    loop {
-        let path = format!("/dev/nvidia{id}");
-        if !fs::exists(&path).unwrap_or_default() {
-            break;
-        }
-        id += 1;
-    }
-    id
-}
-
-fn setup_gpus(gpu_count: u64) {
-    match gpu_count {
-        0 => info!("No GPUs detected"),
-        1 => {
-            info!("Detected a single GPU, setting confidential compute ready state");
-            std::process::Command::new("nvidia-smi")
-                .args(["conf-compute", "-srs", "1"])
-                .status()
-                .expect("failed to run nvidia-smi");
-        }
-        _ => {
-            info!("Detected {gpu_count} GPUs, setting multiple GPU mode");
-            std::process::Command::new("nvidia-smi")
-                .args(["conf-compute", "-mgm"])
-                .status()
-                .expect("failed to run nvidia-smi");
-        }
-    }
-}
-
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     let cli = Cli::parse();
@@ -180,10 +222,25 @@ async fn main() {
         )
         .init();
 
+    if let Some(parent) = cli.bootstrap_state_path.parent() {
+        create_dir_all(parent).expect("failed to create directory for bootstrap state");
+    }
+    let bootstrap = BootstrapState::load(cli.bootstrap_state_path.clone());
+
     let docker = Docker::connect_with_local_defaults().expect("failed to connect to docker daemon");
-    let (_state_dir, context) = build_bootstrap_context(&cli);
-    if matches!(context.vm_type, VmType::Gpu) {
-        setup_gpus(context.gpus);
+    let identity_signer =
+        IdentityTokenSigner::load_or_generate(&cli.identity_key_path).expect("failed to load identity token key");
+    let (_state_dir, context, proxy) = build_bootstrap_context(&cli, identity_signer.public_key());
+    let api_token = match auth::load_api_token(&cli.iso_mount_path) {
+        Ok(api_token) => api_token,
+        Err(e) => {
+            Cli::command().error(ErrorKind::Io, format!("failed to read API token: {e}")).exit();
+        }
+    };
+    match context.accelerator {
+        Some(accelerator) => accelerator.setup(context.gpus as usize),
+        None if matches!(context.vm_type, VmType::Gpu) => info!("No GPUs detected"),
+        None => (),
     }
     let state = Arc::new(AppState {
         docker,
@@ -191,7 +248,37 @@ async fn main() {
         system_state: Default::default(),
         log_path: cli.log_file.clone(),
         heartbeat_handle: Default::default(),
+        bootstrap: bootstrap.into(),
+        caddy_status: Default::default(),
+        proxy: proxy.into(),
+        time_sync_status: Default::default(),
+        certificate_status: Default::default(),
+        drift_status: Default::default(),
+        compose: Default::default(),
+        oom_status: Default::default(),
+        tls_fingerprint: Default::default(),
+        status_rate_limiter: routes::public::status::rate_limiter(),
+        identity_signer,
+        workload_identity: Default::default(),
+        api_token,
+    });
+    let public_router = create_public_router(state.clone());
+    let public_listener = TcpListener::bind(cli.public_bind_endpoint).await.expect("failed to bind public endpoint");
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(public_listener, public_router).await {
+            error!("Failed to serve public endpoints: {e}");
+        }
     });
+
+    let identity_router = create_identity_router(state.clone());
+    let identity_listener =
+        TcpListener::bind(cli.identity_bind_endpoint).await.expect("failed to bind identity endpoint");
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(identity_listener, identity_router).await {
+            error!("Failed to serve identity endpoints: {e}");
+        }
+    });
+
     let router = create_router(state.clone());
     let listener = TcpListener::bind(cli.bind_endpoint).await.expect("failed to bind");
     match axum::serve(listener, router).with_graceful_shutdown(shutdown_signal()).await {
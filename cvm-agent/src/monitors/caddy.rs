@@ -1,4 +1,5 @@
 use crate::{monitors::EventHolder, routes::SystemState};
+use anyhow::{Context, bail};
 use bollard::{
     Docker,
     container::LogOutput,
@@ -8,13 +9,30 @@ use cvm_agent_models::health::EventKind;
 use futures::{Stream, StreamExt};
 use serde::Deserialize;
 use std::{borrow::Cow, mem, sync::Arc, time::Duration};
-use tokio::sync::{Mutex, oneshot};
+use tokio::process::Command;
+use tokio::sync::{Mutex, watch};
 use tokio::time::sleep;
 use tracing::{debug, error, info, warn};
 
-const CONTAINER_NAME: &str = "cvm-nilcc-proxy-1";
+pub(super) const CONTAINER_NAME: &str = "cvm-nilcc-proxy-1";
 const LOOP_INTERVAL: Duration = Duration::from_secs(10);
 
+/// Make caddy reload its config after the Caddyfile is changed.
+///
+/// Unlike restarting the container, this keeps serving existing connections and certificates.
+pub(crate) async fn reload_caddy_config() -> anyhow::Result<()> {
+    info!("Reloading caddy config");
+    let output = Command::new("docker")
+        .args(["exec", CONTAINER_NAME, "caddy", "reload", "--config", "/etc/caddy/Caddyfile", "--adapter", "caddyfile"])
+        .output()
+        .await
+        .context("Failed to run caddy reload")?;
+    if !output.status.success() {
+        bail!("caddy reload failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
 /// A monitor for the caddy container.
 pub struct CaddyMonitor {
     docker: Docker,
@@ -25,7 +43,7 @@ pub struct CaddyMonitor {
 impl CaddyMonitor {
     pub fn spawn(docker: Docker, system_state: Arc<Mutex<SystemState>>, event_holder: EventHolder) -> CaddyStatus {
         let monitor = Self { docker, system_state, event_holder };
-        let (sender, receiver) = oneshot::channel();
+        let (sender, receiver) = watch::channel(false);
         info!("Spawning caddy monitor");
         tokio::spawn(async move {
             monitor.run(sender).await;
@@ -33,8 +51,7 @@ impl CaddyMonitor {
         CaddyStatus(receiver)
     }
 
-    async fn run(self, sender: oneshot::Sender<()>) {
-        let mut sender = Some(sender);
+    async fn run(self, sender: watch::Sender<bool>) {
         let mut threshold_timestamp = 0.0;
         loop {
             let builder = LogsOptionsBuilder::new().tail("10").stderr(true);
@@ -48,10 +65,8 @@ impl CaddyMonitor {
                         SystemState::WaitingBootstrap => error!("System is still waiting for bootstrap"),
                         SystemState::Starting | SystemState::Ready => {
                             info!("Caddy fetched TLS certificate and is running successfully");
-                            // Notify the listener that we're ready
-                            if let Some(sender) = sender.take() {
-                                let _ = sender.send(());
-                            }
+                            // Notify the listeners that we're ready
+                            sender.send_replace(true);
                             *system_state = SystemState::Ready
                         }
                     }
@@ -106,22 +121,27 @@ impl CaddyMonitor {
     }
 }
 
-pub(crate) struct CaddyStatus(oneshot::Receiver<()>);
+#[derive(Clone)]
+pub(crate) struct CaddyStatus(watch::Receiver<bool>);
 
 impl CaddyStatus {
-    pub(crate) async fn wait_tls_certificate(self) {
-        if self.0.await.is_err() {
-            error!("Caddy status receiver dropped");
+    pub(crate) async fn wait_tls_certificate(mut self) {
+        if self.0.wait_for(|ready| *ready).await.is_err() {
+            error!("Caddy status sender dropped");
         }
     }
 }
 
 #[derive(Deserialize)]
-struct LogLine<'a> {
-    ts: f64,
-    msg: Cow<'a, str>,
+pub(super) struct LogLine<'a> {
+    pub(super) ts: f64,
+    pub(super) msg: Cow<'a, str>,
+    #[serde(default)]
+    pub(super) level: Cow<'a, str>,
+    #[serde(default)]
+    pub(super) logger: Cow<'a, str>,
     #[serde(default)]
-    error: Cow<'a, str>,
+    pub(super) error: Cow<'a, str>,
 }
 
 #[cfg_attr(test, derive(Debug, PartialEq))]
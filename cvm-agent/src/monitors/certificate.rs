@@ -0,0 +1,207 @@
+use crate::{
+    monitors::caddy::{CONTAINER_NAME, LogLine},
+    routes::{AppState, SystemState, system::tls::fetch_certificate},
+};
+use anyhow::Context;
+use bollard::{container::LogOutput, query_parameters::LogsOptionsBuilder};
+use chrono::{DateTime, Utc};
+use cvm_agent_models::health::{CertificateStatus, EventKind};
+use futures::{Stream, StreamExt};
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::watch, time::sleep};
+use tracing::{info, warn};
+use x509_parser::parse_x509_certificate;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// A monitor that tracks the expiry of the certificate the proxy serves and whether caddy is failing to renew it.
+///
+/// Caddy renews certificates once a third of their lifetime is left so a warning is raised once only a quarter is left,
+/// which means renewals have been failing for a while.
+pub(crate) struct CertificateMonitor {
+    state: Arc<AppState>,
+}
+
+impl CertificateMonitor {
+    pub(crate) fn spawn(state: Arc<AppState>) -> CertificateMonitorStatus {
+        let monitor = Self { state };
+        let (sender, receiver) = watch::channel(None);
+        info!("Spawning certificate monitor");
+        tokio::spawn(async move {
+            monitor.run(sender).await;
+        });
+        CertificateMonitorStatus(receiver)
+    }
+
+    async fn run(self, sender: watch::Sender<Option<ObservedCertificate>>) {
+        let mut threshold_timestamp = 0.0;
+        let mut renewal_error = None;
+        let mut expiry_warned = false;
+        loop {
+            let builder = LogsOptionsBuilder::new().tail("100").stderr(true);
+            let stream = self.state.docker.logs(CONTAINER_NAME, Some(builder.build()));
+            let (next_timestamp, outcome) = find_renewal_outcome(stream, threshold_timestamp).await;
+            threshold_timestamp = next_timestamp;
+            match outcome {
+                Some(RenewalOutcome::Failed(error)) => renewal_error = Some(error),
+                Some(RenewalOutcome::Succeeded) => renewal_error = None,
+                None => (),
+            };
+
+            if matches!(*self.state.system_state.lock().await, SystemState::Ready) {
+                match self.fetch_validity().await {
+                    Ok(validity) => {
+                        let now = Utc::now();
+                        let expiring = validity.expiring(now);
+                        // Only emit an event when the threshold is first crossed so we don't keep overwriting others.
+                        if expiring && !expiry_warned {
+                            let mut message = format!(
+                                "TLS certificate expires in {} days and hasn't been renewed",
+                                validity.days_to_expiry(now)
+                            );
+                            if let Some(error) = &renewal_error {
+                                message.push_str(&format!(": {error}"));
+                            }
+                            warn!("{message}");
+                            self.state.context.event_holder.set(message, EventKind::Warning);
+                        }
+                        expiry_warned = expiring;
+                        sender
+                            .send_replace(Some(ObservedCertificate { validity, renewal_error: renewal_error.clone() }));
+                    }
+                    Err(e) => warn!("Failed to check TLS certificate: {e:#}"),
+                }
+            }
+            sleep(CHECK_INTERVAL).await;
+        }
+    }
+
+    async fn fetch_validity(&self) -> anyhow::Result<Validity> {
+        let domain = self.state.proxy.lock().await.hostnames.first().cloned().context("No domain configured")?;
+        let cert = fetch_certificate(&domain).await?;
+        let (_, cert) = parse_x509_certificate(&cert).context("Invalid TLS certificate")?;
+        let validity = cert.validity();
+        let not_before = DateTime::from_timestamp(validity.not_before.timestamp(), 0).context("Invalid not before")?;
+        let not_after = DateTime::from_timestamp(validity.not_after.timestamp(), 0).context("Invalid not after")?;
+        Ok(Validity { not_before, not_after })
+    }
+}
+
+/// Find whether the last certificate operation caddy logged after the given timestamp succeeded or failed.
+async fn find_renewal_outcome<T>(mut stream: T, timestamp_threshold: f64) -> (f64, Option<RenewalOutcome>)
+where
+    T: Stream<Item = Result<LogOutput, bollard::errors::Error>> + Unpin,
+{
+    let mut outcome = None;
+    let mut last_timestamp = timestamp_threshold;
+    while let Some(output) = stream.next().await {
+        let Ok(output) = output else {
+            return (timestamp_threshold, None);
+        };
+        let output = output.into_bytes();
+        let line = String::from_utf8_lossy(&output);
+        let Ok(line) = serde_json::from_str::<LogLine>(&line) else {
+            continue;
+        };
+        if line.ts <= timestamp_threshold {
+            continue;
+        }
+        if line.msg == "certificate obtained successfully" || line.msg == "certificate renewed successfully" {
+            outcome = Some(RenewalOutcome::Succeeded);
+        } else if line.level == "error" && line.logger.starts_with("tls.") {
+            let error = if line.error.is_empty() { line.msg } else { line.error };
+            outcome = Some(RenewalOutcome::Failed(error.into_owned()));
+        }
+        last_timestamp = line.ts;
+    }
+    (last_timestamp, outcome)
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum RenewalOutcome {
+    Succeeded,
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+struct Validity {
+    not_before: DateTime<Utc>,
+    not_after: DateTime<Utc>,
+}
+
+impl Validity {
+    fn expiring(&self, now: DateTime<Utc>) -> bool {
+        let lifetime = self.not_after - self.not_before;
+        self.not_after - now < lifetime / 4
+    }
+
+    fn days_to_expiry(&self, now: DateTime<Utc>) -> i64 {
+        (self.not_after - now).num_days()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ObservedCertificate {
+    validity: Validity,
+    renewal_error: Option<String>,
+}
+
+#[derive(Clone)]
+pub(crate) struct CertificateMonitorStatus(watch::Receiver<Option<ObservedCertificate>>);
+
+impl CertificateMonitorStatus {
+    /// The status of the certificate as of its last check, if any.
+    pub(crate) fn certificate_status(&self) -> Option<CertificateStatus> {
+        let observed = self.0.borrow().clone()?;
+        let ObservedCertificate { validity, renewal_error } = observed;
+        let days_to_expiry = validity.days_to_expiry(Utc::now());
+        Some(CertificateStatus { expires_at: validity.not_after, days_to_expiry, renewal_error })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn make_stream(lines: &[&str]) -> impl Stream<Item = Result<LogOutput, bollard::errors::Error>> {
+        let lines = lines.iter().map(|l| Ok(LogOutput::StdErr { message: l.to_string().into() }));
+        futures::stream::iter(lines)
+    }
+
+    #[tokio::test]
+    async fn renewal_failure() {
+        let lines = make_stream(&[
+            r#"{"level":"info","ts":1754341166.3218322,"logger":"tls.renew","msg":"renewing certificate"}"#,
+            r#"{"level":"error","ts":1754341166.41461,"logger":"tls.renew","msg":"could not get certificate from issuer","error":"HTTP 429 urn:ietf:params:acme:error:rateLimited"}"#,
+        ]);
+        let (timestamp, outcome) = find_renewal_outcome(lines, 0.0).await;
+        assert_eq!(timestamp, 1754341166.41461);
+        assert_eq!(outcome, Some(RenewalOutcome::Failed("HTTP 429 urn:ietf:params:acme:error:rateLimited".into())));
+    }
+
+    #[tokio::test]
+    async fn renewal_success() {
+        let lines = make_stream(&[
+            r#"{"level":"error","ts":1754341166.3218322,"logger":"tls.renew","msg":"will retry","error":"timeout"}"#,
+            r#"{"level":"info","ts":1754341166.41461,"logger":"tls.renew","msg":"certificate renewed successfully"}"#,
+            r#"{"level":"info","ts":1754341166.4151092,"logger":"http","msg":"server running"}"#,
+        ]);
+        let (_, outcome) = find_renewal_outcome(lines, 0.0).await;
+        assert_eq!(outcome, Some(RenewalOutcome::Succeeded));
+
+        let lines =
+            make_stream(&[r#"{"level":"error","ts":1754341166.3218322,"logger":"tls.renew","msg":"will retry"}"#]);
+        let (_, outcome) = find_renewal_outcome(lines, 1754341166.4).await;
+        assert_eq!(outcome, None);
+    }
+
+    #[test]
+    fn expiring() {
+        let not_before = Utc::now();
+        let validity = Validity { not_before, not_after: not_before + TimeDelta::days(90) };
+        assert!(!validity.expiring(not_before + TimeDelta::days(60)));
+        assert!(validity.expiring(not_before + TimeDelta::days(70)));
+        assert_eq!(validity.days_to_expiry(not_before + TimeDelta::days(70)), 20);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{
+    bootstrap::compose::{COMPOSE_PROJECT_NAME, ComposeConfig, DockerCompose, ServiceConfig},
+    routes::{
+        AppState,
+        containers::{compose_state::COMPOSE_PROJECT_LABEL, restart::COMPOSE_SERVICE_LABEL},
+    },
+};
+use bollard::{
+    query_parameters::{InspectContainerOptions, ListContainersOptionsBuilder},
+    secret::MountPointTypeEnum,
+};
+use chrono::Utc;
+use cvm_agent_models::health::{ComposeDrift, DriftFinding, EventKind};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+use tokio::{sync::watch, time::sleep};
+use tracing::{info, warn};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The label docker compose sets on containers created by `docker compose run`.
+const COMPOSE_ONE_OFF_LABEL: &str = "com.docker.compose.oneoff";
+
+/// A monitor that checks that the running containers match the measured docker compose.
+///
+/// Any running container that isn't part of the docker compose project, or whose image, command, entrypoint, or bind
+/// mounts differ from its service's definition, is reported, e.g. if a container was started manually.
+pub(crate) struct DriftMonitor {
+    state: Arc<AppState>,
+    compose: DockerCompose,
+}
+
+impl DriftMonitor {
+    pub(crate) fn spawn(state: Arc<AppState>, compose: DockerCompose) -> DriftMonitorStatus {
+        let monitor = Self { state, compose };
+        let (sender, receiver) = watch::channel(None);
+        info!("Spawning compose drift monitor");
+        tokio::spawn(async move {
+            monitor.run(sender).await;
+        });
+        DriftMonitorStatus(receiver)
+    }
+
+    async fn run(self, sender: watch::Sender<Option<ComposeDrift>>) {
+        let mut config = None;
+        let mut last_findings = Vec::new();
+        loop {
+            // The docker compose files can't change so this only needs to be resolved once.
+            if config.is_none() {
+                match self.compose.config().await {
+                    Ok(resolved) => config = Some(resolved),
+                    Err(e) => warn!("Failed to resolve docker compose config: {e:#}"),
+                }
+            }
+            if let Some(config) = &config {
+                match self.observe_containers().await {
+                    Ok(containers) => {
+                        let findings = find_drift(config, &containers);
+                        // Only emit an event when the findings change so we don't keep overwriting others.
+                        if !findings.is_empty() && findings != last_findings {
+                            let containers: BTreeSet<_> = findings.iter().map(|f| f.container.as_str()).collect();
+                            let containers: Vec<_> = containers.into_iter().collect();
+                            let message = format!("Containers diverge from docker compose: {}", containers.join(", "));
+                            warn!("{message}");
+                            self.state.context.event_holder.set(message, EventKind::Error);
+                        }
+                        last_findings = findings.clone();
+                        sender.send_replace(Some(ComposeDrift { checked_at: Utc::now(), findings }));
+                    }
+                    Err(e) => warn!("Failed to inspect containers: {e}"),
+                }
+            }
+            sleep(CHECK_INTERVAL).await;
+        }
+    }
+
+    async fn observe_containers(&self) -> Result<Vec<ObservedContainer>, bollard::errors::Error> {
+        let options = ListContainersOptionsBuilder::new().all(false).build();
+        let mut observed = Vec::new();
+        for container in self.state.docker.list_containers(Some(options)).await? {
+            let Some(id) = container.id else { continue };
+            let details = self.state.docker.inspect_container(&id, None::<InspectContainerOptions>).await?;
+            let config = details.config.unwrap_or_default();
+            let labels = config.labels.unwrap_or_default();
+            let bind_mounts = details
+                .mounts
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|mount| mount.typ == Some(MountPointTypeEnum::BIND))
+                .filter_map(|mount| mount.source.zip(mount.destination))
+                .collect();
+            observed.push(ObservedContainer {
+                name: details.name.unwrap_or(id).trim_start_matches('/').to_string(),
+                project: labels.get(COMPOSE_PROJECT_LABEL).cloned(),
+                service: labels.get(COMPOSE_SERVICE_LABEL).cloned(),
+                one_off: labels.get(COMPOSE_ONE_OFF_LABEL).is_some_and(|value| value.eq_ignore_ascii_case("true")),
+                image: config.image,
+                command: config.cmd,
+                entrypoint: config.entrypoint,
+                bind_mounts,
+            });
+        }
+        Ok(observed)
+    }
+}
+
+/// What was observed about a running container.
+#[derive(Debug, Default)]
+struct ObservedContainer {
+    name: String,
+    project: Option<String>,
+    service: Option<String>,
+    one_off: bool,
+    image: Option<String>,
+    command: Option<Vec<String>>,
+    entrypoint: Option<Vec<String>>,
+    bind_mounts: BTreeSet<(String, String)>,
+}
+
+/// Compare the running containers against the docker compose config.
+fn find_drift(config: &ComposeConfig, containers: &[ObservedContainer]) -> Vec<DriftFinding> {
+    let mut findings = Vec::new();
+    for container in containers {
+        let mut report = |reason: &str| {
+            findings.push(DriftFinding {
+                container: container.name.clone(),
+                service: container.service.clone(),
+                reason: reason.into(),
+            })
+        };
+        let (Some(COMPOSE_PROJECT_NAME), Some(service)) = (container.project.as_deref(), &container.service) else {
+            report("not part of the docker compose project");
+            continue;
+        };
+        let Some(expected) = config.services.get(service) else {
+            report("service is not defined in the docker compose");
+            continue;
+        };
+        for reason in compare_service(expected, container) {
+            report(reason);
+        }
+    }
+    findings
+}
+
+fn compare_service(expected: &ServiceConfig, container: &ObservedContainer) -> Vec<&'static str> {
+    let mut reasons = Vec::new();
+    if let Some(image) = &expected.image
+        && container.image.as_deref().map(normalize_image) != Some(normalize_image(image))
+    {
+        reasons.push("image differs");
+    }
+    // When these aren't set the image's defaults are used, which can't be checked without inspecting the image.
+    // Commands run on demand in runnable services are expected to differ.
+    let command_overridable = container.one_off && expected.runnable();
+    if expected.command.is_some() && !command_overridable && expected.command != container.command {
+        reasons.push("command differs");
+    }
+    if expected.entrypoint.is_some() && expected.entrypoint != container.entrypoint {
+        reasons.push("entrypoint differs");
+    }
+    let bind_mounts: BTreeSet<_> = expected
+        .volumes
+        .iter()
+        .filter(|volume| volume.kind == "bind")
+        .filter_map(|volume| Some((volume.source.clone()?, volume.target.clone())))
+        .collect();
+    if bind_mounts != container.bind_mounts {
+        reasons.push("bind mounts differ");
+    }
+    reasons
+}
+
+/// Normalize an image reference so equivalent ones, like `nginx` and `docker.io/library/nginx:latest`, are equal.
+fn normalize_image(image: &str) -> String {
+    let image = image.strip_prefix("docker.io/").unwrap_or(image);
+    let image = image.strip_prefix("library/").unwrap_or(image);
+    let name = image.rsplit('/').next().unwrap_or(image);
+    if name.contains(':') || name.contains('@') { image.to_string() } else { format!("{image}:latest") }
+}
+
+#[derive(Clone)]
+pub(crate) struct DriftMonitorStatus(watch::Receiver<Option<ComposeDrift>>);
+
+impl DriftMonitorStatus {
+    /// The drift found in the last check, if any was done.
+    pub(crate) fn drift(&self) -> Option<ComposeDrift> {
+        self.0.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::compose::VolumeConfig;
+    use cvm_agent_models::container::RUNNABLE_SERVICE_LABEL;
+    use std::collections::HashMap;
+
+    fn make_config() -> ComposeConfig {
+        let api = ServiceConfig {
+            image: Some("nginx".into()),
+            command: Some(vec!["serve".into()]),
+            volumes: vec![
+                VolumeConfig {
+                    kind: "bind".into(),
+                    source: Some("/media/cvm/files/config".into()),
+                    target: "/config".into(),
+                },
+                VolumeConfig { kind: "volume".into(), source: Some("data".into()), target: "/data".into() },
+            ],
+            ..Default::default()
+        };
+        ComposeConfig { services: HashMap::from([("api".into(), api)]) }
+    }
+
+    fn make_container() -> ObservedContainer {
+        ObservedContainer {
+            name: "cvm-api-1".into(),
+            project: Some(COMPOSE_PROJECT_NAME.into()),
+            service: Some("api".into()),
+            one_off: false,
+            image: Some("docker.io/library/nginx:latest".into()),
+            command: Some(vec!["serve".into()]),
+            entrypoint: Some(vec!["/docker-entrypoint.sh".into()]),
+            bind_mounts: BTreeSet::from([("/media/cvm/files/config".into(), "/config".into())]),
+        }
+    }
+
+    fn reasons(findings: &[DriftFinding]) -> Vec<&str> {
+        findings.iter().map(|f| f.reason.as_str()).collect()
+    }
+
+    #[test]
+    fn no_drift() {
+        let findings = find_drift(&make_config(), &[make_container()]);
+        assert_eq!(findings, vec![]);
+    }
+
+    #[test]
+    fn modified_container() {
+        let container = ObservedContainer {
+            image: Some("nginx:1.25".into()),
+            command: Some(vec!["sh".into()]),
+            bind_mounts: BTreeSet::from([("/".into(), "/host".into())]),
+            ..make_container()
+        };
+        let findings = find_drift(&make_config(), &[container]);
+        assert_eq!(reasons(&findings), &["image differs", "command differs", "bind mounts differ"]);
+        assert_eq!(findings[0].service.as_deref(), Some("api"));
+    }
+
+    #[test]
+    fn one_off_container() {
+        let mut config = make_config();
+        let container = ObservedContainer {
+            name: "cvm-api-run-1a2b3c".into(),
+            one_off: true,
+            command: Some(vec!["backup".into()]),
+            ..make_container()
+        };
+        let findings = find_drift(&config, std::slice::from_ref(&container));
+        assert_eq!(reasons(&findings), &["command differs"]);
+
+        let api = config.services.get_mut("api").expect("no api service");
+        api.labels.insert(RUNNABLE_SERVICE_LABEL.into(), "true".into());
+        let findings = find_drift(&config, &[container]);
+        assert_eq!(findings, vec![]);
+    }
+
+    #[test]
+    fn unknown_containers() {
+        let manual = ObservedContainer { name: "shell".into(), project: None, service: None, ..make_container() };
+        let unknown =
+            ObservedContainer { name: "cvm-miner-1".into(), service: Some("miner".into()), ..make_container() };
+        let findings = find_drift(&make_config(), &[manual, unknown, make_container()]);
+        assert_eq!(
+            reasons(&findings),
+            &["not part of the docker compose project", "service is not defined in the docker compose"]
+        );
+    }
+
+    #[test]
+    fn image_normalization() {
+        assert_eq!(normalize_image("nginx"), "nginx:latest");
+        assert_eq!(normalize_image("docker.io/library/nginx:1.25"), "nginx:1.25");
+        assert_eq!(normalize_image("ghcr.io/foo/bar@sha256:abc"), "ghcr.io/foo/bar@sha256:abc");
+        assert_eq!(normalize_image("localhost:5000/foo"), "localhost:5000/foo:latest");
+    }
+}
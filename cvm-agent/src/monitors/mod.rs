@@ -3,7 +3,10 @@ use cvm_agent_models::health::{EventKind, LastEvent};
 use std::sync::{Arc, Mutex};
 
 pub(crate) mod caddy;
-pub(crate) mod compose;
+pub(crate) mod certificate;
+pub(crate) mod drift;
+pub(crate) mod oom;
+pub(crate) mod time_sync;
 
 #[derive(Clone, Default)]
 pub struct EventHolder(Arc<Mutex<Option<LastEvent>>>);
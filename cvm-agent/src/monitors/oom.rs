@@ -0,0 +1,177 @@
+use crate::routes::AppState;
+use bollard::query_parameters::InspectContainerOptions;
+use chrono::Utc;
+use cvm_agent_models::{health::EventKind, stats::OomKill};
+use std::{collections::VecDeque, io, sync::Arc};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, BufReader},
+    sync::watch,
+};
+use tracing::{info, warn};
+
+const KERNEL_LOG_PATH: &str = "/dev/kmsg";
+const MAX_OOM_KILLS: usize = 20;
+
+/// A monitor that detects processes killed by the kernel's OOM killer by following the kernel log.
+///
+/// The kernel logs an `oom-kill:` line for every process it kills which includes the process' memory cgroup. Docker
+/// places every container in its own cgroup, which lets us tell which container the process was running in.
+pub(crate) struct OomMonitor {
+    state: Arc<AppState>,
+}
+
+impl OomMonitor {
+    pub(crate) fn spawn(state: Arc<AppState>) -> OomMonitorStatus {
+        let monitor = Self { state };
+        let (sender, receiver) = watch::channel(VecDeque::new());
+        info!("Spawning OOM monitor");
+        tokio::spawn(async move {
+            monitor.run(sender).await;
+        });
+        OomMonitorStatus(receiver)
+    }
+
+    async fn run(self, sender: watch::Sender<VecDeque<OomKill>>) {
+        let file = match File::open(KERNEL_LOG_PATH).await {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Failed to open kernel log, OOM kills won't be detected: {e}");
+                return;
+            }
+        };
+        let mut reader = BufReader::new(file);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) => break,
+                Ok(_) => (),
+                // The kernel reports records that were overwritten before we could read them this way.
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => continue,
+                Err(e) => {
+                    warn!("Failed to read kernel log, OOM kills won't be detected: {e}");
+                    break;
+                }
+            }
+            let record = String::from_utf8_lossy(&line);
+            let Some(kill) = parse_oom_kill(&record) else {
+                continue;
+            };
+            let container = match kill.container_id {
+                Some(id) => Some(self.container_name(id).await),
+                None => None,
+            };
+            let message = match &container {
+                Some(container) => format!(
+                    "Container {container} ran out of memory, process {} (pid {}) was killed",
+                    kill.process, kill.pid
+                ),
+                None => format!("CVM ran out of memory, process {} (pid {}) was killed", kill.process, kill.pid),
+            };
+            warn!("{message}");
+            self.state.context.event_holder.set(message, EventKind::Error);
+
+            let kill = OomKill { process: kill.process.into(), pid: kill.pid, container, detected_at: Utc::now() };
+            sender.send_modify(|kills| {
+                if kills.len() == MAX_OOM_KILLS {
+                    kills.pop_front();
+                }
+                kills.push_back(kill);
+            });
+        }
+    }
+
+    async fn container_name(&self, id: &str) -> String {
+        match self.state.docker.inspect_container(id, None::<InspectContainerOptions>).await {
+            Ok(details) => {
+                details.name.map(|name| name.trim_start_matches('/').to_string()).unwrap_or_else(|| id.into())
+            }
+            // The container may have been removed by the time we look it up.
+            Err(_) => id.chars().take(12).collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct ParsedOomKill<'a> {
+    process: &'a str,
+    pid: u32,
+    container_id: Option<&'a str>,
+}
+
+/// Parse a kernel log record like
+/// `oom-kill:constraint=CONSTRAINT_MEMCG,...,task_memcg=/system.slice/docker-<id>.scope,task=python,pid=42,uid=0`.
+fn parse_oom_kill(record: &str) -> Option<ParsedOomKill<'_>> {
+    let (_, fields) = record.split_once("oom-kill:")?;
+    let fields = fields.lines().next().unwrap_or_default();
+    let process = field(fields, "task")?;
+    let pid = field(fields, "pid")?.parse().ok()?;
+    let container_id = field(fields, "task_memcg").and_then(container_id);
+    Some(ParsedOomKill { process, pid, container_id })
+}
+
+fn field<'a>(fields: &'a str, name: &str) -> Option<&'a str> {
+    fields.split(',').find_map(|field| field.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// Get the id of the container a memory cgroup belongs to.
+///
+/// Docker names these `/system.slice/docker-<id>.scope` when using the systemd cgroup driver and `/docker/<id>`
+/// otherwise.
+fn container_id(memcg: &str) -> Option<&str> {
+    let (parent, name) = memcg.rsplit_once('/')?;
+    let id = match name.strip_prefix("docker-").and_then(|name| name.strip_suffix(".scope")) {
+        Some(id) => id,
+        None if parent.ends_with("/docker") || parent == "docker" => name,
+        None => return None,
+    };
+    (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then_some(id)
+}
+
+#[derive(Clone)]
+pub(crate) struct OomMonitorStatus(watch::Receiver<VecDeque<OomKill>>);
+
+impl OomMonitorStatus {
+    /// The most recent OOM kills.
+    pub(crate) fn oom_kills(&self) -> Vec<OomKill> {
+        self.0.borrow().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTAINER_ID: &str = "3f4e8d2a9c1b7e6f5d4c3b2a19087f6e5d4c3b2a19087f6e5d4c3b2a19087f6e";
+
+    #[test]
+    fn container_oom_kill() {
+        let record = format!(
+            "6,1234,5678,-;oom-kill:constraint=CONSTRAINT_MEMCG,nodemask=(null),cpuset=/,mems_allowed=0,\
+            oom_memcg=/system.slice/docker-{CONTAINER_ID}.scope,task_memcg=/system.slice/docker-{CONTAINER_ID}.scope,\
+            task=python3,pid=4242,uid=0\n"
+        );
+        let kill = parse_oom_kill(&record).expect("no kill");
+        assert_eq!(kill, ParsedOomKill { process: "python3", pid: 4242, container_id: Some(CONTAINER_ID) });
+    }
+
+    #[test]
+    fn system_oom_kill() {
+        let record = "6,1234,5678,-;oom-kill:constraint=CONSTRAINT_NONE,nodemask=(null),cpuset=/,mems_allowed=0,\
+            global_oom,task_memcg=/system.slice/dockerd.service,task=dockerd,pid=100,uid=0\n";
+        let kill = parse_oom_kill(record).expect("no kill");
+        assert_eq!(kill, ParsedOomKill { process: "dockerd", pid: 100, container_id: None });
+    }
+
+    #[test]
+    fn unrelated_record() {
+        assert!(parse_oom_kill("6,1234,5678,-;eth0: link up\n").is_none());
+    }
+
+    #[test]
+    fn cgroupfs_container_id() {
+        assert_eq!(container_id(&format!("/docker/{CONTAINER_ID}")), Some(CONTAINER_ID));
+        assert_eq!(container_id("/docker/not-a-container"), None);
+    }
+}
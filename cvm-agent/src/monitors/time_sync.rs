@@ -0,0 +1,124 @@
+use crate::{monitors::EventHolder, roughtime};
+use chrono::{DateTime, TimeDelta, Utc};
+use cvm_agent_models::{bootstrap::TimeSyncConfig, health::EventKind, stats::ClockSkew};
+use std::time::{Duration, Instant};
+use tokio::{sync::watch, time::sleep};
+use tracing::{info, warn};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A monitor that periodically measures the skew of the CVM's clock against a set of Roughtime servers.
+///
+/// The host controls the CVM's clock so it can't be trusted. Roughtime responses are signed by the server so the host
+/// can delay them, which only increases the measurement's uncertainty, but it can't tamper with them.
+pub(crate) struct TimeSyncMonitor {
+    config: TimeSyncConfig,
+    event_holder: EventHolder,
+}
+
+impl TimeSyncMonitor {
+    pub(crate) fn spawn(config: TimeSyncConfig, event_holder: EventHolder) -> TimeSyncStatus {
+        let monitor = Self { config, event_holder };
+        let (sender, receiver) = watch::channel(None);
+        info!("Spawning time sync monitor using {} servers", monitor.config.servers.len());
+        tokio::spawn(async move {
+            monitor.run(sender).await;
+        });
+        TimeSyncStatus(receiver)
+    }
+
+    async fn run(self, sender: watch::Sender<Option<ClockSkew>>) {
+        let max_skew = self.config.max_skew.as_millis() as i64;
+        let mut skew_exceeded = false;
+        loop {
+            let samples = self.sample().await;
+            match aggregate(samples, Utc::now()) {
+                Some(skew) => {
+                    info!("Clock skew is {}ms (±{}ms)", skew.skew_ms, skew.uncertainty_ms);
+                    let exceeded = skew.skew_ms.abs() > max_skew;
+                    // Only emit an event when the threshold is first crossed so we don't keep overwriting other events.
+                    if exceeded && !skew_exceeded {
+                        let message = format!("clock skew is {}ms, above the {max_skew}ms threshold", skew.skew_ms);
+                        warn!("{message}");
+                        self.event_holder.set(message, EventKind::Warning);
+                    }
+                    skew_exceeded = exceeded;
+                    sender.send_replace(Some(skew));
+                }
+                None => warn!("Could not get a trusted time from any server"),
+            }
+            sleep(self.config.interval).await;
+        }
+    }
+
+    async fn sample(&self) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        for server in &self.config.servers {
+            let sent_at = Utc::now();
+            let start = Instant::now();
+            match roughtime::query(&server.address, &server.public_key, REQUEST_TIMEOUT).await {
+                Ok(sample) => {
+                    // Assume the server's midpoint is halfway through the round trip.
+                    let half_round_trip = start.elapsed() / 2;
+                    let local_time = sent_at + TimeDelta::from_std(half_round_trip).unwrap_or_default();
+                    samples.push(Sample {
+                        skew: local_time - sample.midpoint,
+                        uncertainty: sample.radius + half_round_trip,
+                    });
+                }
+                Err(e) => warn!("Failed to query roughtime server {}: {e}", server.address),
+            }
+        }
+        samples
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Sample {
+    skew: TimeDelta,
+    uncertainty: Duration,
+}
+
+/// Aggregate samples into a single clock skew by taking the median one, which tolerates a minority of servers lying.
+fn aggregate(mut samples: Vec<Sample>, now: DateTime<Utc>) -> Option<ClockSkew> {
+    let servers = samples.len();
+    samples.sort_by_key(|s| s.skew);
+    let median = samples.get(servers / 2)?;
+    Some(ClockSkew {
+        skew_ms: median.skew.num_milliseconds(),
+        uncertainty_ms: median.uncertainty.as_millis() as u64,
+        servers,
+        measured_at: now,
+    })
+}
+
+#[derive(Clone)]
+pub(crate) struct TimeSyncStatus(watch::Receiver<Option<ClockSkew>>);
+
+impl TimeSyncStatus {
+    /// The last measured clock skew, if any.
+    pub(crate) fn clock_skew(&self) -> Option<ClockSkew> {
+        self.0.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(skew_ms: i64) -> Sample {
+        Sample { skew: TimeDelta::milliseconds(skew_ms), uncertainty: Duration::from_millis(skew_ms.unsigned_abs()) }
+    }
+
+    #[test]
+    fn aggregate_median() {
+        let now = Utc::now();
+        let skew = aggregate(vec![sample(5000), sample(-20), sample(10)], now).expect("no skew");
+        assert_eq!(skew, ClockSkew { skew_ms: 10, uncertainty_ms: 10, servers: 3, measured_at: now });
+    }
+
+    #[test]
+    fn aggregate_empty() {
+        assert!(aggregate(Vec::new(), Utc::now()).is_none());
+    }
+}
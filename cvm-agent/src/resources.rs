@@ -1,15 +1,20 @@
-use crate::routes::VmType;
+use crate::accelerators::Accelerator;
 use serde::Deserialize;
 
 static CADDYFILE: &str = include_str!("../resources/Caddyfile");
 static DOCKER_COMPOSE: &str = include_str!("../resources/docker-compose.yaml");
-static DOCKER_COMPOSE_DEPLOY: &str = r"
-    deploy:
-      resources:
-        reservations:
-          devices:
-            - driver: nvidia
-              capabilities: [gpu]";
+
+/// The directory the proxy's TLS files are mounted at inside its container.
+const PROXY_TLS_DIR: &str = "/etc/caddy/tls";
+
+/// The name of the file that contains the certificate of the CA the proxy issues its certificates from.
+pub(crate) const CA_CERT_FILE: &str = "ca.pem";
+
+/// The name of the file that contains the private key of the CA the proxy issues its certificates from.
+pub(crate) const CA_KEY_FILE: &str = "ca-key.pem";
+
+/// The name of the file that contains the root CA used to verify an ACME directory.
+pub(crate) const ROOT_CA_FILE: &str = "root-ca.pem";
 
 #[derive(Debug, Deserialize, PartialEq)]
 pub struct ContainerMetadata {
@@ -28,25 +33,118 @@ pub struct Resources {
     pub docker_compose: Vec<u8>,
 }
 
-impl Resources {
-    pub fn render(metadata: &ApplicationMetadata, vm_type: &VmType) -> Self {
-        let container_target = format!("{}:{}", metadata.api.container, metadata.api.port);
-        let caddyfile = CADDYFILE
-            .replace("{NILCC_PROXY_HOSTNAME}", &metadata.hostname)
-            .replace("{NILCC_PROXY_TARGET}", &container_target)
-            .into_bytes();
-        let replacement = match vm_type {
-            VmType::Cpu => "",
-            VmType::Gpu => DOCKER_COMPOSE_DEPLOY,
+/// How the proxy gets its TLS certificate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ProxyTls {
+    /// Request certificates from ZeroSSL using the EAB credentials in the environment.
+    #[default]
+    ZeroSsl,
+
+    /// Issue certificates locally from the CA in the TLS directory.
+    InternalCa,
+
+    /// Request certificates from an ACME directory, optionally trusting the root CA in the TLS directory.
+    Acme { directory_url: String, trusted_root: bool },
+}
+
+impl ProxyTls {
+    fn render(&self) -> String {
+        let issuer = match self {
+            Self::ZeroSsl => Self::render_acme_issuer(&[
+                "dir https://acme.zerossl.com/v2/DV90",
+                "eab {$CADDY_ACME_EAB_KEY_ID} {$CADDY_ACME_EAB_MAC_KEY}",
+            ]),
+            Self::InternalCa => "        issuer internal {\n            ca nilcc\n        }\n".into(),
+            Self::Acme { directory_url, trusted_root } => {
+                let mut options = vec![format!("dir {directory_url}")];
+                if *trusted_root {
+                    options.push(format!("trusted_roots {PROXY_TLS_DIR}/{ROOT_CA_FILE}"));
+                }
+                Self::render_acme_issuer(&options)
+            }
         };
+        format!("    tls {{\n        protocols tls1.2 tls1.3\n{issuer}    }}\n")
+    }
+
+    /// The global options that define the internal CA, if one is used.
+    ///
+    /// The CA's key is only used to sign the intermediate and leaf keys caddy generates itself.
+    fn render_pki(&self) -> String {
+        match self {
+            Self::InternalCa => format!(
+                "    skip_install_trust
+    pki {{
+        ca nilcc {{
+            root {{
+                cert {PROXY_TLS_DIR}/{CA_CERT_FILE}
+                key {PROXY_TLS_DIR}/{CA_KEY_FILE}
+            }}
+        }}
+    }}
+"
+            ),
+            Self::ZeroSsl | Self::Acme { .. } => String::new(),
+        }
+    }
+
+    fn render_acme_issuer<S: AsRef<str>>(options: &[S]) -> String {
+        let mut issuer = String::from("        issuer acme {\n");
+        for option in options.iter().map(AsRef::as_ref).chain(["timeout 5m"]) {
+            issuer.push_str(&format!("            {option}\n"));
+        }
+        issuer.push_str("        }\n");
+        issuer
+    }
+}
+
+/// The proxy's configuration.
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    /// The hostnames to serve.
+    pub hostnames: Vec<String>,
+
+    /// The container and port requests are proxied to.
+    pub target: String,
+
+    /// How the TLS certificate is obtained.
+    pub tls: ProxyTls,
+}
+
+impl ApplicationMetadata {
+    /// The initial proxy configuration.
+    pub fn proxy_config(&self) -> ProxyConfig {
+        ProxyConfig {
+            hostnames: vec![self.hostname.clone()],
+            target: format!("{}:{}", self.api.container, self.api.port),
+            tls: Default::default(),
+        }
+    }
+}
+
+impl Resources {
+    pub fn render(metadata: &ApplicationMetadata, accelerator: Option<&dyn Accelerator>) -> Self {
+        let caddyfile = Self::render_caddyfile(&metadata.proxy_config());
+        let replacement = accelerator.and_then(|accelerator| accelerator.compose_deploy()).unwrap_or_default();
         let docker_compose = DOCKER_COMPOSE.replace("{DOCKER_COMPOSE_DEPLOY}", replacement).into();
         Self { caddyfile, docker_compose }
     }
+
+    /// Render a Caddyfile for the given proxy configuration.
+    pub fn render_caddyfile(config: &ProxyConfig) -> Vec<u8> {
+        let hostnames = config.hostnames.join(", https://");
+        CADDYFILE
+            .replace("{NILCC_PROXY_PKI}", &config.tls.render_pki())
+            .replace("{NILCC_PROXY_HOSTNAME}", &hostnames)
+            .replace("{NILCC_PROXY_TLS}", &config.tls.render())
+            .replace("{NILCC_PROXY_TARGET}", &config.target)
+            .into_bytes()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::accelerators::{AmdInstinctAccelerator, NvidiaAccelerator};
     use regex::bytes::Regex;
     use std::sync::LazyLock;
 
@@ -63,7 +161,7 @@ mod tests {
             hostname: "foo.com".into(),
             api: ContainerMetadata { container: "api".into(), port: 1337 },
         };
-        let caddyfile = Resources::render(&metadata, &VmType::Cpu).caddyfile;
+        let caddyfile = Resources::render(&metadata, None).caddyfile;
         let expected = "{
     servers {
         protocols h1 h2
@@ -80,6 +178,18 @@ https://foo.com {
         }
     }
 
+    log {
+        output file /var/log/caddy/access.log {
+            roll_size 10MiB
+            roll_keep 2
+        }
+        format json
+    }
+
+    handle /nilcc/status {
+      reverse_proxy host.docker.internal:59667
+    }
+
     handle_path /nilcc/* {
       reverse_proxy http://nilcc-attester
     }
@@ -90,13 +200,81 @@ https://foo.com {
         assert_eq!(String::from_utf8_lossy(&caddyfile), expected);
     }
 
+    #[test]
+    fn caddyfile_multiple_hostnames() {
+        let config = ProxyConfig {
+            hostnames: vec!["foo.com".into(), "bar.com".into()],
+            target: "api:1337".into(),
+            tls: Default::default(),
+        };
+        let caddyfile = Resources::render_caddyfile(&config);
+        let caddyfile = String::from_utf8_lossy(&caddyfile);
+        assert!(caddyfile.contains("\nhttps://foo.com, https://bar.com {\n"), "{caddyfile}");
+        assert!(caddyfile.contains("reverse_proxy /* api:1337\n"), "{caddyfile}");
+    }
+
+    #[test]
+    fn caddyfile_internal_ca() {
+        let config = ProxyConfig {
+            hostnames: vec!["foo.internal".into()],
+            target: "api:1337".into(),
+            tls: ProxyTls::InternalCa,
+        };
+        let caddyfile = Resources::render_caddyfile(&config);
+        let caddyfile = String::from_utf8_lossy(&caddyfile);
+        let expected = "
+        protocols h1 h2
+    }
+    skip_install_trust
+    pki {
+        ca nilcc {
+            root {
+                cert /etc/caddy/tls/ca.pem
+                key /etc/caddy/tls/ca-key.pem
+            }
+        }
+    }
+}
+
+https://foo.internal {
+    tls {
+        protocols tls1.2 tls1.3
+        issuer internal {
+            ca nilcc
+        }
+    }
+
+    log {";
+        assert!(caddyfile.contains(expected), "{caddyfile}");
+    }
+
+    #[test]
+    fn caddyfile_internal_acme() {
+        let tls = ProxyTls::Acme { directory_url: "https://acme.internal/directory".into(), trusted_root: true };
+        let config = ProxyConfig { hostnames: vec!["foo.internal".into()], target: "api:1337".into(), tls };
+        let caddyfile = Resources::render_caddyfile(&config);
+        let caddyfile = String::from_utf8_lossy(&caddyfile);
+        let expected = "
+    tls {
+        protocols tls1.2 tls1.3
+        issuer acme {
+            dir https://acme.internal/directory
+            trusted_roots /etc/caddy/tls/root-ca.pem
+            timeout 5m
+        }
+    }
+";
+        assert!(caddyfile.contains(expected), "{caddyfile}");
+        assert!(!caddyfile.contains("eab"), "{caddyfile}");
+    }
+
     #[test]
     fn compose_cpu() {
         let metadata = ApplicationMetadata {
             hostname: "foo.com".into(),
             api: ContainerMetadata { container: "api".into(), port: 1337 },
         };
-        let compose = Resources::render(&metadata, &VmType::Cpu).docker_compose;
+        let compose = Resources::render(&metadata, None).docker_compose;
         let compose = replace_version(&compose);
         let expected = r#"services:
   nilcc-attester:
@@ -105,6 +283,7 @@ https://foo.com {
     privileged: true
     volumes:
       - "/dev/sev-guest:/dev/sev-guest"
+      - "/var/lib/nilcc-boot:/var/lib/nilcc-boot:ro"
     ports:
       - 80
     environment:
@@ -112,6 +291,10 @@ https://foo.com {
       APP__NILCC_VERSION: ${NILCC_VERSION}
       APP__VM_TYPE: ${NILCC_VM_TYPE}
       APP__ATTESTATION_DOMAIN: ${NILCC_DOMAIN}
+      APP__WORKLOAD_ID: ${NILCC_WORKLOAD_ID}
+      APP__AGENT_ID: ${NILCC_AGENT_ID}
+      APP__TOKEN_PUBLIC_KEY: ${NILCC_TOKEN_PUBLIC_KEY}
+      APP__PLATFORM_CLAIMS: ${NILCC_PLATFORM_CLAIMS}
       NO_COLOR: 1
     healthcheck:
       test: ["CMD", "curl", "-f", "http://localhost/health"]
@@ -125,11 +308,15 @@ https://foo.com {
     ports:
       - "80:80"
       - "443:443"
+    extra_hosts:
+      - "host.docker.internal:host-gateway"
     environment:
       CADDY_ACME_EAB_KEY_ID: ${CADDY_ACME_EAB_KEY_ID}
       CADDY_ACME_EAB_MAC_KEY: ${CADDY_ACME_EAB_MAC_KEY}
     volumes:
       - ${CADDY_INPUT_FILE}:/etc/caddy/Caddyfile
+      - ${CADDY_LOGS_DIR}:/var/log/caddy
+      - ${CADDY_TLS_DIR}:/etc/caddy/tls:ro
 "#;
         assert_eq!(String::from_utf8_lossy(&compose), expected);
     }
@@ -140,7 +327,7 @@ https://foo.com {
             hostname: "foo.com".into(),
             api: ContainerMetadata { container: "api".into(), port: 1337 },
         };
-        let compose = Resources::render(&metadata, &VmType::Gpu).docker_compose;
+        let compose = Resources::render(&metadata, Some(&NvidiaAccelerator)).docker_compose;
         let compose = replace_version(&compose);
         let expected = r#"services:
   nilcc-attester:
@@ -149,6 +336,7 @@ https://foo.com {
     privileged: true
     volumes:
       - "/dev/sev-guest:/dev/sev-guest"
+      - "/var/lib/nilcc-boot:/var/lib/nilcc-boot:ro"
     ports:
       - 80
     environment:
@@ -156,6 +344,10 @@ https://foo.com {
       APP__NILCC_VERSION: ${NILCC_VERSION}
       APP__VM_TYPE: ${NILCC_VM_TYPE}
       APP__ATTESTATION_DOMAIN: ${NILCC_DOMAIN}
+      APP__WORKLOAD_ID: ${NILCC_WORKLOAD_ID}
+      APP__AGENT_ID: ${NILCC_AGENT_ID}
+      APP__TOKEN_PUBLIC_KEY: ${NILCC_TOKEN_PUBLIC_KEY}
+      APP__PLATFORM_CLAIMS: ${NILCC_PLATFORM_CLAIMS}
       NO_COLOR: 1
     healthcheck:
       test: ["CMD", "curl", "-f", "http://localhost/health"]
@@ -175,12 +367,27 @@ https://foo.com {
     ports:
       - "80:80"
       - "443:443"
+    extra_hosts:
+      - "host.docker.internal:host-gateway"
     environment:
       CADDY_ACME_EAB_KEY_ID: ${CADDY_ACME_EAB_KEY_ID}
       CADDY_ACME_EAB_MAC_KEY: ${CADDY_ACME_EAB_MAC_KEY}
     volumes:
       - ${CADDY_INPUT_FILE}:/etc/caddy/Caddyfile
+      - ${CADDY_LOGS_DIR}:/var/log/caddy
+      - ${CADDY_TLS_DIR}:/etc/caddy/tls:ro
 "#;
         assert_eq!(String::from_utf8_lossy(&compose), expected);
     }
+
+    #[test]
+    fn compose_amd() {
+        let metadata = ApplicationMetadata {
+            hostname: "foo.com".into(),
+            api: ContainerMetadata { container: "api".into(), port: 1337 },
+        };
+        // AMD devices aren't used by the attester so the compose is the same as in CPU VMs.
+        let compose = Resources::render(&metadata, Some(&AmdInstinctAccelerator)).docker_compose;
+        assert_eq!(compose, Resources::render(&metadata, None).docker_compose);
+    }
 }
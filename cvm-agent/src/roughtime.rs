@@ -0,0 +1,315 @@
+//! A minimal client for the Roughtime protocol.
+//!
+//! Responses are authenticated using the server's long term public key so the time they carry can be trusted even
+//! though the host controls the network the CVM talks through.
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use ring::{
+    digest::{Context as DigestContext, SHA512},
+    signature::{ED25519, UnparsedPublicKey},
+};
+use std::{collections::BTreeMap, io, time::Duration};
+use tokio::{net::UdpSocket, time::timeout};
+
+const REQUEST_SIZE: usize = 1024;
+const NONCE_SIZE: usize = 64;
+const HASH_SIZE: usize = 64;
+const DELEGATION_CONTEXT: &[u8] = b"RoughTime v1 delegation signature--\x00";
+const RESPONSE_CONTEXT: &[u8] = b"RoughTime v1 response signature\x00";
+
+const TAG_SIG: u32 = tag(b"SIG\x00");
+const TAG_NONC: u32 = tag(b"NONC");
+const TAG_DELE: u32 = tag(b"DELE");
+const TAG_PATH: u32 = tag(b"PATH");
+const TAG_RADI: u32 = tag(b"RADI");
+const TAG_PUBK: u32 = tag(b"PUBK");
+const TAG_MIDP: u32 = tag(b"MIDP");
+const TAG_SREP: u32 = tag(b"SREP");
+const TAG_MINT: u32 = tag(b"MINT");
+const TAG_ROOT: u32 = tag(b"ROOT");
+const TAG_CERT: u32 = tag(b"CERT");
+const TAG_MAXT: u32 = tag(b"MAXT");
+const TAG_INDX: u32 = tag(b"INDX");
+const TAG_PAD: u32 = tag(b"PAD\xff");
+
+const fn tag(name: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*name)
+}
+
+/// A verified time sample from a Roughtime server.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TimeSample {
+    /// The time the server reported.
+    pub(crate) midpoint: DateTime<Utc>,
+
+    /// The radius of uncertainty around the midpoint, as reported by the server.
+    pub(crate) radius: Duration,
+}
+
+/// Query a Roughtime server, verifying its response using the given public key.
+pub(crate) async fn query(
+    address: &str,
+    public_key: &[u8],
+    request_timeout: Duration,
+) -> Result<TimeSample, RoughtimeError> {
+    let mut nonce = [0; NONCE_SIZE];
+    rand::rng().fill(&mut nonce[..]);
+    let request = build_request(&nonce);
+
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(address).await?;
+    socket.send(&request).await?;
+    let mut buffer = vec![0; 4096];
+    let length = timeout(request_timeout, socket.recv(&mut buffer)).await.map_err(|_| RoughtimeError::Timeout)??;
+    verify_response(&buffer[..length], &nonce, public_key)
+}
+
+fn build_request(nonce: &[u8; NONCE_SIZE]) -> Vec<u8> {
+    // A header with 2 tags is 16 bytes long; the padding makes the request exactly `REQUEST_SIZE` bytes long.
+    let padding = vec![0; REQUEST_SIZE - 16 - NONCE_SIZE];
+    encode_message(&[(TAG_NONC, nonce.as_slice()), (TAG_PAD, &padding)])
+}
+
+/// Encode a message. Tags must be sorted and values must have a length that's a multiple of 4.
+fn encode_message(fields: &[(u32, &[u8])]) -> Vec<u8> {
+    let mut output = Vec::new();
+    output.extend((fields.len() as u32).to_le_bytes());
+    let mut offset = 0;
+    for (_, value) in &fields[..fields.len().saturating_sub(1)] {
+        offset += value.len() as u32;
+        output.extend(offset.to_le_bytes());
+    }
+    for (tag, _) in fields {
+        output.extend(tag.to_le_bytes());
+    }
+    for (_, value) in fields {
+        output.extend(*value);
+    }
+    output
+}
+
+fn decode_message(input: &[u8]) -> Result<BTreeMap<u32, &[u8]>, RoughtimeError> {
+    let read_u32 = |index: usize| -> Result<u32, RoughtimeError> {
+        let bytes = input.get(index * 4..index * 4 + 4).ok_or(RoughtimeError::Malformed("message too short"))?;
+        Ok(u32::from_le_bytes(bytes.try_into().expect("invalid length")))
+    };
+    let count = read_u32(0)? as usize;
+    if count == 0 {
+        return Ok(BTreeMap::new());
+    }
+    let header_length = count.checked_mul(8).ok_or(RoughtimeError::Malformed("too many tags"))?;
+    let values = input.get(header_length..).ok_or(RoughtimeError::Malformed("message too short"))?;
+    let mut output = BTreeMap::new();
+    let mut start = 0;
+    for index in 0..count {
+        let end = if index + 1 < count { read_u32(1 + index)? as usize } else { values.len() };
+        let tag = read_u32(count + index)?;
+        if end < start || end > values.len() || !end.is_multiple_of(4) {
+            return Err(RoughtimeError::Malformed("invalid offset"));
+        }
+        if output.insert(tag, &values[start..end]).is_some() {
+            return Err(RoughtimeError::Malformed("duplicate tag"));
+        }
+        start = end;
+    }
+    Ok(output)
+}
+
+fn field<'a>(message: &BTreeMap<u32, &'a [u8]>, tag: u32) -> Result<&'a [u8], RoughtimeError> {
+    message.get(&tag).copied().ok_or(RoughtimeError::Malformed("missing tag"))
+}
+
+fn u64_field(message: &BTreeMap<u32, &[u8]>, tag: u32) -> Result<u64, RoughtimeError> {
+    let value = field(message, tag)?.try_into().map_err(|_| RoughtimeError::Malformed("invalid u64 field"))?;
+    Ok(u64::from_le_bytes(value))
+}
+
+fn u32_field(message: &BTreeMap<u32, &[u8]>, tag: u32) -> Result<u32, RoughtimeError> {
+    let value = field(message, tag)?.try_into().map_err(|_| RoughtimeError::Malformed("invalid u32 field"))?;
+    Ok(u32::from_le_bytes(value))
+}
+
+fn verify_signature(public_key: &[u8], context: &[u8], message: &[u8], signature: &[u8]) -> Result<(), RoughtimeError> {
+    let signed = [context, message].concat();
+    UnparsedPublicKey::new(&ED25519, public_key).verify(&signed, signature).map_err(|_| RoughtimeError::Signature)
+}
+
+fn hash(prefix: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let mut context = DigestContext::new(&SHA512);
+    context.update(&[prefix]);
+    for part in parts {
+        context.update(part);
+    }
+    context.finish().as_ref()[..HASH_SIZE].to_vec()
+}
+
+fn verify_response(response: &[u8], nonce: &[u8], public_key: &[u8]) -> Result<TimeSample, RoughtimeError> {
+    let message = decode_message(response)?;
+
+    // The certificate delegates signing responses to an online key, signed with the server's long term key.
+    let cert = decode_message(field(&message, TAG_CERT)?)?;
+    let delegation_bytes = field(&cert, TAG_DELE)?;
+    verify_signature(public_key, DELEGATION_CONTEXT, delegation_bytes, field(&cert, TAG_SIG)?)?;
+    let delegation = decode_message(delegation_bytes)?;
+
+    let signed_response_bytes = field(&message, TAG_SREP)?;
+    verify_signature(
+        field(&delegation, TAG_PUBK)?,
+        RESPONSE_CONTEXT,
+        signed_response_bytes,
+        field(&message, TAG_SIG)?,
+    )?;
+    let signed_response = decode_message(signed_response_bytes)?;
+
+    // Our nonce must be part of the merkle tree whose root was signed.
+    let mut index = u32_field(&message, TAG_INDX)?;
+    let mut current = hash(0, &[nonce]);
+    for node in field(&message, TAG_PATH)?.chunks(HASH_SIZE) {
+        current = match index & 1 {
+            0 => hash(1, &[&current, node]),
+            _ => hash(1, &[node, &current]),
+        };
+        index >>= 1;
+    }
+    if current != field(&signed_response, TAG_ROOT)? {
+        return Err(RoughtimeError::MerkleRoot);
+    }
+
+    let midpoint = u64_field(&signed_response, TAG_MIDP)?;
+    let radius = u32_field(&signed_response, TAG_RADI)?;
+    let (min_time, max_time) = (u64_field(&delegation, TAG_MINT)?, u64_field(&delegation, TAG_MAXT)?);
+    if midpoint < min_time || midpoint > max_time {
+        return Err(RoughtimeError::Delegation);
+    }
+    let midpoint = i64::try_from(midpoint)
+        .ok()
+        .and_then(DateTime::from_timestamp_micros)
+        .ok_or(RoughtimeError::Malformed("invalid midpoint"))?;
+    Ok(TimeSample { midpoint, radius: Duration::from_micros(radius.into()) })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RoughtimeError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("timed out waiting for response")]
+    Timeout,
+
+    #[error("malformed response: {0}")]
+    Malformed(&'static str),
+
+    #[error("invalid signature")]
+    Signature,
+
+    #[error("nonce is not part of the signed merkle tree")]
+    MerkleRoot,
+
+    #[error("response time is outside of the delegation's validity window")]
+    Delegation,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    struct Server {
+        root_key: Ed25519KeyPair,
+        online_key: Ed25519KeyPair,
+    }
+
+    impl Server {
+        fn new() -> Self {
+            let root_key = Ed25519KeyPair::from_seed_unchecked(&[1; 32]).expect("invalid seed");
+            let online_key = Ed25519KeyPair::from_seed_unchecked(&[2; 32]).expect("invalid seed");
+            Self { root_key, online_key }
+        }
+
+        fn public_key(&self) -> Vec<u8> {
+            self.root_key.public_key().as_ref().to_vec()
+        }
+
+        // Builds a response for a batch containing our nonce and a sibling one at index 1.
+        fn respond(&self, nonce: &[u8], midpoint: u64, max_time: u64) -> Vec<u8> {
+            let sibling = hash(0, &[&[9; NONCE_SIZE]]);
+            let root = hash(1, &[&hash(0, &[nonce]), &sibling]);
+            let radius = 1_000_000u32.to_le_bytes();
+            let midpoint = midpoint.to_le_bytes();
+            let signed_response = encode_message(&[(TAG_RADI, &radius), (TAG_MIDP, &midpoint), (TAG_ROOT, &root)]);
+            let response_signature = self.online_key.sign(&[RESPONSE_CONTEXT, &signed_response].concat());
+
+            let (min_time, max_time) = (0u64.to_le_bytes(), max_time.to_le_bytes());
+            let delegation = encode_message(&[
+                (TAG_PUBK, self.online_key.public_key().as_ref()),
+                (TAG_MINT, &min_time),
+                (TAG_MAXT, &max_time),
+            ]);
+            let delegation_signature = self.root_key.sign(&[DELEGATION_CONTEXT, &delegation].concat());
+            let cert = encode_message(&[(TAG_SIG, delegation_signature.as_ref()), (TAG_DELE, &delegation)]);
+
+            let index = 0u32.to_le_bytes();
+            encode_message(&[
+                (TAG_SIG, response_signature.as_ref()),
+                (TAG_PATH, &sibling),
+                (TAG_SREP, &signed_response),
+                (TAG_CERT, &cert),
+                (TAG_INDX, &index),
+            ])
+        }
+    }
+
+    #[test]
+    fn request() {
+        let request = build_request(&[1; NONCE_SIZE]);
+        assert_eq!(request.len(), REQUEST_SIZE);
+
+        let message = decode_message(&request).expect("failed to decode");
+        assert_eq!(message.keys().copied().collect::<Vec<_>>(), &[TAG_NONC, TAG_PAD]);
+        assert_eq!(message[&TAG_NONC], &[1; NONCE_SIZE]);
+    }
+
+    #[test]
+    fn valid_response() {
+        let server = Server::new();
+        let nonce = [3; NONCE_SIZE];
+        let response = server.respond(&nonce, 1_700_000_000_000_000, u64::MAX);
+        let sample = verify_response(&response, &nonce, &server.public_key()).expect("verification failed");
+        assert_eq!(sample.midpoint, DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+        assert_eq!(sample.radius, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn wrong_public_key() {
+        let server = Server::new();
+        let nonce = [3; NONCE_SIZE];
+        let response = server.respond(&nonce, 1_700_000_000_000_000, u64::MAX);
+        let err = verify_response(&response, &nonce, &[0; 32]).expect_err("verification succeeded");
+        assert!(matches!(err, RoughtimeError::Signature), "{err}");
+    }
+
+    #[test]
+    fn wrong_nonce() {
+        let server = Server::new();
+        let response = server.respond(&[3; NONCE_SIZE], 1_700_000_000_000_000, u64::MAX);
+        let err =
+            verify_response(&response, &[4; NONCE_SIZE], &server.public_key()).expect_err("verification succeeded");
+        assert!(matches!(err, RoughtimeError::MerkleRoot), "{err}");
+    }
+
+    #[test]
+    fn expired_delegation() {
+        let server = Server::new();
+        let nonce = [3; NONCE_SIZE];
+        let response = server.respond(&nonce, 1_700_000_000_000_000, 1_600_000_000_000_000);
+        let err = verify_response(&response, &nonce, &server.public_key()).expect_err("verification succeeded");
+        assert!(matches!(err, RoughtimeError::Delegation), "{err}");
+    }
+
+    #[test]
+    fn truncated_message() {
+        let err = decode_message(&[2, 0, 0, 0, 4]).expect_err("decoding succeeded");
+        assert!(matches!(err, RoughtimeError::Malformed(_)), "{err}");
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{
+    bootstrap::compose::{docker_login, registry_name},
+    routes::{ApiError, SharedState},
+};
+use axum::{Json, http::StatusCode};
+use cvm_agent_models::config::DockerCredentialsConfigRequest;
+use tracing::{error, info};
+
+/// Log in to docker registries using new credentials, which are kept around so later image pulls can use them.
+pub(crate) async fn handler(
+    state: SharedState,
+    request: Json<DockerCredentialsConfigRequest>,
+) -> Result<StatusCode, ApiError> {
+    let DockerCredentialsConfigRequest { credentials } = request.0;
+    for credentials in &credentials {
+        let registry = registry_name(credentials);
+        if let Err(e) = docker_login(&state.context.docker_config, credentials).await {
+            error!("Failed to log in to {registry}: {e:#}");
+            let error = ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("{e:#}"), "DOCKER_LOGIN_FAILED");
+            return Err(error.with_detail("registry", registry));
+        }
+    }
+    info!("Updated credentials for {} docker registries", credentials.len());
+    Ok(StatusCode::OK)
+}
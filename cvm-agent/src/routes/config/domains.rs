@@ -0,0 +1,31 @@
+use crate::{monitors::caddy::reload_caddy_config, resources::Resources, routes::SharedState};
+use axum::{Json, http::StatusCode};
+use cvm_agent_models::{bootstrap::BootstrapStep, config::DomainsConfigRequest};
+use tokio::fs;
+use tracing::{error, info};
+
+pub(crate) async fn handler(state: SharedState, request: Json<DomainsConfigRequest>) -> StatusCode {
+    let DomainsConfigRequest { domains } = request.0;
+    let Some(primary_domain) = domains.first().cloned() else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let mut proxy = state.proxy.lock().await;
+    proxy.hostnames = domains.clone();
+    let caddyfile = Resources::render_caddyfile(&proxy);
+    if let Err(e) = fs::write(&state.context.caddy_config, caddyfile).await {
+        error!("Failed to write Caddyfile: {e}");
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    drop(proxy);
+    // If the containers haven't been started yet caddy will pick up the new config when it starts.
+    let containers_started = state.bootstrap.lock().await.status().step > BootstrapStep::StartContainers;
+    if containers_started && let Err(e) = reload_caddy_config().await {
+        error!("Failed to reload caddy config: {e:#}");
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    info!("Serving domains {domains:?}");
+    if let Some(handle) = &*state.heartbeat_handle.lock().await {
+        handle.set_domain(primary_domain).await;
+    }
+    StatusCode::OK
+}
@@ -1 +1,3 @@
+pub(crate) mod docker_credentials;
+pub(crate) mod domains;
 pub(crate) mod heartbeats;
@@ -0,0 +1,209 @@
+use crate::{
+    bootstrap::compose::{COMPOSE_PROJECT_NAME, DockerCompose},
+    routes::{SharedState, containers::restart::COMPOSE_SERVICE_LABEL},
+};
+use axum::{Json, http::StatusCode};
+use bollard::{
+    query_parameters::{InspectContainerOptions, ListContainersOptionsBuilder},
+    secret::{ContainerStateStatusEnum, HealthStatusEnum},
+};
+use cvm_agent_models::{
+    container::{ComposeServiceState, ComposeStateResponse, ServiceState},
+    health::EventKind,
+};
+use std::collections::{BTreeMap, HashMap};
+use tracing::error;
+
+/// The label docker compose sets on containers to indicate the project they belong to.
+pub(crate) const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+
+/// What was observed about a single container.
+struct ObservedContainer {
+    service: String,
+    state: ServiceState,
+    restart_count: u64,
+    health: Option<HealthStatusEnum>,
+    error: Option<String>,
+}
+
+pub(crate) async fn handler(state: SharedState) -> Result<Json<ComposeStateResponse>, StatusCode> {
+    let desired = DockerCompose::services(&state.context).await.map_err(|e| {
+        error!("Failed to list docker compose services: {e:#}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let containers = observe_containers(&state).await.map_err(|e| {
+        error!("Failed to inspect containers: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let services = aggregate(desired, &state.context.jobs, containers);
+    let unhealthy = HealthStatusEnum::UNHEALTHY.to_string();
+    let converged = services.iter().all(|s| s.desired_state == s.actual_state && s.health.as_ref() != Some(&unhealthy));
+    let last_error = state.context.event_holder.get().filter(|event| event.kind == EventKind::Error);
+    Ok(Json(ComposeStateResponse { converged, services, last_error }))
+}
+
+async fn observe_containers(state: &SharedState) -> Result<Vec<ObservedContainer>, bollard::errors::Error> {
+    let filters = HashMap::from([("label", vec![format!("{COMPOSE_PROJECT_LABEL}={COMPOSE_PROJECT_NAME}")])]);
+    let options = ListContainersOptionsBuilder::new().all(true).filters(&filters).build();
+    let mut observed = Vec::new();
+    for container in state.docker.list_containers(Some(options)).await? {
+        let Some(service) = container.labels.as_ref().and_then(|labels| labels.get(COMPOSE_SERVICE_LABEL)).cloned()
+        else {
+            continue;
+        };
+        let Some(id) = container.id else { continue };
+        let details = state.docker.inspect_container(&id, None::<InspectContainerOptions>).await?;
+        let container_state = details.state.unwrap_or_default();
+        let observed_state = match container_state.status {
+            Some(ContainerStateStatusEnum::RUNNING) => ServiceState::Running,
+            Some(ContainerStateStatusEnum::RESTARTING) => ServiceState::Restarting,
+            _ => ServiceState::Stopped,
+        };
+        let error = match (container_state.error.filter(|e| !e.is_empty()), container_state.exit_code) {
+            (Some(error), _) => Some(error),
+            (None, Some(code)) if code != 0 && observed_state != ServiceState::Running => {
+                Some(format!("exited with code {code}"))
+            }
+            _ => None,
+        };
+        observed.push(ObservedContainer {
+            service,
+            state: observed_state,
+            restart_count: details.restart_count.unwrap_or_default().try_into().unwrap_or_default(),
+            health: container_state.health.and_then(|h| h.status).filter(|s| *s != HealthStatusEnum::EMPTY),
+            error,
+        });
+    }
+    Ok(observed)
+}
+
+/// Aggregate the containers for every service, including services without containers and containers whose services
+/// are no longer defined.
+///
+/// One-shot jobs are expected to be stopped once they run to completion.
+fn aggregate(desired: Vec<String>, jobs: &[String], containers: Vec<ObservedContainer>) -> Vec<ComposeServiceState> {
+    let mut services: BTreeMap<_, _> = desired
+        .into_iter()
+        .map(|service| {
+            let state = if jobs.contains(&service) { ServiceState::Stopped } else { ServiceState::Running };
+            (service, (state, Vec::new()))
+        })
+        .collect();
+    for container in containers {
+        services.entry(container.service.clone()).or_insert((ServiceState::Stopped, Vec::new())).1.push(container);
+    }
+    services
+        .into_iter()
+        .map(|(service, (desired_state, containers))| {
+            let actual_state = if containers.is_empty() {
+                ServiceState::Missing
+            } else if containers.iter().any(|c| c.state == ServiceState::Restarting) {
+                ServiceState::Restarting
+            } else if containers.iter().all(|c| c.state == ServiceState::Running) {
+                ServiceState::Running
+            } else {
+                ServiceState::Stopped
+            };
+            // Report the worst health across the containers.
+            let health = containers
+                .iter()
+                .filter_map(|c| c.health.as_ref())
+                .max_by_key(|health| match health {
+                    HealthStatusEnum::UNHEALTHY => 2,
+                    HealthStatusEnum::STARTING => 1,
+                    _ => 0,
+                })
+                .map(|health| health.to_string());
+            ComposeServiceState {
+                service,
+                desired_state,
+                actual_state,
+                restart_count: containers.iter().map(|c| c.restart_count).sum(),
+                health,
+                error: containers.iter().find_map(|c| c.error.clone()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(service: &str, state: ServiceState) -> ObservedContainer {
+        ObservedContainer { service: service.into(), state, restart_count: 0, health: None, error: None }
+    }
+
+    #[test]
+    fn aggregate_services() {
+        let desired = vec!["api".into(), "db".into(), "migrate".into(), "worker".into(), "nilcc-proxy".into()];
+        let containers = vec![
+            ObservedContainer {
+                restart_count: 2,
+                health: Some(HealthStatusEnum::HEALTHY),
+                ..container("api", ServiceState::Running)
+            },
+            ObservedContainer {
+                restart_count: 1,
+                health: Some(HealthStatusEnum::UNHEALTHY),
+                ..container("api", ServiceState::Running)
+            },
+            ObservedContainer { error: Some("exited with code 1".into()), ..container("db", ServiceState::Stopped) },
+            container("migrate", ServiceState::Stopped),
+            container("nilcc-proxy", ServiceState::Running),
+            container("old", ServiceState::Running),
+        ];
+        let services = aggregate(desired, &["migrate".into()], containers);
+        let expected = vec![
+            ComposeServiceState {
+                service: "api".into(),
+                desired_state: ServiceState::Running,
+                actual_state: ServiceState::Running,
+                restart_count: 3,
+                health: Some("unhealthy".into()),
+                error: None,
+            },
+            ComposeServiceState {
+                service: "db".into(),
+                desired_state: ServiceState::Running,
+                actual_state: ServiceState::Stopped,
+                restart_count: 0,
+                health: None,
+                error: Some("exited with code 1".into()),
+            },
+            ComposeServiceState {
+                service: "migrate".into(),
+                desired_state: ServiceState::Stopped,
+                actual_state: ServiceState::Stopped,
+                restart_count: 0,
+                health: None,
+                error: None,
+            },
+            ComposeServiceState {
+                service: "nilcc-proxy".into(),
+                desired_state: ServiceState::Running,
+                actual_state: ServiceState::Running,
+                restart_count: 0,
+                health: None,
+                error: None,
+            },
+            ComposeServiceState {
+                service: "old".into(),
+                desired_state: ServiceState::Stopped,
+                actual_state: ServiceState::Running,
+                restart_count: 0,
+                health: None,
+                error: None,
+            },
+            ComposeServiceState {
+                service: "worker".into(),
+                desired_state: ServiceState::Running,
+                actual_state: ServiceState::Missing,
+                restart_count: 0,
+                health: None,
+                error: None,
+            },
+        ];
+        assert_eq!(services, expected);
+    }
+}
@@ -1,15 +1,41 @@
-use crate::routes::SharedState;
+use crate::{
+    encryption::maybe_encrypt,
+    routes::{ApiError, SharedState},
+};
 use axum::{Json, extract::Query, http::StatusCode};
 use axum_valid::Valid;
-use bollard::query_parameters::{InspectContainerOptionsBuilder, LogsOptionsBuilder};
-use cvm_agent_models::logs::{ContainerLogsRequest, ContainerLogsResponse, OutputStream};
+use bollard::{
+    Docker,
+    query_parameters::{InspectContainerOptionsBuilder, LogsOptionsBuilder},
+};
+use cvm_agent_models::{
+    encryption::MaybeEncrypted,
+    logs::{ContainerLogsRequest, ContainerLogsResponse, OutputStream},
+};
 use futures::StreamExt;
 
 pub(crate) async fn handler(
     state: SharedState,
     request: Valid<Query<ContainerLogsRequest>>,
-) -> Result<Json<ContainerLogsResponse>, StatusCode> {
+) -> Result<Json<MaybeEncrypted<ContainerLogsResponse>>, ApiError> {
     let ContainerLogsRequest { container, tail, stream, max_lines } = request.0.0;
+    if state.docker.inspect_container(&container, Some(InspectContainerOptionsBuilder::new().build())).await.is_err() {
+        let error = ApiError::new(StatusCode::NOT_FOUND, "container not found", "CONTAINER_NOT_FOUND");
+        return Err(error.with_detail("container", container));
+    }
+    let lines = container_logs(&state.docker, &container, tail, stream, max_lines).await?;
+    let response = maybe_encrypt(state.context.log_encryption_key.as_deref(), ContainerLogsResponse { lines })?;
+    Ok(Json(response))
+}
+
+/// Read up to `max_lines` log lines from a container's output stream.
+pub(crate) async fn container_logs(
+    docker: &Docker,
+    container: &str,
+    tail: bool,
+    stream: OutputStream,
+    max_lines: usize,
+) -> Result<Vec<String>, StatusCode> {
     let mut builder = LogsOptionsBuilder::new();
     if tail {
         builder = builder.tail(&max_lines.to_string());
@@ -19,15 +45,11 @@ pub(crate) async fn handler(
         OutputStream::Stderr => builder.stderr(true),
     };
 
-    if state.docker.inspect_container(&container, Some(InspectContainerOptionsBuilder::new().build())).await.is_err() {
-        return Err(StatusCode::NOT_FOUND);
-    }
-
     let mut lines = Vec::new();
-    let mut stream = state.docker.logs(&container, Some(builder.build())).take(max_lines);
+    let mut stream = docker.logs(container, Some(builder.build())).take(max_lines);
     while let Some(output) = stream.next().await {
         let output = output.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
         lines.push(String::from_utf8_lossy(&output.into_bytes()).trim().to_string());
     }
-    Ok(Json(ContainerLogsResponse { lines }))
+    Ok(lines)
 }
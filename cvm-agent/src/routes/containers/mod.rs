@@ -1,2 +1,7 @@
+pub(crate) mod compose_state;
 pub(crate) mod list;
 pub(crate) mod logs;
+pub(crate) mod port_forward;
+pub(crate) mod restart;
+pub(crate) mod run;
+pub(crate) mod stats;
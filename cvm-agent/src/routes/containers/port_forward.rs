@@ -0,0 +1,181 @@
+use crate::{
+    bootstrap::compose::COMPOSE_PROJECT_NAME,
+    routes::{
+        ApiError, SharedState,
+        containers::{compose_state::COMPOSE_PROJECT_LABEL, restart::COMPOSE_SERVICE_LABEL},
+    },
+};
+use axum::{
+    extract::{Query, Request},
+    http::{
+        HeaderValue, StatusCode,
+        header::{CONNECTION, UPGRADE},
+    },
+    response::{IntoResponse, Response},
+};
+use axum_valid::Valid;
+use bollard::{query_parameters::InspectContainerOptions, secret::ContainerInspectResponse};
+use cvm_agent_models::container::{PORT_FORWARD_LABEL, PORT_FORWARD_PROTOCOL, PortForwardRequest};
+use hyper_util::rt::TokioIo;
+use std::net::{IpAddr, Ipv4Addr};
+use tokio::{io::copy_bidirectional, net::TcpStream};
+use tracing::{error, info, warn};
+
+/// The docker compose services the CVM runs itself, which connections can never be forwarded to.
+const SYSTEM_SERVICES: &[&str] = &["nilcc-attester", "nilcc-proxy"];
+
+/// Forward a connection to a port in a container.
+///
+/// The request must ask for the connection to be upgraded to the port forwarding protocol. Once it is, everything sent
+/// over it is forwarded as is to the container's port and vice versa. Only ports that the container's service lists in
+/// its port forwarding label can be forwarded to.
+pub(crate) async fn handler(
+    state: SharedState,
+    request: Valid<Query<PortForwardRequest>>,
+    mut http_request: Request,
+) -> Result<Response, ApiError> {
+    let PortForwardRequest { container, port } = request.0.0;
+    let upgrade = http_request.headers().get(UPGRADE).and_then(|h| h.to_str().ok());
+    if !upgrade.is_some_and(|u| u.eq_ignore_ascii_case(PORT_FORWARD_PROTOCOL)) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    let Ok(details) = state.docker.inspect_container(&container, None::<InspectContainerOptions>).await else {
+        let error = ApiError::new(StatusCode::NOT_FOUND, "container not found", "CONTAINER_NOT_FOUND");
+        return Err(error.with_detail("container", container));
+    };
+    if !forwardable(&details, port) {
+        warn!("Rejecting port forwarding to port {port} in container {container}");
+        let error = ApiError::new(StatusCode::FORBIDDEN, "port can't be forwarded to", "PORT_NOT_FORWARDABLE");
+        return Err(error.with_detail("container", container).with_detail("port", port));
+    }
+    let Some(address) = container_address(details) else {
+        warn!("Container {container} has no address to forward connections to");
+        let error = ApiError::new(StatusCode::PRECONDITION_FAILED, "container has no address", "CONTAINER_UNREACHABLE");
+        return Err(error.with_detail("container", container));
+    };
+    let mut stream = TcpStream::connect((address, port)).await.map_err(|e| {
+        warn!("Failed to connect to {container} on {address}:{port}: {e}");
+        ApiError::new(StatusCode::BAD_GATEWAY, "could not connect to container port", "CONTAINER_UNREACHABLE")
+            .with_detail("container", &container)
+            .with_detail("port", port)
+    })?;
+
+    info!("Forwarding connection to {container} on {address}:{port}");
+    let on_upgrade = hyper::upgrade::on(&mut http_request);
+    tokio::spawn(async move {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                error!("Failed to upgrade port forwarding connection: {e}");
+                return;
+            }
+        };
+        match copy_bidirectional(&mut TokioIo::new(upgraded), &mut stream).await {
+            Ok((sent, received)) => {
+                info!("Port forwarding to {container} closed, sent {sent}, received {received} bytes")
+            }
+            Err(e) => warn!("Port forwarding to {container} failed: {e}"),
+        }
+    });
+    let headers =
+        [(CONNECTION, HeaderValue::from_static("upgrade")), (UPGRADE, HeaderValue::from_static(PORT_FORWARD_PROTOCOL))];
+    Ok((StatusCode::SWITCHING_PROTOCOLS, headers).into_response())
+}
+
+/// Check whether a container belongs to a workload service that allows forwarding connections to a port.
+fn forwardable(details: &ContainerInspectResponse, port: u16) -> bool {
+    let Some(labels) = details.config.as_ref().and_then(|config| config.labels.as_ref()) else {
+        return false;
+    };
+    if labels.get(COMPOSE_PROJECT_LABEL).map(String::as_str) != Some(COMPOSE_PROJECT_NAME) {
+        return false;
+    }
+    if labels.get(COMPOSE_SERVICE_LABEL).is_none_or(|service| SYSTEM_SERVICES.contains(&service.as_str())) {
+        return false;
+    }
+    let Some(ports) = labels.get(PORT_FORWARD_LABEL) else {
+        return false;
+    };
+    ports.split(',').any(|allowed| allowed.trim().parse() == Ok(port))
+}
+
+/// Find the address a container can be reached at.
+fn container_address(details: ContainerInspectResponse) -> Option<IpAddr> {
+    let network_mode = details.host_config.and_then(|c| c.network_mode);
+    if network_mode.as_deref() == Some("host") {
+        return Some(Ipv4Addr::LOCALHOST.into());
+    }
+    let networks = details.network_settings?.networks?;
+    let mut addresses = networks.into_values().filter_map(|n| n.ip_address);
+    addresses.find_map(|address| address.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::secret::{ContainerConfig, EndpointSettings, HostConfig, NetworkSettings};
+    use std::collections::HashMap;
+
+    #[test]
+    fn addresses() {
+        let details = |network_mode: &str, ip_address: &str| ContainerInspectResponse {
+            host_config: Some(HostConfig { network_mode: Some(network_mode.into()), ..Default::default() }),
+            network_settings: Some(NetworkSettings {
+                networks: Some(HashMap::from([(
+                    "nilcc".into(),
+                    EndpointSettings { ip_address: Some(ip_address.into()), ..Default::default() },
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            container_address(details("bridge", "172.18.0.2")),
+            Some("172.18.0.2".parse().expect("invalid address"))
+        );
+        assert_eq!(container_address(details("host", "")), Some(Ipv4Addr::LOCALHOST.into()));
+        assert_eq!(container_address(details("none", "")), None);
+    }
+
+    fn labeled(labels: &[(&str, &str)]) -> ContainerInspectResponse {
+        let labels = labels.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        ContainerInspectResponse {
+            config: Some(ContainerConfig { labels: Some(labels), ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn forwardable_ports() {
+        let cases = [
+            ("db", "5432", 5432, true),
+            ("db", "8080, 5432", 5432, true),
+            ("db", "8080", 5432, false),
+            ("db", "foo", 5432, false),
+            ("nilcc-attester", "80", 80, false),
+            ("nilcc-proxy", "443", 443, false),
+        ];
+        for (service, ports, port, expected) in cases {
+            let details = labeled(&[
+                (COMPOSE_PROJECT_LABEL, COMPOSE_PROJECT_NAME),
+                (COMPOSE_SERVICE_LABEL, service),
+                (PORT_FORWARD_LABEL, ports),
+            ]);
+            assert_eq!(forwardable(&details, port), expected, "{service} {ports} {port}");
+        }
+    }
+
+    #[test]
+    fn not_forwardable() {
+        // Not opted in.
+        let details = labeled(&[(COMPOSE_PROJECT_LABEL, COMPOSE_PROJECT_NAME), (COMPOSE_SERVICE_LABEL, "db")]);
+        assert!(!forwardable(&details, 5432));
+
+        // Not part of the workload.
+        let details =
+            labeled(&[(COMPOSE_PROJECT_LABEL, "other"), (COMPOSE_SERVICE_LABEL, "db"), (PORT_FORWARD_LABEL, "5432")]);
+        assert!(!forwardable(&details, 5432));
+
+        assert!(!forwardable(&ContainerInspectResponse::default(), 5432));
+    }
+}
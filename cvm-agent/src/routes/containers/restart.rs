@@ -0,0 +1,37 @@
+use crate::routes::{ApiError, SharedState};
+use axum::{Json, http::StatusCode};
+use axum_valid::Valid;
+use bollard::query_parameters::{ListContainersOptionsBuilder, RestartContainerOptionsBuilder};
+use cvm_agent_models::container::RestartContainerRequest;
+use std::collections::HashMap;
+use tracing::{error, info};
+
+/// The label docker compose sets on containers to indicate the service they belong to.
+pub(crate) const COMPOSE_SERVICE_LABEL: &str = "com.docker.compose.service";
+
+pub(crate) async fn handler(state: SharedState, request: Valid<Json<RestartContainerRequest>>) -> Result<(), ApiError> {
+    let RestartContainerRequest { service } = request.0.0;
+    let filters = HashMap::from([("label", vec![format!("{COMPOSE_SERVICE_LABEL}={service}")])]);
+    let options = ListContainersOptionsBuilder::new().all(true).filters(&filters).build();
+    let containers = match state.docker.list_containers(Some(options)).await {
+        Ok(containers) => containers,
+        Err(e) => {
+            error!("Failed to list containers: {e}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    };
+    let ids: Vec<_> = containers.into_iter().filter_map(|c| c.id).collect();
+    if ids.is_empty() {
+        let error = ApiError::new(StatusCode::NOT_FOUND, "no containers found for service", "CONTAINER_NOT_FOUND");
+        return Err(error.with_detail("service", service));
+    }
+    for id in ids {
+        info!("Restarting container {id} for service {service}");
+        let options = RestartContainerOptionsBuilder::new().build();
+        if let Err(e) = state.docker.restart_container(&id, Some(options)).await {
+            error!("Failed to restart container {id}: {e}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use crate::routes::{ApiError, SharedState};
+use axum::{
+    Json,
+    body::Body,
+    http::{StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use axum_valid::Valid;
+use cvm_agent_models::{
+    container::{RunContainerEvent, RunContainerRequest},
+    logs::OutputStream,
+};
+use futures::{Stream, StreamExt};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Child,
+    sync::mpsc::{Sender, channel},
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{error, info, warn};
+
+/// Run a command in a new container for a docker compose service.
+///
+/// Only services that opt in via their labels can be run. The command's output is streamed back as newline delimited
+/// JSON events, the last of which contains its exit code.
+pub(crate) async fn handler(
+    state: SharedState,
+    request: Valid<Json<RunContainerRequest>>,
+) -> Result<Response, ApiError> {
+    let RunContainerRequest { service, command } = request.0.0;
+    let Some(compose) = state.compose.lock().await.clone() else {
+        return Err(ApiError::new(StatusCode::PRECONDITION_FAILED, "CVM is not bootstrapped", "NOT_BOOTSTRAPPED"));
+    };
+    let config = compose.config().await.map_err(|e| {
+        error!("Failed to resolve docker compose config: {e:#}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    match config.services.get(&service) {
+        Some(config) if config.runnable() => (),
+        Some(_) => {
+            let error = ApiError::new(StatusCode::FORBIDDEN, "service is not runnable", "SERVICE_NOT_RUNNABLE");
+            return Err(error.with_detail("service", service));
+        }
+        None => {
+            let error = ApiError::new(StatusCode::NOT_FOUND, "service not found", "SERVICE_NOT_FOUND");
+            return Err(error.with_detail("service", service));
+        }
+    };
+    info!("Running command {command:?} for service {service}");
+    let child = compose.run(&service, &command).map_err(|e| {
+        error!("Failed to run command for service {service}: {e:#}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let body = Body::from_stream(output_events(child).map(encode_event));
+    Ok(([(CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+/// Stream the lines a process writes followed by its exit code.
+///
+/// The process is left running if the stream is dropped, so a client going away doesn't interrupt a command halfway.
+fn output_events(mut child: Child) -> impl Stream<Item = RunContainerEvent> {
+    let (sender, receiver) = channel(64);
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    tokio::spawn(async move {
+        tokio::join!(
+            forward_lines(stdout, OutputStream::Stdout, &sender),
+            forward_lines(stderr, OutputStream::Stderr, &sender)
+        );
+        let exit_code = match child.wait().await {
+            Ok(status) => status.code(),
+            Err(e) => {
+                warn!("Failed to wait for command: {e}");
+                None
+            }
+        };
+        info!("Command exited with code {exit_code:?}");
+        let _ = sender.send(RunContainerEvent::Exited { exit_code }).await;
+    });
+    ReceiverStream::new(receiver)
+}
+
+async fn forward_lines<R>(reader: Option<R>, stream: OutputStream, sender: &Sender<RunContainerEvent>)
+where
+    R: AsyncRead + Unpin,
+{
+    let Some(reader) = reader else {
+        return;
+    };
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                // Keep reading even if the client went away so the process doesn't block writing its output.
+                let _ = sender.send(RunContainerEvent::Output { stream, line }).await;
+            }
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Failed to read command output: {e}");
+                break;
+            }
+        }
+    }
+}
+
+fn encode_event(event: RunContainerEvent) -> Result<Vec<u8>, serde_json::Error> {
+    let mut line = serde_json::to_vec(&event)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::compose::make_docker_compose;
+    use std::{fs, os::unix::fs::PermissionsExt};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn command_output() {
+        // Run the exact command `docker compose run` would be invoked with, but against a fake `docker` binary.
+        let bin = tempdir().expect("failed to create tempdir");
+        let docker = bin.path().join("docker");
+        fs::write(&docker, "#!/bin/sh\necho hello; echo oops >&2; exit 3\n").expect("failed to write script");
+        fs::set_permissions(&docker, fs::Permissions::from_mode(0o755)).expect("failed to set permissions");
+        let iso_mount = tempdir().expect("failed to create tempdir");
+        let compose = make_docker_compose(iso_mount.path());
+        let child =
+            compose.run_command("api", &["migrate".into()]).env("PATH", bin.path()).spawn().expect("failed to spawn");
+        let lines: Vec<_> = output_events(child)
+            .map(|event| String::from_utf8(encode_event(event).expect("failed to encode")).expect("invalid utf8"))
+            .collect()
+            .await;
+        assert_eq!(lines.len(), 3);
+        assert!(lines.contains(&"{\"type\":\"output\",\"stream\":\"stdout\",\"line\":\"hello\"}\n".to_string()));
+        assert!(lines.contains(&"{\"type\":\"output\",\"stream\":\"stderr\",\"line\":\"oops\"}\n".to_string()));
+        assert_eq!(lines[2], "{\"type\":\"exited\",\"exitCode\":3}\n");
+    }
+}
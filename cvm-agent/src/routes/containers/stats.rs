@@ -0,0 +1,179 @@
+use crate::{
+    encryption::maybe_encrypt,
+    routes::{SharedState, containers::restart::COMPOSE_SERVICE_LABEL},
+};
+use axum::{Json, http::StatusCode};
+use bollard::{
+    Docker,
+    query_parameters::{ListContainersOptionsBuilder, StatsOptionsBuilder},
+    secret::{ContainerCpuStats, ContainerStatsResponse},
+};
+use cvm_agent_models::{
+    encryption::MaybeEncrypted,
+    stats::{ContainerStats, ContainersStatsResponse},
+};
+use futures::{StreamExt, future::join_all};
+use tracing::{error, warn};
+
+/// The memory stats keys holding the page cache that can be reclaimed, for cgroups v2 and v1 respectively.
+const INACTIVE_FILE_KEYS: &[&str] = &["inactive_file", "total_inactive_file"];
+
+pub(crate) async fn handler(state: SharedState) -> Result<Json<MaybeEncrypted<ContainersStatsResponse>>, StatusCode> {
+    let options = ListContainersOptionsBuilder::new().build();
+    let containers = state.docker.list_containers(Some(options)).await.map_err(|e| {
+        error!("Failed to list containers: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    // Docker samples CPU usage over a second so query every container at once.
+    let futures = containers.into_iter().filter_map(|c| {
+        let id = c.id?;
+        // get rid of the `/` at the beginning of container names
+        let name = c
+            .names
+            .unwrap_or_default()
+            .first()
+            .map(|n| n.trim_start_matches('/').to_string())
+            .unwrap_or_else(|| id.clone());
+        let service = c.labels.unwrap_or_default().remove(COMPOSE_SERVICE_LABEL);
+        Some(container_stats(&state.docker, id, name, service))
+    });
+    let containers = join_all(futures).await.into_iter().flatten().collect();
+    let response = ContainersStatsResponse { containers };
+    let response = maybe_encrypt(state.context.log_encryption_key.as_deref(), response)?;
+    Ok(Json(response))
+}
+
+async fn container_stats(docker: &Docker, id: String, name: String, service: Option<String>) -> Option<ContainerStats> {
+    let options = StatsOptionsBuilder::new().stream(false).build();
+    match docker.stats(&id, Some(options)).next().await? {
+        Ok(stats) => Some(summarize_stats(name, service, &stats)),
+        Err(e) => {
+            // The container may have stopped since it was listed.
+            warn!("Failed to get stats for container {name}: {e}");
+            None
+        }
+    }
+}
+
+fn summarize_stats(name: String, service: Option<String>, stats: &ContainerStatsResponse) -> ContainerStats {
+    let memory = stats.memory_stats.as_ref();
+    let usage = memory.and_then(|m| m.usage).unwrap_or_default();
+    let inactive_file = memory
+        .and_then(|m| m.stats.as_ref())
+        .and_then(|stats| INACTIVE_FILE_KEYS.iter().find_map(|key| stats.get(*key)))
+        .copied()
+        .unwrap_or_default();
+    let networks = stats.networks.iter().flat_map(|networks| networks.values());
+    let (network_rx, network_tx) = networks.fold((0, 0), |(rx, tx), network| {
+        (rx + network.rx_bytes.unwrap_or_default(), tx + network.tx_bytes.unwrap_or_default())
+    });
+    let block_io = stats.blkio_stats.as_ref().and_then(|s| s.io_service_bytes_recursive.as_ref());
+    let block_bytes = |op: &str| -> u64 {
+        block_io
+            .iter()
+            .flat_map(|entries| entries.iter())
+            .filter(|entry| entry.op.as_deref().is_some_and(|entry_op| entry_op.eq_ignore_ascii_case(op)))
+            .map(|entry| entry.value.unwrap_or_default())
+            .sum()
+    };
+    ContainerStats {
+        name,
+        service,
+        cpu_usage: cpu_usage(stats.cpu_stats.as_ref(), stats.precpu_stats.as_ref()),
+        memory_used: usage.saturating_sub(inactive_file),
+        memory_limit: memory.and_then(|m| m.limit).unwrap_or_default(),
+        network_rx,
+        network_tx,
+        block_read: block_bytes("read"),
+        block_write: block_bytes("write"),
+    }
+}
+
+/// Compute the CPU usage between two samples the same way `docker stats` does.
+fn cpu_usage(current: Option<&ContainerCpuStats>, previous: Option<&ContainerCpuStats>) -> f64 {
+    let total_usage = |stats: Option<&ContainerCpuStats>| -> u64 {
+        stats.and_then(|s| s.cpu_usage.as_ref()).and_then(|u| u.total_usage).unwrap_or_default()
+    };
+    let system_usage =
+        |stats: Option<&ContainerCpuStats>| -> u64 { stats.and_then(|s| s.system_cpu_usage).unwrap_or_default() };
+    let container_delta = total_usage(current).saturating_sub(total_usage(previous));
+    let system_delta = system_usage(current).saturating_sub(system_usage(previous));
+    if system_delta == 0 {
+        return 0.0;
+    }
+    let cpus = current.and_then(|s| s.online_cpus).unwrap_or(1);
+    container_delta as f64 / system_delta as f64 * cpus as f64 * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::secret::{
+        ContainerBlkioStatEntry, ContainerBlkioStats, ContainerCpuUsage, ContainerMemoryStats, ContainerNetworkStats,
+    };
+    use std::collections::HashMap;
+
+    fn make_cpu_stats(total_usage: u64, system_cpu_usage: u64) -> ContainerCpuStats {
+        ContainerCpuStats {
+            cpu_usage: Some(ContainerCpuUsage { total_usage: Some(total_usage), ..Default::default() }),
+            system_cpu_usage: Some(system_cpu_usage),
+            online_cpus: Some(4),
+            ..Default::default()
+        }
+    }
+
+    fn make_block_entry(op: &str, value: u64) -> ContainerBlkioStatEntry {
+        ContainerBlkioStatEntry { op: Some(op.into()), value: Some(value), ..Default::default() }
+    }
+
+    fn make_network_stats(rx_bytes: u64, tx_bytes: u64) -> ContainerNetworkStats {
+        ContainerNetworkStats { rx_bytes: Some(rx_bytes), tx_bytes: Some(tx_bytes), ..Default::default() }
+    }
+
+    #[test]
+    fn summarize() {
+        let stats = ContainerStatsResponse {
+            cpu_stats: Some(make_cpu_stats(3_500, 20_000)),
+            precpu_stats: Some(make_cpu_stats(1_000, 10_000)),
+            memory_stats: Some(ContainerMemoryStats {
+                usage: Some(1000),
+                limit: Some(4000),
+                stats: Some(HashMap::from([("inactive_file".into(), 200)])),
+                ..Default::default()
+            }),
+            networks: Some(HashMap::from([
+                ("eth0".into(), make_network_stats(10, 20)),
+                ("eth1".into(), make_network_stats(1, 2)),
+            ])),
+            blkio_stats: Some(ContainerBlkioStats {
+                io_service_bytes_recursive: Some(vec![
+                    make_block_entry("read", 100),
+                    make_block_entry("write", 50),
+                    make_block_entry("Read", 5),
+                ]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let summary = summarize_stats("api-1".into(), Some("api".into()), &stats);
+        let expected = ContainerStats {
+            name: "api-1".into(),
+            service: Some("api".into()),
+            cpu_usage: 100.0,
+            memory_used: 800,
+            memory_limit: 4000,
+            network_rx: 11,
+            network_tx: 22,
+            block_read: 105,
+            block_write: 50,
+        };
+        assert_eq!(summary, expected);
+    }
+
+    #[test]
+    fn idle_cpu_usage() {
+        let current = make_cpu_stats(1_500, 20_000);
+        assert_eq!(cpu_usage(Some(&current), Some(&current)), 0.0);
+        assert_eq!(cpu_usage(None, None), 0.0);
+    }
+}
@@ -4,13 +4,23 @@ use axum::Json;
 use cvm_agent_models::health::HealthResponse;
 
 pub(crate) async fn handler(state: SharedState) -> Json<HealthResponse> {
-    let (https, bootstrapped) = match &*state.system_state.lock().await {
-        SystemState::WaitingBootstrap => (false, false),
-        SystemState::Starting => (false, true),
-        SystemState::Ready => (true, true),
+    let https = matches!(&*state.system_state.lock().await, SystemState::Ready);
+    let (bootstrapped, bootstrap) = {
+        let bootstrap = state.bootstrap.lock().await;
+        (bootstrap.is_bootstrapped(), bootstrap.status().clone())
     };
 
     let last_event = state.context.event_holder.get();
-    let response = HealthResponse { https, bootstrapped, last_event };
+    let certificate = state.certificate_status.lock().await.as_ref().and_then(|status| status.certificate_status());
+    let drift = state.drift_status.lock().await.as_ref().and_then(|status| status.drift());
+    let response = HealthResponse {
+        https,
+        bootstrapped,
+        last_event,
+        bootstrap: Some(bootstrap),
+        certificate,
+        paused: false,
+        drift,
+    };
     Json(response)
 }
@@ -0,0 +1,45 @@
+use crate::routes::SharedState;
+use attestation_report::identity_token::{IDENTITY_TOKEN_ISSUER, IdentityTokenClaims};
+use axum::{Json, http::StatusCode};
+use axum_valid::Valid;
+use chrono::{TimeDelta, Utc};
+use cvm_agent_models::identity::{IdentityTokenRequest, IdentityTokenResponse};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// The maximum lifetime of an identity token.
+const MAX_TOKEN_TTL: Duration = Duration::from_secs(3600);
+
+/// Mint an identity token for the workload running in this CVM.
+///
+/// This is only served on the listener that's reachable from within the CVM.
+pub(crate) async fn handler(
+    state: SharedState,
+    request: Valid<Json<IdentityTokenRequest>>,
+) -> Result<Json<IdentityTokenResponse>, StatusCode> {
+    let IdentityTokenRequest { audience, ttl_seconds } = request.0.0;
+    if ttl_seconds.is_zero() || ttl_seconds > MAX_TOKEN_TTL {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Without an identity the attester doesn't bind the token key into reports so tokens couldn't be verified.
+    let Some(identity) = state.workload_identity.lock().await.clone() else {
+        return Err(StatusCode::PRECONDITION_FAILED);
+    };
+    let now = Utc::now();
+    let expires_at = now + TimeDelta::seconds(ttl_seconds.as_secs() as i64);
+    let claims = IdentityTokenClaims {
+        iss: IDENTITY_TOKEN_ISSUER.into(),
+        sub: identity.workload_id,
+        agent_id: identity.agent_id,
+        aud: audience,
+        iat: now.timestamp() as u64,
+        nbf: None,
+        exp: expires_at.timestamp() as u64,
+    };
+    let token = state.identity_signer.mint(&claims).map_err(|e| {
+        error!("Failed to mint identity token: {e:#}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    info!("Minted identity token for audience {} valid for {}s", claims.aud, ttl_seconds.as_secs());
+    Ok(Json(IdentityTokenResponse { token, expires_at }))
+}
@@ -0,0 +1,16 @@
+use crate::{bootstrap::jobs::job_status, routes::SharedState};
+use axum::{Json, http::StatusCode};
+use cvm_agent_models::jobs::JobStatus;
+use tracing::error;
+
+pub(crate) async fn handler(state: SharedState) -> Result<Json<Vec<JobStatus>>, StatusCode> {
+    let mut statuses = Vec::new();
+    for job in &state.context.jobs {
+        let status = job_status(&state.docker, job).await.map_err(|e| {
+            error!("Failed to get status for job {job}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        statuses.push(status);
+    }
+    Ok(Json(statuses))
+}
@@ -0,0 +1,32 @@
+use crate::{
+    bootstrap::jobs::job_container,
+    encryption::maybe_encrypt,
+    routes::{ApiError, SharedState, containers::logs::container_logs},
+};
+use axum::{Json, extract::Query, http::StatusCode};
+use axum_valid::Valid;
+use cvm_agent_models::{encryption::MaybeEncrypted, jobs::JobLogsRequest, logs::ContainerLogsResponse};
+use tracing::error;
+
+pub(crate) async fn handler(
+    state: SharedState,
+    request: Valid<Query<JobLogsRequest>>,
+) -> Result<Json<MaybeEncrypted<ContainerLogsResponse>>, ApiError> {
+    let JobLogsRequest { job, tail, stream, max_lines } = request.0.0;
+    let not_found =
+        |job: String| ApiError::new(StatusCode::NOT_FOUND, "job not found", "JOB_NOT_FOUND").with_detail("job", job);
+    if !state.context.jobs.contains(&job) {
+        return Err(not_found(job));
+    }
+    let container = job_container(&state.docker, &job).await.map_err(|e| {
+        error!("Failed to find container for job {job}: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    // the job exists but hasn't been started yet
+    let Some(container) = container else {
+        return Err(not_found(job));
+    };
+    let lines = container_logs(&state.docker, &container, tail, stream, max_lines).await?;
+    let response = maybe_encrypt(state.context.log_encryption_key.as_deref(), ContainerLogsResponse { lines })?;
+    Ok(Json(response))
+}
@@ -1,10 +1,28 @@
-use crate::{heartbeat::HeartbeatEmitterHandle, monitors::EventHolder};
+use crate::{
+    accelerators::Accelerator,
+    auth::require_api_token,
+    bootstrap::{BootstrapState, compose::DockerCompose},
+    heartbeat::HeartbeatEmitterHandle,
+    identity::IdentityTokenSigner,
+    monitors::{
+        EventHolder, caddy::CaddyStatus, certificate::CertificateMonitorStatus, drift::DriftMonitorStatus,
+        oom::OomMonitorStatus, time_sync::TimeSyncStatus,
+    },
+    resources::ProxyConfig,
+    routes::system::tls::ObservedFingerprint,
+};
+use attestation_report::report_data::WorkloadIdentity;
 use axum::{
-    Router,
+    Json, Router,
     extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
 };
 use bollard::Docker;
+use cvm_agent_models::errors::ErrorResponse;
+use rate_limit::RateLimiter;
 use serde::{Deserialize, Serialize};
 use std::{fmt, path::PathBuf, sync::Arc};
 use tokio::sync::Mutex;
@@ -12,6 +30,9 @@ use tokio::sync::Mutex;
 pub(crate) mod config;
 pub(crate) mod containers;
 pub(crate) mod health;
+pub(crate) mod identity;
+pub(crate) mod jobs;
+pub(crate) mod public;
 pub(crate) mod system;
 
 #[derive(Default)]
@@ -29,6 +50,8 @@ pub struct BootstrapContext {
     pub user_docker_compose_sha256: [u8; 32],
     pub external_files: PathBuf,
     pub caddy_config: PathBuf,
+    pub proxy_logs: PathBuf,
+    pub proxy_tls: PathBuf,
     pub docker_config: PathBuf,
     pub version: String,
     pub vm_type: VmType,
@@ -36,6 +59,10 @@ pub struct BootstrapContext {
     pub event_holder: EventHolder,
     pub cpus: u64,
     pub gpus: u64,
+    pub accelerator: Option<&'static dyn Accelerator>,
+    pub log_encryption_key: Option<Vec<u8>>,
+    pub jobs: Vec<String>,
+    pub token_public_key: String,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -60,21 +87,96 @@ pub struct AppState {
     pub system_state: Arc<Mutex<SystemState>>,
     pub log_path: PathBuf,
     pub heartbeat_handle: Arc<Mutex<Option<HeartbeatEmitterHandle>>>,
+    pub bootstrap: Mutex<BootstrapState>,
+    pub caddy_status: Mutex<Option<CaddyStatus>>,
+    pub proxy: Mutex<ProxyConfig>,
+    pub time_sync_status: Mutex<Option<TimeSyncStatus>>,
+    pub certificate_status: Mutex<Option<CertificateMonitorStatus>>,
+    pub drift_status: Mutex<Option<DriftMonitorStatus>>,
+    pub compose: Mutex<Option<DockerCompose>>,
+    pub oom_status: Mutex<Option<OomMonitorStatus>>,
+    pub tls_fingerprint: Mutex<Option<ObservedFingerprint>>,
+    pub status_rate_limiter: RateLimiter,
+    pub identity_signer: IdentityTokenSigner,
+    pub workload_identity: Mutex<Option<WorkloadIdentity>>,
+    pub api_token: Option<String>,
 }
 
 pub(crate) type SharedState = State<Arc<AppState>>;
 
+/// An error returned by an endpoint, along with the status code to return it with.
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    status: StatusCode,
+    response: ErrorResponse,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, message: impl Into<String>, error_code: &str) -> Self {
+        // Errors caused by the server rather than the request may go away on their own.
+        let retryable = status.is_server_error();
+        let response = ErrorResponse {
+            message: message.into(),
+            error_code: error_code.into(),
+            retryable,
+            details: Default::default(),
+        };
+        Self { status, response }
+    }
+
+    /// Add a detail about the error.
+    pub(crate) fn with_detail(mut self, key: &str, value: impl ToString) -> Self {
+        self.response.details.insert(key.into(), value.to_string());
+        self
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let reason = status.canonical_reason().unwrap_or("Unknown error");
+        Self::new(status, reason.to_lowercase(), &reason.to_uppercase().replace(' ', "_"))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.response)).into_response()
+    }
+}
+
 pub fn create_router(state: Arc<AppState>) -> Router {
     Router::new().nest(
         "/api/v1",
         Router::new()
             .route("/health", get(health::handler))
+            .route("/config/docker-credentials", post(config::docker_credentials::handler))
+            .route("/config/domains", post(config::domains::handler))
             .route("/config/heartbeats", post(config::heartbeats::handler))
             .route("/containers/logs", get(containers::logs::handler))
+            .route("/containers/compose-state", get(containers::compose_state::handler))
             .route("/containers/list", get(containers::list::handler))
+            .route("/containers/restart", post(containers::restart::handler))
+            .route("/containers/run", post(containers::run::handler))
+            .route("/containers/stats", get(containers::stats::handler))
+            .route("/containers/port-forward", get(containers::port_forward::handler))
+            .route("/jobs/list", get(jobs::list::handler))
+            .route("/jobs/logs", get(jobs::logs::handler))
             .route("/system/bootstrap", post(system::bootstrap::handler))
+            .route("/system/heartbeat", get(system::heartbeat::handler))
             .route("/system/logs", get(system::logs::handler))
             .route("/system/stats", get(system::stats::handler))
+            .route("/system/tls", get(system::tls::handler))
+            .route_layer(middleware::from_fn_with_state(state.clone(), require_api_token))
             .with_state(state),
     )
 }
+
+/// Create the router for the endpoints the proxy exposes publicly.
+pub fn create_public_router(state: Arc<AppState>) -> Router {
+    Router::new().route("/nilcc/status", get(public::status::handler)).with_state(state)
+}
+
+/// Create the router for the endpoints that are only reachable from within the CVM.
+pub fn create_identity_router(state: Arc<AppState>) -> Router {
+    Router::new().route("/api/v1/identity/token", post(identity::token::handler)).with_state(state)
+}
@@ -0,0 +1,68 @@
+use crate::routes::{SharedState, containers::restart::COMPOSE_SERVICE_LABEL};
+use axum::{
+    Json,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bollard::{Docker, query_parameters::ListContainersOptionsBuilder};
+use cvm_agent_models::status::PublicStatusResponse;
+use rate_limit::RateLimiter;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+/// The compose service that serves attestation reports.
+const ATTESTER_SERVICE: &str = "nilcc-attester";
+
+/// The header the proxy sets to the address of the client making the request.
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Build the rate limiter for the status endpoint, which allows 30 requests per minute for each client.
+pub(crate) fn rate_limiter() -> RateLimiter {
+    RateLimiter::new(30, Duration::from_secs(60))
+}
+
+/// Get the coarse-grained status of this CVM.
+///
+/// This is served publicly through the proxy so it must not expose anything beyond whether things are working.
+pub(crate) async fn handler(state: SharedState, headers: HeaderMap) -> Response {
+    // The proxy doesn't trust any incoming forwarded headers so this is always the actual client's address.
+    let client = headers.get(FORWARDED_FOR_HEADER).and_then(|h| h.to_str().ok()).unwrap_or_default();
+    if !state.status_rate_limiter.allow(client, Instant::now()) {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+
+    let bootstrapped = state.bootstrap.lock().await.is_bootstrapped();
+    let (app_responding, attestation_available) = if bootstrapped {
+        let target = state.proxy.lock().await.target.clone();
+        let app_service = target.rsplit_once(':').map(|(service, _)| service).unwrap_or(&target);
+        (service_running(&state.docker, app_service).await, service_running(&state.docker, ATTESTER_SERVICE).await)
+    } else {
+        (false, false)
+    };
+    let status = if bootstrapped && app_responding && attestation_available {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(PublicStatusResponse { bootstrapped, app_responding, attestation_available })).into_response()
+}
+
+/// Check whether a compose service has a running container that isn't unhealthy.
+async fn service_running(docker: &Docker, service: &str) -> bool {
+    let filters = HashMap::from([
+        ("label", vec![format!("{COMPOSE_SERVICE_LABEL}={service}")]),
+        ("status", vec!["running".into()]),
+        ("health", vec!["healthy".into(), "none".into()]),
+    ]);
+    let options = ListContainersOptionsBuilder::new().filters(&filters).build();
+    match docker.list_containers(Some(options)).await {
+        Ok(containers) => !containers.is_empty(),
+        Err(e) => {
+            error!("Failed to list containers for service {service}: {e}");
+            false
+        }
+    }
+}
@@ -1,54 +1,49 @@
 use crate::{
-    heartbeat::{HeartbeatEmitter, HeartbeatEmitterArgs},
-    monitors::{caddy::CaddyMonitor, compose::ComposeMonitor},
+    bootstrap::Bootstrapper,
+    monitors::{caddy::CaddyMonitor, certificate::CertificateMonitor, oom::OomMonitor, time_sync::TimeSyncMonitor},
     routes::{SharedState, SystemState},
 };
+use attestation_report::report_data::WorkloadIdentity;
 use axum::{Json, http::StatusCode};
 use cvm_agent_models::bootstrap::BootstrapRequest;
-use tracing::{error, info};
+use tracing::info;
 
 pub(crate) async fn handler(state: SharedState, request: Json<BootstrapRequest>) -> StatusCode {
-    let mut system_state = state.system_state.lock().await;
-    if !matches!(&*system_state, SystemState::WaitingBootstrap) {
+    let mut bootstrap = state.bootstrap.lock().await;
+    if !bootstrap.can_start() {
         return StatusCode::OK;
     }
-    let request = request.0;
-    let ctx = state.context.clone();
-    let event_holder = ctx.event_holder.clone();
-    *system_state = SystemState::Starting;
+    info!("Starting bootstrap at step {:?}", bootstrap.status().step);
+    bootstrap.start().await;
 
-    let caddy_status = CaddyMonitor::spawn(state.docker.clone(), state.system_state.clone(), event_holder);
-
-    match (request.workload_id, request.heartbeat) {
-        (Some(workload_id), Some(heartbeat)) => {
-            let args = HeartbeatEmitterArgs {
-                workload_id,
-                workload_domain: request.domain.clone(),
-                rpc_endpoint: heartbeat.rpc_endpoint,
-                heartbeat_contract_address: heartbeat.heartbeat_contract_address,
-                token_contract_address: heartbeat.token_contract_address,
-                wallet_private_key: heartbeat.wallet_private_key,
-                nilcc_version: ctx.version.clone(),
-                docker_compose_hash: ctx.user_docker_compose_sha256,
-                tick_interval: heartbeat.interval,
-                measurement_hash_url: heartbeat.measurement_hash_url,
-                cpu_count: ctx.cpus,
-                gpu_count: ctx.gpus,
-                caddy_status,
-            };
-            match HeartbeatEmitter::spawn(args).await {
-                Ok(handle) => {
-                    *state.heartbeat_handle.lock().await = Some(handle);
-                }
-                Err(e) => {
-                    error!("Failed setting up heartbeat emitter: {e}");
-                    return StatusCode::INTERNAL_SERVER_ERROR;
-                }
-            }
+    {
+        let mut system_state = state.system_state.lock().await;
+        if matches!(&*system_state, SystemState::WaitingBootstrap) {
+            *system_state = SystemState::Starting;
         }
-        _ => info!("Not emitting heartbeats since the necessary config wasn't provided"),
-    };
-
-    ComposeMonitor::spawn(ctx, request.acme, request.docker, request.domain);
+    }
+    // The caddy monitor only needs to be spawned once, even if bootstrap is resumed.
+    let caddy_status = state
+        .caddy_status
+        .lock()
+        .await
+        .get_or_insert_with(|| {
+            CaddyMonitor::spawn(state.docker.clone(), state.system_state.clone(), state.context.event_holder.clone())
+        })
+        .clone();
+    state.certificate_status.lock().await.get_or_insert_with(|| CertificateMonitor::spawn(state.0.clone()));
+    state.oom_status.lock().await.get_or_insert_with(|| OomMonitor::spawn(state.0.clone()));
+    if let Some(config) = &request.time_sync {
+        let mut time_sync_status = state.time_sync_status.lock().await;
+        if time_sync_status.is_none() {
+            *time_sync_status = Some(TimeSyncMonitor::spawn(config.clone(), state.context.event_holder.clone()));
+        }
+    }
+    // The attester binds the identity into reports so identity tokens can only be minted when there is one.
+    if let (Some(workload_id), Some(agent_id)) = (request.workload_id, request.agent_id) {
+        let identity = WorkloadIdentity { workload_id: workload_id.to_string(), agent_id: agent_id.to_string() };
+        *state.workload_identity.lock().await = Some(identity);
+    }
+    Bootstrapper::spawn(state.0.clone(), request.0, caddy_status);
     StatusCode::OK
 }
@@ -0,0 +1,10 @@
+use crate::routes::SharedState;
+use axum::{Json, http::StatusCode};
+use cvm_agent_models::heartbeat::HeartbeatStatusResponse;
+
+pub(crate) async fn handler(state: SharedState) -> Result<Json<HeartbeatStatusResponse>, StatusCode> {
+    match &*state.heartbeat_handle.lock().await {
+        Some(handle) => Ok(Json(handle.status())),
+        None => Err(StatusCode::PRECONDITION_FAILED),
+    }
+}
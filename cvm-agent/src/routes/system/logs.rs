@@ -1,36 +1,45 @@
-use crate::routes::SharedState;
+use crate::{encryption::maybe_encrypt, routes::SharedState};
 use axum::{Json, extract::Query, http::StatusCode};
 use axum_valid::Valid;
-use cvm_agent_models::logs::{SystemLogsRequest, SystemLogsResponse, SystemLogsSource};
-use std::io;
+use cvm_agent_models::{
+    encryption::MaybeEncrypted,
+    logs::{SystemLogsRequest, SystemLogsResponse, SystemLogsSource},
+};
+use std::{io, path::Path, process::Stdio};
 use tokio::io::{AsyncBufRead, AsyncBufReadExt};
-use tokio::{fs::File, io::BufReader};
+use tokio::{fs::File, io::BufReader, process::Command};
 use tokio_stream::StreamExt;
 use tokio_stream::wrappers::LinesStream;
 use tracing::error;
 
+/// The name of the access log file the proxy writes to within the proxy logs directory.
+const PROXY_ACCESS_LOG: &str = "access.log";
+
+/// The systemd unit the docker daemon runs as.
+const DOCKER_UNIT: &str = "docker.service";
+
 pub(crate) async fn handler(
     state: SharedState,
     request: Valid<Query<SystemLogsRequest>>,
-) -> Result<Json<SystemLogsResponse>, StatusCode> {
+) -> Result<Json<MaybeEncrypted<SystemLogsResponse>>, StatusCode> {
     let SystemLogsRequest { source, tail, max_lines } = request.0.0;
-    let path = match source {
-        SystemLogsSource::CvmAgent => &state.log_path,
-    };
-    let reader = match File::open(path).await {
-        Ok(file) => file,
-        Err(e) => {
-            error!("Failed to open log file {}: {e}", path.display());
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let result = match source {
+        SystemLogsSource::CvmAgent => read_file(&state.log_path, tail, max_lines).await,
+        SystemLogsSource::Proxy => {
+            match read_file(&state.context.proxy_logs.join(PROXY_ACCESS_LOG), tail, max_lines).await {
+                // The proxy only creates its log file once it's up and running
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+                result => result,
+            }
         }
-    };
-    let reader = BufReader::new(reader);
-    let result = match tail {
-        true => fetch_tail_lines(reader, max_lines).await,
-        false => fetch_head_lines(reader, max_lines).await,
+        SystemLogsSource::Kernel => read_journal(&["--dmesg"], tail, max_lines).await,
+        SystemLogsSource::DockerDaemon => read_journal(&["--unit", DOCKER_UNIT], tail, max_lines).await,
     };
     match result {
-        Ok(lines) => Ok(Json(SystemLogsResponse { lines })),
+        Ok(lines) => {
+            let response = maybe_encrypt(state.context.log_encryption_key.as_deref(), SystemLogsResponse { lines })?;
+            Ok(Json(response))
+        }
         Err(e) => {
             error!("Failed to read logs: {e}");
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -38,6 +47,31 @@ pub(crate) async fn handler(
     }
 }
 
+async fn read_file(path: &Path, tail: bool, max_lines: usize) -> io::Result<Vec<String>> {
+    let reader = BufReader::new(File::open(path).await?);
+    fetch_lines(reader, tail, max_lines).await
+}
+
+/// Read the logs systemd's journal holds for the CVM, filtered using the given `journalctl` arguments.
+async fn read_journal(filter: &[&str], tail: bool, max_lines: usize) -> io::Result<Vec<String>> {
+    let mut command = Command::new("journalctl");
+    command.args(["--no-pager", "--output", "short-iso"]).args(filter);
+    if tail {
+        command.arg("--lines").arg(max_lines.to_string());
+    }
+    // journalctl is killed when dropped in case we stop reading before it's done writing.
+    let mut child = command.stdout(Stdio::piped()).stderr(Stdio::null()).kill_on_drop(true).spawn()?;
+    let stdout = child.stdout.take().ok_or_else(|| io::Error::other("no stdout"))?;
+    fetch_lines(BufReader::new(stdout), tail, max_lines).await
+}
+
+async fn fetch_lines<R: AsyncBufRead + Unpin>(reader: R, tail: bool, max_lines: usize) -> io::Result<Vec<String>> {
+    match tail {
+        true => fetch_tail_lines(reader, max_lines).await,
+        false => fetch_head_lines(reader, max_lines).await,
+    }
+}
+
 async fn fetch_head_lines<R: AsyncBufRead + Unpin>(reader: R, max_lines: usize) -> io::Result<Vec<String>> {
     let mut lines = Vec::new();
     let mut reader = LinesStream::new(reader.lines()).take(max_lines);
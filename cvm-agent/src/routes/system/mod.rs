@@ -1,3 +1,5 @@
 pub(crate) mod bootstrap;
+pub(crate) mod heartbeat;
 pub(crate) mod logs;
 pub(crate) mod stats;
+pub(crate) mod tls;
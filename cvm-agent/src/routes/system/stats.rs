@@ -1,11 +1,22 @@
+use crate::{bootstrap::logging::container_logs_usage, encryption::maybe_encrypt, routes::SharedState};
+use anyhow::{Context, bail};
 use axum::{Json, http::StatusCode};
-use cvm_agent_models::stats::{CpuStats, DiskStats, MemoryStats, SystemStatsResponse};
+use cvm_agent_models::{
+    encryption::MaybeEncrypted,
+    stats::{
+        CpuStats, DiskStats, GpuStats, GpusStats, MemoryPressure, MemoryStats, PressureStats, SystemStatsResponse,
+    },
+};
 use sysinfo::{
     CpuRefreshKind, DiskRefreshKind, Disks, MINIMUM_CPU_UPDATE_INTERVAL, MemoryRefreshKind, RefreshKind, System,
 };
-use tokio::time::sleep;
+use tokio::{fs, process::Command, time::sleep};
+use tracing::warn;
+
+const MIB: u64 = 1024 * 1024;
+const MEMORY_PRESSURE_PATH: &str = "/proc/pressure/memory";
 
-pub(crate) async fn handler() -> Result<Json<SystemStatsResponse>, StatusCode> {
+pub(crate) async fn handler(state: SharedState) -> Result<Json<MaybeEncrypted<SystemStatsResponse>>, StatusCode> {
     let specifics = RefreshKind::nothing()
         .with_memory(MemoryRefreshKind::nothing().with_ram())
         .with_cpu(CpuRefreshKind::nothing().with_cpu_usage().with_frequency());
@@ -14,9 +25,18 @@ pub(crate) async fn handler() -> Result<Json<SystemStatsResponse>, StatusCode> {
     stats.refresh_cpu_usage();
 
     let cpus = cpu_stats(&stats);
-    let memory = memory_stats(&stats);
+    let memory = memory_stats(&stats).await;
     let disks = disk_stats();
-    let response = SystemStatsResponse { memory, cpus, disks };
+    let log_disk_usage = container_logs_usage().await;
+    let clock_skew = state.time_sync_status.lock().await.as_ref().and_then(|status| status.clock_skew());
+    // Stats are only available for GPUs that can be queried via `nvidia-smi`.
+    let gpus = match state.context.accelerator {
+        Some(accelerator) if state.context.gpus > 0 && accelerator.nvidia_smi() => gpu_stats().await,
+        _ => None,
+    };
+    let oom_kills = state.oom_status.lock().await.as_ref().map(|status| status.oom_kills()).unwrap_or_default();
+    let response = SystemStatsResponse { memory, cpus, disks, log_disk_usage, clock_skew, gpus, oom_kills };
+    let response = maybe_encrypt(state.context.log_encryption_key.as_deref(), response)?;
     Ok(Json(response))
 }
 
@@ -28,8 +48,34 @@ fn cpu_stats(stats: &System) -> Vec<CpuStats> {
         .collect()
 }
 
-fn memory_stats(stats: &System) -> MemoryStats {
-    MemoryStats { total: stats.total_memory(), used: stats.used_memory() }
+async fn memory_stats(stats: &System) -> MemoryStats {
+    // Pressure stall information is only available if the kernel was built with it.
+    let pressure = match fs::read_to_string(MEMORY_PRESSURE_PATH).await {
+        Ok(contents) => parse_memory_pressure(&contents),
+        Err(_) => None,
+    };
+    MemoryStats { total: stats.total_memory(), used: stats.used_memory(), pressure }
+}
+
+fn parse_memory_pressure(contents: &str) -> Option<MemoryPressure> {
+    let mut some = None;
+    let mut full = None;
+    for line in contents.lines() {
+        let (kind, fields) = line.split_once(' ')?;
+        let field = |name| fields.split(' ').find_map(|field: &str| field.strip_prefix(name)?.strip_prefix('='));
+        let stats = PressureStats {
+            avg10: field("avg10")?.parse().ok()?,
+            avg60: field("avg60")?.parse().ok()?,
+            avg300: field("avg300")?.parse().ok()?,
+            total: field("total")?.parse().ok()?,
+        };
+        match kind {
+            "some" => some = Some(stats),
+            "full" => full = Some(stats),
+            _ => (),
+        }
+    }
+    Some(MemoryPressure { some: some?, full: full? })
 }
 
 fn disk_stats() -> Vec<DiskStats> {
@@ -47,3 +93,104 @@ fn disk_stats() -> Vec<DiskStats> {
         })
         .collect()
 }
+
+async fn gpu_stats() -> Option<GpusStats> {
+    let devices = match query_gpus().await {
+        Ok(devices) => devices,
+        Err(e) => {
+            warn!("Failed to get GPU stats: {e:#}");
+            return None;
+        }
+    };
+    let cc_mode = match run_nvidia_smi(&["conf-compute", "-f"]).await {
+        Ok(output) => parse_cc_mode(&output),
+        Err(e) => {
+            warn!("Failed to get GPU confidential computing mode: {e:#}");
+            None
+        }
+    };
+    Some(GpusStats { cc_mode, devices })
+}
+
+async fn query_gpus() -> anyhow::Result<Vec<GpuStats>> {
+    let output = run_nvidia_smi(&[
+        "--query-gpu=index,name,utilization.gpu,memory.used,memory.total,temperature.gpu",
+        "--format=csv,noheader,nounits",
+    ])
+    .await?;
+    output.lines().filter(|line| !line.trim().is_empty()).map(parse_gpu_line).collect()
+}
+
+async fn run_nvidia_smi(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("nvidia-smi").args(args).output().await.context("Failed to run nvidia-smi")?;
+    if !output.status.success() {
+        bail!("nvidia-smi failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_gpu_line(line: &str) -> anyhow::Result<GpuStats> {
+    let fields: Vec<_> = line.split(',').map(str::trim).collect();
+    let &[index, name, utilization, memory_used, memory_total, temperature] = fields.as_slice() else {
+        bail!("unexpected nvidia-smi output: {line}");
+    };
+    let parse = |field: &str, value: &str| -> anyhow::Result<u64> {
+        value.parse().with_context(|| format!("invalid {field}: {value}"))
+    };
+    Ok(GpuStats {
+        index: parse("index", index)? as u32,
+        name: name.to_string(),
+        utilization: parse("utilization", utilization)? as u32,
+        memory_used: parse("memory used", memory_used)? * MIB,
+        memory_total: parse("memory total", memory_total)? * MIB,
+        temperature: parse("temperature", temperature)? as u32,
+    })
+}
+
+fn parse_cc_mode(output: &str) -> Option<String> {
+    output.lines().find_map(|line| line.trim().strip_prefix("CC status:")).map(|mode| mode.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpu_line() {
+        let stats = parse_gpu_line("0, NVIDIA H100 80GB HBM3, 42, 1024, 81559, 35").expect("failed to parse");
+        let expected = GpuStats {
+            index: 0,
+            name: "NVIDIA H100 80GB HBM3".into(),
+            utilization: 42,
+            memory_used: 1024 * MIB,
+            memory_total: 81559 * MIB,
+            temperature: 35,
+        };
+        assert_eq!(stats, expected);
+    }
+
+    #[test]
+    fn invalid_gpu_line() {
+        assert!(parse_gpu_line("0, NVIDIA H100 80GB HBM3, [N/A], 1024, 81559, 35").is_err());
+        assert!(parse_gpu_line("0, NVIDIA H100 80GB HBM3").is_err());
+    }
+
+    #[test]
+    fn memory_pressure() {
+        let contents =
+            "some avg10=1.50 avg60=0.75 avg300=0.10 total=123456\nfull avg10=0.50 avg60=0.25 avg300=0.00 total=4567\n";
+        let pressure = parse_memory_pressure(contents).expect("failed to parse");
+        let expected = MemoryPressure {
+            some: PressureStats { avg10: 1.5, avg60: 0.75, avg300: 0.1, total: 123456 },
+            full: PressureStats { avg10: 0.5, avg60: 0.25, avg300: 0.0, total: 4567 },
+        };
+        assert_eq!(pressure, expected);
+        assert!(parse_memory_pressure("some avg10=1.50\n").is_none());
+    }
+
+    #[test]
+    fn cc_mode() {
+        assert_eq!(parse_cc_mode("CC status: ON\n").as_deref(), Some("ON"));
+        assert_eq!(parse_cc_mode("something else\n"), None);
+    }
+}
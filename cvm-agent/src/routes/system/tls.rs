@@ -0,0 +1,96 @@
+use crate::routes::{SharedState, SystemState};
+use anyhow::Context;
+use axum::{Json, http::StatusCode};
+use chrono::{DateTime, Utc};
+use cvm_agent_models::tls::TlsInfoResponse;
+use reqwest::{ClientBuilder, tls::TlsInfo};
+use ring::digest::{SHA256, digest};
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+use tracing::{error, info};
+use x509_parser::parse_x509_certificate;
+
+const PROXY_ADDRESS: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 443));
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A certificate fingerprint along with when it was first seen.
+pub struct ObservedFingerprint {
+    fingerprint: [u8; 32],
+    observed_at: DateTime<Utc>,
+}
+
+impl ObservedFingerprint {
+    /// Record the fingerprint currently being served and return when it was first seen.
+    fn record(current: &mut Option<Self>, fingerprint: [u8; 32], now: DateTime<Utc>) -> DateTime<Utc> {
+        match current {
+            Some(observed) if observed.fingerprint == fingerprint => observed.observed_at,
+            _ => {
+                info!("Proxy is serving a new TLS certificate");
+                *current = Some(Self { fingerprint, observed_at: now });
+                now
+            }
+        }
+    }
+}
+
+pub(crate) async fn handler(state: SharedState) -> Result<Json<TlsInfoResponse>, StatusCode> {
+    if !matches!(&*state.system_state.lock().await, SystemState::Ready) {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    let Some(domain) = state.proxy.lock().await.hostnames.first().cloned() else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let fingerprint = fetch_fingerprint(&domain).await.map_err(|e| {
+        error!("Failed to fetch TLS certificate fingerprint: {e:#}");
+        StatusCode::BAD_GATEWAY
+    })?;
+    let observed_at = ObservedFingerprint::record(&mut *state.tls_fingerprint.lock().await, fingerprint, Utc::now());
+    Ok(Json(TlsInfoResponse { domain, fingerprint, observed_at }))
+}
+
+/// Fetch the fingerprint of the certificate the proxy serves for a domain.
+///
+/// This is computed the same way the attester does so it matches the one bound into attestation reports.
+async fn fetch_fingerprint(domain: &str) -> anyhow::Result<[u8; 32]> {
+    let cert = fetch_certificate(domain).await?;
+    let (_, cert) = parse_x509_certificate(&cert).context("Invalid TLS certificate")?;
+    let hash = digest(&SHA256, cert.tbs_certificate.subject_pki.raw);
+    hash.as_ref().try_into().context("Invalid digest length")
+}
+
+/// Fetch the DER encoded certificate the proxy serves for a domain.
+pub(crate) async fn fetch_certificate(domain: &str) -> anyhow::Result<Vec<u8>> {
+    let client = ClientBuilder::default()
+        .tls_info(true)
+        .danger_accept_invalid_certs(true)
+        .resolve(domain, PROXY_ADDRESS)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("Failed to build HTTP client")?;
+    let response = client.get(format!("https://{domain}")).send().await.context("Failed to send request")?;
+    let info = response.extensions().get::<TlsInfo>().context("No TLS information")?;
+    let cert = info.peer_certificate().context("No certificate in TLS info")?;
+    Ok(cert.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_fingerprint() {
+        let mut current = None;
+        let first_seen = Utc::now();
+        assert_eq!(ObservedFingerprint::record(&mut current, [1; 32], first_seen), first_seen);
+
+        let later = first_seen + chrono::Duration::hours(1);
+        assert_eq!(ObservedFingerprint::record(&mut current, [1; 32], later), first_seen);
+
+        // a renewed certificate comes with a new key
+        let rotated = later + chrono::Duration::hours(1);
+        assert_eq!(ObservedFingerprint::record(&mut current, [2; 32], rotated), rotated);
+        assert_eq!(ObservedFingerprint::record(&mut current, [2; 32], rotated + chrono::Duration::hours(1)), rotated);
+    }
+}
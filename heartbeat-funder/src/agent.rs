@@ -33,11 +33,12 @@ impl NilccAgentMonitor {
         let agent_host = client.base_url.host().map(|h| h.to_string()).unwrap_or_default();
         let monitor = Self { client, ticker, funder_handle, active_addresses: Default::default() };
         let name = name.unwrap_or_else(|| "<unknown>".to_string());
-        tokio::spawn(monitor.run().instrument(info_span!("agent", host = agent_host, name = name)));
+        let span = info_span!("agent", host = agent_host, name = name, chain = monitor.funder_handle.chain());
+        tokio::spawn(monitor.run().instrument(span));
     }
 
     async fn run(mut self) {
-        metrics::get().agents.inc_monitored(1);
+        metrics::get().agents.inc_monitored(self.funder_handle.chain(), 1);
         loop {
             self.ticker.tick().await;
 
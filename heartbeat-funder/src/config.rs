@@ -3,15 +3,19 @@ use alloy::{
     primitives::{Address, map::HashMap},
     signers::local::PrivateKeySigner,
 };
+use anyhow::bail;
 use reqwest::Url;
 use serde::Deserialize;
 use serde_with::{DisplayFromStr, DurationSeconds, serde_as};
-use std::{collections::BTreeMap, time::Duration};
+use std::{collections::BTreeMap, mem, time::Duration};
+
+/// The name of the chain configured via the top level `rpc`, `thresholds`, `contracts`, and `wallets` fields.
+pub const DEFAULT_CHAIN: &str = "default";
 
 #[serde_as]
 #[derive(Deserialize)]
 pub struct Config {
-    /// A list of static wallets to be funded.
+    /// A list of static wallets to be funded in the default chain.
     #[serde(default)]
     pub wallets: BTreeMap<String, Address>,
 
@@ -23,15 +27,19 @@ pub struct Config {
     #[serde(default)]
     pub api: Option<ApiConfig>,
 
-    /// The funding threshold configurations.
-    pub thresholds: ThresholdsConfig,
+    /// The funding threshold configurations for the default chain.
+    pub thresholds: Option<ThresholdsConfig>,
 
-    /// The RPC config.
-    pub rpc: RpcConfig,
+    /// The RPC config for the default chain.
+    pub rpc: Option<RpcConfig>,
 
-    /// The contracts addresses.
+    /// The contracts addresses for the default chain.
     pub contracts: Option<ContractsConfig>,
 
+    /// Any other chains wallets are funded in, by name.
+    #[serde(default)]
+    pub chains: BTreeMap<String, ChainConfig>,
+
     /// The interval configuration.
     #[serde(default)]
     pub intervals: IntervalsConfig,
@@ -55,6 +63,57 @@ impl Config {
         let config = config.try_deserialize()?;
         Ok(config)
     }
+
+    /// Take the configuration of every chain, including the default one, making sure they're all valid.
+    pub fn take_chains(&mut self) -> anyhow::Result<BTreeMap<String, ChainConfig>> {
+        let mut chains = mem::take(&mut self.chains);
+        match (self.rpc.take(), self.thresholds.take()) {
+            (Some(rpc), Some(thresholds)) => {
+                let wallets = mem::take(&mut self.wallets);
+                let chain = ChainConfig { rpc, thresholds, contracts: self.contracts.take(), wallets };
+                if chains.insert(DEFAULT_CHAIN.into(), chain).is_some() {
+                    bail!("chain name '{DEFAULT_CHAIN}' is reserved for the top level chain configuration");
+                }
+            }
+            (None, None) if self.wallets.is_empty() && self.contracts.is_none() => (),
+            _ => bail!("both 'rpc' and 'thresholds' must be set to configure the default chain"),
+        };
+        if chains.is_empty() {
+            bail!("no chains configured");
+        }
+        for (name, chain) in &chains {
+            let ThresholdsConfig { eth, nil } = &chain.thresholds;
+            if eth.minimum >= eth.target {
+                bail!("ETH minimum funding threshold must be lower than its target in chain '{name}'");
+            }
+            if !nil.target.is_zero() && nil.minimum >= nil.target {
+                bail!("NIL minimum funding threshold must be lower than its target in chain '{name}'");
+            }
+        }
+        let agent_chains = self.agents.values().map(|agent| agent.chain());
+        for chain in agent_chains.chain(self.api.as_ref().map(|api| api.chain())) {
+            if !chains.contains_key(chain) {
+                bail!("chain '{chain}' is not configured");
+            }
+        }
+        Ok(chains)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChainConfig {
+    /// The RPC config.
+    pub rpc: RpcConfig,
+
+    /// The funding threshold configurations.
+    pub thresholds: ThresholdsConfig,
+
+    /// The contracts addresses.
+    pub contracts: Option<ContractsConfig>,
+
+    /// A list of static wallets to be funded.
+    #[serde(default)]
+    pub wallets: BTreeMap<String, Address>,
 }
 
 #[serde_as]
@@ -89,6 +148,15 @@ pub struct AgentConfig {
 
     /// The authentication token.
     pub token: String,
+
+    /// The chain the agent's heartbeat wallets are funded in, the default chain if not set.
+    pub chain: Option<String>,
+}
+
+impl AgentConfig {
+    pub fn chain(&self) -> &str {
+        self.chain.as_deref().unwrap_or(DEFAULT_CHAIN)
+    }
 }
 
 #[derive(Deserialize)]
@@ -98,6 +166,15 @@ pub struct ApiConfig {
 
     /// The authentication token.
     pub token: String,
+
+    /// The chain the heartbeat wallets of the agents found in nilcc-api are funded in, the default chain if not set.
+    pub chain: Option<String>,
+}
+
+impl ApiConfig {
+    pub fn chain(&self) -> &str {
+        self.chain.as_deref().unwrap_or(DEFAULT_CHAIN)
+    }
 }
 
 #[derive(Deserialize)]
@@ -218,3 +295,95 @@ fn default_otel_export_timeout() -> Duration {
 fn default_metrics_export_interval() -> Duration {
     Duration::from_secs(15)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    fn parse(yaml: &str) -> Config {
+        let yaml = format!("private_key: {PRIVATE_KEY}\n{yaml}");
+        let config = config::Config::builder()
+            .add_source(config::File::from_str(&yaml, config::FileFormat::Yaml))
+            .build()
+            .expect("failed to build config");
+        config.try_deserialize().expect("failed to deserialize config")
+    }
+
+    #[test]
+    fn default_chain() {
+        let mut config = parse(
+            r#"
+rpc:
+  endpoint: wss://testnet.example.com
+thresholds:
+  eth:
+    minimum: "0.1"
+    target: "0.2"
+agents:
+  foo:
+    url: https://foo.example.com
+    token: secret
+"#,
+        );
+        let chains = config.take_chains().expect("invalid chains");
+        assert_eq!(chains.keys().map(String::as_str).collect::<Vec<_>>(), &[DEFAULT_CHAIN]);
+        assert_eq!(chains[DEFAULT_CHAIN].rpc.endpoint, "wss://testnet.example.com");
+    }
+
+    #[test]
+    fn multiple_chains() {
+        let mut config = parse(
+            r#"
+rpc:
+  endpoint: wss://testnet.example.com
+thresholds:
+  eth:
+    minimum: "0.1"
+    target: "0.2"
+chains:
+  mainnet:
+    rpc:
+      endpoint: wss://mainnet.example.com
+    thresholds:
+      eth:
+        minimum: "0.01"
+        target: "0.05"
+agents:
+  foo:
+    url: https://foo.example.com
+    token: secret
+    chain: mainnet
+api:
+  url: https://api.example.com
+  token: secret
+"#,
+        );
+        let chains = config.take_chains().expect("invalid chains");
+        assert_eq!(chains.keys().map(String::as_str).collect::<Vec<_>>(), &[DEFAULT_CHAIN, "mainnet"]);
+        assert_eq!(config.agents["foo"].chain(), "mainnet");
+        assert_eq!(config.api.as_ref().expect("no api").chain(), DEFAULT_CHAIN);
+    }
+
+    #[test]
+    fn unknown_agent_chain() {
+        let mut config = parse(
+            r#"
+chains:
+  mainnet:
+    rpc:
+      endpoint: wss://mainnet.example.com
+    thresholds:
+      eth:
+        minimum: "0.01"
+        target: "0.05"
+agents:
+  foo:
+    url: https://foo.example.com
+    token: secret
+"#,
+        );
+        assert!(config.take_chains().is_err());
+    }
+}
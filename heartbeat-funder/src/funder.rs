@@ -21,7 +21,7 @@ use tokio::{
     sync::mpsc::{Receiver, Sender, channel},
     time::{Interval, MissedTickBehavior, interval, sleep},
 };
-use tracing::{error, info, warn};
+use tracing::{Instrument, error, info, info_span, warn};
 
 const CONNECT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
 
@@ -36,6 +36,7 @@ sol!(
 );
 
 pub struct FunderArgs {
+    pub chain: String,
     pub rpc_endpoint: String,
     pub signer: PrivateKeySigner,
     pub static_addresses: BTreeSet<Address>,
@@ -45,6 +46,7 @@ pub struct FunderArgs {
 }
 
 pub struct Funder {
+    chain: String,
     rpc_endpoint: String,
     signer: PrivateKeySigner,
     ticker: Interval,
@@ -55,19 +57,22 @@ pub struct Funder {
 
 impl Funder {
     pub fn spawn(args: FunderArgs) -> FunderHandle {
-        let FunderArgs { rpc_endpoint, signer, static_addresses, poll_interval, thresholds, contracts } = args;
+        let FunderArgs { chain, rpc_endpoint, signer, static_addresses, poll_interval, thresholds, contracts } = args;
         let mut ticker = interval(poll_interval);
         ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         let (sender, command_receiver) = channel(1024);
-        let funder = Self { rpc_endpoint, signer, ticker, thresholds, command_receiver, addresses: static_addresses };
-        tokio::spawn(funder.run(contracts));
-        FunderHandle(sender)
+        let span = info_span!("funder", chain = chain);
+        let handle = FunderHandle { sender, chain: chain.clone() };
+        let funder =
+            Self { chain, rpc_endpoint, signer, ticker, thresholds, command_receiver, addresses: static_addresses };
+        tokio::spawn(funder.run(contracts).instrument(span));
+        handle
     }
 
     async fn run(mut self, contracts: Option<ContractsConfig>) {
         info!("Using wallet {}", self.signer.address());
-        metrics::get().addresses.set_monitored(self.addresses.len() as u64);
+        metrics::get().addresses.set_monitored(&self.chain, self.addresses.len() as u64);
 
         info!("Connecting to RPC endpoint {}", self.rpc_endpoint);
         let provider = loop {
@@ -116,7 +121,7 @@ impl Funder {
         match ctx.provider.get_balance(address).await.map(EthAmount::from) {
             Ok(balance) => {
                 info!("Wallet {address} has {balance} ETH");
-                metrics::get().wallet.eth.set_funds(balance);
+                metrics::get().wallet.eth.set_funds(&self.chain, balance);
             }
             Err(e) => {
                 error!("Failed to get our own balance: {e}");
@@ -134,7 +139,7 @@ impl Funder {
             FunderCommand::AddAddress(address) => {
                 info!("Adding address {address} to monitored set");
                 self.addresses.insert(address);
-                metrics::get().addresses.set_monitored(self.addresses.len() as u64);
+                metrics::get().addresses.set_monitored(&self.chain, self.addresses.len() as u64);
                 if let Err(e) = self.ensure_address_funded(address, ctx).await {
                     error!("Failed to fund address {address}: {e}");
                 }
@@ -142,7 +147,7 @@ impl Funder {
             FunderCommand::RemoveAddress(address) => {
                 info!("Removing address {address} from monitored set");
                 self.addresses.remove(&address);
-                metrics::get().addresses.set_monitored(self.addresses.len() as u64);
+                metrics::get().addresses.set_monitored(&self.chain, self.addresses.len() as u64);
             }
         }
     }
@@ -175,8 +180,8 @@ impl Funder {
         let tx = TransactionRequest { to: Some(TxKind::Call(address)), value: Some(missing.0), ..Default::default() };
         let tx_hash = ctx.provider.send_transaction(tx).await?.watch().await?;
         info!("Funded {address} with {missing} ETH in transaction {tx_hash}");
-        metrics::get().wallet.eth.inc_payments(1);
-        metrics::get().wallet.eth.inc_sent(missing);
+        metrics::get().wallet.eth.inc_payments(&self.chain, 1);
+        metrics::get().wallet.eth.inc_sent(&self.chain, missing);
         Ok(())
     }
 
@@ -227,9 +232,17 @@ struct Context {
 }
 
 #[derive(Clone)]
-pub struct FunderHandle(Sender<FunderCommand>);
+pub struct FunderHandle {
+    sender: Sender<FunderCommand>,
+    chain: String,
+}
 
 impl FunderHandle {
+    /// The name of the chain this funder funds wallets in.
+    pub(crate) fn chain(&self) -> &str {
+        &self.chain
+    }
+
     pub(crate) async fn add_address(&self, address: Address) {
         self.send(FunderCommand::AddAddress(address)).await;
     }
@@ -239,7 +252,7 @@ impl FunderHandle {
     }
 
     async fn send(&self, command: FunderCommand) {
-        if self.0.send(command).await.is_err() {
+        if self.sender.send(command).await.is_err() {
             error!("Funder receiver dropped");
         }
     }
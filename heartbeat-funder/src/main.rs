@@ -1,10 +1,10 @@
 use crate::{
     agent::{NilccAgentClient, NilccAgentMonitor, NilccAgentMonitorArgs},
     api::{NilccApiClient, NilccApiMonitor, NilccApiMonitorArgs},
-    config::{Config, OtelConfig},
+    config::{ChainConfig, Config, OtelConfig},
     funder::{Funder, FunderArgs},
 };
-use anyhow::{Context, bail};
+use anyhow::Context;
 use clap::Parser;
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::{MetricExporterBuilder, WithExportConfig};
@@ -12,7 +12,7 @@ use opentelemetry_sdk::{
     Resource,
     metrics::{PeriodicReader, SdkMeterProvider},
 };
-use std::env;
+use std::{collections::HashMap, env};
 use tokio::signal::{self, unix::SignalKind};
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::filter::EnvFilter;
@@ -84,14 +84,8 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let cli = Cli::parse();
-    let config = Config::load(cli.config_path.as_deref())?;
-    if config.thresholds.eth.minimum >= config.thresholds.eth.target {
-        bail!("ETH minimum funding threshold must be lower than its target");
-    }
-
-    if !config.thresholds.nil.target.is_zero() && config.thresholds.nil.minimum >= config.thresholds.nil.target {
-        bail!("NIL minimum funding threshold must be lower than its target");
-    }
+    let mut config = Config::load(cli.config_path.as_deref())?;
+    let chains = config.take_chains().context("Invalid chain configuration")?;
 
     let metrics_handle = match is_otel_disabled() {
         true => {
@@ -105,30 +99,38 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
-    let funder = Funder::spawn(FunderArgs {
-        rpc_endpoint: config.rpc.endpoint,
-        signer: config.private_key,
-        static_addresses: config.wallets.into_values().collect(),
-        poll_interval: config.intervals.funding,
-        thresholds: config.thresholds,
-        contracts: config.contracts,
-    });
+    let mut funders = HashMap::new();
+    for (name, chain) in chains {
+        let ChainConfig { rpc, thresholds, contracts, wallets } = chain;
+        let funder = Funder::spawn(FunderArgs {
+            chain: name.clone(),
+            rpc_endpoint: rpc.endpoint,
+            signer: config.private_key.clone(),
+            static_addresses: wallets.into_values().collect(),
+            poll_interval: config.intervals.funding,
+            thresholds,
+            contracts,
+        });
+        funders.insert(name, funder);
+    }
     for (name, agent) in config.agents {
+        let funder_handle = funders[agent.chain()].clone();
         let client = NilccAgentClient::new(agent.url, &agent.token);
         NilccAgentMonitor::spawn(NilccAgentMonitorArgs {
             client,
             poll_interval: config.intervals.agent,
-            funder_handle: funder.clone(),
+            funder_handle,
             name: Some(name),
         });
     }
     if let Some(api) = config.api {
+        let funder_handle = funders[api.chain()].clone();
         let client = NilccApiClient::new(api.url, &api.token);
         NilccApiMonitor::spawn(NilccApiMonitorArgs {
             client,
             poll_interval: config.intervals.api,
             agent_poll_interval: config.intervals.agent,
-            funder_handle: funder.clone(),
+            funder_handle,
         })
     }
 
@@ -1,6 +1,6 @@
 use crate::funder::EthAmount;
 use opentelemetry::{
-    global,
+    KeyValue, global,
     metrics::{Counter, Gauge, Meter},
 };
 use std::sync::LazyLock;
@@ -14,6 +14,11 @@ pub(crate) fn get() -> &'static Metrics {
     &METRICS
 }
 
+/// The attributes every metric is tagged with, identifying the chain it belongs to.
+fn chain_attributes(chain: &str) -> [KeyValue; 1] {
+    [KeyValue::new("chain", chain.to_string())]
+}
+
 pub(crate) struct Metrics {
     pub(crate) wallet: WalletMetrics,
     pub(crate) agents: AgentMetrics,
@@ -44,8 +49,8 @@ impl AddressMetrics {
         Self { monitored }
     }
 
-    pub(crate) fn set_monitored(&self, amount: u64) {
-        self.monitored.record(amount, &[]);
+    pub(crate) fn set_monitored(&self, chain: &str, amount: u64) {
+        self.monitored.record(amount, &chain_attributes(chain));
     }
 }
 
@@ -62,8 +67,8 @@ impl AgentMetrics {
         Self { monitored }
     }
 
-    pub(crate) fn inc_monitored(&self, amount: u64) {
-        self.monitored.add(amount, &[]);
+    pub(crate) fn inc_monitored(&self, chain: &str, amount: u64) {
+        self.monitored.add(amount, &chain_attributes(chain));
     }
 }
 
@@ -102,15 +107,15 @@ impl WalletEthMetrics {
         Self { funds, payments, sent }
     }
 
-    pub(crate) fn set_funds(&self, amount: EthAmount) {
-        self.funds.record(amount.into(), &[]);
+    pub(crate) fn set_funds(&self, chain: &str, amount: EthAmount) {
+        self.funds.record(amount.into(), &chain_attributes(chain));
     }
 
-    pub(crate) fn inc_payments(&self, amount: u64) {
-        self.payments.add(amount, &[]);
+    pub(crate) fn inc_payments(&self, chain: &str, amount: u64) {
+        self.payments.add(amount, &chain_attributes(chain));
     }
 
-    pub(crate) fn inc_sent(&self, amount: EthAmount) {
-        self.sent.add(amount.into(), &[]);
+    pub(crate) fn inc_sent(&self, chain: &str, amount: EthAmount) {
+        self.sent.add(amount.into(), &chain_attributes(chain));
     }
 }
@@ -1,3 +1,4 @@
+use crate::artifacts::MetadataError;
 use reqwest::{
     StatusCode,
     blocking::{Client, ClientBuilder, Response},
@@ -78,6 +79,9 @@ pub enum RequestError {
 
     #[error("invalid error response for status: {0}")]
     InvalidError(StatusCode),
+
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
 }
 
 #[derive(Deserialize)]
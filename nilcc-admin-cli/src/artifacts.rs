@@ -0,0 +1,142 @@
+use nilcc_artifacts::{
+    VmType,
+    metadata::{Artifact, ArtifactsMetadata, CvmImage},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{io, path::PathBuf};
+
+/// Fetches the `metadata.json` of artifact versions, either from the artifacts bucket or from a local directory.
+pub struct MetadataFetcher {
+    artifacts_url: String,
+    artifacts_path: Option<PathBuf>,
+}
+
+impl MetadataFetcher {
+    pub fn new(artifacts_url: String, artifacts_path: Option<PathBuf>) -> Self {
+        Self { artifacts_url, artifacts_path }
+    }
+
+    pub fn fetch(&self, version: &str) -> Result<VersionMetadata, MetadataError> {
+        let raw = match &self.artifacts_path {
+            Some(path) => std::fs::read(path.join(version).join("metadata.json")).map_err(MetadataError::Read)?,
+            None => {
+                let url = format!("{}/{version}/metadata.json", self.artifacts_url);
+                reqwest::blocking::get(url)?.error_for_status()?.bytes()?.to_vec()
+            }
+        };
+        let metadata = serde_json::from_slice(&raw).map_err(MetadataError::Parse)?;
+        Ok(VersionMetadata {
+            version: version.to_string(),
+            metadata_sha256: hex::encode(Sha256::digest(&raw)),
+            metadata,
+        })
+    }
+}
+
+/// The metadata for an artifacts version.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionMetadata {
+    pub version: String,
+    pub metadata_sha256: String,
+    pub metadata: ArtifactsMetadata,
+}
+
+/// What changed between two artifact versions.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactsDiff {
+    pub from: String,
+    pub to: String,
+    pub components: Vec<Change>,
+    pub kernel_cmdline: CommandLineChanges,
+    pub disk_images: Vec<Change>,
+}
+
+impl ArtifactsDiff {
+    pub fn new(from: &VersionMetadata, to: &VersionMetadata) -> Self {
+        let (old, new) = (&from.metadata, &to.metadata);
+        let mut components = Vec::new();
+        diff_artifact(&mut components, "ovmf", &old.ovmf, &new.ovmf);
+        diff_artifact(&mut components, "initrd", &old.initrd, &new.initrd);
+        for vm_type in [VmType::Cpu, VmType::Gpu] {
+            let (old, new) = (old.cvm.images.resolve(vm_type), new.cvm.images.resolve(vm_type));
+            diff_artifact(&mut components, &format!("{vm_type}.kernel"), &old.kernel, &new.kernel);
+        }
+        diff_value(&mut components, "guest_policy", &old.guest_policy.to_string(), &new.guest_policy.to_string());
+        diff_value(&mut components, "cpu.model", &old.cpu.model.to_string(), &new.cpu.model.to_string());
+
+        let mut disk_images = Vec::new();
+        for vm_type in [VmType::Cpu, VmType::Gpu] {
+            diff_image(&mut disk_images, vm_type, old.cvm.images.resolve(vm_type), new.cvm.images.resolve(vm_type));
+        }
+        Self {
+            from: from.version.clone(),
+            to: to.version.clone(),
+            components,
+            kernel_cmdline: CommandLineChanges::new(&old.cvm.cmdline.0, &new.cvm.cmdline.0),
+            disk_images,
+        }
+    }
+}
+
+/// A value that changed between two versions.
+#[derive(Serialize)]
+pub struct Change {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// The kernel command line parameters that were added or removed between two versions.
+///
+/// A parameter whose value changed shows up as both removed and added.
+#[derive(Serialize)]
+pub struct CommandLineChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl CommandLineChanges {
+    fn new(old: &str, new: &str) -> Self {
+        let old: Vec<_> = old.split_whitespace().collect();
+        let new: Vec<_> = new.split_whitespace().collect();
+        let added = new.iter().filter(|p| !old.contains(p)).map(|p| p.to_string()).collect();
+        let removed = old.iter().filter(|p| !new.contains(p)).map(|p| p.to_string()).collect();
+        Self { added, removed }
+    }
+}
+
+fn diff_value(changes: &mut Vec<Change>, name: &str, old: &str, new: &str) {
+    if old != new {
+        changes.push(Change { name: name.into(), from: old.into(), to: new.into() });
+    }
+}
+
+fn diff_artifact(changes: &mut Vec<Change>, name: &str, old: &Artifact, new: &Artifact) {
+    diff_value(changes, &format!("{name}.path"), &old.path, &new.path);
+    diff_value(changes, &format!("{name}.sha256"), &hex::encode(old.sha256), &hex::encode(new.sha256));
+}
+
+fn diff_image(changes: &mut Vec<Change>, vm_type: VmType, old: &CvmImage, new: &CvmImage) {
+    let name = format!("{vm_type}.disk");
+    diff_artifact(changes, &name, &old.disk.artifact, &new.disk.artifact);
+    diff_value(changes, &format!("{name}.format"), &old.disk.format.to_string(), &new.disk.format.to_string());
+    let name = format!("{vm_type}.verity");
+    diff_value(changes, &format!("{name}.path"), &old.verity.disk.path, &new.verity.disk.path);
+    let (old_hash, new_hash) = (hex::encode(old.verity.root_hash), hex::encode(new.verity.root_hash));
+    diff_value(changes, &format!("{name}.root_hash"), &old_hash, &new_hash);
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MetadataError {
+    #[error("downloading metadata: {0}")]
+    Download(#[from] reqwest::Error),
+
+    #[error("reading metadata: {0}")]
+    Read(io::Error),
+
+    #[error("parsing metadata: {0}")]
+    Parse(serde_json::Error),
+}
@@ -1,9 +1,15 @@
-use crate::api::{ApiClient, RequestError};
+use crate::{
+    api::{ApiClient, RequestError},
+    artifacts::{ArtifactsDiff, MetadataFetcher},
+};
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use nilcc_artifacts::downloader::S3_BUCKET_URL;
 use serde_json::json;
+use std::path::PathBuf;
 use uuid::Uuid;
 
 mod api;
+mod artifacts;
 mod models;
 
 #[derive(Parser)]
@@ -46,6 +52,10 @@ enum Command {
     /// Manage metal instances.
     #[clap(subcommand)]
     MetalInstances(MetalInstancesCommand),
+
+    /// Inspect and manage where workloads are scheduled.
+    #[clap(subcommand)]
+    Workloads(WorkloadsCommand),
 }
 
 #[derive(Subcommand)]
@@ -249,6 +259,44 @@ enum ArtifactsCommand {
         /// The version to disable.
         version: String,
     },
+
+    /// Show the components in an artifact version.
+    Show {
+        /// The version to show.
+        version: String,
+
+        #[clap(flatten)]
+        source: ArtifactsSourceArgs,
+    },
+
+    /// Show what changed between two artifact versions.
+    Diff {
+        /// The version to compare against.
+        from: String,
+
+        /// The version to compare.
+        to: String,
+
+        #[clap(flatten)]
+        source: ArtifactsSourceArgs,
+    },
+}
+
+#[derive(Args)]
+struct ArtifactsSourceArgs {
+    /// The URL artifacts are published under.
+    #[clap(long, env = "NILCC_ARTIFACTS_URL", default_value = S3_BUCKET_URL)]
+    artifacts_url: String,
+
+    /// Read `<path>/<version>/metadata.json` rather than downloading it, e.g. from an agent's artifacts path.
+    #[clap(long)]
+    artifacts_path: Option<PathBuf>,
+}
+
+impl ArtifactsSourceArgs {
+    fn fetcher(self) -> MetadataFetcher {
+        MetadataFetcher::new(self.artifacts_url, self.artifacts_path)
+    }
 }
 
 #[derive(Subcommand)]
@@ -263,6 +311,34 @@ enum MetalInstancesCommand {
     },
 }
 
+#[derive(Subcommand)]
+enum WorkloadsCommand {
+    /// List the workloads for an account.
+    List {
+        /// The account id.
+        account_id: Uuid,
+    },
+
+    /// Show the metal instance a workload is scheduled on.
+    Placement {
+        /// The workload id.
+        id: Uuid,
+    },
+
+    /// Force a workload to be moved to a different metal instance.
+    Reschedule(RescheduleWorkloadArgs),
+}
+
+#[derive(Args)]
+struct RescheduleWorkloadArgs {
+    /// The workload id.
+    id: Uuid,
+
+    /// The metal instance to move the workload to, any other instance with enough free resources is used if unset.
+    #[clap(long)]
+    metal_instance_id: Option<Uuid>,
+}
+
 struct Runner {
     client: ApiClient,
 }
@@ -358,6 +434,26 @@ impl Runner {
         self.client.post("/api/v1/artifacts/disable", &request)
     }
 
+    fn show_artifact_version(
+        &self,
+        version: String,
+        source: ArtifactsSourceArgs,
+    ) -> Result<serde_json::Value, RequestError> {
+        let metadata = source.fetcher().fetch(&version)?;
+        Ok(serde_json::to_value(metadata).expect("failed to serialize"))
+    }
+
+    fn diff_artifact_versions(
+        &self,
+        from: String,
+        to: String,
+        source: ArtifactsSourceArgs,
+    ) -> Result<serde_json::Value, RequestError> {
+        let fetcher = source.fetcher();
+        let diff = ArtifactsDiff::new(&fetcher.fetch(&from)?, &fetcher.fetch(&to)?);
+        Ok(serde_json::to_value(diff).expect("failed to serialize"))
+    }
+
     fn list_metal_instances(&self) -> Result<serde_json::Value, RequestError> {
         self.client.get("/api/v1/metal-instances/list")
     }
@@ -366,6 +462,26 @@ impl Runner {
         let request = models::metal_instances::DeleteMetalInstanceRequest { metal_instance_id };
         self.client.post("/api/v1/metal-instances/delete", &request)
     }
+
+    fn list_account_workloads(&self, account_id: Uuid) -> Result<serde_json::Value, RequestError> {
+        let workloads: Vec<models::workloads::WorkloadSummary> =
+            self.client.get(&format!("/api/v1/workloads/account/{account_id}"))?;
+        Ok(serde_json::to_value(workloads).expect("failed to serialize"))
+    }
+
+    fn workload_placement(&self, workload_id: Uuid) -> Result<serde_json::Value, RequestError> {
+        let placement: models::workloads::WorkloadPlacementResponse =
+            self.client.get(&format!("/api/v1/workloads/{workload_id}/placement"))?;
+        Ok(serde_json::to_value(placement).expect("failed to serialize"))
+    }
+
+    fn reschedule_workload(&self, args: RescheduleWorkloadArgs) -> Result<serde_json::Value, RequestError> {
+        let RescheduleWorkloadArgs { id, metal_instance_id } = args;
+        let request = models::workloads::RescheduleWorkloadRequest { workload_id: id, metal_instance_id };
+        let placement: models::workloads::WorkloadPlacementResponse =
+            self.client.post("/api/v1/workloads/reschedule", &request)?;
+        Ok(serde_json::to_value(placement).expect("failed to serialize"))
+    }
 }
 
 fn main() {
@@ -387,8 +503,15 @@ fn main() {
         Command::Artifacts(ArtifactsCommand::Enable { version }) => runner.enable_artifact_version(version),
         Command::Artifacts(ArtifactsCommand::List) => runner.list_artifact_versions(),
         Command::Artifacts(ArtifactsCommand::Disable { version }) => runner.disable_artifact_version(version),
+        Command::Artifacts(ArtifactsCommand::Show { version, source }) => runner.show_artifact_version(version, source),
+        Command::Artifacts(ArtifactsCommand::Diff { from, to, source }) => {
+            runner.diff_artifact_versions(from, to, source)
+        }
         Command::MetalInstances(MetalInstancesCommand::List) => runner.list_metal_instances(),
         Command::MetalInstances(MetalInstancesCommand::Delete { id }) => runner.delete_metal_instance(id),
+        Command::Workloads(WorkloadsCommand::List { account_id }) => runner.list_account_workloads(account_id),
+        Command::Workloads(WorkloadsCommand::Placement { id }) => runner.workload_placement(id),
+        Command::Workloads(WorkloadsCommand::Reschedule(args)) => runner.reschedule_workload(args),
     };
     let result = match result {
         Ok(response) => response,
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 pub mod accounts {
@@ -113,3 +113,55 @@ pub mod metal_instances {
         pub metal_instance_id: Uuid,
     }
 }
+
+pub mod workloads {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RescheduleWorkloadRequest {
+        pub workload_id: Uuid,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub metal_instance_id: Option<Uuid>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct WorkloadSummary {
+        pub workload_id: Uuid,
+        pub name: String,
+        pub status: String,
+        pub artifacts_version: String,
+        pub metal_instance_domain: String,
+        pub cpus: u64,
+        pub memory: u64,
+        pub disk: u64,
+        pub gpus: u64,
+        pub created_at: DateTime<Utc>,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct WorkloadPlacementResponse {
+        pub workload_id: Uuid,
+        pub account_id: Uuid,
+        pub status: String,
+        pub artifacts_version: String,
+        pub metal_instance: MetalInstanceSummary,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct MetalInstanceSummary {
+        pub metal_instance_id: Uuid,
+        pub hostname: String,
+        pub domain: String,
+        pub agent_version: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub public_ip: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub public_ipv6: Option<String>,
+        pub last_seen_at: DateTime<Utc>,
+    }
+}
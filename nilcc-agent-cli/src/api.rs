@@ -5,10 +5,12 @@ use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
 };
 use serde::{Serialize, de::DeserializeOwned};
+use std::{collections::BTreeMap, io::Write, time::Duration};
 
 pub struct ApiClient {
     base_url: String,
     client: Client,
+    authorization: HeaderValue,
 }
 
 impl ApiClient {
@@ -16,10 +18,10 @@ impl ApiClient {
         let mut headers = HeaderMap::new();
         let mut api_key = HeaderValue::from_str(&format!("Bearer {api_key}")).expect("invalid API key");
         api_key.set_sensitive(true);
-        headers.insert(HeaderName::from_static("authorization"), api_key);
+        headers.insert(HeaderName::from_static("authorization"), api_key.clone());
 
         let client = ClientBuilder::new().default_headers(headers).build().expect("failed to build client");
-        Self { base_url, client }
+        Self { base_url, client, authorization: api_key }
     }
 
     pub fn post<T, O>(&self, path: &str, request: &T) -> Result<O, RequestError>
@@ -32,6 +34,27 @@ impl ApiClient {
         Self::handle_response(response)
     }
 
+    pub fn post_query<Q, T, O>(&self, path: &str, query: &Q, request: &T) -> Result<O, RequestError>
+    where
+        Q: Serialize,
+        T: Serialize,
+        O: DeserializeOwned,
+    {
+        let url = self.make_url(path);
+        let response = self.client.post(url).query(query).json(request).send()?;
+        Self::handle_response(response)
+    }
+
+    pub fn put_bytes<Q, O>(&self, path: &str, query: &Q, body: Vec<u8>) -> Result<O, RequestError>
+    where
+        Q: Serialize,
+        O: DeserializeOwned,
+    {
+        let url = self.make_url(path);
+        let response = self.client.put(url).query(query).body(body).send()?;
+        Self::handle_response(response)
+    }
+
     pub fn get<O>(&self, path: &str) -> Result<O, RequestError>
     where
         O: DeserializeOwned,
@@ -51,6 +74,47 @@ impl ApiClient {
         Self::handle_response(response)
     }
 
+    /// Like `get_query` but with a custom timeout, for requests that block on the server side.
+    pub fn get_query_timeout<T, O>(&self, path: &str, query: &T, timeout: Duration) -> Result<O, RequestError>
+    where
+        T: Serialize,
+        O: DeserializeOwned,
+    {
+        let url = self.make_url(path);
+        let response = self.client.get(url).query(query).timeout(timeout).send()?;
+        Self::handle_response(response)
+    }
+
+    pub fn get_text<T>(&self, path: &str, query: &T) -> Result<String, RequestError>
+    where
+        T: Serialize,
+    {
+        let url = self.make_url(path);
+        let response = self.client.get(url).query(query).send()?;
+        if response.status().is_success() { Ok(response.text()?) } else { Self::handle_response(response) }
+    }
+
+    pub fn post_download<W>(&self, path: &str, output: &mut W) -> Result<u64, RequestError>
+    where
+        W: Write,
+    {
+        let url = self.make_url(path);
+        let mut response = self.client.post(url).send()?;
+        if response.status().is_success() { Ok(response.copy_to(output)?) } else { Self::handle_response(response) }
+    }
+
+    /// Build the websocket URL for an endpoint along with the authorization header to connect to it with.
+    pub fn websocket_endpoint<T>(&self, path: &str, query: &T) -> Result<(String, HeaderValue), RequestError>
+    where
+        T: Serialize,
+    {
+        let url = self.make_url(path);
+        let mut url = self.client.get(url).query(query).build()?.url().clone();
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme).map_err(|_| RequestError::InvalidUrl)?;
+        Ok((url.into(), self.authorization.clone()))
+    }
+
     fn handle_response<O>(response: Response) -> Result<O, RequestError>
     where
         O: DeserializeOwned,
@@ -60,7 +124,12 @@ impl ApiClient {
         } else {
             let status = response.status();
             let err: RequestHandlerError = response.json().map_err(|_| RequestError::InvalidError(status))?;
-            Err(RequestError::Handler { code: err.error_code, details: err.message })
+            Err(RequestError::Handler {
+                code: err.error_code,
+                details: err.message,
+                retryable: err.retryable,
+                context: err.details,
+            })
         }
     }
 
@@ -74,9 +143,23 @@ pub enum RequestError {
     #[error("sending request: {0}")]
     Request(#[from] reqwest::Error),
 
-    #[error("api error, code = {code}, details = {details}")]
-    Handler { code: String, details: String },
+    #[error("api error, code = {code}, details = {details}{}", render_error_context(*retryable, context))]
+    Handler { code: String, details: String, retryable: bool, context: BTreeMap<String, String> },
 
     #[error("invalid error response for status: {0}")]
     InvalidError(StatusCode),
+
+    #[error("invalid url")]
+    InvalidUrl,
+}
+
+fn render_error_context(retryable: bool, context: &BTreeMap<String, String>) -> String {
+    let mut output = String::new();
+    for (key, value) in context {
+        output.push_str(&format!(", {key} = {value}"));
+    }
+    if retryable {
+        output.push_str(" (retryable, try again later)");
+    }
+    output
 }
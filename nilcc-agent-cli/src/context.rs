@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+/// The default path to the contexts file, `$XDG_CONFIG_HOME/nilcc/agent-cli.yaml`.
+///
+/// Returns `None` if neither `XDG_CONFIG_HOME` nor `HOME` are set.
+pub fn default_contexts_path() -> Option<PathBuf> {
+    let var = |name: &str| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let config_dir = var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))?;
+    Some(config_dir.join("nilcc").join("agent-cli.yaml"))
+}
+
+/// A named set of settings used to connect to a nilcc-agent instance.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Context {
+    /// The endpoint where the nilcc-agent instance is reachable at.
+    pub url: String,
+
+    /// The API key to use.
+    pub api_key: String,
+
+    /// The artifacts version to launch workloads with when none is explicitly provided.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifacts_version: Option<String>,
+}
+
+/// The contexts file.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Contexts {
+    /// The context used when none is explicitly selected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<String>,
+
+    /// The contexts, by name.
+    #[serde(default)]
+    pub contexts: BTreeMap<String, Context>,
+}
+
+impl Contexts {
+    /// Load the contexts from a file, returning an empty set if it doesn't exist.
+    pub fn load(path: &Path) -> Result<Self, ContextError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_yaml::from_str(&contents)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Save the contexts into a file.
+    pub fn save(&self, path: &Path) -> Result<(), ContextError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_yaml::to_string(self)?;
+        // This contains API keys so make sure only the owner can read it.
+        let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    /// Select the context with the given name, or the current one if no name is provided.
+    pub fn select(&self, name: Option<&str>) -> Result<Option<&Context>, ContextError> {
+        match name.or(self.current.as_deref()) {
+            Some(name) => self.contexts.get(name).map(Some).ok_or_else(|| ContextError::NotFound(name.into())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The settings used to connect to a nilcc-agent instance.
+pub struct Connection {
+    pub url: String,
+    pub api_key: String,
+    pub artifacts_version: Option<String>,
+}
+
+impl Connection {
+    /// Resolve the connection settings, where the explicitly provided URL and API key take precedence over the ones in
+    /// the selected context.
+    pub fn resolve(
+        contexts: &Contexts,
+        name: Option<&str>,
+        url: Option<String>,
+        api_key: Option<String>,
+    ) -> Result<Self, ContextError> {
+        let context = contexts.select(name)?.cloned();
+        let (context_url, context_api_key, artifacts_version) = match context {
+            Some(Context { url, api_key, artifacts_version }) => (Some(url), Some(api_key), artifacts_version),
+            None => (None, None, None),
+        };
+        let url = url.or(context_url).ok_or(ContextError::MissingUrl)?;
+        let api_key = api_key.or(context_api_key).ok_or(ContextError::MissingApiKey)?;
+        Ok(Self { url, api_key, artifacts_version })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ContextError {
+    #[error("context '{0}' does not exist")]
+    NotFound(String),
+
+    #[error("no url provided, use --url or select a context")]
+    MissingUrl,
+
+    #[error("no API key provided, use --api-key or select a context")]
+    MissingApiKey,
+
+    #[error("no contexts file path, set $HOME or use --contexts-file")]
+    NoContextsFile,
+
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid contexts file: {0}")]
+    Serde(#[from] serde_yaml::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_contexts() -> Contexts {
+        let make_context = |url: &str, api_key: &str| Context {
+            url: url.into(),
+            api_key: api_key.into(),
+            artifacts_version: Some("0.1.0".into()),
+        };
+        Contexts {
+            current: Some("prod".into()),
+            contexts: [
+                ("prod".into(), make_context("https://prod.example.com", "prod-key")),
+                ("staging".into(), make_context("https://staging.example.com", "staging-key")),
+            ]
+            .into(),
+        }
+    }
+
+    #[test]
+    fn resolve_current() {
+        let connection = Connection::resolve(&make_contexts(), None, None, None).expect("resolve failed");
+        assert_eq!(connection.url, "https://prod.example.com");
+        assert_eq!(connection.api_key, "prod-key");
+        assert_eq!(connection.artifacts_version.as_deref(), Some("0.1.0"));
+    }
+
+    #[test]
+    fn resolve_named() {
+        let connection = Connection::resolve(&make_contexts(), Some("staging"), None, None).expect("resolve failed");
+        assert_eq!(connection.url, "https://staging.example.com");
+        assert_eq!(connection.api_key, "staging-key");
+    }
+
+    #[test]
+    fn resolve_explicit_overrides() {
+        let connection = Connection::resolve(
+            &make_contexts(),
+            None,
+            Some("https://other.example.com".into()),
+            Some("other-key".into()),
+        )
+        .expect("resolve failed");
+        assert_eq!(connection.url, "https://other.example.com");
+        assert_eq!(connection.api_key, "other-key");
+        assert_eq!(connection.artifacts_version.as_deref(), Some("0.1.0"));
+    }
+
+    #[test]
+    fn resolve_without_contexts() {
+        let contexts = Contexts::default();
+        let connection = Connection::resolve(&contexts, None, Some("https://example.com".into()), Some("key".into()))
+            .expect("resolve failed");
+        assert_eq!(connection.url, "https://example.com");
+        assert_eq!(connection.artifacts_version, None);
+
+        let err = Connection::resolve(&contexts, None, None, Some("key".into())).expect_err("resolve succeeded");
+        assert!(matches!(err, ContextError::MissingUrl), "{err}");
+        let err = Connection::resolve(&contexts, None, Some("https://example.com".into()), None)
+            .expect_err("resolve succeeded");
+        assert!(matches!(err, ContextError::MissingApiKey), "{err}");
+    }
+
+    #[test]
+    fn resolve_unknown_context() {
+        let err = Connection::resolve(&make_contexts(), Some("dev"), None, None).expect_err("resolve succeeded");
+        assert!(matches!(err, ContextError::NotFound(name) if name == "dev"));
+    }
+}
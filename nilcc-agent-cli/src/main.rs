@@ -1,20 +1,42 @@
 use crate::api::ApiClient;
+use crate::context::{Connection, ContextError, Contexts, default_contexts_path};
+use crate::manifest::{Plan, WorkloadManifest};
+use crate::oci::{BundleEntrypoint, BundleMetadata, OciClient, OciReference, WorkloadBundle};
 use ansi_term::Color;
 use anyhow::Context;
 use anyhow::anyhow;
-use clap::{Args, Parser, Subcommand};
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use cvm_agent_models::bootstrap::BootstrapStatus;
+use cvm_agent_models::encryption::MaybeEncrypted;
+use cvm_agent_models::health::CertificateStatus;
+use cvm_agent_models::health::ComposeDrift;
+use cvm_agent_models::health::DriftFinding;
 use cvm_agent_models::health::HealthResponse;
 use cvm_agent_models::health::LastEvent;
 use cvm_agent_models::logs::SystemLogsRequest;
 use cvm_agent_models::logs::SystemLogsResponse;
 use cvm_agent_models::logs::SystemLogsSource;
+use cvm_agent_models::stats::ClockSkew;
+use cvm_agent_models::stats::ContainerStats;
+use cvm_agent_models::stats::ContainersStatsResponse;
 use cvm_agent_models::stats::CpuStats;
 use cvm_agent_models::stats::DiskStats;
+use cvm_agent_models::stats::GpuStats;
+use cvm_agent_models::stats::GpusStats;
+use cvm_agent_models::stats::MemoryPressure;
+use cvm_agent_models::stats::OomKill;
 use cvm_agent_models::stats::SystemStatsResponse;
+use cvm_agent_models::tls::TlsInfoResponse;
 use cvm_agent_models::{
-    container::Container,
+    container::{
+        ComposeServiceState, ComposeStateResponse, Container, PortForwardRequest, RestartContainerRequest, ServiceState,
+    },
+    jobs::{JobLogsRequest, JobState, JobStatus},
     logs::{ContainerLogsRequest, ContainerLogsResponse, OutputStream},
 };
+use futures_util::{SinkExt, StreamExt};
 use nilcc_agent_models::system::AgentVersionResponse;
 use nilcc_agent_models::system::ArtifactChangelogEntry;
 use nilcc_agent_models::system::ArtifactChangelogEntryOperation;
@@ -22,43 +44,95 @@ use nilcc_agent_models::system::ArtifactChangelogEntryState;
 use nilcc_agent_models::system::ArtifactChangelogResponse;
 use nilcc_agent_models::system::ArtifactVersionsResponse;
 use nilcc_agent_models::system::ArtifactsCleanupResponse;
+use nilcc_agent_models::system::ArtifactsGcPreviewResponse;
 use nilcc_agent_models::system::InstallArtifactVersionRequest;
 use nilcc_agent_models::system::LastUpgrade;
+use nilcc_agent_models::system::RetireVerifierKeyRequest;
+use nilcc_agent_models::system::RotateVerifierKeyRequest;
+use nilcc_agent_models::system::RotateVerifierKeyResponse;
 use nilcc_agent_models::system::UpgradeState;
 use nilcc_agent_models::system::VerifierKey;
+use nilcc_agent_models::system::VerifierKeyBalance;
+use nilcc_agent_models::system::ZeroSslAccount;
+use nilcc_agent_models::system::{FreeResources, SystemCapacityResponse, SystemInfoResponse, WorkloadShape};
+use nilcc_agent_models::workloads::change_domain::ChangeWorkloadDomainRequest;
+use nilcc_agent_models::workloads::create::BandwidthLimits;
 use nilcc_agent_models::workloads::create::CreateWorkloadHeartbeat;
+use nilcc_agent_models::workloads::create::ImagePolicyMode;
+use nilcc_agent_models::workloads::create::LogRotation;
+use nilcc_agent_models::workloads::create::ProxyTimeouts;
+use nilcc_agent_models::workloads::create::StateDisk;
+use nilcc_agent_models::workloads::create::UpgradeChannel;
+use nilcc_agent_models::workloads::create::WorkloadPriority;
+use nilcc_agent_models::workloads::docker_credentials::UpdateDockerCredentialsRequest;
+use nilcc_agent_models::workloads::env_vars::{EnvVarsUpdateMode, UpdateEnvVarsRequest, UpdateEnvVarsResponse};
+use nilcc_agent_models::workloads::files::{UpdateFilesRequest, UpdateFilesResponse};
+use nilcc_agent_models::workloads::pause::PauseWorkloadRequest;
+use nilcc_agent_models::workloads::progress::WorkloadProgressResponse;
+use nilcc_agent_models::workloads::proxy_timeouts::UpdateProxyTimeoutsRequest;
 use nilcc_agent_models::workloads::restart::RestartWorkloadRequest;
+use nilcc_agent_models::workloads::resume::ResumeWorkloadRequest;
 use nilcc_agent_models::workloads::start::StartWorkloadRequest;
 use nilcc_agent_models::workloads::stop::StopWorkloadRequest;
+use nilcc_agent_models::workloads::uploads::{
+    CreateUploadRequest, CreateUploadResponse, UploadChunkQuery, UploadStatus,
+};
+use nilcc_agent_models::workloads::usage::{WorkloadUsageRequest, WorkloadUsageResponse};
+use nilcc_agent_models::workloads::wait::{MAX_WAIT_TIMEOUT_SECONDS, WaitWorkloadQuery, WaitWorkloadResponse};
 use nilcc_agent_models::workloads::{
-    create::{CreateWorkloadRequest, CreateWorkloadResponse},
+    create::{CreateWorkloadQuery, CreateWorkloadRequest, CreateWorkloadResponse},
     delete::DeleteWorkloadRequest,
-    list::WorkloadSummary,
+    list::{ListWorkloadsQuery, WorkloadSummary},
 };
+use reqwest::header::{AUTHORIZATION, HeaderValue};
 use sha3::Digest;
 use sha3::Keccak256;
 use std::{
     collections::HashMap,
     fs::{self, File},
-    io::{BufRead, BufReader},
+    io::{self, BufRead, BufReader, Read, Seek},
+    net::Ipv4Addr,
     path::{Path, PathBuf},
     process::exit,
     str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, stdin, stdout},
+    net::{TcpListener, TcpStream},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Message, client::IntoClientRequest},
 };
 use uuid::Uuid;
 
 mod api;
+mod context;
+mod manifest;
+mod oci;
 
 /// The nilcc-agent CLI.
 #[derive(Parser)]
 struct Cli {
-    /// The endpoint where the nilcc-agent instance is reachable at.
+    /// The endpoint where the nilcc-agent instance is reachable at. Takes precedence over the selected context.
     #[clap(long, env = "NILCC_AGENT_URL")]
-    url: String,
+    url: Option<String>,
 
-    /// The API key to use.
+    /// The API key to use. Takes precedence over the selected context.
     #[clap(long, env = "NILCC_AGENT_API_KEY", hide_env_values = true)]
-    api_key: String,
+    api_key: Option<String>,
+
+    /// The context to use. Defaults to the one selected via `context use`.
+    #[clap(long, global = true, env = "NILCC_AGENT_CONTEXT")]
+    context: Option<String>,
+
+    /// The path to the file contexts are stored in.
+    ///
+    /// Defaults to `$XDG_CONFIG_HOME/nilcc/agent-cli.yaml`, falling back to `$HOME/.config/nilcc/agent-cli.yaml`.
+    #[clap(long, global = true, env = "NILCC_AGENT_CONTEXTS_FILE")]
+    contexts_file: Option<PathBuf>,
 
     /// The command to execute.
     #[clap(subcommand)]
@@ -71,7 +145,7 @@ enum Command {
     Launch(LaunchArgs),
 
     /// List workloads.
-    List,
+    List(ListArgs),
 
     /// Delete a workload.
     Delete(DeleteArgs),
@@ -79,19 +153,62 @@ enum Command {
     /// Check the health for a workload.
     Health(HealthArgs),
 
+    /// Wait until a workload is bootstrapped, serving HTTPS, and all of its services converged.
+    Wait(WaitArgs),
+
     /// Start a workload
     Start(StartArgs),
 
     /// Stop a workload.
     Stop(StopArgs),
 
+    /// Pause a workload, freezing its VM without releasing its resources.
+    Pause(PauseArgs),
+
+    /// Resume a paused workload.
+    Resume(ResumeArgs),
+
+    /// Attach to the serial console of a workload launched in debug mode.
+    Console(ConsoleArgs),
+
     /// Restart a workload.
     Restart(RestartArgs),
 
+    /// Change the domain a workload is served on.
+    ChangeDomain(ChangeDomainArgs),
+
+    /// Update a workload's environment variables.
+    EnvVars(EnvVarsArgs),
+
+    /// Add, replace, or remove the files bundled with a workload, restarting it.
+    Files(FilesArgs),
+
+    /// Publish a workload bundle, made up of a docker compose file, files, and metadata, to an OCI registry.
+    PublishBundle(PublishBundleArgs),
+
+    /// Replace the credentials a workload pulls its images with, without restarting it.
+    DockerCredentials(DockerCredentialsArgs),
+
+    /// Change the timeouts the SNI proxy uses for a workload's traffic, without restarting it.
+    ProxyTimeouts(UpdateProxyTimeoutsArgs),
+
+    /// Show what applying a workload manifest would change.
+    Plan(PlanArgs),
+
+    /// Create or update a workload so it matches a manifest.
+    Apply(ApplyArgs),
+
+    /// Get the resources a workload was allocated over time.
+    Usage(UsageArgs),
+
     /// Container commands.
     #[clap(subcommand)]
     Containers(ContainersCommand),
 
+    /// One-shot job commands.
+    #[clap(subcommand)]
+    Jobs(JobsCommand),
+
     /// System commands.
     #[clap(subcommand)]
     System(SystemCommand),
@@ -99,6 +216,46 @@ enum Command {
     /// Admin commands
     #[clap(subcommand)]
     Admin(AdminCommand),
+
+    /// Manage the contexts used to connect to nilcc-agent instances.
+    #[clap(subcommand)]
+    Context(ContextCommand),
+}
+
+#[derive(Subcommand)]
+enum ContextCommand {
+    /// Create or update a context.
+    Set(SetContextArgs),
+
+    /// Select the context to use by default.
+    Use(UseContextArgs),
+
+    /// List the existing contexts.
+    List,
+}
+
+#[derive(Args)]
+struct SetContextArgs {
+    /// The name of the context.
+    name: String,
+
+    /// The endpoint where the nilcc-agent instance is reachable at.
+    #[clap(long)]
+    url: String,
+
+    /// The API key to use.
+    #[clap(long)]
+    api_key: String,
+
+    /// The artifacts version to launch workloads with when none is explicitly provided.
+    #[clap(long)]
+    artifacts_version: Option<String>,
+}
+
+#[derive(Args)]
+struct UseContextArgs {
+    /// The name of the context.
+    name: String,
 }
 
 #[derive(Subcommand)]
@@ -108,6 +265,24 @@ enum ContainersCommand {
 
     /// Get logs for a container.
     Logs(ContainerLogsArgs),
+
+    /// Restart the containers for a docker compose service.
+    Restart(RestartContainerArgs),
+
+    /// Show the desired and actual state of every docker compose service.
+    State(ComposeStateArgs),
+
+    /// Forward a local port to a port in a container.
+    PortForward(PortForwardArgs),
+}
+
+#[derive(Subcommand)]
+enum JobsCommand {
+    /// Show the status of every job.
+    List(ListJobsArgs),
+
+    /// Get logs for a job.
+    Logs(JobLogsArgs),
 }
 
 #[derive(Subcommand)]
@@ -132,6 +307,17 @@ enum AdminCommand {
     /// Manage verifier information.
     #[clap(subcommand)]
     Verifier(VerifierCommand),
+
+    /// Manage the keys used to submit verifier heartbeats.
+    #[clap(subcommand)]
+    Keys(AdminKeysCommand),
+
+    /// Manage ZeroSSL accounts.
+    #[clap(subcommand)]
+    Zerossl(ZeroSslCommand),
+
+    /// Back up the agent's database along with a manifest of the workloads' state files.
+    Backup(BackupArgs),
 }
 
 #[derive(Subcommand)]
@@ -147,6 +333,9 @@ enum AdminArtifactsCommand {
 
     /// Cleanup unused artifact versions.
     Cleanup,
+
+    /// Show the artifact versions the garbage collection policy would delete.
+    GcPreview,
 }
 
 #[derive(Subcommand)]
@@ -154,8 +343,17 @@ enum AdminAgentCommand {
     /// Upgrade the nilcc-agent binary.
     Upgrade(UpgradeAgentArgs),
 
+    /// Roll the nilcc-agent binary back to the version it was running before its last upgrade.
+    Rollback,
+
     /// Get the current nilcc-agent binary version.
     Version,
+
+    /// Get the agent's build info and capabilities.
+    Info,
+
+    /// Get the resources available for new workloads and the largest workloads that fit in them.
+    Capacity,
 }
 
 #[derive(Subcommand)]
@@ -164,15 +362,33 @@ enum VerifierCommand {
     Keys,
 }
 
+#[derive(Subcommand)]
+enum AdminKeysCommand {
+    /// List the keys along with the workload each of them is assigned to.
+    List,
+
+    /// Move the workload using a key over to a fresh key and retire the previous one.
+    Rotate(VerifierKeyArgs),
+
+    /// Retire a key that isn't in use so it's never assigned to a workload again.
+    Retire(VerifierKeyArgs),
+}
+
+#[derive(Subcommand)]
+enum ZeroSslCommand {
+    /// Get the ZeroSSL accounts certificates are requested with.
+    Accounts,
+}
+
 #[derive(Args)]
 struct LaunchArgs {
     /// The id to use for the workload.
     #[clap(long)]
     id: Option<Uuid>,
 
-    /// The artifacts version to use.
+    /// The artifacts version to use. Defaults to the one in the selected context.
     #[clap(short, long)]
-    artifacts: String,
+    artifacts: Option<String>,
 
     /// Add an environment variable to the workload, in the format `<name>=<value>`.
     #[clap(short, long = "env-var")]
@@ -182,17 +398,29 @@ struct LaunchArgs {
     #[clap(long = "dotenv-file")]
     dotenv: Option<PathBuf>,
 
+    /// Import the environment variables in a control plane managed env group.
+    #[clap(long = "env-group")]
+    env_groups: Vec<String>,
+
     /// Add a file to the workload, in the format `<file-name>=<value>`.
     #[clap(short, long = "file")]
     files: Vec<KeyValue>,
 
+    /// Add a file to the workload by uploading it in chunks, in the format `<file-name>=<path>`.
+    ///
+    /// Use this for large files, which would otherwise make the request too large.
+    #[clap(long = "upload-file")]
+    upload_files: Vec<KeyValue>,
+
     /// Add docker credentials, in the format `<server>:<username>:<password>`
     #[clap(long)]
     docker_credentials: Vec<DockerCredentials>,
 
     /// The container entrypoint, in the format `<container-name>:<container-port>`
-    #[clap(long)]
-    entrypoint: Entrypoint,
+    ///
+    /// Defaults to the bundle's entrypoint when launching from an OCI bundle.
+    #[clap(long, required_unless_present = "oci_ref")]
+    entrypoint: Option<Entrypoint>,
 
     /// The number of CPUs to use in the VM.
     #[clap(long, default_value_t = 1)]
@@ -202,6 +430,14 @@ struct LaunchArgs {
     #[clap(long, default_value_t = 0)]
     gpus: u16,
 
+    /// The GPU model the VM requires, e.g. `H100`.
+    #[clap(long)]
+    gpu_model: Option<String>,
+
+    /// Allow using GPUs that don't support confidential computing, e.g. AMD Instinct ones.
+    #[clap(long)]
+    allow_non_confidential_gpus: bool,
+
     /// The amount of RAM, in MBs.
     #[clap(long, default_value_t = 2048)]
     memory_mb: u32,
@@ -215,12 +451,170 @@ struct LaunchArgs {
     domain: String,
 
     /// The path to the docker compose file to be used.
-    #[clap(long = "docker-compose")]
-    docker_compose_path: PathBuf,
+    #[clap(long = "docker-compose", required_unless_present = "oci_ref", conflicts_with = "oci_ref")]
+    docker_compose_path: Option<PathBuf>,
+
+    /// Launch a workload bundle published to an OCI registry, e.g. `registry.example.com/team/app:1.2.3`.
+    ///
+    /// Append `@sha256:<digest>` to pin the bundle, in which case it's only launched if its manifest has that digest.
+    /// Files passed via `--file` are added to the bundle's files, replacing any with the same name.
+    #[clap(long)]
+    oci_ref: Option<OciReference>,
+
+    #[clap(flatten)]
+    oci_credentials: OciCredentialsArgs,
 
     /// The measurement hash URL.
     #[clap(long = "measurement-hash-url")]
     measurement_hash_url: Option<String>,
+
+    /// The workload's priority. Low priority workloads can be stopped to make room for high priority ones.
+    #[clap(long, value_enum, default_value_t = Priority::Normal)]
+    priority: Priority,
+
+    /// Whether the workload is recreated whenever a newer stable artifacts version is installed.
+    #[clap(long, value_enum, default_value_t = Channel::Pinned)]
+    upgrade_channel: Channel,
+
+    /// A hex encoded X25519 public key to encrypt logs and stats to.
+    ///
+    /// This must match the `x-nilcc-log-encryption-key` extension in the docker compose file.
+    #[clap(long)]
+    log_encryption_key: Option<LogEncryptionKey>,
+
+    /// Override the agent's image vulnerability policy for this workload.
+    #[clap(long, value_enum)]
+    image_policy: Option<ImagePolicy>,
+
+    /// Rotate container logs once they reach this size, in MBs.
+    #[clap(long, requires = "log_max_files")]
+    log_max_size_mb: Option<u32>,
+
+    /// The number of log files to keep per container when rotating logs.
+    #[clap(long, requires = "log_max_size_mb")]
+    log_max_files: Option<u32>,
+
+    /// Override the agent's state disk mode for this workload.
+    #[clap(long, value_enum)]
+    state_disk: Option<StateDiskMode>,
+
+    /// A docker registry mirror to pull images through, overriding the agent's defaults.
+    #[clap(long = "registry-mirror")]
+    registry_mirrors: Vec<String>,
+
+    /// Add a label to the workload, in the format `<key>=<value>`.
+    #[clap(long = "label")]
+    labels: Vec<KeyValue>,
+
+    /// Launch the workload in debug mode, exposing its serial console via the `console` command.
+    ///
+    /// Debug workloads can't be attested.
+    #[clap(long)]
+    debug: bool,
+
+    /// Limit the rate traffic is sent to the workload at, in megabits per second.
+    #[clap(long)]
+    ingress_mbps: Option<u32>,
+
+    /// Limit the rate the workload sends traffic at, in megabits per second.
+    #[clap(long)]
+    egress_mbps: Option<u32>,
+
+    #[clap(flatten)]
+    proxy_timeouts: ProxyTimeoutsArgs,
+
+    /// Require exclusive use of the metal instance.
+    ///
+    /// The workload is only created if no other workload exists, and no other workload can be created while it does.
+    #[clap(long)]
+    isolated: bool,
+
+    /// Validate the workload and print the resources that would be assigned to it without creating it.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Wait until the workload is bootstrapped, serving HTTPS, and all of its services converged.
+    #[clap(long, conflicts_with = "dry_run")]
+    wait: bool,
+
+    /// Print every provisioning step the workload reaches, with timestamps, until it's ready.
+    #[clap(long, conflicts_with_all = ["dry_run", "wait"])]
+    watch: bool,
+
+    /// How long to wait for the workload to be ready when using `--wait` or `--watch`, e.g. `90s`, `10m`, or `1h`.
+    #[clap(long, default_value = "10m", value_parser = parse_duration)]
+    timeout: Duration,
+}
+
+#[derive(Clone, ValueEnum)]
+enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl From<Priority> for WorkloadPriority {
+    fn from(priority: Priority) -> Self {
+        match priority {
+            Priority::Low => Self::Low,
+            Priority::Normal => Self::Normal,
+            Priority::High => Self::High,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum Channel {
+    Pinned,
+    LatestStable,
+}
+
+impl From<Channel> for UpgradeChannel {
+    fn from(channel: Channel) -> Self {
+        match channel {
+            Channel::Pinned => Self::Pinned,
+            Channel::LatestStable => Self::LatestStable,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum ImagePolicy {
+    Enforce,
+    Warn,
+    Disabled,
+}
+
+impl From<ImagePolicy> for ImagePolicyMode {
+    fn from(policy: ImagePolicy) -> Self {
+        match policy {
+            ImagePolicy::Enforce => Self::Enforce,
+            ImagePolicy::Warn => Self::Warn,
+            ImagePolicy::Disabled => Self::Disabled,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum StateDiskMode {
+    Ephemeral,
+    Sealed,
+}
+
+impl From<StateDiskMode> for StateDisk {
+    fn from(mode: StateDiskMode) -> Self {
+        match mode {
+            StateDiskMode::Ephemeral => Self::Ephemeral,
+            StateDiskMode::Sealed => Self::Sealed,
+        }
+    }
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// Only list workloads that have this label, in the format `<key>=<value>`.
+    #[clap(long = "label")]
+    labels: Vec<KeyValue>,
 }
 
 #[derive(Args)]
@@ -235,6 +629,18 @@ struct StopArgs {
     id: Uuid,
 }
 
+#[derive(Args)]
+struct PauseArgs {
+    /// The identifier of the workload to be paused.
+    id: Uuid,
+}
+
+#[derive(Args)]
+struct ResumeArgs {
+    /// The identifier of the workload to be resumed.
+    id: Uuid,
+}
+
 #[derive(Args)]
 struct StartArgs {
     /// The identifier of the workload to be started.
@@ -255,12 +661,196 @@ struct RestartArgs {
     clear_env_vars: bool,
 }
 
+#[derive(Args)]
+struct EnvVarsArgs {
+    /// The identifier of the workload whose environment variables should be updated.
+    id: Uuid,
+
+    /// Set an environment variable, in the format `<name>=<value>`.
+    #[clap(short, long = "env-var")]
+    env_vars: Vec<KeyValue>,
+
+    /// Remove an environment variable.
+    #[clap(long, conflicts_with = "replace")]
+    remove: Vec<String>,
+
+    /// Replace all environment variables with the ones provided rather than patching them.
+    #[clap(long)]
+    replace: bool,
+
+    /// Restart the workload so it picks up the changes right away.
+    #[clap(long)]
+    restart: bool,
+}
+
+#[derive(Args)]
+struct FilesArgs {
+    /// The identifier of the workload whose files should be updated.
+    id: Uuid,
+
+    /// Add or replace a file, in the format `<file-name>=<path>`.
+    #[clap(short, long = "file")]
+    files: Vec<KeyValue>,
+
+    /// Remove a file.
+    #[clap(long)]
+    remove: Vec<String>,
+}
+
+#[derive(Args)]
+struct OciCredentialsArgs {
+    /// The username to authenticate to the OCI registry with.
+    #[clap(long, requires = "oci_password")]
+    oci_username: Option<String>,
+
+    /// The password or token to authenticate to the OCI registry with.
+    #[clap(long, env = "NILCC_OCI_PASSWORD", requires = "oci_username")]
+    oci_password: Option<String>,
+}
+
+impl OciCredentialsArgs {
+    fn into_credentials(self) -> Option<(String, String)> {
+        self.oci_username.zip(self.oci_password)
+    }
+}
+
+#[derive(Args)]
+struct PublishBundleArgs {
+    /// The reference to publish the bundle to, e.g. `registry.example.com/team/app:1.2.3`.
+    oci_ref: OciReference,
+
+    /// The path to the docker compose file.
+    #[clap(long = "docker-compose")]
+    docker_compose_path: PathBuf,
+
+    /// Add a file to the bundle, in the format `<file-name>=<path>`.
+    #[clap(short, long = "file")]
+    files: Vec<KeyValue>,
+
+    /// The container entrypoint, in the format `<container-name>:<container-port>`
+    #[clap(long)]
+    entrypoint: Option<Entrypoint>,
+
+    /// The artifacts version the workload should be launched with.
+    #[clap(short, long)]
+    artifacts: Option<String>,
+
+    #[clap(flatten)]
+    oci_credentials: OciCredentialsArgs,
+}
+
+#[derive(Args)]
+struct ProxyTimeoutsArgs {
+    /// Override how long the SNI proxy waits for a connection to the workload to be established, in milliseconds.
+    #[clap(long)]
+    proxy_connect_timeout_ms: Option<u64>,
+
+    /// Override how long the SNI proxy lets clients stay inactive for, in milliseconds.
+    #[clap(long)]
+    proxy_client_timeout_ms: Option<u64>,
+
+    /// Override how long the SNI proxy lets the workload stay inactive for, in milliseconds.
+    #[clap(long)]
+    proxy_server_timeout_ms: Option<u64>,
+}
+
+impl ProxyTimeoutsArgs {
+    fn into_timeouts(self) -> Option<ProxyTimeouts> {
+        let Self { proxy_connect_timeout_ms, proxy_client_timeout_ms, proxy_server_timeout_ms } = self;
+        let timeouts = ProxyTimeouts {
+            connect_ms: proxy_connect_timeout_ms,
+            client_ms: proxy_client_timeout_ms,
+            server_ms: proxy_server_timeout_ms,
+        };
+        (timeouts != ProxyTimeouts::default()).then_some(timeouts)
+    }
+}
+
+#[derive(Args)]
+struct UpdateProxyTimeoutsArgs {
+    /// The identifier of the workload whose proxy timeouts should be changed.
+    id: Uuid,
+
+    /// The timeouts to use. Any timeout that isn't set uses the agent's default.
+    #[clap(flatten)]
+    timeouts: ProxyTimeoutsArgs,
+}
+
+#[derive(Args)]
+struct DockerCredentialsArgs {
+    /// The identifier of the workload whose docker credentials should be replaced.
+    id: Uuid,
+
+    /// The docker credentials to use, in the format `<server>:<username>:<password>`.
+    #[clap(long)]
+    docker_credentials: Vec<DockerCredentials>,
+}
+
+#[derive(Args)]
+struct PlanArgs {
+    /// The path to the workload manifest.
+    #[clap(short = 'f', long = "file")]
+    manifest: PathBuf,
+}
+
+#[derive(Args)]
+struct ApplyArgs {
+    /// The path to the workload manifest.
+    #[clap(short = 'f', long = "file")]
+    manifest: PathBuf,
+
+    /// Delete and create the workload again when settings that can't be changed in place differ.
+    ///
+    /// This discards the workload's state disk.
+    #[clap(long)]
+    allow_recreate: bool,
+}
+
+#[derive(Args)]
+struct ChangeDomainArgs {
+    /// The identifier of the workload whose domain should be changed.
+    id: Uuid,
+
+    /// The new domain for the workload.
+    domain: String,
+}
+
 #[derive(Args)]
 struct ListContainersArgs {
     /// The identifier of the workload to list containers for.
     id: Uuid,
 }
 
+#[derive(Args)]
+struct ComposeStateArgs {
+    /// The identifier of the workload to get the compose state for.
+    id: Uuid,
+}
+
+#[derive(Args)]
+struct RestartContainerArgs {
+    /// The identifier of the workload the service belongs to.
+    id: Uuid,
+
+    /// The name of the service in the docker compose file.
+    #[clap(short, long)]
+    service: String,
+}
+
+#[derive(Args)]
+struct PortForwardArgs {
+    /// The identifier of the workload the container belongs to.
+    id: Uuid,
+
+    /// The container and port to forward connections to, in the form `<container>:<port>`.
+    #[clap(value_parser = parse_port_forward_target)]
+    target: PortForwardRequest,
+
+    /// The local port to listen on. Defaults to the container's port.
+    #[clap(long)]
+    local_port: Option<u16>,
+}
+
 #[derive(Args)]
 struct ContainerLogsArgs {
     /// The identifier of the workload to get logs from.
@@ -284,6 +874,35 @@ struct ContainerLogsArgs {
     max_lines: usize,
 }
 
+#[derive(Args)]
+struct ListJobsArgs {
+    /// The identifier of the workload to list jobs for.
+    id: Uuid,
+}
+
+#[derive(Args)]
+struct JobLogsArgs {
+    /// The identifier of the workload to get logs from.
+    id: Uuid,
+
+    /// The name of the job's service in the docker compose file.
+    #[clap(short, long)]
+    job: String,
+
+    /// Whether to get stderr logs. By default stdout logs are fetched.
+    #[clap(long)]
+    stderr: bool,
+
+    /// Whether to fetch logs from the head of the stream. By default logs are fetched from the
+    /// tail.
+    #[clap(long)]
+    head: bool,
+
+    /// The maximum number of lines to get.
+    #[clap(long, default_value_t = 1000)]
+    max_lines: usize,
+}
+
 #[derive(Args)]
 struct SystemLogsArgs {
     /// The identifier of the workload to get logs from.
@@ -297,12 +916,57 @@ struct SystemLogsArgs {
     /// The maximum number of lines to get.
     #[clap(long, default_value_t = 1000)]
     max_lines: usize,
+
+    /// The source to get logs from.
+    #[clap(long, value_enum, default_value_t = LogSource::CvmAgent)]
+    source: LogSource,
+}
+
+#[derive(Clone, ValueEnum)]
+enum LogSource {
+    CvmAgent,
+    Proxy,
+    Kernel,
+    DockerDaemon,
+}
+
+impl From<LogSource> for SystemLogsSource {
+    fn from(source: LogSource) -> Self {
+        match source {
+            LogSource::CvmAgent => Self::CvmAgent,
+            LogSource::Proxy => Self::Proxy,
+            LogSource::Kernel => Self::Kernel,
+            LogSource::DockerDaemon => Self::DockerDaemon,
+        }
+    }
+}
+
+#[derive(Args)]
+struct UsageArgs {
+    /// The identifier of the workload to get usage for.
+    id: Uuid,
+
+    /// Only include usage from this point on, in RFC 3339 format.
+    #[clap(long)]
+    from: Option<DateTime<Utc>>,
+
+    /// Only include usage before this point, in RFC 3339 format.
+    #[clap(long)]
+    to: Option<DateTime<Utc>>,
+
+    /// Print the raw usage samples as CSV.
+    #[clap(long)]
+    csv: bool,
 }
 
 #[derive(Args)]
 struct SystemStatsArgs {
     /// The identifier of the workload to get stats from.
     id: Uuid,
+
+    /// Also show the resource usage of every running container.
+    #[clap(long)]
+    containers: bool,
 }
 
 #[derive(Args)]
@@ -311,6 +975,22 @@ struct HealthArgs {
     id: Uuid,
 }
 
+#[derive(Args)]
+struct ConsoleArgs {
+    /// The identifier of the workload to attach to.
+    id: Uuid,
+}
+
+#[derive(Args)]
+struct WaitArgs {
+    /// The identifier of the workload to wait for.
+    id: Uuid,
+
+    /// How long to wait for, e.g. `90s`, `10m`, or `1h`.
+    #[clap(long, default_value = "10m", value_parser = parse_duration)]
+    timeout: Duration,
+}
+
 #[derive(Args)]
 struct InstallArtifactsArgs {
     /// The artifact version to update to.
@@ -323,6 +1003,18 @@ struct UpgradeAgentArgs {
     version: String,
 }
 
+#[derive(Args)]
+struct BackupArgs {
+    /// The path to write the backup tarball to.
+    output: PathBuf,
+}
+
+#[derive(Args)]
+struct VerifierKeyArgs {
+    /// The key's address or its hex encoded public key.
+    key: String,
+}
+
 #[derive(Clone)]
 struct KeyValue {
     key: String,
@@ -357,6 +1049,21 @@ impl FromStr for Entrypoint {
     }
 }
 
+#[derive(Clone)]
+struct LogEncryptionKey(Vec<u8>);
+
+impl FromStr for LogEncryptionKey {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = hex::decode(s).map_err(|_| "invalid hex")?;
+        if key.len() != 32 {
+            return Err("key must be 32 bytes long");
+        }
+        Ok(Self(key))
+    }
+}
+
 #[derive(Clone)]
 struct DockerCredentials {
     server: String,
@@ -379,6 +1086,13 @@ impl FromStr for DockerCredentials {
     }
 }
 
+impl From<DockerCredentials> for nilcc_agent_models::workloads::create::DockerCredentials {
+    fn from(credentials: DockerCredentials) -> Self {
+        let DockerCredentials { server, username, password } = credentials;
+        Self { server, username, password }
+    }
+}
+
 fn load_dotenv(path: &Path) -> anyhow::Result<HashMap<String, String>> {
     let file = File::open(path).context("Failed to open .env file")?;
     let reader = BufReader::new(file);
@@ -395,63 +1109,169 @@ fn load_dotenv(path: &Path) -> anyhow::Result<HashMap<String, String>> {
     Ok(output)
 }
 
-fn launch(client: ApiClient, args: LaunchArgs) -> anyhow::Result<()> {
+fn launch(client: ApiClient, args: LaunchArgs, default_artifacts: Option<String>) -> anyhow::Result<()> {
     let LaunchArgs {
         id,
         artifacts,
         env_vars,
         dotenv,
+        env_groups,
         files,
+        upload_files,
         docker_credentials,
         entrypoint,
         cpus,
         gpus,
+        gpu_model,
+        allow_non_confidential_gpus,
         memory_mb,
         disk_space_gb,
         domain,
         docker_compose_path,
+        oci_ref,
+        oci_credentials,
         measurement_hash_url,
+        priority,
+        upgrade_channel,
+        log_encryption_key,
+        image_policy,
+        log_max_size_mb,
+        log_max_files,
+        state_disk,
+        registry_mirrors,
+        labels,
+        debug,
+        ingress_mbps,
+        egress_mbps,
+        proxy_timeouts,
+        isolated,
+        dry_run,
+        wait,
+        watch,
+        timeout,
     } = args;
-    let docker_compose = fs::read_to_string(docker_compose_path).context("Failed to read docker compose")?;
+    let (docker_compose, mut bundle_files, metadata) = match (oci_ref, docker_compose_path) {
+        (Some(reference), _) => {
+            let mut oci_client = OciClient::new(reference.clone(), oci_credentials.into_credentials());
+            let (bundle, digest) = oci_client.pull_bundle().context("Failed to pull bundle")?;
+            println!("Pulled bundle {}", reference.pinned(&digest));
+            let WorkloadBundle { metadata, docker_compose, files } = bundle;
+            (docker_compose, files, Some(metadata))
+        }
+        (None, Some(path)) => {
+            let docker_compose = fs::read_to_string(path).context("Failed to read docker compose")?;
+            (docker_compose, HashMap::new(), None)
+        }
+        (None, None) => bail!("No docker compose file provided"),
+    };
+    let (bundle_entrypoint, bundle_artifacts) = match metadata {
+        Some(BundleMetadata { entrypoint, artifacts_version }) => {
+            (entrypoint.map(|e| Entrypoint { container: e.container, port: e.port }), artifacts_version)
+        }
+        None => (None, None),
+    };
+    let entrypoint = entrypoint.or(bundle_entrypoint).context("No entrypoint provided")?;
+    let artifacts = artifacts.or(bundle_artifacts).or(default_artifacts).context("No artifacts version provided")?;
     let mut env_vars: HashMap<_, _> = env_vars.into_iter().map(|kv| (kv.key, kv.value)).collect();
     if let Some(dotenv) = dotenv {
         env_vars.extend(load_dotenv(&dotenv)?);
     }
-    let files = files
+    for file in files {
+        let contents = fs::read(&file.value).context("Failed to read file")?;
+        bundle_files.insert(file.key, contents);
+    }
+    let files = bundle_files;
+    let uploaded_files = upload_files
         .into_iter()
-        .map(|f| fs::read(&f.value).map(|contents| (f.key, contents)).context("Failed to read file"))
+        .map(|f| upload_file(&client, Path::new(&f.value)).map(|id| (f.key, id)))
         .collect::<Result<_, _>>()?;
     let request = CreateWorkloadRequest {
         id: id.unwrap_or_else(Uuid::new_v4),
         artifacts_version: artifacts,
         docker_compose,
         env_vars,
+        env_groups,
         files,
-        docker_credentials: docker_credentials
-            .into_iter()
-            .map(|c| nilcc_agent_models::workloads::create::DockerCredentials {
-                server: c.server,
-                username: c.username,
-                password: c.password,
-            })
-            .collect(),
+        uploaded_files,
+        docker_credentials: docker_credentials.into_iter().map(Into::into).collect(),
         public_container_name: entrypoint.container,
         public_container_port: entrypoint.port,
         memory_mb,
         cpus,
         gpus,
+        gpu_model,
+        allow_non_confidential_gpus,
         disk_space_gb,
         domain,
         heartbeat: measurement_hash_url.map(|measurement_hash_url| CreateWorkloadHeartbeat { measurement_hash_url }),
+        priority: priority.into(),
+        upgrade_channel: upgrade_channel.into(),
+        log_encryption_key: log_encryption_key.map(|key| key.0),
+        image_policy: image_policy.map(Into::into),
+        log_rotation: log_max_size_mb
+            .zip(log_max_files)
+            .map(|(max_size_mb, max_files)| LogRotation { max_size_mb, max_files }),
+        state_disk: state_disk.map(Into::into),
+        registry_mirrors: (!registry_mirrors.is_empty()).then_some(registry_mirrors),
+        labels: labels.into_iter().map(|kv| (kv.key, kv.value)).collect(),
+        debug,
+        bandwidth_limits: (ingress_mbps.is_some() || egress_mbps.is_some())
+            .then_some(BandwidthLimits { ingress_mbps, egress_mbps }),
+        proxy_timeouts: proxy_timeouts.into_timeouts(),
+        isolated,
     };
-    let response: CreateWorkloadResponse = client.post("/api/v1/workloads/create", &request)?;
-    let CreateWorkloadResponse { id } = response;
-    println!("Workload {id} launched");
+    let query = CreateWorkloadQuery { dry_run };
+    let response: CreateWorkloadResponse = client.post_query("/api/v1/workloads/create", &query, &request)?;
+    match response {
+        CreateWorkloadResponse { admission: Some(admission), .. } => {
+            let admission = serde_json::to_string_pretty(&admission).expect("failed to serialize");
+            println!("{admission}");
+        }
+        CreateWorkloadResponse { id, admission: None } => {
+            println!("Workload {id} launched");
+            if wait {
+                wait_for_workload(&client, id, timeout)?;
+            } else if watch {
+                watch_workload_progress(&client, id, timeout)?;
+            }
+        }
+    }
     Ok(())
 }
 
-fn list(client: ApiClient) -> anyhow::Result<()> {
-    let workloads: Vec<WorkloadSummary> = client.get("/api/v1/workloads/list")?;
+/// Upload a file in chunks, returning the id to reference it with.
+fn upload_file(client: &ApiClient, path: &Path) -> anyhow::Result<Uuid> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = sha2::Sha256::new();
+    let size = io::copy(&mut file, &mut hasher).context("Failed to read file")?;
+    let request = CreateUploadRequest { size, sha256: hasher.finalize().into() };
+    let upload: CreateUploadResponse = client.post("/api/v1/workloads/files/upload", &request)?;
+    file.rewind().context("Failed to read file")?;
+
+    let mut offset = 0;
+    loop {
+        let mut chunk = Vec::new();
+        (&mut file).take(upload.max_chunk_size).read_to_end(&mut chunk).context("Failed to read file")?;
+        let chunk_size = chunk.len();
+        let query = UploadChunkQuery { offset };
+        let status: UploadStatus =
+            client.put_bytes(&format!("/api/v1/workloads/files/upload/{}", upload.id), &query, chunk)?;
+        if status.complete {
+            println!("Uploaded {} ({size} bytes)", path.display());
+            return Ok(upload.id);
+        }
+        if chunk_size == 0 {
+            bail!("{} changed while being uploaded", path.display());
+        }
+        offset = status.received;
+    }
+}
+
+fn list(client: ApiClient, args: ListArgs) -> anyhow::Result<()> {
+    let ListArgs { labels } = args;
+    let labels = labels.into_iter().map(|kv| format!("{}={}", kv.key, kv.value)).collect::<Vec<_>>();
+    let query = ListWorkloadsQuery { labels: (!labels.is_empty()).then(|| labels.join(",")) };
+    let workloads: Vec<WorkloadSummary> = client.get_query("/api/v1/workloads/list", &query)?;
     let containers = serde_json::to_string_pretty(&workloads).expect("failed to serialize");
     println!("{containers}");
     Ok(())
@@ -481,6 +1301,22 @@ fn stop(client: ApiClient, args: StopArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn pause(client: ApiClient, args: PauseArgs) -> anyhow::Result<()> {
+    let PauseArgs { id } = args;
+    let request = PauseWorkloadRequest { id };
+    let _: () = client.post("/api/v1/workloads/pause", &request)?;
+    println!("Workload {id} paused");
+    Ok(())
+}
+
+fn resume(client: ApiClient, args: ResumeArgs) -> anyhow::Result<()> {
+    let ResumeArgs { id } = args;
+    let request = ResumeWorkloadRequest { id };
+    let _: () = client.post("/api/v1/workloads/resume", &request)?;
+    println!("Workload {id} resumed");
+    Ok(())
+}
+
 fn restart(client: ApiClient, args: RestartArgs) -> anyhow::Result<()> {
     let RestartArgs { id, env_vars, clear_env_vars } = args;
     let env_vars = match clear_env_vars {
@@ -493,15 +1329,236 @@ fn restart(client: ApiClient, args: RestartArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn env_vars(client: ApiClient, args: EnvVarsArgs) -> anyhow::Result<()> {
+    let EnvVarsArgs { id, env_vars, remove, replace, restart } = args;
+    let mode = match replace {
+        true => EnvVarsUpdateMode::Replace,
+        false => EnvVarsUpdateMode::Patch,
+    };
+    let env_vars = env_vars.into_iter().map(|kv| (kv.key, kv.value)).collect();
+    let request = UpdateEnvVarsRequest { mode, env_vars, remove, restart };
+    let response: UpdateEnvVarsResponse = client.post(&format!("/api/v1/workloads/{id}/env-vars"), &request)?;
+    if response.changed.is_empty() {
+        println!("No environment variables changed");
+    } else {
+        println!("Changed environment variables: {}", response.changed.join(", "));
+    }
+    if response.restart_pending {
+        println!("Workload {id} needs to be restarted for the changes to take effect");
+    }
+    Ok(())
+}
+
+fn files(client: ApiClient, args: FilesArgs) -> anyhow::Result<()> {
+    let FilesArgs { id, files, remove } = args;
+    let files = files
+        .into_iter()
+        .map(|f| fs::read(&f.value).map(|contents| (f.key, contents)).context("Failed to read file"))
+        .collect::<Result<_, _>>()?;
+    let request = UpdateFilesRequest { files, remove };
+    let response: UpdateFilesResponse = client.post(&format!("/api/v1/workloads/{id}/files"), &request)?;
+    if response.changed.is_empty() {
+        println!("No files changed");
+    } else {
+        println!("Changed files: {}", response.changed.join(", "));
+    }
+    if response.restarted {
+        println!("Workload {id} was restarted to pick up the changes");
+    }
+    Ok(())
+}
+
+fn docker_credentials(client: ApiClient, args: DockerCredentialsArgs) -> anyhow::Result<()> {
+    let DockerCredentialsArgs { id, docker_credentials } = args;
+    let request =
+        UpdateDockerCredentialsRequest { docker_credentials: docker_credentials.into_iter().map(Into::into).collect() };
+    let _: () = client.post(&format!("/api/v1/workloads/{id}/docker-credentials"), &request)?;
+    println!("Docker credentials for workload {id} updated");
+    Ok(())
+}
+
+fn proxy_timeouts(client: ApiClient, args: UpdateProxyTimeoutsArgs) -> anyhow::Result<()> {
+    let UpdateProxyTimeoutsArgs { id, timeouts } = args;
+    let request = UpdateProxyTimeoutsRequest { proxy_timeouts: timeouts.into_timeouts() };
+    let _: () = client.post(&format!("/api/v1/workloads/{id}/proxy-timeouts"), &request)?;
+    println!("Proxy timeouts for workload {id} updated");
+    Ok(())
+}
+
+fn publish_bundle(args: PublishBundleArgs) -> anyhow::Result<()> {
+    let PublishBundleArgs { oci_ref, docker_compose_path, files, entrypoint, artifacts, oci_credentials } = args;
+    let docker_compose = fs::read_to_string(docker_compose_path).context("Failed to read docker compose")?;
+    let files = files
+        .into_iter()
+        .map(|f| fs::read(&f.value).map(|contents| (f.key, contents)).context("Failed to read file"))
+        .collect::<Result<_, _>>()?;
+    let metadata = BundleMetadata {
+        entrypoint: entrypoint.map(|e| BundleEntrypoint { container: e.container, port: e.port }),
+        artifacts_version: artifacts,
+    };
+    let bundle = WorkloadBundle { metadata, docker_compose, files };
+    let mut oci_client = OciClient::new(oci_ref.clone(), oci_credentials.into_credentials());
+    let digest = oci_client.push_bundle(&bundle).context("Failed to publish bundle")?;
+    println!("Published bundle {}", oci_ref.pinned(&digest));
+    Ok(())
+}
+
+fn load_plan(client: &ApiClient, path: &Path) -> anyhow::Result<(WorkloadManifest, Plan)> {
+    let manifest = WorkloadManifest::load(path)?;
+    let workloads: Vec<WorkloadSummary> = client.get_query("/api/v1/workloads/list", &ListWorkloadsQuery::default())?;
+    let plan = Plan::new(&manifest, workloads.iter().find(|w| w.id == manifest.id));
+    Ok((manifest, plan))
+}
+
+fn plan(client: ApiClient, args: PlanArgs) -> anyhow::Result<()> {
+    let PlanArgs { manifest } = args;
+    let (manifest, plan) = load_plan(&client, &manifest)?;
+    println!("Workload {}: {plan}", manifest.id);
+    Ok(())
+}
+
+fn apply(client: ApiClient, args: ApplyArgs, default_artifacts: Option<String>) -> anyhow::Result<()> {
+    let ApplyArgs { manifest, allow_recreate } = args;
+    let (manifest, plan) = load_plan(&client, &manifest)?;
+    let id = manifest.id;
+    match plan {
+        Plan::Create => {
+            create_from_manifest(&client, manifest, default_artifacts)?;
+            println!("Workload {id} created");
+        }
+        Plan::Recreate { fields } if !allow_recreate => {
+            bail!("{} changed, use --allow-recreate to delete and create workload {id} again", fields.join(", "))
+        }
+        Plan::Recreate { .. } => {
+            let _: () = client.post("/api/v1/workloads/delete", &DeleteWorkloadRequest { id })?;
+            create_from_manifest(&client, manifest, default_artifacts)?;
+            println!("Workload {id} recreated");
+        }
+        Plan::Update { .. } if plan.is_noop() => println!("Workload {id} is up to date"),
+        Plan::Update { domain, env, files } => {
+            if let Some(domain) = domain {
+                let request = ChangeWorkloadDomainRequest { id, domain };
+                let _: () = client.post("/api/v1/workloads/change-domain", &request)?;
+                println!("Workload {id} domain changed");
+            }
+            if !env.is_empty() {
+                // Updating files restarts the workload so only restart here if that's not going to happen.
+                let request = UpdateEnvVarsRequest {
+                    mode: EnvVarsUpdateMode::Replace,
+                    env_vars: manifest.env,
+                    remove: Vec::new(),
+                    restart: files.is_empty(),
+                };
+                let _: UpdateEnvVarsResponse = client.post(&format!("/api/v1/workloads/{id}/env-vars"), &request)?;
+                println!("Workload {id} environment variables updated: {env}");
+            }
+            if !files.is_empty() {
+                let mut changed = manifest.files;
+                changed.retain(|name, _| files.added.contains(name) || files.changed.contains(name));
+                let request = UpdateFilesRequest { files: changed, remove: files.removed.clone() };
+                let _: UpdateFilesResponse = client.post(&format!("/api/v1/workloads/{id}/files"), &request)?;
+                println!("Workload {id} files updated: {files}");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn create_from_manifest(
+    client: &ApiClient,
+    manifest: WorkloadManifest,
+    default_artifacts: Option<String>,
+) -> anyhow::Result<()> {
+    let WorkloadManifest { id, domain, docker_compose, entrypoint, artifacts_version, env, files, resources } =
+        manifest;
+    let request = CreateWorkloadRequest {
+        id,
+        artifacts_version: artifacts_version.or(default_artifacts).context("No artifacts version provided")?,
+        docker_compose,
+        env_vars: env,
+        env_groups: Vec::new(),
+        files,
+        uploaded_files: HashMap::new(),
+        docker_credentials: Vec::new(),
+        public_container_name: entrypoint.container,
+        public_container_port: entrypoint.port,
+        memory_mb: resources.memory_mb,
+        cpus: resources.cpus,
+        gpus: resources.gpus,
+        gpu_model: None,
+        allow_non_confidential_gpus: false,
+        disk_space_gb: resources.disk_space_gb,
+        domain,
+        heartbeat: None,
+        priority: Default::default(),
+        upgrade_channel: Default::default(),
+        log_encryption_key: None,
+        image_policy: None,
+        log_rotation: None,
+        state_disk: None,
+        registry_mirrors: None,
+        labels: Default::default(),
+        debug: false,
+        bandwidth_limits: None,
+        proxy_timeouts: None,
+        isolated: false,
+    };
+    let _: CreateWorkloadResponse =
+        client.post_query("/api/v1/workloads/create", &CreateWorkloadQuery { dry_run: false }, &request)?;
+    Ok(())
+}
+
+fn change_domain(client: ApiClient, args: ChangeDomainArgs) -> anyhow::Result<()> {
+    let ChangeDomainArgs { id, domain } = args;
+    let request = ChangeWorkloadDomainRequest { id, domain };
+    let _: () = client.post("/api/v1/workloads/change-domain", &request)?;
+    println!("Workload {id} domain changed");
+    Ok(())
+}
+
 fn health(client: ApiClient, args: HealthArgs) -> anyhow::Result<()> {
     let HealthArgs { id } = args;
     let response: HealthResponse = client.get(&format!("/api/v1/workloads/{id}/health"))?;
-    let HealthResponse { https, bootstrapped, last_event, .. } = response;
+    let HealthResponse { https, bootstrapped, last_event, bootstrap, certificate, paused, drift } = response;
+    if paused {
+        println!("{}", Color::Yellow.paint("workload is paused"));
+        return Ok(());
+    }
     let color = bool_to_color(bootstrapped);
     println!("bootstrapped: {}", color.paint(bootstrapped.to_string()));
 
+    if let Some(BootstrapStatus { step, running, error }) = bootstrap {
+        println!("bootstrap:    {step:?} (running: {running})");
+        if let Some(error) = error {
+            println!("{}", Color::Red.paint(format!("bootstrap failed: {error}")));
+        }
+    }
+
     let color = bool_to_color(https);
     println!("https up:     {}", color.paint(https.to_string()));
+    if https {
+        match client.get::<TlsInfoResponse>(&format!("/api/v1/workloads/{id}/tls")) {
+            Ok(TlsInfoResponse { fingerprint, observed_at, .. }) => {
+                println!("tls fingerprint: {} (since {observed_at})", hex::encode(fingerprint));
+            }
+            Err(e) => println!("{}", Color::Yellow.paint(format!("tls fingerprint unavailable: {e}"))),
+        }
+    }
+    if let Some(CertificateStatus { expires_at, days_to_expiry, renewal_error }) = certificate {
+        let color = if renewal_error.is_some() { Color::Yellow } else { Color::Green };
+        println!("tls expiry:   {} ({expires_at})", color.paint(format!("{days_to_expiry} days")));
+        if let Some(error) = renewal_error {
+            println!("{}", Color::Yellow.paint(format!("certificate renewal failing: {error}")));
+        }
+    }
+    if let Some(ComposeDrift { checked_at, findings }) = drift {
+        let color = bool_to_color(findings.is_empty());
+        println!("compose drift: {} (checked at {checked_at})", color.paint(findings.len().to_string()));
+        for DriftFinding { container, service, reason } in findings {
+            let service = service.map(|s| format!(" (service {s})")).unwrap_or_default();
+            println!("{}", Color::Red.paint(format!("- {container}{service}: {reason}")));
+        }
+    }
 
     if let Some(last_event) = last_event {
         let LastEvent { message, timestamp, kind, .. } = last_event;
@@ -511,6 +1568,72 @@ fn health(client: ApiClient, args: HealthArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn wait_workload(client: ApiClient, args: WaitArgs) -> anyhow::Result<()> {
+    let WaitArgs { id, timeout } = args;
+    wait_for_workload(&client, id, timeout)
+}
+
+/// Wait until a workload is ready, failing if it doesn't become ready within the timeout or if bootstrapping fails.
+fn wait_for_workload(client: &ApiClient, id: Uuid, timeout: Duration) -> anyhow::Result<()> {
+    // The agent blocks for at most the requested time, plus however long its last check takes.
+    const REQUEST_MARGIN: Duration = Duration::from_secs(30);
+
+    let deadline = Instant::now() + timeout;
+    println!("Waiting up to {}s for workload {id} to be ready", timeout.as_secs());
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let query = WaitWorkloadQuery { timeout_seconds: remaining.as_secs().clamp(1, MAX_WAIT_TIMEOUT_SECONDS) };
+        let request_timeout = Duration::from_secs(query.timeout_seconds) + REQUEST_MARGIN;
+        let response: WaitWorkloadResponse =
+            client.get_query_timeout(&format!("/api/v1/workloads/{id}/wait"), &query, request_timeout)?;
+        let WaitWorkloadResponse { ready, bootstrapped, https, converged, bootstrap_error, last_events } = response;
+        if ready {
+            println!("{}", Color::Green.paint(format!("Workload {id} is ready")));
+            return Ok(());
+        }
+        if bootstrap_error.is_none() && Instant::now() < deadline {
+            continue;
+        }
+        for event in last_events {
+            println!("{}", Color::Red.paint(format!("cvm reported {event}")));
+        }
+        match bootstrap_error {
+            Some(error) => bail!("workload failed to bootstrap: {error}"),
+            None => bail!(
+                "timed out waiting for workload (bootstrapped: {bootstrapped}, https: {https}, converged: {converged})"
+            ),
+        }
+    }
+}
+
+/// Print a workload's provisioning steps as it reaches them, failing if it doesn't become ready within the timeout or
+/// if bootstrapping fails.
+fn watch_workload_progress(client: &ApiClient, id: Uuid, timeout: Duration) -> anyhow::Result<()> {
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+    let deadline = Instant::now() + timeout;
+    let mut printed = 0;
+    loop {
+        let response: WorkloadProgressResponse = client.get(&format!("/api/v1/workloads/{id}/progress"))?;
+        let WorkloadProgressResponse { steps, ready, error } = response;
+        for event in steps.iter().skip(printed) {
+            println!("{} {:?}", event.timestamp.format("%H:%M:%S"), event.step);
+        }
+        printed = printed.max(steps.len());
+        if ready {
+            println!("{}", Color::Green.paint(format!("Workload {id} is ready")));
+            return Ok(());
+        }
+        if let Some(error) = error {
+            bail!("workload failed to bootstrap: {error}");
+        }
+        if Instant::now() + POLL_INTERVAL > deadline {
+            bail!("timed out waiting for workload");
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
 fn list_containers(client: ApiClient, args: ListContainersArgs) -> anyhow::Result<()> {
     let ListContainersArgs { id } = args;
     let containers: Vec<Container> = client.get(&format!("/api/v1/workloads/{id}/containers/list"))?;
@@ -519,12 +1642,171 @@ fn list_containers(client: ApiClient, args: ListContainersArgs) -> anyhow::Resul
     Ok(())
 }
 
+fn compose_state(client: ApiClient, args: ComposeStateArgs) -> anyhow::Result<()> {
+    let ComposeStateArgs { id } = args;
+    let response: ComposeStateResponse = client.get(&format!("/api/v1/workloads/{id}/containers/compose-state"))?;
+    let ComposeStateResponse { converged, services, last_error } = response;
+    println!("converged: {}", bool_to_color(converged).paint(converged.to_string()));
+    for service in services {
+        let ComposeServiceState { service, desired_state, actual_state, restart_count, health, error } = service;
+        let color = match actual_state {
+            _ if actual_state == desired_state => Color::Green,
+            ServiceState::Restarting => Color::Yellow,
+            _ => Color::Red,
+        };
+        let mut details = format!("{actual_state:?} (desired {desired_state:?}), {restart_count} restarts");
+        if let Some(health) = health {
+            details.push_str(&format!(", {health}"));
+        }
+        println!("  * {service}: {}", color.paint(details));
+        if let Some(error) = error {
+            println!("    {}", Color::Red.paint(error));
+        }
+    }
+    if let Some(LastEvent { message, timestamp, .. }) = last_error {
+        println!("{}", Color::Red.paint(format!("last error at {timestamp}: {message}")));
+    }
+    Ok(())
+}
+
 fn container_logs(client: ApiClient, args: ContainerLogsArgs) -> anyhow::Result<()> {
     let ContainerLogsArgs { id, container, head, stderr, max_lines } = args;
     let stream = if stderr { OutputStream::Stderr } else { OutputStream::Stdout };
     let request = ContainerLogsRequest { container, tail: !head, stream, max_lines };
-    let response: ContainerLogsResponse =
+    let response: MaybeEncrypted<ContainerLogsResponse> =
         client.get_query(&format!("/api/v1/workloads/{id}/containers/logs"), &request)?;
+    let Some(response) = plaintext_or_print(response) else {
+        return Ok(());
+    };
+    for line in response.lines {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+fn restart_container(client: ApiClient, args: RestartContainerArgs) -> anyhow::Result<()> {
+    let RestartContainerArgs { id, service } = args;
+    let request = RestartContainerRequest { service };
+    let _: () = client.post(&format!("/api/v1/workloads/{id}/containers/restart"), &request)?;
+    println!("Service {} restarted", request.service);
+    Ok(())
+}
+
+fn port_forward(client: ApiClient, args: PortForwardArgs) -> anyhow::Result<()> {
+    let PortForwardArgs { id, target, local_port } = args;
+    let local_port = local_port.unwrap_or(target.port);
+    let (url, authorization) = client.websocket_endpoint(&format!("/api/v1/workloads/{id}/port-forward"), &target)?;
+    let runtime = tokio::runtime::Runtime::new().context("Failed to create runtime")?;
+    runtime.block_on(async move {
+        let listener =
+            TcpListener::bind((Ipv4Addr::LOCALHOST, local_port)).await.context("Failed to bind local port")?;
+        println!("Forwarding 127.0.0.1:{local_port} to port {} in container {}", target.port, target.container);
+        loop {
+            let (stream, peer) = listener.accept().await.context("Failed to accept connection")?;
+            let url = url.clone();
+            let authorization = authorization.clone();
+            tokio::spawn(async move {
+                println!("Handling connection from {peer}");
+                match forward_connection(stream, &url, authorization).await {
+                    Ok(()) => println!("Connection from {peer} closed"),
+                    Err(e) => eprintln!("Connection from {peer} failed: {e:#}"),
+                }
+            });
+        }
+    })
+}
+
+async fn forward_connection(stream: TcpStream, url: &str, authorization: HeaderValue) -> anyhow::Result<()> {
+    let (reader, writer) = stream.into_split();
+    tunnel_websocket(reader, writer, url, authorization).await
+}
+
+fn console(client: ApiClient, args: ConsoleArgs) -> anyhow::Result<()> {
+    let ConsoleArgs { id } = args;
+    let (url, authorization) = client.websocket_endpoint(&format!("/api/v1/workloads/{id}/console"), &())?;
+    let runtime = tokio::runtime::Runtime::new().context("Failed to create runtime")?;
+    eprintln!("Attached to the console of workload {id}, press Ctrl-D to detach");
+    runtime.block_on(tunnel_websocket(stdin(), stdout(), &url, authorization))?;
+    eprintln!("Detached from console");
+    Ok(())
+}
+
+/// Pipe everything read from `reader` into a websocket as binary messages, and binary messages received into `writer`.
+async fn tunnel_websocket<R, W>(
+    mut reader: R,
+    mut writer: W,
+    url: &str,
+    authorization: HeaderValue,
+) -> anyhow::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut request = url.into_client_request()?;
+    request.headers_mut().insert(AUTHORIZATION, authorization);
+    let (socket, _) = connect_async(request).await.context("Failed to connect to agent")?;
+    let (mut sink, mut messages) = socket.split();
+    let inbound = async {
+        while let Some(message) = messages.next().await {
+            match message? {
+                Message::Binary(data) => {
+                    writer.write_all(&data).await?;
+                    writer.flush().await?;
+                }
+                Message::Close(_) => break,
+                _ => (),
+            }
+        }
+        writer.shutdown().await?;
+        anyhow::Ok(())
+    };
+    let outbound = async {
+        let mut buffer = vec![0; 16 * 1024];
+        loop {
+            let length = reader.read(&mut buffer).await?;
+            if length == 0 {
+                break;
+            }
+            sink.send(Message::binary(buffer[..length].to_vec())).await?;
+        }
+        sink.close().await?;
+        anyhow::Ok(())
+    };
+    tokio::try_join!(inbound, outbound)?;
+    Ok(())
+}
+
+fn list_jobs(client: ApiClient, args: ListJobsArgs) -> anyhow::Result<()> {
+    let ListJobsArgs { id } = args;
+    let jobs: Vec<JobStatus> = client.get(&format!("/api/v1/workloads/{id}/jobs/list"))?;
+    for job in jobs {
+        let JobStatus { name, state, exit_code, error } = job;
+        let color = match state {
+            JobState::Succeeded => Color::Green,
+            JobState::Pending | JobState::Running => Color::Yellow,
+            JobState::Failed => Color::Red,
+        };
+        let mut details = format!("{state:?}");
+        if let Some(exit_code) = exit_code {
+            details.push_str(&format!(", exit code {exit_code}"));
+        }
+        println!("  * {name}: {}", color.paint(details));
+        if let Some(error) = error {
+            println!("    {}", Color::Red.paint(error));
+        }
+    }
+    Ok(())
+}
+
+fn job_logs(client: ApiClient, args: JobLogsArgs) -> anyhow::Result<()> {
+    let JobLogsArgs { id, job, head, stderr, max_lines } = args;
+    let stream = if stderr { OutputStream::Stderr } else { OutputStream::Stdout };
+    let request = JobLogsRequest { job, tail: !head, stream, max_lines };
+    let response: MaybeEncrypted<ContainerLogsResponse> =
+        client.get_query(&format!("/api/v1/workloads/{id}/jobs/logs"), &request)?;
+    let Some(response) = plaintext_or_print(response) else {
+        return Ok(());
+    };
     for line in response.lines {
         println!("{line}");
     }
@@ -532,9 +1814,13 @@ fn container_logs(client: ApiClient, args: ContainerLogsArgs) -> anyhow::Result<
 }
 
 fn system_logs(client: ApiClient, args: SystemLogsArgs) -> anyhow::Result<()> {
-    let SystemLogsArgs { id, head, max_lines } = args;
-    let request = SystemLogsRequest { tail: !head, max_lines, source: SystemLogsSource::CvmAgent };
-    let response: SystemLogsResponse = client.get_query(&format!("/api/v1/workloads/{id}/system/logs"), &request)?;
+    let SystemLogsArgs { id, head, max_lines, source } = args;
+    let request = SystemLogsRequest { tail: !head, max_lines, source: source.into() };
+    let response: MaybeEncrypted<SystemLogsResponse> =
+        client.get_query(&format!("/api/v1/workloads/{id}/system/logs"), &request)?;
+    let Some(response) = plaintext_or_print(response) else {
+        return Ok(());
+    };
     for line in response.lines {
         println!("{line}");
     }
@@ -542,15 +1828,24 @@ fn system_logs(client: ApiClient, args: SystemLogsArgs) -> anyhow::Result<()> {
 }
 
 fn system_stats(client: ApiClient, args: SystemStatsArgs) -> anyhow::Result<()> {
-    let SystemStatsArgs { id } = args;
-    let response: SystemStatsResponse = client.get(&format!("/api/v1/workloads/{id}/system/stats"))?;
-    let SystemStatsResponse { memory, cpus, disks } = response;
+    let SystemStatsArgs { id, containers } = args;
+    let response: MaybeEncrypted<SystemStatsResponse> = client.get(&format!("/api/v1/workloads/{id}/system/stats"))?;
+    let Some(response) = plaintext_or_print(response) else {
+        return Ok(());
+    };
+    let SystemStatsResponse { memory, cpus, disks, log_disk_usage, clock_skew, gpus, oom_kills } = response;
     let memory_total = bytes_to_mb(memory.total);
     let memory_used = bytes_to_mb(memory.used);
     let color = percent_to_color((memory_used as f64) / (memory_total as f64));
     let details = format!("{memory_used}MB/{memory_total}MB");
 
     println!("Mem usage: {}", color.paint(details));
+    if let Some(MemoryPressure { some, full }) = memory.pressure {
+        let color = percent_to_color((some.avg60 / 100.0).into());
+        let details = format!("some {:.2}%, full {:.2}% (last 60s)", some.avg60, full.avg60);
+        println!("Mem pressure: {}", color.paint(details));
+    }
+    let cpu_count = cpus.len().max(1);
     println!("CPU usage:");
     for cpu in cpus {
         let CpuStats { name, usage, frequency } = cpu;
@@ -566,6 +1861,106 @@ fn system_stats(client: ApiClient, args: SystemStatsArgs) -> anyhow::Result<()>
         let details = format!("{:.2}GB/{:.2}GB", bytes_to_gb(used), bytes_to_gb(size));
         println!("  * {name} mounted at {mount_point} ({filesystem}): {}", color.paint(details));
     }
+    println!("Container logs: {}MB", bytes_to_mb(log_disk_usage));
+    if let Some(ClockSkew { skew_ms, uncertainty_ms, servers, measured_at }) = clock_skew {
+        println!("Clock skew: {skew_ms}ms (±{uncertainty_ms}ms, {servers} servers, measured at {measured_at})");
+    }
+    if let Some(GpusStats { cc_mode, devices }) = gpus {
+        println!("GPUs (CC mode: {}):", cc_mode.as_deref().unwrap_or("unknown"));
+        for gpu in devices {
+            let GpuStats { index, name, utilization, memory_used, memory_total, temperature } = gpu;
+            let usage_color = percent_to_color(utilization as f64 / 100.0);
+            let memory_color = percent_to_color(memory_used as f64 / memory_total as f64);
+            let memory = format!("{}MB/{}MB", bytes_to_mb(memory_used), bytes_to_mb(memory_total));
+            println!(
+                "  * {index} {name} ({temperature}°C): {}, memory {}",
+                usage_color.paint(format!("{utilization}%")),
+                memory_color.paint(memory)
+            );
+        }
+    }
+    if !oom_kills.is_empty() {
+        println!("OOM kills:");
+        for kill in oom_kills {
+            let OomKill { process, pid, container, detected_at } = kill;
+            let container = container.as_deref().unwrap_or("no container");
+            println!("  * {detected_at}: {process} (pid {pid}) in {container}");
+        }
+    }
+    if containers {
+        container_stats(&client, id, cpu_count)?;
+    }
+    Ok(())
+}
+
+fn container_stats(client: &ApiClient, id: Uuid, cpu_count: usize) -> anyhow::Result<()> {
+    let response: MaybeEncrypted<ContainersStatsResponse> =
+        client.get(&format!("/api/v1/workloads/{id}/containers/stats"))?;
+    let Some(response) = plaintext_or_print(response) else {
+        return Ok(());
+    };
+    println!("Containers:");
+    for container in response.containers {
+        let ContainerStats {
+            name,
+            service,
+            cpu_usage,
+            memory_used,
+            memory_limit,
+            network_rx,
+            network_tx,
+            block_read,
+            block_write,
+        } = container;
+        let name = match service {
+            Some(service) => format!("{name} ({service})"),
+            None => name,
+        };
+        let cpu_color = percent_to_color(cpu_usage / 100.0 / cpu_count as f64);
+        let memory_color = percent_to_color(memory_used as f64 / memory_limit as f64);
+        let memory = format!("{}MB/{}MB", bytes_to_mb(memory_used), bytes_to_mb(memory_limit));
+        println!(
+            "  * {name}: CPU {}, memory {}, network {}MB in/{}MB out, block IO {}MB read/{}MB written",
+            cpu_color.paint(format!("{cpu_usage:.1}%")),
+            memory_color.paint(memory),
+            bytes_to_mb(network_rx),
+            bytes_to_mb(network_tx),
+            bytes_to_mb(block_read),
+            bytes_to_mb(block_write),
+        );
+    }
+    Ok(())
+}
+
+fn usage(client: ApiClient, args: UsageArgs) -> anyhow::Result<()> {
+    let UsageArgs { id, from, to, csv } = args;
+    let request = WorkloadUsageRequest { from, to };
+    if csv {
+        let output = client.get_text(&format!("/api/v1/workloads/{id}/usage/export"), &request)?;
+        print!("{output}");
+        return Ok(());
+    }
+    let response: WorkloadUsageResponse = client.get_query(&format!("/api/v1/workloads/{id}/usage"), &request)?;
+    let WorkloadUsageResponse {
+        first_sample_at,
+        last_sample_at,
+        running_seconds,
+        cpu_hours,
+        gpu_hours,
+        memory_gb_hours,
+        disk_gb_hours,
+        ..
+    } = response;
+    let (Some(first_sample_at), Some(last_sample_at)) = (first_sample_at, last_sample_at) else {
+        println!("No usage recorded");
+        return Ok(());
+    };
+    println!("Samples from {first_sample_at} to {last_sample_at}");
+    println!("Running time:    {:.2} hours", running_seconds as f64 / 3600.0);
+    println!("CPU hours:       {cpu_hours:.2}");
+    println!("GPU hours:       {gpu_hours:.2}");
+    println!("Memory GB hours: {memory_gb_hours:.2}");
+    println!("Disk GB hours:   {disk_gb_hours:.2}");
     Ok(())
 }
 
@@ -629,6 +2024,19 @@ fn cleanup_artifacts(client: ApiClient) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn preview_artifacts_gc(client: ApiClient) -> anyhow::Result<()> {
+    let ArtifactsGcPreviewResponse { versions } = client.get("/api/v1/system/artifacts/gc/preview")?;
+    if versions.is_empty() {
+        println!("No versions would be deleted");
+    } else {
+        println!("{} versions would be deleted: ", versions.len());
+        for version in versions {
+            println!("- {version}");
+        }
+    }
+    Ok(())
+}
+
 fn upgrade_agent(client: ApiClient, args: UpgradeAgentArgs) -> anyhow::Result<()> {
     let UpgradeAgentArgs { version } = args;
     let request = InstallArtifactVersionRequest { version: version.clone() };
@@ -637,6 +2045,12 @@ fn upgrade_agent(client: ApiClient, args: UpgradeAgentArgs) -> anyhow::Result<()
     Ok(())
 }
 
+fn rollback_agent(client: ApiClient) -> anyhow::Result<()> {
+    let _: () = client.post("/api/v1/system/agent/rollback", &())?;
+    println!("Rollback scheduled");
+    Ok(())
+}
+
 fn agent_version(client: ApiClient) -> anyhow::Result<()> {
     let response: AgentVersionResponse = client.get("/api/v1/system/agent/version")?;
     let AgentVersionResponse { version, last_upgrade } = response;
@@ -645,22 +2059,121 @@ fn agent_version(client: ApiClient) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn verifier_keys(client: ApiClient) -> anyhow::Result<()> {
+fn agent_info(client: ApiClient) -> anyhow::Result<()> {
+    let info: SystemInfoResponse = client.get("/api/v1/system/info")?;
+    println!("{}", serde_json::to_string_pretty(&info).expect("failed to serialize"));
+    Ok(())
+}
+
+fn agent_capacity(client: ApiClient) -> anyhow::Result<()> {
+    let capacity: SystemCapacityResponse = client.get("/api/v1/system/capacity")?;
+    let SystemCapacityResponse {
+        free,
+        largest_workload,
+        largest_preempting_workload,
+        workload_slots,
+        exhausted_resource,
+    } = capacity;
+    let FreeResources { cpus, memory_mb, disk_space_gb, gpus, ports } = free;
+    let format_shape = |shape: Option<WorkloadShape>| match shape {
+        Some(WorkloadShape { cpus, memory_mb, disk_space_gb, gpus }) => {
+            format!("{cpus} CPUs / {memory_mb}MB memory / {disk_space_gb}GB disk / {gpus} GPUs")
+        }
+        None => Color::Red.paint("none").to_string(),
+    };
+    println!("Free: {cpus} CPUs / {memory_mb}MB memory / {disk_space_gb}GB disk / {gpus} GPUs / {ports} ports");
+    println!("Workload slots left: {workload_slots}");
+    println!("Largest workload: {}", format_shape(largest_workload));
+    println!("Largest workload when preempting low priority ones: {}", format_shape(largest_preempting_workload));
+    if let Some(resource) = exhausted_resource {
+        println!("{}", Color::Yellow.paint(format!("Not enough {resource} left for any workload")));
+    }
+    Ok(())
+}
+
+fn list_verifier_keys(client: ApiClient) -> anyhow::Result<()> {
     let keys: Vec<VerifierKey> = client.get("/api/v1/system/verifier/keys")?;
     for key in keys {
-        let status = if key.active { "active" } else { "inactive" };
-        let key = &key.public_key[1..];
-        let digest = Keccak256::digest(key);
-        let address = hex::encode(&digest[digest.len() - 20..]);
-        println!("- 0x{address} ({status})");
+        let VerifierKey { public_key, active, retired, workload_id, balance, last_heartbeat_at } = key;
+        let address = verifier_key_address(&public_key);
+        let status = match (retired, workload_id) {
+            (true, _) => Color::Red.paint("retired").to_string(),
+            (false, Some(workload_id)) => format!("{}, workload {workload_id}", Color::Green.paint("active")),
+            (false, None) if active => Color::Green.paint("active").to_string(),
+            (false, None) => "inactive".to_string(),
+        };
+        println!("- {address} ({status})");
+        if let Some(VerifierKeyBalance { eth, nil, observed_at }) = balance {
+            println!("  balance: {eth} ETH, {nil} NIL (as of {observed_at})");
+        }
+        if workload_id.is_some() {
+            match last_heartbeat_at {
+                Some(timestamp) => println!("  last heartbeat: {timestamp}"),
+                None => println!("  last heartbeat: {}", Color::Yellow.paint("none yet")),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rotate_verifier_key(client: ApiClient, args: VerifierKeyArgs) -> anyhow::Result<()> {
+    let public_key = resolve_verifier_key(&client, &args.key)?;
+    let request = RotateVerifierKeyRequest { public_key };
+    let response: RotateVerifierKeyResponse = client.post("/api/v1/system/verifier/keys/rotate", &request)?;
+    let RotateVerifierKeyResponse { workload_id, public_key } = response;
+    let address = verifier_key_address(&public_key);
+    println!("Workload {workload_id} moved over to key {address}, its VM is being restarted");
+    Ok(())
+}
+
+fn retire_verifier_key(client: ApiClient, args: VerifierKeyArgs) -> anyhow::Result<()> {
+    let public_key = resolve_verifier_key(&client, &args.key)?;
+    let request = RetireVerifierKeyRequest { public_key };
+    let _: () = client.post("/api/v1/system/verifier/keys/retire", &request)?;
+    println!("Key retired");
+    Ok(())
+}
+
+/// Resolve a key given either its address or its public key.
+fn resolve_verifier_key(client: &ApiClient, key: &str) -> anyhow::Result<Vec<u8>> {
+    let decoded = hex::decode(key.trim_start_matches("0x")).context("Invalid key")?;
+    if decoded.len() != 20 {
+        return Ok(decoded);
     }
+    let address = format!("0x{}", hex::encode(decoded));
+    let keys: Vec<VerifierKey> = client.get("/api/v1/system/verifier/keys")?;
+    keys.into_iter()
+        .find(|k| verifier_key_address(&k.public_key) == address)
+        .map(|k| k.public_key)
+        .ok_or_else(|| anyhow!("No key with address {address}"))
+}
+
+fn verifier_key_address(public_key: &[u8]) -> String {
+    let digest = Keccak256::digest(public_key.get(1..).unwrap_or_default());
+    format!("0x{}", hex::encode(&digest[digest.len() - 20..]))
+}
+
+fn zerossl_accounts(client: ApiClient) -> anyhow::Result<()> {
+    let accounts: Vec<ZeroSslAccount> = client.get("/api/v1/system/zerossl/accounts")?;
+    for account in accounts {
+        let ZeroSslAccount { eab_key_id, workloads, certificate_requests } = account;
+        println!("- {eab_key_id}: {workloads} workloads, {certificate_requests} certificate requests");
+    }
+    Ok(())
+}
+
+fn backup(client: ApiClient, args: BackupArgs) -> anyhow::Result<()> {
+    let BackupArgs { output } = args;
+    let mut file = File::create(&output).context("Failed to create output file")?;
+    let size = client.post_download("/api/v1/system/backup", &mut file)?;
+    println!("Wrote {size} bytes backup to {}", output.display());
     Ok(())
 }
 
 fn display_last_upgrade(last_upgrade: Option<LastUpgrade>) {
     match last_upgrade {
         Some(upgrade) => {
-            let LastUpgrade { version, started_at, state } = upgrade;
+            let LastUpgrade { version, started_at, state, sha256 } = upgrade;
             print!("Installation of version {version} was started at {started_at} ");
             match state {
                 UpgradeState::InProgress => println!("and is {}", Color::Yellow.paint("still in progress")),
@@ -671,11 +2184,25 @@ fn display_last_upgrade(last_upgrade: Option<LastUpgrade>) {
                     println!("and {} at {finished_at} with error: {error}", Color::Red.paint("failed"))
                 }
             }
+            if let Some(sha256) = sha256 {
+                println!("Binary SHA256: {sha256}");
+            }
         }
         None => println!("No version installs in progress"),
     };
 }
 
+fn plaintext_or_print<T>(payload: MaybeEncrypted<T>) -> Option<T> {
+    match payload {
+        MaybeEncrypted::Plaintext(payload) => Some(payload),
+        MaybeEncrypted::Encrypted { encrypted } => {
+            println!("{}", Color::Yellow.paint("Response is encrypted using the workload's log encryption key"));
+            println!("{}", serde_json::to_string_pretty(&encrypted).expect("failed to serialize"));
+            None
+        }
+    }
+}
+
 fn bytes_to_mb(bytes: u64) -> u64 {
     bytes / 1024 / 1024
 }
@@ -701,22 +2228,126 @@ fn bool_to_color(value: bool) -> Color {
     }
 }
 
-fn main() {
-    let cli = Cli::parse();
-    let Cli { url, api_key, command } = cli;
+fn set_context(path: &Path, args: SetContextArgs) -> anyhow::Result<()> {
+    let SetContextArgs { name, url, api_key, artifacts_version } = args;
+    let mut contexts = Contexts::load(path)?;
+    contexts.contexts.insert(name.clone(), context::Context { url, api_key, artifacts_version });
+    if contexts.current.is_none() {
+        contexts.current = Some(name.clone());
+    }
+    contexts.save(path)?;
+    println!("Context {name} saved");
+    Ok(())
+}
+
+fn use_context(path: &Path, args: UseContextArgs) -> anyhow::Result<()> {
+    let UseContextArgs { name } = args;
+    let mut contexts = Contexts::load(path)?;
+    contexts.select(Some(&name))?;
+    contexts.current = Some(name.clone());
+    contexts.save(path)?;
+    println!("Using context {name}");
+    Ok(())
+}
+
+fn list_contexts(path: &Path) -> anyhow::Result<()> {
+    let contexts = Contexts::load(path)?;
+    if contexts.contexts.is_empty() {
+        println!("No contexts found");
+        return Ok(());
+    }
+    for (name, context) in &contexts.contexts {
+        let marker = if contexts.current.as_ref() == Some(name) { "*" } else { " " };
+        let artifacts_version = context.artifacts_version.as_deref().unwrap_or("-");
+        println!("{marker} {name}: url = {}, artifacts version = {artifacts_version}", context.url);
+    }
+    Ok(())
+}
+
+fn parse_port_forward_target(target: &str) -> Result<PortForwardRequest, String> {
+    let (container, port) = target.rsplit_once(':').ok_or("expected <container>:<port>")?;
+    let port = port.parse().map_err(|_| format!("invalid port: {port}"))?;
+    if container.is_empty() {
+        return Err("container name can't be empty".into());
+    }
+    Ok(PortForwardRequest { container: container.into(), port })
+}
+
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let split = duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len());
+    let (value, unit) = duration.split_at(split);
+    let value: u64 = value.parse().map_err(|_| format!("invalid duration: {duration}"))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(format!("invalid duration unit: {unit}")),
+    };
+    Ok(Duration::from_secs(value * multiplier))
+}
+
+fn run_context_command(path: &Path, command: ContextCommand) -> anyhow::Result<()> {
+    match command {
+        ContextCommand::Set(args) => set_context(path, args),
+        ContextCommand::Use(args) => use_context(path, args),
+        ContextCommand::List => list_contexts(path),
+    }
+}
 
+fn run_command(
+    url: Option<String>,
+    api_key: Option<String>,
+    context: Option<String>,
+    contexts_file: Option<PathBuf>,
+    command: Command,
+) -> anyhow::Result<()> {
+    let contexts_file = contexts_file.or_else(default_contexts_path);
+    if let Command::Context(command) = command {
+        let contexts_file = contexts_file.ok_or(ContextError::NoContextsFile)?;
+        return run_context_command(&contexts_file, command);
+    }
+    if let Command::PublishBundle(args) = command {
+        // Publishing bundles only talks to the registry.
+        return publish_bundle(args);
+    }
+    let contexts = match &contexts_file {
+        Some(path) => Contexts::load(path)?,
+        // There's nowhere to load contexts from but the URL and API key may still be explicitly provided.
+        None => Contexts::default(),
+    };
+    let Connection { url, api_key, artifacts_version } =
+        Connection::resolve(&contexts, context.as_deref(), url, api_key)?;
     let client = ApiClient::new(url, &api_key);
-    let result = match command {
-        Command::Launch(args) => launch(client, args),
-        Command::List => list(client),
+    match command {
+        Command::Launch(args) => launch(client, args, artifacts_version),
+        Command::List(args) => list(client, args),
         Command::Delete(args) => delete(client, args),
         Command::Health(args) => health(client, args),
+        Command::Wait(args) => wait_workload(client, args),
         Command::Start(args) => start(client, args),
         Command::Stop(args) => stop(client, args),
+        Command::Pause(args) => pause(client, args),
+        Command::Resume(args) => resume(client, args),
+        Command::Console(args) => console(client, args),
         Command::Restart(args) => restart(client, args),
+        Command::ChangeDomain(args) => change_domain(client, args),
+        Command::EnvVars(args) => env_vars(client, args),
+        Command::Files(args) => files(client, args),
+        Command::DockerCredentials(args) => docker_credentials(client, args),
+        Command::ProxyTimeouts(args) => proxy_timeouts(client, args),
+        Command::Plan(args) => plan(client, args),
+        Command::Apply(args) => apply(client, args, artifacts_version),
+        Command::Usage(args) => usage(client, args),
         Command::Containers(command) => match command {
             ContainersCommand::List(args) => list_containers(client, args),
             ContainersCommand::Logs(args) => container_logs(client, args),
+            ContainersCommand::Restart(args) => restart_container(client, args),
+            ContainersCommand::State(args) => compose_state(client, args),
+            ContainersCommand::PortForward(args) => port_forward(client, args),
+        },
+        Command::Jobs(command) => match command {
+            JobsCommand::List(args) => list_jobs(client, args),
+            JobsCommand::Logs(args) => job_logs(client, args),
         },
         Command::System(command) => match command {
             SystemCommand::Logs(args) => system_logs(client, args),
@@ -728,10 +2359,27 @@ fn main() {
         Command::Admin(AdminCommand::Artifacts(AdminArtifactsCommand::Versions)) => artifacts_versions(client),
         Command::Admin(AdminCommand::Artifacts(AdminArtifactsCommand::Changelog)) => artifacts_changelog(client),
         Command::Admin(AdminCommand::Artifacts(AdminArtifactsCommand::Cleanup)) => cleanup_artifacts(client),
+        Command::Admin(AdminCommand::Artifacts(AdminArtifactsCommand::GcPreview)) => preview_artifacts_gc(client),
         Command::Admin(AdminCommand::Agent(AdminAgentCommand::Upgrade(args))) => upgrade_agent(client, args),
+        Command::Admin(AdminCommand::Agent(AdminAgentCommand::Rollback)) => rollback_agent(client),
         Command::Admin(AdminCommand::Agent(AdminAgentCommand::Version)) => agent_version(client),
-        Command::Admin(AdminCommand::Verifier(VerifierCommand::Keys)) => verifier_keys(client),
-    };
+        Command::Admin(AdminCommand::Agent(AdminAgentCommand::Info)) => agent_info(client),
+        Command::Admin(AdminCommand::Agent(AdminAgentCommand::Capacity)) => agent_capacity(client),
+        Command::Admin(AdminCommand::Verifier(VerifierCommand::Keys)) => list_verifier_keys(client),
+        Command::Admin(AdminCommand::Keys(AdminKeysCommand::List)) => list_verifier_keys(client),
+        Command::Admin(AdminCommand::Keys(AdminKeysCommand::Rotate(args))) => rotate_verifier_key(client, args),
+        Command::Admin(AdminCommand::Keys(AdminKeysCommand::Retire(args))) => retire_verifier_key(client, args),
+        Command::Admin(AdminCommand::Zerossl(ZeroSslCommand::Accounts)) => zerossl_accounts(client),
+        Command::Admin(AdminCommand::Backup(args)) => backup(client, args),
+        Command::Context(_) => unreachable!("context commands are handled above"),
+        Command::PublishBundle(_) => unreachable!("publish bundle commands are handled above"),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let Cli { url, api_key, context, contexts_file, command } = cli;
+    let result = run_command(url, api_key, context, contexts_file, command);
     if let Err(e) = result {
         eprintln!("Failed to run command: {e:#}");
         exit(1);
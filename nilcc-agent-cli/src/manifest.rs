@@ -0,0 +1,241 @@
+use nilcc_agent_models::workloads::list::WorkloadSummary;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// A declarative description of a workload.
+///
+/// Paths are relative to the directory the manifest is in.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    id: Uuid,
+    domain: String,
+    docker_compose: PathBuf,
+    entrypoint: ManifestEntrypoint,
+    #[serde(default)]
+    artifacts_version: Option<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    files: HashMap<String, PathBuf>,
+    #[serde(default)]
+    resources: ManifestResources,
+}
+
+/// The container requests are forwarded to.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntrypoint {
+    pub container: String,
+    pub port: u16,
+}
+
+/// The resources a workload uses.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestResources {
+    #[serde(default = "default_cpus")]
+    pub cpus: u32,
+    #[serde(default = "default_memory_mb")]
+    pub memory_mb: u32,
+    #[serde(default = "default_disk_space_gb")]
+    pub disk_space_gb: u32,
+    #[serde(default)]
+    pub gpus: u16,
+}
+
+impl Default for ManifestResources {
+    fn default() -> Self {
+        Self { cpus: default_cpus(), memory_mb: default_memory_mb(), disk_space_gb: default_disk_space_gb(), gpus: 0 }
+    }
+}
+
+fn default_cpus() -> u32 {
+    1
+}
+
+fn default_memory_mb() -> u32 {
+    2048
+}
+
+fn default_disk_space_gb() -> u32 {
+    10
+}
+
+/// A workload manifest along with the contents of the files it references.
+pub struct WorkloadManifest {
+    pub id: Uuid,
+    pub domain: String,
+    pub docker_compose: String,
+    pub entrypoint: ManifestEntrypoint,
+    pub artifacts_version: Option<String>,
+    pub env: HashMap<String, String>,
+    pub files: HashMap<String, Vec<u8>>,
+    pub resources: ManifestResources,
+}
+
+impl WorkloadManifest {
+    /// Load a manifest and the files it references.
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        let contents = fs::read_to_string(path).map_err(|e| ManifestError::Read(path.into(), e))?;
+        let ManifestFile { id, domain, docker_compose, entrypoint, artifacts_version, env, files, resources } =
+            serde_yaml::from_str(&contents)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        let read = |path: &Path| {
+            let path = base.join(path);
+            fs::read(&path).map_err(|e| ManifestError::Read(path, e))
+        };
+        let docker_compose = String::from_utf8(read(&docker_compose)?).map_err(|_| ManifestError::ComposeEncoding)?;
+        let files = files
+            .into_iter()
+            .map(|(name, path)| Ok::<_, ManifestError>((name, read(&path)?)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { id, domain, docker_compose, entrypoint, artifacts_version, env, files, resources })
+    }
+}
+
+/// What applying a manifest does.
+pub enum Plan {
+    /// The workload doesn't exist and is created.
+    Create,
+
+    /// The workload exists and is updated in place, or left untouched if nothing changed.
+    Update { domain: Option<String>, env: EntryChanges, files: EntryChanges },
+
+    /// Settings that can't be changed in place differ, so the workload has to be deleted and created again.
+    Recreate { fields: Vec<&'static str> },
+}
+
+impl Plan {
+    /// Compare a manifest against the workload's current state.
+    pub fn new(manifest: &WorkloadManifest, current: Option<&WorkloadSummary>) -> Self {
+        let Some(current) = current else {
+            return Self::Create;
+        };
+        let mut fields = Vec::new();
+        let resources = &manifest.resources;
+        let checks = [
+            ("docker_compose", current.docker_compose_hash != Some(sha256(manifest.docker_compose.as_bytes()))),
+            ("entrypoint.container", current.public_container_name != manifest.entrypoint.container),
+            ("entrypoint.port", current.public_container_port != manifest.entrypoint.port),
+            ("resources.cpus", current.cpus != resources.cpus),
+            ("resources.memory_mb", current.memory_mb != resources.memory_mb),
+            ("resources.disk_space_gb", current.disk_space_gb != resources.disk_space_gb),
+            ("resources.gpus", current.gpus != resources.gpus),
+            (
+                "artifacts_version",
+                manifest.artifacts_version.as_ref().is_some_and(|version| *version != current.artifacts_version),
+            ),
+        ];
+        for (field, changed) in checks {
+            if changed {
+                fields.push(field);
+            }
+        }
+        if !fields.is_empty() {
+            return Self::Recreate { fields };
+        }
+        let domain = (current.domain != manifest.domain).then(|| manifest.domain.clone());
+        let env = manifest.env.iter().map(|(name, value)| (name, value.as_bytes()));
+        let files = manifest.files.iter().map(|(name, contents)| (name, contents.as_slice()));
+        Self::Update {
+            domain,
+            env: EntryChanges::new(env, &current.env_var_hashes),
+            files: EntryChanges::new(files, &current.file_hashes),
+        }
+    }
+
+    /// Whether applying the manifest doesn't change anything.
+    pub fn is_noop(&self) -> bool {
+        match self {
+            Self::Update { domain, env, files } => domain.is_none() && env.is_empty() && files.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Create => write!(f, "create"),
+            Self::Recreate { fields } => write!(f, "recreate, changed: {}", fields.join(", ")),
+            Self::Update { .. } if self.is_noop() => write!(f, "no changes"),
+            Self::Update { domain, env, files } => {
+                write!(f, "update")?;
+                if let Some(domain) = domain {
+                    write!(f, "\n  domain: {domain}")?;
+                }
+                if !env.is_empty() {
+                    write!(f, "\n  environment variables: {env}")?;
+                }
+                if !files.is_empty() {
+                    write!(f, "\n  files: {files}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The named entries, like environment variables or files, that differ between a manifest and a workload.
+#[derive(Default)]
+pub struct EntryChanges {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl EntryChanges {
+    fn new<'a>(desired: impl Iterator<Item = (&'a String, &'a [u8])>, current: &HashMap<String, [u8; 32]>) -> Self {
+        let desired: BTreeMap<_, _> = desired.map(|(name, value)| (name, sha256(value))).collect();
+        let mut changes = Self::default();
+        for (name, hash) in &desired {
+            match current.get(*name) {
+                None => changes.added.push(name.to_string()),
+                Some(current) if current != hash => changes.changed.push(name.to_string()),
+                Some(_) => (),
+            }
+        }
+        changes.removed = current.keys().filter(|name| !desired.contains_key(name)).cloned().collect();
+        changes.removed.sort();
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for EntryChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [("added", &self.added), ("changed", &self.changed), ("removed", &self.removed)];
+        let sections: Vec<_> = sections
+            .into_iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(label, names)| format!("{label} {}", names.join(", ")))
+            .collect();
+        write!(f, "{}", sections.join("; "))
+    }
+}
+
+fn sha256(value: &[u8]) -> [u8; 32] {
+    Sha256::digest(value).into()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("reading {}: {}", .0.display(), .1)]
+    Read(PathBuf, io::Error),
+
+    #[error("invalid manifest: {0}")]
+    Serde(#[from] serde_yaml::Error),
+
+    #[error("docker compose file is not valid UTF-8")]
+    ComposeEncoding,
+}
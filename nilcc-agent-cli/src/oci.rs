@@ -0,0 +1,380 @@
+use anyhow::{Context, anyhow, bail};
+use reqwest::{
+    StatusCode,
+    blocking::{Client, RequestBuilder, Response},
+    header::{ACCEPT, CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// The artifact type of a workload bundle.
+const BUNDLE_ARTIFACT_TYPE: &str = "application/vnd.nilcc.workload.v1";
+
+/// The media type of a bundle's metadata, which is stored as the artifact's config.
+const METADATA_MEDIA_TYPE: &str = "application/vnd.nilcc.workload.metadata.v1+json";
+
+/// The media type of the layer that contains a bundle's docker compose file.
+const COMPOSE_MEDIA_TYPE: &str = "application/vnd.nilcc.workload.compose.v1+yaml";
+
+/// The media type of the layers that contain the files bundled with a workload.
+const FILE_MEDIA_TYPE: &str = "application/vnd.nilcc.workload.file.v1";
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// The annotation a layer's file name is stored in, as used by `oras`.
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+/// A reference to an artifact in a registry, like `registry.example.com/team/app:1.2.3`.
+///
+/// A reference can pin the artifact's digest via `@sha256:<digest>`, in which case the artifact is only accepted if
+/// its manifest matches it.
+#[derive(Clone)]
+pub struct OciReference {
+    registry: String,
+    repository: String,
+    tag: Option<String>,
+    digest: Option<String>,
+}
+
+impl OciReference {
+    fn manifest_reference(&self) -> &str {
+        self.digest.as_deref().or(self.tag.as_deref()).expect("no tag nor digest")
+    }
+
+    /// This reference, pinned to the given digest.
+    pub fn pinned(&self, digest: &str) -> Self {
+        Self { digest: Some(digest.into()), ..self.clone() }
+    }
+
+    fn base_url(&self) -> String {
+        // Local registries are typically served over plain HTTP.
+        let scheme = if self.registry.starts_with("localhost") || self.registry.starts_with("127.0.0.1") {
+            "http"
+        } else {
+            "https"
+        };
+        let host = match self.registry.as_str() {
+            "docker.io" => "registry-1.docker.io",
+            registry => registry,
+        };
+        format!("{scheme}://{host}/v2/{}", self.repository)
+    }
+}
+
+impl FromStr for OciReference {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => {
+                let hash = digest.strip_prefix("sha256:").ok_or("only sha256 digests are supported")?;
+                if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err("invalid sha256 digest");
+                }
+                (name, Some(digest.to_string()))
+            }
+            None => (s, None),
+        };
+        let (registry, path) = name.split_once('/').ok_or("missing registry")?;
+        if !registry.contains(['.', ':']) && registry != "localhost" {
+            return Err("missing registry");
+        }
+        let (repository, tag) = match path.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag.to_string())),
+            _ => (path, None),
+        };
+        if repository.is_empty() {
+            return Err("missing repository");
+        }
+        if tag.is_none() && digest.is_none() {
+            return Err("missing tag or digest");
+        }
+        Ok(Self { registry: registry.into(), repository: repository.into(), tag, digest })
+    }
+}
+
+impl fmt::Display for OciReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { registry, repository, tag, digest } = self;
+        write!(f, "{registry}/{repository}")?;
+        if let Some(tag) = tag {
+            write!(f, ":{tag}")?;
+        }
+        if let Some(digest) = digest {
+            write!(f, "@{digest}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The metadata stored in a workload bundle.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleMetadata {
+    /// The container requests are forwarded to.
+    pub entrypoint: Option<BundleEntrypoint>,
+
+    /// The artifacts version the workload was built for.
+    pub artifacts_version: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BundleEntrypoint {
+    pub container: String,
+    pub port: u16,
+}
+
+/// A workload definition distributed as an OCI artifact.
+pub struct WorkloadBundle {
+    pub metadata: BundleMetadata,
+    pub docker_compose: String,
+    pub files: HashMap<String, Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+    size: u64,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    annotations: HashMap<String, String>,
+}
+
+impl Descriptor {
+    fn new(media_type: &str, contents: &[u8]) -> Self {
+        Self {
+            media_type: media_type.into(),
+            digest: digest(contents),
+            size: contents.len() as u64,
+            annotations: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    schema_version: u32,
+    #[serde(default)]
+    media_type: Option<String>,
+    #[serde(default)]
+    artifact_type: Option<String>,
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+}
+
+/// A client for a registry that implements the OCI distribution spec.
+pub struct OciClient {
+    client: Client,
+    reference: OciReference,
+    credentials: Option<(String, String)>,
+    // The token to use if the registry uses token authentication.
+    token: Option<String>,
+}
+
+impl OciClient {
+    pub fn new(reference: OciReference, credentials: Option<(String, String)>) -> Self {
+        Self { client: Client::new(), reference, credentials, token: None }
+    }
+
+    /// Pull a workload bundle, verifying it matches the reference's digest if it's pinned.
+    ///
+    /// Returns the bundle along with the digest of its manifest.
+    pub fn pull_bundle(&mut self) -> anyhow::Result<(WorkloadBundle, String)> {
+        let url = format!("{}/manifests/{}", self.reference.base_url(), self.reference.manifest_reference());
+        let response = self.send("pull", |client| client.get(&url).header(ACCEPT, MANIFEST_MEDIA_TYPE))?;
+        let manifest_bytes = response.bytes().context("Failed to read manifest")?;
+        let manifest_digest = digest(&manifest_bytes);
+        match &self.reference.digest {
+            Some(expected) if expected != &manifest_digest => {
+                bail!("manifest digest mismatch: expected {expected}, got {manifest_digest}")
+            }
+            _ => (),
+        }
+        let manifest: Manifest = serde_json::from_slice(&manifest_bytes).context("Invalid manifest")?;
+        let is_bundle = manifest.artifact_type.as_deref() == Some(BUNDLE_ARTIFACT_TYPE)
+            || manifest.config.media_type == METADATA_MEDIA_TYPE;
+        if !is_bundle {
+            bail!("{} is not a workload bundle", self.reference);
+        }
+
+        let metadata = self.pull_blob(&manifest.config)?;
+        let metadata = serde_json::from_slice(&metadata).context("Invalid bundle metadata")?;
+        let mut docker_compose = None;
+        let mut files = HashMap::new();
+        for layer in &manifest.layers {
+            match layer.media_type.as_str() {
+                COMPOSE_MEDIA_TYPE => {
+                    let contents = self.pull_blob(layer)?;
+                    docker_compose = Some(String::from_utf8(contents).context("Docker compose is not valid UTF-8")?);
+                }
+                FILE_MEDIA_TYPE => {
+                    let name = layer
+                        .annotations
+                        .get(TITLE_ANNOTATION)
+                        .ok_or_else(|| anyhow!("layer {} has no file name", layer.digest))?;
+                    files.insert(name.clone(), self.pull_blob(layer)?);
+                }
+                other => bail!("unsupported layer media type {other}"),
+            }
+        }
+        let docker_compose = docker_compose.context("Bundle has no docker compose file")?;
+        Ok((WorkloadBundle { metadata, docker_compose, files }, manifest_digest))
+    }
+
+    /// Push a workload bundle to the reference's tag, returning the digest of its manifest.
+    pub fn push_bundle(&mut self, bundle: &WorkloadBundle) -> anyhow::Result<String> {
+        let tag = match (&self.reference.tag, &self.reference.digest) {
+            (Some(tag), None) => tag.clone(),
+            _ => bail!("bundles can only be pushed to a tag"),
+        };
+        let metadata = serde_json::to_vec(&bundle.metadata).expect("failed to serialize");
+        let config = self.push_blob(METADATA_MEDIA_TYPE, &metadata)?;
+        let mut layers = vec![self.push_blob(COMPOSE_MEDIA_TYPE, bundle.docker_compose.as_bytes())?];
+        let mut files: Vec<_> = bundle.files.iter().collect();
+        files.sort_by_key(|(name, _)| *name);
+        for (name, contents) in files {
+            let mut layer = self.push_blob(FILE_MEDIA_TYPE, contents)?;
+            layer.annotations.insert(TITLE_ANNOTATION.into(), name.clone());
+            layers.push(layer);
+        }
+        let manifest = Manifest {
+            schema_version: 2,
+            media_type: Some(MANIFEST_MEDIA_TYPE.into()),
+            artifact_type: Some(BUNDLE_ARTIFACT_TYPE.into()),
+            config,
+            layers,
+        };
+        let manifest = serde_json::to_vec(&manifest).expect("failed to serialize");
+        let url = format!("{}/manifests/{tag}", self.reference.base_url());
+        self.send("pull,push", |client| {
+            client.put(&url).header(CONTENT_TYPE, MANIFEST_MEDIA_TYPE).body(manifest.clone())
+        })?;
+        Ok(digest(&manifest))
+    }
+
+    fn pull_blob(&mut self, descriptor: &Descriptor) -> anyhow::Result<Vec<u8>> {
+        let url = format!("{}/blobs/{}", self.reference.base_url(), descriptor.digest);
+        let response = self.send("pull", |client| client.get(&url))?;
+        let contents = response.bytes().context("Failed to read blob")?.to_vec();
+        if digest(&contents) != descriptor.digest || contents.len() as u64 != descriptor.size {
+            bail!("blob {} doesn't match its descriptor", descriptor.digest);
+        }
+        Ok(contents)
+    }
+
+    fn push_blob(&mut self, media_type: &str, contents: &[u8]) -> anyhow::Result<Descriptor> {
+        let descriptor = Descriptor::new(media_type, contents);
+        let base_url = self.reference.base_url();
+        let url = format!("{base_url}/blobs/{}", descriptor.digest);
+        let exists = self.send("pull,push", |client| client.head(&url));
+        if exists.is_ok() {
+            return Ok(descriptor);
+        }
+
+        let url = format!("{base_url}/blobs/uploads/");
+        let response = self.send("pull,push", |client| client.post(&url))?;
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .context("No upload location returned")?;
+        // The location can be relative to the registry's host.
+        let mut upload_url = match location.strip_prefix('/') {
+            Some(path) => {
+                let host_url = base_url.split("/v2/").next().expect("no host");
+                format!("{host_url}/{path}")
+            }
+            None => location.to_string(),
+        };
+        upload_url.push(if upload_url.contains('?') { '&' } else { '?' });
+        upload_url.push_str(&format!("digest={}", descriptor.digest));
+        self.send("pull,push", |client| {
+            client.put(&upload_url).header(CONTENT_TYPE, "application/octet-stream").body(contents.to_vec())
+        })?;
+        Ok(descriptor)
+    }
+
+    /// Send a request, authenticating and sending it again if the registry requires it.
+    fn send(&mut self, actions: &str, request: impl Fn(&Client) -> RequestBuilder) -> anyhow::Result<Response> {
+        let response = self.authorize(request(&self.client)).send()?;
+        let response = match response.status() {
+            StatusCode::UNAUTHORIZED => {
+                let challenge = response
+                    .headers()
+                    .get(WWW_AUTHENTICATE)
+                    .and_then(|challenge| challenge.to_str().ok())
+                    .unwrap_or_default();
+                if let Some(params) = challenge.strip_prefix("Bearer ") {
+                    self.token = Some(self.fetch_token(params, actions)?);
+                } else if self.credentials.is_none() {
+                    bail!("registry requires credentials");
+                }
+                self.authorize(request(&self.client)).send()?
+            }
+            _ => response,
+        };
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            bail!("registry returned {status}: {}", body.trim());
+        }
+        Ok(response)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match (&self.token, &self.credentials) {
+            (Some(token), _) => request.bearer_auth(token),
+            (None, Some((username, password))) => request.basic_auth(username, Some(password)),
+            (None, None) => request,
+        }
+    }
+
+    fn fetch_token(&self, challenge: &str, actions: &str) -> anyhow::Result<String> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+
+        let params = parse_challenge(challenge);
+        let realm = params.get("realm").context("No realm in authentication challenge")?;
+        let mut query = vec![("scope", format!("repository:{}:{actions}", self.reference.repository))];
+        if let Some(service) = params.get("service") {
+            query.push(("service", service.clone()));
+        }
+        let mut request = self.client.get(realm).query(&query);
+        if let Some((username, password)) = &self.credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        let response = request.send()?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("failed to get registry token: {status}");
+        }
+        let response: TokenResponse = response.json().context("Invalid token response")?;
+        response.token.or(response.access_token).context("No token in token response")
+    }
+}
+
+/// Parse the parameters in a challenge like `realm="https://auth.example.com/token",service="registry.example.com"`.
+fn parse_challenge(challenge: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut rest = challenge.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(value) => value.split_once('"').unwrap_or((value, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        params.insert(key, value.to_string());
+        rest = remaining;
+    }
+    params
+}
+
+fn digest(contents: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(contents)))
+}
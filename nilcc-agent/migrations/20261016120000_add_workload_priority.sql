@@ -0,0 +1,4 @@
+-- Add `priority` and `preempted` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN priority TEXT NOT NULL DEFAULT '"normal"';
+ALTER TABLE workloads ADD COLUMN preempted BOOLEAN NOT NULL DEFAULT FALSE;
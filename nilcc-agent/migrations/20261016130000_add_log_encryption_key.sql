@@ -0,0 +1,3 @@
+-- Add `log_encryption_key` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN log_encryption_key BLOB;
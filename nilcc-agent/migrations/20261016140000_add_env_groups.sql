@@ -0,0 +1,10 @@
+-- Add `env_groups` to `workloads` table and create a table to cache env groups.
+
+ALTER TABLE workloads ADD COLUMN env_groups TEXT NOT NULL DEFAULT '[]';
+
+CREATE TABLE env_groups (
+  name VARCHAR(64) PRIMARY KEY,
+  version INTEGER NOT NULL,
+  env_vars TEXT NOT NULL,
+  updated_at DATETIME WITH TIMEZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
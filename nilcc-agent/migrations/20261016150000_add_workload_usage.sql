@@ -0,0 +1,13 @@
+-- Create a table to track the resources allocated to workloads over time.
+
+CREATE TABLE workload_usage (
+  workload_id VARCHAR(36) NOT NULL,
+  sampled_at DATETIME WITH TIMEZONE NOT NULL,
+  duration_seconds INTEGER NOT NULL,
+  cpus INTEGER NOT NULL,
+  gpus INTEGER NOT NULL,
+  memory_mb INTEGER NOT NULL,
+  disk_space_gb INTEGER NOT NULL
+);
+
+CREATE INDEX workload_usage_workload_id_sampled_at ON workload_usage (workload_id, sampled_at);
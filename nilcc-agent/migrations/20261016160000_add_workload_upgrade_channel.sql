@@ -0,0 +1,3 @@
+-- Add `upgrade_channel` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN upgrade_channel TEXT NOT NULL DEFAULT '"pinned"';
@@ -0,0 +1,3 @@
+-- Add `log_rotation` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN log_rotation TEXT NOT NULL DEFAULT 'null';
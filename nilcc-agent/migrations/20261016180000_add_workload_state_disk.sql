@@ -0,0 +1,3 @@
+-- Add `state_disk` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN state_disk TEXT NOT NULL DEFAULT '"ephemeral"';
@@ -0,0 +1,3 @@
+-- Add `zerossl_account` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN zerossl_account TEXT;
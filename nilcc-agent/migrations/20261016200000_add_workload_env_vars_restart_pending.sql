@@ -0,0 +1,3 @@
+-- Add `env_vars_restart_pending` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN env_vars_restart_pending BOOLEAN NOT NULL DEFAULT FALSE;
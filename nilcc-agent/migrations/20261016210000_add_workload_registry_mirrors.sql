@@ -0,0 +1,3 @@
+-- Add `registry_mirrors` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN registry_mirrors TEXT NOT NULL DEFAULT 'null';
@@ -0,0 +1,6 @@
+-- Create a table to keep track of verifier keys that must never be handed out again.
+
+CREATE TABLE retired_verifier_keys (
+  public_key BLOB PRIMARY KEY,
+  retired_at DATETIME WITH TIMEZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- Add `labels` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN labels TEXT NOT NULL DEFAULT '{}';
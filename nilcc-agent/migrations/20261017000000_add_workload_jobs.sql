@@ -0,0 +1,3 @@
+-- Add `jobs` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN jobs TEXT NOT NULL DEFAULT '[]';
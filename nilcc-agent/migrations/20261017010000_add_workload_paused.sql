@@ -0,0 +1,3 @@
+-- Add `paused` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN paused BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Add `debug` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN debug BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Add `bandwidth_limits` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN bandwidth_limits TEXT NOT NULL DEFAULT 'null';
@@ -0,0 +1,3 @@
+-- Add `owner` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN owner TEXT;
@@ -0,0 +1,3 @@
+-- Add `proxy_timeouts` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN proxy_timeouts TEXT NOT NULL DEFAULT 'null';
@@ -0,0 +1,8 @@
+-- Create a table to persist the events that haven't been sent to the nilcc API yet.
+
+CREATE TABLE pending_events (
+  id INTEGER PRIMARY KEY,
+  workload_id VARCHAR(36) NOT NULL,
+  event TEXT NOT NULL,
+  timestamp DATETIME WITH TIMEZONE NOT NULL
+);
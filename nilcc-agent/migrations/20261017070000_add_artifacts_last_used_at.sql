@@ -0,0 +1,3 @@
+-- Add `last_used_at` to `artifacts` table.
+
+ALTER TABLE artifacts ADD COLUMN last_used_at DATETIME WITH TIMEZONE DEFAULT NULL;
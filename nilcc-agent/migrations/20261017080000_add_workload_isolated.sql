@@ -0,0 +1,3 @@
+-- Add `isolated` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN isolated BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Remove the `jobs` column from the workloads table, since jobs are now declared in the docker compose file.
+
+ALTER TABLE workloads DROP COLUMN jobs;
@@ -0,0 +1,3 @@
+-- Add `stored_files` to `workloads` table.
+
+ALTER TABLE workloads ADD COLUMN stored_files TEXT NOT NULL DEFAULT '{}';
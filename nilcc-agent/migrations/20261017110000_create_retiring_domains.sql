@@ -0,0 +1,7 @@
+-- Create a table to keep track of the domains workloads stopped using, which are still served until they're retired.
+
+CREATE TABLE retiring_domains (
+  domain VARCHAR(255) PRIMARY KEY,
+  workload_id VARCHAR(36) NOT NULL,
+  retire_at DATETIME WITH TIMEZONE NOT NULL
+);
@@ -0,0 +1,122 @@
+use serde::Serialize;
+use std::fmt;
+
+/// All the accelerators that can be passed through to VMs.
+pub const ACCELERATORS: &[&dyn Accelerator] = &[&NvidiaAccelerator, &AmdInstinctAccelerator];
+
+/// The vendor of an accelerator.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AcceleratorVendor {
+    Nvidia,
+    Amd,
+}
+
+impl AcceleratorVendor {
+    /// The accelerator implementation for this vendor.
+    pub fn accelerator(&self) -> &'static dyn Accelerator {
+        match self {
+            Self::Nvidia => &NvidiaAccelerator,
+            Self::Amd => &AmdInstinctAccelerator,
+        }
+    }
+}
+
+impl fmt::Display for AcceleratorVendor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nvidia => write!(f, "nvidia"),
+            Self::Amd => write!(f, "amd"),
+        }
+    }
+}
+
+/// A kind of PCI accelerator, like a GPU, that can be passed through to VMs.
+pub trait Accelerator: Send + Sync {
+    /// The vendor of these accelerators.
+    fn vendor(&self) -> AcceleratorVendor;
+
+    /// The PCI vendor id devices are identified by.
+    fn pci_vendor_id(&self) -> &'static str;
+
+    /// Get the model of a device given its line in the `lspci` output, or `None` if it's not supported.
+    fn model(&self, lspci_line: &str) -> Option<String>;
+
+    /// Whether devices run in confidential computing mode, which keeps their memory protected from the host.
+    fn confidential_computing(&self) -> bool;
+}
+
+/// NVIDIA GPUs, which run in confidential computing mode.
+pub struct NvidiaAccelerator;
+
+impl Accelerator for NvidiaAccelerator {
+    fn vendor(&self) -> AcceleratorVendor {
+        AcceleratorVendor::Nvidia
+    }
+
+    fn pci_vendor_id(&self) -> &'static str {
+        "10de"
+    }
+
+    fn model(&self, lspci_line: &str) -> Option<String> {
+        lspci_line.contains("H100").then(|| "H100".into())
+    }
+
+    fn confidential_computing(&self) -> bool {
+        true
+    }
+}
+
+/// AMD Instinct accelerators, which are passed through as regular devices without confidential computing.
+pub struct AmdInstinctAccelerator;
+
+impl Accelerator for AmdInstinctAccelerator {
+    fn vendor(&self) -> AcceleratorVendor {
+        AcceleratorVendor::Amd
+    }
+
+    fn pci_vendor_id(&self) -> &'static str {
+        "1002"
+    }
+
+    fn model(&self, lspci_line: &str) -> Option<String> {
+        // e.g. `Aldebaran/MI200 [Instinct MI250X/MI250]`, in which case the first model is used.
+        let (_, model) = lspci_line.split_once("[Instinct ")?;
+        let model = model.split([']', '/', ' ']).next()?;
+        model.starts_with("MI").then(|| model.into())
+    }
+
+    fn confidential_computing(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::h100("01:00.0 3D controller: NVIDIA Corporation GH100 [H100 PCIe] (rev a1)", Some("H100"))]
+    #[case::unsupported("01:00.0 3D controller: NVIDIA Corporation GA100 [A100 PCIe 40GB] (rev a1)", None)]
+    fn nvidia_model(#[case] line: &str, #[case] expected: Option<&str>) {
+        assert_eq!(NvidiaAccelerator.model(line).as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case::mi300x(
+        "03:00.0 Processing accelerators: Advanced Micro Devices, Inc. [AMD/ATI] Aqua Vanjaram [Instinct MI300X]",
+        Some("MI300X")
+    )]
+    #[case::mi250x(
+        "c1:00.0 Display controller: Advanced Micro Devices, Inc. [AMD/ATI] Aldebaran/MI200 [Instinct MI250X/MI250]",
+        Some("MI250X")
+    )]
+    #[case::radeon(
+        "0b:00.0 VGA compatible controller: Advanced Micro Devices, Inc. [AMD/ATI] Navi 31 [Radeon RX 7900 XT]",
+        None
+    )]
+    fn amd_model(#[case] line: &str, #[case] expected: Option<&str>) {
+        assert_eq!(AmdInstinctAccelerator.model(line).as_deref(), expected);
+    }
+}
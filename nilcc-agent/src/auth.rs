@@ -1,7 +1,9 @@
+use crate::config::{ApiScope, ApiTokenConfig};
 use crate::routes::Json;
 use axum::body::Body;
-use axum::http::StatusCode;
+use axum::extract::{FromRequestParts, OriginalUri};
 use axum::http::header::AUTHORIZATION;
+use axum::http::{Method, StatusCode, request::Parts};
 use axum::response::IntoResponse;
 use axum::{extract::Request, response::Response};
 use nilcc_agent_models::errors::RequestHandlerError;
@@ -12,15 +14,19 @@ use std::{
     task::{Context, Poll},
 };
 use tower::{Layer, Service};
+use tracing::{info, warn};
+
+/// The target used for audit log entries.
+const AUDIT_TARGET: &str = "audit";
 
 #[derive(Clone)]
 pub(crate) struct AuthLayer {
-    token: Arc<String>,
+    tokens: Arc<Vec<ApiTokenConfig>>,
 }
 
 impl AuthLayer {
-    pub(crate) fn new(token: String) -> Self {
-        Self { token: Arc::new(token) }
+    pub(crate) fn new(tokens: Vec<ApiTokenConfig>) -> Self {
+        Self { tokens: Arc::new(tokens) }
     }
 }
 
@@ -28,14 +34,14 @@ impl<S> Layer<S> for AuthLayer {
     type Service = AuthMiddleware<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        AuthMiddleware { inner, token: self.token.clone() }
+        AuthMiddleware { inner, tokens: self.tokens.clone() }
     }
 }
 
 #[derive(Clone)]
 pub(crate) struct AuthMiddleware<S> {
     inner: S,
-    token: Arc<String>,
+    tokens: Arc<Vec<ApiTokenConfig>>,
 }
 
 impl<S> Service<Request<Body>> for AuthMiddleware<S>
@@ -51,22 +57,148 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, req: Request) -> Self::Future {
+    fn call(&mut self, mut req: Request) -> Self::Future {
         let mut inner = self.inner.clone();
-        let token = self.token.clone();
+        let tokens = self.tokens.clone();
         Box::pin(async move {
-            if let Some(header) = req.headers().get(AUTHORIZATION)
-                && let Ok(value) = header.to_str()
-                && value.strip_prefix("Bearer ") == Some(&token)
-            {
-                return inner.call(req).await;
-            }
-
-            let response = RequestHandlerError {
-                error_code: "UNAUTHORIZED".into(),
-                message: "invalid or missing bearer token".into(),
+            let method = req.method().clone();
+            let path = match req.extensions().get::<OriginalUri>() {
+                Some(uri) => uri.path().to_string(),
+                None => req.uri().path().to_string(),
             };
-            Ok((StatusCode::UNAUTHORIZED, Json(response)).into_response())
+            let Some(token) = find_token(&tokens, &req) else {
+                warn!(target: AUDIT_TARGET, "Rejected {method} {path}: invalid or missing bearer token");
+                let response = RequestHandlerError::new("invalid or missing bearer token", "UNAUTHORIZED");
+                return Ok((StatusCode::UNAUTHORIZED, Json(response)).into_response());
+            };
+
+            let required_scope = required_scope(&method, req.uri().path());
+            let ApiTokenConfig { name, scope, .. } = token;
+            if *scope < required_scope {
+                warn!(
+                    target: AUDIT_TARGET,
+                    "Rejected {method} {path} by token '{name}' with scope {scope}, {required_scope} is required"
+                );
+                let response = RequestHandlerError::new(
+                    format!("this operation requires the '{required_scope}' scope"),
+                    "FORBIDDEN",
+                )
+                .with_detail("requiredScope", required_scope);
+                return Ok((StatusCode::FORBIDDEN, Json(response)).into_response());
+            }
+            info!(target: AUDIT_TARGET, "Allowed {method} {path} by token '{name}' with scope {scope}");
+            let caller = Caller::Token { name: name.clone(), scope: *scope };
+            req.extensions_mut().insert(caller);
+            inner.call(req).await
         })
     }
 }
+
+/// Who made a request.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Caller {
+    /// The request was authenticated with an API token.
+    Token { name: String, scope: ApiScope },
+
+    /// The request was made over a listener that doesn't require authentication, like the unix socket.
+    Local,
+}
+
+impl Caller {
+    /// The owner of the workloads created by this caller.
+    pub(crate) fn owner(&self) -> Option<&str> {
+        match self {
+            Self::Token { name, .. } => Some(name),
+            Self::Local => None,
+        }
+    }
+
+    /// Whether this caller can access every workload, regardless of its owner.
+    pub(crate) fn is_admin(&self) -> bool {
+        matches!(self, Self::Token { scope: ApiScope::Admin, .. } | Self::Local)
+    }
+
+    /// Whether this caller can see and operate on a workload with the given owner.
+    ///
+    /// Tokens can only access the workloads they created, unless they have the admin scope. Workloads without an
+    /// owner, like the ones created before ownership was tracked, can only be accessed by admins.
+    pub(crate) fn can_access(&self, owner: Option<&str>) -> bool {
+        self.is_admin() || owner == self.owner()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Requests that went through the auth middleware always carry one.
+        Ok(parts.extensions.get::<Caller>().cloned().unwrap_or(Caller::Local))
+    }
+}
+
+fn find_token<'a>(tokens: &'a [ApiTokenConfig], req: &Request) -> Option<&'a ApiTokenConfig> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let value = value.strip_prefix("Bearer ")?;
+    tokens.iter().find(|token| token.token == value)
+}
+
+/// Get the scope needed to perform a request.
+///
+/// The path is relative to the API root, e.g. `/workloads/list`.
+fn required_scope(method: &Method, path: &str) -> ApiScope {
+    // Websockets are opened via GET requests but port forwarding and consoles give access to the inside of CVMs.
+    if path.starts_with("/workloads/") && (path.ends_with("/port-forward") || path.ends_with("/console")) {
+        ApiScope::WorkloadOperator
+    } else if matches!(*method, Method::GET | Method::HEAD) {
+        ApiScope::ReadOnly
+    } else if path.starts_with("/workloads/") {
+        ApiScope::WorkloadOperator
+    } else {
+        ApiScope::Admin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::list_workloads(Method::GET, "/workloads/list", ApiScope::ReadOnly)]
+    #[case::container_logs(Method::GET, "/workloads/abc/containers/logs", ApiScope::ReadOnly)]
+    #[case::versions(Method::GET, "/system/artifacts/versions", ApiScope::ReadOnly)]
+    #[case::create_workload(Method::POST, "/workloads/create", ApiScope::WorkloadOperator)]
+    #[case::restart_containers(Method::POST, "/workloads/abc/containers/restart", ApiScope::WorkloadOperator)]
+    #[case::port_forward(Method::GET, "/workloads/abc/port-forward", ApiScope::WorkloadOperator)]
+    #[case::console(Method::GET, "/workloads/abc/console", ApiScope::WorkloadOperator)]
+    #[case::upgrade(Method::POST, "/system/agent/upgrade", ApiScope::Admin)]
+    #[case::rotate_keys(Method::POST, "/system/verifier/keys/rotate", ApiScope::Admin)]
+    #[case::unknown(Method::POST, "/other", ApiScope::Admin)]
+    fn scopes(#[case] method: Method, #[case] path: &str, #[case] expected: ApiScope) {
+        assert_eq!(required_scope(&method, path), expected);
+    }
+
+    #[test]
+    fn workload_access() {
+        let token = |name: &str, scope| Caller::Token { name: name.into(), scope };
+        let operator = token("team-a", ApiScope::WorkloadOperator);
+        assert!(operator.can_access(Some("team-a")));
+        assert!(!operator.can_access(Some("team-b")));
+        assert!(!operator.can_access(None));
+
+        let read_only = token("team-a", ApiScope::ReadOnly);
+        assert!(read_only.can_access(Some("team-a")));
+        assert!(!read_only.can_access(Some("team-b")));
+
+        let admin = token("default", ApiScope::Admin);
+        assert!(admin.can_access(Some("team-a")));
+        assert!(admin.can_access(None));
+        assert!(Caller::Local.can_access(Some("team-a")));
+    }
+
+    #[test]
+    fn scope_order() {
+        assert!(ApiScope::ReadOnly < ApiScope::WorkloadOperator);
+        assert!(ApiScope::WorkloadOperator < ApiScope::Admin);
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::Client;
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use tracing::info;
+
+/// The path where a CVM's attester serves attestation reports.
+const REPORT_PATH: &str = "/nilcc/api/v2/report";
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait AttesterClient: Send + Sync {
+    /// Fetch the raw attestation report a workload's attester serves.
+    async fn fetch_report(
+        &self,
+        domain: &str,
+        https_port: u16,
+        include_boot_log: bool,
+    ) -> Result<Vec<u8>, AttesterRequestError>;
+}
+
+pub struct DefaultAttesterClient {
+    timeout: Duration,
+}
+
+impl DefaultAttesterClient {
+    pub fn new() -> Self {
+        Self { timeout: Duration::from_secs(10) }
+    }
+}
+
+impl Default for DefaultAttesterClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl AttesterClient for DefaultAttesterClient {
+    async fn fetch_report(
+        &self,
+        domain: &str,
+        https_port: u16,
+        include_boot_log: bool,
+    ) -> Result<Vec<u8>, AttesterRequestError> {
+        // Go through the CVM's local HTTPS port but keep using the domain so the CVM's proxy routes the request and
+        // its certificate is validated just like it would be for any other client.
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, https_port));
+        let client = Client::builder()
+            .timeout(self.timeout)
+            .resolve(domain, address)
+            .build()
+            .context("Failed to build reqwest client")
+            .map_err(AttesterRequestError::Internal)?;
+        let endpoint = format!("https://{domain}{REPORT_PATH}");
+        info!("Sending GET request to {endpoint} via {address}");
+        let mut request = client.get(endpoint);
+        if include_boot_log {
+            request = request.query(&[("include_boot_log", true)]);
+        }
+        let response = request.send().await?.error_for_status()?.bytes().await?;
+        Ok(response.to_vec())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AttesterRequestError {
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("internal: {0:#}")]
+    Internal(anyhow::Error),
+}
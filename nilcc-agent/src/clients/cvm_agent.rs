@@ -2,50 +2,147 @@ use anyhow::Context;
 use async_trait::async_trait;
 use cvm_agent_models::{
     bootstrap::BootstrapRequest,
-    config::HeartbeatConfigRequest,
-    container::Container,
+    config::{DockerCredentialsConfigRequest, DomainsConfigRequest, HeartbeatConfigRequest},
+    container::{ComposeStateResponse, Container, PORT_FORWARD_PROTOCOL, PortForwardRequest, RestartContainerRequest},
+    encryption::MaybeEncrypted,
     health::HealthResponse,
+    heartbeat::HeartbeatStatusResponse,
+    jobs::{JobLogsRequest, JobStatus},
     logs::{ContainerLogsRequest, ContainerLogsResponse, SystemLogsRequest, SystemLogsResponse},
-    stats::SystemStatsResponse,
+    stats::{ContainersStatsResponse, SystemStatsResponse},
+    tls::TlsInfoResponse,
+};
+use hmac::{Hmac, Mac};
+use reqwest::{
+    Client, Upgraded,
+    header::{CONNECTION, UPGRADE},
 };
-use reqwest::Client;
 use serde::{Serialize, de::DeserializeOwned};
-use std::time::Duration;
+use sha2::Sha256;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    time::Duration,
+};
 use tracing::info;
 
 #[async_trait]
 #[cfg_attr(test, mockall::automock)]
 pub trait CvmAgentClient: Send + Sync {
     async fn list_containers(&self, cvm_agent_port: u16) -> Result<Vec<Container>, CvmAgentRequestError>;
+    async fn compose_state(&self, cvm_agent_port: u16) -> Result<ComposeStateResponse, CvmAgentRequestError>;
     async fn container_logs(
         &self,
         cvm_agent_port: u16,
         request: &ContainerLogsRequest,
-    ) -> Result<ContainerLogsResponse, CvmAgentRequestError>;
+    ) -> Result<MaybeEncrypted<ContainerLogsResponse>, CvmAgentRequestError>;
+    async fn restart_container(
+        &self,
+        cvm_agent_port: u16,
+        request: &RestartContainerRequest,
+    ) -> Result<(), CvmAgentRequestError>;
+    async fn container_stats(
+        &self,
+        cvm_agent_port: u16,
+    ) -> Result<MaybeEncrypted<ContainersStatsResponse>, CvmAgentRequestError>;
+    async fn port_forward(
+        &self,
+        cvm_agent_port: u16,
+        request: &PortForwardRequest,
+    ) -> Result<Upgraded, CvmAgentRequestError>;
+    async fn list_jobs(&self, cvm_agent_port: u16) -> Result<Vec<JobStatus>, CvmAgentRequestError>;
+    async fn job_logs(
+        &self,
+        cvm_agent_port: u16,
+        request: &JobLogsRequest,
+    ) -> Result<MaybeEncrypted<ContainerLogsResponse>, CvmAgentRequestError>;
     async fn system_logs(
         &self,
         cvm_agent_port: u16,
         request: &SystemLogsRequest,
-    ) -> Result<SystemLogsResponse, CvmAgentRequestError>;
-    async fn system_stats(&self, cvm_agent_port: u16) -> Result<SystemStatsResponse, CvmAgentRequestError>;
+    ) -> Result<MaybeEncrypted<SystemLogsResponse>, CvmAgentRequestError>;
+    async fn system_stats(
+        &self,
+        cvm_agent_port: u16,
+    ) -> Result<MaybeEncrypted<SystemStatsResponse>, CvmAgentRequestError>;
     async fn check_health(&self, cvm_agent_port: u16) -> Result<HealthResponse, CvmAgentRequestError>;
+    async fn tls_info(&self, cvm_agent_port: u16) -> Result<TlsInfoResponse, CvmAgentRequestError>;
+    async fn heartbeat_status(&self, cvm_agent_port: u16) -> Result<HeartbeatStatusResponse, CvmAgentRequestError>;
     async fn bootstrap(&self, cvm_agent_port: u16, request: &BootstrapRequest) -> Result<(), CvmAgentRequestError>;
     async fn set_heartbeat_config(
         &self,
         cvm_agent_port: u16,
         request: &HeartbeatConfigRequest,
     ) -> Result<(), CvmAgentRequestError>;
+    async fn set_domains_config(
+        &self,
+        cvm_agent_port: u16,
+        request: &DomainsConfigRequest,
+    ) -> Result<(), CvmAgentRequestError>;
+    async fn set_docker_credentials(
+        &self,
+        cvm_agent_port: u16,
+        request: &DockerCredentialsConfigRequest,
+    ) -> Result<(), CvmAgentRequestError>;
+}
+
+/// The name of the file in the VM store that contains the key `cvm-agent` tokens are derived from.
+pub const CVM_AGENT_AUTH_KEY_FILE: &str = "cvm-agent.key";
+
+/// The key the tokens used to authenticate to each CVM's `cvm-agent` are derived from.
+///
+/// Every CVM gets its own token, derived from this key and the port its `cvm-agent` is forwarded to, so a CVM that
+/// learns its token can't use it to talk to any other CVM's `cvm-agent`.
+#[derive(Clone)]
+pub struct CvmAgentAuthKey(pub(crate) [u8; 32]);
+
+impl CvmAgentAuthKey {
+    /// Load the key from a path, generating it if it doesn't exist yet.
+    pub fn load_or_generate(path: &Path) -> anyhow::Result<Self> {
+        let mut key = [0; 32];
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                hex::decode_to_slice(contents.trim(), &mut key).context("Invalid cvm-agent auth key")?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!("Generating cvm-agent auth key in {}", path.display());
+                key = rand::random();
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).context("Failed to create cvm-agent auth key directory")?;
+                }
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(path)
+                    .context("Failed to create cvm-agent auth key")?;
+                file.write_all(hex::encode(key).as_bytes()).context("Failed to write cvm-agent auth key")?;
+            }
+            Err(e) => return Err(e).context("Failed to read cvm-agent auth key"),
+        };
+        Ok(Self(key))
+    }
+
+    /// The token used to authenticate to the `cvm-agent` forwarded to the given port.
+    pub fn token(&self, cvm_agent_port: u16) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any size");
+        mac.update(&cvm_agent_port.to_be_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
 }
 
 pub struct DefaultCvmAgentClient {
     client: Client,
+    auth_key: CvmAgentAuthKey,
 }
 
 impl DefaultCvmAgentClient {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new(auth_key: CvmAgentAuthKey) -> anyhow::Result<Self> {
         let client =
             Client::builder().timeout(Duration::from_secs(5)).build().context("Failed to build reqwest client")?;
-        Ok(Self { client })
+        Ok(Self { client, auth_key })
     }
 
     async fn get<Q: Serialize, T: DeserializeOwned>(
@@ -56,14 +153,29 @@ impl DefaultCvmAgentClient {
     ) -> Result<T, CvmAgentRequestError> {
         let endpoint = format!("http://127.0.0.1:{port}{path}");
         info!("Sending GET request to {endpoint}");
-        let response = self.client.get(endpoint).query(query).send().await?.error_for_status()?.json().await?;
+        let response = self
+            .client
+            .get(endpoint)
+            .query(query)
+            .bearer_auth(self.auth_key.token(port))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
         Ok(response)
     }
 
     async fn post<R: Serialize>(&self, port: u16, path: &str, request: &R) -> Result<(), CvmAgentRequestError> {
         let endpoint = format!("http://127.0.0.1:{port}{path}");
         info!("Sending POST request to {endpoint}");
-        self.client.post(endpoint).json(request).send().await?.error_for_status()?;
+        self.client
+            .post(endpoint)
+            .json(request)
+            .bearer_auth(self.auth_key.token(port))
+            .send()
+            .await?
+            .error_for_status()?;
         Ok(())
     }
 }
@@ -74,23 +186,77 @@ impl CvmAgentClient for DefaultCvmAgentClient {
         self.get(cvm_agent_port, "/api/v1/containers/list", &()).await
     }
 
+    async fn compose_state(&self, cvm_agent_port: u16) -> Result<ComposeStateResponse, CvmAgentRequestError> {
+        self.get(cvm_agent_port, "/api/v1/containers/compose-state", &()).await
+    }
+
     async fn container_logs(
         &self,
         cvm_agent_port: u16,
         request: &ContainerLogsRequest,
-    ) -> Result<ContainerLogsResponse, CvmAgentRequestError> {
+    ) -> Result<MaybeEncrypted<ContainerLogsResponse>, CvmAgentRequestError> {
         self.get(cvm_agent_port, "/api/v1/containers/logs", &request).await
     }
 
+    async fn restart_container(
+        &self,
+        cvm_agent_port: u16,
+        request: &RestartContainerRequest,
+    ) -> Result<(), CvmAgentRequestError> {
+        self.post(cvm_agent_port, "/api/v1/containers/restart", request).await
+    }
+
+    async fn container_stats(
+        &self,
+        cvm_agent_port: u16,
+    ) -> Result<MaybeEncrypted<ContainersStatsResponse>, CvmAgentRequestError> {
+        self.get(cvm_agent_port, "/api/v1/containers/stats", &()).await
+    }
+
+    async fn port_forward(
+        &self,
+        cvm_agent_port: u16,
+        request: &PortForwardRequest,
+    ) -> Result<Upgraded, CvmAgentRequestError> {
+        let endpoint = format!("http://127.0.0.1:{cvm_agent_port}/api/v1/containers/port-forward");
+        info!("Sending port forwarding request to {endpoint}");
+        let response = self
+            .client
+            .get(endpoint)
+            .query(request)
+            .bearer_auth(self.auth_key.token(cvm_agent_port))
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, PORT_FORWARD_PROTOCOL)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.upgrade().await?)
+    }
+
+    async fn list_jobs(&self, cvm_agent_port: u16) -> Result<Vec<JobStatus>, CvmAgentRequestError> {
+        self.get(cvm_agent_port, "/api/v1/jobs/list", &()).await
+    }
+
+    async fn job_logs(
+        &self,
+        cvm_agent_port: u16,
+        request: &JobLogsRequest,
+    ) -> Result<MaybeEncrypted<ContainerLogsResponse>, CvmAgentRequestError> {
+        self.get(cvm_agent_port, "/api/v1/jobs/logs", &request).await
+    }
+
     async fn system_logs(
         &self,
         cvm_agent_port: u16,
         request: &SystemLogsRequest,
-    ) -> Result<SystemLogsResponse, CvmAgentRequestError> {
+    ) -> Result<MaybeEncrypted<SystemLogsResponse>, CvmAgentRequestError> {
         self.get(cvm_agent_port, "/api/v1/system/logs", &request).await
     }
 
-    async fn system_stats(&self, cvm_agent_port: u16) -> Result<SystemStatsResponse, CvmAgentRequestError> {
+    async fn system_stats(
+        &self,
+        cvm_agent_port: u16,
+    ) -> Result<MaybeEncrypted<SystemStatsResponse>, CvmAgentRequestError> {
         self.get(cvm_agent_port, "/api/v1/system/stats", &()).await
     }
 
@@ -98,6 +264,14 @@ impl CvmAgentClient for DefaultCvmAgentClient {
         self.get(cvm_agent_port, "/api/v1/health", &()).await
     }
 
+    async fn tls_info(&self, cvm_agent_port: u16) -> Result<TlsInfoResponse, CvmAgentRequestError> {
+        self.get(cvm_agent_port, "/api/v1/system/tls", &()).await
+    }
+
+    async fn heartbeat_status(&self, cvm_agent_port: u16) -> Result<HeartbeatStatusResponse, CvmAgentRequestError> {
+        self.get(cvm_agent_port, "/api/v1/system/heartbeat", &()).await
+    }
+
     async fn bootstrap(&self, cvm_agent_port: u16, request: &BootstrapRequest) -> Result<(), CvmAgentRequestError> {
         self.post(cvm_agent_port, "/api/v1/system/bootstrap", request).await
     }
@@ -109,6 +283,22 @@ impl CvmAgentClient for DefaultCvmAgentClient {
     ) -> Result<(), CvmAgentRequestError> {
         self.post(cvm_agent_port, "/api/v1/config/heartbeats", request).await
     }
+
+    async fn set_domains_config(
+        &self,
+        cvm_agent_port: u16,
+        request: &DomainsConfigRequest,
+    ) -> Result<(), CvmAgentRequestError> {
+        self.post(cvm_agent_port, "/api/v1/config/domains", request).await
+    }
+
+    async fn set_docker_credentials(
+        &self,
+        cvm_agent_port: u16,
+        request: &DockerCredentialsConfigRequest,
+    ) -> Result<(), CvmAgentRequestError> {
+        self.post(cvm_agent_port, "/api/v1/config/docker-credentials", request).await
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -119,3 +309,19 @@ pub enum CvmAgentRequestError {
     #[error("response decode: {0}")]
     Decode(#[from] serde_json::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn auth_key() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("cvm-agent.key");
+        let key = CvmAgentAuthKey::load_or_generate(&path).expect("failed to generate key");
+        let loaded = CvmAgentAuthKey::load_or_generate(&path).expect("failed to load key");
+        assert_eq!(key.token(1000), loaded.token(1000));
+        assert_ne!(key.token(1000), key.token(1001));
+    }
+}
@@ -1,3 +1,5 @@
+pub mod attester;
 pub mod cvm_agent;
 pub mod nilcc_api;
 pub mod qemu;
+pub mod webhook;
@@ -1,5 +1,8 @@
 use crate::version::agent_version;
-use crate::{config::ApiConfig, resources::SystemResources};
+use crate::{
+    config::ApiConfig,
+    resources::{PublicIps, SystemResources},
+};
 use anyhow::Context;
 use async_trait::async_trait;
 use axum::http::{HeaderMap, HeaderName, HeaderValue};
@@ -7,7 +10,7 @@ use chrono::{DateTime, Utc};
 use reqwest::{Client, Method, StatusCode};
 use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::{Deserialize, Serialize};
-use std::net::Ipv4Addr;
+use std::collections::HashMap;
 use strum::EnumDiscriminants;
 use tracing::info;
 use uuid::Uuid;
@@ -20,7 +23,7 @@ pub trait NilccApiClient: Send + Sync {
         &self,
         config: &ApiConfig,
         resources: &SystemResources,
-        public_ip: Ipv4Addr,
+        public_ips: PublicIps,
     ) -> Result<(), NilccApiError>;
 
     /// Report an event that occurred for a VM.
@@ -32,10 +35,17 @@ pub trait NilccApiClient: Send + Sync {
     ) -> Result<(), NilccApiError>;
 
     /// Send a heartbeat to the API.
-    async fn heartbeat(&self, available_artifact_versions: Vec<String>) -> Result<HeartbeatResponse, NilccApiError>;
+    async fn heartbeat(
+        &self,
+        available_artifact_versions: Vec<String>,
+        workloads: Vec<HeartbeatWorkload>,
+    ) -> Result<HeartbeatResponse, NilccApiError>;
+
+    /// Get the latest version of an environment variable group.
+    async fn env_group(&self, name: &str) -> Result<EnvGroupResponse, NilccApiError>;
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq, EnumDiscriminants)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, EnumDiscriminants)]
 #[serde(tag = "kind", rename_all = "camelCase")]
 pub enum VmEvent {
     Starting,
@@ -44,6 +54,9 @@ pub enum VmEvent {
     Stopped,
     ForcedRestart,
     VmRestarted,
+    Preempted,
+    ArtifactsUpgraded { version: String },
+    EnvVarsRotated { keys: Vec<String> },
     FailedToStart { error: String },
     Warning { message: String },
 }
@@ -113,13 +126,14 @@ impl NilccApiClient for HttpNilccApiClient {
         &self,
         api_config: &ApiConfig,
         resources: &SystemResources,
-        public_ip: Ipv4Addr,
+        public_ips: PublicIps,
     ) -> Result<(), NilccApiError> {
         let url = self.make_url("/api/v1/metal-instances/register");
         let payload = RegisterRequest {
             id: self.agent_id,
             agent_version: agent_version().to_string(),
-            public_ip: public_ip.to_string(),
+            public_ip: public_ips.ipv4.map(|ip| ip.to_string()),
+            public_ipv6: public_ips.ipv6.map(|ip| ip.to_string()),
             token: api_config.token.clone(),
             hostname: resources.hostname.clone(),
             memory_mb: Resource { reserved: resources.reserved_memory_mb, total: resources.memory_mb },
@@ -144,9 +158,19 @@ impl NilccApiClient for HttpNilccApiClient {
         Ok(())
     }
 
-    async fn heartbeat(&self, available_artifact_versions: Vec<String>) -> Result<HeartbeatResponse, NilccApiError> {
+    async fn heartbeat(
+        &self,
+        available_artifact_versions: Vec<String>,
+        workloads: Vec<HeartbeatWorkload>,
+    ) -> Result<HeartbeatResponse, NilccApiError> {
         let url = self.make_url("/api/v1/metal-instances/heartbeat");
-        let payload = HeartbeatRequest { id: self.agent_id, available_artifact_versions };
+        let payload = HeartbeatRequest { id: self.agent_id, available_artifact_versions, workloads };
+        self.send_request(Method::POST, url, &payload).await
+    }
+
+    async fn env_group(&self, name: &str) -> Result<EnvGroupResponse, NilccApiError> {
+        let url = self.make_url("/api/v1/env-groups/get");
+        let payload = EnvGroupRequest { id: self.agent_id, name: name.to_string() };
         self.send_request(Method::POST, url, &payload).await
     }
 }
@@ -159,9 +183,9 @@ impl NilccApiClient for DummyNilccApiClient {
         &self,
         _api_config: &ApiConfig,
         resources: &SystemResources,
-        public_ip: Ipv4Addr,
+        public_ips: PublicIps,
     ) -> Result<(), NilccApiError> {
-        info!("Registering with resources: {resources:?} and public IP: {public_ip}");
+        info!("Registering with resources: {resources:?} and public IPs: {public_ips}");
         Ok(())
     }
 
@@ -175,10 +199,24 @@ impl NilccApiClient for DummyNilccApiClient {
         Ok(())
     }
 
-    async fn heartbeat(&self, available_artifact_versions: Vec<String>) -> Result<HeartbeatResponse, NilccApiError> {
-        info!("Reporting heartbeat, available versions = {available_artifact_versions:?}");
+    async fn heartbeat(
+        &self,
+        available_artifact_versions: Vec<String>,
+        workloads: Vec<HeartbeatWorkload>,
+    ) -> Result<HeartbeatResponse, NilccApiError> {
+        info!(
+            "Reporting heartbeat, available versions = {available_artifact_versions:?}, workloads = {}",
+            workloads.len()
+        );
         Ok(HeartbeatResponse { expected_artifact_versions: available_artifact_versions })
     }
+
+    async fn env_group(&self, name: &str) -> Result<EnvGroupResponse, NilccApiError> {
+        Err(NilccApiError::Api {
+            status: StatusCode::NOT_FOUND,
+            message: format!("env group {name} can't be resolved in standalone mode"),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -187,7 +225,13 @@ pub struct RegisterRequest {
     #[serde(rename = "metalInstanceId")]
     id: Uuid,
     agent_version: String,
-    public_ip: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public_ip: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public_ipv6: Option<String>,
+
     token: String,
     hostname: String,
     memory_mb: Resource,
@@ -223,10 +267,38 @@ struct HeartbeatRequest {
     id: Uuid,
 
     available_artifact_versions: Vec<String>,
+    workloads: Vec<HeartbeatWorkload>,
+}
+
+/// A workload running in this agent, as reported in heartbeats.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatWorkload {
+    pub workload_id: Uuid,
+    pub labels: HashMap<String, String>,
+
+    /// Whether the workload has exclusive use of this metal instance, so no other workload should be scheduled on it.
+    pub isolated: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HeartbeatResponse {
-    pub(crate) expected_artifact_versions: Vec<String>,
+    pub expected_artifact_versions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EnvGroupRequest {
+    #[serde(rename = "metalInstanceId")]
+    id: Uuid,
+
+    name: String,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvGroupResponse {
+    pub(crate) version: i64,
+    pub(crate) env_vars: HashMap<String, String>,
 }
@@ -1,10 +1,11 @@
-use crate::resources::GpuAddress;
+use crate::resources::{GpuAddress, NumaPlacement};
 use async_trait::async_trait;
-use nilcc_artifacts::metadata::DiskFormat;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use nilcc_artifacts::metadata::{CvmCpu, DiskFormat, GuestPolicy};
 use qapi::{
     Command as QapiCommandTrait, ExecuteError,
     futures::{QapiService, QapiStream, QmpStreamNegotiation, QmpStreamTokio},
-    qmp::{QmpCommand, quit, system_powerdown, system_reset},
+    qmp::{QmpCommand, cont, quit, stop, system_powerdown, system_reset},
 };
 use std::{
     io,
@@ -27,6 +28,9 @@ type NegotiatedQmpStream = QapiStream<QmpReadStreamHalf, QmpWriteStreamHalf>;
 type QmpCommandService = QapiService<QmpWriteStreamHalf>;
 type QmpDriverTaskHandle = JoinHandle<()>;
 
+/// The binary used to pin VMs to a set of host CPUs.
+const TASKSET_BIN: &str = "taskset";
+
 /// The spec for a hard disk.
 #[derive(Debug, Clone, PartialEq)]
 pub struct HardDiskSpec {
@@ -60,6 +64,9 @@ pub struct VmSpec {
     /// Vec of (HOST, GUEST) ports to forward.
     pub port_forwarding: Vec<(u16, u16)>,
 
+    /// Whether ports are also forwarded from the host's IPv6 loopback address.
+    pub ipv6_port_forwarding: bool,
+
     /// Optional BIOS path to use for the VM.
     pub bios_path: Option<PathBuf>,
 
@@ -77,6 +84,18 @@ pub struct VmSpec {
 
     /// Enable CVM (Confidential VM) support.
     pub enable_cvm: bool,
+
+    /// The SEV-SNP guest policy to launch the CVM with.
+    pub guest_policy: GuestPolicy,
+
+    /// The virtual CPU model and the SEV-SNP parameters that depend on the host's CPU.
+    pub cvm_cpu: CvmCpu,
+
+    /// The data to launch the CVM with, which is included in every attestation report it generates.
+    pub host_data: Option<[u8; 32]>,
+
+    /// The NUMA node and host CPUs to pin the VM to, if any.
+    pub numa: Option<NumaPlacement>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq)]
@@ -84,6 +103,9 @@ pub enum VmDisplayMode {
     #[default]
     None,
     Console,
+
+    /// Run in the background and expose the serial console via a unix socket at the given path.
+    SerialSocket(PathBuf),
 }
 
 #[derive(Error, Debug)]
@@ -126,6 +148,12 @@ pub trait VmClient: Send + Sync {
     /// Stop a VM.
     async fn stop_vm(&self, socket_path: &Path, force: bool) -> Result<()>;
 
+    /// Freeze a VM's vCPUs, keeping its memory and devices as they are.
+    async fn pause_vm(&self, socket_path: &Path) -> Result<()>;
+
+    /// Resume a VM whose vCPUs were frozen.
+    async fn resume_vm(&self, socket_path: &Path) -> Result<()>;
+
     /// Check if a VM is running.
     async fn is_vm_running(&self, socket_path: &Path) -> bool;
 }
@@ -206,28 +234,47 @@ impl VmClient for QemuClient {
 
         // --- CVM support ---
         if spec.enable_cvm {
+            let mut guest = format!(
+                "sev-snp-guest,id=sev0,policy={},cbitpos={},reduced-phys-bits={},kernel-hashes=on",
+                spec.guest_policy, spec.cvm_cpu.cbitpos, spec.cvm_cpu.reduced_phys_bits
+            );
+            if let Some(host_data) = &spec.host_data {
+                guest.push_str(&format!(",host-data={}", BASE64_STANDARD.encode(host_data)));
+            }
             args.extend([
                 "-machine".into(),
                 "confidential-guest-support=sev0,vmport=off".into(),
                 "-object".into(),
-                "sev-snp-guest,id=sev0,cbitpos=51,reduced-phys-bits=1,kernel-hashes=on".into(),
+                guest,
             ]);
         }
 
         // --- Display ---
-        match spec.display {
+        match &spec.display {
             VmDisplayMode::None => args.extend(["-display".into(), "none".into(), "-daemonize".into()]),
             VmDisplayMode::Console => args.extend(["-nographic".into(), "-serial".into(), "mon:stdio".into()]),
+            VmDisplayMode::SerialSocket(path) => args.extend([
+                "-display".into(),
+                "none".into(),
+                "-daemonize".into(),
+                "-serial".into(),
+                format!("unix:{},server,nowait", path.display()),
+            ]),
         };
 
         // --- Base machine + CPU / RAM ---
+        let smp = match &spec.numa {
+            // Expose the pinned CPUs as a single socket since they all belong to the same host node.
+            Some(_) => format!("{cpu},sockets=1,cores={cpu},threads=1", cpu = spec.cpu),
+            None => spec.cpu.to_string(),
+        };
         args.extend([
             "-enable-kvm".into(),
             "-no-reboot".into(),
             "-cpu".into(),
-            "EPYC-v4".into(),
+            spec.cvm_cpu.model.to_string(),
             "-smp".into(),
-            spec.cpu.to_string(),
+            smp,
             "-m".into(),
             spec.ram_mib.to_string(),
             "-machine".into(),
@@ -238,6 +285,16 @@ impl VmClient for QemuClient {
             format!("unix:{},server,nowait", socket_path.display()),
         ]);
 
+        // --- NUMA placement ---
+        if let Some(numa) = &spec.numa {
+            args.extend([
+                "-object".into(),
+                format!("memory-backend-memfd,id=ram0,size={}M,host-nodes={},policy=bind", spec.ram_mib, numa.node),
+                "-numa".into(),
+                format!("node,nodeid=0,cpus=0-{},memdev=ram0", spec.cpu.saturating_sub(1)),
+            ]);
+        }
+
         // --- BIOS ---
         if let Some(bios) = &spec.bios_path {
             args.extend(["-bios".into(), bios.display().to_string()]);
@@ -294,12 +351,14 @@ impl VmClient for QemuClient {
 
         // --- Network and Port forwarding ---
         if !spec.port_forwarding.is_empty() {
-            let fwd = spec
-                .port_forwarding
-                .iter()
-                .map(|(h, g)| format!("hostfwd=tcp:127.0.0.1:{h}-:{g}"))
-                .collect::<Vec<_>>()
-                .join(",");
+            let mut fwd = Vec::new();
+            for (h, g) in &spec.port_forwarding {
+                fwd.push(format!("hostfwd=tcp:127.0.0.1:{h}-:{g}"));
+                if spec.ipv6_port_forwarding {
+                    fwd.push(format!("hostfwd=tcp:[::1]:{h}-:{g}"));
+                }
+            }
+            let fwd = fwd.join(",");
             args.extend([
                 "-device".into(),
                 "virtio-net-pci,disable-legacy=on,iommu_platform=true,netdev=vmnic,romfile=".into(),
@@ -333,10 +392,18 @@ impl VmClient for QemuClient {
             return Err(QemuClientError::VmAlreadyRunning);
         }
 
-        let args = self.build_start_vm_args(&spec, socket_path)?;
+        let mut args = self.build_start_vm_args(&spec, socket_path)?;
+        // Pinned VMs are started through taskset, which qemu's threads inherit the CPU affinity from.
+        let command = match &spec.numa {
+            Some(numa) => {
+                args.splice(0..0, ["-c".into(), numa.cpu_list(), self.qemu_bin.display().to_string()]);
+                Path::new(TASKSET_BIN)
+            }
+            None => self.qemu_bin.as_path(),
+        };
         let args: Vec<_> = args.iter().map(Deref::deref).collect();
 
-        let output = Self::invoke_cli_command(&self.qemu_bin, &args).await?;
+        let output = Self::invoke_cli_command(command, &args).await?;
         if !output.status.success() {
             return Err(QemuClientError::Io(io::Error::other(format!("qemu failed: {}", output.stderr))));
         }
@@ -359,6 +426,16 @@ impl VmClient for QemuClient {
         Ok(())
     }
 
+    async fn pause_vm(&self, socket_path: &Path) -> Result<()> {
+        self.execute_qmp_command(socket_path, stop {}).await?;
+        Ok(())
+    }
+
+    async fn resume_vm(&self, socket_path: &Path) -> Result<()> {
+        self.execute_qmp_command(socket_path, cont {}).await?;
+        Ok(())
+    }
+
     async fn is_vm_running(&self, socket_path: &Path) -> bool {
         match QmpStreamTokio::open_uds(socket_path).await {
             Ok(stream) => stream.negotiate().await.is_ok(),
@@ -373,6 +450,7 @@ mod tests {
 
     use super::*;
     use crate::services::disk::{DefaultDiskService, DiskService};
+    use nilcc_artifacts::metadata::CpuModel;
     use tokio::{fs, time::sleep};
     use tracing_test::traced_test;
 
@@ -394,12 +472,17 @@ mod tests {
             cdrom_iso_path: Some("/tmp/cd.iso".into()),
             gpus: vec![GpuAddress("A".into()), GpuAddress("B".into())],
             port_forwarding: vec![(8080, 80)],
+            ipv6_port_forwarding: false,
             bios_path: Some("/tmp/bios".into()),
             initrd_path: Some("/tmp/initrd".into()),
             kernel_path: Some("/tmp/kernel".into()),
             kernel_args: Some("root=/dev/foo1".into()),
             display: Default::default(),
             enable_cvm: true,
+            guest_policy: GuestPolicy { smt: false, ..Default::default() },
+            cvm_cpu: Default::default(),
+            numa: None,
+            host_data: None,
         };
         let socket_path = Path::new("/tmp/vm.socket");
         let args = client.build_start_vm_args(&spec, &socket_path).expect("failed to build command line");
@@ -408,7 +491,7 @@ mod tests {
             "-machine",
             "confidential-guest-support=sev0,vmport=off",
             "-object",
-            "sev-snp-guest,id=sev0,cbitpos=51,reduced-phys-bits=1,kernel-hashes=on",
+            "sev-snp-guest,id=sev0,policy=0x20000,cbitpos=51,reduced-phys-bits=1,kernel-hashes=on",
             // Display
             "-display",
             "none",
@@ -485,6 +568,72 @@ mod tests {
         assert_eq!(args, expected);
     }
 
+    #[test]
+    fn build_cmd_dual_stack_port_forwarding() {
+        let client = make_client();
+        let spec = VmSpec { port_forwarding: vec![(8080, 80)], ipv6_port_forwarding: true, ..Default::default() };
+        let socket_path = Path::new("/tmp/vm.socket");
+        let args = client.build_start_vm_args(&spec, socket_path).expect("failed to build command line");
+        let netdev = args.iter().position(|arg| arg == "-netdev").expect("no netdev");
+        assert_eq!(args[netdev + 1], "user,id=vmnic,hostfwd=tcp:127.0.0.1:8080-:80,hostfwd=tcp:[::1]:8080-:80");
+    }
+
+    #[test]
+    fn build_cmd_cvm_cpu() {
+        let client = make_client();
+        let cvm_cpu = CvmCpu { model: CpuModel::EpycGenoa, cbitpos: 51, reduced_phys_bits: 3 };
+        let spec = VmSpec { enable_cvm: true, cvm_cpu, ..Default::default() };
+        let args =
+            client.build_start_vm_args(&spec, Path::new("/tmp/vm.socket")).expect("failed to build command line");
+        let cpu = args.iter().position(|arg| arg == "-cpu").expect("no cpu");
+        assert_eq!(args[cpu + 1], "EPYC-Genoa");
+        let object = args.iter().position(|arg| arg == "-object").expect("no object");
+        assert_eq!(
+            args[object + 1],
+            "sev-snp-guest,id=sev0,policy=0x30000,cbitpos=51,reduced-phys-bits=3,kernel-hashes=on"
+        );
+    }
+
+    #[test]
+    fn build_cmd_host_data() {
+        let client = make_client();
+        let spec = VmSpec { enable_cvm: true, host_data: Some([1; 32]), ..Default::default() };
+        let args =
+            client.build_start_vm_args(&spec, Path::new("/tmp/vm.socket")).expect("failed to build command line");
+        let object = args.iter().position(|arg| arg == "-object").expect("no object");
+        assert!(
+            args[object + 1].ends_with(",kernel-hashes=on,host-data=AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="),
+            "{}",
+            args[object + 1]
+        );
+    }
+
+    #[test]
+    fn build_cmd_numa() {
+        let client = make_client();
+        let numa = NumaPlacement { node: 1, cpus: vec![8, 9, 10, 11] };
+        let spec = VmSpec { cpu: 4, ram_mib: 4096, numa: Some(numa), ..Default::default() };
+        let args =
+            client.build_start_vm_args(&spec, Path::new("/tmp/vm.socket")).expect("failed to build command line");
+        let smp = args.iter().position(|arg| arg == "-smp").expect("no smp");
+        assert_eq!(args[smp + 1], "4,sockets=1,cores=4,threads=1");
+        let object = args.iter().position(|arg| arg == "-object").expect("no object");
+        assert_eq!(args[object + 1], "memory-backend-memfd,id=ram0,size=4096M,host-nodes=1,policy=bind");
+        let numa = args.iter().position(|arg| arg == "-numa").expect("no numa");
+        assert_eq!(args[numa + 1], "node,nodeid=0,cpus=0-3,memdev=ram0");
+    }
+
+    #[test]
+    fn build_cmd_serial_socket() {
+        let client = make_client();
+        let spec = VmSpec { display: VmDisplayMode::SerialSocket("/tmp/vm.console".into()), ..Default::default() };
+        let args =
+            client.build_start_vm_args(&spec, Path::new("/tmp/vm.socket")).expect("failed to build command line");
+        assert!(args.iter().any(|arg| arg == "-daemonize"));
+        let serial = args.iter().position(|arg| arg == "-serial").expect("no serial");
+        assert_eq!(args[serial + 1], "unix:/tmp/vm.console,server,nowait");
+    }
+
     #[test_with::no_env(GITHUB_ACTIONS)]
     #[tokio::test]
     #[traced_test]
@@ -509,6 +658,7 @@ mod tests {
             cdrom_iso_path: None,
             gpus: Vec::new(),
             port_forwarding: vec![],
+            ipv6_port_forwarding: false,
             bios_path: None,
             initrd_path: None,
             kernel_path: None,
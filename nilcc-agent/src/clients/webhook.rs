@@ -0,0 +1,130 @@
+use crate::clients::nilcc_api::VmEvent;
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode, header::CONTENT_TYPE};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The header that contains the signature of a webhook request's body.
+pub const SIGNATURE_HEADER: &str = "x-nilcc-signature";
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait WebhookClient: Send + Sync {
+    /// The URL this client sends events to.
+    fn url(&self) -> &str;
+
+    /// Send an event to the webhook.
+    async fn send(&self, event: &WebhookEvent) -> Result<(), WebhookError>;
+}
+
+/// An event sent to webhooks.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookEvent {
+    pub agent_id: Uuid,
+    pub workload_id: Uuid,
+    pub event: VmEvent,
+    pub timestamp: DateTime<Utc>,
+}
+
+pub struct HttpWebhookClientArgs {
+    pub url: String,
+    pub secret: String,
+    pub timeout: Duration,
+}
+
+/// A webhook client that POSTs events as JSON, signing them using HMAC-SHA256.
+pub struct HttpWebhookClient {
+    client: Client,
+    url: String,
+    secret: String,
+}
+
+impl HttpWebhookClient {
+    pub fn new(args: HttpWebhookClientArgs) -> anyhow::Result<Self> {
+        let HttpWebhookClientArgs { url, secret, timeout } = args;
+        let client = Client::builder().timeout(timeout).build().context("Failed to build reqwest client")?;
+        Ok(Self { client, url, secret })
+    }
+}
+
+#[async_trait]
+impl WebhookClient for HttpWebhookClient {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    async fn send(&self, event: &WebhookEvent) -> Result<(), WebhookError> {
+        let body = serde_json::to_vec(event)?;
+        let signature = sign(&self.secret, &body);
+        let response = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, format!("sha256={signature}"))
+            .body(body)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let status = response.status();
+            let message = response.text().await.unwrap_or_default();
+            Err(WebhookError::Status { status, message })
+        }
+    }
+}
+
+/// Sign a webhook request's body, returning the hex encoded HMAC-SHA256 of it.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("serializing event: {0}")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("webhook error: status={status}, message={message}")]
+    Status { status: StatusCode, message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature() {
+        // RFC 4231 test case 2
+        let signature = sign("Jefe", b"what do ya want for nothing?");
+        assert_eq!(signature, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn event_serialization() {
+        let event = WebhookEvent {
+            agent_id: Uuid::nil(),
+            workload_id: Uuid::nil(),
+            event: VmEvent::FailedToStart { error: "oops".into() },
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+        };
+        let value = serde_json::to_value(&event).expect("failed to serialize");
+        let expected = serde_json::json!({
+            "agentId": Uuid::nil(),
+            "workloadId": Uuid::nil(),
+            "event": { "kind": "failedToStart", "error": "oops" },
+            "timestamp": "1970-01-01T00:00:00Z",
+        });
+        assert_eq!(value, expected);
+    }
+}
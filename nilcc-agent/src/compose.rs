@@ -1,21 +1,85 @@
-use cvm_agent_models::bootstrap::{CADDY_ACME_EAB_KEY_ID, CADDY_ACME_EAB_MAC_KEY};
+use cvm_agent_models::{
+    bootstrap::{CADDY_ACME_EAB_KEY_ID, CADDY_ACME_EAB_MAC_KEY},
+    container::JOB_SERVICE_LABEL,
+    encryption::LOG_ENCRYPTION_KEY_EXTENSION,
+};
 use docker_compose_types::{
-    Compose, ComposeNetworks, ComposeVolume, MapOrEmpty, Ports, PublishedPort, Service, StringOrList, TopLevelVolumes,
-    Volumes,
+    Compose, ComposeNetworks, ComposeVolume, Labels, MapOrEmpty, Ports, PublishedPort, Service, StringOrList,
+    TopLevelVolumes, Volumes,
 };
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     iter,
 };
 
 const RESERVED_CONTAINERS: &[&str] = &["nilcc-attester", "nilcc-proxy"];
 const RESERVED_PORTS: &[u16] = &[80, 443];
+const MB: u64 = 1024 * 1024;
+
+/// A docker compose file that passed validation.
+#[derive(Debug)]
+pub(crate) struct ValidatedDockerCompose {
+    /// The images referenced by the compose file's services.
+    pub(crate) images: BTreeSet<String>,
+
+    /// The sum of the resource limits declared by the compose file's services.
+    pub(crate) limits: DeclaredLimits,
+
+    /// The X25519 public key declared in the compose file to encrypt logs and stats to, if any.
+    pub(crate) log_encryption_key: Option<Vec<u8>>,
+}
+
+impl ValidatedDockerCompose {
+    /// Ensure the resource limits declared by the services fit within the resources allocated to the workload.
+    ///
+    /// Services without limits are not taken into account.
+    pub(crate) fn ensure_limits_fit(&self, cpus: u32, memory_mb: u32) -> Result<(), DockerComposeValidationError> {
+        use DockerComposeValidationError as Error;
+        let DeclaredLimits { cpus: declared_cpus, memory_bytes } = self.limits;
+        if declared_cpus > cpus as f64 {
+            return Err(Error::CpuLimits(declared_cpus, cpus));
+        }
+        if memory_bytes > memory_mb as u64 * MB {
+            return Err(Error::MemoryLimits(memory_bytes.div_ceil(MB), memory_mb));
+        }
+        Ok(())
+    }
+
+    /// Ensure every image is pinned by digest, which is required when pulling through registry mirrors since those
+    /// could otherwise serve any image under a tag.
+    pub(crate) fn ensure_pinned_images(&self) -> Result<(), DockerComposeValidationError> {
+        match self.images.iter().find(|image| !image.contains("@sha256:")) {
+            Some(image) => Err(DockerComposeValidationError::UnpinnedImage(image.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Ensure the log encryption key requested for a workload, if any, is the one declared in the compose file.
+    pub(crate) fn ensure_log_encryption_key(&self, key: Option<&[u8]>) -> Result<(), DockerComposeValidationError> {
+        match key {
+            Some(key) if self.log_encryption_key.as_deref() != Some(key) => {
+                Err(DockerComposeValidationError::LogEncryptionKeyMismatch)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The resource limits declared by a set of services.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct DeclaredLimits {
+    /// The number of CPUs.
+    pub(crate) cpus: f64,
+
+    /// The memory, in bytes.
+    pub(crate) memory_bytes: u64,
+}
 
 pub(crate) fn validate_docker_compose(
     docker_compose: &str,
     public_container_name: &str,
-    files: &HashMap<String, Vec<u8>>,
-) -> Result<(), DockerComposeValidationError> {
+    files: &HashSet<&str>,
+) -> Result<ValidatedDockerCompose, DockerComposeValidationError> {
     use DockerComposeValidationError as Error;
     for env in &[CADDY_ACME_EAB_KEY_ID, CADDY_ACME_EAB_MAC_KEY] {
         if docker_compose.contains(env) {
@@ -28,9 +92,15 @@ pub(crate) fn validate_docker_compose(
         return Err(Error::Invalid("no services defined".into()));
     }
     let top_level_volumes = validate_top_level_volumes(&compose.volumes)?;
-    let mut found_public_container = false;
+    let log_encryption_key = parse_log_encryption_key(&compose)?;
+    let mut public_service = None;
+    let mut images = BTreeSet::new();
+    let mut limits = DeclaredLimits::default();
     for (service_name, service) in &compose.services.0 {
         let service = service.as_ref().ok_or_else(|| Error::Invalid(format!("no body in service '{service_name}'")))?;
+        if let Some(image) = &service.image {
+            images.insert(image.clone());
+        }
         let container_names = iter::once(service_name).chain(service.container_name.as_ref());
         for container_name in container_names {
             // Make sure it doesn't contain a substring of our reserved container names
@@ -40,11 +110,13 @@ pub(crate) fn validate_docker_compose(
                 }
             }
             if container_name == public_container_name {
-                found_public_container = true;
+                public_service = Some((service_name, service));
             }
         }
-        validate_service(service, &top_level_volumes, files)
+        let service_limits = validate_service(service, &top_level_volumes, files)
             .map_err(|e| Error::InvalidService(service_name.to_string(), e))?;
+        limits.cpus += service_limits.cpus;
+        limits.memory_bytes = limits.memory_bytes.saturating_add(service_limits.memory_bytes);
     }
     if compose.includes.is_some() {
         return Err(Error::Includes);
@@ -53,14 +125,27 @@ pub(crate) fn validate_docker_compose(
         return Err(Error::Secrets);
     }
     validate_networks(&compose.networks)?;
-    if found_public_container { Ok(()) } else { Err(Error::PublicContainer(public_container_name.to_string())) }
+    let (public_service, service) =
+        public_service.ok_or_else(|| Error::PublicContainer(public_container_name.to_string()))?;
+    if is_job(service) {
+        return Err(Error::PublicContainerJob(public_service.clone()));
+    }
+    Ok(ValidatedDockerCompose { images, limits, log_encryption_key })
+}
+
+fn parse_log_encryption_key(compose: &Compose) -> Result<Option<Vec<u8>>, DockerComposeValidationError> {
+    let Some(value) = compose.extensions.get(LOG_ENCRYPTION_KEY_EXTENSION) else {
+        return Ok(None);
+    };
+    let key = value.as_str().and_then(|key| hex::decode(key).ok()).filter(|key| key.len() == 32);
+    key.map(Some).ok_or(DockerComposeValidationError::LogEncryptionKey)
 }
 
 fn validate_service(
     service: &Service,
     top_level_volumes: &HashSet<&str>,
-    files: &HashMap<String, Vec<u8>>,
-) -> Result<(), ServiceValidationError> {
+    files: &HashSet<&str>,
+) -> Result<DeclaredLimits, ServiceValidationError> {
     use ServiceValidationError as Error;
     validate_ports(&service.ports)?;
     if !service.cap_add.is_empty() {
@@ -95,7 +180,62 @@ fn validate_service(
         return Err(Error::Cgroups);
     }
 
-    Ok(())
+    validate_resource_limits(service)
+}
+
+fn validate_resource_limits(service: &Service) -> Result<DeclaredLimits, ServiceValidationError> {
+    use ServiceValidationError as Error;
+    let Some(deploy) = &service.deploy else {
+        return Ok(DeclaredLimits::default());
+    };
+    let Some(limits) = deploy.resources.as_ref().and_then(|resources| resources.limits.as_ref()) else {
+        return Ok(DeclaredLimits::default());
+    };
+    let replicas = u64::try_from(deploy.replicas.unwrap_or(1)).map_err(|_| Error::InvalidReplicas)?;
+    let cpus = match &limits.cpus {
+        Some(cpus) => parse_cpus(cpus)?,
+        None => 0.0,
+    };
+    let memory_bytes = match &limits.memory {
+        Some(memory) => parse_memory(memory)?,
+        None => 0,
+    };
+    Ok(DeclaredLimits { cpus: cpus * replicas as f64, memory_bytes: memory_bytes.saturating_mul(replicas) })
+}
+
+fn parse_cpus(cpus: &str) -> Result<f64, ServiceValidationError> {
+    match cpus.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(ServiceValidationError::InvalidCpuLimit(cpus.to_string())),
+    }
+}
+
+/// Parses a memory amount using docker's format (e.g. `512m`, `1.5g`, `1024kb`) into bytes.
+fn parse_memory(memory: &str) -> Result<u64, ServiceValidationError> {
+    let invalid = || ServiceValidationError::InvalidMemoryLimit(memory.to_string());
+    let normalized = memory.trim().to_ascii_lowercase();
+    let unit_start = normalized.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(normalized.len());
+    let (value, unit) = normalized.split_at(unit_start);
+    let multiplier: u64 = match unit {
+        "" | "b" => 1,
+        "k" | "kb" => 1024,
+        "m" | "mb" => MB,
+        "g" | "gb" => 1024 * MB,
+        _ => return Err(invalid()),
+    };
+    let value: f64 = value.parse().map_err(|_| invalid())?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(invalid());
+    }
+    Ok((value * multiplier as f64).ceil() as u64)
+}
+
+/// Whether a service is a one-shot job, as declared by its labels.
+fn is_job(service: &Service) -> bool {
+    match &service.labels {
+        Labels::List(labels) => labels.iter().any(|label| label.split_once('=') == Some((JOB_SERVICE_LABEL, "true"))),
+        Labels::Map(labels) => labels.get(JOB_SERVICE_LABEL).is_some_and(|value| value == "true"),
+    }
 }
 
 fn validate_top_level_volumes(volumes: &TopLevelVolumes) -> Result<HashSet<&str>, DockerComposeValidationError> {
@@ -187,7 +327,7 @@ impl PublishedPortExt for PublishedPort {
 fn validate_volumes(
     volume: &Volumes,
     top_level_volumes: &HashSet<&str>,
-    files: &HashMap<String, Vec<u8>>,
+    files: &HashSet<&str>,
 ) -> Result<(), ServiceValidationError> {
     use ServiceValidationError as Error;
     let Volumes::Simple(spec) = volume else {
@@ -203,7 +343,7 @@ fn validate_volumes(
     validate_volume_path(source, files)
 }
 
-fn validate_volume_path(path: &str, files: &HashMap<String, Vec<u8>>) -> Result<(), ServiceValidationError> {
+fn validate_volume_path(path: &str, files: &HashSet<&str>) -> Result<(), ServiceValidationError> {
     use ServiceValidationError as Error;
     if path.contains("../") {
         return Err(Error::MountDotDot);
@@ -221,14 +361,14 @@ fn validate_volume_path(path: &str, files: &HashMap<String, Vec<u8>>) -> Result<
     let path = path.strip_prefix('/').ok_or_else(|| ServiceValidationError::NoSourceMount(path.to_string()))?;
 
     // Otherwise make sure the referenced files are mounted.
-    if !files.contains_key(path) {
+    if !files.contains(path) {
         return Err(ServiceValidationError::MissingMount(path.to_string()));
     }
 
     Ok(())
 }
 
-fn validate_env_file(env: &StringOrList, files: &HashMap<String, Vec<u8>>) -> Result<(), ServiceValidationError> {
+fn validate_env_file(env: &StringOrList, files: &HashSet<&str>) -> Result<(), ServiceValidationError> {
     match env {
         StringOrList::Simple(path) => validate_volume_path(path, files),
         StringOrList::List(paths) => {
@@ -270,6 +410,100 @@ fn validate_networks(networks: &ComposeNetworks) -> Result<(), DockerComposeVali
     Ok(())
 }
 
+/// Ensure every variable interpolated in the docker compose file is provided.
+///
+/// This follows the compose spec's interpolation syntax: `${VAR:-default}` and `${VAR-default}` only need the
+/// variables in their default value if `VAR` is not provided, `${VAR:+alt}` and `${VAR+alt}` only need the ones in
+/// their alternative value if it is, and `${VAR:?err}`, `${VAR?err}`, `${VAR}` and `$VAR` need `VAR` to be provided.
+pub(crate) fn validate_interpolations<F>(
+    docker_compose: &str,
+    is_provided: F,
+) -> Result<(), DockerComposeValidationError>
+where
+    F: Fn(&str) -> bool,
+{
+    let compose: serde_yaml::Value = serde_yaml::from_str(docker_compose)?;
+    let mut missing = BTreeSet::new();
+    let mut pending = vec![&compose];
+    while let Some(value) = pending.pop() {
+        match value {
+            serde_yaml::Value::String(value) => find_missing_variables(value, &is_provided, &mut missing),
+            serde_yaml::Value::Sequence(values) => pending.extend(values),
+            serde_yaml::Value::Mapping(mapping) => pending.extend(mapping.values()),
+            serde_yaml::Value::Tagged(tagged) => pending.push(&tagged.value),
+            _ => (),
+        }
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(DockerComposeValidationError::MissingEnvVars(missing.into_iter().collect()))
+    }
+}
+
+fn find_missing_variables<F>(value: &str, is_provided: &F, missing: &mut BTreeSet<String>)
+where
+    F: Fn(&str) -> bool,
+{
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut rest = value;
+    while let Some(position) = rest.find('$') {
+        rest = &rest[position + 1..];
+        if let Some(tail) = rest.strip_prefix('$') {
+            // `$$` is an escaped `$`.
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix('{') {
+            // Malformed interpolations are rejected by docker compose itself, so just stop here.
+            let Some(end) = closing_brace(tail) else {
+                return;
+            };
+            let (body, tail) = (&tail[..end], &tail[end + 1..]);
+            rest = tail;
+            let (name, modifier) = body.split_at(body.find(|c| !is_name_char(c)).unwrap_or(body.len()));
+            if name.is_empty() {
+                continue;
+            }
+            let provided = is_provided(name);
+            if let Some(default) = modifier.strip_prefix(":-").or_else(|| modifier.strip_prefix('-')) {
+                if !provided {
+                    find_missing_variables(default, is_provided, missing);
+                }
+            } else if let Some(alternative) = modifier.strip_prefix(":+").or_else(|| modifier.strip_prefix('+')) {
+                if provided {
+                    find_missing_variables(alternative, is_provided, missing);
+                }
+            } else if !provided {
+                missing.insert(name.to_string());
+            }
+        } else {
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            let name = &rest[..end];
+            if !name.is_empty() && !is_provided(name) {
+                missing.insert(name.to_string());
+            }
+            rest = &rest[end..];
+        }
+    }
+}
+
+/// Find the position of the brace that closes an interpolation, taking nested ones into account.
+fn closing_brace(value: &str) -> Option<usize> {
+    let mut depth = 1;
+    for (index, c) in value.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum DockerComposeValidationError {
     #[error("malformed docker compose: {0}")]
@@ -307,6 +541,27 @@ pub(crate) enum DockerComposeValidationError {
 
     #[error("invalid service '{0}': {1}")]
     InvalidService(String, ServiceValidationError),
+
+    #[error("services declare {0} CPUs in their limits but the workload only has {1}")]
+    CpuLimits(f64, u32),
+
+    #[error("services declare {0}MB of memory in their limits but the workload only has {1}MB")]
+    MemoryLimits(u64, u32),
+
+    #[error("missing environment variables: {}", .0.join(", "))]
+    MissingEnvVars(Vec<String>),
+
+    #[error("service '{0}' the public container belongs to can't be a job")]
+    PublicContainerJob(String),
+
+    #[error("'{LOG_ENCRYPTION_KEY_EXTENSION}' must be a hex encoded 32 byte X25519 public key")]
+    LogEncryptionKey,
+
+    #[error("log encryption key must match the one in '{LOG_ENCRYPTION_KEY_EXTENSION}'")]
+    LogEncryptionKeyMismatch,
+
+    #[error("image '{0}' must be pinned by digest when using registry mirrors")]
+    UnpinnedImage(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -361,6 +616,15 @@ pub(crate) enum ServiceValidationError {
 
     #[error("cannot use network-mode")]
     NetworkMode,
+
+    #[error("invalid CPU limit: '{0}'")]
+    InvalidCpuLimit(String),
+
+    #[error("invalid memory limit: '{0}'")]
+    InvalidMemoryLimit(String),
+
+    #[error("invalid number of replicas")]
+    InvalidReplicas,
 }
 
 #[cfg(test)]
@@ -451,7 +715,7 @@ services:
 volumes:
   other:
 "#;
-        let files = ["foo", "bar", "dotenv1", "dotenv2"].into_iter().map(|file| (file.to_string(), vec![])).collect();
+        let files = ["foo", "bar", "dotenv1", "dotenv2"].into_iter().collect();
         validate_docker_compose(compose, "api", &files).expect("validation failed");
     }
 
@@ -475,6 +739,42 @@ services:
         validate_success(compose, "api");
     }
 
+    #[test]
+    fn images() {
+        let compose = r#"
+services:
+  api:
+    image: caddy:2
+  worker:
+    image: ghcr.io/foo/worker@sha256:abcd
+  other-worker:
+    image: ghcr.io/foo/worker@sha256:abcd
+"#;
+        let validated = validate_docker_compose(compose, "api", &Default::default()).expect("validation failed");
+        let images: Vec<_> = validated.images.into_iter().collect();
+        assert_eq!(images, &["caddy:2", "ghcr.io/foo/worker@sha256:abcd"]);
+    }
+
+    #[test]
+    fn pinned_images() {
+        let compose = r#"
+services:
+  api:
+    image: caddy@sha256:abcd
+  worker:
+    image: ghcr.io/foo/worker:latest
+"#;
+        let validated = validate_docker_compose(compose, "api", &Default::default()).expect("validation failed");
+        let err = validated.ensure_pinned_images().expect_err("unpinned image accepted");
+        assert!(
+            matches!(err, DockerComposeValidationError::UnpinnedImage(image) if image == "ghcr.io/foo/worker:latest")
+        );
+
+        let compose = compose.replace("worker:latest", "worker@sha256:1234");
+        let validated = validate_docker_compose(&compose, "api", &Default::default()).expect("validation failed");
+        validated.ensure_pinned_images().expect("pinned images rejected");
+    }
+
     #[test]
     fn container_not_found() {
         let compose = r#"
@@ -886,4 +1186,173 @@ services:
 "#;
         validate_failure(&compose, "api", ServiceValidationError::MissingMount("foo/bar".into()));
     }
+
+    #[rstest]
+    #[case::bytes("1024", 1024)]
+    #[case::bytes_suffix("1024b", 1024)]
+    #[case::kilobytes("2k", 2048)]
+    #[case::kilobytes_long("2kb", 2048)]
+    #[case::megabytes("512m", 512 * MB)]
+    #[case::megabytes_uppercase("512M", 512 * MB)]
+    #[case::gigabytes("1g", 1024 * MB)]
+    #[case::fractional("1.5gb", 1536 * MB)]
+    fn memory_limit(#[case] memory: &str, #[case] expected: u64) {
+        assert_eq!(parse_memory(memory).expect("invalid memory"), expected);
+    }
+
+    #[rstest]
+    #[case::empty("")]
+    #[case::unit_only("m")]
+    #[case::unknown_unit("1t")]
+    #[case::negative("-1m")]
+    fn invalid_memory_limit(#[case] memory: &str) {
+        parse_memory(memory).expect_err("parsing succeeded");
+    }
+
+    #[test]
+    fn declared_limits() {
+        let compose = r#"
+services:
+  api:
+    image: caddy:2
+    deploy:
+      resources:
+        limits:
+          cpus: "0.5"
+          memory: 512m
+  worker:
+    image: worker:1
+    deploy:
+      replicas: 2
+      resources:
+        limits:
+          cpus: "1"
+          memory: 1g
+  unconstrained:
+    image: other:1
+"#;
+        let validated = validate_docker_compose(compose, "api", &Default::default()).expect("validation failed");
+        assert_eq!(validated.limits, DeclaredLimits { cpus: 2.5, memory_bytes: 2560 * MB });
+        validated.ensure_limits_fit(3, 2560).expect("limits don't fit");
+
+        let err = validated.ensure_limits_fit(2, 4096).expect_err("limits fit");
+        assert!(matches!(err, DockerComposeValidationError::CpuLimits(..)), "{err}");
+
+        let err = validated.ensure_limits_fit(4, 2048).expect_err("limits fit");
+        assert!(matches!(err, DockerComposeValidationError::MemoryLimits(2560, 2048)), "{err}");
+    }
+
+    #[test]
+    fn jobs() {
+        let compose = r#"
+services:
+  api:
+    image: caddy:2
+  seed:
+    image: seed:1
+    labels:
+      - nilcc.job=true
+  migrate:
+    image: migrate:1
+    labels:
+      nilcc.job: "true"
+  worker:
+    image: worker:1
+    labels:
+      nilcc.job: "false"
+"#;
+        let compose: Compose = serde_yaml::from_str(compose).expect("invalid compose");
+        let jobs: Vec<_> = compose
+            .services
+            .0
+            .iter()
+            .filter(|(_, service)| service.as_ref().is_some_and(is_job))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(jobs, &["seed", "migrate"]);
+
+        let compose = r#"
+services:
+  api:
+    image: caddy:2
+    labels:
+      nilcc.job: "true"
+"#;
+        let err = validate_docker_compose(compose, "api", &Default::default()).expect_err("validation succeeded");
+        assert!(matches!(err, DockerComposeValidationError::PublicContainerJob(_)), "{err}");
+    }
+
+    #[test]
+    fn log_encryption_key() {
+        let key = [1; 32];
+        let compose = format!(
+            r"
+{LOG_ENCRYPTION_KEY_EXTENSION}: {}
+services:
+  api:
+    image: caddy:2
+",
+            hex::encode(key)
+        );
+        let validated = validate_docker_compose(&compose, "api", &Default::default()).expect("validation failed");
+        assert_eq!(validated.log_encryption_key.as_deref(), Some(key.as_slice()));
+        validated.ensure_log_encryption_key(None).expect("key mismatch");
+        validated.ensure_log_encryption_key(Some(&key)).expect("key mismatch");
+
+        let err = validated.ensure_log_encryption_key(Some(&[2; 32])).expect_err("key matches");
+        assert!(matches!(err, DockerComposeValidationError::LogEncryptionKeyMismatch), "{err}");
+    }
+
+    #[rstest]
+    #[case::not_hex("foo")]
+    #[case::short("0102")]
+    fn invalid_log_encryption_key(#[case] key: &str) {
+        let compose = format!(
+            r"
+{LOG_ENCRYPTION_KEY_EXTENSION}: {key}
+services:
+  api:
+    image: caddy:2
+"
+        );
+        let err = validate_docker_compose(&compose, "api", &Default::default()).expect_err("validation succeeded");
+        assert!(matches!(err, DockerComposeValidationError::LogEncryptionKey), "{err}");
+    }
+
+    #[test]
+    fn invalid_cpu_limit() {
+        let compose = r#"
+services:
+  api:
+    image: caddy:2
+    deploy:
+      resources:
+        limits:
+          cpus: "lots"
+"#;
+        validate_failure(compose, "api", ServiceValidationError::InvalidCpuLimit("lots".into()));
+    }
+
+    #[rstest]
+    #[case::braces("FOO: ${A}", &["A"])]
+    #[case::plain("FOO: $A/bar", &["A"])]
+    #[case::required("FOO: ${A:?must be set} ${B?must be set}", &["A", "B"])]
+    #[case::defaults("FOO: ${A:-foo} ${B-bar}", &[])]
+    #[case::nested_default("FOO: ${A:-${B}}", &["B"])]
+    #[case::provided_default("FOO: ${PROVIDED:-${B}}", &[])]
+    #[case::alternative("FOO: ${A:+${B}}", &[])]
+    #[case::provided_alternative("FOO: ${PROVIDED:+${B}}", &["B"])]
+    #[case::escaped("FOO: $$A $${B}", &[])]
+    #[case::provided("FOO: ${PROVIDED} $PROVIDED", &[])]
+    #[case::lone_dollar("FOO: costs 5 $", &[])]
+    #[case::comment("# uses ${A}\nFOO: bar", &[])]
+    #[case::list("FOO: [$A, $B, $A]", &["A", "B"])]
+    fn interpolations(#[case] compose: &str, #[case] expected: &[&str]) {
+        let result = validate_interpolations(compose, |name| name == "PROVIDED");
+        match result {
+            Ok(()) => assert!(expected.is_empty(), "expected missing variables {expected:?}"),
+            Err(DockerComposeValidationError::MissingEnvVars(missing)) => assert_eq!(missing, expected),
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
 }
@@ -1,6 +1,10 @@
 use anyhow::Context;
 use bitcoin::bip32::DerivationPath;
+use cvm_agent_models::bootstrap::PrivatePki;
+use nilcc_agent_models::workloads::create::{ImagePolicyMode, StateDisk};
+use nilcc_artifacts::signature::SigningKey;
 use serde::{Deserialize, Deserializer, Serialize};
+use serde_with::DurationMilliSeconds;
 use serde_with::DurationSeconds;
 use serde_with::hex::Hex;
 use serde_with::serde_as;
@@ -51,12 +55,82 @@ pub struct AgentConfig {
 
     /// The heartbeat verifier configuration.
     pub verifier_heartbeat: VerifierHeartbeatConfig,
+
+    /// The optional image vulnerability policy configuration.
+    #[serde(default)]
+    pub image_policy: Option<ImagePolicyConfig>,
+
+    /// The public IP change detection configuration.
+    #[serde(default)]
+    pub public_ip: PublicIpConfig,
+
+    /// The workload usage tracking configuration.
+    #[serde(default)]
+    pub usage: UsageConfig,
+
+    /// The host networking configuration.
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    /// The workload state disk configuration.
+    #[serde(default)]
+    pub state_disk: StateDiskConfig,
+
+    /// The storage backend workload disks are created in.
+    #[serde(default)]
+    pub storage: StorageConfig,
+
+    /// The event webhooks configuration.
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
+
+    /// The configuration for forwarding workload events to the nilcc API.
+    #[serde(default)]
+    pub events: EventsConfig,
+
+    /// The optional trusted time synchronization configuration for CVMs.
+    #[serde(default)]
+    pub time_sync: Option<TimeSyncConfig>,
+
+    /// The host disk space watchdog configuration.
+    #[serde(default)]
+    pub disk_watchdog: DiskWatchdogConfig,
+
+    /// The configuration for preempting low priority workloads when the host is under pressure.
+    #[serde(default)]
+    pub pressure: PressureConfig,
+
+    /// The artifacts garbage collection configuration.
+    #[serde(default)]
+    pub artifacts_gc: ArtifactsGcConfig,
+
+    /// The optional private PKI CVMs get their TLS certificates from instead of ZeroSSL.
+    #[serde(default)]
+    pub private_pki: Option<PrivatePkiConfig>,
+
+    /// The optional configuration for the platform claims the agent signs and hands to CVMs.
+    #[serde(default)]
+    pub platform_claims: Option<PlatformClaimsConfig>,
+
+    /// The agent upgrade configuration.
+    #[serde(default)]
+    pub agent_upgrade: AgentUpgradeConfig,
+
+    /// The chunked file upload configuration.
+    #[serde(default)]
+    pub uploads: UploadsConfig,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct CvmConfigs {
     /// The base path where all configs are.
     pub artifacts_path: PathBuf,
+
+    /// The keys trusted to sign artifacts metadata.
+    ///
+    /// If any are set, artifacts versions are only installed if their metadata is signed by one of these keys.
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKey>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -66,6 +140,22 @@ pub struct QemuConfig {
 
     /// Path to the qemu-img binary
     pub img_bin: PathBuf,
+
+    /// Overrides for the SEV-SNP parameters in the artifacts metadata.
+    #[serde(default)]
+    pub snp: SnpConfig,
+}
+
+/// SEV-SNP parameters that depend on the host's CPU.
+///
+/// These aren't part of a CVM's measurement so they can be set per host without affecting verification.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SnpConfig {
+    /// The position of the C-bit in page table entries.
+    pub cbitpos: Option<u8>,
+
+    /// The number of physical address bits lost when memory encryption is enabled.
+    pub reduced_phys_bits: Option<u8>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -96,15 +186,199 @@ pub struct ApiConfig {
     pub domain: String,
 
     /// The API key that needs to be presented when making requests to this instance.
+    ///
+    /// This token has the `admin` scope and is the one handed over to nilcc-api on registration.
     pub token: String,
+
+    /// Additional API tokens, each of them limited to a scope.
+    #[serde(default)]
+    pub tokens: Vec<ApiTokenConfig>,
+
+    /// Additional endpoints to bind to.
+    ///
+    /// These are served over plain HTTP even if TLS is configured, and requests still need to present the API token.
+    #[serde(default)]
+    pub additional_bind_endpoints: Vec<SocketAddr>,
+
+    /// An optional unix socket to serve the API on.
+    #[serde(default)]
+    pub unix_socket: Option<UnixSocketConfig>,
+
+    /// Serve the API on the sockets passed in via systemd socket activation, if any.
+    #[serde(default)]
+    pub systemd_activation: Option<SystemdActivationConfig>,
+
+    /// The rate limits for the public attestation proxy endpoint.
+    #[serde(default)]
+    pub public_attestation: PublicAttestationConfig,
+
+    /// The limits for requests proxied to the workloads' cvm-agent instances.
+    #[serde(default)]
+    pub cvm_agent_limits: CvmAgentLimitsConfig,
+
+    /// Whether workloads can be launched in debug mode, with their serial console exposed over a websocket.
+    ///
+    /// Debug workloads can't be attested so this should only be enabled in development environments.
+    #[serde(default)]
+    pub debug_console: bool,
+}
+
+impl ApiConfig {
+    /// All the tokens that are accepted by the API, including the main one.
+    pub fn scoped_tokens(&self) -> Vec<ApiTokenConfig> {
+        let main = ApiTokenConfig { name: "default".into(), token: self.token.clone(), scope: ApiScope::Admin };
+        let mut tokens = vec![main];
+        tokens.extend(self.tokens.iter().cloned());
+        tokens
+    }
+}
+
+/// An API token restricted to a scope.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApiTokenConfig {
+    /// A name for this token, used to identify who made a request in the audit log.
+    pub name: String,
+
+    /// The token that needs to be presented in requests.
+    pub token: String,
+
+    /// The scope this token is granted.
+    pub scope: ApiScope,
+}
+
+/// The scope an API token is granted.
+///
+/// Scopes are ordered: every scope is allowed to do everything the ones before it can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, strum::Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum ApiScope {
+    /// Only allows reading information, e.g. listing workloads or getting their logs.
+    ReadOnly,
+
+    /// Also allows managing workloads, e.g. creating, restarting, or deleting them.
+    WorkloadOperator,
+
+    /// Allows everything, including system operations like upgrades and key rotations.
+    Admin,
+}
+
+/// The configuration for the API's unix socket.
+///
+/// Requests made over this socket are not authenticated, access to it is controlled via the socket's file permissions.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UnixSocketConfig {
+    /// The path to the socket.
+    pub path: PathBuf,
+
+    /// The socket's file mode.
+    #[serde(default = "default_unix_socket_mode")]
+    pub mode: u32,
+
+    /// The group id to set as the socket's owner group, if any.
+    #[serde(default)]
+    pub group_id: Option<u32>,
+
+    /// The peers allowed to connect to the socket.
+    #[serde(default)]
+    pub allowed_peers: PeerCredentialsConfig,
+}
+
+/// The configuration for the API's systemd activated sockets.
+///
+/// Requests made over activated TCP sockets need to present the API token, like any other TCP listener. Requests made
+/// over activated unix sockets are not authenticated.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SystemdActivationConfig {
+    /// The peers allowed to connect to activated unix sockets.
+    #[serde(default)]
+    pub allowed_peers: PeerCredentialsConfig,
+}
+
+/// The configuration for the unauthenticated `/attestation/{workload_domain}` endpoint.
+///
+/// Requests are rate limited per client address using a fixed window.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct PublicAttestationConfig {
+    /// The maximum number of requests a client can make within a window.
+    #[serde(default = "default_public_attestation_max_requests")]
+    pub max_requests: u32,
+
+    /// The length of a rate limiting window.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_public_attestation_window")]
+    pub window_seconds: Duration,
+}
+
+impl Default for PublicAttestationConfig {
+    fn default() -> Self {
+        Self {
+            max_requests: default_public_attestation_max_requests(),
+            window_seconds: default_public_attestation_window(),
+        }
+    }
+}
+
+/// The limits for requests proxied to `cvm-agent` instances, like the ones to get logs, stats, or health.
+///
+/// These prevent aggressive API clients from overwhelming a small CVM's agent and starving the application running
+/// in it. Requests that go over the limits are queued and, if they can't be proxied within the queue timeout, they're
+/// rejected with a 429.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct CvmAgentLimitsConfig {
+    /// The maximum number of requests proxied concurrently across all workloads.
+    #[serde(default = "default_cvm_agent_max_requests")]
+    pub max_requests: usize,
+
+    /// The maximum number of requests proxied concurrently to a single workload.
+    #[serde(default = "default_cvm_agent_max_workload_requests")]
+    pub max_workload_requests: usize,
+
+    /// How long a request can be queued for before it's rejected.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_cvm_agent_queue_timeout")]
+    pub queue_timeout_seconds: Duration,
+}
+
+impl Default for CvmAgentLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_requests: default_cvm_agent_max_requests(),
+            max_workload_requests: default_cvm_agent_max_workload_requests(),
+            queue_timeout_seconds: default_cvm_agent_queue_timeout(),
+        }
+    }
+}
+
+/// The peers allowed to connect to a unix socket, identified by their credentials.
+///
+/// A peer is allowed if either its user or group id is listed. Any peer is allowed if both lists are empty.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PeerCredentialsConfig {
+    /// The allowed user ids.
+    #[serde(default)]
+    pub uids: Vec<u32>,
+
+    /// The allowed group ids.
+    #[serde(default)]
+    pub gids: Vec<u32>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct DbConfig {
     /// The database URL.
     pub url: String,
+
+    /// A backup created via `POST /api/v1/system/backup` to restore the database from.
+    ///
+    /// This is only used if the database doesn't exist yet, e.g. after a host is reprovisioned.
+    #[serde(default)]
+    pub restore_from: Option<PathBuf>,
 }
 
+#[serde_as]
 #[derive(Clone, Debug, Deserialize)]
 pub struct SniProxyConfig {
     /// Start of the port range for the SNI proxy.
@@ -130,6 +404,11 @@ pub struct SniProxyConfig {
     /// Whether to tell haproxy to reload the config.
     #[serde(default = "default_true")]
     pub reload_config: bool,
+
+    /// How long a workload's previous domain keeps being served after its domain is changed.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_domain_grace_period")]
+    pub domain_grace_period_seconds: Duration,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -168,6 +447,42 @@ pub struct ResourcesConfig {
     /// The resource limits for VMs.
     #[serde(default)]
     pub limits: ResourceLimitsConfig,
+
+    /// Automatically tune the reserved CPUs and memory based on the host's observed overhead.
+    #[serde(default)]
+    pub auto_tune: Option<ReservationAutoTuneConfig>,
+
+    /// Pin every VM's vCPUs and memory to a single NUMA node.
+    #[serde(default)]
+    pub numa_pinning: bool,
+}
+
+/// The configuration for automatically tuning the host's reserved resources.
+///
+/// The reserved resources in the `reserved` section act as a lower bound: the reservation never goes below them.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct ReservationAutoTuneConfig {
+    /// How often the host's overhead is sampled.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_auto_tune_sample_interval")]
+    pub sample_interval_seconds: Duration,
+
+    /// The number of samples the reservation is based on.
+    ///
+    /// The reservation isn't changed until this many samples have been taken.
+    #[serde(default = "default_auto_tune_window")]
+    pub window_samples: usize,
+
+    /// The headroom, in percent, added on top of the peak overhead seen.
+    #[serde(default = "default_auto_tune_margin")]
+    pub margin_percent: u32,
+
+    /// The maximum number of CPUs that can be reserved.
+    pub max_cpus: u32,
+
+    /// The maximum memory, in MBs, that can be reserved.
+    pub max_memory_mb: u32,
 }
 
 /// The reserved resources configuration.
@@ -213,26 +528,124 @@ pub struct ZeroSslConfig {
 
     /// The EAB MAC key.
     pub eab_mac_key: String,
+
+    /// Additional ZeroSSL accounts to spread certificate issuance across.
+    #[serde(default)]
+    pub pool: Vec<ZeroSslAccountConfig>,
+}
+
+/// The EAB credentials for a ZeroSSL account.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ZeroSslAccountConfig {
+    /// The EAB key id.
+    pub eab_key_id: String,
+
+    /// The EAB MAC key.
+    pub eab_mac_key: String,
 }
 
 /// The TLS configuration.
 #[derive(Clone, Debug, Deserialize)]
-pub struct TlsConfig {
+#[serde(untagged)]
+pub enum TlsConfig {
+    /// Use a pre-issued certificate.
+    Certificate(TlsCertificateConfig),
+
+    /// Request a certificate via ACME.
+    Acme(AcmeTlsConfig),
+}
+
+/// A pre-issued TLS certificate.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TlsCertificateConfig {
+    /// The path to the PEM encoded certificate chain.
+    pub cert_chain_path: PathBuf,
+
+    /// The path to the PEM encoded private key.
+    pub private_key_path: PathBuf,
+}
+
+/// The ACME TLS configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AcmeTlsConfig {
     /// The path to the certificate cache.
     pub cert_cache: PathBuf,
 
     /// The contact email address to use for ACME requests.
     pub acme_contact: String,
+
+    /// The ACME directory URL, Let's Encrypt's if not set.
+    #[serde(default)]
+    pub acme_directory: Option<String>,
 }
 
-/// The docker hub credentials to use.
+/// A private PKI CVMs get their TLS certificates from.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PrivatePkiConfig {
+    /// Have CVMs issue their own certificates from an internal CA, generating the private keys themselves.
+    Ca {
+        /// The path to the PEM encoded CA certificate.
+        ca_cert_path: PathBuf,
+
+        /// The path to the PEM encoded CA private key.
+        ca_key_path: PathBuf,
+    },
+
+    /// Request certificates from an internal ACME directory.
+    Acme {
+        /// The ACME directory URL.
+        directory_url: String,
+
+        /// The path to the PEM encoded root CA that signs the ACME directory's own certificate.
+        #[serde(default)]
+        root_ca_path: Option<PathBuf>,
+    },
+}
+
+impl PrivatePkiConfig {
+    /// Load the files this configuration points to.
+    pub fn load(&self) -> anyhow::Result<PrivatePki> {
+        let read = |path: &PathBuf| {
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+        };
+        let pki = match self {
+            Self::Ca { ca_cert_path, ca_key_path } => {
+                PrivatePki::Ca { ca_cert_pem: read(ca_cert_path)?, ca_key_pem: read(ca_key_path)? }
+            }
+            Self::Acme { directory_url, root_ca_path } => PrivatePki::Acme {
+                directory_url: directory_url.clone(),
+                root_ca_pem: root_ca_path.as_ref().map(read).transpose()?,
+            },
+        };
+        Ok(pki)
+    }
+}
+
+/// The platform claims configuration.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlatformClaimsConfig {
+    /// The hex encoded secp256k1 private key the claims are signed with.
+    #[serde_as(as = "Hex")]
+    pub signing_key: [u8; 32],
+
+    /// The region this agent's metal instance is located in.
+    pub region: String,
+}
+
+/// The docker configuration to use.
 #[derive(Clone, Debug, Deserialize)]
 pub struct DockerConfig {
-    /// The username.
+    /// The docker hub username.
     pub username: String,
 
-    /// The password.
+    /// The docker hub password.
     pub password: String,
+
+    /// The registry mirrors CVMs pull images through, unless a workload overrides them. These must be https URLs.
+    #[serde(default)]
+    pub registry_mirrors: Vec<String>,
 }
 
 /// The verifier heartbeat configuration.
@@ -262,6 +675,399 @@ pub struct VerifierHeartbeatConfig {
     pub token_contract_address: String,
 }
 
+/// The image vulnerability policy configuration.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct ImagePolicyConfig {
+    /// The URL of the trivy server to use, which must expose the pluggable scanner API.
+    pub trivy_server_url: String,
+
+    /// What to do when an image has critical vulnerabilities, unless overridden in a request.
+    #[serde(default)]
+    pub mode: ImagePolicyMode,
+
+    /// The timeout for a single image scan.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_image_scan_timeout")]
+    pub scan_timeout_seconds: Duration,
+}
+
+/// The workload state disk configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct StateDiskConfig {
+    /// The state disk mode used for workloads that don't request one.
+    #[serde(default)]
+    pub mode: StateDisk,
+}
+
+/// The storage backend workload disks are created in.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum StorageConfig {
+    /// Create disks as files in the VM store.
+    #[default]
+    Files,
+
+    /// Create disks as thin logical volumes in an LVM thin pool.
+    LvmThin {
+        /// The volume group the thin pool belongs to.
+        volume_group: String,
+
+        /// The name of the thin pool logical volume.
+        thin_pool: String,
+    },
+}
+
+/// The public IP change detection configuration.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct PublicIpConfig {
+    /// How often the public IP address is re-detected.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_public_ip_check_interval")]
+    pub check_interval_seconds: Duration,
+
+    /// The optional DNS update configuration, used to update DNS records when the public IP changes.
+    #[serde(default)]
+    pub dns_update: Option<DnsUpdateConfig>,
+}
+
+impl Default for PublicIpConfig {
+    fn default() -> Self {
+        Self { check_interval_seconds: default_public_ip_check_interval(), dns_update: None }
+    }
+}
+
+/// The host networking configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct NetworkConfig {
+    /// Whether to use IPv6 in addition to IPv4.
+    ///
+    /// When enabled, a public IPv6 address is discovered and registered, workload ports are also forwarded over
+    /// IPv6, and the SNI proxy binds on both address families. This allows running on IPv6-only networks.
+    #[serde(default)]
+    pub ipv6: bool,
+}
+
+/// The DNS update configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DnsUpdateConfig {
+    /// The zone whose records are managed by this agent.
+    ///
+    /// Only the agent's domain and workload domains within this zone are updated.
+    pub zone: String,
+
+    /// The TTL to use for the records.
+    #[serde(default = "default_dns_ttl")]
+    pub ttl: u32,
+
+    /// The provider the records are managed through.
+    #[serde(flatten)]
+    pub provider: DnsProviderConfig,
+}
+
+/// The provider DNS records are managed through.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DnsProviderConfig {
+    /// Manage records via Cloudflare's API.
+    Cloudflare { cloudflare: CloudflareDnsConfig },
+
+    /// Manage records via Route53's API.
+    Route53 { route53: Route53DnsConfig },
+
+    /// Send RFC 2136 dynamic updates via `nsupdate`.
+    Nsupdate {
+        /// The DNS server to send updates to.
+        server: String,
+
+        /// The path to the TSIG key file used to authenticate updates.
+        key_file: PathBuf,
+
+        /// The path to the nsupdate binary.
+        #[serde(default = "default_nsupdate_bin")]
+        nsupdate_bin: PathBuf,
+    },
+}
+
+/// The Cloudflare DNS configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CloudflareDnsConfig {
+    /// The API token used to authenticate, which needs the `Zone.DNS` edit permission.
+    pub api_token: String,
+
+    /// The id of the zone the records live in.
+    pub zone_id: String,
+}
+
+/// The Route53 DNS configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Route53DnsConfig {
+    /// The id of the hosted zone the records live in.
+    pub hosted_zone_id: String,
+
+    /// The AWS access key id used to sign requests.
+    pub access_key_id: String,
+
+    /// The AWS secret access key used to sign requests.
+    pub secret_access_key: String,
+}
+
+/// The workload usage tracking configuration.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct UsageConfig {
+    /// How often the resources allocated to each running workload are sampled.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_usage_sample_interval")]
+    pub sample_interval_seconds: Duration,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self { sample_interval_seconds: default_usage_sample_interval() }
+    }
+}
+
+/// The agent upgrade configuration.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct AgentUpgradeConfig {
+    /// How many times an upgraded agent can start before sending its first heartbeat until it's rolled back.
+    #[serde(default = "default_max_boot_attempts")]
+    pub max_boot_attempts: u32,
+
+    /// How long an upgraded agent has to send its first heartbeat before it's rolled back.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_upgrade_confirmation_timeout")]
+    pub confirmation_timeout_seconds: Duration,
+
+    /// The keys trusted to sign agent binaries.
+    ///
+    /// Upgrades are refused unless the downloaded binary is signed by one of these keys, so the agent can't be
+    /// upgraded at all if none are set.
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKey>,
+}
+
+impl Default for AgentUpgradeConfig {
+    fn default() -> Self {
+        Self {
+            max_boot_attempts: default_max_boot_attempts(),
+            confirmation_timeout_seconds: default_upgrade_confirmation_timeout(),
+            signing_keys: Vec::new(),
+        }
+    }
+}
+
+/// The chunked file upload configuration.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct UploadsConfig {
+    /// The maximum size, in MB, of a file uploaded in chunks.
+    #[serde(default = "default_max_upload_size")]
+    pub max_file_size_mb: u64,
+
+    /// How long an upload is kept after its last chunk was received if no workload uses it.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_upload_expiry")]
+    pub expiry_seconds: Duration,
+}
+
+impl Default for UploadsConfig {
+    fn default() -> Self {
+        Self { max_file_size_mb: default_max_upload_size(), expiry_seconds: default_upload_expiry() }
+    }
+}
+
+/// The host disk space watchdog configuration.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct DiskWatchdogConfig {
+    /// How often free disk space is checked and files left behind by deleted workloads are cleaned up.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_disk_check_interval")]
+    pub check_interval_seconds: Duration,
+
+    /// The minimum free space, in GB, in the VM store and artifacts path filesystems.
+    ///
+    /// Below this, unused artifacts versions are deleted and new workloads are refused until space is freed up.
+    #[serde(default = "default_min_free_disk_space")]
+    pub min_free_space_gb: u64,
+}
+
+impl Default for DiskWatchdogConfig {
+    fn default() -> Self {
+        Self { check_interval_seconds: default_disk_check_interval(), min_free_space_gb: default_min_free_disk_space() }
+    }
+}
+
+/// The configuration for preempting low priority workloads when the host is under memory or disk pressure.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct PressureConfig {
+    /// How often the host's available memory and disk space are checked.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_pressure_check_interval")]
+    pub check_interval_seconds: Duration,
+
+    /// The minimum memory, in MB, available on the host.
+    ///
+    /// Below this, or when disk space is below `disk_watchdog.min_free_space_gb`, low priority workloads are preempted.
+    #[serde(default = "default_min_available_memory")]
+    pub min_available_memory_mb: u64,
+}
+
+impl Default for PressureConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: default_pressure_check_interval(),
+            min_available_memory_mb: default_min_available_memory(),
+        }
+    }
+}
+
+/// The artifacts garbage collection configuration.
+///
+/// Versions used by a workload and the most recently installed ones are always kept.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct ArtifactsGcConfig {
+    /// Whether unused artifacts versions are periodically deleted.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// How often the garbage collection policy is evaluated.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_artifacts_gc_interval")]
+    pub interval_seconds: Duration,
+
+    /// The number of most recently installed versions that are always kept.
+    #[serde(default = "default_artifacts_gc_keep_latest")]
+    pub keep_latest: usize,
+
+    /// The number of days a version must have gone unused before it's deleted.
+    #[serde(default = "default_artifacts_gc_max_unused_days")]
+    pub max_unused_days: u32,
+}
+
+impl Default for ArtifactsGcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: default_artifacts_gc_interval(),
+            keep_latest: default_artifacts_gc_keep_latest(),
+            max_unused_days: default_artifacts_gc_max_unused_days(),
+        }
+    }
+}
+
+/// The event webhooks configuration.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhooksConfig {
+    /// The webhooks workload events are sent to.
+    #[serde(default)]
+    pub sinks: Vec<WebhookSinkConfig>,
+
+    /// The number of times delivering an event to a webhook is attempted before giving up on it.
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+
+    /// The timeout for a single delivery attempt.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_webhook_timeout")]
+    pub timeout_seconds: Duration,
+
+    /// A file where events that couldn't be delivered are appended to, one JSON object per line.
+    #[serde(default)]
+    pub dead_letter_path: Option<PathBuf>,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            sinks: Vec::new(),
+            max_attempts: default_webhook_max_attempts(),
+            timeout_seconds: default_webhook_timeout(),
+            dead_letter_path: None,
+        }
+    }
+}
+
+/// The configuration for forwarding workload events to the nilcc API.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct EventsConfig {
+    /// The maximum number of events waiting to be sent. Once reached, the lowest priority events are dropped.
+    #[serde(default = "default_events_max_queued")]
+    pub max_queued: usize,
+
+    /// The maximum number of events that are queued or sent at once.
+    #[serde(default = "default_events_batch_size")]
+    pub batch_size: usize,
+
+    /// The number of events a workload can send in a burst before it's rate limited.
+    #[serde(default = "default_events_workload_burst")]
+    pub workload_burst: u32,
+
+    /// How often a rate limited workload can send an event.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_events_workload_interval")]
+    pub workload_interval_seconds: Duration,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            max_queued: default_events_max_queued(),
+            batch_size: default_events_batch_size(),
+            workload_burst: default_events_workload_burst(),
+            workload_interval_seconds: default_events_workload_interval(),
+        }
+    }
+}
+
+/// A webhook workload events are sent to.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookSinkConfig {
+    /// The URL to POST events to.
+    pub url: String,
+
+    /// The secret used to sign every request using HMAC-SHA256.
+    pub secret: String,
+}
+
+/// The trusted time synchronization configuration for CVMs.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct TimeSyncConfig {
+    /// The Roughtime servers CVMs query to get a trusted time.
+    pub servers: Vec<RoughtimeServerConfig>,
+
+    /// How often CVMs query the servers.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_time_sync_interval")]
+    pub interval_seconds: Duration,
+
+    /// The clock skew above which CVMs emit a warning event.
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_max_clock_skew")]
+    pub max_skew_ms: Duration,
+}
+
+/// A Roughtime server.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize)]
+pub struct RoughtimeServerConfig {
+    /// The server's address, as a `host:port` pair.
+    pub address: String,
+
+    /// The server's hex encoded Ed25519 public key.
+    #[serde_as(as = "Hex")]
+    pub public_key: Vec<u8>,
+}
+
 pub fn read_file_as_string<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'de>,
@@ -277,6 +1083,34 @@ fn u32_max() -> u32 {
     u32::MAX
 }
 
+fn default_unix_socket_mode() -> u32 {
+    0o600
+}
+
+fn default_public_attestation_max_requests() -> u32 {
+    30
+}
+
+fn default_public_attestation_window() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_cvm_agent_max_requests() -> usize {
+    64
+}
+
+fn default_cvm_agent_max_workload_requests() -> usize {
+    4
+}
+
+fn default_cvm_agent_queue_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_domain_grace_period() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
 fn default_true() -> bool {
     true
 }
@@ -304,3 +1138,111 @@ fn default_base_derivation_path() -> DerivationPath {
 fn default_verifier_heartbeat_interval() -> Duration {
     Duration::from_secs(60 * 10)
 }
+
+fn default_image_scan_timeout() -> Duration {
+    Duration::from_secs(60 * 5)
+}
+
+fn default_public_ip_check_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_nsupdate_bin() -> PathBuf {
+    "nsupdate".into()
+}
+
+fn default_dns_ttl() -> u32 {
+    60
+}
+
+fn default_usage_sample_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_artifacts_gc_interval() -> Duration {
+    Duration::from_secs(3600)
+}
+
+fn default_artifacts_gc_keep_latest() -> usize {
+    3
+}
+
+fn default_artifacts_gc_max_unused_days() -> u32 {
+    30
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    5
+}
+
+fn default_webhook_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_events_max_queued() -> usize {
+    1000
+}
+
+fn default_events_batch_size() -> usize {
+    50
+}
+
+fn default_events_workload_burst() -> u32 {
+    10
+}
+
+fn default_events_workload_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_time_sync_interval() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_max_clock_skew() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_boot_attempts() -> u32 {
+    3
+}
+
+fn default_upgrade_confirmation_timeout() -> Duration {
+    Duration::from_secs(600)
+}
+
+fn default_auto_tune_sample_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_auto_tune_window() -> usize {
+    120
+}
+
+fn default_auto_tune_margin() -> u32 {
+    20
+}
+
+fn default_disk_check_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_min_free_disk_space() -> u64 {
+    20
+}
+
+fn default_pressure_check_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_min_available_memory() -> u64 {
+    1024
+}
+
+fn default_max_upload_size() -> u64 {
+    512
+}
+
+fn default_upload_expiry() -> Duration {
+    Duration::from_secs(60 * 60)
+}
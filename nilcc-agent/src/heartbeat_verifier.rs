@@ -2,17 +2,21 @@ use crate::config::VerifierHeartbeatConfig;
 use anyhow::Context;
 use bitcoin::{
     NetworkKind,
-    bip32::{ChildNumber, Xpriv},
+    bip32::{ChildNumber, DerivationPath, Xpriv},
     key::Secp256k1,
 };
 use std::{
-    collections::BTreeSet,
-    fmt::Debug,
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Debug},
     sync::{Arc, Mutex},
 };
+use tracing::warn;
 
 struct Inner {
+    keys: Vec<Keypair>,
     available_keys: BTreeSet<usize>,
+    // Retired keys along with whether whoever was holding them already released them.
+    retired_keys: BTreeMap<usize, bool>,
 }
 
 #[derive(Clone, Copy)]
@@ -24,48 +28,136 @@ struct Keypair {
 
 #[derive(Clone)]
 pub struct VerifierKeys {
-    keys: Arc<Vec<Keypair>>,
+    master_key: Xpriv,
+    base_derivation_path: DerivationPath,
     inner: Arc<Mutex<Inner>>,
 }
 
 impl VerifierKeys {
     pub fn new(config: &VerifierHeartbeatConfig, key_count: usize) -> anyhow::Result<Self> {
-        let engine = Secp256k1::new();
         let network = NetworkKind::Main;
         let master_key = Xpriv::new_master(network, &config.seed).context("Failed to generate verifier master key")?;
+        let base_derivation_path = config.base_derivation_path.clone();
         // Generate N keys and keep their private/public keys
         let mut keys = Vec::new();
-        for index in 0..key_count as u32 {
-            let key_path = config.base_derivation_path.child(ChildNumber::Hardened { index });
-            let key = master_key.derive_priv(&engine, &key_path).context("Failed to derive private key")?;
-            let private = key.private_key.secret_bytes();
-            let public = key.private_key.public_key(&engine).serialize();
-            let public_uncompressed = key.private_key.public_key(&engine).serialize_uncompressed();
-            keys.push(Keypair { private, public, public_uncompressed });
+        for index in 0..key_count {
+            keys.push(Self::derive(&master_key, &base_derivation_path, index)?);
         }
-        let inner = Inner { available_keys: (0..key_count).collect() };
-        Ok(Self { keys: keys.into(), inner: Arc::new(Mutex::new(inner)) })
+        let inner = Inner { keys, available_keys: (0..key_count).collect(), retired_keys: Default::default() };
+        Ok(Self { master_key, base_derivation_path, inner: Arc::new(Mutex::new(inner)) })
     }
 
     pub fn get(&self, public_key: &[u8]) -> Result<VerifierKey, KeyLookupError> {
-        let key_index = self.keys.iter().position(|k| k.public == public_key).ok_or(KeyLookupError::NotFound)?;
         let mut inner = self.inner.lock().expect("lock poisoned");
+        let key_index = Self::position(&inner, public_key).ok_or(KeyLookupError::NotFound)?;
+        if inner.retired_keys.contains_key(&key_index) {
+            return Err(KeyLookupError::Retired);
+        }
         if !inner.available_keys.remove(&key_index) {
             return Err(KeyLookupError::AlreadyInUse);
         }
-        let key = self.keys[key_index];
+        let key = inner.keys[key_index];
         Ok(VerifierKey { key, key_index, inner: self.inner.clone() })
     }
 
     pub fn next_key(&self) -> Result<VerifierKey, NoMoreKeys> {
         let mut inner = self.inner.lock().expect("lock poisoned");
         let key_index = inner.available_keys.pop_first().ok_or(NoMoreKeys)?;
-        let key = self.keys[key_index];
+        let key = inner.keys[key_index];
         Ok(VerifierKey { key, key_index, inner: self.inner.clone() })
     }
 
+    /// Find a key given either its compressed or uncompressed public key.
+    pub fn find(&self, public_key: &[u8]) -> Option<PublicKey> {
+        let inner = self.inner.lock().expect("lock poisoned");
+        Self::position(&inner, public_key).map(|index| Self::public_key(index, &inner))
+    }
+
     pub fn public_keys(&self) -> Vec<PublicKey> {
-        self.keys.iter().map(|k| PublicKey { public: k.public, public_uncompressed: k.public_uncompressed }).collect()
+        let inner = self.inner.lock().expect("lock poisoned");
+        (0..inner.keys.len()).map(|index| Self::public_key(index, &inner)).collect()
+    }
+
+    /// Retire a key so it's never handed out again.
+    ///
+    /// A key that's currently in use stays with its holder and simply doesn't go back to the pool once released. A
+    /// new key is derived to take the retired one's place so the pool doesn't shrink.
+    pub fn retire(&self, public_key: &[u8]) -> Result<(), KeyLookupError> {
+        let mut inner = self.inner.lock().expect("lock poisoned");
+        let key_index = Self::position(&inner, public_key).ok_or(KeyLookupError::NotFound)?;
+        if inner.retired_keys.contains_key(&key_index) {
+            return Ok(());
+        }
+        let released = inner.available_keys.remove(&key_index);
+        inner.retired_keys.insert(key_index, released);
+        self.replenish(&mut inner);
+        Ok(())
+    }
+
+    /// Retire a key as long as nobody is using it.
+    pub fn retire_unused(&self, public_key: &[u8]) -> Result<(), KeyLookupError> {
+        let mut inner = self.inner.lock().expect("lock poisoned");
+        let key_index = Self::position(&inner, public_key).ok_or(KeyLookupError::NotFound)?;
+        if inner.retired_keys.contains_key(&key_index) {
+            return Err(KeyLookupError::Retired);
+        }
+        if !inner.available_keys.remove(&key_index) {
+            return Err(KeyLookupError::AlreadyInUse);
+        }
+        inner.retired_keys.insert(key_index, true);
+        self.replenish(&mut inner);
+        Ok(())
+    }
+
+    /// Undo the retirement of a key, putting it back in the pool unless it's still being held.
+    ///
+    /// The most recently derived replacement key is dropped, as long as it hasn't been handed out yet, so the pool
+    /// keeps its size.
+    pub fn reinstate(&self, public_key: &[u8]) -> Result<(), KeyLookupError> {
+        let mut inner = self.inner.lock().expect("lock poisoned");
+        let key_index = Self::position(&inner, public_key).ok_or(KeyLookupError::NotFound)?;
+        let Some(released) = inner.retired_keys.remove(&key_index) else {
+            return Ok(());
+        };
+        if released {
+            inner.available_keys.insert(key_index);
+        }
+        let last_index = inner.keys.len() - 1;
+        if last_index != key_index && inner.available_keys.remove(&last_index) {
+            inner.keys.pop();
+        }
+        Ok(())
+    }
+
+    fn replenish(&self, inner: &mut Inner) {
+        let index = inner.keys.len();
+        match Self::derive(&self.master_key, &self.base_derivation_path, index) {
+            Ok(key) => {
+                inner.keys.push(key);
+                inner.available_keys.insert(index);
+            }
+            Err(e) => warn!("Failed to derive verifier key to replace retired one: {e:#}"),
+        }
+    }
+
+    fn derive(master_key: &Xpriv, base_derivation_path: &DerivationPath, index: usize) -> anyhow::Result<Keypair> {
+        let engine = Secp256k1::new();
+        let index = u32::try_from(index).context("Too many verifier keys")?;
+        let key_path = base_derivation_path.child(ChildNumber::Hardened { index });
+        let key = master_key.derive_priv(&engine, &key_path).context("Failed to derive private key")?;
+        let private = key.private_key.secret_bytes();
+        let public = key.private_key.public_key(&engine).serialize();
+        let public_uncompressed = key.private_key.public_key(&engine).serialize_uncompressed();
+        Ok(Keypair { private, public, public_uncompressed })
+    }
+
+    fn position(inner: &Inner, public_key: &[u8]) -> Option<usize> {
+        inner.keys.iter().position(|k| k.public == public_key || k.public_uncompressed == public_key)
+    }
+
+    fn public_key(index: usize, inner: &Inner) -> PublicKey {
+        let Keypair { public, public_uncompressed, .. } = inner.keys[index];
+        PublicKey { public, public_uncompressed, retired: inner.retired_keys.contains_key(&index) }
     }
 
     #[cfg(test)]
@@ -85,9 +177,11 @@ impl VerifierKeys {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct PublicKey {
     pub public: [u8; 33],
     pub public_uncompressed: [u8; 65],
+    pub retired: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -101,6 +195,9 @@ pub enum KeyLookupError {
 
     #[error("key already in use")]
     AlreadyInUse,
+
+    #[error("key is retired")]
+    Retired,
 }
 
 pub struct VerifierKey {
@@ -112,7 +209,11 @@ pub struct VerifierKey {
 impl VerifierKey {
     #[cfg(test)]
     pub(crate) fn dummy() -> Self {
-        let inner = Arc::new(Mutex::new(Inner { available_keys: Default::default() }));
+        let inner = Arc::new(Mutex::new(Inner {
+            keys: Default::default(),
+            available_keys: Default::default(),
+            retired_keys: Default::default(),
+        }));
         let key = Keypair { private: [0; 32], public: [0; 33], public_uncompressed: [0; 65] };
         let key_index = 0;
         Self { key, key_index, inner }
@@ -127,10 +228,22 @@ impl VerifierKey {
     }
 }
 
+impl Debug for VerifierKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't print the private key
+        f.debug_struct("VerifierKey").field("public_key", &hex::encode(self.key.public)).finish()
+    }
+}
+
 impl Drop for VerifierKey {
     fn drop(&mut self) {
         let mut inner = self.inner.lock().expect("lock poisoned");
-        inner.available_keys.insert(self.key_index);
+        match inner.retired_keys.get_mut(&self.key_index) {
+            Some(released) => *released = true,
+            None => {
+                inner.available_keys.insert(self.key_index);
+            }
+        }
     }
 }
 
@@ -191,4 +304,66 @@ mod tests {
         let _key = keys.get(&public_key).expect("lookup failed");
         assert!(matches!(keys.get(&public_key), Err(KeyLookupError::AlreadyInUse)));
     }
+
+    #[test]
+    fn find_key() {
+        let keys = VerifierKeys::new(&CONFIG, 2).expect("creating keys");
+        let public_keys = keys.public_keys();
+        let key = &public_keys[1];
+        assert_eq!(keys.find(&key.public).as_ref(), Some(key));
+        assert_eq!(keys.find(&key.public_uncompressed).as_ref(), Some(key));
+        assert_eq!(keys.find(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn retire_available_key() {
+        let keys = VerifierKeys::new(&CONFIG, 2).expect("creating keys");
+        let public_key = keys.public_keys()[0].public;
+        keys.retire_unused(&public_key).expect("retire failed");
+        assert!(keys.find(&public_key).expect("key not found").retired);
+        assert!(matches!(keys.get(&public_key), Err(KeyLookupError::Retired)));
+
+        // the other key and the one derived to replace the retired one are handed out and then there's nothing left
+        let first = keys.next_key().expect("no keys available");
+        let second = keys.next_key().expect("no keys available");
+        assert_ne!(first.public_key(), public_key);
+        assert_ne!(second.public_key(), public_key);
+        assert!(keys.next_key().is_err(), "retired key handed out");
+        assert!(matches!(keys.retire(&[1, 2, 3]), Err(KeyLookupError::NotFound)));
+    }
+
+    #[test]
+    fn retire_key_in_use() {
+        let keys = VerifierKeys::new(&CONFIG, 1).expect("creating keys");
+        let key = keys.next_key().expect("no keys available");
+        assert!(matches!(keys.retire_unused(&key.public_key()), Err(KeyLookupError::AlreadyInUse)));
+        keys.retire(&key.public_key()).expect("retire failed");
+        assert!(matches!(keys.retire_unused(&key.public_key()), Err(KeyLookupError::Retired)));
+
+        // it doesn't go back to the pool once released, a replacement is handed out instead
+        let public_key = key.public_key();
+        drop(key);
+        let replacement = keys.next_key().expect("no keys available");
+        assert_ne!(replacement.public_key(), public_key);
+        assert!(keys.next_key().is_err(), "retired key handed out");
+    }
+
+    #[test]
+    fn reinstate_key() {
+        let keys = VerifierKeys::new(&CONFIG, 1).expect("creating keys");
+        let public_key = keys.public_keys()[0].public;
+        keys.retire(&public_key).expect("retire failed");
+        keys.reinstate(&public_key).expect("reinstate failed");
+        assert_eq!(keys.public_keys().len(), 1);
+        assert!(!keys.find(&public_key).expect("key not found").retired);
+
+        // a key that's still held only goes back to the pool once released
+        let key = keys.next_key().expect("no keys available");
+        assert_eq!(key.public_key(), public_key);
+        keys.retire(&public_key).expect("retire failed");
+        keys.reinstate(&public_key).expect("reinstate failed");
+        assert!(keys.next_key().is_err(), "held key handed out");
+        drop(key);
+        assert_eq!(keys.next_key().expect("no keys available").public_key(), public_key);
+    }
 }
@@ -1,11 +1,16 @@
+pub mod accelerators;
 pub mod auth;
 pub mod clients;
 pub mod compose;
 pub mod config;
 pub mod heartbeat_verifier;
+pub mod listeners;
+pub mod platform_claims;
+pub mod preflight;
 pub mod repositories;
 pub mod resources;
 pub mod routes;
 pub mod services;
 pub mod version;
 pub mod workers;
+pub mod zerossl;
@@ -0,0 +1,161 @@
+use crate::config::PeerCredentialsConfig;
+use axum::serve::Listener;
+use std::{
+    env, io,
+    net::TcpListener,
+    os::{
+        fd::{FromRawFd, IntoRawFd, RawFd},
+        unix::net::UnixListener as StdUnixListener,
+    },
+    process,
+};
+use tokio::net::{UnixListener, UnixStream, unix::SocketAddr};
+use tracing::{info, warn};
+
+/// The first file descriptor passed in by systemd, as defined by `sd_listen_fds(3)`.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// A unix socket listener that only accepts connections from allowed peers.
+///
+/// Peers are identified via their credentials (`SO_PEERCRED`), so this can't be spoofed by the connecting process.
+/// Connections from any other peer are closed right after being accepted.
+pub struct PeerCredentialsListener {
+    inner: UnixListener,
+    allowed_peers: PeerCredentialsConfig,
+}
+
+impl PeerCredentialsListener {
+    pub fn new(inner: UnixListener, allowed_peers: PeerCredentialsConfig) -> Self {
+        Self { inner, allowed_peers }
+    }
+
+    fn is_allowed(&self, stream: &UnixStream) -> bool {
+        let PeerCredentialsConfig { uids, gids } = &self.allowed_peers;
+        if uids.is_empty() && gids.is_empty() {
+            return true;
+        }
+        match stream.peer_cred() {
+            Ok(cred) => {
+                let allowed = uids.contains(&cred.uid()) || gids.contains(&cred.gid());
+                if !allowed {
+                    warn!("Rejecting unix socket connection from uid {}, gid {}", cred.uid(), cred.gid());
+                }
+                allowed
+            }
+            Err(e) => {
+                warn!("Rejecting unix socket connection, could not get peer credentials: {e}");
+                false
+            }
+        }
+    }
+}
+
+impl Listener for PeerCredentialsListener {
+    type Io = UnixStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            let (stream, addr) = Listener::accept(&mut self.inner).await;
+            if self.is_allowed(&stream) {
+                return (stream, addr);
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Listener::local_addr(&self.inner)
+    }
+}
+
+/// A listening socket passed in by systemd socket activation.
+#[derive(Debug)]
+pub enum ActivatedListener {
+    Tcp(TcpListener),
+    Unix(StdUnixListener),
+}
+
+impl ActivatedListener {
+    /// Take the listening sockets systemd passed in to this process, if any.
+    ///
+    /// This must only be called once, as the returned listeners take ownership of the file descriptors.
+    pub fn from_systemd() -> io::Result<Vec<Self>> {
+        let fds = activated_fds(
+            env::var("LISTEN_PID").ok().as_deref(),
+            env::var("LISTEN_FDS").ok().as_deref(),
+            process::id(),
+        );
+        let mut listeners = Vec::new();
+        for fd in fds {
+            // SAFETY: systemd hands these file descriptors over to us and nothing else in the process uses them.
+            let listener = unsafe { Self::from_raw_fd(fd) }?;
+            info!("Found systemd activated socket {listener:?}");
+            listeners.push(listener);
+        }
+        Ok(listeners)
+    }
+
+    unsafe fn from_raw_fd(fd: RawFd) -> io::Result<Self> {
+        // `local_addr` fails on anything that's not an `AF_UNIX` socket, so use that to tell them apart.
+        let listener = unsafe { StdUnixListener::from_raw_fd(fd) };
+        let listener = match listener.local_addr() {
+            Ok(_) => Self::Unix(listener),
+            Err(_) => Self::Tcp(unsafe { TcpListener::from_raw_fd(listener.into_raw_fd()) }),
+        };
+        match &listener {
+            Self::Tcp(listener) => listener.set_nonblocking(true)?,
+            Self::Unix(listener) => listener.set_nonblocking(true)?,
+        };
+        Ok(listener)
+    }
+}
+
+fn activated_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> Vec<RawFd> {
+    // The sockets are only meant for us if systemd set our own pid, otherwise they were inherited from a parent.
+    if listen_pid.and_then(|p| p.parse::<u32>().ok()) != Some(pid) {
+        return Vec::new();
+    }
+    let count = listen_fds.and_then(|c| c.parse::<RawFd>().ok()).unwrap_or(0);
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::{os::unix::fs::MetadataExt, time::Duration};
+    use tokio::{io::AsyncReadExt, time::timeout};
+
+    #[rstest]
+    #[case::no_env(None, None, vec![])]
+    #[case::other_pid(Some("1"), Some("2"), vec![])]
+    #[case::own_pid(Some("42"), Some("2"), vec![3, 4])]
+    #[case::no_fds(Some("42"), None, vec![])]
+    #[case::invalid_fds(Some("42"), Some("potato"), vec![])]
+    fn listen_fds(#[case] listen_pid: Option<&str>, #[case] listen_fds: Option<&str>, #[case] expected: Vec<RawFd>) {
+        assert_eq!(activated_fds(listen_pid, listen_fds, 42), expected);
+    }
+
+    async fn connect(allowed_peers: impl FnOnce(u32) -> PeerCredentialsConfig) -> bool {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let uid = dir.path().metadata().expect("failed to get metadata").uid();
+        let path = dir.path().join("api.sock");
+        let mut listener = PeerCredentialsListener::new(UnixListener::bind(&path).unwrap(), allowed_peers(uid));
+        let mut client = UnixStream::connect(&path).await.expect("failed to connect");
+        match timeout(Duration::from_millis(100), listener.accept()).await {
+            Ok(_) => true,
+            Err(_) => {
+                // Rejected connections are closed right away.
+                assert!(matches!(client.read(&mut [0; 1]).await, Ok(0)));
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_credentials() {
+        assert!(connect(|_| PeerCredentialsConfig::default()).await);
+        assert!(connect(|uid| PeerCredentialsConfig { uids: vec![uid], gids: vec![] }).await);
+        assert!(!connect(|uid| PeerCredentialsConfig { uids: vec![uid + 1], gids: vec![] }).await);
+    }
+}
@@ -1,50 +1,107 @@
-use anyhow::{Context, Result};
-use axum_server::Handle;
+use anyhow::{Context, Result, bail};
+use axum_server::{
+    Handle,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use cvm_agent_models::config::HeartbeatConfigRequest;
+use cvm_agent_models::{bootstrap::PrivatePki, config::HeartbeatConfigRequest};
 use metrics_exporter_prometheus::PrometheusBuilder;
 use nilcc_agent::{
     clients::{
-        cvm_agent::{CvmAgentClient, DefaultCvmAgentClient},
+        attester::DefaultAttesterClient,
+        cvm_agent::{CVM_AGENT_AUTH_KEY_FILE, CvmAgentAuthKey, CvmAgentClient, DefaultCvmAgentClient},
         nilcc_api::{DummyNilccApiClient, HttpNilccApiClient, NilccApiClient, NilccApiClientArgs},
         qemu::{QemuClient, VmClient, VmDisplayMode},
+        webhook::{HttpWebhookClient, HttpWebhookClientArgs, WebhookClient},
+    },
+    config::{
+        AgentConfig, AgentMode, DnsProviderConfig, DnsUpdateConfig, EventsConfig, StorageConfig, TlsConfig,
+        UnixSocketConfig, VerifierHeartbeatConfig, WebhooksConfig,
     },
-    config::{AgentConfig, AgentMode, VerifierHeartbeatConfig},
     heartbeat_verifier::VerifierKeys,
-    repositories::sqlite::{RepositoryProvider, SqliteDb, SqliteRepositoryProvider},
-    resources::SystemResources,
-    routes::{AppState, Clients, Services, build_router},
+    listeners::{ActivatedListener, PeerCredentialsListener},
+    platform_claims::PlatformClaimsSigner,
+    preflight::PreflightReport,
+    repositories::sqlite::{ProviderMode, RepositoryProvider, SqliteDb, SqliteRepositoryProvider},
+    resources::{
+        HostOverhead, HostReservation, MountedDiskFreeSpaceFinder, NetworkInterfacePublicIpFinder, NumaAllocator,
+        OverheadSampler, OverheadTracker, SystemAvailableMemoryFinder, SystemResources,
+    },
+    routes::{AgentCapabilities, AppState, Clients, Services, build_router, limits::CvmAgentLimiter},
     services::{
+        agent_backup::{AgentBackup, BootCheck, UpgradeRecord},
+        backup::{self, DefaultBackupService, DefaultBackupServiceArgs, StateFileMismatch},
+        bandwidth::TcBandwidthLimiter,
         disk::{
             ApplicationMetadata, ContainerMetadata, DefaultDiskService, DiskService, EnvironmentVariable, ExternalFile,
-            IsoSpec,
+            IsoSpec, LvmDiskService,
         },
+        dns::{
+            CloudflareDnsRecordUpdater, CloudflareDnsRecordUpdaterArgs, DefaultWorkloadDnsService, DnsRecordUpdater,
+            DnsUpdates, NsupdateDnsRecordUpdater, NsupdateDnsRecordUpdaterArgs, Route53DnsRecordUpdater,
+            Route53DnsRecordUpdaterArgs,
+        },
+        env_groups::{DefaultEnvGroupService, EnvGroupService},
+        image_policy::{ImagePolicyChecker, TrivyImagePolicyChecker, TrivyImagePolicyCheckerArgs},
         proxy::{HaProxyProxyService, ProxyService, ProxyServiceArgs},
-        upgrade::{DefaultUpgradeService, DefaultUpgradeServiceArgs},
-        vm::{DefaultVmService, VmService, VmServiceArgs},
+        upgrade::{ArtifactsGcPolicy, DefaultUpgradeService, DefaultUpgradeServiceArgs},
+        upload::{DefaultUploadService, DefaultUploadServiceArgs},
+        usage::DefaultUsageService,
+        verifier_keys::{DefaultVerifierKeyService, VerifierKeyServiceArgs},
+        vm::{DEBUG_KERNEL_ARGS, DefaultVmService, VmService, VmServiceArgs},
         workload::{DefaultWorkloadService, WorkloadService, WorkloadServiceArgs},
     },
     version,
     workers::{
-        events::{EventWorker, EventWorkerArgs},
+        agent_upgrade::{AgentUpgradeWatchdog, AgentUpgradeWatchdogArgs},
+        artifacts_gc::{ArtifactsGcWorker, ArtifactsGcWorkerArgs},
+        disk_watchdog::{DiskSpaceStatus, DiskWatchdog, DiskWatchdogArgs},
+        domain_retirement::{DomainRetirementWorker, DomainRetirementWorkerArgs},
+        events::{EventWorker, EventWorkerArgs, WebhookDispatcher, WebhookDispatcherArgs},
         heartbeat::{HeartbeatWorker, HeartbeatWorkerArgs},
+        pressure::{PressureWatchdog, PressureWatchdogArgs},
+        public_ip::{PublicIpWorker, PublicIpWorkerArgs},
+        reservation::{ReservationTuner, ReservationTunerArgs},
+        upgrade_channel::{UpgradeChannelWorker, UpgradeChannelWorkerArgs},
+        usage::{UsageSampler, UsageSamplerArgs},
     },
+    zerossl::ZeroSslAccounts,
+};
+use nilcc_agent_models::{
+    system::{AgentFeatures, GpuInfo},
+    workloads::create::ImagePolicyMode,
+};
+use nilcc_artifacts::{
+    VmType,
+    downloader::ArtifactsDownloader,
+    metadata::KernelCommandLine,
+    packer::{ArtifactsPackSpec, ArtifactsPacker, CvmImageFiles, DEFAULT_KERNEL_COMMAND_LINE},
+    signature::SigningKey,
 };
-use nilcc_artifacts::{VmType, downloader::ArtifactsDownloader};
+use rate_limit::RateLimiter;
 use rustls_acme::{AcmeConfig, AcmeState, caches::DirCache};
+use serde::Serialize;
 use std::{
     fmt, fs, io,
+    net::SocketAddr,
+    os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
     time::Duration,
 };
-use tokio::signal;
+use tokio::{
+    net::UnixListener,
+    signal,
+    sync::{Notify, watch},
+    time::{Instant, sleep},
+};
 use tokio_stream::StreamExt;
 use tracing::{debug, error, info, level_filters::LevelFilter, warn};
 use uuid::Uuid;
 
 const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+const RESERVATION_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Parser)]
 #[clap(author, version = version::agent_version(), about = "nilcc agent")]
@@ -82,7 +139,34 @@ enum Command {
     },
 
     /// Display system resources.
-    Resources,
+    Resources {
+        /// Sample the host's overhead and recommend how many CPUs and how much memory to reserve for it.
+        #[clap(long)]
+        recommend: bool,
+
+        /// How long to sample the host's overhead for when recommending reservations.
+        #[clap(long, default_value = "60", requires = "recommend")]
+        sample_seconds: u64,
+
+        /// The headroom, in percent, added on top of the peak overhead seen when recommending reservations.
+        #[clap(long, default_value = "20", requires = "recommend")]
+        margin_percent: u32,
+
+        /// The qemu binary VMs are run with, used to tell VM processes apart from the host's.
+        #[clap(long, default_value = "qemu-system-x86_64", requires = "recommend")]
+        qemu_bin: PathBuf,
+    },
+
+    /// Check whether this host meets the requirements to run workloads.
+    Preflight {
+        /// Path to the agent configuration file
+        #[clap(long, short)]
+        config: PathBuf,
+
+        /// Print the report as JSON.
+        #[clap(long)]
+        json: bool,
+    },
 
     /// Validate the config file.
     ValidateConfig {
@@ -94,6 +178,10 @@ enum Command {
     #[clap(subcommand)]
     Download(DownloadCommand),
 
+    /// Artifacts commands.
+    #[clap(subcommand)]
+    Artifacts(ArtifactsCommand),
+
     /// Generate a list of the available heartbeat verifier keys.
     VerifierKeys {
         /// Path to the agent configuration file
@@ -151,6 +239,77 @@ struct DownloadArtifactsArgs {
 
     #[clap(long, default_value_t = VmTypeArtifacts::All)]
     vm_type: VmTypeArtifacts,
+
+    /// A key the artifacts metadata must be signed by, as `<name>:<hex ed25519 public key>`.
+    #[clap(long = "signing-key")]
+    signing_keys: Vec<SigningKey>,
+}
+
+#[derive(Subcommand)]
+enum ArtifactsCommand {
+    /// Pack locally built artifacts and install them as a new artifacts version.
+    Pack(PackArtifactsArgs),
+}
+
+#[derive(Args)]
+struct PackArtifactsArgs {
+    /// Path to the agent configuration file
+    #[clap(long, short)]
+    config: PathBuf,
+
+    /// The version to install the artifacts as. Defaults to `dev-<unix timestamp>`.
+    #[clap(long)]
+    version: Option<String>,
+
+    /// The path to the OVMF file.
+    #[clap(long)]
+    ovmf: PathBuf,
+
+    /// The path to the initrd file.
+    #[clap(long)]
+    initrd: PathBuf,
+
+    /// The kernel command line.
+    #[clap(long, default_value = DEFAULT_KERNEL_COMMAND_LINE)]
+    cmdline: String,
+
+    /// The path to the CPU CVM disk.
+    #[clap(long)]
+    cpu_disk: PathBuf,
+
+    /// The path to the CPU CVM verity disk.
+    #[clap(long)]
+    cpu_verity_disk: PathBuf,
+
+    /// The hex encoded CPU CVM verity root hash.
+    #[clap(long, value_parser = parse_hash)]
+    cpu_verity_root_hash: [u8; 32],
+
+    /// The path to the CPU CVM kernel.
+    #[clap(long)]
+    cpu_kernel: PathBuf,
+
+    /// The path to the GPU CVM disk. The CPU image is used for GPU workloads if not set.
+    #[clap(long, requires_all = ["gpu_verity_disk", "gpu_verity_root_hash", "gpu_kernel"])]
+    gpu_disk: Option<PathBuf>,
+
+    /// The path to the GPU CVM verity disk.
+    #[clap(long, requires = "gpu_disk")]
+    gpu_verity_disk: Option<PathBuf>,
+
+    /// The hex encoded GPU CVM verity root hash.
+    #[clap(long, requires = "gpu_disk", value_parser = parse_hash)]
+    gpu_verity_root_hash: Option<[u8; 32]>,
+
+    /// The path to the GPU CVM kernel.
+    #[clap(long, requires = "gpu_disk")]
+    gpu_kernel: Option<PathBuf>,
+}
+
+fn parse_hash(s: &str) -> Result<[u8; 32], String> {
+    let mut hash = [0; 32];
+    hex::decode_to_slice(s, &mut hash).map_err(|e| format!("invalid hash: {e}"))?;
+    Ok(hash)
 }
 
 #[derive(Clone, ValueEnum)]
@@ -180,7 +339,7 @@ impl FromStr for CliExternalFile {
         let (name, path) = s.split_once('=').context("expected environment variable in <name>=<value> syntax")?;
         let name = name.trim().to_string();
         let contents = std::fs::read(path).context("Failed to read external file")?;
-        Ok(Self(ExternalFile { name, contents }))
+        Ok(Self(ExternalFile::new(name, contents)))
     }
 }
 
@@ -204,9 +363,14 @@ async fn run_iso_command(command: IsoCommand) -> Result<()> {
             let compose = std::fs::read_to_string(docker_compose_path).context("reading docker compose")?;
             let spec = IsoSpec {
                 docker_compose_yaml: compose,
-                metadata: ApplicationMetadata { hostname, api: ContainerMetadata { container, port } },
+                metadata: ApplicationMetadata {
+                    hostname,
+                    api: ContainerMetadata { container, port },
+                    sealed_state_disk: false,
+                },
                 environment_variables: environment_variables.into_iter().map(|e| e.0).collect(),
                 files: files.into_iter().map(|f| f.0).collect(),
+                api_token: None,
             };
             let disk_service = DefaultDiskService::new("qemu-img".into());
             disk_service.create_application_iso(&output, spec).await.context("creating ISO")?;
@@ -223,10 +387,34 @@ fn load_config(config_path: &Path) -> Result<AgentConfig> {
 
     let config: AgentConfig = serde_yaml::from_reader(config_file)
         .map_err(|e| anyhow::anyhow!("Failed to parse YAML from config file {config_path:?}: {e}"))?;
+    if let Some(mirror) = config.docker.registry_mirrors.iter().find(|mirror| !mirror.starts_with("https://")) {
+        bail!("registry mirror {mirror} is not an https URL");
+    }
 
     Ok(config)
 }
 
+fn load_private_pki(config: &AgentConfig) -> anyhow::Result<Option<PrivatePki>> {
+    let Some(private_pki) = &config.private_pki else {
+        return Ok(None);
+    };
+    info!("Using private PKI for CVM TLS certificates: {private_pki:?}");
+    private_pki.load().context("Failed to load private PKI").map(Some)
+}
+
+fn load_platform_claims_signer(config: &AgentConfig) -> anyhow::Result<Option<PlatformClaimsSigner>> {
+    let Some(platform_claims) = &config.platform_claims else {
+        return Ok(None);
+    };
+    let signer = PlatformClaimsSigner::new(platform_claims, config.agent_id)?;
+    info!(
+        "Signing platform claims for region {} using key {}",
+        platform_claims.region,
+        hex::encode(signer.public_key())
+    );
+    Ok(Some(signer))
+}
+
 async fn process_acme_events(mut state: AcmeState<io::Error, io::Error>) {
     while let Some(event) = state.next().await {
         match event {
@@ -243,23 +431,37 @@ async fn debug_workload(config: AgentConfig, workload_id: Uuid, print_only: bool
 
     let db = SqliteDb::connect(&config.db.url).await.context("Failed to create database")?;
     let repository_provider = Arc::new(SqliteRepositoryProvider::new(db.clone()));
-    let workload = repository_provider.workloads(Default::default()).await?.find(workload_id).await?;
+    let mut workload = repository_provider.workloads(Default::default()).await?.find(workload_id).await?;
     let state_path = tempfile::tempdir().context("Failed to create tempdir")?;
     info!("Storing state in {}", state_path.path().display());
 
     let vm_client = Arc::new(QemuClient::new(config.qemu.system_bin.clone()));
-    let cvm_agent_client = Arc::new(DefaultCvmAgentClient::new().context("Failed to create cvm-agent client")?);
+    let cvm_agent_auth_key = CvmAgentAuthKey::load_or_generate(&config.vm_store.join(CVM_AGENT_AUTH_KEY_FILE))?;
+    let cvm_agent_client =
+        Arc::new(DefaultCvmAgentClient::new(cvm_agent_auth_key.clone()).context("Failed to create cvm-agent client")?);
+    // Don't notify webhooks about workloads being debugged.
+    let webhooks = build_webhook_dispatcher(config.agent_id, &WebhooksConfig::default())?;
     let event_sender = EventWorker::spawn(EventWorkerArgs {
-        api_client: nilcc_api_client,
+        api_client: nilcc_api_client.clone(),
         repository_provider: repository_provider.clone(),
+        webhooks,
+        config: EventsConfig::default(),
     });
+    // There's no nilcc API in debug mode so this will use the cached env groups.
+    let env_group_service = DefaultEnvGroupService::new(nilcc_api_client, repository_provider.clone());
+    let env_vars = env_group_service.resolve(&workload.env_groups).await.context("Failed to resolve env groups")?;
+    workload.env_vars = env_vars.into_iter().chain(workload.env_vars).collect();
+    let private_pki = load_private_pki(&config)?;
+    let platform_claims = load_platform_claims_signer(&config)?;
     let vm_service = DefaultVmService::new(VmServiceArgs {
+        agent_id: config.agent_id,
         vm_client: vm_client.clone(),
         cvm_agent_client: cvm_agent_client.clone(),
+        cvm_agent_auth_key,
         state_path: state_path.path().into(),
-        disk_service: Box::new(DefaultDiskService::new(config.qemu.img_bin)),
+        disk_service: build_disk_service(config.qemu.img_bin, &config.storage),
         cvm_artifacts_path: config.cvm.artifacts_path,
-        zerossl_config: config.zerossl,
+        zerossl_accounts: ZeroSslAccounts::new(config.zerossl),
         docker_config: config.docker,
         event_sender,
         repository_provider: repository_provider.clone(),
@@ -267,10 +469,19 @@ async fn debug_workload(config: AgentConfig, workload_id: Uuid, print_only: bool
         verifier_heartbeat_rpc: config.verifier_heartbeat.rpc_endpoint,
         verifier_contract_address: config.verifier_heartbeat.heartbeat_contract_address,
         token_contract_address: config.verifier_heartbeat.token_contract_address,
+        ipv6: config.network.ipv6,
+        time_sync: config.time_sync,
+        private_pki,
+        platform_claims,
+        snp: config.qemu.snp.clone(),
+        numa: None,
+        bandwidth_limiter: Arc::new(TcBandwidthLimiter::default()),
     })
     .await?;
     let mut spec = vm_service.create_workload_spec(&workload).await.context("Failed to create workload spec")?;
-    spec.kernel_args.as_mut().expect("no kernel args").push_str(" console=ttyS0 debug_mode=1");
+    if !workload.debug {
+        spec.kernel_args.as_mut().expect("no kernel args").push_str(&format!(" {DEBUG_KERNEL_ARGS}"));
+    }
     spec.display = VmDisplayMode::Console;
     spec.port_forwarding.clear();
 
@@ -295,23 +506,163 @@ async fn debug_workload(config: AgentConfig, workload_id: Uuid, print_only: bool
 }
 
 async fn download_artifacts(args: DownloadArtifactsArgs) -> Result<()> {
-    let DownloadArtifactsArgs { download_path, version, vm_type } = args;
+    let DownloadArtifactsArgs { download_path, version, vm_type, signing_keys } = args;
     let vm_types = match vm_type {
         VmTypeArtifacts::Cpu => vec![VmType::Cpu],
         VmTypeArtifacts::Gpu => vec![VmType::Gpu],
         VmTypeArtifacts::All => vec![VmType::Cpu, VmType::Gpu],
     };
-    let downloader = ArtifactsDownloader::new(version.clone(), vm_types);
+    let downloader = ArtifactsDownloader::new(version.clone(), vm_types).with_signing_keys(signing_keys);
     downloader.download(&download_path).await.context("Failed to download artifacts")?;
     Ok(())
 }
 
+async fn pack_artifacts(args: PackArtifactsArgs) -> Result<()> {
+    let PackArtifactsArgs {
+        config,
+        version,
+        ovmf,
+        initrd,
+        cmdline,
+        cpu_disk,
+        cpu_verity_disk,
+        cpu_verity_root_hash,
+        cpu_kernel,
+        gpu_disk,
+        gpu_verity_disk,
+        gpu_verity_root_hash,
+        gpu_kernel,
+    } = args;
+    let config = load_config(&config).context("Loading agent configuration")?;
+    let version = version.unwrap_or_else(|| format!("dev-{}", chrono::Utc::now().timestamp()));
+    let cpu = CvmImageFiles {
+        disk: cpu_disk,
+        verity_disk: cpu_verity_disk,
+        verity_root_hash: cpu_verity_root_hash,
+        kernel: cpu_kernel,
+    };
+    let gpu = match (gpu_disk, gpu_verity_disk, gpu_verity_root_hash, gpu_kernel) {
+        (Some(disk), Some(verity_disk), Some(verity_root_hash), Some(kernel)) => {
+            CvmImageFiles { disk, verity_disk, verity_root_hash, kernel }
+        }
+        _ => {
+            warn!("No GPU image provided, using CPU image for GPU workloads");
+            cpu.clone()
+        }
+    };
+
+    let db = SqliteDb::connect(&config.db.url).await.context("Failed to connect to database")?;
+    let repository_provider = SqliteRepositoryProvider::new(db);
+    let mut repo = repository_provider.artifacts(ProviderMode::Transactional).await?;
+    if repo.exists(&version).await? {
+        bail!("artifacts version {version} already exists");
+    }
+    let target_path = config.cvm.artifacts_path.join(&version);
+    if target_path.exists() {
+        bail!("artifacts directory {} already exists", target_path.display());
+    }
+
+    let spec = ArtifactsPackSpec { ovmf, initrd, cmdline: KernelCommandLine(cmdline), cpu, gpu };
+    let artifacts = match ArtifactsPacker::new(spec).pack(&target_path).await {
+        Ok(artifacts) => artifacts,
+        Err(e) => {
+            if let Err(e) = fs::remove_dir_all(&target_path) {
+                warn!("Failed to remove {}: {e}", target_path.display());
+            }
+            return Err(e).context("Failed to pack artifacts");
+        }
+    };
+    repo.create(&version, &artifacts.metadata).await.context("Failed to store version")?;
+    repo.commit().await?;
+    println!("Installed artifacts version {version}");
+    println!("Metadata hash: {}", hex::encode(artifacts.metadata_hash));
+    Ok(())
+}
+
+async fn run_preflight(config: &AgentConfig, json: bool) -> Result<()> {
+    let report = PreflightReport::run(config).await;
+    if json {
+        let report = serde_json::to_string_pretty(&report).expect("failed to serialize");
+        println!("{report}");
+    } else {
+        for check in &report.checks {
+            println!("[{}] {}: {}", check.status, check.name, check.details);
+            if let Some(remediation) = &check.remediation {
+                println!("       hint: {remediation}");
+            }
+        }
+    }
+    if !report.passed() {
+        bail!("host does not meet the requirements to run workloads");
+    }
+    Ok(())
+}
+
 fn validate_config(config_path: &Path) -> Result<()> {
     let config = fs::read(config_path).context("Failed to read config")?;
     serde_yaml::from_slice::<AgentConfig>(&config).context("Failed to deserialize config file")?;
     Ok(())
 }
 
+fn build_dns_updates(config: DnsUpdateConfig) -> DnsUpdates {
+    let DnsUpdateConfig { zone, ttl, provider } = config;
+    let updater: Arc<dyn DnsRecordUpdater> = match provider {
+        DnsProviderConfig::Cloudflare { cloudflare } => {
+            Arc::new(CloudflareDnsRecordUpdater::new(CloudflareDnsRecordUpdaterArgs {
+                api_token: cloudflare.api_token,
+                zone_id: cloudflare.zone_id,
+                ttl,
+            }))
+        }
+        DnsProviderConfig::Route53 { route53 } => Arc::new(Route53DnsRecordUpdater::new(Route53DnsRecordUpdaterArgs {
+            hosted_zone_id: route53.hosted_zone_id,
+            access_key_id: route53.access_key_id,
+            secret_access_key: route53.secret_access_key,
+            ttl,
+        })),
+        DnsProviderConfig::Nsupdate { server, key_file, nsupdate_bin } => {
+            Arc::new(NsupdateDnsRecordUpdater::new(NsupdateDnsRecordUpdaterArgs {
+                nsupdate_bin,
+                server,
+                key_file,
+                ttl,
+            }))
+        }
+    };
+    DnsUpdates { updater, zone }
+}
+
+fn build_disk_service(qemu_img_bin: PathBuf, config: &StorageConfig) -> Arc<dyn DiskService> {
+    let files = DefaultDiskService::new(qemu_img_bin);
+    match config {
+        StorageConfig::Files => Arc::new(files),
+        StorageConfig::LvmThin { volume_group, thin_pool } => {
+            info!("Creating workload disks in LVM thin pool {volume_group}/{thin_pool}");
+            Arc::new(LvmDiskService::new(files, volume_group.clone(), thin_pool.clone()))
+        }
+    }
+}
+
+fn build_webhook_dispatcher(agent_id: Uuid, config: &WebhooksConfig) -> Result<Arc<WebhookDispatcher>> {
+    let mut clients: Vec<Arc<dyn WebhookClient>> = Vec::new();
+    for sink in &config.sinks {
+        info!("Sending workload events to webhook {}", sink.url);
+        let client = HttpWebhookClient::new(HttpWebhookClientArgs {
+            url: sink.url.clone(),
+            secret: sink.secret.clone(),
+            timeout: config.timeout_seconds,
+        })?;
+        clients.push(Arc::new(client));
+    }
+    Ok(Arc::new(WebhookDispatcher::new(WebhookDispatcherArgs {
+        agent_id,
+        clients,
+        max_attempts: config.max_attempts,
+        retry_interval: Duration::from_secs(1),
+        dead_letter_path: config.dead_letter_path.clone(),
+    })))
+}
+
 async fn sync_heartbeat_config(
     provider: &Arc<SqliteRepositoryProvider>,
     config: &VerifierHeartbeatConfig,
@@ -341,8 +692,60 @@ async fn sync_heartbeat_config(
     Ok(())
 }
 
+async fn check_agent_upgrade(config_path: &Path, config: &AgentConfig) -> Result<Option<(AgentBackup, UpgradeRecord)>> {
+    let agent_path = std::env::current_exe().context("Failed to get agent binary path")?;
+    let backup = AgentBackup::new(agent_path, config_path.into());
+    let max_boot_attempts = config.agent_upgrade.max_boot_attempts;
+    match backup.check_boot(version::agent_version(), max_boot_attempts).await? {
+        BootCheck::Normal => Ok(None),
+        BootCheck::PendingConfirmation(record) => {
+            info!(
+                "Agent was upgraded to version {}, boot attempt {}/{max_boot_attempts}",
+                record.version, record.boot_attempts
+            );
+            Ok(Some((backup, record)))
+        }
+        BootCheck::RollBack(record) => {
+            error!(
+                "Agent was started {} times since upgrading to version {} without sending a heartbeat, rolling back",
+                max_boot_attempts, record.version
+            );
+            backup.roll_back().await.context("Failed to roll back agent")?;
+            bail!("agent was rolled back to version {}", record.previous_version);
+        }
+    }
+}
+
+async fn restore_database(config: &AgentConfig) -> Result<()> {
+    let Some(archive) = &config.db.restore_from else {
+        return Ok(());
+    };
+    let database_path = backup::database_path(&config.db.url).context("Invalid database URL")?;
+    if database_path.exists() {
+        info!("Not restoring backup from {} because the database already exists", archive.display());
+        return Ok(());
+    }
+    info!("Restoring database from backup at {}", archive.display());
+    let mismatches = backup::restore_backup(archive, &database_path, &config.vm_store, config.agent_id)
+        .await
+        .context("Failed to restore database backup")?;
+    for mismatch in mismatches {
+        match mismatch {
+            StateFileMismatch::Missing(file) => {
+                warn!("State file {} for workload {} is missing", file.name, file.workload_id)
+            }
+            StateFileMismatch::HashMismatch { file, sha256 } => warn!(
+                "State file {} for workload {} has hash {sha256}, expected {}",
+                file.name, file.workload_id, file.sha256
+            ),
+        }
+    }
+    Ok(())
+}
+
 async fn run_daemon(config_path: PathBuf) -> Result<()> {
     let config = load_config(&config_path).context("Loading agent configuration")?;
+    let pending_upgrade = check_agent_upgrade(&config_path, &config).await?;
     info!("Setting up dependencies");
     let nilcc_api_client: Arc<dyn NilccApiClient> = match config.controller {
         AgentMode::Standalone => Arc::new(DummyNilccApiClient),
@@ -358,16 +761,54 @@ async fn run_daemon(config_path: PathBuf) -> Result<()> {
         .install()
         .context("Failed to start metrics exporter")?;
 
-    let system_resources =
+    let disk_service = build_disk_service(config.qemu.img_bin.clone(), &config.storage);
+    let mut system_resources =
         SystemResources::gather(config.resources.reserved).await.context("Failed to find resources")?;
+    if let Some(pool) = disk_service.pool_usage().await.context("Failed to find storage pool usage")? {
+        system_resources.use_storage_pool(pool.size_bytes).context("Invalid storage pool")?;
+    }
     system_resources.create_gpu_vfio_devices().await.context("Failed to create PCI VFIO GPU devices")?;
+    let initial_reservation =
+        HostReservation { cpus: system_resources.reserved_cpus, memory_mb: system_resources.reserved_memory_mb };
 
     let vm_types = if system_resources.gpus.is_some() { vec![VmType::Cpu, VmType::Gpu] } else { vec![VmType::Cpu] };
+    let gpus = system_resources.gpus.as_ref().map(|gpus| GpuInfo {
+        vendor: gpus.vendor.to_string(),
+        model: gpus.model.clone(),
+        count: gpus.addresses.len(),
+        confidential: gpus.confidential_computing(),
+    });
+    let capabilities = AgentCapabilities {
+        vm_types: vm_types.iter().map(ToString::to_string).collect(),
+        snp: SystemResources::sev_snp_enabled(),
+        gpus,
+        features: AgentFeatures {
+            heartbeats: true,
+            tls: config.tls.is_some(),
+            image_policy: config.image_policy.as_ref().map_or(ImagePolicyMode::Disabled, |policy| policy.mode),
+            time_sync: config.time_sync.is_some(),
+            private_pki: config.private_pki.is_some(),
+            ipv6: config.network.ipv6,
+            numa_pinning: config.resources.numa_pinning,
+            reservation_auto_tune: config.resources.auto_tune.is_some(),
+            debug_console: config.api.debug_console,
+        },
+    };
 
+    restore_database(&config).await?;
     let db = SqliteDb::connect(&config.db.url).await.context("Failed to create database")?;
     let repository_provider = SqliteRepositoryProvider::new(db.clone());
     system_resources.adjust_gpu_assignment(&repository_provider).await.context("Failed to adjust GPU configs")?;
 
+    let repository_provider = Arc::new(repository_provider);
+    let webhooks = build_webhook_dispatcher(config.agent_id, &config.webhooks)?;
+    let event_sender = EventWorker::spawn(EventWorkerArgs {
+        api_client: nilcc_api_client.clone(),
+        repository_provider: repository_provider.clone(),
+        webhooks,
+        config: config.events.clone(),
+    });
+
     let proxied_vms = {
         let mut workload_repository = repository_provider.workloads(Default::default()).await?;
         let existing_workloads = workload_repository.list().await.context("Failed to find existing workloads")?;
@@ -382,55 +823,101 @@ async fn run_daemon(config_path: PathBuf) -> Result<()> {
         max_connections: config.sni_proxy.max_connections,
         proxied_vms,
         reload_config: config.sni_proxy.reload_config,
+        ipv6: config.network.ipv6,
+        event_sender: event_sender.clone(),
     });
     info!("Storing current proxy config into {}", config.sni_proxy.config_file_path.display());
     proxy_service.persist_current_config().await.context("Failed to store current proxy config")?;
 
-    info!("Finding public IPv4 address");
-    let public_ip = SystemResources::find_public_ip().context("Failed to find public IPv4 address")?;
+    info!("Finding public IP addresses");
+    let public_ips =
+        SystemResources::find_public_ips(config.network.ipv6).context("Failed to find public IP addresses")?;
+    info!("Found public IP addresses: {public_ips}");
 
     info!("Registering with API");
-    nilcc_api_client.register(&config.api, &system_resources, public_ip).await.context("Failed to register")?;
+    nilcc_api_client.register(&config.api, &system_resources, public_ips).await.context("Failed to register")?;
 
-    let vm_client = Arc::new(QemuClient::new(config.qemu.system_bin));
+    let vm_client = Arc::new(QemuClient::new(config.qemu.system_bin.clone()));
 
     // We can't run more than one workload per CPU so use that as the upper bound
     let max_workloads = system_resources.cpus as usize;
     let verifier_keys = VerifierKeys::new(&config.verifier_heartbeat, max_workloads)?;
 
-    let repository_provider = Arc::new(repository_provider);
-    let cvm_agent_client = Arc::new(DefaultCvmAgentClient::new().context("Failed to create cvm-agent client")?);
+    let cvm_agent_auth_key = CvmAgentAuthKey::load_or_generate(&config.vm_store.join(CVM_AGENT_AUTH_KEY_FILE))?;
+    let cvm_agent_client =
+        Arc::new(DefaultCvmAgentClient::new(cvm_agent_auth_key.clone()).context("Failed to create cvm-agent client")?);
     sync_heartbeat_config(&repository_provider, &config.verifier_heartbeat, &cvm_agent_client)
         .await
         .context("Failed to sync heartbeat config")?;
-    let event_sender = EventWorker::spawn(EventWorkerArgs {
-        api_client: nilcc_api_client.clone(),
-        repository_provider: repository_provider.clone(),
-    });
+    let zerossl_accounts = ZeroSslAccounts::new(config.zerossl);
+    let disk_space = DiskSpaceStatus::default();
+    let private_pki = load_private_pki(&config)?;
+    let platform_claims = load_platform_claims_signer(&config)?;
+    let numa = match (config.resources.numa_pinning, &system_resources.numa) {
+        (true, Some(topology)) => {
+            info!("Pinning VMs to {} NUMA nodes", topology.nodes.len());
+            Some(NumaAllocator::new(topology.clone(), system_resources.reserved_cpus))
+        }
+        (true, None) => {
+            warn!("NUMA pinning is enabled but the host's NUMA topology could not be detected, not pinning VMs");
+            None
+        }
+        (false, _) => None,
+    };
     let vm_service = DefaultVmService::new(VmServiceArgs {
+        agent_id: config.agent_id,
         vm_client,
         cvm_agent_client: cvm_agent_client.clone(),
-        state_path: config.vm_store,
-        disk_service: Box::new(DefaultDiskService::new(config.qemu.img_bin)),
+        cvm_agent_auth_key,
+        state_path: config.vm_store.clone(),
+        disk_service: disk_service.clone(),
         cvm_artifacts_path: config.cvm.artifacts_path.clone(),
-        zerossl_config: config.zerossl,
+        zerossl_accounts: zerossl_accounts.clone(),
         docker_config: config.docker,
-        event_sender,
+        event_sender: event_sender.clone(),
         repository_provider: repository_provider.clone(),
         verifier_heartbeat_interval: config.verifier_heartbeat.interval_seconds,
         verifier_heartbeat_rpc: config.verifier_heartbeat.rpc_endpoint,
         verifier_contract_address: config.verifier_heartbeat.heartbeat_contract_address,
         token_contract_address: config.verifier_heartbeat.token_contract_address,
+        ipv6: config.network.ipv6,
+        time_sync: config.time_sync,
+        private_pki,
+        platform_claims,
+        snp: config.qemu.snp.clone(),
+        numa,
+        bandwidth_limiter: Arc::new(TcBandwidthLimiter::default()),
     })
     .await?;
+    let vm_service = Arc::new(vm_service);
+    // This takes retired keys out of the pool so it needs to happen before any workloads are started.
+    let verifier_key_service = DefaultVerifierKeyService::new(VerifierKeyServiceArgs {
+        verifier_keys: verifier_keys.clone(),
+        repository_provider: repository_provider.clone(),
+        vm_service: vm_service.clone(),
+        cvm_agent_client: cvm_agent_client.clone(),
+    })
+    .await
+    .context("Creating verifier key service")?;
+    let dns = config.public_ip.dns_update.clone().map(build_dns_updates);
     let workload_service = DefaultWorkloadService::new(WorkloadServiceArgs {
-        vm_service: Box::new(vm_service),
+        vm_service,
         repository_provider: repository_provider.clone(),
         resources: system_resources.clone(),
         open_ports: config.sni_proxy.start_port_range..config.sni_proxy.end_port_range,
-        proxy_service: Box::new(proxy_service),
-        verifier_keys: verifier_keys.clone(),
+        proxy_service: Arc::new(proxy_service),
+        env_group_service: Arc::new(DefaultEnvGroupService::new(nilcc_api_client.clone(), repository_provider.clone())),
+        verifier_keys,
         verifier_heartbeat_interval: config.verifier_heartbeat.interval_seconds,
+        event_sender: event_sender.clone(),
+        domain_grace_period: config.sni_proxy.domain_grace_period_seconds,
+        default_state_disk: config.state_disk.mode,
+        zerossl_accounts: zerossl_accounts.clone(),
+        disk_space: disk_space.clone(),
+        dns_service: Arc::new(DefaultWorkloadDnsService::new(
+            dns.clone(),
+            Box::new(NetworkInterfacePublicIpFinder { ipv6: config.network.ipv6 }),
+        )),
     })
     .await
     .context("Creating workload service")?;
@@ -438,56 +925,288 @@ async fn run_daemon(config_path: PathBuf) -> Result<()> {
     workload_service.bootstrap().await?;
 
     let workload_service = Arc::new(workload_service);
+    let artifacts_installed = Arc::new(Notify::new());
     let upgrade_service = Arc::new(DefaultUpgradeService::new(DefaultUpgradeServiceArgs {
         repository_provider: repository_provider.clone(),
         config_file_path: config_path,
         cvm_artifacts_path: config.cvm.artifacts_path.clone(),
         vm_types,
+        signing_keys: config.cvm.signing_keys.clone(),
+        agent_signing_keys: config.agent_upgrade.signing_keys.clone(),
+        artifacts_installed: artifacts_installed.clone(),
+        gc_policy: ArtifactsGcPolicy {
+            keep_latest: config.artifacts_gc.keep_latest,
+            max_unused: chrono::TimeDelta::days(config.artifacts_gc.max_unused_days.into()),
+        },
     }));
+    let (image_policy_checker, image_policy_mode) = match config.image_policy {
+        Some(image_policy) => {
+            info!("Checking images using trivy server at {}", image_policy.trivy_server_url);
+            let checker = TrivyImagePolicyChecker::new(TrivyImagePolicyCheckerArgs {
+                server_url: image_policy.trivy_server_url,
+                timeout: image_policy.scan_timeout_seconds,
+            })
+            .context("Creating image policy checker")?;
+            let checker: Arc<dyn ImagePolicyChecker> = Arc::new(checker);
+            (Some(checker), image_policy.mode)
+        }
+        None => (None, ImagePolicyMode::Disabled),
+    };
+    let upload_service = DefaultUploadService::new(DefaultUploadServiceArgs {
+        directory: config.vm_store.join("uploads"),
+        vm_store: config.vm_store.clone(),
+        max_file_size: config.uploads.max_file_size_mb * 1024 * 1024,
+        expiry: config.uploads.expiry_seconds,
+    })
+    .await
+    .context("Creating upload service")?;
     let state = AppState {
-        services: Services { workload: workload_service.clone(), upgrade: upgrade_service.clone() },
-        clients: Clients { cvm_agent: cvm_agent_client },
+        services: Services {
+            workload: workload_service.clone(),
+            upgrade: upgrade_service.clone(),
+            backup: Arc::new(DefaultBackupService::new(DefaultBackupServiceArgs {
+                db: db.clone(),
+                vm_store: config.vm_store.clone(),
+                agent_id: config.agent_id,
+            })),
+            usage: Arc::new(DefaultUsageService::new(repository_provider.clone())),
+            verifier_key: Arc::new(verifier_key_service),
+            image_policy: image_policy_checker,
+            upload: Arc::new(upload_service),
+        },
+        clients: Clients { cvm_agent: cvm_agent_client, attester: Arc::new(DefaultAttesterClient::new()) },
         resource_limits: config.resources.limits,
         agent_domain: config.api.domain.clone(),
-        verifier_keys,
+        image_policy_mode,
+        registry_mirrors: config.docker.registry_mirrors.clone(),
+        zerossl_accounts,
+        attestation_rate_limiter: Arc::new(RateLimiter::new(
+            config.api.public_attestation.max_requests,
+            config.api.public_attestation.window_seconds,
+        )),
+        cvm_agent_limiter: Arc::new(CvmAgentLimiter::new(
+            config.api.cvm_agent_limits.max_requests,
+            config.api.cvm_agent_limits.max_workload_requests,
+            config.api.cvm_agent_limits.queue_timeout_seconds,
+        )),
+        capabilities: Arc::new(capabilities),
     };
-    let router = build_router(state, config.api.token);
+    let router = build_router(state.clone(), Some(config.api.scoped_tokens()));
     let handle = Handle::new();
-    tokio::spawn(shutdown_handler(handle.clone()));
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    tokio::spawn(shutdown_handler(handle.clone(), shutdown_sender));
+
+    info!("Starting public IP worker");
+    PublicIpWorker::spawn(PublicIpWorkerArgs {
+        api_client: nilcc_api_client.clone(),
+        api_config: config.api.clone(),
+        resources: system_resources,
+        provider: repository_provider.clone(),
+        event_sender: event_sender.clone(),
+        ip_finder: Box::new(NetworkInterfacePublicIpFinder { ipv6: config.network.ipv6 }),
+        dns,
+        public_ips,
+        check_interval: config.public_ip.check_interval_seconds,
+    });
+
+    info!("Starting usage sampler, sampling every {:?}", config.usage.sample_interval_seconds);
+    UsageSampler::spawn(UsageSamplerArgs {
+        provider: repository_provider.clone(),
+        sample_interval: config.usage.sample_interval_seconds,
+    });
+
+    info!("Starting upgrade channel worker");
+    UpgradeChannelWorker::spawn(UpgradeChannelWorkerArgs {
+        provider: repository_provider.clone(),
+        workload_service: workload_service.clone(),
+        artifacts_installed,
+    });
+
+    info!("Starting disk watchdog, checking every {:?}", config.disk_watchdog.check_interval_seconds);
+    DiskWatchdog::spawn(DiskWatchdogArgs {
+        provider: repository_provider.clone(),
+        upgrade_service: upgrade_service.clone(),
+        event_sender,
+        space_finder: Box::new(MountedDiskFreeSpaceFinder),
+        disk_service,
+        status: disk_space.clone(),
+        vm_store: config.vm_store,
+        artifacts_path: config.cvm.artifacts_path,
+        min_free_space_gb: config.disk_watchdog.min_free_space_gb,
+        check_interval: config.disk_watchdog.check_interval_seconds,
+    });
+
+    info!("Starting domain retirement worker");
+    DomainRetirementWorker::spawn(DomainRetirementWorkerArgs { workload_service: workload_service.clone() });
+
+    info!("Starting pressure watchdog, checking every {:?}", config.pressure.check_interval_seconds);
+    PressureWatchdog::spawn(PressureWatchdogArgs {
+        workload_service: workload_service.clone(),
+        memory_finder: Box::new(SystemAvailableMemoryFinder),
+        disk_space,
+        min_available_memory_mb: config.pressure.min_available_memory_mb,
+        check_interval: config.pressure.check_interval_seconds,
+    });
+
+    if config.artifacts_gc.enabled {
+        info!("Starting artifacts garbage collector, running every {:?}", config.artifacts_gc.interval_seconds);
+        ArtifactsGcWorker::spawn(ArtifactsGcWorkerArgs {
+            upgrade_service: upgrade_service.clone(),
+            interval: config.artifacts_gc.interval_seconds,
+        });
+    }
+
+    if let Some(auto_tune) = config.resources.auto_tune {
+        info!("Starting host reservation tuner, sampling every {:?}", auto_tune.sample_interval_seconds);
+        ReservationTuner::spawn(ReservationTunerArgs {
+            workload_service: workload_service.clone(),
+            sampler: OverheadSampler::new(&config.qemu.system_bin),
+            sample_interval: auto_tune.sample_interval_seconds,
+            window_samples: auto_tune.window_samples,
+            margin_percent: auto_tune.margin_percent,
+            min_reservation: initial_reservation,
+            max_reservation: HostReservation { cpus: auto_tune.max_cpus, memory_mb: auto_tune.max_memory_mb },
+        });
+    }
 
     info!("Starting heartbeat worker");
 
+    let heartbeat_sent = Arc::new(Notify::new());
     HeartbeatWorker::spawn(HeartbeatWorkerArgs {
         api_client: nilcc_api_client,
         provider: repository_provider.clone(),
         upgrader: upgrade_service,
+        heartbeat_sent: heartbeat_sent.clone(),
     });
 
+    if let Some((backup, record)) = pending_upgrade {
+        info!("Starting agent upgrade watchdog");
+        AgentUpgradeWatchdog::spawn(AgentUpgradeWatchdogArgs {
+            backup,
+            record,
+            heartbeat_sent,
+            confirmation_timeout: config.agent_upgrade.confirmation_timeout_seconds,
+        });
+    }
+
+    for endpoint in config.api.additional_bind_endpoints {
+        info!("Listening to requests on {endpoint}");
+        let listener = std::net::TcpListener::bind(endpoint).context(format!("Failed to bind to {endpoint}"))?;
+        listener.set_nonblocking(true).context("Failed to set listener as non blocking")?;
+        let server = axum_server::from_tcp(listener).handle(handle.clone());
+        let router = router.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server.serve(router.into_make_service_with_connect_info::<SocketAddr>()).await {
+                error!("Failed to serve on {endpoint}: {e}");
+            }
+        });
+    }
+    if let Some(unix_socket) = config.api.unix_socket {
+        info!("Listening to requests on unix socket {}", unix_socket.path.display());
+        let listener = bind_unix_socket(&unix_socket)?;
+        let listener = PeerCredentialsListener::new(listener, unix_socket.allowed_peers);
+        serve_unix_socket(listener, &state, shutdown_receiver.clone());
+    }
+    if let Some(systemd_activation) = config.api.systemd_activation {
+        let listeners = ActivatedListener::from_systemd().context("Failed to take systemd activated sockets")?;
+        if listeners.is_empty() {
+            warn!("Systemd socket activation is enabled but no sockets were passed in");
+        }
+        for listener in listeners {
+            match listener {
+                ActivatedListener::Tcp(listener) => {
+                    info!("Listening to requests on systemd activated socket {:?}", listener.local_addr());
+                    let server = axum_server::from_tcp(listener).handle(handle.clone());
+                    let router = router.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = server.serve(router.into_make_service_with_connect_info::<SocketAddr>()).await {
+                            error!("Failed to serve on systemd activated socket: {e}");
+                        }
+                    });
+                }
+                ActivatedListener::Unix(listener) => {
+                    info!("Listening to requests on systemd activated unix socket {:?}", listener.local_addr());
+                    let listener =
+                        UnixListener::from_std(listener).context("Failed to use systemd activated socket")?;
+                    let listener = PeerCredentialsListener::new(listener, systemd_activation.allowed_peers.clone());
+                    serve_unix_socket(listener, &state, shutdown_receiver.clone());
+                }
+            }
+        }
+    }
+
     info!("Listening to requests on {}", config.api.bind_endpoint);
     let server = axum_server::bind(config.api.bind_endpoint).handle(handle);
     let result = match config.tls {
-        Some(tls) => {
+        Some(TlsConfig::Certificate(tls)) => {
+            info!("Using TLS certificate at {}", tls.cert_chain_path.display());
+            let rustls_config = RustlsConfig::from_pem_file(&tls.cert_chain_path, &tls.private_key_path)
+                .await
+                .context("Failed to load TLS certificate")?;
+            server
+                .acceptor(RustlsAcceptor::new(rustls_config))
+                .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+        }
+        Some(TlsConfig::Acme(tls)) => {
             info!(
                 "Setting up TLS certificate generation, using domain = {}, cert cache = {}, ACME contact = {}",
                 config.api.domain,
                 tls.cert_cache.display(),
                 tls.acme_contact
             );
-            let state = AcmeConfig::new([config.api.domain])
+            let acme_config = AcmeConfig::new([config.api.domain])
                 .contact([format!("mailto:{}", tls.acme_contact)])
-                .cache(DirCache::new(tls.cert_cache.clone()))
-                .directory_lets_encrypt(true)
-                .state();
+                .cache(DirCache::new(tls.cert_cache.clone()));
+            let acme_config = match tls.acme_directory {
+                Some(directory) => acme_config.directory(directory),
+                None => acme_config.directory_lets_encrypt(true),
+            };
+            let state = acme_config.state();
             let acceptor = state.axum_acceptor(state.default_rustls_config());
             // Spin up a task that polls the ACME cert generation future
             tokio::spawn(process_acme_events(state));
-            server.acceptor(acceptor).serve(router.into_make_service()).await
+            server.acceptor(acceptor).serve(router.into_make_service_with_connect_info::<SocketAddr>()).await
         }
-        None => server.serve(router.into_make_service()).await,
+        None => server.serve(router.into_make_service_with_connect_info::<SocketAddr>()).await,
     };
     result.context("Failed to serve")
 }
 
+fn serve_unix_socket(
+    listener: PeerCredentialsListener,
+    state: &AppState,
+    mut shutdown_receiver: watch::Receiver<bool>,
+) {
+    // Access to unix sockets is controlled via their file permissions and peer credentials so requests aren't
+    // authenticated.
+    let router = build_router(state.clone(), None);
+    tokio::spawn(async move {
+        let shutdown = async move {
+            let _ = shutdown_receiver.wait_for(|shutdown| *shutdown).await;
+        };
+        if let Err(e) = axum::serve(listener, router).with_graceful_shutdown(shutdown).await {
+            error!("Failed to serve on unix socket: {e}");
+        }
+    });
+}
+
+fn bind_unix_socket(config: &UnixSocketConfig) -> Result<UnixListener> {
+    let UnixSocketConfig { path, mode, group_id, .. } = config;
+    // Remove any socket left behind by a previous run, otherwise binding fails.
+    match fs::remove_file(path) {
+        Ok(()) => info!("Removed stale unix socket at {}", path.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e).context("Failed to remove stale unix socket"),
+    };
+    let listener = UnixListener::bind(path).context("Failed to bind unix socket")?;
+    if let Some(group_id) = group_id {
+        std::os::unix::fs::chown(path, None, Some(*group_id)).context("Failed to set unix socket group")?;
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(*mode)).context("Failed to set unix socket permissions")?;
+    Ok(listener)
+}
+
 async fn print_verifier_keys(config: AgentConfig) -> Result<()> {
     let resources = SystemResources::gather(config.resources.reserved).await?;
     let total = resources.cpus as usize;
@@ -505,7 +1224,7 @@ async fn print_verifier_keys(config: AgentConfig) -> Result<()> {
     Ok(())
 }
 
-async fn shutdown_handler(handle: Handle) {
+async fn shutdown_handler(handle: Handle, shutdown_sender: watch::Sender<bool>) {
     let ctrl_c = async {
         signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
     };
@@ -523,6 +1242,31 @@ async fn shutdown_handler(handle: Handle) {
     }
     info!("Received shutdown signal");
     handle.graceful_shutdown(Some(SHUTDOWN_GRACE_PERIOD));
+    shutdown_sender.send_replace(true);
+}
+
+async fn recommend_reservation(duration: Duration, margin_percent: u32, qemu_bin: &Path) {
+    #[derive(Serialize)]
+    struct Recommendation {
+        peak_overhead: HostOverhead,
+        recommended: HostReservation,
+    }
+
+    eprintln!("Sampling host overhead for {duration:?}");
+    let mut sampler = OverheadSampler::new(qemu_bin);
+    let mut tracker = OverheadTracker::new(usize::MAX);
+    let start = Instant::now();
+    loop {
+        sleep(RESERVATION_SAMPLE_INTERVAL).await;
+        tracker.observe(sampler.sample());
+        if start.elapsed() >= duration {
+            break;
+        }
+    }
+    let recommendation =
+        Recommendation { peak_overhead: tracker.peak(), recommended: tracker.recommend(margin_percent) };
+    let recommendation = serde_json::to_string_pretty(&recommendation).expect("failed to serialize");
+    println!("{recommendation}");
 }
 
 async fn run(cli: Cli) -> anyhow::Result<()> {
@@ -538,23 +1282,32 @@ async fn run(cli: Cli) -> anyhow::Result<()> {
             debug_workload(agent_config, workload_id, print_only).await?;
             Ok(())
         }
-        Command::Resources => {
+        Command::Resources { recommend: false, .. } => {
             let resources = SystemResources::gather(Default::default()).await?;
             let resources = serde_json::to_string_pretty(&resources).expect("failed to serialize");
             println!("{resources}");
             Ok(())
         }
+        Command::Resources { recommend: true, sample_seconds, margin_percent, qemu_bin } => {
+            recommend_reservation(Duration::from_secs(sample_seconds), margin_percent, &qemu_bin).await;
+            Ok(())
+        }
         Command::VerifierKeys { config } => {
             let agent_config = load_config(&config).context("Loading agent configuration")?;
             print_verifier_keys(agent_config).await?;
             Ok(())
         }
+        Command::Preflight { config, json } => {
+            let agent_config = load_config(&config).context("Loading agent configuration")?;
+            run_preflight(&agent_config, json).await
+        }
         Command::ValidateConfig { config } => {
             validate_config(&config).context("Invalid config file")?;
             println!("Config file is valid");
             Ok(())
         }
         Command::Download(DownloadCommand::Artifacts(args)) => download_artifacts(args).await,
+        Command::Artifacts(ArtifactsCommand::Pack(args)) => pack_artifacts(args).await,
     }
 }
 
@@ -0,0 +1,74 @@
+use crate::{config::PlatformClaimsConfig, version};
+use anyhow::Context;
+use attestation_report::platform_claims::{PlatformClaims, SignedPlatformClaims};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use uuid::Uuid;
+
+/// Signs the platform claims handed to CVMs when they're bootstrapped.
+///
+/// The claims are exposed by the attester next to the attestation report, so anyone that trusts this agent's key can
+/// tell where a workload runs.
+#[derive(Clone)]
+pub struct PlatformClaimsSigner {
+    secret_key: SecretKey,
+    metal_instance_id: String,
+    region: String,
+}
+
+impl PlatformClaimsSigner {
+    pub fn new(config: &PlatformClaimsConfig, agent_id: Uuid) -> anyhow::Result<Self> {
+        let secret_key = SecretKey::from_slice(&config.signing_key).context("Invalid platform claims signing key")?;
+        Ok(Self { secret_key, metal_instance_id: agent_id.to_string(), region: config.region.clone() })
+    }
+
+    /// The compressed public key claims are signed with.
+    pub fn public_key(&self) -> [u8; 33] {
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.secret_key).serialize()
+    }
+
+    /// Sign the claims for a workload.
+    pub fn sign(&self, workload_id: Uuid) -> SignedPlatformClaims {
+        let claims = PlatformClaims {
+            workload_id: workload_id.to_string(),
+            metal_instance_id: self.metal_instance_id.clone(),
+            region: self.region.clone(),
+            agent_version: version::agent_version().into(),
+        };
+        let message = Message::from_digest(claims.digest());
+        let signature = Secp256k1::signing_only().sign_ecdsa(&message, &self.secret_key);
+        SignedPlatformClaims {
+            claims,
+            public_key: self.public_key().to_vec(),
+            signature: signature.serialize_compact().to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::ecdsa::Signature;
+
+    #[test]
+    fn sign() {
+        let config = PlatformClaimsConfig { signing_key: [1; 32], region: "us-east-1".into() };
+        let agent_id = Uuid::new_v4();
+        let workload_id = Uuid::new_v4();
+        let signer = PlatformClaimsSigner::new(&config, agent_id).expect("failed to create signer");
+        let signed = signer.sign(workload_id);
+        assert_eq!(signed.claims.workload_id, workload_id.to_string());
+        assert_eq!(signed.claims.metal_instance_id, agent_id.to_string());
+        assert_eq!(signed.claims.region, "us-east-1");
+
+        let public_key = PublicKey::from_slice(&signed.public_key).expect("invalid public key");
+        let signature = Signature::from_compact(&signed.signature).expect("invalid signature");
+        let message = Message::from_digest(signed.claims.digest());
+        Secp256k1::verification_only().verify_ecdsa(&message, &signature, &public_key).expect("invalid signature");
+    }
+
+    #[test]
+    fn invalid_key() {
+        let config = PlatformClaimsConfig { signing_key: [0; 32], region: "us-east-1".into() };
+        assert!(PlatformClaimsSigner::new(&config, Uuid::new_v4()).is_err());
+    }
+}
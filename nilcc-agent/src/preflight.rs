@@ -0,0 +1,348 @@
+use crate::{
+    config::{AgentConfig, StorageConfig},
+    resources::SystemResources,
+};
+use serde::Serialize;
+use std::{
+    fmt, io,
+    net::{SocketAddr, TcpListener},
+    path::Path,
+};
+use tokio::{fs, process::Command};
+
+const KVM_DEVICE_PATH: &str = "/dev/kvm";
+const SEV_DEVICE_PATH: &str = "/dev/sev";
+const IOMMU_GROUPS_PATH: &str = "/sys/kernel/iommu_groups";
+const VFIO_PCI_DRIVER_PATH: &str = "/sys/bus/pci/drivers/vfio-pci";
+const MEMINFO_PATH: &str = "/proc/meminfo";
+
+/// The oldest SEV firmware version Linux supports running SEV-SNP guests with.
+const MIN_SEV_FIRMWARE_VERSION: (u32, u32) = (1, 51);
+
+/// The first qemu version that supports SEV-SNP guests.
+const MIN_QEMU_VERSION: (u32, u32, u32) = (9, 1, 0);
+
+/// The ports haproxy routes workload traffic through.
+const PROXY_PORTS: [u16; 2] = [80, 443];
+
+/// The result of checking whether a host meets the requirements to run workloads.
+#[derive(Serialize)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    /// Run every check against this host.
+    pub async fn run(config: &AgentConfig) -> Self {
+        let mut checks = vec![check_kvm().await, check_sev_snp().await, check_sev_firmware().await];
+        checks.push(check_iommu().await);
+        checks.push(check_vfio().await);
+        checks.push(check_hugepages().await);
+        checks.push(check_qemu(&config.qemu.system_bin).await);
+        checks.push(check_qemu_img(&config.qemu.img_bin).await);
+        if let StorageConfig::LvmThin { volume_group, thin_pool } = &config.storage {
+            checks.push(check_thin_pool(volume_group, thin_pool).await);
+        }
+
+        let mut ports = vec![(config.api.bind_endpoint, "api.bind_endpoint")];
+        ports.extend(config.api.additional_bind_endpoints.iter().map(|e| (*e, "api.additional_bind_endpoints")));
+        ports.push((config.metrics.bind_endpoint, "metrics.bind_endpoint"));
+        for (endpoint, field) in ports {
+            checks.push(check_port(endpoint, &format!("change `{field}` in the config")));
+        }
+        for port in PROXY_PORTS {
+            let endpoint = SocketAddr::from(([0, 0, 0, 0], port));
+            checks.push(check_port(endpoint, "haproxy needs this port to route traffic to workloads"));
+        }
+        Self { checks }
+    }
+
+    /// Whether none of the checks failed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.status != CheckStatus::Fail)
+    }
+}
+
+/// A single requirement that was checked.
+#[derive(Serialize)]
+pub struct PreflightCheck {
+    /// The name of the check.
+    pub name: String,
+
+    /// Whether the host meets the requirement.
+    pub status: CheckStatus,
+
+    /// What was found.
+    pub details: String,
+
+    /// How to fix the host if it doesn't meet the requirement.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remediation: Option<String>,
+}
+
+impl PreflightCheck {
+    fn pass(name: impl Into<String>, details: impl Into<String>) -> Self {
+        Self { name: name.into(), status: CheckStatus::Pass, details: details.into(), remediation: None }
+    }
+
+    fn warn(name: impl Into<String>, details: impl Into<String>, remediation: impl Into<String>) -> Self {
+        let remediation = Some(remediation.into());
+        Self { name: name.into(), status: CheckStatus::Warn, details: details.into(), remediation }
+    }
+
+    fn fail(name: impl Into<String>, details: impl Into<String>, remediation: impl Into<String>) -> Self {
+        let remediation = Some(remediation.into());
+        Self { name: name.into(), status: CheckStatus::Fail, details: details.into(), remediation }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            Self::Pass => "PASS",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+        };
+        write!(f, "{status}")
+    }
+}
+
+async fn check_kvm() -> PreflightCheck {
+    match fs::try_exists(KVM_DEVICE_PATH).await {
+        Ok(true) => PreflightCheck::pass("kvm", format!("{KVM_DEVICE_PATH} is available")),
+        _ => PreflightCheck::fail(
+            "kvm",
+            format!("{KVM_DEVICE_PATH} does not exist"),
+            "enable SVM in the BIOS and load the kvm_amd module",
+        ),
+    }
+}
+
+async fn check_sev_snp() -> PreflightCheck {
+    if !SystemResources::sev_snp_enabled() {
+        return PreflightCheck::fail(
+            "sev-snp",
+            "SEV-SNP is not enabled in the kvm_amd module",
+            "enable SEV-SNP and SMEE in the BIOS and load the kvm_amd module with `sev_snp=1`",
+        );
+    }
+    match fs::try_exists(SEV_DEVICE_PATH).await {
+        Ok(true) => PreflightCheck::pass("sev-snp", "SEV-SNP is enabled"),
+        _ => PreflightCheck::fail(
+            "sev-snp",
+            format!("{SEV_DEVICE_PATH} does not exist"),
+            "make sure the ccp module is loaded and the SEV firmware initialized, see `dmesg | grep SEV`",
+        ),
+    }
+}
+
+async fn check_sev_firmware() -> PreflightCheck {
+    let remediation =
+        "update the SEV firmware, either via a BIOS update or by installing a newer AMD SEV firmware file";
+    let log = match Command::new("dmesg").output().await {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).into_owned(),
+        _ => {
+            return PreflightCheck::warn(
+                "sev-firmware",
+                "failed to read the kernel log",
+                "run this command as root so the SEV firmware version can be determined",
+            );
+        }
+    };
+    let Some(version) = parse_sev_firmware_version(&log) else {
+        return PreflightCheck::warn("sev-firmware", "SEV firmware version not found in the kernel log", remediation);
+    };
+    let details = format!("SEV firmware version is {}.{}", version.0, version.1);
+    if version >= MIN_SEV_FIRMWARE_VERSION {
+        PreflightCheck::pass("sev-firmware", details)
+    } else {
+        let (major, minor) = MIN_SEV_FIRMWARE_VERSION;
+        PreflightCheck::fail("sev-firmware", format!("{details}, need at least {major}.{minor}"), remediation)
+    }
+}
+
+async fn check_iommu() -> PreflightCheck {
+    let groups = count_entries(Path::new(IOMMU_GROUPS_PATH)).await.unwrap_or_default();
+    if groups > 0 {
+        PreflightCheck::pass("iommu", format!("{groups} IOMMU groups found"))
+    } else {
+        PreflightCheck::fail(
+            "iommu",
+            "no IOMMU groups found",
+            "enable the IOMMU in the BIOS and make sure the kernel isn't booted with `amd_iommu=off`",
+        )
+    }
+}
+
+async fn check_vfio() -> PreflightCheck {
+    match fs::try_exists(VFIO_PCI_DRIVER_PATH).await {
+        Ok(true) => PreflightCheck::pass("vfio", "vfio-pci driver is loaded"),
+        _ => PreflightCheck::warn(
+            "vfio",
+            "vfio-pci driver is not loaded, GPUs can't be passed through to VMs",
+            "load it via `modprobe vfio-pci` and add it to /etc/modules-load.d if this host has GPUs",
+        ),
+    }
+}
+
+async fn check_hugepages() -> PreflightCheck {
+    let meminfo = fs::read_to_string(MEMINFO_PATH).await.unwrap_or_default();
+    let Some(reserved_mb) = parse_hugepages_mb(&meminfo) else {
+        return PreflightCheck::warn("hugepages", format!("failed to parse {MEMINFO_PATH}"), "check the host's kernel");
+    };
+    if reserved_mb == 0 {
+        PreflightCheck::pass("hugepages", "no memory is reserved for hugepages")
+    } else {
+        // VMs are backed by regular memory so anything reserved for hugepages is unusable by them.
+        PreflightCheck::warn(
+            "hugepages",
+            format!("{reserved_mb}MB are reserved for hugepages, which VMs don't use"),
+            "set `vm.nr_hugepages` to 0 and remove any `hugepages` kernel parameters",
+        )
+    }
+}
+
+async fn check_qemu(system_bin: &Path) -> PreflightCheck {
+    let remediation = format!(
+        "install qemu {}.{}.{} or newer and point `qemu.system_bin` to it",
+        MIN_QEMU_VERSION.0, MIN_QEMU_VERSION.1, MIN_QEMU_VERSION.2
+    );
+    let version = match binary_version(system_bin).await {
+        Ok(version) => version,
+        Err(e) => return PreflightCheck::fail("qemu", e, remediation),
+    };
+    let details = format!("{} is version {}.{}.{}", system_bin.display(), version.0, version.1, version.2);
+    if version >= MIN_QEMU_VERSION {
+        PreflightCheck::pass("qemu", details)
+    } else {
+        PreflightCheck::fail("qemu", format!("{details}, which doesn't support SEV-SNP"), remediation)
+    }
+}
+
+async fn check_qemu_img(img_bin: &Path) -> PreflightCheck {
+    match binary_version(img_bin).await {
+        Ok((major, minor, patch)) => {
+            PreflightCheck::pass("qemu-img", format!("{} is version {major}.{minor}.{patch}", img_bin.display()))
+        }
+        Err(e) => PreflightCheck::fail("qemu-img", e, "install qemu-utils and point `qemu.img_bin` to qemu-img"),
+    }
+}
+
+async fn check_thin_pool(volume_group: &str, thin_pool: &str) -> PreflightCheck {
+    let pool = format!("{volume_group}/{thin_pool}");
+    let remediation = "create the thin pool or fix `storage.volume_group` and `storage.thin_pool`";
+    let output = match Command::new("lvs").args(["--noheadings", "-o", "lv_attr", &pool]).output().await {
+        Ok(output) => output,
+        Err(e) => return PreflightCheck::fail("thin-pool", format!("failed to run lvs: {e}"), "install lvm2"),
+    };
+    // Thin pools are the only volumes whose attributes start with `t`.
+    let attributes = String::from_utf8_lossy(&output.stdout);
+    if output.status.success() && attributes.trim().starts_with('t') {
+        PreflightCheck::pass("thin-pool", format!("{pool} is a thin pool"))
+    } else {
+        PreflightCheck::fail("thin-pool", format!("{pool} is not a thin pool"), remediation)
+    }
+}
+
+fn check_port(endpoint: SocketAddr, remediation: &str) -> PreflightCheck {
+    let name = format!("port {}", endpoint.port());
+    match TcpListener::bind(endpoint) {
+        Ok(_) => PreflightCheck::pass(name, format!("{endpoint} is available")),
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => PreflightCheck::fail(
+            name,
+            format!("{endpoint} is already in use"),
+            format!("stop whatever is listening on it, {remediation}"),
+        ),
+        Err(e) => PreflightCheck::fail(name, format!("can't bind to {endpoint}: {e}"), "run this command as root"),
+    }
+}
+
+async fn count_entries(path: &Path) -> io::Result<usize> {
+    let mut entries = fs::read_dir(path).await?;
+    let mut count = 0;
+    while entries.next_entry().await?.is_some() {
+        count += 1;
+    }
+    Ok(count)
+}
+
+async fn binary_version(path: &Path) -> Result<(u32, u32, u32), String> {
+    let output = match Command::new(path).arg("--version").output().await {
+        Ok(output) => output,
+        Err(e) => return Err(format!("failed to run {}: {e}", path.display())),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_qemu_version(&stdout).ok_or_else(|| format!("failed to parse version from {}", path.display()))
+}
+
+/// Find the SEV firmware version in the kernel log, which contains a line like
+/// `ccp 0000:47:00.1: SEV-SNP API:1.55 build:21` when the firmware is initialized.
+fn parse_sev_firmware_version(log: &str) -> Option<(u32, u32)> {
+    let line = log.lines().rev().find(|line| line.contains("SEV-SNP API:") || line.contains("SEV API:"))?;
+    let (_, version) = line.split_once("API:")?;
+    let version = version.split_whitespace().next()?;
+    let (major, minor) = version.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Parse the output of `qemu-system-x86_64 --version` or `qemu-img --version`, which start with lines like
+/// `QEMU emulator version 9.2.0 (Debian 1:9.2.0+ds-1)` and `qemu-img version 9.2.0`.
+fn parse_qemu_version(output: &str) -> Option<(u32, u32, u32)> {
+    let line = output.lines().next()?;
+    let (_, version) = line.split_once("version ")?;
+    let version = version.split_whitespace().next()?;
+    let mut parts = version.split('.').map(|part| part.parse::<u32>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next()?.ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+/// Get the amount of memory reserved for hugepages out of `/proc/meminfo`.
+fn parse_hugepages_mb(meminfo: &str) -> Option<u64> {
+    let field = |name: &str| {
+        let line = meminfo.lines().find(|line| line.starts_with(name))?;
+        line.split_whitespace().nth(1)?.parse::<u64>().ok()
+    };
+    let total = field("HugePages_Total:")?;
+    let size_kb = field("Hugepagesize:")?;
+    Some(total * size_kb / 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sev_firmware_version() {
+        let log = "[    5.1] ccp 0000:47:00.1: sev enabled\n\
+                   [    5.9] ccp 0000:47:00.1: SEV API:1.55 build:21\n\
+                   [    6.0] ccp 0000:47:00.1: SEV-SNP API:1.55 build:21\n";
+        assert_eq!(parse_sev_firmware_version(log), Some((1, 55)));
+        assert_eq!(parse_sev_firmware_version("[    5.1] kvm_amd: SEV disabled\n"), None);
+    }
+
+    #[test]
+    fn qemu_version() {
+        let output = "QEMU emulator version 9.2.0 (Debian 1:9.2.0+ds-1)\nCopyright (c) 2003-2024 Fabrice Bellard\n";
+        assert_eq!(parse_qemu_version(output), Some((9, 2, 0)));
+        assert_eq!(parse_qemu_version("qemu-img version 8.2\n"), Some((8, 2, 0)));
+        assert_eq!(parse_qemu_version("unknown\n"), None);
+    }
+
+    #[test]
+    fn hugepages() {
+        let meminfo = "MemTotal:       263842360 kB\nHugePages_Total:     512\nHugePages_Free:      512\n\
+                       Hugepagesize:       2048 kB\n";
+        assert_eq!(parse_hugepages_mb(meminfo), Some(1024));
+        assert_eq!(parse_hugepages_mb("MemTotal:       263842360 kB\n"), None);
+    }
+}
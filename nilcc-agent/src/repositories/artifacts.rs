@@ -1,5 +1,6 @@
 use crate::repositories::sqlite::SqliteTransactionContext;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use nilcc_artifacts::metadata::ArtifactsMetadata;
 use sqlx::FromRow;
 
@@ -22,6 +23,12 @@ pub trait ArtifactsRepository: Send + Sync {
     /// List the available versions.
     async fn list(&mut self) -> Result<Vec<Artifacts>, ArtifactsRepositoryError>;
 
+    /// List when the available versions were installed and last used.
+    async fn list_usage(&mut self) -> Result<Vec<ArtifactsUsage>, ArtifactsRepositoryError>;
+
+    /// Set the last time a version was used by a workload.
+    async fn set_last_used(&mut self, version: &str, at: DateTime<Utc>) -> Result<(), ArtifactsRepositoryError>;
+
     /// Check if a version already exists.
     async fn exists(&mut self, version: &str) -> Result<bool, ArtifactsRepositoryError>;
 
@@ -39,6 +46,16 @@ pub struct Artifacts {
     pub metadata: ArtifactsMetadata,
 }
 
+/// When an artifacts version was installed and last used by a workload.
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct ArtifactsUsage {
+    pub version: String,
+    pub installed_at: DateTime<Utc>,
+
+    /// This is only tracked since the artifacts garbage collection policy was first evaluated.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ArtifactsRepositoryError {
     #[error("database error: {0}")]
@@ -85,6 +102,18 @@ impl<'a> ArtifactsRepository for SqliteArtifactsRepository<'a> {
         Ok(rows)
     }
 
+    async fn list_usage(&mut self) -> Result<Vec<ArtifactsUsage>, ArtifactsRepositoryError> {
+        let query = "SELECT version, updated_at AS installed_at, last_used_at FROM artifacts";
+        let rows = sqlx::query_as(query).fetch_all(&mut *self.ctx).await?;
+        Ok(rows)
+    }
+
+    async fn set_last_used(&mut self, version: &str, at: DateTime<Utc>) -> Result<(), ArtifactsRepositoryError> {
+        let query = "UPDATE artifacts SET last_used_at = ? WHERE version = ?";
+        sqlx::query(query).bind(at).bind(version).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
     async fn exists(&mut self, version: &str) -> Result<bool, ArtifactsRepositoryError> {
         let query = "SELECT 1 FROM artifacts WHERE version = ?";
         let row = sqlx::query(query).bind(version).fetch_optional(&mut *self.ctx).await?;
@@ -106,8 +135,8 @@ impl<'a> ArtifactsRepository for SqliteArtifactsRepository<'a> {
 #[cfg(test)]
 pub(crate) mod utils {
     use nilcc_artifacts::metadata::{
-        Artifact, ArtifactsMetadata, Cvm, CvmDisk, CvmImage, CvmImages, DiskFormat, KernelCommandLine, Verity,
-        VerityDisk,
+        Artifact, ArtifactsMetadata, Cvm, CvmDisk, CvmImage, CvmImages, DiskFormat, GuestPolicy, KernelCommandLine,
+        Verity, VerityDisk,
     };
 
     pub(crate) fn make_artifacts_metadata() -> ArtifactsMetadata {
@@ -148,6 +177,8 @@ pub(crate) mod utils {
                     },
                 },
             },
+            guest_policy: GuestPolicy::default(),
+            cpu: Default::default(),
         }
     }
 }
@@ -177,6 +208,13 @@ mod tests {
         let versions = repo.list().await.expect("list failed");
         assert_eq!(versions.len(), 2);
 
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        repo.set_last_used("bbb", now).await.expect("update failed");
+        let mut usage = repo.list_usage().await.expect("list failed");
+        usage.sort_by(|a, b| a.version.cmp(&b.version));
+        let last_used: Vec<_> = usage.iter().map(|u| (u.version.as_str(), u.last_used_at)).collect();
+        assert_eq!(last_used, &[("aaa", None), ("bbb", Some(now))]);
+
         repo.delete("aaa").await.expect("delete failed");
         assert!(!repo.exists("aaa").await.expect("lookup failed"));
     }
@@ -0,0 +1,91 @@
+use crate::repositories::sqlite::SqliteTransactionContext;
+use async_trait::async_trait;
+use sqlx::prelude::FromRow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// A locally cached copy of an environment variable group managed by the control plane.
+#[derive(Clone, FromRow, PartialEq)]
+pub struct EnvGroup {
+    pub name: String,
+    pub version: i64,
+    #[sqlx(json)]
+    pub env_vars: HashMap<String, String>,
+}
+
+impl fmt::Debug for EnvGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { name, version, env_vars } = self;
+        // Hide this one since it can have sensitive data
+        let env_vars: BTreeMap<_, _> = env_vars.keys().map(|key| (key, "...")).collect();
+        f.debug_struct("EnvGroup").field("name", name).field("version", version).field("env_vars", &env_vars).finish()
+    }
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait EnvGroupRepository: Send + Sync {
+    /// Find a cached env group.
+    async fn find(&mut self, name: &str) -> Result<Option<EnvGroup>, EnvGroupRepositoryError>;
+
+    /// Insert or replace a cached env group.
+    async fn upsert(&mut self, group: &EnvGroup) -> Result<(), EnvGroupRepositoryError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnvGroupRepositoryError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct SqliteEnvGroupRepository<'a> {
+    ctx: SqliteTransactionContext<'a>,
+}
+
+impl<'a> SqliteEnvGroupRepository<'a> {
+    pub fn new(ctx: SqliteTransactionContext<'a>) -> Self {
+        Self { ctx }
+    }
+}
+
+#[async_trait]
+impl<'a> EnvGroupRepository for SqliteEnvGroupRepository<'a> {
+    async fn find(&mut self, name: &str) -> Result<Option<EnvGroup>, EnvGroupRepositoryError> {
+        let query = "SELECT name, version, env_vars FROM env_groups WHERE name = ?";
+        let row = sqlx::query_as(query).bind(name).fetch_optional(&mut *self.ctx).await?;
+        Ok(row)
+    }
+
+    async fn upsert(&mut self, group: &EnvGroup) -> Result<(), EnvGroupRepositoryError> {
+        let query = r"
+INSERT INTO env_groups (name, version, env_vars)
+VALUES ($1, $2, $3)
+ON CONFLICT (name) DO UPDATE SET version = $2, env_vars = $3, updated_at = CURRENT_TIMESTAMP";
+        let EnvGroup { name, version, env_vars } = group;
+        sqlx::query(query).bind(name).bind(version).bind(sqlx::types::Json(env_vars)).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::sqlite::{SqliteDb, SqliteTransactionContextInner};
+
+    #[tokio::test]
+    async fn crud() {
+        let db = SqliteDb::connect("sqlite://:memory:").await.expect("failed to create db");
+        let connection = db.0.acquire().await.expect("failed to acquire");
+        let mut repo = SqliteEnvGroupRepository::new(SqliteTransactionContextInner::Connection(connection).into());
+        assert_eq!(repo.find("shared").await.expect("find failed"), None);
+
+        let group =
+            EnvGroup { name: "shared".into(), version: 1, env_vars: HashMap::from([("FOO".into(), "bar".into())]) };
+        repo.upsert(&group).await.expect("upsert failed");
+        assert_eq!(repo.find("shared").await.expect("find failed"), Some(group.clone()));
+
+        let group = EnvGroup { version: 2, env_vars: HashMap::from([("FOO".into(), "baz".into())]), ..group };
+        repo.upsert(&group).await.expect("upsert failed");
+        assert_eq!(repo.find("shared").await.expect("find failed"), Some(group));
+    }
+}
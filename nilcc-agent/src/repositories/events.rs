@@ -0,0 +1,108 @@
+use crate::{clients::nilcc_api::VmEvent, repositories::sqlite::SqliteTransactionContext};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+/// An event that hasn't been sent to the nilcc API yet.
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct PendingEvent {
+    pub id: i64,
+    pub workload_id: Uuid,
+    #[sqlx(json)]
+    pub event: VmEvent,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait PendingEventRepository: Send + Sync {
+    /// Insert a new event.
+    async fn insert(&mut self, event: &PendingEvent) -> Result<(), PendingEventRepositoryError>;
+
+    /// List all events, sorted by id.
+    async fn list(&mut self) -> Result<Vec<PendingEvent>, PendingEventRepositoryError>;
+
+    /// Delete an event.
+    async fn delete(&mut self, id: i64) -> Result<(), PendingEventRepositoryError>;
+
+    /// Commit all changes.
+    async fn commit(self: Box<Self>) -> Result<(), PendingEventRepositoryError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PendingEventRepositoryError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct SqlitePendingEventRepository<'a> {
+    ctx: SqliteTransactionContext<'a>,
+}
+
+impl<'a> SqlitePendingEventRepository<'a> {
+    pub fn new(ctx: SqliteTransactionContext<'a>) -> Self {
+        Self { ctx }
+    }
+}
+
+#[async_trait]
+impl<'a> PendingEventRepository for SqlitePendingEventRepository<'a> {
+    async fn insert(&mut self, event: &PendingEvent) -> Result<(), PendingEventRepositoryError> {
+        let query = "INSERT INTO pending_events (id, workload_id, event, timestamp) VALUES ($1, $2, $3, $4)";
+        let PendingEvent { id, workload_id, event, timestamp } = event;
+        sqlx::query(query)
+            .bind(id)
+            .bind(workload_id)
+            .bind(sqlx::types::Json(event))
+            .bind(timestamp)
+            .execute(&mut *self.ctx)
+            .await?;
+        Ok(())
+    }
+
+    async fn list(&mut self) -> Result<Vec<PendingEvent>, PendingEventRepositoryError> {
+        let query = "SELECT * FROM pending_events ORDER BY id";
+        let events = sqlx::query_as(query).fetch_all(&mut *self.ctx).await?;
+        Ok(events)
+    }
+
+    async fn delete(&mut self, id: i64) -> Result<(), PendingEventRepositoryError> {
+        let query = "DELETE FROM pending_events WHERE id = $1";
+        sqlx::query(query).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn commit(mut self: Box<Self>) -> Result<(), PendingEventRepositoryError> {
+        self.ctx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::sqlite::{SqliteDb, SqliteTransactionContextInner};
+
+    #[tokio::test]
+    async fn crud() {
+        let db = SqliteDb::connect("sqlite://:memory:").await.expect("failed to create db");
+        let connection = db.0.acquire().await.expect("failed to acquire");
+        let mut repo = SqlitePendingEventRepository::new(SqliteTransactionContextInner::Connection(connection).into());
+
+        let timestamp = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let first = PendingEvent {
+            id: 2,
+            workload_id: Uuid::new_v4(),
+            event: VmEvent::FailedToStart { error: "oops".into() },
+            timestamp,
+        };
+        let second = PendingEvent { id: 5, workload_id: Uuid::new_v4(), event: VmEvent::Running, timestamp };
+        repo.insert(&second).await.expect("insert failed");
+        repo.insert(&first).await.expect("insert failed");
+        assert_eq!(repo.list().await.expect("list failed"), vec![first.clone(), second]);
+
+        repo.delete(5).await.expect("delete failed");
+        assert_eq!(repo.list().await.expect("list failed"), vec![first]);
+    }
+}
@@ -1,4 +1,8 @@
 pub mod artifacts;
 pub mod changelog;
+pub mod env_groups;
+pub mod events;
 pub mod sqlite;
+pub mod usage;
+pub mod verifier_keys;
 pub mod workload;
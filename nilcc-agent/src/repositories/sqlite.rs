@@ -1,29 +1,54 @@
 use crate::repositories::{
     artifacts::{ArtifactsRepository, SqliteArtifactsRepository},
     changelog::{ChangelogRepository, SqliteChangelogRepository},
+    env_groups::{EnvGroupRepository, SqliteEnvGroupRepository},
+    events::{PendingEventRepository, SqlitePendingEventRepository},
+    usage::{SqliteUsageRepository, UsageRepository},
+    verifier_keys::{SqliteVerifierKeyRepository, VerifierKeyRepository},
     workload::{SqliteWorkloadRepository, WorkloadRepository},
 };
 use async_trait::async_trait;
+use metrics::counter;
 use sqlx::{
     Sqlite, SqliteConnection, SqlitePool, SqliteTransaction,
     pool::PoolConnection,
-    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
 };
 use std::{
+    hash::{BuildHasher, RandomState},
     mem,
     ops::{Deref, DerefMut},
     path::Path,
     str::FromStr,
+    time::Duration,
 };
+use tokio::time::sleep;
 use tracing::{info, warn};
 
+/// How long a statement waits for a lock held by another connection before failing with `SQLITE_BUSY`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many times starting a transaction is retried when the database is busy.
+const MAX_BUSY_RETRIES: u32 = 5;
+
+/// The delay before the first retry, which is doubled on every subsequent one.
+const BUSY_RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
+
+// The primary result codes for a busy or locked database. Extended codes keep these in their lowest byte.
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+
 #[derive(Clone)]
 pub struct SqliteDb(pub(crate) SqlitePool);
 
 impl SqliteDb {
     pub async fn connect(url: &str) -> anyhow::Result<Self> {
-        let connect_options =
-            SqliteConnectOptions::from_str(url)?.journal_mode(SqliteJournalMode::Wal).create_if_missing(true);
+        // WAL lets readers run concurrently with the writer, at which point a full sync on every commit is unnecessary.
+        let connect_options = SqliteConnectOptions::from_str(url)?
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT)
+            .create_if_missing(true);
         let mut pool_options = SqlitePoolOptions::new();
         if connect_options.get_filename() == Path::new(":memory:") {
             // if we don't do this eventually the database gets dropped and tables disappear.
@@ -113,6 +138,10 @@ pub trait RepositoryProvider: Send + Sync {
     async fn workloads(&self, mode: ProviderMode) -> Result<Box<dyn WorkloadRepository>, ProviderError>;
     async fn artifacts(&self, mode: ProviderMode) -> Result<Box<dyn ArtifactsRepository>, ProviderError>;
     async fn changelog(&self, mode: ProviderMode) -> Result<Box<dyn ChangelogRepository>, ProviderError>;
+    async fn env_groups(&self, mode: ProviderMode) -> Result<Box<dyn EnvGroupRepository>, ProviderError>;
+    async fn usage(&self, mode: ProviderMode) -> Result<Box<dyn UsageRepository>, ProviderError>;
+    async fn pending_events(&self, mode: ProviderMode) -> Result<Box<dyn PendingEventRepository>, ProviderError>;
+    async fn verifier_keys(&self, mode: ProviderMode) -> Result<Box<dyn VerifierKeyRepository>, ProviderError>;
 }
 
 pub struct SqliteRepositoryProvider {
@@ -130,13 +159,53 @@ impl SqliteRepositoryProvider {
                 let connection = self.db.0.acquire().await.map_err(|e| ProviderError(e.to_string()))?;
                 SqliteTransactionContextInner::Connection(connection)
             }
-            ProviderMode::Transactional => {
-                let tx = self.db.0.begin().await.map_err(|e| ProviderError(e.to_string()))?;
-                SqliteTransactionContextInner::Transaction(tx)
-            }
+            ProviderMode::Transactional => SqliteTransactionContextInner::Transaction(self.begin().await?),
         };
         Ok(ctx.into())
     }
+
+    /// Start a transaction, retrying with a jittered backoff while the database is busy.
+    ///
+    /// Transactions take the write lock upfront: a deferred transaction that reads and then writes fails right away
+    /// with `SQLITE_BUSY` if another connection wrote in between, without waiting for the busy timeout.
+    async fn begin(&self) -> Result<SqliteTransaction<'static>, ProviderError> {
+        let mut attempt = 0;
+        loop {
+            match self.db.0.begin_with("BEGIN IMMEDIATE").await {
+                Ok(tx) => return Ok(tx),
+                Err(e) if is_busy(&e) && attempt < MAX_BUSY_RETRIES => {
+                    attempt += 1;
+                    counter!("sqlite_busy_retries_total").increment(1);
+                    let delay = busy_retry_delay(attempt);
+                    warn!("Database is busy, retrying in {delay:?} (attempt {attempt}/{MAX_BUSY_RETRIES}): {e}");
+                    sleep(delay).await;
+                }
+                Err(e) => {
+                    if is_busy(&e) {
+                        counter!("sqlite_busy_errors_total").increment(1);
+                    }
+                    return Err(ProviderError(e.to_string()));
+                }
+            }
+        }
+    }
+}
+
+/// Check whether an error was caused by the database being busy or locked by another connection.
+fn is_busy(e: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(e) = e else {
+        return false;
+    };
+    e.code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED))
+}
+
+/// The delay before a retry, with up to one base delay worth of jitter so concurrent retries don't line up.
+fn busy_retry_delay(attempt: u32) -> Duration {
+    let backoff = BUSY_RETRY_BASE_DELAY * 2u32.pow(attempt.saturating_sub(1));
+    let jitter = RandomState::new().hash_one(attempt) % BUSY_RETRY_BASE_DELAY.as_millis() as u64;
+    backoff + Duration::from_millis(jitter)
 }
 
 #[async_trait]
@@ -155,6 +224,26 @@ impl RepositoryProvider for SqliteRepositoryProvider {
         let ctx = self.build_ctx(mode).await?;
         Ok(Box::new(SqliteChangelogRepository::new(ctx)))
     }
+
+    async fn env_groups(&self, mode: ProviderMode) -> Result<Box<dyn EnvGroupRepository>, ProviderError> {
+        let ctx = self.build_ctx(mode).await?;
+        Ok(Box::new(SqliteEnvGroupRepository::new(ctx)))
+    }
+
+    async fn usage(&self, mode: ProviderMode) -> Result<Box<dyn UsageRepository>, ProviderError> {
+        let ctx = self.build_ctx(mode).await?;
+        Ok(Box::new(SqliteUsageRepository::new(ctx)))
+    }
+
+    async fn pending_events(&self, mode: ProviderMode) -> Result<Box<dyn PendingEventRepository>, ProviderError> {
+        let ctx = self.build_ctx(mode).await?;
+        Ok(Box::new(SqlitePendingEventRepository::new(ctx)))
+    }
+
+    async fn verifier_keys(&self, mode: ProviderMode) -> Result<Box<dyn VerifierKeyRepository>, ProviderError> {
+        let ctx = self.build_ctx(mode).await?;
+        Ok(Box::new(SqliteVerifierKeyRepository::new(ctx)))
+    }
 }
 
 #[derive(Debug, Default)]
@@ -163,3 +252,59 @@ pub enum ProviderMode {
     Single,
     Transactional,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Connection, Executor};
+    use tempfile::TempDir;
+
+    async fn make_db() -> (TempDir, String, SqliteRepositoryProvider) {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let url = format!("sqlite://{}", dir.path().join("db.sqlite").display());
+        let db = SqliteDb::connect(&url).await.expect("failed to create db");
+        (dir, url, SqliteRepositoryProvider::new(db))
+    }
+
+    async fn lock_db(url: &str) -> SqliteConnection {
+        let options = SqliteConnectOptions::from_str(url).expect("invalid url").busy_timeout(Duration::ZERO);
+        let mut connection = SqliteConnection::connect_with(&options).await.expect("failed to connect");
+        connection.execute("BEGIN IMMEDIATE").await.expect("failed to lock");
+        connection
+    }
+
+    #[tokio::test]
+    async fn busy_error() {
+        let (_dir, url, _provider) = make_db().await;
+        let _locked = lock_db(&url).await;
+
+        let options = SqliteConnectOptions::from_str(&url).expect("invalid url").busy_timeout(Duration::ZERO);
+        let mut connection = SqliteConnection::connect_with(&options).await.expect("failed to connect");
+        let err = connection.execute("BEGIN IMMEDIATE").await.expect_err("lock acquired");
+        assert!(is_busy(&err), "{err:?}");
+        assert!(!is_busy(&sqlx::Error::RowNotFound));
+    }
+
+    #[tokio::test]
+    async fn transaction_waits_for_lock() {
+        let (_dir, url, provider) = make_db().await;
+        let mut locked = lock_db(&url).await;
+        let release = tokio::spawn(async move {
+            sleep(Duration::from_millis(100)).await;
+            locked.execute("COMMIT").await.expect("failed to unlock");
+        });
+
+        let repo = provider.workloads(ProviderMode::Transactional).await.expect("failed to begin transaction");
+        repo.commit().await.expect("failed to commit");
+        release.await.expect("release failed");
+    }
+
+    #[test]
+    fn retry_delay() {
+        for attempt in 1..=MAX_BUSY_RETRIES {
+            let backoff = BUSY_RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+            let delay = busy_retry_delay(attempt);
+            assert!(delay >= backoff && delay < backoff + BUSY_RETRY_BASE_DELAY, "{attempt}: {delay:?}");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::repositories::sqlite::SqliteTransactionContext;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+/// The resources allocated to a workload during a period of time.
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct UsageSample {
+    pub workload_id: Uuid,
+    pub sampled_at: DateTime<Utc>,
+    pub duration_seconds: u32,
+    pub cpus: u32,
+    pub gpus: u32,
+    pub memory_mb: u32,
+    pub disk_space_gb: u32,
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait UsageRepository: Send + Sync {
+    /// Insert a new sample.
+    async fn insert(&mut self, sample: &UsageSample) -> Result<(), UsageRepositoryError>;
+
+    /// List the samples for a workload taken within the given time range, sorted by time.
+    async fn list(
+        &mut self,
+        workload_id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSample>, UsageRepositoryError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UsageRepositoryError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct SqliteUsageRepository<'a> {
+    ctx: SqliteTransactionContext<'a>,
+}
+
+impl<'a> SqliteUsageRepository<'a> {
+    pub fn new(ctx: SqliteTransactionContext<'a>) -> Self {
+        Self { ctx }
+    }
+}
+
+#[async_trait]
+impl<'a> UsageRepository for SqliteUsageRepository<'a> {
+    async fn insert(&mut self, sample: &UsageSample) -> Result<(), UsageRepositoryError> {
+        let query = r"
+INSERT INTO workload_usage (workload_id, sampled_at, duration_seconds, cpus, gpus, memory_mb, disk_space_gb)
+VALUES ($1, $2, $3, $4, $5, $6, $7)";
+        let UsageSample { workload_id, sampled_at, duration_seconds, cpus, gpus, memory_mb, disk_space_gb } = sample;
+        sqlx::query(query)
+            .bind(workload_id)
+            .bind(sampled_at)
+            .bind(duration_seconds)
+            .bind(cpus)
+            .bind(gpus)
+            .bind(memory_mb)
+            .bind(disk_space_gb)
+            .execute(&mut *self.ctx)
+            .await?;
+        Ok(())
+    }
+
+    async fn list(
+        &mut self,
+        workload_id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSample>, UsageRepositoryError> {
+        let query = r"
+SELECT * FROM workload_usage
+WHERE workload_id = $1 AND ($2 IS NULL OR sampled_at >= $2) AND ($3 IS NULL OR sampled_at < $3)
+ORDER BY sampled_at";
+        let samples = sqlx::query_as(query).bind(workload_id).bind(from).bind(to).fetch_all(&mut *self.ctx).await?;
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::sqlite::{SqliteDb, SqliteTransactionContextInner};
+    use chrono::TimeDelta;
+
+    #[tokio::test]
+    async fn crud() {
+        let db = SqliteDb::connect("sqlite://:memory:").await.expect("failed to create db");
+        let connection = db.0.acquire().await.expect("failed to acquire");
+        let mut repo = SqliteUsageRepository::new(SqliteTransactionContextInner::Connection(connection).into());
+
+        let workload_id = Uuid::new_v4();
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let make_sample = |workload_id, sampled_at| UsageSample {
+            workload_id,
+            sampled_at,
+            duration_seconds: 60,
+            cpus: 2,
+            gpus: 1,
+            memory_mb: 2048,
+            disk_space_gb: 10,
+        };
+        let first = make_sample(workload_id, now);
+        let second = make_sample(workload_id, now + TimeDelta::minutes(1));
+        repo.insert(&second).await.expect("insert failed");
+        repo.insert(&first).await.expect("insert failed");
+        repo.insert(&make_sample(Uuid::new_v4(), now)).await.expect("insert failed");
+
+        let samples = repo.list(workload_id, None, None).await.expect("list failed");
+        assert_eq!(samples, vec![first.clone(), second.clone()]);
+
+        let samples = repo.list(workload_id, Some(second.sampled_at), None).await.expect("list failed");
+        assert_eq!(samples, vec![second]);
+
+        let samples = repo.list(workload_id, None, Some(second.sampled_at)).await.expect("list failed");
+        assert_eq!(samples, vec![first]);
+    }
+}
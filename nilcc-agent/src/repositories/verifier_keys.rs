@@ -0,0 +1,73 @@
+use crate::repositories::sqlite::SqliteTransactionContext;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::prelude::FromRow;
+
+/// A verifier key that was retired.
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct RetiredVerifierKey {
+    pub public_key: Vec<u8>,
+    pub retired_at: DateTime<Utc>,
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait VerifierKeyRepository: Send + Sync {
+    /// Mark a key as retired. Retiring a key that's already retired is a no-op.
+    async fn retire(&mut self, public_key: &[u8]) -> Result<(), VerifierKeyRepositoryError>;
+
+    /// List all retired keys.
+    async fn list_retired(&mut self) -> Result<Vec<RetiredVerifierKey>, VerifierKeyRepositoryError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifierKeyRepositoryError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct SqliteVerifierKeyRepository<'a> {
+    ctx: SqliteTransactionContext<'a>,
+}
+
+impl<'a> SqliteVerifierKeyRepository<'a> {
+    pub fn new(ctx: SqliteTransactionContext<'a>) -> Self {
+        Self { ctx }
+    }
+}
+
+#[async_trait]
+impl<'a> VerifierKeyRepository for SqliteVerifierKeyRepository<'a> {
+    async fn retire(&mut self, public_key: &[u8]) -> Result<(), VerifierKeyRepositoryError> {
+        let query = "INSERT INTO retired_verifier_keys (public_key) VALUES ($1) ON CONFLICT (public_key) DO NOTHING";
+        sqlx::query(query).bind(public_key).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn list_retired(&mut self) -> Result<Vec<RetiredVerifierKey>, VerifierKeyRepositoryError> {
+        let query = "SELECT public_key, retired_at FROM retired_verifier_keys ORDER BY retired_at";
+        let keys = sqlx::query_as(query).fetch_all(&mut *self.ctx).await?;
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::sqlite::{SqliteDb, SqliteTransactionContextInner};
+
+    #[tokio::test]
+    async fn crud() {
+        let db = SqliteDb::connect("sqlite://:memory:").await.expect("failed to create db");
+        let connection = db.0.acquire().await.expect("failed to acquire");
+        let mut repo = SqliteVerifierKeyRepository::new(SqliteTransactionContextInner::Connection(connection).into());
+        assert!(repo.list_retired().await.expect("list failed").is_empty());
+
+        repo.retire(&[1, 2, 3]).await.expect("retire failed");
+        // retiring it again is fine
+        repo.retire(&[1, 2, 3]).await.expect("retire failed");
+
+        let keys: Vec<_> = repo.list_retired().await.expect("list failed").into_iter().map(|k| k.public_key).collect();
+        assert_eq!(keys, vec![vec![1, 2, 3]]);
+    }
+}
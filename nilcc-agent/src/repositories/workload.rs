@@ -1,7 +1,9 @@
 use crate::{repositories::sqlite::SqliteTransactionContext, resources::GpuAddress};
 use async_trait::async_trait;
-use chrono::Utc;
-use nilcc_agent_models::workloads::create::DockerCredentials;
+use chrono::{DateTime, Utc};
+use nilcc_agent_models::workloads::create::{
+    BandwidthLimits, DockerCredentials, LogRotation, ProxyTimeouts, StateDisk, UpgradeChannel, WorkloadPriority,
+};
 use serde::{Deserialize, Serialize};
 use serde_with::hex::Hex;
 use serde_with::serde_as;
@@ -9,6 +11,7 @@ use sqlx::prelude::FromRow;
 use std::{
     collections::{BTreeMap, HashMap},
     fmt,
+    path::PathBuf,
     time::Duration,
 };
 use strum::{Display, EnumString};
@@ -22,7 +25,12 @@ pub struct Workload {
     #[sqlx(json)]
     pub env_vars: HashMap<String, String>,
     #[sqlx(json)]
+    pub env_groups: Vec<String>,
+    #[sqlx(json)]
     pub files: HashMap<String, Vec<u8>>,
+    /// Files that were uploaded separately and are kept on disk rather than in the database.
+    #[sqlx(json)]
+    pub stored_files: HashMap<String, StoredFile>,
     #[sqlx(json)]
     pub docker_credentials: Vec<DockerCredentials>,
     pub public_container_name: String,
@@ -39,6 +47,32 @@ pub struct Workload {
     pub last_reported_event: Option<String>,
     #[sqlx(json)]
     pub heartbeat: Option<WorkloadHeartbeat>,
+    #[sqlx(json)]
+    pub priority: WorkloadPriority,
+    pub preempted: bool,
+    pub log_encryption_key: Option<Vec<u8>>,
+    #[sqlx(json)]
+    pub upgrade_channel: UpgradeChannel,
+    #[sqlx(json)]
+    pub log_rotation: Option<LogRotation>,
+    #[sqlx(json)]
+    pub state_disk: StateDisk,
+    pub zerossl_account: Option<String>,
+    pub env_vars_restart_pending: bool,
+    #[sqlx(json)]
+    pub registry_mirrors: Option<Vec<String>>,
+    #[sqlx(json)]
+    pub labels: HashMap<String, String>,
+    pub paused: bool,
+    pub debug: bool,
+    #[sqlx(json)]
+    pub bandwidth_limits: Option<BandwidthLimits>,
+    #[sqlx(json)]
+    pub proxy_timeouts: Option<ProxyTimeouts>,
+    /// The name of the API token that created this workload, if it was created with one.
+    pub owner: Option<String>,
+    /// Whether this workload has exclusive use of the metal instance.
+    pub isolated: bool,
 }
 
 impl Workload {
@@ -62,7 +96,9 @@ impl fmt::Debug for Workload {
             docker_compose,
             artifacts_version,
             env_vars,
+            env_groups,
             files,
+            stored_files,
             public_container_name,
             public_container_port,
             memory_mb,
@@ -75,6 +111,22 @@ impl fmt::Debug for Workload {
             docker_credentials,
             last_reported_event,
             heartbeat,
+            priority,
+            preempted,
+            log_encryption_key,
+            upgrade_channel,
+            log_rotation,
+            state_disk,
+            zerossl_account,
+            env_vars_restart_pending,
+            registry_mirrors,
+            labels,
+            paused,
+            debug,
+            bandwidth_limits,
+            proxy_timeouts,
+            owner,
+            isolated,
         } = self;
         // Hide this one since it can have sensitive data
         let environment_variables: BTreeMap<_, _> = env_vars.keys().map(|key| (key, "...")).collect();
@@ -83,7 +135,9 @@ impl fmt::Debug for Workload {
             .field("docker_compose", docker_compose)
             .field("artifacts_version", artifacts_version)
             .field("env_vars", &environment_variables)
+            .field("env_groups", env_groups)
             .field("files", &files)
+            .field("stored_files", stored_files)
             .field("public_container_name", public_container_name)
             .field("public_container_port", public_container_port)
             .field("memory_mb", memory_mb)
@@ -96,10 +150,41 @@ impl fmt::Debug for Workload {
             .field("docker_credentials", docker_credentials)
             .field("last_reported_event", last_reported_event)
             .field("heartbeat", heartbeat)
+            .field("priority", priority)
+            .field("preempted", preempted)
+            .field("log_encryption_key", &log_encryption_key.as_ref().map(hex::encode))
+            .field("upgrade_channel", upgrade_channel)
+            .field("log_rotation", log_rotation)
+            .field("state_disk", state_disk)
+            .field("zerossl_account", zerossl_account)
+            .field("env_vars_restart_pending", env_vars_restart_pending)
+            .field("registry_mirrors", registry_mirrors)
+            .field("labels", labels)
+            .field("paused", paused)
+            .field("debug", debug)
+            .field("bandwidth_limits", bandwidth_limits)
+            .field("proxy_timeouts", proxy_timeouts)
+            .field("owner", owner)
+            .field("isolated", isolated)
             .finish()
     }
 }
 
+/// A workload file that's stored on disk.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredFile {
+    /// The path the file is stored at.
+    pub path: PathBuf,
+
+    /// The SHA256 hash of the file's contents.
+    #[serde_as(as = "Hex")]
+    pub sha256: [u8; 32],
+
+    /// The file's size, in bytes.
+    pub size: u64,
+}
+
 #[serde_as]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WorkloadHeartbeat {
@@ -109,6 +194,14 @@ pub struct WorkloadHeartbeat {
     pub heartbeat_interval: Option<Duration>,
 }
 
+/// A domain a workload stopped using, which is still served until it's retired.
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct RetiringDomain {
+    pub domain: String,
+    pub workload_id: Uuid,
+    pub retire_at: DateTime<Utc>,
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Display, EnumString, sqlx::Type)]
 pub enum WorkloadModelStatus {
     #[default]
@@ -152,9 +245,53 @@ pub trait WorkloadRepository: Send + Sync {
         env_vars: HashMap<String, String>,
     ) -> Result<(), WorkloadRepositoryError>;
 
+    /// Update the files bundled in a workload's application ISO.
+    async fn set_files(&mut self, id: Uuid, files: HashMap<String, Vec<u8>>) -> Result<(), WorkloadRepositoryError>;
+
+    /// Update the credentials a workload pulls its images with.
+    async fn set_docker_credentials(
+        &mut self,
+        id: Uuid,
+        credentials: &[DockerCredentials],
+    ) -> Result<(), WorkloadRepositoryError>;
+
+    /// Update the overrides for the timeouts the SNI proxy uses for a workload's traffic.
+    async fn set_proxy_timeouts(
+        &mut self,
+        id: Uuid,
+        timeouts: Option<ProxyTimeouts>,
+    ) -> Result<(), WorkloadRepositoryError>;
+
     /// Set the `last_reported_event` column for a workload.
     async fn set_last_reported_event(&mut self, id: Uuid, event: String) -> Result<(), WorkloadRepositoryError>;
 
+    /// Set the `preempted` column for a workload.
+    async fn set_preempted(&mut self, id: Uuid, value: bool) -> Result<(), WorkloadRepositoryError>;
+
+    /// Set the `env_vars_restart_pending` column for a workload.
+    async fn set_env_vars_restart_pending(&mut self, id: Uuid, value: bool) -> Result<(), WorkloadRepositoryError>;
+
+    /// Set the `paused` column for a workload.
+    async fn set_paused(&mut self, id: Uuid, value: bool) -> Result<(), WorkloadRepositoryError>;
+
+    /// Set the `domain` column for a workload.
+    ///
+    /// Domains other workloads are retiring can't be used, while a domain the workload itself is retiring is taken
+    /// back and is no longer retired.
+    async fn set_domain(&mut self, id: Uuid, domain: &str) -> Result<(), WorkloadRepositoryError>;
+
+    /// Set the `artifacts_version` column for a workload.
+    async fn set_artifacts_version(&mut self, id: Uuid, version: &str) -> Result<(), WorkloadRepositoryError>;
+
+    /// Keep track of a domain a workload stopped using until it's retired.
+    async fn add_retiring_domain(&mut self, domain: &RetiringDomain) -> Result<(), WorkloadRepositoryError>;
+
+    /// List the domains that are being retired, sorted by the time they're retired at.
+    async fn list_retiring_domains(&mut self) -> Result<Vec<RetiringDomain>, WorkloadRepositoryError>;
+
+    /// Stop keeping track of a domain that was retired.
+    async fn remove_retiring_domain(&mut self, domain: &str) -> Result<(), WorkloadRepositoryError>;
+
     /// Commit any changes that were performed on this repository.
     async fn commit(self: Box<Self>) -> Result<(), WorkloadRepositoryError>;
 }
@@ -200,11 +337,20 @@ impl<'a> SqliteWorkloadRepository<'a> {
     pub fn new(ctx: SqliteTransactionContext<'a>) -> Self {
         Self { ctx }
     }
+
+    /// The workload that's retiring a domain, if any.
+    async fn retiring_domain_owner(&mut self, domain: &str) -> Result<Option<Uuid>, WorkloadRepositoryError> {
+        let query = "SELECT workload_id FROM retiring_domains WHERE domain = ?";
+        Ok(sqlx::query_scalar(query).bind(domain).fetch_optional(&mut *self.ctx).await?)
+    }
 }
 
 #[async_trait]
 impl<'a> WorkloadRepository for SqliteWorkloadRepository<'a> {
     async fn create(&mut self, workload: &Workload) -> Result<(), WorkloadRepositoryError> {
+        if self.retiring_domain_owner(&workload.domain).await?.is_some() {
+            return Err(WorkloadRepositoryError::DuplicateDomain);
+        }
         let query = r"
 INSERT INTO workloads (
     id,
@@ -223,17 +369,40 @@ INSERT INTO workloads (
     domain,
     last_reported_event,
     heartbeat,
+    priority,
+    preempted,
+    log_encryption_key,
+    env_groups,
     enabled,
+    upgrade_channel,
+    log_rotation,
+    state_disk,
+    zerossl_account,
+    env_vars_restart_pending,
+    registry_mirrors,
+    labels,
+    paused,
+    debug,
+    bandwidth_limits,
+    proxy_timeouts,
+    owner,
+    isolated,
+    stored_files,
     created_at
 )
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)
+VALUES (
+    $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25,
+    $26, $27, $28, $29, $30, $31, $32, $33, $34, $35, $36
+)
 ";
         let Workload {
             id,
             docker_compose,
             artifacts_version,
             env_vars,
+            env_groups,
             files,
+            stored_files,
             docker_credentials,
             public_container_name,
             public_container_port,
@@ -246,6 +415,22 @@ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $
             last_reported_event,
             enabled,
             heartbeat,
+            priority,
+            preempted,
+            log_encryption_key,
+            upgrade_channel,
+            log_rotation,
+            state_disk,
+            zerossl_account,
+            env_vars_restart_pending,
+            registry_mirrors,
+            labels,
+            paused,
+            debug,
+            bandwidth_limits,
+            proxy_timeouts,
+            owner,
+            isolated,
         } = workload;
 
         sqlx::query(query)
@@ -265,7 +450,25 @@ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $
             .bind(domain)
             .bind(last_reported_event)
             .bind(sqlx::types::Json(heartbeat))
+            .bind(sqlx::types::Json(priority))
+            .bind(preempted)
+            .bind(log_encryption_key)
+            .bind(sqlx::types::Json(env_groups))
             .bind(enabled)
+            .bind(sqlx::types::Json(upgrade_channel))
+            .bind(sqlx::types::Json(log_rotation))
+            .bind(sqlx::types::Json(state_disk))
+            .bind(zerossl_account)
+            .bind(env_vars_restart_pending)
+            .bind(sqlx::types::Json(registry_mirrors))
+            .bind(sqlx::types::Json(labels))
+            .bind(paused)
+            .bind(debug)
+            .bind(sqlx::types::Json(bandwidth_limits))
+            .bind(sqlx::types::Json(proxy_timeouts))
+            .bind(owner)
+            .bind(isolated)
+            .bind(sqlx::types::Json(stored_files))
             .bind(Utc::now())
             .execute(&mut *self.ctx)
             .await?;
@@ -326,17 +529,144 @@ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $
         Ok(())
     }
 
+    async fn set_files(&mut self, id: Uuid, files: HashMap<String, Vec<u8>>) -> Result<(), WorkloadRepositoryError> {
+        let query = "UPDATE workloads SET files = ? WHERE id = ?";
+        sqlx::query(query).bind(sqlx::types::Json(files)).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn set_docker_credentials(
+        &mut self,
+        id: Uuid,
+        credentials: &[DockerCredentials],
+    ) -> Result<(), WorkloadRepositoryError> {
+        let query = "UPDATE workloads SET docker_credentials = ? WHERE id = ?";
+        sqlx::query(query).bind(sqlx::types::Json(credentials)).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn set_proxy_timeouts(
+        &mut self,
+        id: Uuid,
+        timeouts: Option<ProxyTimeouts>,
+    ) -> Result<(), WorkloadRepositoryError> {
+        let query = "UPDATE workloads SET proxy_timeouts = ? WHERE id = ?";
+        sqlx::query(query).bind(sqlx::types::Json(timeouts)).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
     async fn set_last_reported_event(&mut self, id: Uuid, event: String) -> Result<(), WorkloadRepositoryError> {
         let query = "UPDATE workloads SET last_reported_event = ? WHERE id = ?";
         sqlx::query(query).bind(event).bind(id).execute(&mut *self.ctx).await?;
         Ok(())
     }
 
+    async fn set_preempted(&mut self, id: Uuid, value: bool) -> Result<(), WorkloadRepositoryError> {
+        let query = "UPDATE workloads SET preempted = ? WHERE id = ?";
+        sqlx::query(query).bind(value).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn set_env_vars_restart_pending(&mut self, id: Uuid, value: bool) -> Result<(), WorkloadRepositoryError> {
+        let query = "UPDATE workloads SET env_vars_restart_pending = ? WHERE id = ?";
+        sqlx::query(query).bind(value).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn set_paused(&mut self, id: Uuid, value: bool) -> Result<(), WorkloadRepositoryError> {
+        let query = "UPDATE workloads SET paused = ? WHERE id = ?";
+        sqlx::query(query).bind(value).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn set_domain(&mut self, id: Uuid, domain: &str) -> Result<(), WorkloadRepositoryError> {
+        match self.retiring_domain_owner(domain).await? {
+            Some(owner) if owner == id => self.remove_retiring_domain(domain).await?,
+            Some(_) => return Err(WorkloadRepositoryError::DuplicateDomain),
+            None => (),
+        };
+        let query = "UPDATE workloads SET domain = ? WHERE id = ?";
+        sqlx::query(query).bind(domain).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn set_artifacts_version(&mut self, id: Uuid, version: &str) -> Result<(), WorkloadRepositoryError> {
+        let query = "UPDATE workloads SET artifacts_version = ? WHERE id = ?";
+        sqlx::query(query).bind(version).bind(id).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn add_retiring_domain(&mut self, domain: &RetiringDomain) -> Result<(), WorkloadRepositoryError> {
+        let query = r"
+INSERT INTO retiring_domains (domain, workload_id, retire_at) VALUES (?, ?, ?)
+ON CONFLICT (domain) DO UPDATE SET workload_id = excluded.workload_id, retire_at = excluded.retire_at";
+        let RetiringDomain { domain, workload_id, retire_at } = domain;
+        sqlx::query(query).bind(domain).bind(workload_id).bind(retire_at).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
+    async fn list_retiring_domains(&mut self) -> Result<Vec<RetiringDomain>, WorkloadRepositoryError> {
+        let query = "SELECT * FROM retiring_domains ORDER BY retire_at";
+        Ok(sqlx::query_as(query).fetch_all(&mut *self.ctx).await?)
+    }
+
+    async fn remove_retiring_domain(&mut self, domain: &str) -> Result<(), WorkloadRepositoryError> {
+        let query = "DELETE FROM retiring_domains WHERE domain = ?";
+        sqlx::query(query).bind(domain).execute(&mut *self.ctx).await?;
+        Ok(())
+    }
+
     async fn commit(self: Box<Self>) -> Result<(), WorkloadRepositoryError> {
         Ok(self.ctx.commit().await?)
     }
 }
 
+#[cfg(test)]
+pub(crate) mod utils {
+    use super::Workload;
+    use uuid::Uuid;
+
+    pub(crate) fn make_workload() -> Workload {
+        Workload {
+            id: Uuid::new_v4(),
+            docker_compose: Default::default(),
+            artifacts_version: "default".into(),
+            env_vars: Default::default(),
+            env_groups: Default::default(),
+            files: Default::default(),
+            stored_files: Default::default(),
+            docker_credentials: Default::default(),
+            public_container_name: Default::default(),
+            public_container_port: Default::default(),
+            memory_mb: Default::default(),
+            cpus: 1,
+            disk_space_gb: 1,
+            gpus: Default::default(),
+            ports: [150, 151, 152],
+            domain: "example.com".into(),
+            last_reported_event: None,
+            enabled: true,
+            heartbeat: None,
+            priority: Default::default(),
+            preempted: false,
+            log_encryption_key: None,
+            upgrade_channel: Default::default(),
+            log_rotation: None,
+            state_disk: Default::default(),
+            zerossl_account: None,
+            env_vars_restart_pending: false,
+            registry_mirrors: None,
+            labels: Default::default(),
+            paused: false,
+            debug: false,
+            bandwidth_limits: None,
+            proxy_timeouts: None,
+            owner: None,
+            isolated: false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -353,7 +683,12 @@ mod tests {
             artifacts_version: "default".into(),
             docker_compose: "hi".into(),
             env_vars: HashMap::from([("FOO".into(), "value".into())]),
+            env_groups: vec!["shared".into()],
             files: HashMap::from([("foo.txt".into(), vec![1, 2, 3])]),
+            stored_files: HashMap::from([(
+                "model.bin".into(),
+                StoredFile { path: "/var/vms/model.bin".into(), sha256: [1; 32], size: 1024 },
+            )]),
             docker_credentials: vec![DockerCredentials {
                 server: "registry.example.com".into(),
                 username: "foo".into(),
@@ -370,6 +705,22 @@ mod tests {
             last_reported_event: None,
             enabled: true,
             heartbeat: None,
+            priority: WorkloadPriority::Low,
+            preempted: false,
+            log_encryption_key: Some(vec![42; 32]),
+            upgrade_channel: UpgradeChannel::LatestStable,
+            log_rotation: Some(LogRotation { max_size_mb: 10, max_files: 3 }),
+            state_disk: StateDisk::Sealed,
+            zerossl_account: Some("key-2".into()),
+            env_vars_restart_pending: false,
+            registry_mirrors: Some(vec!["https://10.0.0.1:5000".into()]),
+            labels: HashMap::from([("team".into(), "payments".into())]),
+            paused: false,
+            debug: false,
+            bandwidth_limits: None,
+            proxy_timeouts: None,
+            owner: Some("team-a".into()),
+            isolated: true,
         };
         repo.create(&workload).await.expect("failed to insert");
 
@@ -414,11 +765,66 @@ mod tests {
             [("BAR".into(), "42".into())].into()
         );
 
+        repo.set_files(workload.id, [("bar.txt".into(), vec![4, 5])].into()).await.expect("failed to update");
+        assert_eq!(
+            repo.find(workload.id).await.expect("failed to find").files,
+            [("bar.txt".into(), vec![4, 5])].into()
+        );
+
+        let credentials =
+            vec![DockerCredentials { server: "ghcr.io".into(), username: "bar".into(), password: "baz".into() }];
+        repo.set_docker_credentials(workload.id, &credentials).await.expect("failed to update");
+        assert_eq!(repo.find(workload.id).await.expect("failed to find").docker_credentials, credentials);
+
+        let timeouts = ProxyTimeouts { connect_ms: Some(1000), client_ms: None, server_ms: Some(600_000) };
+        repo.set_proxy_timeouts(workload.id, Some(timeouts)).await.expect("failed to update");
+        assert_eq!(repo.find(workload.id).await.expect("failed to find").proxy_timeouts, Some(timeouts));
+
         repo.set_last_reported_event(workload.id, "SOMETHING".into()).await.expect("failed to update");
         assert_eq!(repo.find(workload.id).await.expect("failed to find").last_reported_event, Some("SOMETHING".into()));
 
-        let workload_same_domain = Workload { id: Uuid::new_v4(), ..workload };
+        repo.set_preempted(workload.id, true).await.expect("failed to update");
+        assert!(repo.find(workload.id).await.expect("failed to find").preempted);
+
+        repo.set_env_vars_restart_pending(workload.id, true).await.expect("failed to update");
+        assert!(repo.find(workload.id).await.expect("failed to find").env_vars_restart_pending);
+
+        repo.set_paused(workload.id, true).await.expect("failed to update");
+        assert!(repo.find(workload.id).await.expect("failed to find").paused);
+
+        repo.set_artifacts_version(workload.id, "0.3.0").await.expect("failed to update");
+        assert_eq!(repo.find(workload.id).await.expect("failed to find").artifacts_version, "0.3.0");
+
+        let workload_same_domain = Workload { id: Uuid::new_v4(), ..workload.clone() };
         let err = repo.create(&workload_same_domain).await.expect_err("insertion succeeded");
         assert!(matches!(err, WorkloadRepositoryError::DuplicateDomain), "{err:?}");
+
+        repo.set_domain(workload.id, "new.example.com").await.expect("failed to update");
+        assert_eq!(repo.find(workload.id).await.expect("failed to find").domain, "new.example.com");
+
+        let other_workload = Workload { id: Uuid::new_v4(), domain: "other.example.com".into(), ..workload };
+        repo.create(&other_workload).await.expect("failed to insert");
+        let err = repo.set_domain(other_workload.id, "new.example.com").await.expect_err("update succeeded");
+        assert!(matches!(err, WorkloadRepositoryError::DuplicateDomain), "{err:?}");
+
+        // Domains that are being retired are still in use.
+        let retire_at = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let retiring = RetiringDomain { domain: "old.example.com".into(), workload_id: workload.id, retire_at };
+        repo.add_retiring_domain(&retiring).await.expect("failed to add retiring domain");
+        assert_eq!(repo.list_retiring_domains().await.expect("failed to list"), &[retiring.clone()]);
+        let err = repo.set_domain(other_workload.id, "old.example.com").await.expect_err("update succeeded");
+        assert!(matches!(err, WorkloadRepositoryError::DuplicateDomain), "{err:?}");
+        let workload_retiring_domain =
+            Workload { id: Uuid::new_v4(), domain: "old.example.com".into(), ..other_workload.clone() };
+        let err = repo.create(&workload_retiring_domain).await.expect_err("insertion succeeded");
+        assert!(matches!(err, WorkloadRepositoryError::DuplicateDomain), "{err:?}");
+
+        // The workload retiring it can take it back.
+        repo.set_domain(workload.id, "old.example.com").await.expect("failed to update");
+        assert!(repo.list_retiring_domains().await.expect("failed to list").is_empty());
+
+        repo.add_retiring_domain(&retiring).await.expect("failed to add retiring domain");
+        repo.remove_retiring_domain("old.example.com").await.expect("failed to remove retiring domain");
+        assert!(repo.list_retiring_domains().await.expect("failed to list").is_empty());
     }
 }
@@ -1,4 +1,5 @@
 use crate::{
+    accelerators::{ACCELERATORS, Accelerator, AcceleratorVendor},
     config::ReservedResourcesConfig,
     repositories::sqlite::{ProviderMode, RepositoryProvider},
 };
@@ -6,17 +7,21 @@ use anyhow::{Context, anyhow, bail};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeSet, HashMap, VecDeque},
+    ffi::OsString,
     fmt, io,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+    sync::Mutex,
 };
-use sysinfo::{Disks, Networks, System};
+use sysinfo::{Disks, MemoryRefreshKind, Networks, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
 use tokio::{fs, process::Command};
 use tracing::{debug, info, warn};
+use uuid::Uuid;
 
-const H100_MODEL: &str = "H100";
-const NVIDIA_GPU_VENDOR_ID: &str = "10de";
 const NEW_VFIO_PCI_ID_PATH: &str = "/sys/bus/pci/drivers/vfio-pci/new_id";
+const NUMA_NODES_PATH: &str = "/sys/devices/system/node";
+const SEV_SNP_PARAMETER_PATH: &str = "/sys/module/kvm_amd/parameters/sev_snp";
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SystemResources {
@@ -28,6 +33,8 @@ pub struct SystemResources {
     pub cpus: u32,
     pub reserved_cpus: u32,
     pub gpus: Option<Gpus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub numa: Option<NumaTopology>,
 }
 
 impl SystemResources {
@@ -60,6 +67,7 @@ impl SystemResources {
         }
 
         let gpus = Self::find_gpus().await?;
+        let numa = NumaTopology::detect(Path::new(NUMA_NODES_PATH)).await.context("Failed to detect NUMA topology")?;
         Ok(Self {
             hostname,
             memory_mb,
@@ -69,9 +77,20 @@ impl SystemResources {
             cpus,
             reserved_cpus: reserved.cpus,
             gpus,
+            numa,
         })
     }
 
+    /// Use the size of the storage pool workload disks are created in as the disk space, rather than the root disk's.
+    pub fn use_storage_pool(&mut self, size_bytes: u64) -> anyhow::Result<()> {
+        let disk_space_gb = (size_bytes / (1024 * 1024 * 1024)).try_into().context("Too much disk space")?;
+        if self.reserved_disk_space_gb > disk_space_gb {
+            bail!("Reserved disk space ({}) exceeds storage pool size ({disk_space_gb})", self.reserved_disk_space_gb);
+        }
+        self.disk_space_gb = disk_space_gb;
+        Ok(())
+    }
+
     pub(crate) fn available_cpus(&self) -> u32 {
         self.cpus.saturating_sub(self.reserved_cpus)
     }
@@ -88,14 +107,15 @@ impl SystemResources {
         let Some(gpus) = &self.gpus else {
             return Ok(());
         };
-        info!("Creating PCI VFIO devices for {} GPUs", gpus.addresses.len());
+        let vendor_id = gpus.vendor.accelerator().pci_vendor_id();
+        info!("Creating PCI VFIO devices for {} {} GPUs", gpus.addresses.len(), gpus.vendor);
         for address in &gpus.addresses {
             info!("Finding device id for {address}");
             let output = Command::new("lspci").arg("-n").arg("-s").arg(&address.0).invoke().await?;
             let device_id = Self::parse_device_id(&output).context("Failed to parse device id")?;
             info!("Creating PCI VFIO for device {device_id}");
 
-            let command = format!("{NVIDIA_GPU_VENDOR_ID} {device_id}");
+            let command = format!("{vendor_id} {device_id}");
             match fs::write(NEW_VFIO_PCI_ID_PATH, &command).await {
                 Ok(()) => info!("PCI VFIO device {device_id} created"),
                 Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
@@ -107,23 +127,43 @@ impl SystemResources {
         Ok(())
     }
 
-    /// Finds supported NVIDIA GPUs
+    /// Finds supported GPUs, which must all be from the same vendor and of the same model.
     pub(crate) async fn find_gpus() -> anyhow::Result<Option<Gpus>> {
-        let output = Command::new("lspci").arg("-d").arg(format!("{NVIDIA_GPU_VENDOR_ID}:")).invoke().await?;
-        Self::parse_gpus(&output).context("Failed to parse GPUs")
+        let mut found = Vec::new();
+        for accelerator in ACCELERATORS {
+            let vendor_id = accelerator.pci_vendor_id();
+            let output = Command::new("lspci").arg("-d").arg(format!("{vendor_id}:")).invoke().await?;
+            if let Some(gpus) = Self::parse_gpus(*accelerator, &output).context("Failed to parse GPUs")? {
+                found.push(gpus);
+            }
+        }
+        if found.len() > 1 {
+            let vendors: Vec<_> = found.iter().map(|gpus| gpus.vendor.to_string()).collect();
+            bail!("GPUs from multiple vendors found: {}", vendors.join(", "));
+        }
+        Ok(found.pop())
     }
 
-    fn parse_gpus(lspci_output: &str) -> anyhow::Result<Option<Gpus>> {
+    fn parse_gpus(accelerator: &dyn Accelerator, lspci_output: &str) -> anyhow::Result<Option<Gpus>> {
         let lines: Vec<&str> = lspci_output.lines().filter(|&line| !line.trim().is_empty()).collect();
         if lines.is_empty() {
             return Ok(None);
         }
 
+        let vendor = accelerator.vendor();
+        let mut model = None;
         let mut addresses = Vec::new();
         for line in lines {
-            if !line.contains("H100") {
-                bail!("Unsupported NVIDIA GPU found. All GPUs must be {H100_MODEL}. Detected: {line}");
-            }
+            let Some(line_model) = accelerator.model(line) else {
+                bail!("Unsupported {vendor} GPU found: {line}");
+            };
+            match &model {
+                Some(model) if *model != line_model => {
+                    bail!("All GPUs must be the same model, found {model} and {line_model}")
+                }
+                Some(_) => (),
+                None => model = Some(line_model),
+            };
             if let Some(bdf) = line.split_whitespace().next() {
                 addresses.push(GpuAddress(bdf.to_string()));
             } else {
@@ -133,7 +173,8 @@ impl SystemResources {
 
         addresses.sort();
 
-        Ok(Some(Gpus::new(H100_MODEL, addresses)))
+        let model = model.expect("no model");
+        Ok(Some(Gpus::new(vendor, model, addresses)))
     }
 
     fn parse_device_id(lspci_output: &str) -> anyhow::Result<String> {
@@ -147,21 +188,21 @@ impl SystemResources {
         Ok(device_id.to_string())
     }
 
-    pub fn find_public_ip() -> anyhow::Result<Ipv4Addr> {
+    /// Find the public IP addresses of this host, optionally looking for an IPv6 one as well.
+    pub fn find_public_ips(ipv6: bool) -> anyhow::Result<PublicIps> {
         let networks = Networks::new_with_refreshed_list();
-        for (_, network) in &networks {
-            for network in network.ip_networks() {
-                let IpAddr::V4(addr) = network.addr else {
-                    debug!("Ignoring IPv6 address {}", network.addr);
-                    continue;
-                };
-                if addr.is_public() {
-                    info!("Found public IPv4 address: {addr}");
-                    return Ok(addr);
-                }
-            }
+        let addrs = (&networks).into_iter().flat_map(|(_, network)| network.ip_networks().iter().map(|n| n.addr));
+        let ips = PublicIps::select(addrs, ipv6);
+        if ips.is_empty() {
+            bail!("not public addresses available");
         }
-        bail!("not public addresses available");
+        debug!("Found public IP addresses: {ips}");
+        Ok(ips)
+    }
+
+    /// Check whether the host's KVM module has SEV-SNP enabled.
+    pub fn sev_snp_enabled() -> bool {
+        std::fs::read_to_string(SEV_SNP_PARAMETER_PATH).is_ok_and(|value| matches!(value.trim(), "Y" | "1"))
     }
 
     pub async fn adjust_gpu_assignment(&self, provider: &dyn RepositoryProvider) -> anyhow::Result<()> {
@@ -198,6 +239,105 @@ impl SystemResources {
     }
 }
 
+/// The public IP addresses this host is reachable at.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PublicIps {
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+impl PublicIps {
+    /// Pick the first public address of each family out of the given ones.
+    fn select(addrs: impl IntoIterator<Item = IpAddr>, ipv6: bool) -> Self {
+        let mut ips = Self::default();
+        for addr in addrs {
+            match addr {
+                IpAddr::V4(addr) if ips.ipv4.is_none() && addr.is_public() => ips.ipv4 = Some(addr),
+                IpAddr::V6(addr) if ipv6 && ips.ipv6.is_none() && addr.is_public() => ips.ipv6 = Some(addr),
+                _ => debug!("Ignoring address {addr}"),
+            }
+        }
+        ips
+    }
+
+    /// Whether no public address was found.
+    pub fn is_empty(&self) -> bool {
+        self.ipv4.is_none() && self.ipv6.is_none()
+    }
+
+    /// Iterate over the addresses that are set.
+    pub fn iter(&self) -> impl Iterator<Item = IpAddr> {
+        self.ipv4.map(IpAddr::V4).into_iter().chain(self.ipv6.map(IpAddr::V6))
+    }
+}
+
+impl fmt::Display for PublicIps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ips: Vec<_> = self.iter().map(|ip| ip.to_string()).collect();
+        write!(f, "[{}]", ips.join(", "))
+    }
+}
+
+/// Finds the public IP addresses this host is reachable at.
+#[cfg_attr(test, mockall::automock)]
+pub trait PublicIpFinder: Send + Sync {
+    /// Find the public IPv4 and, if enabled, IPv6 addresses.
+    fn find_public_ips(&self) -> anyhow::Result<PublicIps>;
+}
+
+/// A [PublicIpFinder] that looks for public addresses in the host's network interfaces.
+pub struct NetworkInterfacePublicIpFinder {
+    pub ipv6: bool,
+}
+
+impl PublicIpFinder for NetworkInterfacePublicIpFinder {
+    fn find_public_ips(&self) -> anyhow::Result<PublicIps> {
+        SystemResources::find_public_ips(self.ipv6)
+    }
+}
+
+/// Finds the free space in the filesystem a path lives in.
+#[cfg_attr(test, mockall::automock)]
+pub trait FreeSpaceFinder: Send + Sync {
+    /// Find the number of bytes available in the filesystem the given path lives in.
+    fn free_space_bytes(&self, path: &Path) -> anyhow::Result<u64>;
+}
+
+/// A [FreeSpaceFinder] that looks up the disk mounted at the longest prefix of the path.
+pub struct MountedDiskFreeSpaceFinder;
+
+impl FreeSpaceFinder for MountedDiskFreeSpaceFinder {
+    fn free_space_bytes(&self, path: &Path) -> anyhow::Result<u64> {
+        let path = path.canonicalize().context("Failed to canonicalize path")?;
+        let disks = Disks::new_with_refreshed_list();
+        disks
+            .list()
+            .iter()
+            .filter(|disk| path.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+            .map(|disk| disk.available_space())
+            .context("No disk is mounted at path")
+    }
+}
+
+/// Finds the memory available on the host.
+#[cfg_attr(test, mockall::automock)]
+pub trait AvailableMemoryFinder: Send + Sync {
+    /// Find the memory, in MB, that can be allocated without swapping.
+    fn available_memory_mb(&self) -> u64;
+}
+
+/// An [AvailableMemoryFinder] that looks up the host's memory via sysinfo.
+pub struct SystemAvailableMemoryFinder;
+
+impl AvailableMemoryFinder for SystemAvailableMemoryFinder {
+    fn available_memory_mb(&self) -> u64 {
+        let mut system = System::new();
+        system.refresh_memory_specifics(MemoryRefreshKind::nothing().with_ram());
+        system.available_memory() / (1024 * 1024)
+    }
+}
+
 trait IsPublic {
     fn is_public(&self) -> bool;
 }
@@ -246,6 +386,25 @@ impl IsPublic for Ipv4Addr {
     }
 }
 
+impl IsPublic for Ipv6Addr {
+    fn is_public(&self) -> bool {
+        // TODO: use `Ipv6Addr::is_global` when stabilized
+        let segments = self.segments();
+
+        // Only global unicast addresses (2000::/3) are routable, this excludes loopback, link local, ULAs, etc.
+        if segments[0] & 0xe000 != 0x2000 {
+            return false;
+        }
+
+        // 2001:db8::/32
+        if segments[0] == 0x2001 && segments[1] == 0xdb8 {
+            return false;
+        }
+
+        true
+    }
+}
+
 #[async_trait]
 trait CommandExt {
     async fn invoke(&mut self) -> anyhow::Result<String>;
@@ -281,47 +440,279 @@ impl fmt::Display for GpuAddress {
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Gpus {
+    pub vendor: AcceleratorVendor,
     pub model: String,
     pub addresses: Vec<GpuAddress>,
 }
 
 impl Gpus {
-    pub(crate) fn new<S: Into<String>, I: Into<Vec<GpuAddress>>>(model: S, addresses: I) -> Self {
-        Self { model: model.into(), addresses: addresses.into() }
+    pub(crate) fn new<S: Into<String>, I: Into<Vec<GpuAddress>>>(
+        vendor: AcceleratorVendor,
+        model: S,
+        addresses: I,
+    ) -> Self {
+        Self { vendor, model: model.into(), addresses: addresses.into() }
+    }
+
+    /// Whether these GPUs run in confidential computing mode.
+    pub fn confidential_computing(&self) -> bool {
+        self.vendor.accelerator().confidential_computing()
+    }
+}
+
+/// The host's NUMA topology.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NumaTopology {
+    pub nodes: Vec<NumaNode>,
+}
+
+impl NumaTopology {
+    /// Detect the topology from the nodes sysfs exposes in the given path, if any.
+    async fn detect(path: &Path) -> anyhow::Result<Option<Self>> {
+        let mut entries = match fs::read_dir(path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to list NUMA nodes"),
+        };
+        let mut nodes = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(id) = name.to_str().and_then(|name| name.strip_prefix("node")).and_then(|id| id.parse().ok())
+            else {
+                continue;
+            };
+            let cpus = fs::read_to_string(entry.path().join("cpulist")).await.context("Failed to read node CPUs")?;
+            let cpus = parse_cpu_list(cpus.trim()).with_context(|| format!("Invalid CPU list for node {id}"))?;
+            let meminfo =
+                fs::read_to_string(entry.path().join("meminfo")).await.context("Failed to read node memory")?;
+            let memory_mb = parse_node_memory_mb(&meminfo).with_context(|| format!("Invalid meminfo for node {id}"))?;
+            nodes.push(NumaNode { id, cpus, memory_mb });
+        }
+        nodes.sort_by_key(|node| node.id);
+        Ok((!nodes.is_empty()).then_some(Self { nodes }))
+    }
+}
+
+/// A NUMA node.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NumaNode {
+    pub id: u32,
+    pub cpus: Vec<u32>,
+    pub memory_mb: u32,
+}
+
+/// Parse a CPU list as sysfs formats it, e.g. `0-3,8-11`.
+fn parse_cpu_list(list: &str) -> anyhow::Result<Vec<u32>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => cpus.extend(start.parse::<u32>()?..=end.parse::<u32>()?),
+            None => cpus.push(range.parse()?),
+        }
+    }
+    Ok(cpus)
+}
+
+/// Parse the total memory out of a node's `meminfo`, whose lines look like `Node 0 MemTotal: 1024 kB`.
+fn parse_node_memory_mb(meminfo: &str) -> anyhow::Result<u32> {
+    let value = meminfo
+        .lines()
+        .find_map(|line| line.split_once("MemTotal:").map(|(_, value)| value))
+        .ok_or_else(|| anyhow!("no MemTotal"))?;
+    let kb: u64 = value.trim().trim_end_matches("kB").trim().parse()?;
+    (kb / 1024).try_into().context("Too much memory")
+}
+
+/// Where a workload's VM is placed when pinning VMs to NUMA nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumaPlacement {
+    /// The node the VM's memory is allocated from.
+    pub node: u32,
+
+    /// The host CPUs the VM runs on.
+    pub cpus: Vec<u32>,
+}
+
+impl NumaPlacement {
+    /// The CPUs as a list that can be passed to `taskset`.
+    pub fn cpu_list(&self) -> String {
+        self.cpus.iter().map(ToString::to_string).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Places workloads on NUMA nodes so that each VM's vCPUs and memory are kept on a single node.
+///
+/// Every workload gets its own host CPUs. The lowest numbered CPUs are left for the host, as many as are reserved for
+/// it. Workloads go on the node with the fewest free CPUs that can fit them, so large workloads still fit later.
+pub struct NumaAllocator {
+    nodes: Vec<NumaNode>,
+    placements: Mutex<HashMap<Uuid, (NumaPlacement, u32)>>,
+}
+
+impl NumaAllocator {
+    pub fn new(topology: NumaTopology, reserved_cpus: u32) -> Self {
+        let mut host_cpus: Vec<_> = topology.nodes.iter().flat_map(|node| node.cpus.iter().copied()).collect();
+        host_cpus.sort();
+        host_cpus.truncate(reserved_cpus as usize);
+        let nodes = topology
+            .nodes
+            .into_iter()
+            .map(|mut node| {
+                node.cpus.retain(|cpu| !host_cpus.contains(cpu));
+                node
+            })
+            .collect();
+        Self { nodes, placements: Default::default() }
+    }
+
+    /// Place a workload, returning its existing placement if it already has one.
+    ///
+    /// Returns `None` if no single node has enough free CPUs and memory for it.
+    pub(crate) fn allocate(&self, workload_id: Uuid, cpus: u32, memory_mb: u32) -> Option<NumaPlacement> {
+        let mut placements = self.placements.lock().expect("lock poisoned");
+        if let Some((placement, _)) = placements.get(&workload_id) {
+            return Some(placement.clone());
+        }
+        let mut best: Option<(&NumaNode, Vec<u32>)> = None;
+        for node in &self.nodes {
+            let placed = placements.values().filter(|(placement, _)| placement.node == node.id);
+            let used_memory_mb: u32 = placed.clone().map(|(_, memory_mb)| memory_mb).sum();
+            let used_cpus: Vec<_> = placed.flat_map(|(placement, _)| placement.cpus.iter()).collect();
+            let free_cpus: Vec<_> = node.cpus.iter().copied().filter(|cpu| !used_cpus.contains(&cpu)).collect();
+            if free_cpus.len() < cpus as usize || node.memory_mb.saturating_sub(used_memory_mb) < memory_mb {
+                continue;
+            }
+            if best.as_ref().is_none_or(|(_, best_cpus)| free_cpus.len() < best_cpus.len()) {
+                best = Some((node, free_cpus));
+            }
+        }
+        let (node, mut free_cpus) = best?;
+        free_cpus.truncate(cpus as usize);
+        let placement = NumaPlacement { node: node.id, cpus: free_cpus };
+        placements.insert(workload_id, (placement.clone(), memory_mb));
+        Some(placement)
+    }
+
+    /// Release the resources a workload was placed on.
+    pub(crate) fn release(&self, workload_id: Uuid) {
+        self.placements.lock().expect("lock poisoned").remove(&workload_id);
+    }
+}
+
+/// The CPUs and memory reserved for the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HostReservation {
+    pub cpus: u32,
+    pub memory_mb: u32,
+}
+
+/// The resources used by the host outside of the VMs it runs, e.g. by the agent, the proxy, and the OS.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct HostOverhead {
+    /// The number of CPUs in use.
+    pub cpus: f64,
+
+    /// The memory in use, in MBs.
+    pub memory_mb: u64,
+}
+
+/// Measures the host's overhead by subtracting the usage of VM processes from the system's usage.
+pub struct OverheadSampler {
+    system: System,
+    vm_process_name: OsString,
+}
+
+impl OverheadSampler {
+    /// Construct a new sampler, identifying VM processes by the file name of the given qemu binary.
+    ///
+    /// CPU usage is computed between consecutive samples so samples should be at least
+    /// [`sysinfo::MINIMUM_CPU_UPDATE_INTERVAL`] apart.
+    pub fn new(qemu_bin: &Path) -> Self {
+        let vm_process_name = qemu_bin.file_name().unwrap_or(qemu_bin.as_os_str()).to_os_string();
+        let mut sampler = Self { system: System::new(), vm_process_name };
+        sampler.refresh();
+        sampler
+    }
+
+    /// Take a sample of the host's overhead.
+    pub fn sample(&mut self) -> HostOverhead {
+        self.refresh();
+        let total_cpus = self.system.cpus().len() as f64 * self.system.global_cpu_usage() as f64 / 100.0;
+        let (mut vm_cpus, mut vm_memory) = (0.0, 0);
+        for process in self.system.processes().values() {
+            if process.exe().and_then(Path::file_name) == Some(self.vm_process_name.as_os_str()) {
+                vm_cpus += process.cpu_usage() as f64 / 100.0;
+                vm_memory += process.memory();
+            }
+        }
+        HostOverhead {
+            cpus: (total_cpus - vm_cpus).max(0.0),
+            memory_mb: self.system.used_memory().saturating_sub(vm_memory) / (1024 * 1024),
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.system.refresh_cpu_usage();
+        self.system.refresh_memory_specifics(MemoryRefreshKind::nothing().with_ram());
+        let processes = ProcessRefreshKind::nothing().with_cpu().with_memory().with_exe(UpdateKind::OnlyIfNotSet);
+        self.system.refresh_processes_specifics(ProcessesToUpdate::All, true, processes);
+    }
+}
+
+/// Keeps track of the host's overhead over a window of samples and recommends reservations that cover it.
+pub struct OverheadTracker {
+    samples: VecDeque<HostOverhead>,
+    window: usize,
+}
+
+impl OverheadTracker {
+    /// Construct a tracker that only takes into account the last `window` samples.
+    pub fn new(window: usize) -> Self {
+        Self { samples: VecDeque::new(), window: window.max(1) }
+    }
+
+    pub fn observe(&mut self, sample: HostOverhead) {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Whether the window is full.
+    pub fn is_full(&self) -> bool {
+        self.samples.len() == self.window
+    }
+
+    /// The highest overhead seen during the window.
+    pub fn peak(&self) -> HostOverhead {
+        self.samples.iter().fold(HostOverhead::default(), |peak, sample| HostOverhead {
+            cpus: peak.cpus.max(sample.cpus),
+            memory_mb: peak.memory_mb.max(sample.memory_mb),
+        })
+    }
+
+    /// The reservation that covers the peak overhead plus the given margin.
+    pub fn recommend(&self, margin_percent: u32) -> HostReservation {
+        let factor = 100 + margin_percent as u64;
+        let HostOverhead { cpus, memory_mb } = self.peak();
+        HostReservation {
+            cpus: (cpus * factor as f64 / 100.0).ceil().min(u32::MAX as f64) as u32,
+            memory_mb: (memory_mb * factor).div_ceil(100).try_into().unwrap_or(u32::MAX),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::accelerators::{AmdInstinctAccelerator, NvidiaAccelerator};
     use crate::repositories::{
         sqlite::{SqliteDb, SqliteRepositoryProvider},
-        workload::Workload,
+        workload::{Workload, utils::make_workload},
     };
+    use rstest::rstest;
     use uuid::Uuid;
 
-    fn make_workload(domain: &str, gpus: &[GpuAddress]) -> Workload {
-        Workload {
-            id: Uuid::new_v4(),
-            docker_compose: Default::default(),
-            artifacts_version: "default".into(),
-            env_vars: Default::default(),
-            files: Default::default(),
-            docker_credentials: Default::default(),
-            public_container_name: Default::default(),
-            public_container_port: Default::default(),
-            memory_mb: Default::default(),
-            cpus: 1.try_into().unwrap(),
-            disk_space_gb: 1.try_into().unwrap(),
-            gpus: gpus.iter().cloned().collect(),
-            ports: [150, 151, 152],
-            domain: domain.into(),
-            last_reported_event: None,
-            enabled: true,
-            heartbeat: None,
-        }
-    }
-
     #[tokio::test]
     async fn gather() {
         let resources = SystemResources::gather(Default::default()).await.expect("failed to gather resources");
@@ -354,9 +745,67 @@ mod tests {
             "01:00.0 3D controller: NVIDIA Corporation GH100 [H100 PCIe] (rev a1)",
         ]
         .join("\n");
-        let gpus = SystemResources::parse_gpus(&input).expect("failed to parse").expect("no gpus detected");
-        assert_eq!(gpus.model, H100_MODEL);
+        let gpus = SystemResources::parse_gpus(&NvidiaAccelerator, &input)
+            .expect("failed to parse")
+            .expect("no gpus detected");
+        assert_eq!(gpus.vendor, AcceleratorVendor::Nvidia);
+        assert_eq!(gpus.model, "H100");
         assert_eq!(gpus.addresses, &["01:00.0".into(), "01:00.1".into()]);
+        assert!(gpus.confidential_computing());
+    }
+
+    #[test]
+    fn parse_amd_instinct() {
+        let input = [
+            "03:00.0 Processing accelerators: Advanced Micro Devices, Inc. [AMD/ATI] Aqua Vanjaram [Instinct MI300X]",
+            "23:00.0 Processing accelerators: Advanced Micro Devices, Inc. [AMD/ATI] Aqua Vanjaram [Instinct MI300X]",
+        ]
+        .join("\n");
+        let gpus = SystemResources::parse_gpus(&AmdInstinctAccelerator, &input)
+            .expect("failed to parse")
+            .expect("no gpus detected");
+        assert_eq!(gpus.vendor, AcceleratorVendor::Amd);
+        assert_eq!(gpus.model, "MI300X");
+        assert!(!gpus.confidential_computing());
+
+        assert!(SystemResources::parse_gpus(&AmdInstinctAccelerator, "").expect("failed to parse").is_none());
+    }
+
+    #[test]
+    fn parse_mixed_models() {
+        let input = [
+            "01:00.0 3D controller: NVIDIA Corporation GH100 [H100 PCIe] (rev a1)",
+            "02:00.0 3D controller: NVIDIA Corporation GA100 [A100 PCIe 40GB] (rev a1)",
+        ]
+        .join("\n");
+        SystemResources::parse_gpus(&NvidiaAccelerator, &input).expect_err("unsupported GPU accepted");
+    }
+
+    #[rstest]
+    #[case::ipv4_only(false, None)]
+    #[case::dual_stack(true, Some("2a01:4f8::1".parse().unwrap()))]
+    fn select_public_ips(#[case] ipv6: bool, #[case] expected_ipv6: Option<Ipv6Addr>) {
+        let addrs = [
+            "127.0.0.1",
+            "192.168.1.10",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "2001:db8::1",
+            "1.2.3.4",
+            "2a01:4f8::1",
+            "5.6.7.8",
+            "2a01:4f8::2",
+        ];
+        let ips = PublicIps::select(addrs.into_iter().map(|a| a.parse().unwrap()), ipv6);
+        assert_eq!(ips, PublicIps { ipv4: Some(Ipv4Addr::new(1, 2, 3, 4)), ipv6: expected_ipv6 });
+    }
+
+    #[test]
+    fn select_ipv6_only() {
+        let ips = PublicIps::select(["10.0.0.1".parse().unwrap(), "2a01:4f8::1".parse().unwrap()], true);
+        assert_eq!(ips, PublicIps { ipv4: None, ipv6: Some("2a01:4f8::1".parse().unwrap()) });
+        assert!(!ips.is_empty());
     }
 
     #[test]
@@ -369,8 +818,15 @@ mod tests {
     #[tokio::test]
     async fn adjust_gpus() {
         let mut resources = SystemResources::gather(Default::default()).await.expect("failed to gather");
-        resources.gpus = Some(Gpus { model: "foo".into(), addresses: vec!["aa".into(), "bb".into()] });
-        let workloads = vec![make_workload("a.com", &["bb".into()]), make_workload("b.com", &["cc".into()])];
+        resources.gpus = Some(Gpus {
+            vendor: AcceleratorVendor::Nvidia,
+            model: "foo".into(),
+            addresses: vec!["aa".into(), "bb".into()],
+        });
+        let workloads = vec![
+            Workload { domain: "a.com".into(), gpus: vec!["bb".into()], ..make_workload() },
+            Workload { domain: "b.com".into(), gpus: vec!["cc".into()], ..make_workload() },
+        ];
         let db = SqliteDb::connect("sqlite://:memory:").await.expect("failed to create db");
         let provider = SqliteRepositoryProvider::new(db);
         {
@@ -390,8 +846,8 @@ mod tests {
     #[tokio::test]
     async fn no_gpus() {
         let mut resources = SystemResources::gather(Default::default()).await.expect("failed to gather");
-        resources.gpus = Some(Gpus { model: "foo".into(), addresses: vec![] });
-        let workloads = vec![make_workload("a.com", &["aa".into()])];
+        resources.gpus = Some(Gpus { vendor: AcceleratorVendor::Nvidia, model: "foo".into(), addresses: vec![] });
+        let workloads = vec![Workload { domain: "a.com".into(), gpus: vec!["aa".into()], ..make_workload() }];
         let db = SqliteDb::connect("sqlite://:memory:").await.expect("failed to create db");
         let provider = SqliteRepositoryProvider::new(db);
         {
@@ -406,8 +862,9 @@ mod tests {
     #[tokio::test]
     async fn same_gpus() {
         let mut resources = SystemResources::gather(Default::default()).await.expect("failed to gather");
-        resources.gpus = Some(Gpus { model: "foo".into(), addresses: vec!["aa".into()] });
-        let workloads = vec![make_workload("a.com", &["aa".into()])];
+        resources.gpus =
+            Some(Gpus { vendor: AcceleratorVendor::Nvidia, model: "foo".into(), addresses: vec!["aa".into()] });
+        let workloads = vec![Workload { domain: "a.com".into(), gpus: vec!["aa".into()], ..make_workload() }];
         let db = SqliteDb::connect("sqlite://:memory:").await.expect("failed to create db");
         let provider = SqliteRepositoryProvider::new(db);
         {
@@ -422,4 +879,82 @@ mod tests {
         let updated_workloads = repo.list().await.expect("failed to list");
         assert_eq!(updated_workloads, workloads);
     }
+
+    #[test]
+    fn overhead_recommendation() {
+        let mut tracker = OverheadTracker::new(2);
+        assert_eq!(tracker.recommend(20), HostReservation::default());
+        tracker.observe(HostOverhead { cpus: 3.5, memory_mb: 1000 });
+        assert!(!tracker.is_full());
+        tracker.observe(HostOverhead { cpus: 1.0, memory_mb: 2000 });
+        assert!(tracker.is_full());
+        assert_eq!(tracker.recommend(20), HostReservation { cpus: 5, memory_mb: 2400 });
+
+        // The first sample falls out of the window.
+        tracker.observe(HostOverhead { cpus: 0.5, memory_mb: 500 });
+        assert_eq!(tracker.recommend(0), HostReservation { cpus: 1, memory_mb: 2000 });
+    }
+
+    #[rstest]
+    #[case::empty("", &[])]
+    #[case::single("3", &[3])]
+    #[case::ranges("0-2,8-9", &[0, 1, 2, 8, 9])]
+    #[case::mixed("0,4-5", &[0, 4, 5])]
+    fn cpu_list(#[case] list: &str, #[case] expected: &[u32]) {
+        assert_eq!(parse_cpu_list(list).expect("invalid list"), expected);
+    }
+
+    #[tokio::test]
+    async fn detect_numa_topology() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        for (node, cpus, memory_kb) in [("node1", "4-5", 2097152), ("node0", "0-3", 4194304)] {
+            let path = dir.path().join(node);
+            fs::create_dir(&path).await.expect("failed to create node");
+            fs::write(path.join("cpulist"), format!("{cpus}\n")).await.expect("failed to write cpus");
+            let meminfo = format!("Node 0 MemTotal:       {memory_kb} kB\nNode 0 MemFree:        1024 kB\n");
+            fs::write(path.join("meminfo"), meminfo).await.expect("failed to write meminfo");
+        }
+        fs::write(dir.path().join("has_cpu"), "0-5").await.expect("failed to write file");
+
+        let topology = NumaTopology::detect(dir.path()).await.expect("detection failed").expect("no topology");
+        let expected = vec![
+            NumaNode { id: 0, cpus: vec![0, 1, 2, 3], memory_mb: 4096 },
+            NumaNode { id: 1, cpus: vec![4, 5], memory_mb: 2048 },
+        ];
+        assert_eq!(topology.nodes, expected);
+
+        let missing = NumaTopology::detect(&dir.path().join("missing")).await.expect("detection failed");
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn numa_allocation() {
+        let topology = NumaTopology {
+            nodes: vec![
+                NumaNode { id: 0, cpus: vec![0, 1, 2, 3], memory_mb: 4096 },
+                NumaNode { id: 1, cpus: vec![4, 5, 6, 7, 8, 9], memory_mb: 8192 },
+            ],
+        };
+        // The first 2 CPUs are left for the host.
+        let allocator = NumaAllocator::new(topology, 2);
+        let (first, second, third) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        // The smallest node that fits is used.
+        let placement = allocator.allocate(first, 2, 1024).expect("first not placed");
+        assert_eq!(placement, NumaPlacement { node: 0, cpus: vec![2, 3] });
+        assert_eq!(placement.cpu_list(), "2,3");
+        let placement = allocator.allocate(second, 4, 2048).expect("second not placed");
+        assert_eq!(placement, NumaPlacement { node: 1, cpus: vec![4, 5, 6, 7] });
+
+        // Placements are stable.
+        assert_eq!(allocator.allocate(first, 2, 1024), Some(NumaPlacement { node: 0, cpus: vec![2, 3] }));
+
+        // Not enough CPUs or memory left on any single node.
+        assert!(allocator.allocate(third, 4, 1024).is_none());
+        assert!(allocator.allocate(third, 1, 8192).is_none());
+
+        allocator.release(second);
+        let placement = allocator.allocate(third, 4, 1024).expect("third not placed");
+        assert_eq!(placement, NumaPlacement { node: 1, cpus: vec![4, 5, 6, 7] });
+    }
 }
@@ -0,0 +1,106 @@
+use crate::clients::attester::AttesterRequestError;
+use crate::routes::{AppState, Json, Query, RequestHandlerError};
+use crate::services::workload::WorkloadLookupError;
+use axum::extract::{ConnectInfo, Path, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{Extensions, StatusCode};
+use axum::response::{IntoResponse, Response};
+use nilcc_agent_models::attestation::AttestationReportQuery;
+use std::{net::SocketAddr, time::Instant};
+use strum::EnumDiscriminants;
+use tracing::{error, info};
+
+/// Get the attestation report for the workload running under a domain.
+///
+/// This forwards the request to the workload's attester and returns its response as is. It doesn't require
+/// authentication and is rate limited per client address.
+#[utoipa::path(
+    get,
+    path = "/attestation/{workload_domain}",
+    operation_id = "workload_attestation",
+    tag = "attestation",
+    security(()),
+    params(
+        ("workload_domain" = String, Path, description = "The workload's domain"),
+        AttestationReportQuery,
+    ),
+    responses(
+        (status = 200, description = "The attestation report, as returned by the workload's attester"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "No workload uses this domain", body = RequestHandlerError),
+        (status = 412, description = "The workload is not running", body = RequestHandlerError),
+        (status = 429, description = "Too many requests were made by this client", body = RequestHandlerError),
+        (status = 502, description = "The workload's attester could not be reached", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<String>,
+    extensions: Extensions,
+    query: Query<AttestationReportQuery>,
+) -> Result<Response, AttestationHandlerError> {
+    // Requests made over unix sockets have no address so they all share the same limit.
+    let client = extensions.get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip().to_string()).unwrap_or_default();
+    if !state.attestation_rate_limiter.allow(&client, Instant::now()) {
+        return Err(AttestationHandlerError::RateLimited);
+    }
+
+    let domain = path.0;
+    let workloads = state.services.workload.list_workloads().await?;
+    let workload =
+        workloads.into_iter().find(|w| w.domain == domain).ok_or(AttestationHandlerError::WorkloadNotFound)?;
+    if !workload.enabled {
+        return Err(AttestationHandlerError::WorkloadNotRunning);
+    }
+    info!("Fetching attestation report for workload {} on behalf of '{client}'", workload.id);
+    let report = state.clients.attester.fetch_report(&domain, workload.https_port(), query.include_boot_log).await?;
+    Ok(([(CONTENT_TYPE, "application/json")], report).into_response())
+}
+
+#[derive(EnumDiscriminants)]
+pub(crate) enum AttestationHandlerError {
+    Internal(String),
+    WorkloadNotFound,
+    WorkloadNotRunning,
+    RateLimited,
+    Attester(String),
+}
+
+impl From<WorkloadLookupError> for AttestationHandlerError {
+    fn from(e: WorkloadLookupError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<AttesterRequestError> for AttestationHandlerError {
+    fn from(e: AttesterRequestError) -> Self {
+        match e {
+            AttesterRequestError::Http(e) => match e.status() {
+                Some(status) => Self::Attester(format!("attester returned status {}", status.as_u16())),
+                None if e.is_timeout() => Self::Attester("timed out waiting for attester".into()),
+                None => Self::Attester("could not connect to attester".into()),
+            },
+            AttesterRequestError::Internal(e) => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for AttestationHandlerError {
+    fn into_response(self) -> Response {
+        let discriminant = AttestationHandlerErrorDiscriminants::from(&self);
+        let retryable = !matches!(self, Self::WorkloadNotFound);
+        let (code, message) = match self {
+            Self::Internal(e) => {
+                error!("Failed to process request: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".to_string())
+            }
+            Self::WorkloadNotFound => (StatusCode::NOT_FOUND, "workload not found".into()),
+            Self::WorkloadNotRunning => (StatusCode::PRECONDITION_FAILED, "workload is not running".into()),
+            Self::RateLimited => (StatusCode::TOO_MANY_REQUESTS, "too many requests".into()),
+            Self::Attester(details) => (StatusCode::BAD_GATEWAY, details),
+        };
+        let response = RequestHandlerError::new(message, format!("{discriminant:?}")).with_retryable(retryable);
+        (code, Json(response)).into_response()
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+/// Limits how many requests are proxied to `cvm-agent` instances concurrently.
+///
+/// There's a global limit as well as one for every workload. Requests that can't be proxied right away are queued for
+/// up to a timeout, after which they are rejected.
+pub struct CvmAgentLimiter {
+    global: Arc<Semaphore>,
+    max_workload_requests: usize,
+    workloads: Mutex<HashMap<Uuid, Arc<Semaphore>>>,
+    queue_timeout: Duration,
+}
+
+impl CvmAgentLimiter {
+    pub fn new(max_requests: usize, max_workload_requests: usize, queue_timeout: Duration) -> Self {
+        Self {
+            global: Arc::new(Semaphore::new(max_requests)),
+            max_workload_requests,
+            workloads: Default::default(),
+            queue_timeout,
+        }
+    }
+
+    /// Wait until a request to a workload's `cvm-agent` can be made.
+    ///
+    /// The returned permit must be held until the request finishes.
+    pub(crate) async fn acquire(&self, workload_id: Uuid) -> Result<CvmAgentPermit, LimitExceeded> {
+        let workload = self.workload_semaphore(workload_id);
+        let acquire = async {
+            // Take the workload's permit first so a busy workload doesn't hold global permits while it waits.
+            let workload = workload.acquire_owned().await?;
+            let global = self.global.clone().acquire_owned().await?;
+            Ok::<_, tokio::sync::AcquireError>(CvmAgentPermit { _global: global, _workload: workload })
+        };
+        match tokio::time::timeout(self.queue_timeout, acquire).await {
+            Ok(Ok(permit)) => Ok(permit),
+            _ => Err(LimitExceeded { retry_after: self.queue_timeout.max(Duration::from_secs(1)) }),
+        }
+    }
+
+    fn workload_semaphore(&self, workload_id: Uuid) -> Arc<Semaphore> {
+        let mut workloads = self.workloads.lock().expect("lock poisoned");
+        // Once no request holds or waits for a workload's permits, the map is the only one referencing its semaphore.
+        workloads.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+        workloads.entry(workload_id).or_insert_with(|| Arc::new(Semaphore::new(self.max_workload_requests))).clone()
+    }
+}
+
+/// A permit to make a request to a `cvm-agent`.
+pub(crate) struct CvmAgentPermit {
+    _global: OwnedSemaphorePermit,
+    _workload: OwnedSemaphorePermit,
+}
+
+/// Too many requests are being proxied to `cvm-agent` instances.
+#[derive(Debug, thiserror::Error)]
+#[error("too many concurrent requests to cvm-agent")]
+pub(crate) struct LimitExceeded {
+    /// How long to wait before retrying.
+    pub(crate) retry_after: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUEUE_TIMEOUT: Duration = Duration::from_millis(10);
+
+    #[tokio::test]
+    async fn workload_limit() {
+        let limiter = CvmAgentLimiter::new(10, 2, QUEUE_TIMEOUT);
+        let workload = Uuid::new_v4();
+        let first = limiter.acquire(workload).await.expect("first request rejected");
+        let _second = limiter.acquire(workload).await.expect("second request rejected");
+        assert!(limiter.acquire(workload).await.is_err());
+
+        // Other workloads have their own limits.
+        let _other = limiter.acquire(Uuid::new_v4()).await.expect("other workload rejected");
+
+        // Finishing a request lets the next one through.
+        drop(first);
+        let _third = limiter.acquire(workload).await.expect("third request rejected");
+    }
+
+    #[tokio::test]
+    async fn global_limit() {
+        let limiter = CvmAgentLimiter::new(1, 2, QUEUE_TIMEOUT);
+        let permit = limiter.acquire(Uuid::new_v4()).await.expect("first request rejected");
+        let error = limiter.acquire(Uuid::new_v4()).await.err().expect("request not rejected");
+        assert_eq!(error.retry_after, Duration::from_secs(1));
+        drop(permit);
+        limiter.acquire(Uuid::new_v4()).await.expect("request rejected");
+    }
+
+    #[tokio::test]
+    async fn queued_request() {
+        let limiter = Arc::new(CvmAgentLimiter::new(10, 1, Duration::from_secs(5)));
+        let workload = Uuid::new_v4();
+        let permit = limiter.acquire(workload).await.expect("first request rejected");
+        let queued = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(workload).await.is_ok() }
+        });
+        drop(permit);
+        assert!(queued.await.expect("task panicked"));
+    }
+
+    #[tokio::test]
+    async fn unused_workloads_removed() {
+        let limiter = CvmAgentLimiter::new(10, 1, QUEUE_TIMEOUT);
+        let permit = limiter.acquire(Uuid::new_v4()).await.expect("request rejected");
+        drop(permit);
+        let _permit = limiter.acquire(Uuid::new_v4()).await.expect("request rejected");
+        assert_eq!(limiter.workloads.lock().expect("lock poisoned").len(), 1);
+    }
+}
@@ -1,26 +1,42 @@
 #![allow(clippy::disallowed_types)]
 
 use crate::auth::AuthLayer;
+use crate::clients::attester::AttesterClient;
 use crate::clients::cvm_agent::CvmAgentClient;
-use crate::config::ResourceLimitsConfig;
-use crate::heartbeat_verifier::VerifierKeys;
+use crate::config::{ApiTokenConfig, ResourceLimitsConfig};
+use crate::services::backup::BackupService;
+use crate::services::image_policy::ImagePolicyChecker;
 use crate::services::upgrade::UpgradeService;
+use crate::services::upload::UploadService;
+use crate::services::usage::UsageService;
+use crate::services::verifier_keys::VerifierKeyService;
 use crate::services::workload::WorkloadService;
-use axum::Router;
+use crate::zerossl::ZeroSslAccounts;
+use axum::extract::DefaultBodyLimit;
 use axum::extract::rejection::QueryRejection;
 use axum::extract::{FromRequest, rejection::JsonRejection};
 use axum::extract::{FromRequestParts, Request};
 use axum::http::StatusCode;
 use axum::http::request::Parts;
 use axum::response::IntoResponse;
-use axum::routing::{get, post};
+use axum::routing::{get, post, put};
+use axum::{Router, middleware};
 use nilcc_agent_models::errors::RequestHandlerError;
+use nilcc_agent_models::system::{AgentFeatures, GpuInfo};
+use nilcc_agent_models::workloads::create::ImagePolicyMode;
+use nilcc_agent_models::workloads::uploads::MAX_UPLOAD_CHUNK_SIZE;
+use rate_limit::RateLimiter;
 use serde::Serialize;
 use std::ops::Deref;
 use std::sync::Arc;
 use tower::ServiceBuilder;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 use validator::{Validate, ValidationErrors, ValidationErrorsKind};
 
+pub mod attestation;
+pub mod limits;
+pub(crate) mod openapi;
 pub(crate) mod system;
 pub(crate) mod workloads;
 
@@ -28,11 +44,17 @@ pub(crate) mod workloads;
 pub struct Services {
     pub workload: Arc<dyn WorkloadService>,
     pub upgrade: Arc<dyn UpgradeService>,
+    pub backup: Arc<dyn BackupService>,
+    pub usage: Arc<dyn UsageService>,
+    pub verifier_key: Arc<dyn VerifierKeyService>,
+    pub image_policy: Option<Arc<dyn ImagePolicyChecker>>,
+    pub upload: Arc<dyn UploadService>,
 }
 
 #[derive(Clone)]
 pub struct Clients {
     pub cvm_agent: Arc<dyn CvmAgentClient>,
+    pub attester: Arc<dyn AttesterClient>,
 }
 
 #[derive(Clone)]
@@ -41,42 +63,105 @@ pub struct AppState {
     pub clients: Clients,
     pub resource_limits: ResourceLimitsConfig,
     pub agent_domain: String,
-    pub verifier_keys: VerifierKeys,
+    pub image_policy_mode: ImagePolicyMode,
+    pub registry_mirrors: Vec<String>,
+    pub zerossl_accounts: ZeroSslAccounts,
+    pub attestation_rate_limiter: Arc<RateLimiter>,
+    pub cvm_agent_limiter: Arc<limits::CvmAgentLimiter>,
+    pub capabilities: Arc<AgentCapabilities>,
 }
 
-pub fn build_router(state: AppState, token: String) -> Router {
-    Router::new().route("/health", get(health)).nest(
-        "/api/v1",
-        Router::new()
-            .nest(
-                "/system",
-                Router::new()
-                    .route("/artifacts/install", post(system::artifacts::install::handler))
-                    .route("/artifacts/versions", get(system::artifacts::versions::handler))
-                    .route("/artifacts/changelog", get(system::artifacts::changelog::handler))
-                    .route("/artifacts/cleanup", post(system::artifacts::cleanup::handler))
-                    .route("/agent/upgrade", post(system::agent::upgrade::handler))
-                    .route("/agent/version", get(system::agent::version::handler))
-                    .route("/verifier/keys", get(system::verifier::keys::handler)),
-            )
-            .nest(
-                "/workloads",
-                Router::new()
-                    .route("/create", post(workloads::create::handler))
-                    .route("/delete", post(workloads::delete::handler))
-                    .route("/restart", post(workloads::restart::handler))
-                    .route("/stop", post(workloads::stop::handler))
-                    .route("/start", post(workloads::start::handler))
-                    .route("/list", get(workloads::list::handler))
-                    .route("/{workload_id}/health", get(workloads::health::handler))
-                    .route("/{workload_id}/containers/list", get(workloads::containers::list::handler))
-                    .route("/{workload_id}/containers/logs", get(workloads::containers::logs::handler))
-                    .route("/{workload_id}/system/logs", get(workloads::system::logs::handler))
-                    .route("/{workload_id}/system/stats", get(workloads::system::stats::handler)),
-            )
-            .with_state(state)
-            .layer(ServiceBuilder::new().layer(AuthLayer::new(token))),
-    )
+/// The agent's capabilities, which are determined on startup and don't change while it runs.
+#[derive(Clone, Debug)]
+pub struct AgentCapabilities {
+    pub vm_types: Vec<String>,
+    pub snp: bool,
+    pub gpus: Option<GpuInfo>,
+    pub features: AgentFeatures,
+}
+
+/// Build the API router.
+///
+/// If no tokens are provided, API requests are not authenticated. This should only be used for listeners that are
+/// protected by other means, like a unix socket's file permissions. Otherwise every request needs to present one of
+/// the tokens, and that token's scope must allow the operation being performed. The OpenAPI spec and Swagger UI served
+/// under `/api/docs` and the rate limited `/attestation/{workload_domain}` endpoint never require authentication.
+pub fn build_router(state: AppState, tokens: Option<Vec<ApiTokenConfig>>) -> Router {
+    let attestation =
+        Router::new().route("/attestation/{workload_domain}", get(attestation::handler)).with_state(state.clone());
+    // Endpoints that operate on a single workload, which are only accessible to the callers that can access it.
+    let workload_routes = Router::new()
+        .route("/{workload_id}/details", get(workloads::details::handler))
+        .route("/{workload_id}/health", get(workloads::health::handler))
+        .route("/{workload_id}/tls", get(workloads::tls::handler))
+        .route("/{workload_id}/wait", get(workloads::wait::handler))
+        .route("/{workload_id}/progress", get(workloads::progress::handler))
+        .route("/{workload_id}/env-vars", post(workloads::env_vars::handler))
+        .route("/{workload_id}/files", post(workloads::files::handler))
+        .route("/{workload_id}/docker-credentials", post(workloads::docker_credentials::handler))
+        .route("/{workload_id}/proxy-timeouts", post(workloads::proxy_timeouts::handler))
+        .route("/{workload_id}/containers/compose-state", get(workloads::containers::compose_state::handler))
+        .route("/{workload_id}/containers/list", get(workloads::containers::list::handler))
+        .route("/{workload_id}/containers/logs", get(workloads::containers::logs::handler))
+        .route("/{workload_id}/containers/restart", post(workloads::containers::restart::handler))
+        .route("/{workload_id}/containers/stats", get(workloads::containers::stats::handler))
+        .route("/{workload_id}/port-forward", get(workloads::containers::port_forward::handler))
+        .route("/{workload_id}/console", get(workloads::console::handler))
+        .route("/{workload_id}/jobs/list", get(workloads::jobs::list::handler))
+        .route("/{workload_id}/jobs/logs", get(workloads::jobs::logs::handler))
+        .route("/{workload_id}/system/logs", get(workloads::system::logs::handler))
+        .route("/{workload_id}/system/stats", get(workloads::system::stats::handler))
+        .route("/{workload_id}/usage", get(workloads::usage::summary::handler))
+        .route("/{workload_id}/usage/export", get(workloads::usage::export::handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), workloads::require_workload_access));
+    let api = Router::new()
+        .nest(
+            "/system",
+            Router::new()
+                .route("/artifacts/install", post(system::artifacts::install::handler))
+                .route("/artifacts/versions", get(system::artifacts::versions::handler))
+                .route("/artifacts/changelog", get(system::artifacts::changelog::handler))
+                .route("/artifacts/cleanup", post(system::artifacts::cleanup::handler))
+                .route("/artifacts/gc/preview", get(system::artifacts::gc_preview::handler))
+                .route("/agent/upgrade", post(system::agent::upgrade::handler))
+                .route("/agent/rollback", post(system::agent::rollback::handler))
+                .route("/agent/version", get(system::agent::version::handler))
+                .route("/backup", post(system::backup::handler))
+                .route("/capacity", get(system::capacity::handler))
+                .route("/info", get(system::info::handler))
+                .route("/verifier/keys", get(system::verifier::keys::handler))
+                .route("/verifier/keys/rotate", post(system::verifier::rotate::handler))
+                .route("/verifier/keys/retire", post(system::verifier::retire::handler))
+                .route("/zerossl/accounts", get(system::zerossl::accounts::handler)),
+        )
+        .nest(
+            "/workloads",
+            Router::new()
+                .route("/change-domain", post(workloads::change_domain::handler))
+                .route("/create", post(workloads::create::handler))
+                .route("/delete", post(workloads::delete::handler))
+                .route("/restart", post(workloads::restart::handler))
+                .route("/stop", post(workloads::stop::handler))
+                .route("/start", post(workloads::start::handler))
+                .route("/pause", post(workloads::pause::handler))
+                .route("/resume", post(workloads::resume::handler))
+                .route("/list", get(workloads::list::handler))
+                .route("/files/upload", post(workloads::uploads::create::handler))
+                .route(
+                    "/files/upload/{upload_id}",
+                    get(workloads::uploads::status::handler)
+                        .put(workloads::uploads::chunk::handler)
+                        .layer(DefaultBodyLimit::max(MAX_UPLOAD_CHUNK_SIZE)),
+                )
+                .merge(workload_routes),
+        )
+        .with_state(state);
+    let api = match tokens {
+        Some(tokens) => api.layer(ServiceBuilder::new().layer(AuthLayer::new(tokens))),
+        None => api,
+    };
+    let docs = SwaggerUi::new("/api/docs").url("/api/docs/openapi.json", openapi::ApiDoc::openapi());
+    Router::new().route("/health", get(health)).merge(docs).merge(attestation).nest("/api/v1", api)
 }
 
 async fn health() -> impl IntoResponse {
@@ -0,0 +1,112 @@
+use super::{attestation, system, workloads};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+};
+
+/// The OpenAPI spec for the agent's API.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "nilcc-agent", description = "The API used to manage workloads running in a nilcc agent."),
+    paths(
+        system::artifacts::install::handler,
+        system::artifacts::versions::handler,
+        system::artifacts::changelog::handler,
+        system::artifacts::cleanup::handler,
+        system::artifacts::gc_preview::handler,
+        system::agent::upgrade::handler,
+        system::agent::rollback::handler,
+        system::agent::version::handler,
+        system::backup::handler,
+        system::capacity::handler,
+        system::info::handler,
+        system::verifier::keys::handler,
+        system::verifier::rotate::handler,
+        system::verifier::retire::handler,
+        system::zerossl::accounts::handler,
+        workloads::change_domain::handler,
+        workloads::create::handler,
+        workloads::delete::handler,
+        workloads::env_vars::handler,
+        workloads::files::handler,
+        workloads::docker_credentials::handler,
+        workloads::proxy_timeouts::handler,
+        workloads::uploads::create::handler,
+        workloads::uploads::chunk::handler,
+        workloads::uploads::status::handler,
+        workloads::restart::handler,
+        workloads::stop::handler,
+        workloads::start::handler,
+        workloads::pause::handler,
+        workloads::resume::handler,
+        workloads::list::handler,
+        workloads::details::handler,
+        workloads::health::handler,
+        workloads::tls::handler,
+        workloads::wait::handler,
+        workloads::progress::handler,
+        workloads::containers::compose_state::handler,
+        workloads::containers::list::handler,
+        workloads::containers::logs::handler,
+        workloads::containers::restart::handler,
+        workloads::containers::stats::handler,
+        workloads::containers::port_forward::handler,
+        workloads::console::handler,
+        workloads::jobs::list::handler,
+        workloads::jobs::logs::handler,
+        workloads::system::logs::handler,
+        workloads::system::stats::handler,
+        workloads::usage::summary::handler,
+        workloads::usage::export::handler,
+        attestation::handler,
+    ),
+    modifiers(&TokenSecurity),
+    security(("token" = [])),
+    tags(
+        (name = "system", description = "Agent, artifacts and key management."),
+        (name = "workloads", description = "Workload management."),
+        (name = "attestation", description = "Unauthenticated access to workload attestation reports."),
+    )
+)]
+pub struct ApiDoc;
+
+struct TokenSecurity;
+
+impl Modify for TokenSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let scheme = HttpBuilder::new()
+            .scheme(HttpAuthScheme::Bearer)
+            .description(Some("The agent's API token. Not required when using the agent's unix socket."))
+            .build();
+        components.add_security_scheme("token", SecurityScheme::Http(scheme));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn spec() {
+        let spec = ApiDoc::openapi();
+        let operation_ids: Vec<_> = spec
+            .paths
+            .paths
+            .values()
+            .flat_map(|item| [&item.get, &item.post, &item.put])
+            .flatten()
+            .map(|operation| operation.operation_id.clone().expect("no operation id"))
+            .collect();
+        let unique: HashSet<_> = operation_ids.iter().collect();
+        assert_eq!(unique.len(), operation_ids.len(), "duplicate operation ids: {operation_ids:?}");
+        assert_eq!(operation_ids.len(), 49);
+
+        let schemas = &spec.components.as_ref().expect("no components").schemas;
+        for schema in ["CreateWorkloadRequest", "WorkloadSummary", "RequestHandlerError", "HealthResponse"] {
+            assert!(schemas.contains_key(schema), "{schema} not found");
+        }
+        serde_json::to_string(&spec).expect("failed to serialize");
+    }
+}
@@ -1,2 +1,3 @@
+pub(crate) mod rollback;
 pub(crate) mod upgrade;
 pub(crate) mod version;
@@ -0,0 +1,22 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::upgrade::RollbackError,
+};
+use axum::extract::State;
+
+/// Roll the agent back to the version it was running before its last upgrade.
+#[utoipa::path(
+    post,
+    path = "/api/v1/system/agent/rollback",
+    operation_id = "rollback_agent",
+    tag = "system",
+    responses(
+        (status = 200, description = "The agent is being rolled back"),
+        (status = 412, description = "An upgrade is running or there's no backup", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(state: State<AppState>) -> Result<Json<()>, RollbackError> {
+    state.services.upgrade.rollback_agent().await?;
+    Ok(Json(()))
+}
@@ -1,10 +1,24 @@
 use crate::{
-    routes::{AppState, Json},
+    routes::{AppState, Json, RequestHandlerError},
     services::upgrade::UpgradeError,
 };
 use axum::extract::State;
 use nilcc_agent_models::system::UpgradeRequest;
 
+/// Upgrade the agent to a new version.
+#[utoipa::path(
+    post,
+    path = "/api/v1/system/agent/upgrade",
+    operation_id = "upgrade_agent",
+    tag = "system",
+    request_body = UpgradeRequest,
+    responses(
+        (status = 200, description = "The agent is being upgraded"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 412, description = "An upgrade is running or the version exists", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(state: State<AppState>, request: Json<UpgradeRequest>) -> Result<Json<()>, UpgradeError> {
     let UpgradeRequest { version } = request.0;
     state.services.upgrade.upgrade_agent(version).await?;
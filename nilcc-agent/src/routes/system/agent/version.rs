@@ -6,21 +6,32 @@ use axum::response::Response;
 use axum::{Json, extract::State};
 use nilcc_agent_models::system::{AgentVersionResponse, LastUpgrade};
 
+/// Get the agent's version.
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/agent/version",
+    operation_id = "agent_version",
+    tag = "system",
+    responses(
+        (status = 200, body = AgentVersionResponse),
+    )
+)]
 pub(crate) async fn handler(state: State<AppState>) -> Result<Json<AgentVersionResponse>, Response> {
     let version = state.services.upgrade.agent_version();
     let last_upgrade = match state.services.upgrade.agent_upgrade_state().await {
         UpgradeState::None => None,
         UpgradeState::Upgrading { metadata } => {
-            let UpgradeMetadata { version, started_at, .. } = metadata;
-            Some(LastUpgrade { version, started_at, state: nilcc_agent_models::system::UpgradeState::InProgress })
+            let UpgradeMetadata { version, started_at, sha256, .. } = metadata;
+            let state = nilcc_agent_models::system::UpgradeState::InProgress;
+            Some(LastUpgrade { version, started_at, state, sha256: sha256.map(hex::encode) })
         }
         UpgradeState::Done { metadata, finished_at, error } => {
-            let UpgradeMetadata { version, started_at, .. } = metadata;
+            let UpgradeMetadata { version, started_at, sha256, .. } = metadata;
             let state = match error {
                 Some(error) => nilcc_agent_models::system::UpgradeState::Error { error, finished_at },
                 None => nilcc_agent_models::system::UpgradeState::Success { finished_at },
             };
-            Some(LastUpgrade { version, started_at, state })
+            Some(LastUpgrade { version, started_at, state, sha256: sha256.map(hex::encode) })
         }
     };
     Ok(Json(AgentVersionResponse { version, last_upgrade }))
@@ -12,6 +12,17 @@ use nilcc_agent_models::{
 };
 use tracing::error;
 
+/// Get the artifacts install/uninstall changelog.
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/artifacts/changelog",
+    operation_id = "artifacts_changelog",
+    tag = "system",
+    responses(
+        (status = 200, body = ArtifactChangelogResponse),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(state: State<AppState>) -> Result<Json<ArtifactChangelogResponse>, Response> {
     let entries = state.services.upgrade.artifacts_changelog().await.map_err(|e| {
         error!("Failed to get artifacts changelog: {e:#}");
@@ -1,10 +1,21 @@
 use crate::{
-    routes::{AppState, Json},
+    routes::{AppState, Json, RequestHandlerError},
     services::upgrade::CleanupError,
 };
 use axum::extract::State;
 use nilcc_agent_models::system::ArtifactsCleanupResponse;
 
+/// Delete artifacts versions that aren't used by any workload.
+#[utoipa::path(
+    post,
+    path = "/api/v1/system/artifacts/cleanup",
+    operation_id = "cleanup_artifacts",
+    tag = "system",
+    responses(
+        (status = 200, body = ArtifactsCleanupResponse),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(state: State<AppState>) -> Result<Json<ArtifactsCleanupResponse>, CleanupError> {
     let versions_deleted = state.services.upgrade.cleanup_artifacts().await?;
     let response = ArtifactsCleanupResponse { versions_deleted };
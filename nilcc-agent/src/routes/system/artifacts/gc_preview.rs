@@ -0,0 +1,22 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::upgrade::CleanupError,
+};
+use axum::extract::State;
+use nilcc_agent_models::system::ArtifactsGcPreviewResponse;
+
+/// Get the artifacts versions the garbage collection policy would delete, without deleting them.
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/artifacts/gc/preview",
+    operation_id = "preview_artifacts_gc",
+    tag = "system",
+    responses(
+        (status = 200, body = ArtifactsGcPreviewResponse),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(state: State<AppState>) -> Result<Json<ArtifactsGcPreviewResponse>, CleanupError> {
+    let versions = state.services.upgrade.collect_artifacts(true).await?;
+    Ok(Json(ArtifactsGcPreviewResponse { versions }))
+}
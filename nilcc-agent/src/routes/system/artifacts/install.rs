@@ -1,10 +1,24 @@
 use crate::{
-    routes::{AppState, Json},
+    routes::{AppState, Json, RequestHandlerError},
     services::upgrade::UpgradeError,
 };
 use axum::extract::State;
 use nilcc_agent_models::system::InstallArtifactVersionRequest;
 
+/// Install an artifacts version.
+#[utoipa::path(
+    post,
+    path = "/api/v1/system/artifacts/install",
+    operation_id = "install_artifacts",
+    tag = "system",
+    request_body = InstallArtifactVersionRequest,
+    responses(
+        (status = 200, description = "The artifacts version is being installed"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 412, description = "An upgrade is running or the version exists", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
     request: Json<InstallArtifactVersionRequest>,
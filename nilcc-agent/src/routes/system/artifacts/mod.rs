@@ -1,4 +1,5 @@
 pub(crate) mod changelog;
 pub(crate) mod cleanup;
+pub(crate) mod gc_preview;
 pub(crate) mod install;
 pub(crate) mod versions;
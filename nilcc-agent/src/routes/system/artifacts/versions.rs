@@ -10,6 +10,17 @@ use nilcc_agent_models::{
 };
 use tracing::error;
 
+/// Get the installed artifacts versions.
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/artifacts/versions",
+    operation_id = "artifacts_versions",
+    tag = "system",
+    responses(
+        (status = 200, body = ArtifactVersionsResponse),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(state: State<AppState>) -> Result<Json<ArtifactVersionsResponse>, Response> {
     let versions = state.services.upgrade.artifacts_versions().await.map_err(|e| {
         error!("Failed to get current artifacts version: {e:#}");
@@ -20,7 +31,12 @@ pub(crate) async fn handler(state: State<AppState>) -> Result<Json<ArtifactVersi
         UpgradeState::None => None,
         UpgradeState::Upgrading { metadata } => {
             let UpgradeMetadata { version, started_at, .. } = metadata;
-            Some(LastUpgrade { version, started_at, state: nilcc_agent_models::system::UpgradeState::InProgress })
+            Some(LastUpgrade {
+                version,
+                started_at,
+                state: nilcc_agent_models::system::UpgradeState::InProgress,
+                sha256: None,
+            })
         }
         UpgradeState::Done { metadata, finished_at, error } => {
             let UpgradeMetadata { version, started_at, .. } = metadata;
@@ -28,7 +44,7 @@ pub(crate) async fn handler(state: State<AppState>) -> Result<Json<ArtifactVersi
                 Some(error) => nilcc_agent_models::system::UpgradeState::Error { error, finished_at },
                 None => nilcc_agent_models::system::UpgradeState::Success { finished_at },
             };
-            Some(LastUpgrade { version, started_at, state })
+            Some(LastUpgrade { version, started_at, state, sha256: None })
         }
     };
     Ok(Json(ArtifactVersionsResponse { versions, last_upgrade }))
@@ -0,0 +1,74 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::backup::BackupError,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{
+        StatusCode,
+        header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+    },
+    response::{IntoResponse, Response},
+};
+use std::io;
+use tokio::{fs::File, io::AsyncReadExt, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{error, warn};
+
+/// The size of the chunks the backup is streamed in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Create a backup of the agent's database along with a manifest of the workloads' state files.
+///
+/// The backup is returned as a tarball and can be restored on a reprovisioned host by pointing `db.restore_from` at
+/// it in the agent's config.
+#[utoipa::path(
+    post,
+    path = "/api/v1/system/backup",
+    operation_id = "create_backup",
+    tag = "system",
+    responses(
+        (status = 200, description = "The backup tarball", content_type = "application/x-tar"),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(state: State<AppState>) -> Result<Response, BackupError> {
+    let archive = state.services.backup.create_backup().await?;
+    let mut file = File::open(&archive.path).await.map_err(|e| BackupError(e.into()))?;
+    let archive_size = archive.size;
+    let (sender, receiver) = mpsc::channel::<io::Result<Bytes>>(4);
+    tokio::spawn(async move {
+        // Keep the archive alive until it's fully sent since dropping it deletes it.
+        let _archive = archive;
+        loop {
+            let mut buffer = Vec::with_capacity(CHUNK_SIZE);
+            let chunk = match file.read_buf(&mut buffer).await {
+                Ok(0) => break,
+                Ok(_) => Ok(Bytes::from(buffer)),
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            if sender.send(chunk).await.is_err() {
+                warn!("Client disconnected while backup was being sent");
+                break;
+            }
+            if failed {
+                break;
+            }
+        }
+    });
+    let headers = [
+        (CONTENT_TYPE, "application/x-tar".to_string()),
+        (CONTENT_LENGTH, archive_size.to_string()),
+        (CONTENT_DISPOSITION, "attachment; filename=\"nilcc-agent-backup.tar\"".to_string()),
+    ];
+    Ok((headers, Body::from_stream(ReceiverStream::new(receiver))).into_response())
+}
+
+impl IntoResponse for BackupError {
+    fn into_response(self) -> Response {
+        error!("Failed to create backup: {self}");
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(RequestHandlerError::internal())).into_response()
+    }
+}
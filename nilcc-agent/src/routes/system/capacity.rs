@@ -0,0 +1,34 @@
+use crate::routes::{AppState, Json};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use nilcc_agent_models::{errors::RequestHandlerError, system::SystemCapacityResponse};
+use tracing::error;
+
+/// Get the resources available for new workloads and the largest workloads that fit in them.
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/capacity",
+    operation_id = "system_capacity",
+    tag = "system",
+    responses(
+        (status = 200, body = SystemCapacityResponse),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(state: State<AppState>) -> Result<Json<SystemCapacityResponse>, Response> {
+    let mut capacity = state.services.workload.capacity().await.map_err(|e| {
+        error!("Failed to compute capacity: {e}");
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(RequestHandlerError::internal())).into_response()
+    })?;
+    // No single workload can go over the configured limits, regardless of how much is free.
+    let limits = &state.resource_limits;
+    for shape in [&mut capacity.largest_workload, &mut capacity.largest_preempting_workload].into_iter().flatten() {
+        shape.cpus = shape.cpus.min(limits.cpus);
+        shape.memory_mb = shape.memory_mb.min(limits.memory_mb);
+        shape.disk_space_gb = shape.disk_space_gb.min(limits.disk_space_gb);
+    }
+    Ok(Json(capacity))
+}
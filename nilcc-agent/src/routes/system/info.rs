@@ -0,0 +1,47 @@
+use crate::{
+    routes::{AgentCapabilities, AppState},
+    version,
+};
+use axum::{Json, extract::State, response::IntoResponse};
+use axum::{http::StatusCode, response::Response};
+use nilcc_agent_models::{
+    errors::RequestHandlerError,
+    system::{ResourceLimits, SystemInfoResponse},
+};
+use tracing::error;
+
+/// Get the agent's build info and capabilities.
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/info",
+    operation_id = "system_info",
+    tag = "system",
+    responses(
+        (status = 200, body = SystemInfoResponse),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(state: State<AppState>) -> Result<Json<SystemInfoResponse>, Response> {
+    let artifacts_versions = state.services.upgrade.artifacts_versions().await.map_err(|e| {
+        error!("Failed to get current artifacts version: {e:#}");
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(RequestHandlerError::new("internal server error", "INTERNAL")))
+            .into_response()
+    })?;
+    let AgentCapabilities { vm_types, snp, gpus, features } = state.capabilities.as_ref().clone();
+    // Limits that aren't configured default to the maximum value.
+    let limit = |value: u32| (value != u32::MAX).then_some(value);
+    let resource_limits = ResourceLimits {
+        cpus: limit(state.resource_limits.cpus),
+        memory_mb: limit(state.resource_limits.memory_mb),
+        disk_space_gb: limit(state.resource_limits.disk_space_gb),
+    };
+    Ok(Json(SystemInfoResponse {
+        build: version::build_info(),
+        vm_types,
+        snp,
+        gpus,
+        artifacts_versions,
+        features,
+        resource_limits,
+    }))
+}
@@ -1,3 +1,7 @@
 pub(crate) mod agent;
 pub(crate) mod artifacts;
+pub(crate) mod backup;
+pub(crate) mod capacity;
+pub(crate) mod info;
 pub(crate) mod verifier;
+pub(crate) mod zerossl;
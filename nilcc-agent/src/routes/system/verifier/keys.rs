@@ -1,29 +1,51 @@
-use crate::routes::{AppState, Json};
-use axum::{
-    extract::State,
-    http::StatusCode,
-    response::{IntoResponse, Response},
+use crate::{
+    routes::{AppState, Json},
+    services::verifier_keys::{VerifierKeyServiceError, VerifierKeyStatus},
+};
+use axum::extract::State;
+use nilcc_agent_models::{
+    errors::RequestHandlerError,
+    system::{VerifierKey, VerifierKeyBalance},
 };
-use nilcc_agent_models::{errors::RequestHandlerError, system::VerifierKey};
-use std::collections::HashSet;
 
-pub(crate) async fn handler(state: State<AppState>) -> Result<Json<Vec<VerifierKey>>, Response> {
-    let workloads = state
-        .services
-        .workload
-        .list_workloads()
-        .await
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(RequestHandlerError::internal())).into_response())?;
-    let active_keys: HashSet<_> =
-        workloads.into_iter().flat_map(|w| w.heartbeat.and_then(|h| h.wallet_public_key)).collect();
-    let keys = state
-        .verifier_keys
-        .public_keys()
+/// Get the public keys used to submit verifier heartbeats.
+///
+/// Keys assigned to a workload include the balance and last heartbeat reported by the workload's CVM, when available.
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/verifier/keys",
+    operation_id = "verifier_keys",
+    tag = "system",
+    responses(
+        (status = 200, body = Vec<VerifierKey>),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(state: State<AppState>) -> Result<Json<Vec<VerifierKey>>, VerifierKeyServiceError> {
+    let keys = state.services.verifier_key.list_keys().await?;
+    let keys = keys
         .into_iter()
-        .map(|k| {
-            let public_key = k.public_uncompressed.into();
-            let active = active_keys.contains(k.public.as_slice());
-            VerifierKey { public_key, active }
+        .map(|status| {
+            let VerifierKeyStatus { key, workload_id, heartbeat } = status;
+            let (balance, last_heartbeat_at) = match heartbeat {
+                Some(heartbeat) => {
+                    let balance = heartbeat.balance.map(|balance| VerifierKeyBalance {
+                        eth: balance.eth,
+                        nil: balance.nil,
+                        observed_at: balance.observed_at,
+                    });
+                    (balance, heartbeat.last_heartbeat_at)
+                }
+                None => (None, None),
+            };
+            VerifierKey {
+                public_key: key.public_uncompressed.into(),
+                active: workload_id.is_some(),
+                retired: key.retired,
+                workload_id,
+                balance,
+                last_heartbeat_at,
+            }
         })
         .collect();
     Ok(Json(keys))
@@ -1 +1,31 @@
+use crate::{
+    routes::{Json, RequestHandlerError},
+    services::verifier_keys::{VerifierKeyServiceError, VerifierKeyServiceErrorDiscriminants},
+};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tracing::error;
+
 pub(crate) mod keys;
+pub(crate) mod retire;
+pub(crate) mod rotate;
+
+impl IntoResponse for VerifierKeyServiceError {
+    fn into_response(self) -> Response {
+        let discriminant = VerifierKeyServiceErrorDiscriminants::from(&self);
+        let (code, message) = match self {
+            Self::KeyNotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            Self::KeyNotAssigned | Self::KeyInUse(_) | Self::KeyHeld | Self::KeyRetired | Self::NoMoreKeys => {
+                (StatusCode::PRECONDITION_FAILED, self.to_string())
+            }
+            Self::Internal(e) => {
+                error!("Failed to process request: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
+            }
+        };
+        let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
+        (code, Json(response)).into_response()
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::verifier_keys::VerifierKeyServiceError,
+};
+use axum::extract::State;
+use nilcc_agent_models::system::RetireVerifierKeyRequest;
+
+/// Retire a verifier key so it's never assigned to a workload again.
+///
+/// Keys that are in use need to be rotated instead.
+#[utoipa::path(
+    post,
+    path = "/api/v1/system/verifier/keys/retire",
+    operation_id = "retire_verifier_key",
+    tag = "system",
+    request_body = RetireVerifierKeyRequest,
+    responses(
+        (status = 200, description = "The key was retired"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The key does not exist", body = RequestHandlerError),
+        (status = 412, description = "The key is in use", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    request: Json<RetireVerifierKeyRequest>,
+) -> Result<Json<()>, VerifierKeyServiceError> {
+    state.services.verifier_key.retire_key(&request.public_key).await?;
+    Ok(Json(()))
+}
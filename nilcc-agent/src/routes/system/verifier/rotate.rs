@@ -0,0 +1,39 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::verifier_keys::VerifierKeyServiceError,
+};
+use axum::extract::State;
+use nilcc_agent_models::system::{RotateVerifierKeyRequest, RotateVerifierKeyResponse};
+
+/// Rotate a verifier key.
+///
+/// The workload using the key is moved over to a fresh key and its VM is restarted so the CVM registers the new key on
+/// chain when it's bootstrapped again. The previous key is retired.
+#[utoipa::path(
+    post,
+    path = "/api/v1/system/verifier/keys/rotate",
+    operation_id = "rotate_verifier_key",
+    tag = "system",
+    request_body = RotateVerifierKeyRequest,
+    responses(
+        (status = 200, body = RotateVerifierKeyResponse),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The key does not exist", body = RequestHandlerError),
+        (
+            status = 412,
+            description = "The key is not assigned to a workload or there are no keys left",
+            body = RequestHandlerError
+        ),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    request: Json<RotateVerifierKeyRequest>,
+) -> Result<Json<RotateVerifierKeyResponse>, VerifierKeyServiceError> {
+    let rotated = state.services.verifier_key.rotate_key(&request.public_key).await?;
+    Ok(Json(RotateVerifierKeyResponse {
+        workload_id: rotated.workload_id,
+        public_key: rotated.key.public_uncompressed.into(),
+    }))
+}
@@ -0,0 +1,44 @@
+use crate::routes::{AppState, Json};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use nilcc_agent_models::{errors::RequestHandlerError, system::ZeroSslAccount};
+use std::collections::HashMap;
+
+/// Get the ZeroSSL accounts certificates are requested with.
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/zerossl/accounts",
+    operation_id = "zerossl_accounts",
+    tag = "system",
+    responses(
+        (status = 200, body = Vec<ZeroSslAccount>),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(state: State<AppState>) -> Result<Json<Vec<ZeroSslAccount>>, Response> {
+    let workloads = state
+        .services
+        .workload
+        .list_workloads()
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(RequestHandlerError::internal())).into_response())?;
+    let mut bound_workloads: HashMap<&str, usize> = HashMap::new();
+    for workload in &workloads {
+        let account = state.zerossl_accounts.resolve(workload.zerossl_account.as_deref());
+        *bound_workloads.entry(account.eab_key_id()).or_default() += 1;
+    }
+    let accounts = state
+        .zerossl_accounts
+        .accounts()
+        .iter()
+        .map(|account| ZeroSslAccount {
+            eab_key_id: account.eab_key_id().to_string(),
+            workloads: bound_workloads.get(account.eab_key_id()).copied().unwrap_or_default(),
+            certificate_requests: account.certificate_requests(),
+        })
+        .collect();
+    Ok(Json(accounts))
+}
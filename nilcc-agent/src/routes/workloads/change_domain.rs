@@ -0,0 +1,97 @@
+use crate::{
+    auth::Caller,
+    routes::{AppState, Json, RequestHandlerError, workloads::authorize_workload},
+    services::workload::{ChangeDomainError, WorkloadLookupError},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use nilcc_agent_models::workloads::change_domain::ChangeWorkloadDomainRequest;
+use strum::EnumDiscriminants;
+use tracing::error;
+
+/// Change the domain a workload is served on.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/change-domain",
+    operation_id = "change_workload_domain",
+    tag = "workloads",
+    request_body = ChangeWorkloadDomainRequest,
+    responses(
+        (status = 200, description = "The domain was changed"),
+        (status = 400, description = "The domain can't be used", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "An env group the workload uses is not available", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    caller: Caller,
+    request: Json<ChangeWorkloadDomainRequest>,
+) -> Result<Json<()>, HandlerError> {
+    let ChangeWorkloadDomainRequest { id, domain } = request.0;
+    if domain == state.agent_domain {
+        return Err(HandlerError::AgentDomain);
+    }
+    authorize_workload(&state, &caller, id).await?;
+    state.services.workload.change_domain(id, domain).await?;
+    Ok(Json(()))
+}
+
+#[derive(Debug, thiserror::Error, EnumDiscriminants)]
+pub(crate) enum HandlerError {
+    #[error("workload not found")]
+    WorkloadNotFound,
+
+    #[error("domain is already managed by another workload")]
+    DomainExists,
+
+    #[error("cannot use agent's domain for workload")]
+    AgentDomain,
+
+    #[error("env group '{0}' is not available: {1}")]
+    EnvGroupUnavailable(String, String),
+
+    #[error("internal: {0}")]
+    Internal(String),
+}
+
+impl From<ChangeDomainError> for HandlerError {
+    fn from(e: ChangeDomainError) -> Self {
+        match e {
+            ChangeDomainError::WorkloadNotFound => Self::WorkloadNotFound,
+            ChangeDomainError::DomainExists => Self::DomainExists,
+            ChangeDomainError::EnvGroupUnavailable(name, reason) => Self::EnvGroupUnavailable(name, reason),
+            ChangeDomainError::Internal(e) => Self::Internal(e),
+        }
+    }
+}
+
+impl From<WorkloadLookupError> for HandlerError {
+    fn from(e: WorkloadLookupError) -> Self {
+        match e {
+            WorkloadLookupError::WorkloadNotFound => Self::WorkloadNotFound,
+            e => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for HandlerError {
+    fn into_response(self) -> Response {
+        let discriminant = HandlerErrorDiscriminants::from(&self);
+        let (code, message) = match self {
+            Self::WorkloadNotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            Self::DomainExists | Self::AgentDomain => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::EnvGroupUnavailable(..) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
+            Self::Internal(e) => {
+                error!("Failed to change workload domain: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
+            }
+        };
+        let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
+        (code, Json(response)).into_response()
+    }
+}
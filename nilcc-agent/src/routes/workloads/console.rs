@@ -0,0 +1,121 @@
+use crate::{
+    routes::{
+        AppState, Json, RequestHandlerError,
+        workloads::containers::port_forward::{tunnel, websocket_accept_key},
+    },
+    services::workload::WorkloadLookupError,
+};
+use axum::{
+    extract::{Path, Request, State},
+    http::{
+        HeaderValue, StatusCode,
+        header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, UPGRADE},
+    },
+    response::{IntoResponse, Response},
+};
+use hyper_util::rt::TokioIo;
+use strum::EnumDiscriminants;
+use tokio::net::UnixStream;
+use tokio_tungstenite::{WebSocketStream, tungstenite::protocol::Role};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Attach to the serial console of a workload launched in debug mode.
+///
+/// This is a websocket endpoint: once the connection is upgraded, binary messages sent over it are written to the VM's
+/// serial console, and anything the VM writes to it is sent back as binary messages. This is only available if the
+/// agent has debug consoles enabled.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/console",
+    operation_id = "workload_console",
+    tag = "workloads",
+    params(
+        ("workload_id" = Uuid, Path, description = "The workload id"),
+    ),
+    responses(
+        (status = 101, description = "The connection was upgraded to a websocket"),
+        (status = 400, description = "The request isn't a websocket upgrade", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (
+            status = 412,
+            description = "Debug consoles are disabled or the workload isn't running in debug mode",
+            body = RequestHandlerError
+        ),
+        (status = 502, description = "The workload's console could not be reached", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    mut http_request: Request,
+) -> Result<Response, HandlerError> {
+    let accept_key = websocket_accept_key(http_request.headers()).ok_or(HandlerError::NotWebSocket)?;
+    if !state.capabilities.features.debug_console {
+        return Err(HandlerError::Disabled);
+    }
+    let id = path.0;
+    let console_path = state.services.workload.debug_console_path(id).await?.ok_or(HandlerError::NotDebug)?;
+    let stream = UnixStream::connect(&console_path).await.map_err(|e| {
+        warn!("Failed to connect to console for workload {id}: {e}");
+        HandlerError::ConsoleUnreachable
+    })?;
+
+    info!("Attaching to console of workload {id}");
+    let on_upgrade = hyper::upgrade::on(&mut http_request);
+    tokio::spawn(async move {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                error!("Failed to upgrade console connection: {e}");
+                return;
+            }
+        };
+        let socket = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+        match tunnel(socket, stream).await {
+            Ok(()) => info!("Console of workload {id} detached"),
+            Err(e) => warn!("Console of workload {id} failed: {e:#}"),
+        }
+    });
+    let headers = [
+        (CONNECTION, HeaderValue::from_static("upgrade")),
+        (UPGRADE, HeaderValue::from_static("websocket")),
+        (SEC_WEBSOCKET_ACCEPT, accept_key),
+    ];
+    Ok((StatusCode::SWITCHING_PROTOCOLS, headers).into_response())
+}
+
+#[derive(Debug, thiserror::Error, EnumDiscriminants)]
+pub(crate) enum HandlerError {
+    #[error("expected a websocket upgrade request")]
+    NotWebSocket,
+
+    #[error("debug consoles are not enabled in this agent")]
+    Disabled,
+
+    #[error("workload is not running in debug mode")]
+    NotDebug,
+
+    #[error("could not connect to the workload's console")]
+    ConsoleUnreachable,
+
+    #[error(transparent)]
+    Lookup(#[from] WorkloadLookupError),
+}
+
+impl IntoResponse for HandlerError {
+    fn into_response(self) -> Response {
+        let discriminant = HandlerErrorDiscriminants::from(&self);
+        let (code, message) = match self {
+            Self::Lookup(e) => return e.into_response(),
+            Self::NotWebSocket => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::Disabled | Self::NotDebug => (StatusCode::PRECONDITION_FAILED, self.to_string()),
+            Self::ConsoleUnreachable => (StatusCode::BAD_GATEWAY, self.to_string()),
+        };
+        // The console may just not be up yet.
+        let retryable = code == StatusCode::BAD_GATEWAY;
+        let response = RequestHandlerError::new(message, format!("{discriminant:?}")).with_retryable(retryable);
+        (code, Json(response)).into_response()
+    }
+}
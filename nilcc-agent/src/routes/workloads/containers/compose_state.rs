@@ -0,0 +1,41 @@
+use crate::{
+    clients::cvm_agent::CvmAgentRequestError,
+    routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError},
+};
+use axum::extract::{Path, State};
+use cvm_agent_models::container::ComposeStateResponse;
+use reqwest::StatusCode;
+use uuid::Uuid;
+
+/// Get the state of every docker compose service in a workload's CVM.
+///
+/// Unlike the container list, this compares the services defined in the docker compose files against the containers
+/// actually running, so it can be used to tell whether the deployment converged.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/containers/compose-state",
+    operation_id = "compose_state",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = ComposeStateResponse),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+) -> Result<Json<ComposeStateResponse>, CvmAgentHandlerError> {
+    let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
+    match state.clients.cvm_agent.compose_state(port).await {
+        Ok(response) => Ok(Json(response)),
+        Err(CvmAgentRequestError::Http(e)) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            Err(CvmAgentHandlerError::CvmAgent("cvm-agent does not report compose state"))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
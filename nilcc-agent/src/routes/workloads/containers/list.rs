@@ -1,12 +1,28 @@
-use crate::routes::{AppState, Json, workloads::containers::CvmAgentHandlerError};
+use crate::routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError};
 use axum::extract::{Path, State};
 use cvm_agent_models::container::Container;
 use uuid::Uuid;
 
+/// List the containers running in a workload's CVM.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/containers/list",
+    operation_id = "list_containers",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = Vec<Container>),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
     path: Path<Uuid>,
 ) -> Result<Json<Vec<Container>>, CvmAgentHandlerError> {
     let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
     Ok(state.clients.cvm_agent.list_containers(port).await.map(Json)?)
 }
@@ -1,23 +1,46 @@
 use crate::{
     clients::cvm_agent::CvmAgentRequestError,
-    routes::{AppState, Json, Query, workloads::containers::CvmAgentHandlerError},
+    routes::{AppState, Json, Query, RequestHandlerError, workloads::containers::CvmAgentHandlerError},
 };
 use axum::extract::{Path, State};
-use cvm_agent_models::logs::{ContainerLogsRequest, ContainerLogsResponse};
+use cvm_agent_models::{
+    encryption::MaybeEncrypted,
+    logs::{ContainerLogsRequest, ContainerLogsResponse},
+};
 use reqwest::StatusCode;
 use uuid::Uuid;
 
+/// Get the logs for a container in a workload's CVM.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/containers/logs",
+    operation_id = "container_logs",
+    tag = "workloads",
+    params(
+        ("workload_id" = Uuid, Path, description = "The workload id"),
+        ContainerLogsRequest,
+    ),
+    responses(
+        (status = 200, body = MaybeEncrypted<ContainerLogsResponse>),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload or container does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
     path: Path<Uuid>,
     request: Query<ContainerLogsRequest>,
-) -> Result<Json<ContainerLogsResponse>, CvmAgentHandlerError> {
+) -> Result<Json<MaybeEncrypted<ContainerLogsResponse>>, CvmAgentHandlerError> {
     let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
     let result = state.clients.cvm_agent.container_logs(port, &request.0).await;
     match result {
         Ok(response) => Ok(Json(response)),
         Err(CvmAgentRequestError::Http(e)) if e.status() == Some(StatusCode::NOT_FOUND) => {
-            Err(CvmAgentHandlerError::ContainerNotFound)
+            Err(CvmAgentHandlerError::ContainerNotFound(request.container.clone()))
         }
         Err(e) => Err(e.into()),
     }
@@ -1,22 +1,38 @@
 use crate::clients::cvm_agent::CvmAgentRequestError;
-use crate::routes::{Json, RequestHandlerError};
+use crate::routes::{Json, RequestHandlerError, limits::LimitExceeded};
 use crate::services::workload::WorkloadLookupError;
-use axum::http::StatusCode;
+use axum::http::{StatusCode, header::RETRY_AFTER};
 use axum::response::{IntoResponse, Response};
+use std::time::Duration;
 use strum::EnumDiscriminants;
 use tracing::error;
 
+pub(crate) mod compose_state;
 pub(crate) mod list;
 pub(crate) mod logs;
+pub(crate) mod port_forward;
+pub(crate) mod restart;
+pub(crate) mod stats;
 
 #[derive(EnumDiscriminants)]
 pub(crate) enum CvmAgentHandlerError {
     Internal(String),
     WorkloadNotFound,
-    ContainerNotFound,
+    ContainerNotFound(String),
+    JobNotFound(String),
+    NotWebSocket,
+    ContainerUnreachable,
+    TooManyRequests(Duration),
+    InsufficientResources(&'static str),
     CvmAgent(&'static str),
 }
 
+impl From<LimitExceeded> for CvmAgentHandlerError {
+    fn from(e: LimitExceeded) -> Self {
+        Self::TooManyRequests(e.retry_after)
+    }
+}
+
 impl From<CvmAgentRequestError> for CvmAgentHandlerError {
     fn from(e: CvmAgentRequestError) -> Self {
         use CvmAgentRequestError::*;
@@ -35,6 +51,10 @@ impl From<WorkloadLookupError> for CvmAgentHandlerError {
             WorkloadLookupError::WorkloadNotFound => Self::WorkloadNotFound,
             WorkloadLookupError::Database(e) => Self::Internal(e.to_string()),
             WorkloadLookupError::Internal(e) => Self::Internal(e.to_string()),
+            WorkloadLookupError::InsufficientResources(resource) => Self::InsufficientResources(resource),
+            WorkloadLookupError::EnvGroupUnavailable(..) | WorkloadLookupError::WorkloadNotRunning => {
+                Self::Internal(e.to_string())
+            }
         }
     }
 }
@@ -42,16 +62,49 @@ impl From<WorkloadLookupError> for CvmAgentHandlerError {
 impl IntoResponse for CvmAgentHandlerError {
     fn into_response(self) -> Response {
         let discriminant = CvmAgentHandlerErrorDiscriminants::from(&self);
-        let (code, message) = match self {
+        let retry_after = match &self {
+            Self::TooManyRequests(retry_after) => Some(retry_after.as_secs()),
+            _ => None,
+        };
+        // Anything but a missing resource or a malformed request can go away on its own.
+        let retryable = !matches!(
+            self,
+            Self::WorkloadNotFound | Self::ContainerNotFound(_) | Self::JobNotFound(_) | Self::NotWebSocket
+        );
+        let (code, message, detail) = match self {
             Self::Internal(e) => {
                 error!("Failed to process request: {e}");
-                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".to_string())
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".to_string(), None)
             }
-            Self::WorkloadNotFound => (StatusCode::NOT_FOUND, "workload not found".into()),
-            Self::ContainerNotFound => (StatusCode::NOT_FOUND, "container not found".into()),
-            Self::CvmAgent(details) => (StatusCode::PRECONDITION_FAILED, details.to_string()),
+            Self::WorkloadNotFound => (StatusCode::NOT_FOUND, "workload not found".into(), None),
+            Self::ContainerNotFound(container) => {
+                (StatusCode::NOT_FOUND, "container not found".into(), Some(("container", container)))
+            }
+            Self::JobNotFound(job) => {
+                (StatusCode::NOT_FOUND, "job not found or not started yet".into(), Some(("job", job)))
+            }
+            Self::NotWebSocket => (StatusCode::BAD_REQUEST, "expected a websocket upgrade request".into(), None),
+            Self::ContainerUnreachable => (StatusCode::BAD_GATEWAY, "could not connect to container port".into(), None),
+            Self::TooManyRequests(retry_after) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "too many requests to cvm-agent".into(),
+                Some(("retryAfterSeconds", retry_after.as_secs().to_string())),
+            ),
+            Self::InsufficientResources(resource) => (
+                StatusCode::PRECONDITION_FAILED,
+                format!("not enough {resource} available"),
+                Some(("resource", resource.to_string())),
+            ),
+            Self::CvmAgent(details) => (StatusCode::PRECONDITION_FAILED, details.to_string(), None),
         };
-        let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
-        (code, Json(response)).into_response()
+        let mut response = RequestHandlerError::new(message, format!("{discriminant:?}")).with_retryable(retryable);
+        if let Some((key, value)) = detail {
+            response = response.with_detail(key, value);
+        }
+        let mut response = (code, Json(response)).into_response();
+        if let Some(retry_after) = retry_after {
+            response.headers_mut().insert(RETRY_AFTER, retry_after.into());
+        }
+        response
     }
 }
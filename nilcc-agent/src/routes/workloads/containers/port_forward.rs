@@ -0,0 +1,185 @@
+use crate::{
+    clients::cvm_agent::CvmAgentRequestError,
+    routes::{AppState, Query, RequestHandlerError, workloads::containers::CvmAgentHandlerError},
+};
+use axum::{
+    extract::{Path, Request, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE},
+    },
+    response::{IntoResponse, Response},
+};
+use cvm_agent_models::container::PortForwardRequest;
+use futures_util::{SinkExt, StreamExt};
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{Message, handshake::derive_accept_key, protocol::Role},
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// The size of the buffer used to read from the forwarded connection.
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// Forward connections to a port in a container in a workload's CVM.
+///
+/// This is a websocket endpoint: once the connection is upgraded, binary messages sent over it are forwarded to the
+/// container's port, and anything the container sends back is sent as binary messages.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/port-forward",
+    operation_id = "port_forward",
+    tag = "workloads",
+    params(
+        ("workload_id" = Uuid, Path, description = "The workload id"),
+        PortForwardRequest,
+    ),
+    responses(
+        (status = 101, description = "The connection was upgraded to a websocket"),
+        (status = 400, description = "The request is malformed or isn't a websocket upgrade", body = RequestHandlerError),
+        (status = 404, description = "The workload or container does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 502, description = "The container's port could not be reached", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Query<PortForwardRequest>,
+    mut http_request: Request,
+) -> Result<Response, CvmAgentHandlerError> {
+    let accept_key = websocket_accept_key(http_request.headers()).ok_or(CvmAgentHandlerError::NotWebSocket)?;
+    let id = path.0;
+    let port = state.services.workload.cvm_agent_port(id).await?;
+    let _permit = state.cvm_agent_limiter.acquire(id).await?;
+    let stream = match state.clients.cvm_agent.port_forward(port, &request.0).await {
+        Ok(stream) => stream,
+        Err(CvmAgentRequestError::Http(e)) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            return Err(CvmAgentHandlerError::ContainerNotFound(request.container.clone()));
+        }
+        Err(CvmAgentRequestError::Http(e))
+            if matches!(e.status(), Some(StatusCode::BAD_GATEWAY | StatusCode::PRECONDITION_FAILED)) =>
+        {
+            return Err(CvmAgentHandlerError::ContainerUnreachable);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let PortForwardRequest { container, port } = request.0;
+    info!("Forwarding connection to port {port} in container {container} in workload {id}");
+    let on_upgrade = hyper::upgrade::on(&mut http_request);
+    tokio::spawn(async move {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                error!("Failed to upgrade port forwarding connection: {e}");
+                return;
+            }
+        };
+        let socket = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+        match tunnel(socket, stream).await {
+            Ok(()) => info!("Port forwarding to container {container} in workload {id} closed"),
+            Err(e) => warn!("Port forwarding to container {container} in workload {id} failed: {e:#}"),
+        }
+    });
+    let headers = [
+        (CONNECTION, HeaderValue::from_static("upgrade")),
+        (UPGRADE, HeaderValue::from_static("websocket")),
+        (SEC_WEBSOCKET_ACCEPT, accept_key),
+    ];
+    Ok((StatusCode::SWITCHING_PROTOCOLS, headers).into_response())
+}
+
+/// Get the accept key to respond to a websocket upgrade request with, if this is one.
+pub(crate) fn websocket_accept_key(headers: &HeaderMap) -> Option<HeaderValue> {
+    let upgrade = headers.get(UPGRADE)?.to_str().ok()?;
+    let version = headers.get(SEC_WEBSOCKET_VERSION)?;
+    if !upgrade.eq_ignore_ascii_case("websocket") || version != "13" {
+        return None;
+    }
+    let key = headers.get(SEC_WEBSOCKET_KEY)?;
+    HeaderValue::from_str(&derive_accept_key(key.as_bytes())).ok()
+}
+
+/// Forward binary messages sent over a websocket to a stream and vice versa.
+pub(crate) async fn tunnel<S, T>(socket: WebSocketStream<S>, stream: T) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut sink, mut messages) = socket.split();
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let inbound = async {
+        while let Some(message) = messages.next().await {
+            match message? {
+                Message::Binary(data) => writer.write_all(&data).await?,
+                Message::Close(_) => break,
+                // Pings are answered by the websocket implementation itself.
+                _ => (),
+            }
+        }
+        writer.shutdown().await?;
+        anyhow::Ok(())
+    };
+    let outbound = async {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        loop {
+            let length = reader.read(&mut buffer).await?;
+            if length == 0 {
+                break;
+            }
+            sink.send(Message::binary(buffer[..length].to_vec())).await?;
+        }
+        sink.close().await?;
+        anyhow::Ok(())
+    };
+    tokio::try_join!(inbound, outbound)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key() {
+        // The example in RFC 6455.
+        let mut headers = HeaderMap::new();
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+        headers.insert(SEC_WEBSOCKET_KEY, HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ=="));
+        let key = websocket_accept_key(&headers).expect("no accept key");
+        assert_eq!(key, "s3pPLMBiTxaQ9kxGzzhZRbK+xOo=");
+
+        headers.insert(UPGRADE, HeaderValue::from_static("h2c"));
+        assert!(websocket_accept_key(&headers).is_none());
+    }
+
+    #[tokio::test]
+    async fn tunnel_messages() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (stream, mut container) = tokio::io::duplex(1024);
+        let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let tunnel = tokio::spawn(tunnel(server, stream));
+        let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+
+        client.send(Message::binary(b"ping".to_vec())).await.expect("failed to send");
+        let mut buffer = [0; 4];
+        container.read_exact(&mut buffer).await.expect("failed to read");
+        assert_eq!(&buffer, b"ping");
+
+        container.write_all(b"pong").await.expect("failed to write");
+        let message = client.next().await.expect("no message").expect("invalid message");
+        assert_eq!(message, Message::binary(b"pong".to_vec()));
+
+        // Closing the container's side closes the websocket, and the tunnel ends once the client acknowledges it.
+        drop(container);
+        while client.next().await.is_some() {}
+        tunnel.await.expect("tunnel panicked").expect("tunnel failed");
+    }
+}
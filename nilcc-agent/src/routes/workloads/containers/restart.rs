@@ -0,0 +1,42 @@
+use crate::{
+    clients::cvm_agent::CvmAgentRequestError,
+    routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError},
+};
+use axum::extract::{Path, State};
+use cvm_agent_models::container::RestartContainerRequest;
+use reqwest::StatusCode;
+use uuid::Uuid;
+
+/// Restart the containers for a docker compose service in a workload's CVM.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/{workload_id}/containers/restart",
+    operation_id = "restart_container",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    request_body = RestartContainerRequest,
+    responses(
+        (status = 200, description = "The containers were restarted"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload or service does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Json<RestartContainerRequest>,
+) -> Result<Json<()>, CvmAgentHandlerError> {
+    let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
+    let result = state.clients.cvm_agent.restart_container(port, &request.0).await;
+    match result {
+        Ok(()) => Ok(Json(())),
+        Err(CvmAgentRequestError::Http(e)) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            Err(CvmAgentHandlerError::ContainerNotFound(request.service.clone()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,29 @@
+use crate::routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError};
+use axum::extract::{Path, State};
+use cvm_agent_models::{encryption::MaybeEncrypted, stats::ContainersStatsResponse};
+use uuid::Uuid;
+
+/// Get the CPU, memory, network and block IO usage of every running container in a workload's CVM.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/containers/stats",
+    operation_id = "container_stats",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = MaybeEncrypted<ContainersStatsResponse>),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+) -> Result<Json<MaybeEncrypted<ContainersStatsResponse>>, CvmAgentHandlerError> {
+    let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
+    let response = state.clients.cvm_agent.container_stats(port).await?;
+    Ok(Json(response))
+}
@@ -1,33 +1,59 @@
 use crate::{
-    compose::{DockerComposeValidationError, validate_docker_compose},
-    routes::{AppState, Json, RequestHandlerError},
-    services::workload::CreateWorkloadError,
+    auth::Caller,
+    compose::{DockerComposeValidationError, validate_docker_compose, validate_interpolations},
+    routes::{AppState, Json, Query, RequestHandlerError},
+    services::{disk::RESERVED_ENVIRONMENT_VARIABLES, upload::UploadError, workload::CreateWorkloadError},
 };
 use axum::{
     extract::State,
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use cvm_agent_models::bootstrap::{CADDY_ACME_EAB_KEY_ID, CADDY_ACME_EAB_MAC_KEY};
-use nilcc_agent_models::workloads::create::{CreateWorkloadRequest, CreateWorkloadResponse};
+use nilcc_agent_models::workloads::create::{
+    CreateWorkloadQuery, CreateWorkloadRequest, CreateWorkloadResponse, ImagePolicyMode,
+};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use strum::EnumDiscriminants;
-use tracing::error;
-
-/// The list of reserved environment variable names.
-static RESERVED_ENVIRONMENT_VARIABLES: &[&str] = &[
-    "NILCC_VERSION",
-    "NILCC_VM_TYPE",
-    "NILCC_DOMAIN",
-    "FILES",
-    "CADDY_INPUT_FILE",
-    CADDY_ACME_EAB_KEY_ID,
-    CADDY_ACME_EAB_MAC_KEY,
-];
+use tracing::{error, info, warn};
+use uuid::Uuid;
 
+/// Create a workload.
+///
+/// When doing a dry run, the workload is fully validated and the resources that would be assigned to it are returned
+/// without creating it.
+///
+/// Files uploaded in chunks can be referenced via `uploadedFiles`. These are kept on disk next to the workload rather
+/// than loaded into memory, and the uploads are deleted once the workload is created but kept around when doing a dry
+/// run.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/create",
+    operation_id = "create_workload",
+    tag = "workloads",
+    params(CreateWorkloadQuery),
+    request_body = CreateWorkloadRequest,
+    responses(
+        (status = 200, body = CreateWorkloadResponse),
+        (status = 400, description = "The request is malformed or the workload is invalid", body = RequestHandlerError),
+        (status = 403, description = "A non admin caller overrode the image policy", body = RequestHandlerError),
+        (
+            status = 412,
+            description = "Not enough resources, artifacts missing, GPU model unavailable, debug workloads disabled or \
+                the workload can't share the agent with the existing ones",
+            body = RequestHandlerError
+        ),
+        (status = 503, description = "An image could not be checked for vulnerabilities", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
+    caller: Caller,
+    query: Query<CreateWorkloadQuery>,
     request: Json<CreateWorkloadRequest>,
 ) -> Result<Json<CreateWorkloadResponse>, HandlerError> {
+    let mut request = request.0;
+    let uploads = std::mem::take(&mut request.uploaded_files);
     let limits = &state.resource_limits;
     let checks = [
         (request.cpus, limits.cpus, "cpus"),
@@ -42,15 +68,104 @@ pub(crate) async fn handler(
     if request.domain == state.agent_domain {
         return Err(HandlerError::AgentDomain);
     }
+    if request.debug && !state.capabilities.features.debug_console {
+        return Err(HandlerError::DebugConsoleDisabled);
+    }
+    if request.image_policy.is_some() && !caller.is_admin() {
+        return Err(HandlerError::ImagePolicyOverride);
+    }
     // Make sure no reserved environment variable names are used.
     if let Some(name) = request.env_vars.keys().find(|var| RESERVED_ENVIRONMENT_VARIABLES.contains(&var.as_str())) {
         return Err(HandlerError::ReservedEnvironmentVariable(name.clone()));
     }
-    validate_docker_compose(&request.docker_compose, &request.public_container_name, &request.files)?;
+    for (name, upload_id) in &uploads {
+        if request.files.contains_key(name) {
+            return Err(HandlerError::DuplicateFile(name.clone()));
+        }
+        if !state.services.upload.upload_status(*upload_id).await?.complete {
+            return Err(UploadError::Incomplete(*upload_id).into());
+        }
+    }
+    let files: HashSet<&str> = request.files.keys().chain(uploads.keys()).map(String::as_str).collect();
+    let compose = validate_docker_compose(&request.docker_compose, &request.public_container_name, &files)?;
+    compose.ensure_limits_fit(request.cpus, request.memory_mb)?;
+    compose.ensure_log_encryption_key(request.log_encryption_key.as_deref())?;
+    request.log_encryption_key = compose.log_encryption_key.clone();
+    if !request.registry_mirrors.as_ref().unwrap_or(&state.registry_mirrors).is_empty() {
+        compose.ensure_pinned_images()?;
+    }
+    // The variables env groups provide are only known once they're resolved so we can't check those here.
+    if request.env_groups.is_empty() {
+        validate_interpolations(&request.docker_compose, |name| {
+            request.env_vars.contains_key(name) || RESERVED_ENVIRONMENT_VARIABLES.contains(&name)
+        })?;
+    }
+    check_images(&state, &request, &compose.images).await?;
 
     let id = request.id;
-    state.services.workload.create_workload(request.0).await?;
-    Ok(Json(CreateWorkloadResponse { id }))
+    if query.dry_run {
+        let admission = state.services.workload.preview_workload(&request).await?;
+        return Ok(Json(CreateWorkloadResponse { id, admission: Some(admission) }));
+    }
+    // Make sure the workload fits before copying its uploads over.
+    state.services.workload.preview_workload(&request).await?;
+    let mut stored_files = HashMap::new();
+    for (name, upload_id) in &uploads {
+        match state.services.upload.store_upload(*upload_id, id).await {
+            Ok(file) => stored_files.insert(name.clone(), file),
+            Err(e) => {
+                state.services.upload.delete_workload_files(id).await;
+                return Err(e.into());
+            }
+        };
+    }
+    if let Err(e) =
+        state.services.workload.create_workload(request, stored_files, caller.owner().map(String::from)).await
+    {
+        // If the workload already exists these are its files, so they must be left alone.
+        if !matches!(e, CreateWorkloadError::AlreadyExists) {
+            state.services.upload.delete_workload_files(id).await;
+        }
+        return Err(e.into());
+    }
+    for upload_id in uploads.values() {
+        state.services.upload.delete_upload(*upload_id).await;
+    }
+    Ok(Json(CreateWorkloadResponse { id, admission: None }))
+}
+
+async fn check_images(
+    state: &AppState,
+    request: &CreateWorkloadRequest,
+    images: &BTreeSet<String>,
+) -> Result<(), HandlerError> {
+    let mode = request.image_policy.unwrap_or(state.image_policy_mode);
+    if mode == ImagePolicyMode::Disabled {
+        return Ok(());
+    }
+    let checker = state.services.image_policy.as_ref().ok_or(HandlerError::ImagePolicyNotConfigured)?;
+    for image in images {
+        let vulnerabilities = match checker.critical_vulnerabilities(image, &request.docker_credentials).await {
+            Ok(vulnerabilities) => vulnerabilities,
+            Err(e) if mode == ImagePolicyMode::Warn => {
+                warn!("Failed to check image {image} for workload {}: {e}", request.id);
+                continue;
+            }
+            Err(e) => return Err(HandlerError::ImagePolicyCheck(image.clone(), e.to_string())),
+        };
+        if vulnerabilities.is_empty() {
+            info!("Image {image} has no critical vulnerabilities");
+            continue;
+        }
+        let vulnerabilities = vulnerabilities.join(", ");
+        match mode {
+            ImagePolicyMode::Warn => {
+                warn!("Image {image} for workload {} has critical vulnerabilities: {vulnerabilities}", request.id)
+            }
+            _ => return Err(HandlerError::VulnerableImage(image.clone(), vulnerabilities)),
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, thiserror::Error, EnumDiscriminants)]
@@ -81,6 +196,51 @@ pub(crate) enum HandlerError {
 
     #[error("cannot set reserved environment variable '{0}'")]
     ReservedEnvironmentVariable(String),
+
+    #[error("image '{0}' has critical vulnerabilities: {1}")]
+    VulnerableImage(String, String),
+
+    #[error("failed to check image '{0}': {1}")]
+    ImagePolicyCheck(String, String),
+
+    #[error("image policy checks are not configured in this agent")]
+    ImagePolicyNotConfigured,
+
+    #[error("only admins can override the image policy")]
+    ImagePolicyOverride,
+
+    #[error("env group '{0}' is not available: {1}")]
+    EnvGroupUnavailable(String, String),
+
+    #[error("GPU model '{0}' is not available")]
+    GpuModelUnavailable(String),
+
+    #[error("GPUs in this agent don't support confidential computing, the workload must allow non confidential GPUs")]
+    NonConfidentialGpus,
+
+    #[error("file '{0}' is both embedded and uploaded")]
+    DuplicateFile(String),
+
+    #[error("invalid upload: {0}")]
+    InvalidUpload(String),
+
+    #[error("debug workloads are not enabled in this agent")]
+    DebugConsoleDisabled,
+
+    #[error("isolated workloads can only be created in agents that don't have any other workloads")]
+    HostNotEmpty,
+
+    #[error("agent is reserved by isolated workload {0}")]
+    HostIsolated(Uuid),
+}
+
+impl From<UploadError> for HandlerError {
+    fn from(e: UploadError) -> Self {
+        match e {
+            UploadError::Internal(e) => Self::Internal(e),
+            _ => Self::InvalidUpload(e.to_string()),
+        }
+    }
 }
 
 impl From<CreateWorkloadError> for HandlerError {
@@ -92,6 +252,11 @@ impl From<CreateWorkloadError> for HandlerError {
             CreateWorkloadError::DomainExists => Self::DomainExists,
             CreateWorkloadError::ArtifactVersionMissing => Self::ArtifactVersionMissing,
             CreateWorkloadError::NotEnoughKeys => Self::Internal(e.to_string()),
+            CreateWorkloadError::EnvGroupUnavailable(name, reason) => Self::EnvGroupUnavailable(name, reason),
+            CreateWorkloadError::GpuModelUnavailable(model) => Self::GpuModelUnavailable(model),
+            CreateWorkloadError::NonConfidentialGpus => Self::NonConfidentialGpus,
+            CreateWorkloadError::HostNotEmpty => Self::HostNotEmpty,
+            CreateWorkloadError::HostIsolated(id) => Self::HostIsolated(id),
         }
     }
 }
@@ -99,22 +264,56 @@ impl From<CreateWorkloadError> for HandlerError {
 impl IntoResponse for HandlerError {
     fn into_response(self) -> Response {
         let discriminant = HandlerErrorDiscriminants::from(&self);
-        let (code, message) = match self {
-            Self::InsufficientResources(_) | Self::ArtifactVersionMissing => {
-                (StatusCode::PRECONDITION_FAILED, self.to_string())
+        let retryable = matches!(
+            self,
+            Self::InsufficientResources(_)
+                | Self::EnvGroupUnavailable(..)
+                | Self::ImagePolicyCheck(..)
+                | Self::Internal(_)
+        );
+        let details = match &self {
+            Self::InsufficientResources(resource) => vec![("resource", resource.to_string())],
+            Self::ResourceLimit(resource, limit) => {
+                vec![("resource", resource.to_string()), ("limit", limit.to_string())]
             }
+            Self::ReservedEnvironmentVariable(name) => vec![("envVar", name.clone())],
+            Self::VulnerableImage(image, _) | Self::ImagePolicyCheck(image, _) => vec![("image", image.clone())],
+            Self::EnvGroupUnavailable(group, _) => vec![("envGroup", group.clone())],
+            Self::GpuModelUnavailable(model) => vec![("gpuModel", model.clone())],
+            Self::DuplicateFile(name) => vec![("file", name.clone())],
+            Self::HostIsolated(id) => vec![("workloadId", id.to_string())],
+            _ => Vec::new(),
+        };
+        let (code, message) = match self {
+            Self::InsufficientResources(_)
+            | Self::ArtifactVersionMissing
+            | Self::EnvGroupUnavailable(..)
+            | Self::GpuModelUnavailable(_)
+            | Self::NonConfidentialGpus
+            | Self::DebugConsoleDisabled
+            | Self::HostNotEmpty
+            | Self::HostIsolated(_) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
             Self::AlreadyExists
             | Self::DomainExists
             | Self::DockerCompose(_)
             | Self::AgentDomain
             | Self::ReservedEnvironmentVariable(_)
-            | Self::ResourceLimit(..) => (StatusCode::BAD_REQUEST, self.to_string()),
+            | Self::VulnerableImage(..)
+            | Self::ImagePolicyNotConfigured
+            | Self::ResourceLimit(..)
+            | Self::DuplicateFile(_)
+            | Self::InvalidUpload(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::ImagePolicyCheck(..) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            Self::ImagePolicyOverride => (StatusCode::FORBIDDEN, self.to_string()),
             Self::Internal(e) => {
                 error!("Failed to create workload: {e}");
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
             }
         };
-        let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
+        let mut response = RequestHandlerError::new(message, format!("{discriminant:?}")).with_retryable(retryable);
+        for (key, value) in details {
+            response = response.with_detail(key, value);
+        }
         (code, Json(response)).into_response()
     }
 }
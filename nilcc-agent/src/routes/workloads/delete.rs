@@ -1,14 +1,32 @@
 use crate::{
-    routes::{AppState, Json},
+    auth::Caller,
+    routes::{AppState, Json, RequestHandlerError, workloads::authorize_workload},
     services::workload::WorkloadLookupError,
 };
 use axum::extract::State;
 use nilcc_agent_models::workloads::delete::DeleteWorkloadRequest;
 
+/// Delete a workload.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/delete",
+    operation_id = "delete_workload",
+    tag = "workloads",
+    request_body = DeleteWorkloadRequest,
+    responses(
+        (status = 200, description = "The workload was deleted"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
+    caller: Caller,
     request: Json<DeleteWorkloadRequest>,
 ) -> Result<Json<()>, WorkloadLookupError> {
+    authorize_workload(&state, &caller, request.id).await?;
     state.services.workload.delete_workload(request.id).await?;
+    state.services.upload.delete_workload_files(request.id).await;
     Ok(Json(()))
 }
@@ -0,0 +1,31 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError, workloads::list::summarize},
+    services::workload::WorkloadLookupError,
+};
+use axum::extract::{Path, State};
+use nilcc_agent_models::workloads::list::WorkloadSummary;
+use uuid::Uuid;
+
+/// Get a workload's summary.
+///
+/// This is the same summary the list endpoint returns for the workload.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/details",
+    operation_id = "workload_details",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = WorkloadSummary),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+) -> Result<Json<WorkloadSummary>, WorkloadLookupError> {
+    let workload = state.services.workload.find_workload(path.0).await?;
+    let iso_content_hash = state.services.workload.iso_content_hash(workload.id).await;
+    Ok(Json(summarize(workload, iso_content_hash)))
+}
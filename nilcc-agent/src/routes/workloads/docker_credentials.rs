@@ -0,0 +1,34 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::workload::WorkloadLookupError,
+};
+use axum::extract::{Path, State};
+use nilcc_agent_models::workloads::docker_credentials::UpdateDockerCredentialsRequest;
+use uuid::Uuid;
+
+/// Replace the credentials a workload pulls its images with.
+///
+/// If the workload is running, its CVM logs in to its docker registries again using the new credentials without
+/// restarting the VM. Failing to log in is reported as a warning event for the workload.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/{workload_id}/docker-credentials",
+    operation_id = "update_workload_docker_credentials",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    request_body = UpdateDockerCredentialsRequest,
+    responses(
+        (status = 200, description = "The credentials were updated"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Json<UpdateDockerCredentialsRequest>,
+) -> Result<Json<()>, WorkloadLookupError> {
+    state.services.workload.update_docker_credentials(path.0, request.0.docker_credentials).await?;
+    Ok(Json(()))
+}
@@ -0,0 +1,65 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::{disk::RESERVED_ENVIRONMENT_VARIABLES, workload::WorkloadLookupError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use nilcc_agent_models::workloads::env_vars::{UpdateEnvVarsRequest, UpdateEnvVarsResponse};
+use strum::EnumDiscriminants;
+use uuid::Uuid;
+
+/// Update a workload's environment variables.
+///
+/// The changes are applied the next time the workload is restarted, unless a restart is requested as part of the
+/// update.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/{workload_id}/env-vars",
+    operation_id = "update_workload_env_vars",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    request_body = UpdateEnvVarsRequest,
+    responses(
+        (status = 200, body = UpdateEnvVarsResponse),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Json<UpdateEnvVarsRequest>,
+) -> Result<Json<UpdateEnvVarsResponse>, HandlerError> {
+    let request = request.0;
+    if let Some(name) = request.env_vars.keys().find(|var| RESERVED_ENVIRONMENT_VARIABLES.contains(&var.as_str())) {
+        return Err(HandlerError::ReservedEnvironmentVariable(name.clone()));
+    }
+    let response = state.services.workload.update_env_vars(path.0, request).await?;
+    Ok(Json(response))
+}
+
+#[derive(Debug, thiserror::Error, EnumDiscriminants)]
+pub(crate) enum HandlerError {
+    #[error("cannot set reserved environment variable '{0}'")]
+    ReservedEnvironmentVariable(String),
+
+    #[error(transparent)]
+    Lookup(#[from] WorkloadLookupError),
+}
+
+impl IntoResponse for HandlerError {
+    fn into_response(self) -> Response {
+        let discriminant = HandlerErrorDiscriminants::from(&self);
+        match self {
+            Self::ReservedEnvironmentVariable(_) => {
+                let response = RequestHandlerError::new(self.to_string(), format!("{discriminant:?}"));
+                (StatusCode::BAD_REQUEST, Json(response)).into_response()
+            }
+            Self::Lookup(e) => e.into_response(),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::workload::UpdateFilesError,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use nilcc_agent_models::workloads::files::{UpdateFilesRequest, UpdateFilesResponse};
+use strum::EnumDiscriminants;
+use tracing::error;
+use uuid::Uuid;
+
+/// Add, replace, or remove the files bundled in a workload's application ISO.
+///
+/// The ISO is regenerated and the workload's VM is restarted so it picks up the new files. Workloads that aren't
+/// running pick them up the next time they're started.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/{workload_id}/files",
+    operation_id = "update_workload_files",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    request_body = UpdateFilesRequest,
+    responses(
+        (status = 200, body = UpdateFilesResponse),
+        (
+            status = 400,
+            description = "The request is malformed, the docker compose mounts a file that no longer exists or an \
+                uploaded file is changed",
+            body = RequestHandlerError
+        ),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "An env group the workload uses is not available", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Json<UpdateFilesRequest>,
+) -> Result<Json<UpdateFilesResponse>, HandlerError> {
+    let response = state.services.workload.update_files(path.0, request.0).await?;
+    Ok(Json(response))
+}
+
+#[derive(Debug, thiserror::Error, EnumDiscriminants)]
+pub(crate) enum HandlerError {
+    #[error("workload not found")]
+    WorkloadNotFound,
+
+    #[error("invalid docker compose: {0}")]
+    DockerCompose(String),
+
+    #[error("file '{0}' was uploaded when the workload was created and can't be changed")]
+    StoredFile(String),
+
+    #[error("env group '{0}' is not available: {1}")]
+    EnvGroupUnavailable(String, String),
+
+    #[error("internal: {0}")]
+    Internal(String),
+}
+
+impl From<UpdateFilesError> for HandlerError {
+    fn from(e: UpdateFilesError) -> Self {
+        match e {
+            UpdateFilesError::WorkloadNotFound => Self::WorkloadNotFound,
+            UpdateFilesError::DockerCompose(e) => Self::DockerCompose(e),
+            UpdateFilesError::StoredFile(name) => Self::StoredFile(name),
+            UpdateFilesError::EnvGroupUnavailable(name, reason) => Self::EnvGroupUnavailable(name, reason),
+            UpdateFilesError::Internal(e) => Self::Internal(e),
+        }
+    }
+}
+
+impl IntoResponse for HandlerError {
+    fn into_response(self) -> Response {
+        let discriminant = HandlerErrorDiscriminants::from(&self);
+        let (code, message) = match self {
+            Self::WorkloadNotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            Self::DockerCompose(_) | Self::StoredFile(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::EnvGroupUnavailable(..) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
+            Self::Internal(e) => {
+                error!("Failed to update workload files: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
+            }
+        };
+        let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
+        (code, Json(response)).into_response()
+    }
+}
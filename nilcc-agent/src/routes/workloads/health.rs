@@ -1,13 +1,43 @@
-use crate::routes::{AppState, Json, workloads::containers::CvmAgentHandlerError};
+use crate::routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError};
 use axum::extract::{Path, State};
 use cvm_agent_models::health::HealthResponse;
 use uuid::Uuid;
 
+/// Get the health of a workload's CVM.
+///
+/// Paused workloads are reported as such without reaching out to their CVM, since it can't respond while paused.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/health",
+    operation_id = "workload_health",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = HealthResponse),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
     path: Path<Uuid>,
 ) -> Result<Json<HealthResponse>, CvmAgentHandlerError> {
+    if state.services.workload.workload_paused(path.0).await? {
+        let response = HealthResponse {
+            https: false,
+            bootstrapped: false,
+            last_event: None,
+            bootstrap: None,
+            certificate: None,
+            paused: true,
+            drift: None,
+        };
+        return Ok(Json(response));
+    }
     let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
     let response = state.clients.cvm_agent.check_health(port).await?;
     Ok(Json(response))
 }
@@ -0,0 +1,38 @@
+use crate::{
+    clients::cvm_agent::CvmAgentRequestError,
+    routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError},
+};
+use axum::extract::{Path, State};
+use cvm_agent_models::jobs::JobStatus;
+use reqwest::StatusCode;
+use uuid::Uuid;
+
+/// Get the status of the one-shot jobs in a workload's CVM.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/jobs/list",
+    operation_id = "list_jobs",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = Vec<JobStatus>),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+) -> Result<Json<Vec<JobStatus>>, CvmAgentHandlerError> {
+    let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
+    match state.clients.cvm_agent.list_jobs(port).await {
+        Ok(response) => Ok(Json(response)),
+        Err(CvmAgentRequestError::Http(e)) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            Err(CvmAgentHandlerError::CvmAgent("cvm-agent does not report jobs"))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{
+    clients::cvm_agent::CvmAgentRequestError,
+    routes::{AppState, Json, Query, RequestHandlerError, workloads::containers::CvmAgentHandlerError},
+};
+use axum::extract::{Path, State};
+use cvm_agent_models::{encryption::MaybeEncrypted, jobs::JobLogsRequest, logs::ContainerLogsResponse};
+use reqwest::StatusCode;
+use uuid::Uuid;
+
+/// Get the logs for a one-shot job in a workload's CVM.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/jobs/logs",
+    operation_id = "job_logs",
+    tag = "workloads",
+    params(
+        ("workload_id" = Uuid, Path, description = "The workload id"),
+        JobLogsRequest,
+    ),
+    responses(
+        (status = 200, body = MaybeEncrypted<ContainerLogsResponse>),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (
+            status = 404,
+            description = "The workload or job does not exist, or the job wasn't started yet",
+            body = RequestHandlerError
+        ),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Query<JobLogsRequest>,
+) -> Result<Json<MaybeEncrypted<ContainerLogsResponse>>, CvmAgentHandlerError> {
+    let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
+    match state.clients.cvm_agent.job_logs(port, &request.0).await {
+        Ok(response) => Ok(Json(response)),
+        Err(CvmAgentRequestError::Http(e)) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            Err(CvmAgentHandlerError::JobNotFound(request.job.clone()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
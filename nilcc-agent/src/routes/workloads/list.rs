@@ -1,13 +1,82 @@
 use crate::{
-    routes::{AppState, Json},
+    auth::Caller,
+    repositories::workload::Workload,
+    routes::{AppState, Json, Query, RequestHandlerError},
     services::workload::WorkloadLookupError,
 };
 use axum::extract::State;
-use nilcc_agent_models::workloads::list::WorkloadSummary;
+use nilcc_agent_models::workloads::list::{ListWorkloadsQuery, WorkloadSummary};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 
-pub(crate) async fn handler(state: State<AppState>) -> Result<Json<Vec<WorkloadSummary>>, WorkloadLookupError> {
+/// List all workloads the caller can access.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/list",
+    operation_id = "list_workloads",
+    tag = "workloads",
+    params(ListWorkloadsQuery),
+    responses(
+        (status = 200, body = Vec<WorkloadSummary>),
+        (status = 400, description = "The label selector is malformed", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    caller: Caller,
+    query: Query<ListWorkloadsQuery>,
+) -> Result<Json<Vec<WorkloadSummary>>, WorkloadLookupError> {
+    let selector = query.label_selector();
     let workloads = state.services.workload.list_workloads().await?;
-    let workloads =
-        workloads.into_iter().map(|w| WorkloadSummary { id: w.id, enabled: w.enabled, domain: w.domain }).collect();
-    Ok(Json(workloads))
+    let mut summaries = Vec::new();
+    for w in workloads {
+        if !caller.can_access(w.owner.as_deref()) {
+            continue;
+        }
+        if !selector.iter().all(|(key, value)| w.labels.get(key) == Some(value)) {
+            continue;
+        }
+        let iso_content_hash = state.services.workload.iso_content_hash(w.id).await;
+        summaries.push(summarize(w, iso_content_hash));
+    }
+    Ok(Json(summaries))
+}
+
+/// Build the summary of a workload that's returned by the API.
+pub(crate) fn summarize(w: Workload, iso_content_hash: Option<[u8; 32]>) -> WorkloadSummary {
+    let env_var_hashes = hash_values(w.env_vars.iter().map(|(name, value)| (name, value.as_bytes())));
+    let file_hashes = hash_values(w.files.iter().map(|(name, contents)| (name, contents.as_slice())));
+    WorkloadSummary {
+        id: w.id,
+        enabled: w.enabled,
+        domain: w.domain,
+        priority: w.priority,
+        artifacts_version: w.artifacts_version,
+        upgrade_channel: w.upgrade_channel,
+        state_disk: w.state_disk,
+        preempted: w.preempted,
+        paused: w.paused,
+        bandwidth_limits: w.bandwidth_limits,
+        proxy_timeouts: w.proxy_timeouts,
+        owner: w.owner,
+        isolated: w.isolated,
+        env_vars_restart_pending: w.env_vars_restart_pending,
+        iso_content_hash,
+        labels: w.labels,
+        cpus: w.cpus,
+        memory_mb: w.memory_mb,
+        disk_space_gb: w.disk_space_gb,
+        gpus: w.gpus.len() as u16,
+        public_container_name: w.public_container_name,
+        public_container_port: w.public_container_port,
+        docker_compose_hash: Some(Sha256::digest(&w.docker_compose).into()),
+        env_var_hashes,
+        file_hashes,
+    }
+}
+
+/// Hash values so their contents can be compared without exposing them.
+fn hash_values<'a>(values: impl Iterator<Item = (&'a String, &'a [u8])>) -> HashMap<String, [u8; 32]> {
+    values.map(|(name, value)| (name.clone(), Sha256::digest(value).into())).collect()
 }
@@ -1,34 +1,98 @@
-use crate::routes::{Json, RequestHandlerError};
+use crate::auth::Caller;
+use crate::routes::{AppState, Json, RequestHandlerError};
 use crate::services::workload::{WorkloadLookupError, WorkloadLookupErrorDiscriminants};
+use axum::extract::{Path, Request, State};
 use axum::http::StatusCode;
+use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
-use tracing::error;
+use tracing::{error, warn};
+use uuid::Uuid;
 
+pub(crate) mod change_domain;
+pub(crate) mod console;
 pub(crate) mod containers;
 pub(crate) mod create;
 pub(crate) mod delete;
+pub(crate) mod details;
+pub(crate) mod docker_credentials;
+pub(crate) mod env_vars;
+pub(crate) mod files;
 pub(crate) mod health;
+pub(crate) mod jobs;
 pub(crate) mod list;
+pub(crate) mod pause;
+pub(crate) mod progress;
+pub(crate) mod proxy_timeouts;
 pub(crate) mod restart;
+pub(crate) mod resume;
 pub(crate) mod start;
 pub(crate) mod stop;
 pub(crate) mod system;
+pub(crate) mod tls;
+pub(crate) mod uploads;
+pub(crate) mod usage;
+pub(crate) mod wait;
+
+/// Make sure the caller is allowed to access a workload.
+///
+/// Workloads owned by other API tokens are reported as not found so their existence isn't leaked.
+pub(crate) async fn authorize_workload(state: &AppState, caller: &Caller, id: Uuid) -> Result<(), WorkloadLookupError> {
+    if caller.is_admin() {
+        return Ok(());
+    }
+    let owner = state.services.workload.workload_owner(id).await?;
+    if !caller.can_access(owner.as_deref()) {
+        warn!("Rejecting access to workload {id} by {caller:?}, which is owned by {owner:?}");
+        return Err(WorkloadLookupError::WorkloadNotFound);
+    }
+    Ok(())
+}
+
+/// A middleware that rejects requests to `/{workload_id}/...` endpoints made by callers that can't access the workload.
+pub(crate) async fn require_workload_access(
+    state: State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    request: Request,
+    next: Next,
+) -> Result<Response, WorkloadLookupError> {
+    authorize_workload(&state, &caller, id).await?;
+    Ok(next.run(request).await)
+}
 
 impl IntoResponse for WorkloadLookupError {
     fn into_response(self) -> Response {
-        let discriminant = WorkloadLookupErrorDiscriminants::from(&self);
-        let (code, message) = match self {
+        let discriminant = format!("{:?}", WorkloadLookupErrorDiscriminants::from(&self));
+        let message = self.to_string();
+        let (code, response) = match self {
             WorkloadLookupError::Database(e) => {
                 error!("Failed to run queries: {e}");
-                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
+                let response = RequestHandlerError::new("internal error", discriminant).with_retryable(true);
+                (StatusCode::INTERNAL_SERVER_ERROR, response)
+            }
+            WorkloadLookupError::WorkloadNotFound => {
+                (StatusCode::NOT_FOUND, RequestHandlerError::new(message, discriminant))
+            }
+            WorkloadLookupError::InsufficientResources(resource) => {
+                let response = RequestHandlerError::new(message, discriminant)
+                    .with_retryable(true)
+                    .with_detail("resource", resource);
+                (StatusCode::PRECONDITION_FAILED, response)
+            }
+            WorkloadLookupError::EnvGroupUnavailable(group, _) => {
+                let response =
+                    RequestHandlerError::new(message, discriminant).with_retryable(true).with_detail("envGroup", group);
+                (StatusCode::PRECONDITION_FAILED, response)
+            }
+            WorkloadLookupError::WorkloadNotRunning => {
+                (StatusCode::PRECONDITION_FAILED, RequestHandlerError::new(message, discriminant).with_retryable(true))
             }
-            WorkloadLookupError::WorkloadNotFound => (StatusCode::NOT_FOUND, self.to_string()),
             WorkloadLookupError::Internal(e) => {
                 error!("Failed to process request: {e}");
-                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
+                let response = RequestHandlerError::new("internal error", discriminant).with_retryable(true);
+                (StatusCode::INTERNAL_SERVER_ERROR, response)
             }
         };
-        let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
         (code, Json(response)).into_response()
     }
 }
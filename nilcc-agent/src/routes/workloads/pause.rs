@@ -0,0 +1,34 @@
+use crate::{
+    auth::Caller,
+    routes::{AppState, Json, RequestHandlerError, workloads::authorize_workload},
+    services::workload::WorkloadLookupError,
+};
+use axum::extract::State;
+use nilcc_agent_models::workloads::pause::PauseWorkloadRequest;
+
+/// Pause a workload.
+///
+/// This freezes the workload's VM without releasing any of its resources, until the workload is resumed.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/pause",
+    operation_id = "pause_workload",
+    tag = "workloads",
+    request_body = PauseWorkloadRequest,
+    responses(
+        (status = 200, description = "The workload was paused"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The workload is not running", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    caller: Caller,
+    request: Json<PauseWorkloadRequest>,
+) -> Result<Json<()>, WorkloadLookupError> {
+    authorize_workload(&state, &caller, request.id).await?;
+    state.services.workload.pause_workload(request.id).await?;
+    Ok(Json(()))
+}
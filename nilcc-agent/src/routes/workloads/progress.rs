@@ -0,0 +1,32 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::workload::WorkloadLookupError,
+};
+use axum::extract::{Path, State};
+use nilcc_agent_models::workloads::progress::WorkloadProgressResponse;
+use uuid::Uuid;
+
+/// Get a workload's provisioning progress.
+///
+/// This returns every provisioning step the workload reached since it was created, along with the time it reached
+/// it. Progress is only tracked in memory, so workloads that were running before the agent was last restarted only
+/// report the steps reached since then.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/progress",
+    operation_id = "workload_progress",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = WorkloadProgressResponse),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+) -> Result<Json<WorkloadProgressResponse>, WorkloadLookupError> {
+    let progress = state.services.workload.workload_progress(path.0).await?;
+    Ok(Json(progress))
+}
@@ -0,0 +1,34 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::workload::WorkloadLookupError,
+};
+use axum::extract::{Path, State};
+use nilcc_agent_models::workloads::proxy_timeouts::UpdateProxyTimeoutsRequest;
+use uuid::Uuid;
+
+/// Change the timeouts the SNI proxy uses for a workload's traffic.
+///
+/// The proxy's config is reloaded right away, so this doesn't restart the workload. Any timeout that isn't set uses
+/// the agent's default.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/{workload_id}/proxy-timeouts",
+    operation_id = "update_workload_proxy_timeouts",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    request_body = UpdateProxyTimeoutsRequest,
+    responses(
+        (status = 200, description = "The timeouts were updated"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Json<UpdateProxyTimeoutsRequest>,
+) -> Result<Json<()>, WorkloadLookupError> {
+    state.services.workload.update_proxy_timeouts(path.0, request.0.proxy_timeouts).await?;
+    Ok(Json(()))
+}
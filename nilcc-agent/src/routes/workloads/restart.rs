@@ -1,15 +1,32 @@
 use crate::{
-    routes::{AppState, Json},
+    auth::Caller,
+    routes::{AppState, Json, RequestHandlerError, workloads::authorize_workload},
     services::workload::WorkloadLookupError,
 };
 use axum::extract::State;
 use nilcc_agent_models::workloads::restart::RestartWorkloadRequest;
 
+/// Restart a workload, optionally changing its environment variables.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/restart",
+    operation_id = "restart_workload",
+    tag = "workloads",
+    request_body = RestartWorkloadRequest,
+    responses(
+        (status = 200, description = "The workload was restarted"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
+    caller: Caller,
     request: Json<RestartWorkloadRequest>,
 ) -> Result<Json<()>, WorkloadLookupError> {
     let RestartWorkloadRequest { id, env_vars } = request.0;
+    authorize_workload(&state, &caller, id).await?;
     state.services.workload.restart_workload(id, env_vars).await?;
     Ok(Json(()))
 }
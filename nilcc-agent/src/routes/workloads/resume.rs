@@ -0,0 +1,31 @@
+use crate::{
+    auth::Caller,
+    routes::{AppState, Json, RequestHandlerError, workloads::authorize_workload},
+    services::workload::WorkloadLookupError,
+};
+use axum::extract::State;
+use nilcc_agent_models::workloads::resume::ResumeWorkloadRequest;
+
+/// Resume a paused workload.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/resume",
+    operation_id = "resume_workload",
+    tag = "workloads",
+    request_body = ResumeWorkloadRequest,
+    responses(
+        (status = 200, description = "The workload was resumed"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    caller: Caller,
+    request: Json<ResumeWorkloadRequest>,
+) -> Result<Json<()>, WorkloadLookupError> {
+    authorize_workload(&state, &caller, request.id).await?;
+    state.services.workload.resume_workload(request.id).await?;
+    Ok(Json(()))
+}
@@ -1,14 +1,32 @@
 use crate::{
-    routes::{AppState, Json},
+    auth::Caller,
+    routes::{AppState, Json, RequestHandlerError, workloads::authorize_workload},
     services::workload::WorkloadLookupError,
 };
 use axum::extract::State;
 use nilcc_agent_models::workloads::start::StartWorkloadRequest;
 
+/// Start a stopped workload.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/start",
+    operation_id = "start_workload",
+    tag = "workloads",
+    request_body = StartWorkloadRequest,
+    responses(
+        (status = 200, description = "The workload was started"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "There aren't enough resources to start the workload", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
+    caller: Caller,
     request: Json<StartWorkloadRequest>,
 ) -> Result<Json<()>, WorkloadLookupError> {
+    authorize_workload(&state, &caller, request.id).await?;
     state.services.workload.start_workload(request.id).await?;
     Ok(Json(()))
 }
@@ -1,14 +1,31 @@
 use crate::{
-    routes::{AppState, Json},
+    auth::Caller,
+    routes::{AppState, Json, RequestHandlerError, workloads::authorize_workload},
     services::workload::WorkloadLookupError,
 };
 use axum::extract::State;
 use nilcc_agent_models::workloads::stop::StopWorkloadRequest;
 
+/// Stop a workload.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/stop",
+    operation_id = "stop_workload",
+    tag = "workloads",
+    request_body = StopWorkloadRequest,
+    responses(
+        (status = 200, description = "The workload was stopped"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
+    caller: Caller,
     request: Json<StopWorkloadRequest>,
 ) -> Result<Json<()>, WorkloadLookupError> {
+    authorize_workload(&state, &caller, request.id).await?;
     state.services.workload.stop_workload(request.id).await?;
     Ok(Json(()))
 }
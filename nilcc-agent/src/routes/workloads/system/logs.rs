@@ -1,14 +1,37 @@
-use crate::routes::{AppState, Json, workloads::containers::CvmAgentHandlerError};
+use crate::routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError};
 use axum::extract::{Path, Query, State};
-use cvm_agent_models::logs::{SystemLogsRequest, SystemLogsResponse};
+use cvm_agent_models::{
+    encryption::MaybeEncrypted,
+    logs::{SystemLogsRequest, SystemLogsResponse},
+};
 use uuid::Uuid;
 
+/// Get the system logs for a workload's CVM.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/system/logs",
+    operation_id = "system_logs",
+    tag = "workloads",
+    params(
+        ("workload_id" = Uuid, Path, description = "The workload id"),
+        SystemLogsRequest,
+    ),
+    responses(
+        (status = 200, body = MaybeEncrypted<SystemLogsResponse>),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
     path: Path<Uuid>,
     request: Query<SystemLogsRequest>,
-) -> Result<Json<SystemLogsResponse>, CvmAgentHandlerError> {
+) -> Result<Json<MaybeEncrypted<SystemLogsResponse>>, CvmAgentHandlerError> {
     let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
     let response = state.clients.cvm_agent.system_logs(port, &request.0).await?;
     Ok(Json(response))
 }
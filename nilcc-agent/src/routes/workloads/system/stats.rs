@@ -1,13 +1,29 @@
-use crate::routes::{AppState, Json, workloads::containers::CvmAgentHandlerError};
+use crate::routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError};
 use axum::extract::{Path, State};
-use cvm_agent_models::stats::SystemStatsResponse;
+use cvm_agent_models::{encryption::MaybeEncrypted, stats::SystemStatsResponse};
 use uuid::Uuid;
 
+/// Get the system stats for a workload's CVM.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/system/stats",
+    operation_id = "system_stats",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = MaybeEncrypted<SystemStatsResponse>),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The CVM agent could not be reached", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
 pub(crate) async fn handler(
     state: State<AppState>,
     path: Path<Uuid>,
-) -> Result<Json<SystemStatsResponse>, CvmAgentHandlerError> {
+) -> Result<Json<MaybeEncrypted<SystemStatsResponse>>, CvmAgentHandlerError> {
     let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
     let response = state.clients.cvm_agent.system_stats(port).await?;
     Ok(Json(response))
 }
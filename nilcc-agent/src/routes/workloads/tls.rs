@@ -0,0 +1,51 @@
+use crate::{
+    clients::cvm_agent::CvmAgentRequestError,
+    routes::{AppState, Json, RequestHandlerError, workloads::containers::CvmAgentHandlerError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use cvm_agent_models::tls::TlsInfoResponse;
+use uuid::Uuid;
+
+/// Get the fingerprint of the TLS certificate a workload's CVM is serving.
+///
+/// This is the same fingerprint the CVM's attestation reports are bound to, and it changes whenever the certificate is
+/// renewed with a new key.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/tls",
+    operation_id = "workload_tls",
+    tag = "workloads",
+    params(("workload_id" = Uuid, Path, description = "The workload id")),
+    responses(
+        (status = 200, body = TlsInfoResponse),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (
+            status = 412,
+            description = "The CVM agent could not be reached or the certificate is not available yet",
+            body = RequestHandlerError
+        ),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+) -> Result<Json<TlsInfoResponse>, CvmAgentHandlerError> {
+    let port = state.services.workload.cvm_agent_port(path.0).await?;
+    let _permit = state.cvm_agent_limiter.acquire(path.0).await?;
+    match state.clients.cvm_agent.tls_info(port).await {
+        Ok(response) => Ok(Json(response)),
+        Err(CvmAgentRequestError::Http(e)) => match e.status() {
+            Some(StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY) => {
+                Err(CvmAgentHandlerError::CvmAgent("TLS certificate is not available yet"))
+            }
+            Some(StatusCode::NOT_FOUND) => Err(CvmAgentHandlerError::CvmAgent("cvm-agent does not report TLS info")),
+            _ => Err(CvmAgentRequestError::Http(e).into()),
+        },
+        Err(e) => Err(e.into()),
+    }
+}
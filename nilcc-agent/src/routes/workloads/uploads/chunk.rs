@@ -0,0 +1,47 @@
+use crate::{
+    routes::{AppState, Json, Query, RequestHandlerError},
+    services::upload::UploadError,
+};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+};
+use nilcc_agent_models::workloads::uploads::{UploadChunkQuery, UploadStatus};
+use uuid::Uuid;
+
+/// Upload a chunk of a file.
+///
+/// The request body is the raw chunk. Chunks must be sent in order, and the file's hash is checked once the last one
+/// is received.
+#[utoipa::path(
+    put,
+    path = "/api/v1/workloads/files/upload/{upload_id}",
+    operation_id = "upload_chunk",
+    tag = "workloads",
+    params(
+        ("upload_id" = Uuid, Path, description = "The upload id"),
+        UploadChunkQuery,
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, body = UploadStatus),
+        (
+            status = 400,
+            description = "The chunk goes past the end of the file or the file's hash doesn't match",
+            body = RequestHandlerError
+        ),
+        (status = 404, description = "The upload does not exist", body = RequestHandlerError),
+        (status = 409, description = "The offset doesn't match the bytes received so far", body = RequestHandlerError),
+        (status = 413, description = "The chunk is too large"),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    query: Query<UploadChunkQuery>,
+    chunk: Bytes,
+) -> Result<Json<UploadStatus>, UploadError> {
+    let status = state.services.upload.write_chunk(path.0, query.offset, &chunk).await?;
+    Ok(Json(status))
+}
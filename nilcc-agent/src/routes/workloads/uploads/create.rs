@@ -0,0 +1,30 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::upload::UploadError,
+};
+use axum::extract::State;
+use nilcc_agent_models::workloads::uploads::{CreateUploadRequest, CreateUploadResponse, MAX_UPLOAD_CHUNK_SIZE};
+
+/// Start uploading a file in chunks.
+///
+/// Once every chunk is uploaded, the returned id can be used to reference the file when creating a workload. Uploads
+/// that aren't used are deleted after a while.
+#[utoipa::path(
+    post,
+    path = "/api/v1/workloads/files/upload",
+    operation_id = "create_upload",
+    tag = "workloads",
+    request_body = CreateUploadRequest,
+    responses(
+        (status = 200, body = CreateUploadResponse),
+        (status = 400, description = "The request is malformed or the file is too large", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    request: Json<CreateUploadRequest>,
+) -> Result<Json<CreateUploadResponse>, UploadError> {
+    let id = state.services.upload.create_upload(request.size, request.sha256).await?;
+    Ok(Json(CreateUploadResponse { id, max_chunk_size: MAX_UPLOAD_CHUNK_SIZE as u64 }))
+}
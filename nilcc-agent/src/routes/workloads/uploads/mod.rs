@@ -0,0 +1,32 @@
+use crate::{
+    routes::{Json, RequestHandlerError},
+    services::upload::{UploadError, UploadErrorDiscriminants},
+};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tracing::error;
+
+pub(crate) mod chunk;
+pub(crate) mod create;
+pub(crate) mod status;
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        let discriminant = UploadErrorDiscriminants::from(&self);
+        let (code, message) = match self {
+            Self::UploadNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            Self::FileTooLarge(_) | Self::ChunkTooLarge | Self::HashMismatch | Self::Incomplete(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string())
+            }
+            Self::OffsetMismatch(_) => (StatusCode::CONFLICT, self.to_string()),
+            Self::Internal(e) => {
+                error!("Failed to process upload: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
+            }
+        };
+        let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
+        (code, Json(response)).into_response()
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{
+    routes::{AppState, Json, RequestHandlerError},
+    services::upload::UploadError,
+};
+use axum::extract::{Path, State};
+use nilcc_agent_models::workloads::uploads::UploadStatus;
+use uuid::Uuid;
+
+/// Get the state of an upload, e.g. to find where to resume it from.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/files/upload/{upload_id}",
+    operation_id = "upload_status",
+    tag = "workloads",
+    params(("upload_id" = Uuid, Path, description = "The upload id")),
+    responses(
+        (status = 200, body = UploadStatus),
+        (status = 404, description = "The upload does not exist", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(state: State<AppState>, path: Path<Uuid>) -> Result<Json<UploadStatus>, UploadError> {
+    let status = state.services.upload.upload_status(path.0).await?;
+    Ok(Json(status))
+}
@@ -0,0 +1,73 @@
+use crate::repositories::usage::UsageSample;
+use crate::routes::{AppState, Query, RequestHandlerError};
+use crate::services::usage::UsageServiceError;
+use axum::extract::{Path, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use nilcc_agent_models::workloads::usage::WorkloadUsageRequest;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const HEADER: &str = "workload_id,sampled_at,duration_seconds,cpus,gpus,memory_mb,disk_space_gb\n";
+
+/// Export the usage samples for a workload within a time range as CSV.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/usage/export",
+    operation_id = "export_workload_usage",
+    tag = "workloads",
+    params(
+        ("workload_id" = Uuid, Path, description = "The workload id"),
+        WorkloadUsageRequest,
+    ),
+    responses(
+        (status = 200, body = String, content_type = "text/csv"),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Query<WorkloadUsageRequest>,
+) -> Result<Response, UsageServiceError> {
+    let samples = state.services.usage.workload_samples(path.0, request.from, request.to).await?;
+    Ok(([(CONTENT_TYPE, "text/csv")], to_csv(&samples)).into_response())
+}
+
+fn to_csv(samples: &[UsageSample]) -> String {
+    let mut output = HEADER.to_string();
+    for sample in samples {
+        let UsageSample { workload_id, sampled_at, duration_seconds, cpus, gpus, memory_mb, disk_space_gb } = sample;
+        let sampled_at = sampled_at.to_rfc3339();
+        // None of these fields can contain commas or quotes so they don't need escaping.
+        let _ =
+            writeln!(output, "{workload_id},{sampled_at},{duration_seconds},{cpus},{gpus},{memory_mb},{disk_space_gb}");
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    #[test]
+    fn csv() {
+        let workload_id = Uuid::nil();
+        let sample = UsageSample {
+            workload_id,
+            sampled_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            duration_seconds: 60,
+            cpus: 2,
+            gpus: 1,
+            memory_mb: 2048,
+            disk_space_gb: 10,
+        };
+        let output = to_csv(&[sample]);
+        let expected = "workload_id,sampled_at,duration_seconds,cpus,gpus,memory_mb,disk_space_gb
+00000000-0000-0000-0000-000000000000,2023-11-14T22:13:20+00:00,60,2,1,2048,10
+";
+        assert_eq!(output, expected);
+    }
+}
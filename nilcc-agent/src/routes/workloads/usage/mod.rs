@@ -0,0 +1,19 @@
+use crate::routes::{Json, RequestHandlerError};
+use crate::services::usage::UsageServiceError;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use tracing::error;
+
+pub(crate) mod export;
+pub(crate) mod summary;
+
+impl IntoResponse for UsageServiceError {
+    fn into_response(self) -> Response {
+        match self {
+            UsageServiceError::Internal(e) => {
+                error!("Failed to process request: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(RequestHandlerError::internal())).into_response()
+            }
+        }
+    }
+}
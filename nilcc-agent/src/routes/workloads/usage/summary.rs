@@ -0,0 +1,51 @@
+use crate::routes::{AppState, Json, Query, RequestHandlerError};
+use crate::services::usage::{UsageServiceError, WorkloadUsage};
+use axum::extract::{Path, State};
+use nilcc_agent_models::workloads::usage::{WorkloadUsageRequest, WorkloadUsageResponse};
+use uuid::Uuid;
+
+const SECONDS_PER_HOUR: f64 = 3600.0;
+
+/// Get the resources allocated to a workload within a time range.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/usage",
+    operation_id = "workload_usage",
+    tag = "workloads",
+    params(
+        ("workload_id" = Uuid, Path, description = "The workload id"),
+        WorkloadUsageRequest,
+    ),
+    responses(
+        (status = 200, body = WorkloadUsageResponse),
+        (status = 400, description = "The request is malformed", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    request: Query<WorkloadUsageRequest>,
+) -> Result<Json<WorkloadUsageResponse>, UsageServiceError> {
+    let id = path.0;
+    let usage = state.services.usage.workload_usage(id, request.from, request.to).await?;
+    let WorkloadUsage {
+        first_sample_at,
+        last_sample_at,
+        running_seconds,
+        cpu_seconds,
+        gpu_seconds,
+        memory_mb_seconds,
+        disk_gb_seconds,
+    } = usage;
+    Ok(Json(WorkloadUsageResponse {
+        id,
+        first_sample_at,
+        last_sample_at,
+        running_seconds,
+        cpu_hours: cpu_seconds as f64 / SECONDS_PER_HOUR,
+        gpu_hours: gpu_seconds as f64 / SECONDS_PER_HOUR,
+        memory_gb_hours: memory_mb_seconds as f64 / 1024.0 / SECONDS_PER_HOUR,
+        disk_gb_hours: disk_gb_seconds as f64 / SECONDS_PER_HOUR,
+    }))
+}
@@ -0,0 +1,159 @@
+use crate::{
+    clients::cvm_agent::CvmAgentRequestError,
+    routes::{AppState, Json, Query, RequestHandlerError, workloads::containers::CvmAgentHandlerError},
+};
+use axum::extract::{Path, State};
+use cvm_agent_models::{container::ComposeStateResponse, health::HealthResponse};
+use nilcc_agent_models::workloads::wait::{MAX_WAIT_TIMEOUT_SECONDS, WaitWorkloadQuery, WaitWorkloadResponse};
+use reqwest::StatusCode;
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+use uuid::Uuid;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Wait until a workload is ready.
+///
+/// This blocks until the workload is bootstrapped, serving HTTPS, and all of its services converged, until
+/// bootstrapping fails, or until the timeout is reached, and returns the workload's readiness at that point.
+#[utoipa::path(
+    get,
+    path = "/api/v1/workloads/{workload_id}/wait",
+    operation_id = "wait_workload",
+    tag = "workloads",
+    params(
+        ("workload_id" = Uuid, Path, description = "The workload id"),
+        WaitWorkloadQuery,
+    ),
+    responses(
+        (status = 200, body = WaitWorkloadResponse),
+        (status = 404, description = "The workload does not exist", body = RequestHandlerError),
+        (status = 412, description = "The workload is paused", body = RequestHandlerError),
+        (status = 429, description = "Too many requests are being made to the CVM agent", body = RequestHandlerError),
+        (status = 500, description = "An internal error occurred", body = RequestHandlerError),
+    )
+)]
+pub(crate) async fn handler(
+    state: State<AppState>,
+    path: Path<Uuid>,
+    query: Query<WaitWorkloadQuery>,
+) -> Result<Json<WaitWorkloadResponse>, CvmAgentHandlerError> {
+    let id = path.0;
+    let timeout = Duration::from_secs(query.timeout_seconds.clamp(1, MAX_WAIT_TIMEOUT_SECONDS));
+    let deadline = Instant::now() + timeout;
+    loop {
+        let response = check_readiness(&state, id).await?;
+        if response.ready || response.bootstrap_error.is_some() || Instant::now() + POLL_INTERVAL > deadline {
+            return Ok(Json(response));
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn check_readiness(state: &AppState, id: Uuid) -> Result<WaitWorkloadResponse, CvmAgentHandlerError> {
+    if state.services.workload.workload_paused(id).await? {
+        return Err(CvmAgentHandlerError::CvmAgent("workload is paused"));
+    }
+    let port = state.services.workload.cvm_agent_port(id).await?;
+    let _permit = state.cvm_agent_limiter.acquire(id).await?;
+    let health = match state.clients.cvm_agent.check_health(port).await.map_err(CvmAgentHandlerError::from) {
+        Ok(health) => health,
+        // The CVM may still be booting, in which case its agent can't be reached yet.
+        Err(CvmAgentHandlerError::CvmAgent(_)) => return Ok(WaitWorkloadResponse::default()),
+        Err(e) => return Err(e),
+    };
+    if !health.bootstrapped || !health.https {
+        return Ok(readiness(health, None));
+    }
+    let compose_state = match state.clients.cvm_agent.compose_state(port).await {
+        Ok(compose_state) => Some(compose_state),
+        // Older agents don't report compose state so there's nothing else to check.
+        Err(CvmAgentRequestError::Http(e)) if e.status() == Some(StatusCode::NOT_FOUND) => None,
+        Err(e) => match CvmAgentHandlerError::from(e) {
+            CvmAgentHandlerError::CvmAgent(_) => return Ok(WaitWorkloadResponse::default()),
+            e => return Err(e),
+        },
+    };
+    Ok(readiness(health, compose_state))
+}
+
+fn readiness(health: HealthResponse, compose_state: Option<ComposeStateResponse>) -> WaitWorkloadResponse {
+    let HealthResponse { https, bootstrapped, last_event, bootstrap, .. } = health;
+    // Only a bootstrap that stopped running failed for good, otherwise the failed step is being retried.
+    let bootstrap_error = bootstrap.filter(|bootstrap| !bootstrap.running).and_then(|bootstrap| bootstrap.error);
+    let (converged, last_error) = match compose_state {
+        Some(ComposeStateResponse { converged, last_error, .. }) => (converged, last_error),
+        None => (bootstrapped && https, None),
+    };
+    let mut last_events = Vec::new();
+    for event in [last_event, last_error].into_iter().flatten() {
+        let event = format!("{:?} at {}: {}", event.kind, event.timestamp, event.message);
+        if !last_events.contains(&event) {
+            last_events.push(event);
+        }
+    }
+    WaitWorkloadResponse {
+        ready: bootstrapped && https && converged,
+        bootstrapped,
+        https,
+        converged,
+        bootstrap_error,
+        last_events,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use cvm_agent_models::{
+        bootstrap::{BootstrapStatus, BootstrapStep},
+        health::{EventKind, LastEvent},
+    };
+
+    fn make_health(bootstrapped: bool, https: bool) -> HealthResponse {
+        HealthResponse {
+            https,
+            bootstrapped,
+            last_event: None,
+            bootstrap: None,
+            certificate: None,
+            paused: false,
+            drift: None,
+        }
+    }
+
+    #[test]
+    fn ready() {
+        let compose_state = ComposeStateResponse { converged: true, services: vec![], last_error: None };
+        let response = readiness(make_health(true, true), Some(compose_state));
+        assert!(response.ready);
+
+        let compose_state = ComposeStateResponse { converged: false, services: vec![], last_error: None };
+        let response = readiness(make_health(true, true), Some(compose_state));
+        assert!(!response.ready);
+
+        let response = readiness(make_health(true, false), None);
+        assert!(!response.ready);
+        assert!(!response.converged);
+    }
+
+    #[test]
+    fn bootstrap_failure() {
+        let event = LastEvent { id: 1, kind: EventKind::Error, message: "pull failed".into(), timestamp: Utc::now() };
+        let failed_health = |running| {
+            let status =
+                BootstrapStatus { step: BootstrapStep::PullImages, running, error: Some("pull failed".into()) };
+            HealthResponse { last_event: Some(event.clone()), bootstrap: Some(status), ..make_health(false, false) }
+        };
+        let response = readiness(failed_health(true), None);
+        assert_eq!(response.bootstrap_error, None);
+        assert_eq!(response.last_events.len(), 1);
+
+        let compose_state =
+            ComposeStateResponse { converged: false, services: vec![], last_error: Some(event.clone()) };
+        let response = readiness(failed_health(false), Some(compose_state));
+        assert_eq!(response.bootstrap_error.as_deref(), Some("pull failed"));
+        assert_eq!(response.last_events.len(), 1);
+    }
+}
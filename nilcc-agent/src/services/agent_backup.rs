@@ -0,0 +1,253 @@
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::{fs, process::Command};
+use tracing::{info, warn};
+
+/// An agent upgrade that can be rolled back.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct UpgradeRecord {
+    /// The version the agent was upgraded from.
+    pub previous_version: String,
+
+    /// The version the agent was upgraded to.
+    pub version: String,
+
+    /// When the upgrade was started.
+    pub started_at: DateTime<Utc>,
+
+    /// The number of times the upgraded agent started.
+    pub boot_attempts: u32,
+
+    /// Whether the upgraded agent was confirmed to be working.
+    pub confirmed: bool,
+}
+
+/// What to do with an agent that just started.
+#[derive(Debug, PartialEq)]
+pub enum BootCheck {
+    /// There's no upgrade pending confirmation.
+    Normal,
+
+    /// The agent was upgraded and needs to be confirmed to be working.
+    PendingConfirmation(UpgradeRecord),
+
+    /// The upgraded agent failed to start too many times and needs to be rolled back.
+    RollBack(UpgradeRecord),
+}
+
+/// Keeps the previous agent binary and config around during upgrades so they can be rolled back.
+#[derive(Clone)]
+pub struct AgentBackup {
+    agent_path: PathBuf,
+    config_path: PathBuf,
+}
+
+impl AgentBackup {
+    pub fn new(agent_path: PathBuf, config_path: PathBuf) -> Self {
+        Self { agent_path, config_path }
+    }
+
+    /// Back up the current agent binary and config and record an upgrade to the given version.
+    pub async fn create(&self, previous_version: &str, version: &str) -> anyhow::Result<()> {
+        info!("Backing up agent binary and config before upgrading to {version}");
+        fs::copy(&self.agent_path, self.previous_agent_path()).await.context("Failed to back up agent binary")?;
+        fs::copy(&self.config_path, self.previous_config_path()).await.context("Failed to back up config")?;
+        let record = UpgradeRecord {
+            previous_version: previous_version.to_string(),
+            version: version.to_string(),
+            started_at: Utc::now(),
+            boot_attempts: 0,
+            confirmed: false,
+        };
+        self.save_record(&record).await
+    }
+
+    /// Load the last upgrade record, if any.
+    pub async fn load_record(&self) -> anyhow::Result<Option<UpgradeRecord>> {
+        match fs::read(self.record_path()).await {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents).context("Invalid upgrade record")?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read upgrade record"),
+        }
+    }
+
+    /// Persist an upgrade record.
+    pub async fn save_record(&self, record: &UpgradeRecord) -> anyhow::Result<()> {
+        let contents = serde_json::to_vec(record)?;
+        write_atomically(&self.record_path(), &contents).await.context("Failed to write upgrade record")
+    }
+
+    /// Discard the upgrade record, which makes the upgrade impossible to roll back.
+    pub async fn discard_record(&self) -> anyhow::Result<()> {
+        match fs::remove_file(self.record_path()).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to delete upgrade record"),
+        }
+    }
+
+    /// Check whether an agent running the given version needs its upgrade confirmed or rolled back.
+    ///
+    /// Every call counts as a boot attempt for an unconfirmed upgrade.
+    pub async fn check_boot(&self, current_version: &str, max_boot_attempts: u32) -> anyhow::Result<BootCheck> {
+        let Some(mut record) = self.load_record().await? else {
+            return Ok(BootCheck::Normal);
+        };
+        if record.confirmed {
+            return Ok(BootCheck::Normal);
+        }
+        if record.version != current_version {
+            // The binary was never replaced, e.g. because the updater failed.
+            warn!("Discarding upgrade record for version {} since we're running {current_version}", record.version);
+            self.discard_record().await?;
+            return Ok(BootCheck::Normal);
+        }
+        record.boot_attempts += 1;
+        self.save_record(&record).await?;
+        if record.boot_attempts > max_boot_attempts {
+            Ok(BootCheck::RollBack(record))
+        } else {
+            Ok(BootCheck::PendingConfirmation(record))
+        }
+    }
+
+    /// Restore the backed up agent binary and config and discard the upgrade record.
+    pub async fn restore(&self) -> anyhow::Result<()> {
+        let previous_agent = fs::read(self.previous_agent_path()).await.context("Failed to read previous agent")?;
+        let previous_config = fs::read(self.previous_config_path()).await.context("Failed to read previous config")?;
+        write_atomically(&self.config_path, &previous_config).await.context("Failed to restore config")?;
+        // The running binary can't be written to but it can be replaced.
+        write_atomically(&self.agent_path, &previous_agent).await.context("Failed to restore agent binary")?;
+        let permissions = fs::metadata(self.previous_agent_path()).await?.permissions();
+        fs::set_permissions(&self.agent_path, permissions).await.context("Failed to set agent permissions")?;
+        self.discard_record().await
+    }
+
+    /// Restore the backed up agent and restart the agent service so it takes effect.
+    pub async fn roll_back(&self) -> anyhow::Result<()> {
+        self.restore().await?;
+        info!("Restarting agent to complete rollback");
+        let status = Command::new("systemctl")
+            .args(["restart", "nilcc-agent"])
+            .status()
+            .await
+            .context("Failed to run systemctl")?;
+        if !status.success() {
+            bail!("systemctl restart failed with status {status}");
+        }
+        Ok(())
+    }
+
+    fn previous_agent_path(&self) -> PathBuf {
+        with_suffix(&self.agent_path, ".previous")
+    }
+
+    fn previous_config_path(&self) -> PathBuf {
+        with_suffix(&self.config_path, ".previous")
+    }
+
+    fn record_path(&self) -> PathBuf {
+        with_suffix(&self.agent_path, ".upgrade.json")
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+async fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = with_suffix(path, ".tmp");
+    fs::write(&tmp_path, contents).await?;
+    fs::rename(&tmp_path, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{TempDir, tempdir};
+
+    struct Context {
+        backup: AgentBackup,
+        dir: TempDir,
+    }
+
+    impl Context {
+        async fn new() -> Self {
+            let dir = tempdir().expect("failed to create tempdir");
+            let agent_path = dir.path().join("nilcc-agent");
+            let config_path = dir.path().join("config.yaml");
+            fs::write(&agent_path, "agent v1").await.expect("failed to write agent");
+            fs::write(&config_path, "config v1").await.expect("failed to write config");
+            Self { backup: AgentBackup::new(agent_path, config_path), dir }
+        }
+
+        async fn upgrade(&self) {
+            self.backup.create("v1", "v2").await.expect("failed to create backup");
+            fs::write(self.dir.path().join("nilcc-agent"), "agent v2").await.expect("failed to write agent");
+            fs::write(self.dir.path().join("config.yaml"), "config v2").await.expect("failed to write config");
+        }
+
+        async fn read(&self, name: &str) -> String {
+            fs::read_to_string(self.dir.path().join(name)).await.expect("failed to read")
+        }
+    }
+
+    #[tokio::test]
+    async fn restore() {
+        let ctx = Context::new().await;
+        ctx.upgrade().await;
+        ctx.backup.restore().await.expect("failed to restore");
+
+        assert_eq!(ctx.read("nilcc-agent").await, "agent v1");
+        assert_eq!(ctx.read("config.yaml").await, "config v1");
+        assert_eq!(ctx.backup.load_record().await.expect("failed to load"), None);
+    }
+
+    #[tokio::test]
+    async fn boot_attempts() {
+        let ctx = Context::new().await;
+        ctx.upgrade().await;
+
+        for attempt in 1..=3 {
+            let check = ctx.backup.check_boot("v2", 3).await.expect("failed to check");
+            let BootCheck::PendingConfirmation(record) = check else { panic!("unexpected check: {check:?}") };
+            assert_eq!(record.boot_attempts, attempt);
+        }
+        let check = ctx.backup.check_boot("v2", 3).await.expect("failed to check");
+        assert!(matches!(check, BootCheck::RollBack(_)), "unexpected check: {check:?}");
+    }
+
+    #[tokio::test]
+    async fn confirmed_upgrade() {
+        let ctx = Context::new().await;
+        ctx.upgrade().await;
+
+        let mut record = ctx.backup.load_record().await.expect("failed to load").expect("no record");
+        record.confirmed = true;
+        ctx.backup.save_record(&record).await.expect("failed to save");
+        assert_eq!(ctx.backup.check_boot("v2", 0).await.expect("failed to check"), BootCheck::Normal);
+    }
+
+    #[tokio::test]
+    async fn version_mismatch() {
+        let ctx = Context::new().await;
+        ctx.upgrade().await;
+
+        assert_eq!(ctx.backup.check_boot("v1", 3).await.expect("failed to check"), BootCheck::Normal);
+        assert_eq!(ctx.backup.load_record().await.expect("failed to load"), None);
+    }
+
+    #[tokio::test]
+    async fn no_upgrade() {
+        let ctx = Context::new().await;
+        assert_eq!(ctx.backup.check_boot("v1", 3).await.expect("failed to check"), BootCheck::Normal);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::{repositories::sqlite::SqliteDb, version};
+use anyhow::{Context, bail};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqliteConnectOptions;
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tempfile::TempDir;
+use tokio::{fs, process::Command, task::spawn_blocking};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// The version of the backup format, bumped whenever the manifest changes in an incompatible way.
+const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// The name of the database snapshot within a backup.
+const DATABASE_FILE_NAME: &str = "agent.sqlite";
+
+/// The name of the manifest within a backup.
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Describes the contents of a backup and the state files it expects to find on the host.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct BackupManifest {
+    /// The version of the backup format.
+    pub version: u32,
+
+    /// The id of the agent the backup was taken from.
+    pub agent_id: Uuid,
+
+    /// The version of the agent the backup was taken from.
+    pub agent_version: String,
+
+    /// When the backup was taken.
+    pub created_at: DateTime<Utc>,
+
+    /// The hex encoded sha256 hash of the database snapshot.
+    pub database_sha256: String,
+
+    /// The files in the VM store that belong to workloads, like ISOs and state disks.
+    pub state_files: Vec<StateFile>,
+}
+
+/// A file in the VM store that belongs to a workload.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct StateFile {
+    /// The workload this file belongs to.
+    pub workload_id: Uuid,
+
+    /// The file name, relative to the VM store.
+    pub name: String,
+
+    /// The file size, in bytes.
+    pub size: u64,
+
+    /// The hex encoded sha256 hash of the file's contents.
+    pub sha256: String,
+}
+
+/// A backup archive that's ready to be sent.
+///
+/// The archive lives in a temporary directory that is deleted once this is dropped.
+pub struct BackupArchive {
+    pub path: PathBuf,
+    pub size: u64,
+    _dir: TempDir,
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait BackupService: Send + Sync {
+    /// Create a backup containing a consistent snapshot of the database and a manifest of the state files.
+    async fn create_backup(&self) -> Result<BackupArchive, BackupError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("internal: {0:#}")]
+pub struct BackupError(#[from] anyhow::Error);
+
+pub struct DefaultBackupServiceArgs {
+    pub db: SqliteDb,
+    pub vm_store: PathBuf,
+    pub agent_id: Uuid,
+}
+
+pub struct DefaultBackupService {
+    db: SqliteDb,
+    vm_store: PathBuf,
+    agent_id: Uuid,
+}
+
+impl DefaultBackupService {
+    pub fn new(args: DefaultBackupServiceArgs) -> Self {
+        let DefaultBackupServiceArgs { db, vm_store, agent_id } = args;
+        Self { db, vm_store, agent_id }
+    }
+
+    async fn snapshot_database(&self, path: &Path) -> anyhow::Result<()> {
+        // `VACUUM INTO` takes a transactionally consistent copy even while other connections are writing.
+        let path = path.to_str().context("Non UTF-8 snapshot path")?;
+        sqlx::query("VACUUM INTO ?").bind(path).execute(&self.db.0).await.context("Failed to snapshot database")?;
+        Ok(())
+    }
+
+    async fn find_state_files(&self, workload_ids: &[Uuid]) -> anyhow::Result<Vec<StateFile>> {
+        let mut state_files = Vec::new();
+        let mut entries = fs::read_dir(&self.vm_store).await.context("Failed to read VM store")?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Every file the agent creates for a workload is prefixed by its id, e.g. `<id>.iso`.
+            let Some(workload_id) = workload_ids.iter().find(|id| name.starts_with(&format!("{id}."))) else {
+                continue;
+            };
+            let path = entry.path();
+            // Disks backed by logical volumes are symlinks to the volume's device, so links are followed.
+            let file_type = fs::metadata(&path).await.with_context(|| format!("Failed to stat {name}"))?.file_type();
+            if !file_type.is_file() && !file_type.is_block_device() {
+                continue;
+            }
+            let (size, sha256) = hash_file(path.clone()).await.with_context(|| format!("Failed to hash {name}"))?;
+            state_files.push(StateFile { workload_id: *workload_id, name, size, sha256 });
+        }
+        state_files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(state_files)
+    }
+}
+
+#[async_trait]
+impl BackupService for DefaultBackupService {
+    async fn create_backup(&self) -> Result<BackupArchive, BackupError> {
+        let dir = tempfile::tempdir().context("Failed to create tempdir")?;
+        let contents_path = dir.path().join("contents");
+        fs::create_dir(&contents_path).await.context("Failed to create contents directory")?;
+
+        info!("Taking database snapshot");
+        let database_path = contents_path.join(DATABASE_FILE_NAME);
+        self.snapshot_database(&database_path).await?;
+        let (_, database_sha256) = hash_file(database_path).await.context("Failed to hash database snapshot")?;
+
+        let workload_ids: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM workloads")
+            .fetch_all(&self.db.0)
+            .await
+            .context("Failed to list workloads")?;
+        info!("Hashing state files for {} workloads", workload_ids.len());
+        let state_files = self.find_state_files(&workload_ids).await?;
+        let manifest = BackupManifest {
+            version: BACKUP_FORMAT_VERSION,
+            agent_id: self.agent_id,
+            agent_version: version::agent_version().to_string(),
+            created_at: Utc::now(),
+            database_sha256,
+            state_files,
+        };
+        let manifest = serde_json::to_vec_pretty(&manifest).context("Failed to serialize manifest")?;
+        fs::write(contents_path.join(MANIFEST_FILE_NAME), manifest).await.context("Failed to write manifest")?;
+
+        let path = dir.path().join("backup.tar");
+        run_tar(&[
+            "-c".as_ref(),
+            "-f".as_ref(),
+            path.as_os_str(),
+            "-C".as_ref(),
+            contents_path.as_os_str(),
+            MANIFEST_FILE_NAME.as_ref(),
+            DATABASE_FILE_NAME.as_ref(),
+        ])
+        .await?;
+        let size = fs::metadata(&path).await.context("Failed to stat backup")?.len();
+        info!("Created backup of {size} bytes");
+        Ok(BackupArchive { path, size, _dir: dir })
+    }
+}
+
+/// Get the path to the file backing a sqlite database URL.
+pub fn database_path(database_url: &str) -> anyhow::Result<PathBuf> {
+    Ok(SqliteConnectOptions::from_str(database_url)?.get_filename().to_path_buf())
+}
+
+/// Restore the database from a backup archive.
+///
+/// The manifest and the database snapshot are validated before the database is put in place. State files that are
+/// missing or don't match the manifest are returned so they can be reported, as workloads using them will likely
+/// fail to start.
+pub async fn restore_backup(
+    archive: &Path,
+    database_path: &Path,
+    vm_store: &Path,
+    agent_id: Uuid,
+) -> anyhow::Result<Vec<StateFileMismatch>> {
+    let dir = tempfile::tempdir().context("Failed to create tempdir")?;
+    run_tar(&["-x".as_ref(), "-f".as_ref(), archive.as_os_str(), "-C".as_ref(), dir.path().as_os_str()]).await?;
+
+    let manifest = fs::read(dir.path().join(MANIFEST_FILE_NAME)).await.context("Failed to read backup manifest")?;
+    let manifest: BackupManifest = serde_json::from_slice(&manifest).context("Invalid backup manifest")?;
+    validate_manifest(&manifest)?;
+    if manifest.agent_id != agent_id {
+        warn!("Restoring backup taken by agent {} into agent {agent_id}", manifest.agent_id);
+    }
+
+    let snapshot_path = dir.path().join(DATABASE_FILE_NAME);
+    let (_, database_sha256) = hash_file(snapshot_path.clone()).await.context("Failed to hash database snapshot")?;
+    if database_sha256 != manifest.database_sha256 {
+        bail!("database snapshot hash mismatch: expected {}, got {database_sha256}", manifest.database_sha256);
+    }
+
+    let mut mismatches = Vec::new();
+    for file in manifest.state_files {
+        let path = vm_store.join(&file.name);
+        let sha256 = match hash_file(path).await {
+            Ok((_, sha256)) => Some(sha256),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("Failed to hash {}", file.name)),
+        };
+        if let Some(mismatch) = check_state_file(file, sha256) {
+            mismatches.push(mismatch);
+        }
+    }
+
+    info!("Restoring database backup taken at {} into {}", manifest.created_at, database_path.display());
+    if let Some(parent) = database_path.parent() {
+        fs::create_dir_all(parent).await.context("Failed to create database directory")?;
+    }
+    fs::copy(&snapshot_path, database_path).await.context("Failed to restore database")?;
+    Ok(mismatches)
+}
+
+/// A state file referenced by a backup that doesn't match what's on disk.
+#[derive(Debug, PartialEq)]
+pub enum StateFileMismatch {
+    Missing(StateFile),
+    HashMismatch { file: StateFile, sha256: String },
+}
+
+fn validate_manifest(manifest: &BackupManifest) -> anyhow::Result<()> {
+    if manifest.version != BACKUP_FORMAT_VERSION {
+        bail!("unsupported backup format version {}, expected {BACKUP_FORMAT_VERSION}", manifest.version);
+    }
+    for file in &manifest.state_files {
+        // State files are looked up relative to the VM store so they can't point anywhere else.
+        if file.name.contains('/') || file.name.starts_with('.') {
+            bail!("invalid state file name '{}'", file.name);
+        }
+    }
+    Ok(())
+}
+
+fn check_state_file(file: StateFile, sha256: Option<String>) -> Option<StateFileMismatch> {
+    match sha256 {
+        None => Some(StateFileMismatch::Missing(file)),
+        Some(sha256) if sha256 != file.sha256 => Some(StateFileMismatch::HashMismatch { file, sha256 }),
+        Some(_) => None,
+    }
+}
+
+async fn hash_file(path: PathBuf) -> io::Result<(u64, String)> {
+    spawn_blocking(move || {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut file, &mut hasher)?;
+        Ok((size, hex::encode(hasher.finalize())))
+    })
+    .await?
+}
+
+async fn run_tar(args: &[&OsStr]) -> anyhow::Result<()> {
+    let output = Command::new("tar").args(args).output().await.context("Failed to invoke tar")?;
+    if !output.status.success() {
+        bail!("tar failed: {}", String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::{
+        sqlite::{RepositoryProvider, SqliteRepositoryProvider},
+        workload::utils::make_workload,
+    };
+
+    fn make_manifest(state_files: Vec<StateFile>) -> BackupManifest {
+        BackupManifest {
+            version: BACKUP_FORMAT_VERSION,
+            agent_id: Uuid::new_v4(),
+            agent_version: "0.1.0".into(),
+            created_at: Utc::now(),
+            database_sha256: "aa".repeat(32),
+            state_files,
+        }
+    }
+
+    fn make_state_file(name: &str) -> StateFile {
+        StateFile { workload_id: Uuid::new_v4(), name: name.into(), size: 42, sha256: "bb".repeat(32) }
+    }
+
+    #[test]
+    fn valid_manifest() {
+        let manifest = make_manifest(vec![make_state_file("foo.iso")]);
+        validate_manifest(&manifest).expect("invalid manifest");
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let manifest = BackupManifest { version: BACKUP_FORMAT_VERSION + 1, ..make_manifest(vec![]) };
+        validate_manifest(&manifest).expect_err("manifest was valid");
+    }
+
+    #[test]
+    fn invalid_state_file_names() {
+        for name in ["../foo.iso", "a/b.iso", ".hidden"] {
+            let manifest = make_manifest(vec![make_state_file(name)]);
+            validate_manifest(&manifest).expect_err("manifest was valid");
+        }
+    }
+
+    #[test]
+    fn state_file_checks() {
+        let file = make_state_file("foo.iso");
+        assert_eq!(check_state_file(file.clone(), Some(file.sha256.clone())), None);
+        assert_eq!(check_state_file(file.clone(), None), Some(StateFileMismatch::Missing(file.clone())));
+        assert_eq!(
+            check_state_file(file.clone(), Some("cc".into())),
+            Some(StateFileMismatch::HashMismatch { file, sha256: "cc".into() })
+        );
+    }
+
+    #[tokio::test]
+    async fn hash_files() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("foo");
+        fs::write(&path, "hello").await.expect("failed to write");
+        let (size, sha256) = hash_file(path).await.expect("failed to hash");
+        assert_eq!(size, 5);
+        assert_eq!(sha256, "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+    }
+
+    #[tokio::test]
+    async fn backup_round_trip() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let url = format!("sqlite://{}", dir.path().join("db.sqlite").display());
+        let db = SqliteDb::connect(&url).await.expect("failed to create db");
+        let workload = make_workload();
+        let provider = SqliteRepositoryProvider::new(db.clone());
+        let mut repo = provider.workloads(Default::default()).await.expect("failed to get repo");
+        repo.create(&workload).await.expect("failed to create workload");
+
+        let vm_store = dir.path().join("vms");
+        std::fs::create_dir(&vm_store).expect("failed to create VM store");
+        std::fs::write(vm_store.join(format!("{}.iso", workload.id)), b"iso").expect("failed to write file");
+        std::fs::write(vm_store.join("unrelated.iso"), b"other").expect("failed to write file");
+        // Disks backed by volumes are symlinks to the volume's device.
+        let volume = dir.path().join("volume");
+        std::fs::write(&volume, b"disk").expect("failed to write file");
+        let disk_name = format!("{}.state.qcow2", workload.id);
+        std::os::unix::fs::symlink(&volume, vm_store.join(&disk_name)).expect("failed to create symlink");
+
+        let agent_id = Uuid::new_v4();
+        let service = DefaultBackupService::new(DefaultBackupServiceArgs { db, vm_store: vm_store.clone(), agent_id });
+        let archive = service.create_backup().await.expect("failed to create backup");
+
+        let restored_path = dir.path().join("restored/db.sqlite");
+        let mismatches =
+            restore_backup(&archive.path, &restored_path, &vm_store, agent_id).await.expect("failed to restore");
+        assert!(mismatches.is_empty(), "{mismatches:?}");
+        let url = format!("sqlite://{}", restored_path.display());
+        let provider = SqliteRepositoryProvider::new(SqliteDb::connect(&url).await.expect("failed to open db"));
+        let mut repo = provider.workloads(Default::default()).await.expect("failed to get repo");
+        assert_eq!(repo.find(workload.id).await.expect("workload not restored"), workload);
+
+        // The volume changed and the ISO is gone after the backup was taken.
+        std::fs::write(&volume, b"changed").expect("failed to write file");
+        std::fs::remove_file(vm_store.join(format!("{}.iso", workload.id))).expect("failed to delete file");
+        let restored_path = dir.path().join("restored-again/db.sqlite");
+        let mismatches =
+            restore_backup(&archive.path, &restored_path, &vm_store, agent_id).await.expect("failed to restore");
+        let names: Vec<_> = mismatches
+            .iter()
+            .map(|mismatch| match mismatch {
+                StateFileMismatch::Missing(file) => format!("missing {}", file.name),
+                StateFileMismatch::HashMismatch { file, .. } => format!("changed {}", file.name),
+            })
+            .collect();
+        assert_eq!(names, &[format!("missing {}.iso", workload.id), format!("changed {disk_name}")]);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::repositories::workload::Workload;
+use anyhow::{Context, bail};
+use async_trait::async_trait;
+use nilcc_agent_models::workloads::create::BandwidthLimits;
+use std::{collections::BTreeMap, process::Stdio};
+use tokio::{io::AsyncWriteExt, process::Command, sync::Mutex};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// The interface the SNI proxy reaches VMs' forwarded ports through.
+const LOOPBACK_DEVICE: &str = "lo";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LimitedVm {
+    pub(crate) id: Uuid,
+    /// The host ports the VM's public traffic is forwarded through.
+    pub(crate) ports: Vec<u16>,
+    pub(crate) limits: BandwidthLimits,
+}
+
+impl LimitedVm {
+    /// The bandwidth limits for a workload's VM, if it has any.
+    pub(crate) fn new(workload: &Workload) -> Option<Self> {
+        let limits = workload.bandwidth_limits.filter(|l| l.ingress_mbps.is_some() || l.egress_mbps.is_some())?;
+        Some(Self { id: workload.id, ports: vec![workload.http_port(), workload.https_port()], limits })
+    }
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait BandwidthLimiter: Send + Sync {
+    /// Limit a VM's bandwidth, replacing any limits it previously had.
+    async fn limit_vm(&self, vm: LimitedVm);
+
+    /// Remove a VM's bandwidth limits, if it has any.
+    async fn unlimit_vm(&self, id: Uuid);
+}
+
+/// Limits VMs' bandwidth by shaping the traffic on their forwarded ports via `tc`.
+///
+/// Public traffic reaches VMs through the SNI proxy, which connects to their forwarded ports over the loopback
+/// interface. Packets sent to those ports are a VM's ingress and packets sent from them its egress, so each limited
+/// direction gets its own HTB class on the loopback interface. The whole configuration is rebuilt every time a VM's
+/// limits change, and `tc` is never invoked unless some VM has limits.
+#[derive(Default)]
+pub struct TcBandwidthLimiter {
+    vms: Mutex<BTreeMap<Uuid, LimitedVm>>,
+}
+
+impl TcBandwidthLimiter {
+    async fn apply(&self, vms: &BTreeMap<Uuid, LimitedVm>) -> anyhow::Result<()> {
+        // This fails if there's no root qdisc yet, which is fine.
+        let _ = Command::new("tc").args(["qdisc", "del", "dev", LOOPBACK_DEVICE, "root"]).output().await;
+        if vms.is_empty() {
+            return Ok(());
+        }
+        let commands = render_commands(LOOPBACK_DEVICE, vms.values());
+        let mut child = Command::new("tc")
+            .args(["-batch", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run tc")?;
+        let mut stdin = child.stdin.take().context("No stdin")?;
+        stdin.write_all(commands.as_bytes()).await.context("Failed to write tc commands")?;
+        drop(stdin);
+        let output = child.wait_with_output().await.context("Failed to wait for tc")?;
+        if !output.status.success() {
+            bail!("tc failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BandwidthLimiter for TcBandwidthLimiter {
+    async fn limit_vm(&self, vm: LimitedVm) {
+        let mut vms = self.vms.lock().await;
+        let id = vm.id;
+        if vms.get(&id) == Some(&vm) {
+            return;
+        }
+        info!("Limiting bandwidth for VM {id} to {:?}", vm.limits);
+        vms.insert(id, vm);
+        if let Err(e) = self.apply(&vms).await {
+            warn!("Failed to apply bandwidth limits for VM {id}: {e:#}");
+        }
+    }
+
+    async fn unlimit_vm(&self, id: Uuid) {
+        let mut vms = self.vms.lock().await;
+        if vms.remove(&id).is_none() {
+            return;
+        }
+        info!("Removing bandwidth limits for VM {id}");
+        if let Err(e) = self.apply(&vms).await {
+            warn!("Failed to remove bandwidth limits for VM {id}: {e:#}");
+        }
+    }
+}
+
+/// Render the `tc` batch commands that limit the given VMs.
+///
+/// Traffic that doesn't match any filter isn't assigned to any class, which HTB sends out without limiting it.
+fn render_commands<'a>(device: &str, vms: impl IntoIterator<Item = &'a LimitedVm>) -> String {
+    let mut commands = vec![format!("qdisc add dev {device} root handle 1: htb")];
+    let filter = format!("filter add dev {device} parent 1: protocol ip prio 1 u32");
+    let mut class_id = 0;
+    for vm in vms {
+        let LimitedVm { ports, limits, .. } = vm;
+        for (rate, direction) in [(limits.ingress_mbps, "dport"), (limits.egress_mbps, "sport")] {
+            let Some(rate) = rate else { continue };
+            class_id += 1;
+            commands.push(format!("class add dev {device} parent 1: classid 1:{class_id:x} htb rate {rate}mbit"));
+            for port in ports {
+                commands.push(format!("{filter} match ip {direction} {port} 0xffff flowid 1:{class_id:x}"));
+            }
+        }
+    }
+    commands.push(String::new());
+    commands.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let vms = [
+            LimitedVm {
+                id: Uuid::new_v4(),
+                ports: vec![1000, 1001],
+                limits: BandwidthLimits { ingress_mbps: Some(100), egress_mbps: Some(50) },
+            },
+            LimitedVm {
+                id: Uuid::new_v4(),
+                ports: vec![1003, 1004],
+                limits: BandwidthLimits { ingress_mbps: None, egress_mbps: Some(10) },
+            },
+        ];
+        let commands = render_commands("lo", &vms);
+        let expected = "qdisc add dev lo root handle 1: htb
+class add dev lo parent 1: classid 1:1 htb rate 100mbit
+filter add dev lo parent 1: protocol ip prio 1 u32 match ip dport 1000 0xffff flowid 1:1
+filter add dev lo parent 1: protocol ip prio 1 u32 match ip dport 1001 0xffff flowid 1:1
+class add dev lo parent 1: classid 1:2 htb rate 50mbit
+filter add dev lo parent 1: protocol ip prio 1 u32 match ip sport 1000 0xffff flowid 1:2
+filter add dev lo parent 1: protocol ip prio 1 u32 match ip sport 1001 0xffff flowid 1:2
+class add dev lo parent 1: classid 1:3 htb rate 10mbit
+filter add dev lo parent 1: protocol ip prio 1 u32 match ip sport 1003 0xffff flowid 1:3
+filter add dev lo parent 1: protocol ip prio 1 u32 match ip sport 1004 0xffff flowid 1:3
+";
+        assert_eq!(commands, expected);
+    }
+}
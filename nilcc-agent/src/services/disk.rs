@@ -1,18 +1,65 @@
+use crate::repositories::workload::StoredFile;
 use anyhow::{Context, bail};
 use async_trait::async_trait;
+use cvm_agent_models::bootstrap::{CADDY_ACME_EAB_KEY_ID, CADDY_ACME_EAB_MAC_KEY};
 use nilcc_artifacts::metadata::DiskFormat;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
     fmt::Write,
+    fs::{File, FileTimes},
     io,
     path::{Path, PathBuf},
     process::{ExitStatus, Stdio},
+    time::{Duration, SystemTime},
 };
 use tokio::{
-    fs::{self, create_dir_all},
+    fs::{self, create_dir_all, symlink},
     process::Command,
+    sync::Mutex,
+    task,
 };
-use tracing::info;
+use tracing::{info, warn};
+
+/// The timestamp used for every file and directory in application ISOs (2020-01-01T00:00:00Z).
+const ISO_TIMESTAMP_SECONDS: u64 = 1_577_836_800;
+
+/// The name of the file in application ISOs that contains the token used to authenticate to `cvm-agent`.
+const API_TOKEN_FILE: &str = "api-token";
+
+/// The files in application ISOs that hold secrets and are therefore left out of their content hash.
+const SECRET_FILES: &[&str] = &[".env", API_TOKEN_FILE];
+
+/// The list of reserved environment variable names.
+pub(crate) static RESERVED_ENVIRONMENT_VARIABLES: &[&str] = &[
+    "NILCC_VERSION",
+    "NILCC_VM_TYPE",
+    "NILCC_DOMAIN",
+    "NILCC_WORKLOAD_ID",
+    "NILCC_AGENT_ID",
+    "FILES",
+    "CADDY_INPUT_FILE",
+    "CADDY_LOGS_DIR",
+    "CADDY_TLS_DIR",
+    CADDY_ACME_EAB_KEY_ID,
+    CADDY_ACME_EAB_MAC_KEY,
+];
+
+/// The prefix used for the names of the logical volumes created for workload disks.
+const LOGICAL_VOLUME_PREFIX: &str = "nilcc-";
+
+/// The prefix used for the names of the logical volumes base disks are imported into.
+///
+/// This doesn't clash with workload disks since those are always named after a workload id.
+const BASE_VOLUME_PREFIX: &str = "nilcc-base-";
+
+/// The suffix of a base volume's name while its disk is being imported into it.
+const IMPORT_VOLUME_SUFFIX: &str = "-import";
+
+/// The space reserved on top of a qcow2 image's virtual size for its metadata when it's stored in a volume.
+const QCOW2_OVERHEAD_GIB: u64 = 1;
+
+const BYTES_PER_GIB: u64 = 1024 * 1024 * 1024;
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
@@ -23,8 +70,17 @@ pub trait DiskService: Send + Sync {
     /// Create a qemu disk snapshot.
     async fn create_qcow2_snapshot(&self, target: &Path, origin: &Path) -> anyhow::Result<()>;
 
-    /// Create the ISO for an application.
-    async fn create_application_iso(&self, path: &Path, spec: IsoSpec) -> Result<(), CreateIsoError>;
+    /// Grow a disk created by this service so it can hold the given number of GiB.
+    async fn resize_disk(&self, path: &Path, format: DiskFormat, size_gib: u32) -> anyhow::Result<()>;
+
+    /// Delete a disk or ISO created by this service.
+    async fn delete_disk(&self, path: &Path) -> anyhow::Result<()>;
+
+    /// Get the usage of the storage pool disks are created in, or `None` if they're created in the VM store.
+    async fn pool_usage(&self) -> anyhow::Result<Option<StoragePoolUsage>>;
+
+    /// Create the ISO for an application, returning the content hash of the files written into it.
+    async fn create_application_iso(&self, path: &Path, spec: IsoSpec) -> Result<[u8; 32], CreateIsoError>;
 }
 
 pub struct DefaultDiskService {
@@ -36,12 +92,51 @@ impl DefaultDiskService {
         Self { qemu_img_path }
     }
 
-    fn serialize_environment_variables(variables: &[EnvironmentVariable]) -> String {
-        let mut output = String::new();
-        for EnvironmentVariable { name, value } in variables {
-            writeln!(output, "{name}={value}").expect("cannot happen");
+    async fn normalize_timestamps(path: PathBuf) -> io::Result<()> {
+        task::spawn_blocking(move || Self::normalize_timestamps_sync(&path)).await.map_err(io::Error::other)?
+    }
+
+    fn normalize_timestamps_sync(path: &Path) -> io::Result<()> {
+        if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                Self::normalize_timestamps_sync(&entry.path())?;
+            }
         }
-        output
+        // Directories are processed after their children since creating files in them updates their timestamps.
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(ISO_TIMESTAMP_SECONDS);
+        let times = FileTimes::new().set_accessed(timestamp).set_modified(timestamp);
+        File::open(path)?.set_times(times)
+    }
+
+    /// Compute the content hash of the files staged to be written into an ISO.
+    async fn staged_content_hash(path: PathBuf) -> io::Result<[u8; 32]> {
+        task::spawn_blocking(move || {
+            let mut entries = Vec::new();
+            Self::collect_staged_entries(&path, &path, &mut entries)?;
+            Ok(hash_entries(entries))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    fn collect_staged_entries(root: &Path, path: &Path, entries: &mut Vec<HashEntry>) -> io::Result<()> {
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::collect_staged_entries(root, &path, entries)?;
+                continue;
+            }
+            let name = path.strip_prefix(root).map_err(io::Error::other)?.to_string_lossy().into_owned();
+            if SECRET_FILES.contains(&name.as_str()) {
+                continue;
+            }
+            let mut hasher = Sha256::new();
+            let size = io::copy(&mut File::open(&path)?, &mut hasher)?;
+            entries.push((name, size, hasher.finalize().into()));
+        }
+        Ok(())
     }
 
     async fn persist_files(&self, base_path: &Path, files: Vec<ExternalFile>) -> Result<(), CreateIsoError> {
@@ -61,23 +156,40 @@ impl DefaultDiskService {
             if let Some(parent) = target_path.parent() {
                 fs::create_dir_all(parent).await.map_err(FilesWrite)?;
             }
-            fs::write(target_path, &file.contents).await.map_err(FilesWrite)?;
+            match &file.contents {
+                ExternalFileContents::Inline(contents) => fs::write(target_path, contents).await.map_err(FilesWrite)?,
+                ExternalFileContents::Stored(stored) => {
+                    fs::copy(&stored.path, target_path).await.map_err(FilesWrite)?;
+                }
+            };
         }
         Ok(())
     }
 
-    async fn qemu_img(&self, args: &[&str]) -> anyhow::Result<()> {
+    async fn qemu_img(&self, args: &[&str]) -> anyhow::Result<Vec<u8>> {
         let output = Command::new(&self.qemu_img_path)
             .args(args)
             .output()
             .await
             .context("Failed to invoke qemu-img (is qemu-img path correct?)")?;
         if output.status.success() {
-            Ok(())
+            Ok(output.stdout)
         } else {
             bail!("qemu-img failed: {}", String::from_utf8_lossy(&output.stderr))
         }
     }
+
+    async fn virtual_size_bytes(&self, path: &Path) -> anyhow::Result<u64> {
+        #[derive(Deserialize)]
+        struct ImageInfo {
+            #[serde(rename = "virtual-size")]
+            virtual_size: u64,
+        }
+
+        let output = self.qemu_img(&["info", "--output=json", &path.to_string_lossy()]).await?;
+        let info: ImageInfo = serde_json::from_slice(&output).context("Invalid qemu-img info output")?;
+        Ok(info.virtual_size)
+    }
 }
 
 #[async_trait]
@@ -85,18 +197,35 @@ impl DiskService for DefaultDiskService {
     async fn create_disk(&self, path: &Path, format: DiskFormat, size_gib: u32) -> anyhow::Result<()> {
         let format = format.to_string();
         let args = ["create", "-f", &format, &path.to_string_lossy(), &format!("{size_gib}G")];
-        self.qemu_img(&args).await
+        self.qemu_img(&args).await?;
+        Ok(())
     }
 
     async fn create_qcow2_snapshot(&self, target: &Path, origin: &Path) -> anyhow::Result<()> {
         let format = "qcow2";
         let args = ["create", "-f", format, "-b", &origin.to_string_lossy(), "-F", format, &target.to_string_lossy()];
-        self.qemu_img(&args).await
+        self.qemu_img(&args).await?;
+        Ok(())
+    }
+
+    async fn resize_disk(&self, path: &Path, format: DiskFormat, size_gib: u32) -> anyhow::Result<()> {
+        let format = format.to_string();
+        self.qemu_img(&["resize", "-f", &format, &path.to_string_lossy(), &format!("{size_gib}G")]).await?;
+        Ok(())
+    }
+
+    async fn delete_disk(&self, path: &Path) -> anyhow::Result<()> {
+        fs::remove_file(path).await.with_context(|| format!("Failed to delete {}", path.display()))
     }
 
-    async fn create_application_iso(&self, path: &Path, spec: IsoSpec) -> Result<(), CreateIsoError> {
+    async fn pool_usage(&self) -> anyhow::Result<Option<StoragePoolUsage>> {
+        Ok(None)
+    }
+
+    async fn create_application_iso(&self, path: &Path, spec: IsoSpec) -> Result<[u8; 32], CreateIsoError> {
         use CreateIsoError::*;
-        let IsoSpec { docker_compose_yaml, metadata, environment_variables, files } = spec;
+        let volume_id = spec.volume_id()?;
+        let IsoSpec { docker_compose_yaml, metadata, environment_variables, files, api_token } = spec;
 
         let tempdir = tempfile::TempDir::with_prefix("nilcc-agent").map_err(Tempdir)?;
         let input_path = tempdir.path().join("contents");
@@ -107,16 +236,26 @@ impl DiskService for DefaultDiskService {
         let metadata = serde_json::to_string(&metadata)?;
         fs::write(input_path.join("docker-compose.yaml"), &docker_compose_yaml).await.map_err(FilesWrite)?;
         fs::write(input_path.join("metadata.json"), &metadata).await.map_err(FilesWrite)?;
+        if let Some(api_token) = api_token {
+            fs::write(input_path.join(API_TOKEN_FILE), api_token).await.map_err(FilesWrite)?;
+        }
 
-        let variables = Self::serialize_environment_variables(&environment_variables);
+        let variables = serialize_environment_variables(&environment_variables);
         fs::write(input_path.join(".env"), &variables).await.map_err(FilesWrite)?;
+        Self::normalize_timestamps(input_path.clone()).await.map_err(FilesWrite)?;
+        let content_hash = Self::staged_content_hash(input_path.clone()).await.map_err(HashFiles)?;
 
-        info!("Invoking mkisofs to generate ISO in {}", path.display());
+        info!("Invoking mkisofs to generate ISO with volume id {volume_id} in {}", path.display());
         let mut child = Command::new("mkisofs")
             .arg("-U")
+            .args(["-input-charset", "utf-8"])
+            .args(["-V", &volume_id])
+            .args(["-A", "nilcc", "-p", "nilcc-agent", "-sysid", "LINUX"])
             .arg("-o")
             .arg(path)
             .arg(input_path)
+            // Makes the volume creation and modification dates fixed.
+            .env("SOURCE_DATE_EPOCH", ISO_TIMESTAMP_SECONDS.to_string())
             .stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(Stdio::null())
@@ -127,8 +266,257 @@ impl DiskService for DefaultDiskService {
             return Err(MkisofsExit(status));
         }
         info!("ISO file generated at {}", path.display());
+        Ok(content_hash)
+    }
+}
+
+/// A disk service that creates disks as thin logical volumes in an LVM thin pool.
+///
+/// Every disk is backed by a logical volume named after the path it's requested at, and that path is a symlink to the
+/// volume's device so the rest of the agent keeps treating disks as paths in the VM store. This gives thin
+/// provisioning and cheap snapshots on dedicated disks. ISOs are still created as regular files.
+///
+/// Base disks are imported into the pool the first time they're snapshotted and every snapshot is a thin snapshot of
+/// that volume. The imported volume is removed once its last snapshot is deleted.
+pub struct LvmDiskService {
+    files: DefaultDiskService,
+    volume_group: String,
+    thin_pool: String,
+    // Held while base volumes are imported or removed so snapshots are never taken of a volume that's going away.
+    base_volumes: Mutex<()>,
+}
+
+impl LvmDiskService {
+    pub fn new(files: DefaultDiskService, volume_group: String, thin_pool: String) -> Self {
+        Self { files, volume_group, thin_pool, base_volumes: Mutex::new(()) }
+    }
+
+    fn volume_name(path: &Path) -> anyhow::Result<String> {
+        let file_name = path.file_name().and_then(|name| name.to_str()).context("Invalid disk path")?;
+        let valid = file_name.chars().all(|c| c.is_ascii_alphanumeric() || "+_.-".contains(c));
+        if !valid {
+            bail!("Invalid disk name: {file_name}");
+        }
+        Ok(format!("{LOGICAL_VOLUME_PREFIX}{file_name}"))
+    }
+
+    fn base_volume_name(origin: &Path) -> String {
+        let hash = Sha256::digest(origin.as_os_str().as_encoded_bytes());
+        format!("{BASE_VOLUME_PREFIX}{}", hex::encode(&hash[0..8]))
+    }
+
+    fn device_path(&self, volume_name: &str) -> PathBuf {
+        Path::new("/dev").join(&self.volume_group).join(volume_name)
+    }
+
+    fn qualified_name(&self, volume_name: &str) -> String {
+        format!("{}/{volume_name}", self.volume_group)
+    }
+
+    async fn lvm(&self, command: &str, args: &[&str]) -> anyhow::Result<String> {
+        let output = Command::new(command)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .with_context(|| format!("Failed to invoke {command} (is LVM installed?)"))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            bail!("{command} failed: {}", String::from_utf8_lossy(&output.stderr))
+        }
+    }
+
+    /// Find the volumes in our volume group that match an `lvs` selection.
+    async fn find_volumes(&self, selection: &str) -> anyhow::Result<Vec<String>> {
+        let output = self.lvm("lvs", &["--noheadings", "-o", "lv_name", "-S", selection, &self.volume_group]).await?;
+        Ok(output.lines().map(|line| line.trim().to_string()).filter(|line| !line.is_empty()).collect())
+    }
+
+    async fn volume_exists(&self, name: &str) -> anyhow::Result<bool> {
+        Ok(!self.find_volumes(&format!("lv_name={name}")).await?.is_empty())
+    }
+
+    async fn create_volume(&self, name: &str, size_gib: u64) -> anyhow::Result<PathBuf> {
+        let pool = self.qualified_name(&self.thin_pool);
+        info!("Creating {size_gib}GiB thin volume {name} in {pool}");
+        self.lvm("lvcreate", &["--thin", "--virtualsize", &format!("{size_gib}G"), "--name", name, &pool]).await?;
+        Ok(self.device_path(name))
+    }
+
+    async fn remove_volume(&self, name: &str) -> anyhow::Result<()> {
+        info!("Removing thin volume {name}");
+        self.lvm("lvremove", &["--yes", &self.qualified_name(name)]).await?;
         Ok(())
     }
+
+    /// Get the volume holding a copy of a base disk, importing the disk into the pool if it's not there yet.
+    ///
+    /// The disk is copied into a temporary volume that's only renamed once the copy is complete, so an interrupted
+    /// import is never used as a base.
+    async fn base_volume(&self, origin: &Path) -> anyhow::Result<String> {
+        let name = Self::base_volume_name(origin);
+        if self.volume_exists(&name).await? {
+            return Ok(name);
+        }
+        let import_name = format!("{name}{IMPORT_VOLUME_SUFFIX}");
+        if self.volume_exists(&import_name).await? {
+            warn!("Removing leftover volume {import_name} from an interrupted import");
+            self.remove_volume(&import_name).await?;
+        }
+        // The image is copied as is, so leave room for the qcow2 metadata to grow as snapshots are written to.
+        let size_gib = self.files.virtual_size_bytes(origin).await?.div_ceil(BYTES_PER_GIB) + QCOW2_OVERHEAD_GIB;
+        let device = self.create_volume(&import_name, size_gib).await?;
+        info!("Importing base disk {} into volume {name}", origin.display());
+        let imported = match copy_to_device(origin, &device).await {
+            Ok(()) => self.lvm("lvrename", &[&self.volume_group, &import_name, &name]).await.map(|_| ()),
+            Err(e) => Err(anyhow::Error::new(e).context("Failed to copy base disk")),
+        };
+        if let Err(e) = imported {
+            if let Err(e) = self.remove_volume(&import_name).await {
+                warn!("Failed to remove volume {import_name}: {e:#}");
+            }
+            return Err(e);
+        }
+        Ok(name)
+    }
+
+    /// Remove a base volume if none of the remaining volumes are snapshots of it.
+    async fn remove_unused_base_volume(&self, name: &str) -> anyhow::Result<()> {
+        if self.find_volumes(&format!("origin={name}")).await?.is_empty() {
+            info!("Removing base volume {name} since it has no snapshots left");
+            self.remove_volume(name).await?;
+        }
+        Ok(())
+    }
+
+    async fn volume_origin(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let origin = self.lvm("lvs", &["--noheadings", "-o", "origin", &self.qualified_name(name)]).await?;
+        Ok(Some(origin).filter(|origin| !origin.is_empty()))
+    }
+
+    /// Whether the given path is a link to the volume with the given name.
+    async fn is_volume_link(&self, path: &Path, name: &str) -> bool {
+        fs::read_link(path).await.is_ok_and(|target| target == self.device_path(name))
+    }
+
+    /// Link an initialized volume at the given path.
+    ///
+    /// The volume is removed if initializing or linking it failed so that it's not leaked.
+    async fn link_volume(&self, name: &str, path: &Path, initialized: anyhow::Result<()>) -> anyhow::Result<()> {
+        let result = match initialized {
+            Ok(()) => symlink(self.device_path(name), path).await.context("Failed to link volume"),
+            Err(e) => Err(e),
+        };
+        if result.is_err()
+            && let Err(e) = self.remove_volume(name).await
+        {
+            warn!("Failed to remove volume {name}: {e:#}");
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl DiskService for LvmDiskService {
+    async fn create_disk(&self, path: &Path, format: DiskFormat, size_gib: u32) -> anyhow::Result<()> {
+        let name = Self::volume_name(path)?;
+        let initialized = match format {
+            // The volume itself is the raw disk.
+            DiskFormat::Raw => {
+                self.create_volume(&name, size_gib.into()).await?;
+                Ok(())
+            }
+            DiskFormat::Qcow2 => {
+                let device = self.create_volume(&name, u64::from(size_gib) + QCOW2_OVERHEAD_GIB).await?;
+                self.files.create_disk(&device, format, size_gib).await
+            }
+        };
+        self.link_volume(&name, path, initialized).await
+    }
+
+    async fn create_qcow2_snapshot(&self, target: &Path, origin: &Path) -> anyhow::Result<()> {
+        let name = Self::volume_name(target)?;
+        let _guard = self.base_volumes.lock().await;
+        let base = self.base_volume(origin).await?;
+        info!("Creating snapshot {name} of base volume {base}");
+        // Thin snapshots are skipped during activation by default, which would leave them without a device.
+        let args = ["--snapshot", "--setactivationskip", "n", "--name", &name, &self.qualified_name(&base)];
+        self.lvm("lvcreate", &args).await?;
+        self.link_volume(&name, target, Ok(())).await
+    }
+
+    async fn resize_disk(&self, path: &Path, format: DiskFormat, size_gib: u32) -> anyhow::Result<()> {
+        let name = Self::volume_name(path)?;
+        if !self.is_volume_link(path, &name).await {
+            bail!("{} is not linked to volume {name}", path.display());
+        }
+        let volume_size_gib = match format {
+            DiskFormat::Raw => u64::from(size_gib),
+            DiskFormat::Qcow2 => u64::from(size_gib) + QCOW2_OVERHEAD_GIB,
+        };
+        info!("Resizing volume {name} to {volume_size_gib}GiB");
+        self.lvm("lvextend", &["--size", &format!("{volume_size_gib}G"), &self.qualified_name(&name)]).await?;
+        match format {
+            DiskFormat::Raw => Ok(()),
+            DiskFormat::Qcow2 => self.files.resize_disk(&self.device_path(&name), format, size_gib).await,
+        }
+    }
+
+    async fn delete_disk(&self, path: &Path) -> anyhow::Result<()> {
+        let name = Self::volume_name(path)?;
+        // Anything that isn't linked to one of our volumes, like ISOs, is a regular file.
+        if self.is_volume_link(path, &name).await {
+            let _guard = self.base_volumes.lock().await;
+            let origin = self.volume_origin(&name).await?;
+            self.remove_volume(&name).await?;
+            if let Some(origin) = origin.filter(|origin| origin.starts_with(BASE_VOLUME_PREFIX)) {
+                // The disk is gone either way, so failing to clean up its base only leaves it around until the next
+                // snapshot of it is deleted.
+                if let Err(e) = self.remove_unused_base_volume(&origin).await {
+                    warn!("Failed to remove base volume {origin}: {e:#}");
+                }
+            }
+        }
+        self.files.delete_disk(path).await
+    }
+
+    async fn pool_usage(&self) -> anyhow::Result<Option<StoragePoolUsage>> {
+        let pool = self.qualified_name(&self.thin_pool);
+        let args = ["--noheadings", "--nosuffix", "--units", "b", "-o", "lv_size,data_percent", &pool];
+        let output = self.lvm("lvs", &args).await?;
+        parse_pool_usage(&output).map(Some).with_context(|| format!("Invalid lvs output for {pool}: {output}"))
+    }
+
+    async fn create_application_iso(&self, path: &Path, spec: IsoSpec) -> Result<[u8; 32], CreateIsoError> {
+        self.files.create_application_iso(path, spec).await
+    }
+}
+
+/// The space in the storage pool disks are created in, when they don't live in the VM store.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StoragePoolUsage {
+    /// The pool's size, in bytes.
+    pub size_bytes: u64,
+
+    /// The number of bytes in the pool that aren't in use yet.
+    pub free_bytes: u64,
+}
+
+/// Parse the `lv_size,data_percent` columns `lvs` outputs for a thin pool.
+fn parse_pool_usage(output: &str) -> Option<StoragePoolUsage> {
+    let mut columns = output.split_whitespace();
+    let size_bytes: u64 = columns.next()?.parse().ok()?;
+    let used_percent: f64 = columns.next()?.parse().ok()?;
+    let used_bytes = (size_bytes as f64 * used_percent / 100.0) as u64;
+    Some(StoragePoolUsage { size_bytes, free_bytes: size_bytes.saturating_sub(used_bytes) })
+}
+
+async fn copy_to_device(source: &Path, device: &Path) -> io::Result<()> {
+    let mut source = fs::File::open(source).await?;
+    let mut device = fs::OpenOptions::new().write(true).open(device).await?;
+    tokio::io::copy(&mut source, &mut device).await?;
+    device.sync_all().await
 }
 
 /// An environment variable.
@@ -147,20 +535,48 @@ impl EnvironmentVariable {
     }
 }
 
-/// The contents of a file to be written into the ISO.
+/// A file to be written into the ISO.
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExternalFile {
     /// The filename.
     pub name: String,
 
     /// The contents of this file.
-    pub contents: Vec<u8>,
+    pub contents: ExternalFileContents,
 }
 
 impl ExternalFile {
     pub fn new<S: Into<String>, B: Into<Vec<u8>>>(name: S, contents: B) -> Self {
-        Self { name: name.into(), contents: contents.into() }
+        Self { name: name.into(), contents: ExternalFileContents::Inline(contents.into()) }
     }
+
+    pub fn stored<S: Into<String>>(name: S, file: StoredFile) -> Self {
+        Self { name: name.into(), contents: ExternalFileContents::Stored(file) }
+    }
+
+    fn sha256(&self) -> [u8; 32] {
+        match &self.contents {
+            ExternalFileContents::Inline(contents) => Sha256::digest(contents).into(),
+            ExternalFileContents::Stored(file) => file.sha256,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match &self.contents {
+            ExternalFileContents::Inline(contents) => contents.len() as u64,
+            ExternalFileContents::Stored(file) => file.size,
+        }
+    }
+}
+
+/// Where the contents of a file come from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExternalFileContents {
+    /// The contents are held in memory.
+    Inline(Vec<u8>),
+
+    /// The contents are in a file on disk, which is copied into the ISO rather than loaded.
+    Stored(StoredFile),
 }
 
 /// Information about the API container that will be the entrypoint to the VM image.
@@ -181,6 +597,10 @@ pub struct ApplicationMetadata {
 
     /// The entrypoint container information.
     pub api: ContainerMetadata,
+
+    /// Whether the state disk is encrypted using a key sealed to the CVM's measurement rather than a random one.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub sealed_state_disk: bool,
 }
 
 /// The spec for the ISO being created.
@@ -197,6 +617,67 @@ pub struct IsoSpec {
 
     /// The files to be accessible in the docker compose.
     pub files: Vec<ExternalFile>,
+
+    /// The token the agent authenticates to the CVM's `cvm-agent` with, if any.
+    ///
+    /// This is a secret that belongs to the agent rather than an input of the workload.
+    pub api_token: Option<String>,
+}
+
+impl IsoSpec {
+    /// Compute the hash of the contents of the ISO generated for this spec, without building it.
+    ///
+    /// This matches the hash [`DiskService::create_application_iso`] computes over the files it writes into the ISO.
+    /// Hashing the files' hashes means stored files never need to be read.
+    pub fn content_hash(&self) -> Result<[u8; 32], serde_json::Error> {
+        let file_entry = |path: &str, contents: &[u8]| {
+            (path.to_string(), contents.len() as u64, <[u8; 32]>::from(Sha256::digest(contents)))
+        };
+        let mut entries = vec![
+            file_entry("docker-compose.yaml", self.docker_compose_yaml.as_bytes()),
+            file_entry("metadata.json", &serde_json::to_vec(&self.metadata)?),
+        ];
+        entries.extend(self.files.iter().map(|file| (format!("files/{}", file.name), file.size(), file.sha256())));
+        Ok(hash_entries(entries))
+    }
+
+    /// The ISO volume id, derived from the content hash.
+    fn volume_id(&self) -> Result<String, serde_json::Error> {
+        let hash = self.content_hash()?;
+        Ok(format!("NILCC_{}", hex::encode_upper(&hash[0..8])))
+    }
+}
+
+/// A file in an application ISO: its path, its length, and the SHA256 hash of its contents.
+type HashEntry = (String, u64, [u8; 32]);
+
+/// Hash the files in an application ISO, leaving out the ones that hold secrets.
+///
+/// This is the SHA256 hash over every file, sorted by path, where each file is encoded as its path, a zero byte, its
+/// length as a big endian u64, and the SHA256 hash of its contents.
+fn hash_entries(mut entries: Vec<HashEntry>) -> [u8; 32] {
+    entries.retain(|(path, _, _)| !SECRET_FILES.contains(&path.as_str()));
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut hasher = Sha256::new();
+    for (path, size, sha256) in entries {
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update(size.to_be_bytes());
+        hasher.update(sha256);
+    }
+    hasher.finalize().into()
+}
+
+fn serialize_environment_variables(variables: &[EnvironmentVariable]) -> String {
+    let mut variables: Vec<_> = variables.iter().collect();
+    variables.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut output = String::new();
+    for EnvironmentVariable { name, value } in variables {
+        writeln!(output, "{name}={value}").expect("cannot happen");
+    }
+    output
 }
 
 /// An error when creating an application ISO.
@@ -211,6 +692,9 @@ pub enum CreateIsoError {
     #[error("failed to write ISO files: {0}")]
     FilesWrite(io::Error),
 
+    #[error("hashing ISO files: {0}")]
+    HashFiles(io::Error),
+
     #[error("spawning mkisofs: {0}")]
     SpawnMkisofs(io::Error),
 
@@ -240,16 +724,22 @@ mod tests {
     #[tokio::test]
     async fn persist_files() {
         let service = make_service();
+        let workdir = tempdir().expect("failed to create tempdir");
+        let stored_path = workdir.path().join("stored");
+        std::fs::write(&stored_path, b"stored").expect("failed to write");
+        let stored = StoredFile { path: stored_path, sha256: Sha256::digest(b"stored").into(), size: 6 };
         let files = vec![
-            ExternalFile { name: "foo.txt".into(), contents: b"hi".into() },
-            ExternalFile { name: "bar/tar.txt".into(), contents: b"bye".into() },
+            ExternalFile::new("foo.txt", b"hi"),
+            ExternalFile::new("bar/tar.txt", b"bye"),
+            ExternalFile::stored("model.bin", stored),
         ];
-        let workdir = tempdir().expect("failed to create tempdir");
-        let base_path = workdir.path();
+        let base_path = workdir.path().join("contents");
+        std::fs::create_dir(&base_path).expect("failed to create dir");
         service.persist_files(&base_path, files).await.expect("failed to persist");
 
         assert_eq!(std::fs::read_to_string(base_path.join("files/foo.txt")).expect("failed to read"), "hi");
         assert_eq!(std::fs::read_to_string(base_path.join("files/bar/tar.txt")).expect("failed to read"), "bye");
+        assert_eq!(std::fs::read_to_string(base_path.join("files/model.bin")).expect("failed to read"), "stored");
     }
 
     #[rstest]
@@ -259,10 +749,138 @@ mod tests {
     #[tokio::test]
     async fn non_relative_file_paths(#[case] path: &str) {
         let service = make_service();
-        let files = vec![ExternalFile { name: path.into(), contents: b"hi".into() }];
+        let files = vec![ExternalFile::new(path, b"hi")];
         let workdir = tempdir().expect("failed to create tempdir");
         let base_path = workdir.path();
         let err = service.persist_files(&base_path, files).await.expect_err("persist succeeded");
         assert!(matches!(err, CreateIsoError::RelativePath(_)));
     }
+
+    fn make_spec(environment_variables: Vec<EnvironmentVariable>, files: Vec<ExternalFile>) -> IsoSpec {
+        IsoSpec {
+            docker_compose_yaml: "services: {}".into(),
+            metadata: ApplicationMetadata {
+                hostname: "example.com".into(),
+                api: ContainerMetadata { container: "api".into(), port: 80 },
+                sealed_state_disk: false,
+            },
+            environment_variables,
+            files,
+            api_token: None,
+        }
+    }
+
+    #[test]
+    fn content_hash_is_order_independent() {
+        let first = make_spec(
+            vec![EnvironmentVariable::new("A", "1"), EnvironmentVariable::new("B", "2")],
+            vec![ExternalFile::new("foo.txt", b"hi"), ExternalFile::new("bar/tar.txt", b"bye")],
+        );
+        let second = make_spec(
+            vec![EnvironmentVariable::new("B", "2"), EnvironmentVariable::new("A", "1")],
+            vec![ExternalFile::new("bar/tar.txt", b"bye"), ExternalFile::new("foo.txt", b"hi")],
+        );
+        assert_eq!(first.content_hash().unwrap(), second.content_hash().unwrap());
+        assert_eq!(first.volume_id().unwrap(), second.volume_id().unwrap());
+    }
+
+    #[rstest]
+    #[case::file_contents(vec![EnvironmentVariable::new("A", "1")], vec![ExternalFile::new("foo.txt", b"bye")])]
+    #[case::file_name(vec![EnvironmentVariable::new("A", "1")], vec![ExternalFile::new("bar.txt", b"hi")])]
+    fn content_hash_changes(#[case] environment_variables: Vec<EnvironmentVariable>, #[case] files: Vec<ExternalFile>) {
+        let base = make_spec(vec![EnvironmentVariable::new("A", "1")], vec![ExternalFile::new("foo.txt", b"hi")]);
+        let other = make_spec(environment_variables, files);
+        assert_ne!(base.content_hash().unwrap(), other.content_hash().unwrap());
+    }
+
+    #[test]
+    fn content_hash_excludes_secrets() {
+        let base = make_spec(vec![EnvironmentVariable::new("A", "1")], vec![]);
+        let other =
+            IsoSpec { api_token: Some("token".into()), ..make_spec(vec![EnvironmentVariable::new("A", "2")], vec![]) };
+        assert_eq!(base.content_hash().unwrap(), other.content_hash().unwrap());
+    }
+
+    #[tokio::test]
+    async fn staged_content_hash() {
+        let service = make_service();
+        let spec = make_spec(vec![EnvironmentVariable::new("A", "1")], vec![ExternalFile::new("bar/tar.txt", b"bye")]);
+        let workdir = tempdir().expect("failed to create tempdir");
+        let base_path = workdir.path().to_path_buf();
+        service.persist_files(&base_path, spec.files.clone()).await.expect("failed to persist");
+        let metadata = serde_json::to_vec(&spec.metadata).expect("failed to serialize");
+        std::fs::write(base_path.join("docker-compose.yaml"), &spec.docker_compose_yaml).expect("failed to write");
+        std::fs::write(base_path.join("metadata.json"), metadata).expect("failed to write");
+        std::fs::write(base_path.join(".env"), "A=1\n").expect("failed to write");
+        std::fs::write(base_path.join(API_TOKEN_FILE), "token").expect("failed to write");
+
+        let hash = DefaultDiskService::staged_content_hash(base_path).await.expect("failed to hash");
+        assert_eq!(hash, spec.content_hash().unwrap());
+    }
+
+    #[test]
+    fn content_hash_stored_files() {
+        let stored = StoredFile { path: "/does/not/exist".into(), sha256: Sha256::digest(b"hi").into(), size: 2 };
+        let inline = make_spec(vec![], vec![ExternalFile::new("foo.txt", b"hi")]);
+        let stored = make_spec(vec![], vec![ExternalFile::stored("foo.txt", stored)]);
+        assert_eq!(inline.content_hash().unwrap(), stored.content_hash().unwrap());
+    }
+
+    #[rstest]
+    #[case::state("/var/vms/a3a7.state.raw", "nilcc-a3a7.state.raw")]
+    #[case::base("a3a7.base.qcow2", "nilcc-a3a7.base.qcow2")]
+    fn volume_names(#[case] path: &str, #[case] expected: &str) {
+        let name = LvmDiskService::volume_name(Path::new(path)).expect("invalid name");
+        assert_eq!(name, expected);
+    }
+
+    #[test]
+    fn invalid_volume_names() {
+        for path in ["/var/vms/", "/var/vms/a b.raw", "/var/vms/a:b.raw"] {
+            assert!(LvmDiskService::volume_name(Path::new(path)).is_err(), "{path} accepted");
+        }
+    }
+
+    #[test]
+    fn base_volume_names() {
+        let name = LvmDiskService::base_volume_name(Path::new("/opt/nilcc/artifacts/0.1.0/vm_images/cvm-cpu.qcow2"));
+        assert!(name.starts_with(BASE_VOLUME_PREFIX));
+        assert_eq!(name.len(), BASE_VOLUME_PREFIX.len() + 16);
+        let other = LvmDiskService::base_volume_name(Path::new("/opt/nilcc/artifacts/0.2.0/vm_images/cvm-cpu.qcow2"));
+        assert_ne!(name, other);
+    }
+
+    #[test]
+    fn pool_usage() {
+        let usage = parse_pool_usage("  1073741824000 25.00\n").expect("invalid output");
+        assert_eq!(usage, StoragePoolUsage { size_bytes: 1073741824000, free_bytes: 805306368000 });
+        assert_eq!(parse_pool_usage(""), None);
+        assert_eq!(parse_pool_usage("abc 25.00"), None);
+    }
+
+    #[tokio::test]
+    async fn lvm_delete_regular_file() {
+        let service = LvmDiskService::new(make_service(), "vg".into(), "pool".into());
+        let workdir = tempdir().expect("failed to create tempdir");
+        let path = workdir.path().join("foo.iso");
+        std::fs::write(&path, b"hi").expect("failed to write");
+
+        service.delete_disk(&path).await.expect("failed to delete");
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn normalize_timestamps() {
+        let workdir = tempdir().expect("failed to create tempdir");
+        let base_path = workdir.path().to_path_buf();
+        std::fs::create_dir(base_path.join("foo")).expect("failed to create dir");
+        std::fs::write(base_path.join("foo/bar.txt"), b"hi").expect("failed to write");
+
+        DefaultDiskService::normalize_timestamps(base_path.clone()).await.expect("failed to normalize");
+        let expected = SystemTime::UNIX_EPOCH + Duration::from_secs(ISO_TIMESTAMP_SECONDS);
+        for path in [base_path.clone(), base_path.join("foo"), base_path.join("foo/bar.txt")] {
+            let modified = std::fs::metadata(&path).expect("failed to get metadata").modified().expect("no mtime");
+            assert_eq!(modified, expected, "invalid timestamp for {}", path.display());
+        }
+    }
 }
@@ -0,0 +1,615 @@
+use crate::resources::{PublicIpFinder, PublicIps};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, RequestBuilder};
+use serde::{
+    Deserialize, Serialize,
+    de::{DeserializeOwned, IgnoredAny},
+};
+use sha2::{Digest, Sha256};
+use std::{io, net::IpAddr, path::PathBuf, process::Stdio, sync::Arc};
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{debug, info, warn};
+
+const CLOUDFLARE_API_URL: &str = "https://api.cloudflare.com/client/v4";
+const ROUTE53_HOST: &str = "route53.amazonaws.com";
+
+// Route53 is a global service so requests are always signed for this region.
+const ROUTE53_REGION: &str = "us-east-1";
+
+/// Updates the DNS records that point to this agent.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait DnsRecordUpdater: Send + Sync {
+    /// Point the A and AAAA records for the given domains to the given IP addresses.
+    async fn update_records(&self, domains: &[String], ips: PublicIps) -> Result<(), DnsUpdateError>;
+
+    /// Delete the A and AAAA records for the given domains.
+    ///
+    /// `ips` are the addresses the records currently point to, which some providers need to delete them.
+    async fn delete_records(&self, domains: &[String], ips: PublicIps) -> Result<(), DnsUpdateError>;
+}
+
+/// The DNS records managed by this agent.
+#[derive(Clone)]
+pub struct DnsUpdates {
+    pub updater: Arc<dyn DnsRecordUpdater>,
+
+    /// The zone whose records are managed by this agent.
+    pub zone: String,
+}
+
+impl DnsUpdates {
+    /// Whether a domain is within the zone managed by this agent.
+    ///
+    /// Workloads can use domains we don't manage so only the ones in our zone should be touched.
+    pub(crate) fn manages(&self, domain: &str) -> bool {
+        domain.strip_suffix(&self.zone).is_some_and(|prefix| prefix.ends_with('.'))
+    }
+}
+
+pub struct NsupdateDnsRecordUpdaterArgs {
+    /// The path to the nsupdate binary.
+    pub nsupdate_bin: PathBuf,
+
+    /// The DNS server to send updates to.
+    pub server: String,
+
+    /// The path to the TSIG key file used to authenticate updates.
+    pub key_file: PathBuf,
+
+    /// The TTL to use for the records.
+    pub ttl: u32,
+}
+
+/// A [DnsRecordUpdater] that sends RFC 2136 dynamic updates via `nsupdate`.
+pub struct NsupdateDnsRecordUpdater {
+    nsupdate_bin: PathBuf,
+    server: String,
+    key_file: PathBuf,
+    ttl: u32,
+}
+
+impl NsupdateDnsRecordUpdater {
+    pub fn new(args: NsupdateDnsRecordUpdaterArgs) -> Self {
+        let NsupdateDnsRecordUpdaterArgs { nsupdate_bin, server, key_file, ttl } = args;
+        Self { nsupdate_bin, server, key_file, ttl }
+    }
+
+    fn build_script(&self, domains: &[String], ips: PublicIps) -> String {
+        let mut script = format!("server {}\n", self.server);
+        for domain in domains {
+            // Records of both families are deleted so the domain stops resolving to addresses the host no longer has.
+            for record_type in ["A", "AAAA"] {
+                script.push_str(&format!("update delete {domain}. {record_type}\n"));
+            }
+            for ip in ips.iter() {
+                script.push_str(&format!("update add {domain}. {} {} {ip}\n", self.ttl, record_type(ip)));
+            }
+        }
+        // All records are updated in a single transaction.
+        script.push_str("send\n");
+        script
+    }
+
+    fn build_delete_script(&self, domains: &[String]) -> String {
+        let mut script = format!("server {}\n", self.server);
+        for domain in domains {
+            for record_type in ["A", "AAAA"] {
+                script.push_str(&format!("update delete {domain}. {record_type}\n"));
+            }
+        }
+        script.push_str("send\n");
+        script
+    }
+
+    async fn run_script(&self, script: String) -> Result<(), DnsUpdateError> {
+        let mut child = Command::new(&self.nsupdate_bin)
+            .arg("-k")
+            .arg(&self.key_file)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(DnsUpdateError::Spawn)?;
+        {
+            let mut stdin = child.stdin.take().expect("no stdin");
+            stdin.write_all(script.as_bytes()).await.map_err(DnsUpdateError::Spawn)?;
+        }
+        let output = child.wait_with_output().await.map_err(DnsUpdateError::Spawn)?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(DnsUpdateError::Nsupdate(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        }
+    }
+}
+
+#[async_trait]
+impl DnsRecordUpdater for NsupdateDnsRecordUpdater {
+    async fn update_records(&self, domains: &[String], ips: PublicIps) -> Result<(), DnsUpdateError> {
+        if domains.is_empty() {
+            return Ok(());
+        }
+        info!("Pointing DNS records for {domains:?} to {ips}");
+        self.run_script(self.build_script(domains, ips)).await
+    }
+
+    async fn delete_records(&self, domains: &[String], _ips: PublicIps) -> Result<(), DnsUpdateError> {
+        if domains.is_empty() {
+            return Ok(());
+        }
+        info!("Deleting DNS records for {domains:?}");
+        self.run_script(self.build_delete_script(domains)).await
+    }
+}
+
+pub struct CloudflareDnsRecordUpdaterArgs {
+    /// The API token used to authenticate, which needs the `Zone.DNS` edit permission.
+    pub api_token: String,
+
+    /// The id of the zone the records live in.
+    pub zone_id: String,
+
+    /// The TTL to use for the records.
+    pub ttl: u32,
+}
+
+/// A [DnsRecordUpdater] that manages records via Cloudflare's API.
+pub struct CloudflareDnsRecordUpdater {
+    client: Client,
+    api_token: String,
+    zone_id: String,
+    ttl: u32,
+}
+
+impl CloudflareDnsRecordUpdater {
+    pub fn new(args: CloudflareDnsRecordUpdaterArgs) -> Self {
+        let CloudflareDnsRecordUpdaterArgs { api_token, zone_id, ttl } = args;
+        Self { client: Client::new(), api_token, zone_id, ttl }
+    }
+
+    fn records_url(&self) -> String {
+        format!("{CLOUDFLARE_API_URL}/zones/{}/dns_records", self.zone_id)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, DnsUpdateError> {
+        // Errors are reported in the body, along with a non-2xx status code.
+        let response: CloudflareResponse<T> = request.bearer_auth(&self.api_token).send().await?.json().await?;
+        match response.result {
+            Some(result) if response.success => Ok(result),
+            _ => {
+                let errors: Vec<_> =
+                    response.errors.into_iter().map(|e| format!("{} ({})", e.message, e.code)).collect();
+                Err(DnsUpdateError::Api(errors.join(", ")))
+            }
+        }
+    }
+
+    async fn find_records(&self, domain: &str, record_type: &str) -> Result<Vec<CloudflareRecord>, DnsUpdateError> {
+        let query = [("name", domain), ("type", record_type)];
+        self.send(self.client.get(self.records_url()).query(&query)).await
+    }
+
+    async fn delete_record(&self, record: &CloudflareRecord) -> Result<(), DnsUpdateError> {
+        let url = format!("{}/{}", self.records_url(), record.id);
+        self.send::<IgnoredAny>(self.client.delete(url)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DnsRecordUpdater for CloudflareDnsRecordUpdater {
+    async fn update_records(&self, domains: &[String], ips: PublicIps) -> Result<(), DnsUpdateError> {
+        for domain in domains {
+            info!("Pointing DNS records for {domain} to {ips}");
+            for ip in ips.iter() {
+                let record_type = record_type(ip);
+                let records = self.find_records(domain, record_type).await?;
+                let request = CloudflareRecordRequest {
+                    r#type: record_type,
+                    name: domain,
+                    content: ip,
+                    ttl: self.ttl,
+                    proxied: false,
+                };
+                // Update the first record in place and drop any others so the domain only resolves to this agent.
+                match records.split_first() {
+                    Some((record, rest)) => {
+                        let url = format!("{}/{}", self.records_url(), record.id);
+                        self.send::<IgnoredAny>(self.client.put(url).json(&request)).await?;
+                        for record in rest {
+                            self.delete_record(record).await?;
+                        }
+                    }
+                    None => {
+                        self.send::<IgnoredAny>(self.client.post(self.records_url()).json(&request)).await?;
+                    }
+                }
+            }
+            // Drop the records of a family the host no longer has an address for.
+            for (record_type, missing) in [("A", ips.ipv4.is_none()), ("AAAA", ips.ipv6.is_none())] {
+                if missing {
+                    for record in self.find_records(domain, record_type).await? {
+                        self.delete_record(&record).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_records(&self, domains: &[String], _ips: PublicIps) -> Result<(), DnsUpdateError> {
+        for domain in domains {
+            info!("Deleting DNS records for {domain}");
+            for record_type in ["A", "AAAA"] {
+                for record in self.find_records(domain, record_type).await? {
+                    self.delete_record(&record).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct CloudflareResponse<T> {
+    success: bool,
+
+    #[serde(default)]
+    errors: Vec<CloudflareError>,
+
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct CloudflareError {
+    code: u32,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct CloudflareRecord {
+    id: String,
+}
+
+#[derive(Serialize)]
+struct CloudflareRecordRequest<'a> {
+    r#type: &'static str,
+    name: &'a str,
+    content: IpAddr,
+    ttl: u32,
+    proxied: bool,
+}
+
+pub struct Route53DnsRecordUpdaterArgs {
+    /// The id of the hosted zone the records live in.
+    pub hosted_zone_id: String,
+
+    /// The AWS access key id used to sign requests.
+    pub access_key_id: String,
+
+    /// The AWS secret access key used to sign requests.
+    pub secret_access_key: String,
+
+    /// The TTL to use for the records.
+    pub ttl: u32,
+}
+
+/// A [DnsRecordUpdater] that manages records via Route53's API.
+pub struct Route53DnsRecordUpdater {
+    client: Client,
+    hosted_zone_id: String,
+    access_key_id: String,
+    secret_access_key: String,
+    ttl: u32,
+}
+
+impl Route53DnsRecordUpdater {
+    pub fn new(args: Route53DnsRecordUpdaterArgs) -> Self {
+        let Route53DnsRecordUpdaterArgs { hosted_zone_id, access_key_id, secret_access_key, ttl } = args;
+        Self { client: Client::new(), hosted_zone_id, access_key_id, secret_access_key, ttl }
+    }
+
+    fn build_change_batch<'a>(&self, action: &str, records: impl IntoIterator<Item = (&'a String, IpAddr)>) -> String {
+        let mut changes = String::new();
+        for (domain, ip) in records {
+            let record_type = record_type(ip);
+            let ttl = self.ttl;
+            changes.push_str(&format!(
+                "<Change><Action>{action}</Action><ResourceRecordSet><Name>{domain}.</Name><Type>{record_type}</Type>\
+                <TTL>{ttl}</TTL><ResourceRecords><ResourceRecord><Value>{ip}</Value></ResourceRecord></ResourceRecords>\
+                </ResourceRecordSet></Change>"
+            ));
+        }
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+            <ChangeResourceRecordSetsRequest xmlns=\"https://route53.amazonaws.com/doc/2013-04-01/\">\
+            <ChangeBatch><Changes>{changes}</Changes></ChangeBatch></ChangeResourceRecordSetsRequest>"
+        )
+    }
+
+    /// Build the AWS signature v4 `Authorization` header for a `POST` request.
+    fn authorization(&self, path: &str, body: &str, now: DateTime<Utc>) -> String {
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ");
+        let scope = format!("{date}/{ROUTE53_REGION}/route53/aws4_request");
+        let body_hash = hex::encode(Sha256::digest(body));
+        let canonical_request =
+            format!("POST\n{path}\n\nhost:{ROUTE53_HOST}\nx-amz-date:{timestamp}\n\nhost;x-amz-date\n{body_hash}");
+        let string_to_sign =
+            format!("AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}", hex::encode(Sha256::digest(canonical_request)));
+        let mut key = hmac_sha256(format!("AWS4{}", self.secret_access_key).as_bytes(), date.as_bytes());
+        for part in [ROUTE53_REGION, "route53", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders=host;x-amz-date, Signature={signature}",
+            self.access_key_id
+        )
+    }
+
+    async fn change(&self, body: String) -> Result<(), DnsUpdateError> {
+        let path = format!("/2013-04-01/hostedzone/{}/rrset", self.hosted_zone_id);
+        let now = Utc::now();
+        let response = self
+            .client
+            .post(format!("https://{ROUTE53_HOST}{path}"))
+            .header("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string())
+            .header("authorization", self.authorization(&path, &body, now))
+            .header("content-type", "application/xml")
+            .body(body)
+            .send()
+            .await?;
+        if response.status().is_success() { Ok(()) } else { Err(DnsUpdateError::Api(response.text().await?)) }
+    }
+}
+
+#[async_trait]
+impl DnsRecordUpdater for Route53DnsRecordUpdater {
+    async fn update_records(&self, domains: &[String], ips: PublicIps) -> Result<(), DnsUpdateError> {
+        if domains.is_empty() {
+            return Ok(());
+        }
+        info!("Pointing DNS records for {domains:?} to {ips}");
+        let records = domains.iter().flat_map(|domain| ips.iter().map(move |ip| (domain, ip)));
+        // All records are updated in a single transaction.
+        self.change(self.build_change_batch("UPSERT", records)).await
+    }
+
+    async fn delete_records(&self, domains: &[String], ips: PublicIps) -> Result<(), DnsUpdateError> {
+        for domain in domains {
+            info!("Deleting DNS records for {domain}");
+            // Change batches are atomic, so delete each record on its own in case some of them don't exist.
+            for ip in ips.iter() {
+                match self.change(self.build_change_batch("DELETE", [(domain, ip)])).await {
+                    Err(DnsUpdateError::Api(e)) if e.contains("but it was not found") => {
+                        debug!("{} record for {domain} doesn't exist", record_type(ip));
+                    }
+                    result => result?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn record_type(ip: IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "A",
+        IpAddr::V6(_) => "AAAA",
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DnsUpdateError {
+    #[error("failed to run nsupdate: {0}")]
+    Spawn(io::Error),
+
+    #[error("nsupdate failed: {0}")]
+    Nsupdate(String),
+
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("DNS provider API error: {0}")]
+    Api(String),
+}
+
+/// Keeps the DNS records for workload domains pointing to this agent.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait WorkloadDnsService: Send + Sync {
+    /// Point a workload's domain to this agent, if it's in the zone managed by it.
+    async fn add_domain(&self, domain: &str);
+
+    /// Delete the records for a workload's domain, if it's in the zone managed by it.
+    async fn remove_domain(&self, domain: &str);
+}
+
+/// A [WorkloadDnsService] that does nothing unless DNS updates are configured.
+///
+/// Failing to update a record is logged rather than returned since the workload can still be reached once it's
+/// fixed manually.
+pub struct DefaultWorkloadDnsService {
+    dns: Option<DnsUpdates>,
+    ip_finder: Box<dyn PublicIpFinder>,
+}
+
+impl DefaultWorkloadDnsService {
+    pub fn new(dns: Option<DnsUpdates>, ip_finder: Box<dyn PublicIpFinder>) -> Self {
+        Self { dns, ip_finder }
+    }
+
+    fn managed_domain(&self, domain: &str) -> Option<(&DnsUpdates, PublicIps)> {
+        let dns = self.dns.as_ref().filter(|dns| dns.manages(domain))?;
+        match self.ip_finder.find_public_ips() {
+            Ok(ips) => Some((dns, ips)),
+            Err(e) => {
+                warn!("Failed to find public IPs to update DNS records for {domain}: {e:#}");
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl WorkloadDnsService for DefaultWorkloadDnsService {
+    async fn add_domain(&self, domain: &str) {
+        let Some((dns, ips)) = self.managed_domain(domain) else {
+            return;
+        };
+        if let Err(e) = dns.updater.update_records(&[domain.to_string()], ips).await {
+            warn!("Failed to create DNS records for {domain}: {e}");
+        }
+    }
+
+    async fn remove_domain(&self, domain: &str) {
+        let Some((dns, ips)) = self.managed_domain(domain) else {
+            return;
+        };
+        if let Err(e) = dns.updater.delete_records(&[domain.to_string()], ips).await {
+            warn!("Failed to delete DNS records for {domain}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::MockPublicIpFinder;
+    use chrono::TimeZone;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const IPS: PublicIps = PublicIps { ipv4: Some(Ipv4Addr::new(1, 2, 3, 4)), ipv6: None };
+
+    fn make_updater() -> NsupdateDnsRecordUpdater {
+        NsupdateDnsRecordUpdater::new(NsupdateDnsRecordUpdaterArgs {
+            nsupdate_bin: "nsupdate".into(),
+            server: "ns1.example.com".into(),
+            key_file: "/etc/nilcc/dns.key".into(),
+            ttl: 60,
+        })
+    }
+
+    fn make_route53_updater() -> Route53DnsRecordUpdater {
+        Route53DnsRecordUpdater::new(Route53DnsRecordUpdaterArgs {
+            hosted_zone_id: "Z123".into(),
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            ttl: 60,
+        })
+    }
+
+    #[test]
+    fn build_script() {
+        let domains = ["a.example.com".to_string(), "b.example.com".to_string()];
+        let script = make_updater().build_script(&domains, IPS);
+        let expected = "server ns1.example.com
+update delete a.example.com. A
+update delete a.example.com. AAAA
+update add a.example.com. 60 A 1.2.3.4
+update delete b.example.com. A
+update delete b.example.com. AAAA
+update add b.example.com. 60 A 1.2.3.4
+send
+";
+        assert_eq!(script, expected);
+    }
+
+    #[test]
+    fn build_dual_stack_script() {
+        let domains = ["a.example.com".to_string()];
+        let ipv6 = Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1);
+        let ips = PublicIps { ipv6: Some(ipv6), ..IPS };
+        let script = make_updater().build_script(&domains, ips);
+        let expected = "server ns1.example.com
+update delete a.example.com. A
+update delete a.example.com. AAAA
+update add a.example.com. 60 A 1.2.3.4
+update add a.example.com. 60 AAAA 2a01:4f8::1
+send
+";
+        assert_eq!(script, expected);
+    }
+
+    #[test]
+    fn build_delete_script() {
+        let domains = ["a.example.com".to_string()];
+        let script = make_updater().build_delete_script(&domains);
+        let expected = "server ns1.example.com
+update delete a.example.com. A
+update delete a.example.com. AAAA
+send
+";
+        assert_eq!(script, expected);
+    }
+
+    #[test]
+    fn route53_change_batch() {
+        let domain = "a.example.com".to_string();
+        let batch =
+            make_route53_updater().build_change_batch("UPSERT", [(&domain, IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)))]);
+        let expected = concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<ChangeResourceRecordSetsRequest xmlns="https://route53.amazonaws.com/doc/2013-04-01/">"#,
+            "<ChangeBatch><Changes><Change><Action>UPSERT</Action><ResourceRecordSet><Name>a.example.com.</Name>",
+            "<Type>A</Type><TTL>60</TTL><ResourceRecords><ResourceRecord><Value>1.2.3.4</Value></ResourceRecord>",
+            "</ResourceRecords></ResourceRecordSet></Change></Changes></ChangeBatch></ChangeResourceRecordSetsRequest>"
+        );
+        assert_eq!(batch, expected);
+    }
+
+    #[test]
+    fn route53_authorization() {
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let authorization = make_route53_updater().authorization("/2013-04-01/hostedzone/Z123/rrset", "<xml/>", now);
+        let expected = "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/route53/aws4_request, \
+            SignedHeaders=host;x-amz-date, Signature=ae649075e58f48ab3833edf0d3f13500fa162571f62533387598a4552b07aeea";
+        assert_eq!(authorization, expected);
+    }
+
+    #[test]
+    fn managed_domains() {
+        let dns = DnsUpdates { updater: Arc::new(MockDnsRecordUpdater::default()), zone: "nilcc.com".into() };
+        assert!(dns.manages("foo.nilcc.com"));
+        assert!(dns.manages("foo.bar.nilcc.com"));
+        assert!(!dns.manages("nilcc.com"));
+        assert!(!dns.manages("foonilcc.com"));
+        assert!(!dns.manages("example.com"));
+    }
+
+    #[tokio::test]
+    async fn workload_domains() {
+        let mut updater = MockDnsRecordUpdater::default();
+        updater
+            .expect_update_records()
+            .withf(|domains, ips| domains == ["foo.nilcc.com"] && *ips == IPS)
+            .once()
+            .return_once(|_, _| Ok(()));
+        updater
+            .expect_delete_records()
+            .withf(|domains, ips| domains == ["foo.nilcc.com"] && *ips == IPS)
+            .once()
+            .return_once(|_, _| Ok(()));
+        let mut ip_finder = MockPublicIpFinder::default();
+        ip_finder.expect_find_public_ips().returning(|| Ok(IPS));
+        let dns = DnsUpdates { updater: Arc::new(updater), zone: "nilcc.com".into() };
+        let service = DefaultWorkloadDnsService::new(Some(dns), Box::new(ip_finder));
+
+        service.add_domain("foo.nilcc.com").await;
+        service.remove_domain("foo.nilcc.com").await;
+
+        // These aren't ours so they're left alone.
+        service.add_domain("example.com").await;
+        service.remove_domain("example.com").await;
+    }
+}
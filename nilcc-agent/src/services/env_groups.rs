@@ -0,0 +1,181 @@
+use crate::{
+    clients::nilcc_api::{EnvGroupResponse, NilccApiClient},
+    repositories::{
+        env_groups::{EnvGroup, EnvGroupRepository, EnvGroupRepositoryError},
+        sqlite::{ProviderError, RepositoryProvider},
+    },
+    services::disk::RESERVED_ENVIRONMENT_VARIABLES,
+};
+use async_trait::async_trait;
+use std::{collections::HashMap, sync::Arc};
+use tracing::{info, warn};
+
+/// Resolves the environment variable groups that are managed by the control plane.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait EnvGroupService: Send + Sync {
+    /// Resolve the environment variables for the given groups.
+    ///
+    /// Groups are applied in order, so a variable defined in more than one group takes the value of the last one.
+    async fn resolve(&self, groups: &[String]) -> Result<HashMap<String, String>, EnvGroupError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnvGroupError {
+    #[error("env group '{0}' is not available: {1}")]
+    Unavailable(String, String),
+
+    #[error("internal: {0}")]
+    Internal(String),
+}
+
+impl From<ProviderError> for EnvGroupError {
+    fn from(e: ProviderError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<EnvGroupRepositoryError> for EnvGroupError {
+    fn from(e: EnvGroupRepositoryError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+/// An [EnvGroupService] that fetches groups from the nilcc API and caches them locally.
+///
+/// The cached copy of a group is used if the nilcc API can't be reached, so workloads can still be restarted while
+/// the control plane is down.
+pub struct DefaultEnvGroupService {
+    api_client: Arc<dyn NilccApiClient>,
+    repository_provider: Arc<dyn RepositoryProvider>,
+}
+
+impl DefaultEnvGroupService {
+    pub fn new(api_client: Arc<dyn NilccApiClient>, repository_provider: Arc<dyn RepositoryProvider>) -> Self {
+        Self { api_client, repository_provider }
+    }
+
+    async fn find_group(&self, repo: &mut dyn EnvGroupRepository, name: &str) -> Result<EnvGroup, EnvGroupError> {
+        let cached = repo.find(name).await?;
+        match self.api_client.env_group(name).await {
+            Ok(EnvGroupResponse { version, env_vars }) => {
+                let group = EnvGroup { name: name.to_string(), version, env_vars };
+                match &cached {
+                    Some(cached) if cached.version == version => return Ok(group),
+                    Some(cached) => info!("Env group {name} changed from version {} to {version}", cached.version),
+                    None => info!("Caching env group {name} at version {version}"),
+                };
+                repo.upsert(&group).await?;
+                Ok(group)
+            }
+            Err(e) => match cached {
+                Some(cached) => {
+                    warn!("Failed to fetch env group {name}, using cached version {}: {e}", cached.version);
+                    Ok(cached)
+                }
+                None => Err(EnvGroupError::Unavailable(name.to_string(), e.to_string())),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl EnvGroupService for DefaultEnvGroupService {
+    async fn resolve(&self, groups: &[String]) -> Result<HashMap<String, String>, EnvGroupError> {
+        let mut repo = self.repository_provider.env_groups(Default::default()).await?;
+        let mut env_vars = HashMap::new();
+        for name in groups {
+            let group = self.find_group(repo.as_mut(), name).await?;
+            for (key, value) in group.env_vars {
+                if RESERVED_ENVIRONMENT_VARIABLES.contains(&key.as_str()) {
+                    warn!("Ignoring reserved environment variable {key} in env group {name}");
+                    continue;
+                }
+                env_vars.insert(key, value);
+            }
+        }
+        Ok(env_vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clients::nilcc_api::{MockNilccApiClient, NilccApiError},
+        repositories::{env_groups::MockEnvGroupRepository, sqlite::MockRepositoryProvider},
+    };
+    use mockall::predicate::eq;
+    use reqwest::StatusCode;
+
+    fn make_service(api_client: MockNilccApiClient, repo: MockEnvGroupRepository) -> DefaultEnvGroupService {
+        let mut provider = MockRepositoryProvider::default();
+        provider.expect_env_groups().return_once(move |_| Ok(Box::new(repo)));
+        DefaultEnvGroupService::new(Arc::new(api_client), Arc::new(provider))
+    }
+
+    fn make_vars(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn unavailable() -> NilccApiError {
+        NilccApiError::Api { status: StatusCode::BAD_GATEWAY, message: "unavailable".into() }
+    }
+
+    #[tokio::test]
+    async fn resolve_updated_groups() {
+        let mut api_client = MockNilccApiClient::default();
+        api_client.expect_env_group().with(eq("a")).return_once(|_| {
+            Ok(EnvGroupResponse { version: 2, env_vars: make_vars(&[("FOO", "a"), ("NILCC_DOMAIN", "evil.com")]) })
+        });
+        api_client
+            .expect_env_group()
+            .with(eq("b"))
+            .return_once(|_| Ok(EnvGroupResponse { version: 1, env_vars: make_vars(&[("FOO", "b"), ("BAR", "b")]) }));
+
+        let mut repo = MockEnvGroupRepository::default();
+        repo.expect_find()
+            .with(eq("a"))
+            .return_once(|_| Ok(Some(EnvGroup { name: "a".into(), version: 1, env_vars: make_vars(&[]) })));
+        repo.expect_find().with(eq("b")).return_once(|_| {
+            Ok(Some(EnvGroup { name: "b".into(), version: 1, env_vars: make_vars(&[("FOO", "b"), ("BAR", "b")]) }))
+        });
+        let expected_group = EnvGroup {
+            name: "a".into(),
+            version: 2,
+            env_vars: make_vars(&[("FOO", "a"), ("NILCC_DOMAIN", "evil.com")]),
+        };
+        repo.expect_upsert().with(eq(expected_group)).once().return_once(|_| Ok(()));
+
+        let service = make_service(api_client, repo);
+        let env_vars = service.resolve(&["a".into(), "b".into()]).await.expect("failed to resolve");
+        assert_eq!(env_vars, make_vars(&[("FOO", "b"), ("BAR", "b")]));
+    }
+
+    #[tokio::test]
+    async fn resolve_cached_group() {
+        let mut api_client = MockNilccApiClient::default();
+        api_client.expect_env_group().return_once(|_| Err(unavailable()));
+
+        let mut repo = MockEnvGroupRepository::default();
+        repo.expect_find()
+            .return_once(|_| Ok(Some(EnvGroup { name: "a".into(), version: 3, env_vars: make_vars(&[("FOO", "a")]) })));
+
+        let service = make_service(api_client, repo);
+        let env_vars = service.resolve(&["a".into()]).await.expect("failed to resolve");
+        assert_eq!(env_vars, make_vars(&[("FOO", "a")]));
+    }
+
+    #[tokio::test]
+    async fn resolve_unavailable_group() {
+        let mut api_client = MockNilccApiClient::default();
+        api_client.expect_env_group().return_once(|_| Err(unavailable()));
+
+        let mut repo = MockEnvGroupRepository::default();
+        repo.expect_find().return_once(|_| Ok(None));
+
+        let service = make_service(api_client, repo);
+        let err = service.resolve(&["a".into()]).await.expect_err("resolve succeeded");
+        assert!(matches!(err, EnvGroupError::Unavailable(name, _) if name == "a"), "{err:?}");
+    }
+}
@@ -0,0 +1,301 @@
+use async_trait::async_trait;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use nilcc_agent_models::workloads::create::DockerCredentials;
+use reqwest::{
+    Client, StatusCode,
+    header::{ACCEPT, CONTENT_TYPE},
+    redirect::Policy,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, time::Duration};
+use tokio::time::{sleep, timeout};
+use tracing::info;
+
+/// The registry images without an explicit one are pulled from.
+const DOCKER_HUB: &str = "docker.io";
+
+/// The hostnames docker hub is reachable at, which credentials may use instead of [DOCKER_HUB].
+const DOCKER_HUB_ALIASES: &[&str] = &["index.docker.io", "registry-1.docker.io", "registry.hub.docker.com"];
+
+const SCAN_REQUEST_MIME_TYPE: &str = "application/vnd.scanner.adapter.scan.request+json; version=1.0";
+const SCAN_REPORT_MIME_TYPE: &str = "application/vnd.security.vulnerability.report; version=1.1";
+
+/// How long to wait before polling for a scan report if the server doesn't say.
+const DEFAULT_REFRESH_AFTER: Duration = Duration::from_secs(1);
+
+/// Checks the images a workload uses for vulnerabilities before the workload is created.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait ImagePolicyChecker: Send + Sync {
+    /// Get the identifiers of the critical vulnerabilities found in an image.
+    async fn critical_vulnerabilities(
+        &self,
+        image: &str,
+        credentials: &[DockerCredentials],
+    ) -> Result<Vec<String>, ImagePolicyError>;
+}
+
+pub struct TrivyImagePolicyCheckerArgs {
+    /// The URL of the trivy server.
+    pub server_url: String,
+
+    /// The timeout for a single image scan.
+    pub timeout: Duration,
+}
+
+/// An [ImagePolicyChecker] that uses a trivy server exposing the
+/// [pluggable scanner API](https://github.com/goharbor/pluggable-scanner-spec), like `harbor-scanner-trivy` does.
+///
+/// The server pulls and scans images itself, so agents don't need trivy nor a copy of the vulnerability database.
+pub struct TrivyImagePolicyChecker {
+    client: Client,
+    server_url: String,
+    timeout: Duration,
+}
+
+impl TrivyImagePolicyChecker {
+    pub fn new(args: TrivyImagePolicyCheckerArgs) -> Result<Self, ImagePolicyError> {
+        let TrivyImagePolicyCheckerArgs { server_url, timeout } = args;
+        // The server responds to report requests with a 302 while the scan is in progress.
+        let client = Client::builder().redirect(Policy::none()).build().map_err(ImagePolicyError::Http)?;
+        Ok(Self { client, server_url: server_url.trim_end_matches('/').to_string(), timeout })
+    }
+
+    async fn scan(&self, request: &ScanRequest) -> Result<ScanReport, ImagePolicyError> {
+        let body = serde_json::to_vec(request).map_err(ImagePolicyError::Report)?;
+        let response = self
+            .client
+            .post(format!("{}/api/v1/scan", self.server_url))
+            .header(CONTENT_TYPE, SCAN_REQUEST_MIME_TYPE)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        let ScanResponse { id } = response.json().await?;
+        loop {
+            let response = self
+                .client
+                .get(format!("{}/api/v1/scan/{id}/report", self.server_url))
+                .header(ACCEPT, SCAN_REPORT_MIME_TYPE)
+                .send()
+                .await?;
+            if response.status() != StatusCode::FOUND {
+                return Ok(response.error_for_status()?.json().await?);
+            }
+            let refresh_after = response
+                .headers()
+                .get("Refresh-After")
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_REFRESH_AFTER);
+            sleep(refresh_after).await;
+        }
+    }
+
+    fn critical(report: ScanReport) -> Vec<String> {
+        let vulnerabilities: BTreeSet<_> = report
+            .vulnerabilities
+            .into_iter()
+            .filter(|vulnerability| vulnerability.severity.eq_ignore_ascii_case("critical"))
+            .map(|vulnerability| vulnerability.id)
+            .collect();
+        vulnerabilities.into_iter().collect()
+    }
+}
+
+#[async_trait]
+impl ImagePolicyChecker for TrivyImagePolicyChecker {
+    async fn critical_vulnerabilities(
+        &self,
+        image: &str,
+        credentials: &[DockerCredentials],
+    ) -> Result<Vec<String>, ImagePolicyError> {
+        info!("Scanning image {image} using trivy server at {}", self.server_url);
+        let image = ImageReference::parse(image);
+        // Only hand out the credentials for the registry the image lives in.
+        let authorization = image.credentials(credentials).map(|credentials| {
+            let DockerCredentials { username, password, .. } = credentials;
+            format!("Basic {}", BASE64_STANDARD.encode(format!("{username}:{password}")))
+        });
+        let request = ScanRequest {
+            registry: ScanRegistry { url: format!("https://{}", image.registry), authorization },
+            artifact: ScanArtifact { repository: image.repository, tag: image.tag, digest: image.digest },
+        };
+        let report = timeout(self.timeout, self.scan(&request)).await.map_err(|_| ImagePolicyError::Timeout)??;
+        Ok(Self::critical(report))
+    }
+}
+
+/// A parsed image reference, e.g. `ghcr.io/foo/bar:1.0`.
+#[derive(Debug, PartialEq)]
+struct ImageReference {
+    registry: String,
+    repository: String,
+    tag: Option<String>,
+    digest: Option<String>,
+}
+
+impl ImageReference {
+    fn parse(image: &str) -> Self {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (image, None),
+        };
+        let (name, tag) = match name.rsplit_once(':') {
+            // A colon followed by a slash belongs to the registry's port.
+            Some((name, tag)) if !tag.contains('/') => (name, Some(tag.to_string())),
+            _ => (name, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((registry, repository)) if registry.contains(['.', ':']) || registry == "localhost" => {
+                (normalize_registry(registry), repository.to_string())
+            }
+            _ => (DOCKER_HUB.to_string(), name.to_string()),
+        };
+        let repository = if registry == DOCKER_HUB && !repository.contains('/') {
+            format!("library/{repository}")
+        } else {
+            repository
+        };
+        let tag = match (tag, &digest) {
+            (None, None) => Some("latest".into()),
+            (tag, _) => tag,
+        };
+        Self { registry, repository, tag, digest }
+    }
+
+    fn credentials<'a>(&self, credentials: &'a [DockerCredentials]) -> Option<&'a DockerCredentials> {
+        credentials.iter().find(|credentials| normalize_registry(&credentials.server) == self.registry)
+    }
+}
+
+/// Normalize a registry server, which may be a URL, into its host.
+fn normalize_registry(server: &str) -> String {
+    let server = server.trim_start_matches("https://").trim_start_matches("http://");
+    let host = server.split('/').next().unwrap_or_default().to_lowercase();
+    if DOCKER_HUB_ALIASES.contains(&host.as_str()) { DOCKER_HUB.into() } else { host }
+}
+
+#[derive(Serialize)]
+struct ScanRequest {
+    registry: ScanRegistry,
+    artifact: ScanArtifact,
+}
+
+#[derive(Serialize)]
+struct ScanRegistry {
+    url: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    authorization: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ScanArtifact {
+    repository: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ScanResponse {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ScanReport {
+    #[serde(default)]
+    vulnerabilities: Vec<ScanVulnerability>,
+}
+
+#[derive(Deserialize)]
+struct ScanVulnerability {
+    id: String,
+    severity: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImagePolicyError {
+    #[error("request to trivy server failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("scan timed out")]
+    Timeout,
+
+    #[error("invalid trivy report: {0}")]
+    Report(serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::official("caddy", "docker.io", "library/caddy", Some("latest"), None)]
+    #[case::tag("caddy:2", "docker.io", "library/caddy", Some("2"), None)]
+    #[case::user("foo/bar:1.0", "docker.io", "foo/bar", Some("1.0"), None)]
+    #[case::registry("ghcr.io/foo/bar:1.0", "ghcr.io", "foo/bar", Some("1.0"), None)]
+    #[case::port("localhost:5000/bar", "localhost:5000", "bar", Some("latest"), None)]
+    #[case::digest("ghcr.io/foo/bar@sha256:abcd", "ghcr.io", "foo/bar", None, Some("sha256:abcd"))]
+    #[case::tag_digest("foo/bar:1.0@sha256:abcd", "docker.io", "foo/bar", Some("1.0"), Some("sha256:abcd"))]
+    fn parse_reference(
+        #[case] image: &str,
+        #[case] registry: &str,
+        #[case] repository: &str,
+        #[case] tag: Option<&str>,
+        #[case] digest: Option<&str>,
+    ) {
+        let expected = ImageReference {
+            registry: registry.into(),
+            repository: repository.into(),
+            tag: tag.map(Into::into),
+            digest: digest.map(Into::into),
+        };
+        assert_eq!(ImageReference::parse(image), expected);
+    }
+
+    #[test]
+    fn registry_credentials() {
+        let make_credentials = |server: &str| DockerCredentials {
+            server: server.into(),
+            username: server.into(),
+            password: "password".into(),
+        };
+        let credentials =
+            [make_credentials("https://index.docker.io/v1/"), make_credentials("ghcr.io"), make_credentials("quay.io")];
+
+        let found = ImageReference::parse("caddy:2").credentials(&credentials).expect("no credentials");
+        assert_eq!(found.server, "https://index.docker.io/v1/");
+
+        let found = ImageReference::parse("ghcr.io/foo/bar").credentials(&credentials).expect("no credentials");
+        assert_eq!(found.server, "ghcr.io");
+
+        assert!(ImageReference::parse("registry.example.com/bar").credentials(&credentials).is_none());
+    }
+
+    #[test]
+    fn critical_vulnerabilities() {
+        let report = r#"{
+  "artifact": { "repository": "library/caddy", "tag": "2" },
+  "vulnerabilities": [
+    { "id": "CVE-2024-0002", "package": "libssl3", "severity": "Critical" },
+    { "id": "CVE-2024-0001", "package": "libcrypto3", "severity": "Critical" },
+    { "id": "CVE-2024-0002", "package": "libcrypto3", "severity": "Critical" },
+    { "id": "CVE-2024-0003", "package": "busybox", "severity": "High" }
+  ]
+}"#;
+        let report: ScanReport = serde_json::from_str(report).expect("invalid report");
+        assert_eq!(TrivyImagePolicyChecker::critical(report), &["CVE-2024-0001", "CVE-2024-0002"]);
+    }
+
+    #[test]
+    fn report_without_vulnerabilities() {
+        let report: ScanReport = serde_json::from_str(r#"{"artifact": {}}"#).expect("invalid report");
+        assert!(TrivyImagePolicyChecker::critical(report).is_empty());
+    }
+}
@@ -1,5 +1,15 @@
+pub mod agent_backup;
+pub mod backup;
+pub mod bandwidth;
 pub mod disk;
+pub mod dns;
+pub mod env_groups;
+pub mod image_policy;
+pub mod progress;
 pub mod proxy;
 pub mod upgrade;
+pub mod upload;
+pub mod usage;
+pub mod verifier_keys;
 pub mod vm;
 pub mod workload;
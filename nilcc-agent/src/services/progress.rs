@@ -0,0 +1,88 @@
+use chrono::Utc;
+use nilcc_agent_models::workloads::progress::{ProvisioningEvent, ProvisioningStep, WorkloadProgressResponse};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+/// Tracks the provisioning steps every workload reached since its VM was created.
+///
+/// This is only kept in memory, so workloads that were created before the agent was last restarted only report the
+/// steps reached since then.
+#[derive(Clone, Default)]
+pub struct ProvisioningTracker(Arc<Mutex<HashMap<Uuid, WorkloadProgressResponse>>>);
+
+impl ProvisioningTracker {
+    /// Start tracking a workload from scratch, discarding any steps it previously reached.
+    pub(crate) fn start(&self, id: Uuid) {
+        self.0.lock().expect("lock poisoned").insert(id, Default::default());
+    }
+
+    /// Record that a workload reached a step, unless it already did.
+    pub(crate) fn record(&self, id: Uuid, step: ProvisioningStep) {
+        let mut inner = self.0.lock().expect("lock poisoned");
+        let progress = inner.entry(id).or_default();
+        if progress.steps.iter().any(|event| event.step == step) {
+            return;
+        }
+        progress.steps.push(ProvisioningEvent { step, timestamp: Utc::now() });
+        if step == ProvisioningStep::Ready {
+            progress.ready = true;
+            progress.error = None;
+        }
+    }
+
+    /// Record that a workload failed to provision.
+    pub(crate) fn fail(&self, id: Uuid, error: String) {
+        self.0.lock().expect("lock poisoned").entry(id).or_default().error = Some(error);
+    }
+
+    /// Whether a workload reached a step.
+    pub(crate) fn reached(&self, id: Uuid, step: ProvisioningStep) -> bool {
+        let inner = self.0.lock().expect("lock poisoned");
+        inner.get(&id).is_some_and(|progress| progress.steps.iter().any(|event| event.step == step))
+    }
+
+    /// Get a workload's progress.
+    pub(crate) fn get(&self, id: Uuid) -> WorkloadProgressResponse {
+        self.0.lock().expect("lock poisoned").get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Stop tracking a workload.
+    pub(crate) fn remove(&self, id: Uuid) {
+        self.0.lock().expect("lock poisoned").remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let tracker = ProvisioningTracker::default();
+        let id = Uuid::new_v4();
+        tracker.start(id);
+        tracker.record(id, ProvisioningStep::IsoBuilt);
+        tracker.record(id, ProvisioningStep::DisksCreated);
+        tracker.record(id, ProvisioningStep::IsoBuilt);
+        tracker.fail(id, "pull failed".into());
+
+        let progress = tracker.get(id);
+        let steps: Vec<_> = progress.steps.iter().map(|event| event.step).collect();
+        assert_eq!(steps, &[ProvisioningStep::IsoBuilt, ProvisioningStep::DisksCreated]);
+        assert!(!progress.ready);
+        assert_eq!(progress.error.as_deref(), Some("pull failed"));
+        assert!(tracker.reached(id, ProvisioningStep::DisksCreated));
+        assert!(!tracker.reached(id, ProvisioningStep::VmStarted));
+
+        tracker.record(id, ProvisioningStep::Ready);
+        let progress = tracker.get(id);
+        assert!(progress.ready);
+        assert!(progress.error.is_none());
+
+        tracker.start(id);
+        assert!(tracker.get(id).steps.is_empty());
+    }
+}
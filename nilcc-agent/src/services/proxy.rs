@@ -1,31 +1,54 @@
+use crate::clients::nilcc_api::VmEvent;
 use crate::config::SniProxyConfigTimeouts;
 use crate::repositories::workload::Workload;
+use crate::workers::events::EventSender;
 use anyhow::{Context as anyhowContext, Result, bail};
 use async_trait::async_trait;
+use chrono::Utc;
+use nilcc_agent_models::workloads::create::ProxyTimeouts;
 use serde::Serialize;
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tinytemplate::TinyTemplate;
-use tokio::{io::AsyncWriteExt, net::UnixSocket, process::Command, sync::Mutex};
-use tracing::{error, info};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixSocket,
+    process::Command,
+    sync::Mutex,
+    time::timeout,
+};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 const HAPROXY_TEMPLATE: &str = include_str!("../../resources/haproxy.cfg.j2");
 
-#[derive(Debug, PartialEq)]
+/// How long to wait for HAProxy to report the result of a reload.
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct ProxiedVm {
     pub(crate) id: Uuid,
-    pub(crate) domain: String,
+    /// The domains this VM is served on, the first one being its primary domain.
+    pub(crate) domains: Vec<String>,
     pub(crate) http_port: u16,
     pub(crate) https_port: u16,
+    /// The overrides for the proxy's default timeouts, if any.
+    pub(crate) timeouts: Option<ProxyTimeouts>,
 }
 
 impl From<&Workload> for ProxiedVm {
     fn from(workload: &Workload) -> Self {
         Self {
             id: workload.id,
-            domain: workload.domain.clone(),
+            domains: vec![workload.domain.clone()],
             http_port: workload.http_port(),
             https_port: workload.https_port(),
+            timeouts: workload.proxy_timeouts,
         }
     }
 }
@@ -41,6 +64,15 @@ pub trait ProxyService: Send + Sync {
 
     /// Stop proxying a VM.
     async fn stop_vm_proxy(&self, id: Uuid);
+
+    /// Change a VM's primary domain, while still proxying its previous domains.
+    async fn change_vm_domain(&self, id: Uuid, domain: String);
+
+    /// Stop proxying a domain that is no longer the primary domain of a VM.
+    async fn retire_vm_domain(&self, id: Uuid, domain: String);
+
+    /// Change the overrides for the timeouts used for a VM's traffic.
+    async fn set_vm_timeouts(&self, id: Uuid, timeouts: Option<ProxyTimeouts>);
 }
 
 pub struct ProxyServiceArgs {
@@ -52,8 +84,15 @@ pub struct ProxyServiceArgs {
     pub max_connections: u64,
     pub proxied_vms: Vec<ProxiedVm>,
     pub reload_config: bool,
+    pub ipv6: bool,
+    pub event_sender: EventSender,
 }
 
+/// Proxies workloads via HAProxy.
+///
+/// When reloading is enabled, every new config is checked with `haproxy -c` before replacing the current one, and the
+/// previous config is kept next to it so it can be restored if HAProxy fails to reload. A change that can't be applied
+/// is reverted and reported as an event for the workload it belongs to, so it never takes down the other workloads.
 pub struct HaProxyProxyService {
     config_file_path: PathBuf,
     master_socket_path: PathBuf,
@@ -62,6 +101,8 @@ pub struct HaProxyProxyService {
     agent_port: u16,
     max_connections: u64,
     reload_config: bool,
+    ipv6: bool,
+    event_sender: EventSender,
     proxied_vms: Mutex<BTreeMap<Uuid, ProxiedVm>>,
 }
 
@@ -76,6 +117,8 @@ impl HaProxyProxyService {
             max_connections,
             proxied_vms,
             reload_config,
+            ipv6,
+            event_sender,
         } = args;
         let proxied_vms: BTreeMap<_, _> = proxied_vms.into_iter().map(|vm| (vm.id, vm)).collect();
         Self {
@@ -86,6 +129,8 @@ impl HaProxyProxyService {
             agent_port,
             max_connections,
             reload_config,
+            ipv6,
+            event_sender,
             proxied_vms: proxied_vms.into(),
         }
     }
@@ -94,11 +139,28 @@ impl HaProxyProxyService {
         let mut socket =
             UnixSocket::new_stream()?.connect(&self.master_socket_path).await.context("Connecting to master socket")?;
         socket.write_all(b"reload\n").await?;
+        // Since 2.7 HAProxy replies with the result of the reload, older versions just close the connection.
+        let mut response = String::new();
+        match timeout(RELOAD_TIMEOUT, socket.read_to_string(&mut response)).await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => warn!("Failed to read reload response: {e}"),
+            Err(_) => warn!("Timed out waiting for reload response"),
+        }
+        if !reload_succeeded(&response) {
+            bail!("HAProxy failed to reload: {}", response.trim());
+        }
         Ok(())
     }
 
-    async fn validate_config(&self) -> Result<()> {
-        let output = Command::new("haproxy").arg("-c").arg("-f").arg(&self.config_file_path).output().await?;
+    /// A path next to the config file, so it can be atomically renamed into it.
+    fn sibling_path(&self, suffix: &str) -> PathBuf {
+        let mut path = self.config_file_path.clone().into_os_string();
+        path.push(suffix);
+        path.into()
+    }
+
+    async fn validate_config(&self, path: &Path) -> Result<()> {
+        let output = Command::new("haproxy").arg("-c").arg("-f").arg(path).output().await?;
         if !output.status.success() {
             let stderr_message = String::from_utf8_lossy(&output.stderr);
             let stdout_message = String::from_utf8_lossy(&output.stdout);
@@ -111,12 +173,13 @@ impl HaProxyProxyService {
         let backends: Vec<_> = proxied_vms
             .into_iter()
             .map(|vm| {
-                let ProxiedVm { id, domain, http_port, https_port } = vm;
+                let ProxiedVm { id, domains, http_port, https_port, timeouts } = vm;
                 ProxyBackend {
                     id: id.to_string(),
-                    domain: domain.clone(),
+                    domains: domains.join(" "),
                     http_address: format!("127.0.0.1:{http_port}"),
                     https_address: format!("127.0.0.1:{https_port}"),
+                    timeouts: timeouts.map(|timeouts| BackendTimeouts::new(&self.timeouts, timeouts)),
                 }
             })
             .collect();
@@ -126,19 +189,60 @@ impl HaProxyProxyService {
             agent_domain: self.agent_domain.clone(),
             agent_port: self.agent_port,
             backends,
+            ipv6: self.ipv6,
         };
         info!("Persisting HA proxy config using {} VMs as backends", context.backends.len());
         let config_file = context.render_config_file()?;
-        tokio::fs::write(&self.config_file_path, config_file).await.context("Failed to write HAProxy config file")?;
-        if self.reload_config {
-            self.validate_config().await.context("Failed to check config")?;
-            self.reload().await?;
+        if !self.reload_config {
+            fs::write(&self.config_file_path, config_file).await.context("Failed to write HAProxy config file")?;
+            return Ok(());
+        }
+
+        let candidate_path = self.sibling_path(".new");
+        fs::write(&candidate_path, config_file).await.context("Failed to write candidate HAProxy config file")?;
+        if let Err(e) = self.validate_config(&candidate_path).await {
+            let _ = fs::remove_file(&candidate_path).await;
+            return Err(e.context("Failed to check config"));
+        }
+        self.apply_config(&candidate_path).await
+    }
+
+    /// Replace the current config with a validated one and reload, restoring the previous config if that fails.
+    async fn apply_config(&self, candidate_path: &Path) -> Result<()> {
+        let previous_path = self.sibling_path(".previous");
+        let has_previous = match fs::copy(&self.config_file_path, &previous_path).await {
+            Ok(_) => true,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e).context("Failed to keep previous HAProxy config file"),
+        };
+        fs::rename(candidate_path, &self.config_file_path).await.context("Failed to replace HAProxy config file")?;
+        let Err(e) = self.reload().await else {
             info!("HA proxy config reloaded");
+            return Ok(());
+        };
+        if !has_previous {
+            return Err(e);
         }
-        Ok(())
+        error!("Failed to reload HAProxy, rolling back to the previous config: {e:#}");
+        fs::copy(&previous_path, &self.config_file_path).await.context("Failed to restore previous HAProxy config")?;
+        if let Err(e) = self.reload().await {
+            error!("Failed to reload previous HAProxy config: {e:#}");
+        }
+        Err(e.context("Rolled back to the previous config"))
+    }
+
+    async fn report_failure(&self, id: Uuid, error: anyhow::Error) {
+        error!("Failed to persist configuration: {error:#}");
+        let message = format!("Proxy configuration could not be applied: {error:#}");
+        self.event_sender.send_event(id, VmEvent::Warning { message }, Utc::now()).await;
     }
 }
 
+/// Check whether a reload succeeded based on the master socket's response.
+fn reload_succeeded(response: &str) -> bool {
+    !response.lines().any(|line| line.trim() == "Success=0")
+}
+
 #[async_trait]
 impl ProxyService for HaProxyProxyService {
     async fn persist_current_config(&self) -> Result<()> {
@@ -148,9 +252,15 @@ impl ProxyService for HaProxyProxyService {
 
     async fn start_vm_proxy(&self, vm: ProxiedVm) {
         let mut proxied_vms = self.proxied_vms.lock().await;
-        proxied_vms.insert(vm.id, vm);
+        let id = vm.id;
+        let previous = proxied_vms.insert(id, vm);
         if let Err(e) = self.persist_config(proxied_vms.values()).await {
-            error!("Failed to persist configuration: {e}");
+            // Don't keep a VM that breaks the config around, otherwise every later change would fail too.
+            match previous {
+                Some(vm) => proxied_vms.insert(id, vm),
+                None => proxied_vms.remove(&id),
+            };
+            self.report_failure(id, e).await;
         }
     }
 
@@ -158,14 +268,86 @@ impl ProxyService for HaProxyProxyService {
         let mut proxied_vms = self.proxied_vms.lock().await;
         proxied_vms.remove(&id);
     }
+
+    async fn change_vm_domain(&self, id: Uuid, domain: String) {
+        let mut proxied_vms = self.proxied_vms.lock().await;
+        let Some(vm) = proxied_vms.get_mut(&id) else {
+            warn!("VM {id} is not being proxied");
+            return;
+        };
+        let previous = vm.domains.clone();
+        vm.domains.retain(|d| d != &domain);
+        vm.domains.insert(0, domain);
+        if let Err(e) = self.persist_config(proxied_vms.values()).await {
+            if let Some(vm) = proxied_vms.get_mut(&id) {
+                vm.domains = previous;
+            }
+            self.report_failure(id, e).await;
+        }
+    }
+
+    async fn retire_vm_domain(&self, id: Uuid, domain: String) {
+        let mut proxied_vms = self.proxied_vms.lock().await;
+        let Some(vm) = proxied_vms.get_mut(&id) else {
+            return;
+        };
+        // The primary domain could have been changed back to this one in the meantime.
+        if vm.domains.first() == Some(&domain) {
+            return;
+        }
+        let previous = vm.domains.clone();
+        vm.domains.retain(|d| d != &domain);
+        if let Err(e) = self.persist_config(proxied_vms.values()).await {
+            if let Some(vm) = proxied_vms.get_mut(&id) {
+                vm.domains = previous;
+            }
+            self.report_failure(id, e).await;
+        }
+    }
+
+    async fn set_vm_timeouts(&self, id: Uuid, timeouts: Option<ProxyTimeouts>) {
+        let mut proxied_vms = self.proxied_vms.lock().await;
+        let Some(vm) = proxied_vms.get_mut(&id) else {
+            // The VM will be proxied using these timeouts when it's started.
+            return;
+        };
+        let previous = std::mem::replace(&mut vm.timeouts, timeouts);
+        if let Err(e) = self.persist_config(proxied_vms.values()).await {
+            if let Some(vm) = proxied_vms.get_mut(&id) {
+                vm.timeouts = previous;
+            }
+            self.report_failure(id, e).await;
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct ProxyBackend {
     id: String,
-    domain: String,
+    // Space separated, haproxy matches any of them.
+    domains: String,
     http_address: String,
     https_address: String,
+    timeouts: Option<BackendTimeouts>,
+}
+
+/// The timeouts for a VM's backends, in milliseconds.
+#[derive(Debug, PartialEq, Serialize)]
+struct BackendTimeouts {
+    connect: u64,
+    server: u64,
+    // The client timeout only applies to frontends. Once a connection is established HAProxy uses the tunnel timeout
+    // for both sides instead, which is how the client timeout is enforced per VM.
+    tunnel: u64,
+}
+
+impl BackendTimeouts {
+    fn new(defaults: &SniProxyConfigTimeouts, overrides: ProxyTimeouts) -> Self {
+        let ProxyTimeouts { connect_ms, client_ms, server_ms } = overrides;
+        let client = client_ms.unwrap_or(defaults.client);
+        let server = server_ms.unwrap_or(defaults.server);
+        Self { connect: connect_ms.unwrap_or(defaults.connect), server, tunnel: client.max(server) }
+    }
 }
 
 #[derive(Serialize)]
@@ -175,6 +357,7 @@ struct SniProxyTemplateContext {
     backends: Vec<ProxyBackend>,
     agent_domain: String,
     agent_port: u16,
+    ipv6: bool,
 }
 
 impl SniProxyTemplateContext {
@@ -189,6 +372,7 @@ impl SniProxyTemplateContext {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::{net::UnixListener, sync::mpsc::channel};
 
     #[test]
     fn render_config_file() {
@@ -261,12 +445,141 @@ backend backend-https-foo
             agent_port: 8080,
             backends: vec![ProxyBackend {
                 id: "foo".into(),
-                domain: "foo.nilcc.com".into(),
+                domains: "foo.nilcc.com".into(),
                 http_address: "127.0.0.1:9000".into(),
                 https_address: "127.0.0.1:9001".into(),
+                timeouts: None,
             }],
+            ipv6: false,
         };
         let config_file = config.render_config_file().unwrap();
         assert_eq!(config_file, expected_config);
     }
+
+    #[test]
+    fn render_dual_stack_config_file() {
+        let config = SniProxyTemplateContext {
+            max_connections: 100,
+            timeouts: SniProxyConfigTimeouts { connect: 5000, server: 50000, client: 50000 },
+            agent_domain: "agent1.example.com".into(),
+            agent_port: 8080,
+            backends: vec![],
+            ipv6: true,
+        };
+        let config_file = config.render_config_file().unwrap();
+        assert!(config_file.contains("    bind :::80 v4v6\n"), "{config_file}");
+        assert!(config_file.contains("    bind :::443 v4v6\n"), "{config_file}");
+    }
+
+    #[test]
+    fn render_backend_timeouts() {
+        let defaults = SniProxyConfigTimeouts { connect: 5000, server: 50000, client: 50000 };
+        let overrides = ProxyTimeouts { connect_ms: None, client_ms: Some(3_600_000), server_ms: Some(600_000) };
+        let timeouts = BackendTimeouts::new(&defaults, overrides);
+        assert_eq!(timeouts, BackendTimeouts { connect: 5000, server: 600_000, tunnel: 3_600_000 });
+
+        let config = SniProxyTemplateContext {
+            max_connections: 100,
+            timeouts: defaults,
+            agent_domain: "agent1.example.com".into(),
+            agent_port: 8080,
+            backends: vec![ProxyBackend {
+                id: "foo".into(),
+                domains: "foo.nilcc.com".into(),
+                http_address: "127.0.0.1:9000".into(),
+                https_address: "127.0.0.1:9001".into(),
+                timeouts: Some(timeouts),
+            }],
+            ipv6: false,
+        };
+        let config_file = config.render_config_file().unwrap();
+        let expected = "    server cvm 127.0.0.1:9001 check
+    timeout connect 5000ms
+    timeout server 600000ms
+    timeout tunnel 3600000ms
+";
+        assert!(config_file.contains(expected), "{config_file}");
+        assert_eq!(config_file.matches("timeout tunnel").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn change_domain() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let id = Uuid::new_v4();
+        let vm = ProxiedVm { id, domains: vec!["foo.com".into()], http_port: 9000, https_port: 9001, timeouts: None };
+        let service = HaProxyProxyService::new(ProxyServiceArgs {
+            config_file_path: dir.path().join("haproxy.cfg"),
+            master_socket_path: dir.path().join("master.sock"),
+            timeouts: SniProxyConfigTimeouts { connect: 5000, server: 50000, client: 50000 },
+            agent_domain: "agent1.example.com".into(),
+            agent_port: 8080,
+            max_connections: 100,
+            proxied_vms: vec![vm],
+            reload_config: false,
+            ipv6: false,
+            event_sender: EventSender(channel(1).0),
+        });
+        let domains = async || service.proxied_vms.lock().await[&id].domains.clone();
+
+        service.change_vm_domain(id, "bar.com".into()).await;
+        assert_eq!(domains().await, &["bar.com", "foo.com"]);
+
+        // The primary domain is never retired.
+        service.retire_vm_domain(id, "bar.com".into()).await;
+        assert_eq!(domains().await, &["bar.com", "foo.com"]);
+
+        service.retire_vm_domain(id, "foo.com".into()).await;
+        assert_eq!(domains().await, &["bar.com"]);
+
+        let config = std::fs::read_to_string(dir.path().join("haproxy.cfg")).expect("failed to read config");
+        assert!(config.contains("req.ssl_sni -i bar.com }"), "{config}");
+    }
+
+    #[test]
+    fn reload_response() {
+        assert!(reload_succeeded(""));
+        assert!(reload_succeeded("Success=1\n--\n[NOTICE] Loading success.\n"));
+        assert!(!reload_succeeded("Success=0\n--\n[ALERT] config : parsing [haproxy.cfg:20] : unknown keyword\n"));
+    }
+
+    #[tokio::test]
+    async fn rollback_on_reload_failure() {
+        let dir = tempfile::tempdir().expect("failed to create tempdir");
+        let config_path = dir.path().join("haproxy.cfg");
+        let socket_path = dir.path().join("master.sock");
+        let listener = UnixListener::bind(&socket_path).expect("failed to bind");
+        let master = tokio::spawn(async move {
+            // The first reload fails and the one after the rollback succeeds.
+            for response in ["Success=0\n--\n[ALERT] bad config\n", "Success=1\n"] {
+                let (mut stream, _) = listener.accept().await.expect("failed to accept");
+                let mut command = [0; 7];
+                stream.read_exact(&mut command).await.expect("failed to read command");
+                assert_eq!(&command, b"reload\n");
+                stream.write_all(response.as_bytes()).await.expect("failed to write response");
+            }
+        });
+        let (sender, mut receiver) = channel(1);
+        let service = HaProxyProxyService::new(ProxyServiceArgs {
+            config_file_path: config_path.clone(),
+            master_socket_path: socket_path,
+            timeouts: SniProxyConfigTimeouts { connect: 5000, server: 50000, client: 50000 },
+            agent_domain: "agent1.example.com".into(),
+            agent_port: 8080,
+            max_connections: 100,
+            proxied_vms: vec![],
+            reload_config: true,
+            ipv6: false,
+            event_sender: EventSender(sender),
+        });
+        std::fs::write(&config_path, "previous").expect("failed to write config");
+        let candidate_path = dir.path().join("haproxy.cfg.new");
+        std::fs::write(&candidate_path, "candidate").expect("failed to write candidate");
+
+        service.apply_config(&candidate_path).await.expect_err("reload succeeded");
+        master.await.expect("master socket failed");
+        assert_eq!(std::fs::read_to_string(&config_path).expect("failed to read config"), "previous");
+
+        service.report_failure(Uuid::new_v4(), anyhow::anyhow!("reload failed")).await;
+        receiver.try_recv().expect("no event sent");
+    }
 }
@@ -1,4 +1,5 @@
 use crate::repositories::artifacts::Artifacts;
+use crate::repositories::artifacts::ArtifactsUsage;
 use crate::repositories::changelog::ChangelogEntry;
 use crate::repositories::changelog::ChangelogEntryDetails;
 use crate::repositories::changelog::ChangelogEntryOperation;
@@ -7,16 +8,19 @@ use crate::repositories::changelog::ChangelogRepository;
 use crate::repositories::sqlite::ProviderMode;
 use crate::repositories::sqlite::RepositoryProvider;
 use crate::routes::Json;
+use crate::services::agent_backup::AgentBackup;
 use anyhow::Context;
 use anyhow::bail;
 use async_trait::async_trait;
 use axum::response::IntoResponse;
 use axum::response::Response;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use nilcc_agent_models::errors::RequestHandlerError;
 use nilcc_artifacts::VmType;
 use nilcc_artifacts::downloader::{ArtifactsDownloader, FileDownloader};
+use nilcc_artifacts::signature::{SigningKey, verify_signature};
 use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
 use std::collections::HashSet;
 use std::env;
 use std::fs::Permissions;
@@ -29,7 +33,7 @@ use strum::EnumDiscriminants;
 use tempfile::NamedTempFile;
 use tokio::fs;
 use tokio::process::Command;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use tracing::warn;
 use tracing::{error, info};
 use uuid::Uuid;
@@ -42,7 +46,13 @@ pub trait UpgradeService: Send + Sync {
     async fn install_artifacts(&self, version: String) -> Result<(), UpgradeError>;
     async fn uninstall_artifact_version(&self, version: &str) -> Result<(), CleanupError>;
     async fn upgrade_agent(&self, version: String) -> Result<(), UpgradeError>;
+    async fn rollback_agent(&self) -> Result<(), RollbackError>;
     async fn cleanup_artifacts(&self) -> Result<Vec<String>, CleanupError>;
+
+    /// Delete the artifacts versions selected by the garbage collection policy, returning them.
+    ///
+    /// When `dry_run` is set, the versions that would be deleted are returned but nothing is deleted.
+    async fn collect_artifacts(&self, dry_run: bool) -> Result<Vec<String>, CleanupError>;
     async fn artifacts_upgrade_state(&self) -> UpgradeState;
     async fn artifacts_versions(&self) -> anyhow::Result<Vec<String>>;
     async fn artifacts_changelog(&self) -> anyhow::Result<Vec<ChangelogEntryDetails>>;
@@ -61,6 +71,12 @@ pub enum UpgradeError {
     #[error("an upgrade to version {0} is already in progress")]
     ActiveUpgrade(String),
 
+    #[error("no keys to verify agent binaries with are configured")]
+    MissingSigningKeys,
+
+    #[error("agent binary is not signed")]
+    MissingSignature,
+
     #[error("internal error")]
     Internal,
 }
@@ -69,8 +85,34 @@ impl IntoResponse for UpgradeError {
     fn into_response(self) -> Response {
         let discriminant = UpgradeErrorDiscriminants::from(&self);
         let (code, message) = match self {
-            Self::InvalidVersion => (StatusCode::BAD_REQUEST, self.to_string()),
-            Self::ActiveUpgrade(_) | Self::ExistingVersion => (StatusCode::PRECONDITION_FAILED, self.to_string()),
+            Self::InvalidVersion | Self::MissingSignature => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::ActiveUpgrade(_) | Self::ExistingVersion | Self::MissingSigningKeys => {
+                (StatusCode::PRECONDITION_FAILED, self.to_string())
+            }
+            Self::Internal => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+        };
+        let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
+        (code, Json(response)).into_response()
+    }
+}
+
+#[derive(Debug, EnumDiscriminants, thiserror::Error)]
+pub enum RollbackError {
+    #[error("there's no previous version to roll back to")]
+    NoPreviousVersion,
+
+    #[error("an upgrade to version {0} is already in progress")]
+    ActiveUpgrade(String),
+
+    #[error("internal error")]
+    Internal,
+}
+
+impl IntoResponse for RollbackError {
+    fn into_response(self) -> Response {
+        let discriminant = RollbackErrorDiscriminants::from(&self);
+        let (code, message) = match self {
+            Self::NoPreviousVersion | Self::ActiveUpgrade(_) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
             Self::Internal => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
         };
         let response = RequestHandlerError::new(message, format!("{discriminant:?}"));
@@ -100,6 +142,42 @@ pub struct DefaultUpgradeServiceArgs {
     pub config_file_path: PathBuf,
     pub cvm_artifacts_path: PathBuf,
     pub vm_types: Vec<VmType>,
+
+    /// The keys the metadata of artifacts versions being installed must be signed by, if any.
+    pub signing_keys: Vec<SigningKey>,
+
+    /// The keys agent binaries must be signed by.
+    pub agent_signing_keys: Vec<SigningKey>,
+
+    /// Notified every time a new artifacts version is installed.
+    pub artifacts_installed: Arc<Notify>,
+
+    /// The policy that decides which artifacts versions are garbage collected.
+    pub gc_policy: ArtifactsGcPolicy,
+}
+
+/// The policy that decides which artifacts versions are garbage collected.
+#[derive(Clone, Debug)]
+pub struct ArtifactsGcPolicy {
+    /// The number of most recently installed versions that are always kept.
+    pub keep_latest: usize,
+
+    /// How long a version must have gone unused before it's deleted.
+    pub max_unused: TimeDelta,
+}
+
+impl ArtifactsGcPolicy {
+    /// Select the versions that should be deleted, given the ones used by workloads.
+    fn select(&self, mut versions: Vec<ArtifactsUsage>, used: &HashSet<String>, now: DateTime<Utc>) -> Vec<String> {
+        versions.sort_by(|a, b| b.installed_at.cmp(&a.installed_at));
+        versions
+            .into_iter()
+            .skip(self.keep_latest)
+            .filter(|usage| !used.contains(&usage.version))
+            .filter(|usage| now - usage.last_used_at.unwrap_or(usage.installed_at) >= self.max_unused)
+            .map(|usage| usage.version)
+            .collect()
+    }
 }
 
 pub struct DefaultUpgradeService {
@@ -108,21 +186,50 @@ pub struct DefaultUpgradeService {
     config_file_path: PathBuf,
     cvm_artifacts_path: PathBuf,
     repository_provider: Arc<dyn RepositoryProvider>,
+    artifacts_installed: Arc<Notify>,
+    signing_keys: Vec<SigningKey>,
+    agent_signing_keys: Arc<Vec<SigningKey>>,
+    gc_policy: ArtifactsGcPolicy,
     pub vm_types: Vec<VmType>,
 }
 
 impl DefaultUpgradeService {
     pub fn new(args: DefaultUpgradeServiceArgs) -> Self {
-        let DefaultUpgradeServiceArgs { repository_provider, config_file_path, cvm_artifacts_path, vm_types } = args;
+        let DefaultUpgradeServiceArgs {
+            repository_provider,
+            config_file_path,
+            cvm_artifacts_path,
+            vm_types,
+            signing_keys,
+            agent_signing_keys,
+            artifacts_installed,
+            gc_policy,
+        } = args;
         Self {
             artifacts: Default::default(),
             agent: Default::default(),
             repository_provider,
             config_file_path,
             cvm_artifacts_path,
+            artifacts_installed,
+            signing_keys,
+            agent_signing_keys: Arc::new(agent_signing_keys),
+            gc_policy,
             vm_types,
         }
     }
+
+    async fn used_artifacts_versions(&self) -> Result<HashSet<String>, CleanupError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await.map_err(|e| {
+            error!("Failed to get repository: {e}");
+            CleanupError::Internal
+        })?;
+        let workloads = repo.list().await.map_err(|e| {
+            error!("Failed to list workloads: {e}");
+            CleanupError::Internal
+        })?;
+        Ok(workloads.into_iter().map(|w| w.artifacts_version).collect())
+    }
 }
 
 #[async_trait]
@@ -146,13 +253,20 @@ impl UpgradeService for DefaultUpgradeService {
         if exists {
             return Err(UpgradeError::ExistingVersion);
         }
+        let installed = repo.list().await.map_err(|e| {
+            error!("Failed to list installed versions: {e}");
+            UpgradeError::Internal
+        })?;
+        let reuse_dirs = installed.into_iter().map(|a| self.cvm_artifacts_path.join(a.version)).collect();
 
         let vm_types = self.vm_types.clone();
-        let downloader = ArtifactsDownloader::new(version.clone(), vm_types.clone());
+        let downloader = ArtifactsDownloader::new(version.clone(), vm_types.clone())
+            .with_reuse_dirs(reuse_dirs)
+            .with_signing_keys(self.signing_keys.clone());
         downloader.validate_exists().await.map_err(|_| UpgradeError::InvalidVersion)?;
 
         info!("Initiating artifacts upgrade to version {version}");
-        let metadata = UpgradeMetadata { version: version.clone(), started_at: Utc::now(), vm_types };
+        let metadata = UpgradeMetadata { version: version.clone(), started_at: Utc::now(), vm_types, sha256: None };
         let state = self.artifacts.clone();
         *current = UpgradeState::Upgrading { metadata };
 
@@ -163,6 +277,7 @@ impl UpgradeService for DefaultUpgradeService {
             state,
             version,
             repository_provider: self.repository_provider.clone(),
+            artifacts_installed: self.artifacts_installed.clone(),
         };
         tokio::spawn(async move { worker.run().await });
         Ok(())
@@ -212,17 +327,7 @@ impl UpgradeService for DefaultUpgradeService {
     }
 
     async fn cleanup_artifacts(&self) -> Result<Vec<String>, CleanupError> {
-        let used_versions: HashSet<_> = {
-            let mut repo = self.repository_provider.workloads(Default::default()).await.map_err(|e| {
-                error!("Failed to get repository: {e}");
-                CleanupError::Internal
-            })?;
-            let workloads = repo.list().await.map_err(|e| {
-                error!("Failed to list workloads: {e}");
-                CleanupError::Internal
-            })?;
-            workloads.into_iter().map(|w| w.artifacts_version).collect()
-        };
+        let used_versions = self.used_artifacts_versions().await?;
 
         let mut repo = self.repository_provider.artifacts(Default::default()).await.map_err(|e| {
             error!("Failed to get repository: {e}");
@@ -247,6 +352,39 @@ impl UpgradeService for DefaultUpgradeService {
         Ok(deleted_versions)
     }
 
+    async fn collect_artifacts(&self, dry_run: bool) -> Result<Vec<String>, CleanupError> {
+        let used_versions = self.used_artifacts_versions().await?;
+        let mut repo = self.repository_provider.artifacts(Default::default()).await.map_err(|e| {
+            error!("Failed to get repository: {e}");
+            CleanupError::Internal
+        })?;
+        // Keep track of when versions were last used so they're only deleted after being unused for long enough. Dry
+        // runs leave this alone since versions in use are never selected anyway.
+        let now = Utc::now();
+        if !dry_run {
+            for version in &used_versions {
+                repo.set_last_used(version, now).await.map_err(|e| {
+                    error!("Failed to set last use of version {version}: {e}");
+                    CleanupError::Internal
+                })?;
+            }
+        }
+        let versions = repo.list_usage().await.map_err(|e| {
+            error!("Failed to list versions: {e}");
+            CleanupError::Internal
+        })?;
+        let versions = self.gc_policy.select(versions, &used_versions, now);
+        if dry_run || versions.is_empty() {
+            return Ok(versions);
+        }
+
+        info!("Garbage collecting {} artifacts versions", versions.len());
+        for version in &versions {
+            self.uninstall_artifact_version(version).await?;
+        }
+        Ok(versions)
+    }
+
     async fn upgrade_agent(&self, version: String) -> Result<(), UpgradeError> {
         let mut current = self.agent.lock().await;
         match &*current {
@@ -255,11 +393,18 @@ impl UpgradeService for DefaultUpgradeService {
             }
             UpgradeState::None | UpgradeState::Done { .. } => (),
         };
+        if self.agent_signing_keys.is_empty() {
+            return Err(UpgradeError::MissingSigningKeys);
+        }
         let url_path = agent_url(&version);
         FileDownloader::default().exists(&url_path).await.map_err(|e| {
             warn!("Failed to check if agent exists: {e:#}");
             UpgradeError::InvalidVersion
         })?;
+        FileDownloader::default().exists(&agent_signature_url(&version)).await.map_err(|e| {
+            warn!("Failed to check if agent signature exists: {e:#}");
+            UpgradeError::MissingSignature
+        })?;
 
         let agent_path = env::current_exe().map_err(|e| {
             error!("Failed to get agent binary path: {e}");
@@ -285,8 +430,12 @@ impl UpgradeService for DefaultUpgradeService {
             UpgradeError::Internal
         })?;
 
-        let metadata =
-            UpgradeMetadata { version: version.clone(), started_at: Utc::now(), vm_types: Default::default() };
+        let metadata = UpgradeMetadata {
+            version: version.clone(),
+            started_at: Utc::now(),
+            vm_types: Default::default(),
+            sha256: None,
+        };
         *current = UpgradeState::Upgrading { metadata };
         info!("Initiating agent upgrade to version {version}");
 
@@ -295,13 +444,59 @@ impl UpgradeService for DefaultUpgradeService {
             updater,
             state: self.agent.clone(),
             version,
+            backup: AgentBackup::new(agent_path.clone(), self.config_file_path.clone()),
             agent_path,
             config_path: self.config_file_path.clone(),
+            signing_keys: self.agent_signing_keys.clone(),
         };
         tokio::spawn(async move { worker.run().await });
         Ok(())
     }
 
+    async fn rollback_agent(&self) -> Result<(), RollbackError> {
+        let mut current = self.agent.lock().await;
+        if let UpgradeState::Upgrading { metadata } = &*current {
+            return Err(RollbackError::ActiveUpgrade(metadata.version.clone()));
+        }
+        let agent_path = env::current_exe().map_err(|e| {
+            error!("Failed to get agent binary path: {e}");
+            RollbackError::Internal
+        })?;
+        let backup = AgentBackup::new(agent_path, self.config_file_path.clone());
+        let record = backup
+            .load_record()
+            .await
+            .map_err(|e| {
+                error!("Failed to load upgrade record: {e:#}");
+                RollbackError::Internal
+            })?
+            .ok_or(RollbackError::NoPreviousVersion)?;
+
+        info!("Rolling back agent to version {}", record.previous_version);
+        let metadata = UpgradeMetadata {
+            version: record.previous_version,
+            started_at: Utc::now(),
+            vm_types: Default::default(),
+            sha256: None,
+        };
+        *current = UpgradeState::Upgrading { metadata };
+        let state = self.agent.clone();
+        tokio::spawn(async move {
+            let error = match backup.roll_back().await {
+                Ok(()) => None,
+                Err(e) => {
+                    error!("Failed to roll back agent: {e:#}");
+                    Some(format!("{e:#}"))
+                }
+            };
+            let mut state = state.lock().await;
+            if let UpgradeState::Upgrading { metadata } = &*state {
+                *state = UpgradeState::Done { metadata: metadata.clone(), finished_at: Utc::now(), error };
+            }
+        });
+        Ok(())
+    }
+
     async fn artifacts_upgrade_state(&self) -> UpgradeState {
         self.artifacts.lock().await.clone()
     }
@@ -346,6 +541,9 @@ pub struct UpgradeMetadata {
     pub version: String,
     pub started_at: DateTime<Utc>,
     pub vm_types: Vec<VmType>,
+
+    /// The SHA256 digest of the agent binary being installed, once its signature has been verified.
+    pub sha256: Option<[u8; 32]>,
 }
 
 struct ArtifactInstallWorker {
@@ -354,6 +552,7 @@ struct ArtifactInstallWorker {
     state: Arc<Mutex<UpgradeState>>,
     version: String,
     repository_provider: Arc<dyn RepositoryProvider>,
+    artifacts_installed: Arc<Notify>,
 }
 
 impl ArtifactInstallWorker {
@@ -377,6 +576,7 @@ impl ArtifactInstallWorker {
         let error = match self.perform_upgrade().await {
             Ok(_) => {
                 changelog.success().await;
+                self.artifacts_installed.notify_one();
                 None
             }
             Err(e) => {
@@ -436,8 +636,10 @@ struct AgentUpgradeWorker {
     updater: NamedTempFile,
     state: Arc<Mutex<UpgradeState>>,
     version: String,
+    backup: AgentBackup,
     agent_path: PathBuf,
     config_path: PathBuf,
+    signing_keys: Arc<Vec<SigningKey>>,
 }
 
 impl AgentUpgradeWorker {
@@ -466,13 +668,19 @@ impl AgentUpgradeWorker {
     }
 
     async fn perform_upgrade(&self) -> anyhow::Result<()> {
-        let Self { temp_agent_path, updater, version, agent_path, config_path, .. } = self;
+        let Self { temp_agent_path, updater, state, version, backup, agent_path, config_path, signing_keys } = self;
         let url_path = agent_url(version);
         info!("Downloading agent {url_path} to {}", temp_agent_path.path().display());
-        FileDownloader::default()
-            .download(&url_path, temp_agent_path.path())
-            .await
-            .context("Failed to download agent")?;
+        let downloader = FileDownloader::default();
+        downloader.download(&url_path, temp_agent_path.path()).await.context("Failed to download agent")?;
+        let signature =
+            downloader.fetch_text(&agent_signature_url(version)).await.context("Failed to download agent signature")?;
+        let binary = fs::read(temp_agent_path.path()).await.context("Failed to read agent binary")?;
+        let sha256 = verify_agent_binary(signing_keys, &binary, &signature)?;
+        if let UpgradeState::Upgrading { metadata } = &mut *state.lock().await {
+            metadata.sha256 = Some(sha256);
+        }
+        backup.create(crate::version::agent_version(), version).await?;
 
         info!("Starting updater at {}", updater.path().display());
         let child = Command::new(updater.path())
@@ -487,6 +695,9 @@ impl AgentUpgradeWorker {
         if status.success() {
             Ok(())
         } else {
+            if let Err(e) = backup.discard_record().await {
+                warn!("Failed to discard upgrade record: {e:#}");
+            }
             let stderr = String::from_utf8_lossy(&stderr);
             let stdout = String::from_utf8_lossy(&stdout);
             bail!("agent updater failed with status code {status}: stdout = {stdout}, stderr = {stderr}");
@@ -498,6 +709,19 @@ fn agent_url(version: &str) -> String {
     format!("/{version}/nilcc-agent/x86-64/nilcc-agent")
 }
 
+/// The URL of the hex encoded detached ed25519 signature over an agent binary.
+fn agent_signature_url(version: &str) -> String {
+    format!("{}.sig", agent_url(version))
+}
+
+/// Verify an agent binary is signed by one of the trusted keys, returning its SHA256 digest.
+fn verify_agent_binary(keys: &[SigningKey], binary: &[u8], signature: &str) -> anyhow::Result<[u8; 32]> {
+    let signer = verify_signature(keys, binary, signature).context("Invalid agent binary signature")?;
+    let sha256 = Sha256::digest(binary).into();
+    info!("Agent binary with SHA256 {} is signed by {}", hex::encode(sha256), signer.name);
+    Ok(sha256)
+}
+
 struct ChangelogAppender {
     repo: Box<dyn ChangelogRepository>,
     id: Uuid,
@@ -528,3 +752,54 @@ impl ChangelogAppender {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    #[test]
+    fn gc_policy() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let days_ago = |days| now - TimeDelta::days(days);
+        let usage = |version: &str, installed_days_ago, last_used_days_ago: Option<i64>| ArtifactsUsage {
+            version: version.into(),
+            installed_at: days_ago(installed_days_ago),
+            last_used_at: last_used_days_ago.map(days_ago),
+        };
+        let versions = vec![
+            usage("0.1.0", 100, None),
+            usage("0.2.0", 90, Some(5)),
+            usage("0.3.0", 80, None),
+            usage("0.4.0", 70, Some(40)),
+            usage("0.5.0", 60, None),
+            usage("0.6.0", 1, None),
+        ];
+        let used = HashSet::from(["0.3.0".to_string()]);
+        let policy = ArtifactsGcPolicy { keep_latest: 2, max_unused: TimeDelta::days(30) };
+
+        // 0.5.0 and 0.6.0 are the latest ones, 0.3.0 is in use and 0.2.0 was used recently.
+        let mut selected = policy.select(versions, &used, now);
+        selected.sort();
+        assert_eq!(selected, &["0.1.0", "0.4.0"]);
+    }
+
+    #[test]
+    fn agent_binary_signature() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).expect("failed to generate key");
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("invalid key");
+        let public_key = key_pair.public_key().as_ref().try_into().expect("invalid public key length");
+        let keys = [SigningKey { name: "release".into(), public_key }];
+        let binary = b"\x7fELF agent";
+        let signature = hex::encode(key_pair.sign(binary));
+
+        let sha256 = verify_agent_binary(&keys, binary, &signature).expect("signature not valid");
+        assert_eq!(sha256, <[u8; 32]>::from(Sha256::digest(binary)));
+
+        assert!(verify_agent_binary(&keys, b"\x7fELF tampered", &signature).is_err());
+        assert!(verify_agent_binary(&[], binary, &signature).is_err());
+    }
+}
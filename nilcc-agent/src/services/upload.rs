@@ -0,0 +1,426 @@
+use crate::repositories::workload::StoredFile;
+use async_trait::async_trait;
+use nilcc_agent_models::workloads::uploads::UploadStatus;
+use serde::{Deserialize, Serialize};
+use serde_with::{hex::Hex, serde_as};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    io::{self, SeekFrom},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use strum::EnumDiscriminants;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Stores files that are uploaded in chunks so workloads can reference them rather than embedding them in requests.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait UploadService: Send + Sync {
+    /// Start an upload for a file of the given size and hash.
+    async fn create_upload(&self, size: u64, sha256: [u8; 32]) -> Result<Uuid, UploadError>;
+
+    /// Write a chunk of an upload.
+    ///
+    /// Chunks must be sent in order, so the offset must match the number of bytes received so far. Once the last
+    /// chunk is received the file's hash is checked, and the upload is discarded if it doesn't match.
+    async fn write_chunk(&self, id: Uuid, offset: u64, chunk: &[u8]) -> Result<UploadStatus, UploadError>;
+
+    /// Get the state of an upload.
+    async fn upload_status(&self, id: Uuid) -> Result<UploadStatus, UploadError>;
+
+    /// Store a complete upload as one of a workload's files, without loading it into memory.
+    ///
+    /// The upload itself is kept until it's deleted, so it can be used again if creating the workload fails.
+    async fn store_upload(&self, id: Uuid, workload_id: Uuid) -> Result<StoredFile, UploadError>;
+
+    /// Delete an upload.
+    async fn delete_upload(&self, id: Uuid);
+
+    /// Delete the files stored for a workload.
+    async fn delete_workload_files(&self, workload_id: Uuid);
+}
+
+#[derive(Debug, thiserror::Error, EnumDiscriminants)]
+pub enum UploadError {
+    #[error("upload {0} not found")]
+    UploadNotFound(Uuid),
+
+    #[error("files can't be larger than {0} bytes")]
+    FileTooLarge(u64),
+
+    #[error("expected chunk at offset {0}")]
+    OffsetMismatch(u64),
+
+    #[error("chunk goes past the end of the file")]
+    ChunkTooLarge,
+
+    #[error("file hash does not match the expected one")]
+    HashMismatch,
+
+    #[error("upload {0} is not complete")]
+    Incomplete(Uuid),
+
+    #[error("internal: {0}")]
+    Internal(String),
+}
+
+impl From<io::Error> for UploadError {
+    fn from(e: io::Error) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+/// The metadata of an upload, which is persisted next to its contents so uploads survive restarts.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct UploadMetadata {
+    size: u64,
+    #[serde_as(as = "Hex")]
+    sha256: [u8; 32],
+}
+
+struct Upload {
+    size: u64,
+    sha256: [u8; 32],
+    received: u64,
+    hasher: Sha256,
+    complete: bool,
+    last_activity: Instant,
+}
+
+impl Upload {
+    fn status(&self) -> UploadStatus {
+        UploadStatus { size: self.size, received: self.received, complete: self.complete }
+    }
+}
+
+pub struct DefaultUploadServiceArgs {
+    /// The directory uploads are stored in.
+    pub directory: PathBuf,
+
+    /// The VM store, where the files workloads reference are stored in a `{workload_id}.files` directory.
+    pub vm_store: PathBuf,
+
+    /// The maximum size of an uploaded file, in bytes.
+    pub max_file_size: u64,
+
+    /// How long uploads are kept after their last chunk is received.
+    pub expiry: Duration,
+}
+
+pub struct DefaultUploadService {
+    directory: PathBuf,
+    vm_store: PathBuf,
+    max_file_size: u64,
+    expiry: Duration,
+    uploads: Mutex<HashMap<Uuid, Upload>>,
+}
+
+impl DefaultUploadService {
+    /// Create a new upload service.
+    ///
+    /// Uploads left behind by a previous run are restored, and their expiry starts over.
+    pub async fn new(args: DefaultUploadServiceArgs) -> io::Result<Self> {
+        let DefaultUploadServiceArgs { directory, vm_store, max_file_size, expiry } = args;
+        fs::create_dir_all(&directory).await?;
+        let service = Self { directory, vm_store, max_file_size, expiry, uploads: Default::default() };
+        service.restore_uploads().await?;
+        Ok(service)
+    }
+
+    async fn restore_uploads(&self) -> io::Result<()> {
+        let mut uploads = self.uploads.lock().await;
+        let mut entries = fs::read_dir(&self.directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<Uuid>().ok()) else {
+                continue;
+            };
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            match self.restore_upload(id).await {
+                Ok(upload) => {
+                    info!("Restored upload {id} with {}/{} bytes", upload.received, upload.size);
+                    uploads.insert(id, upload);
+                }
+                Err(e) => {
+                    warn!("Discarding upload {id} that can't be restored: {e}");
+                    self.remove_files(id).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn restore_upload(&self, id: Uuid) -> io::Result<Upload> {
+        let metadata = fs::read(self.metadata_path(id)).await?;
+        let UploadMetadata { size, sha256 } = serde_json::from_slice(&metadata).map_err(io::Error::other)?;
+        // The hash is computed incrementally so it needs to be recomputed over whatever was received so far.
+        let mut file = fs::File::open(self.upload_path(id)).await?;
+        let mut hasher = Sha256::new();
+        let mut received = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            received += read as u64;
+        }
+        if received > size {
+            return Err(io::Error::other("upload is larger than expected"));
+        }
+        let complete = received == size;
+        if complete && <[u8; 32]>::from(hasher.clone().finalize()) != sha256 {
+            return Err(io::Error::other("upload doesn't match its expected hash"));
+        }
+        Ok(Upload { size, sha256, received, hasher, complete, last_activity: Instant::now() })
+    }
+
+    fn upload_path(&self, id: Uuid) -> PathBuf {
+        self.directory.join(id.to_string())
+    }
+
+    fn metadata_path(&self, id: Uuid) -> PathBuf {
+        self.directory.join(format!("{id}.json"))
+    }
+
+    fn workload_files_path(&self, workload_id: Uuid) -> PathBuf {
+        self.vm_store.join(format!("{workload_id}.files"))
+    }
+
+    async fn remove_files(&self, id: Uuid) {
+        for path in [self.upload_path(id), self.metadata_path(id)] {
+            match fs::remove_file(&path).await {
+                Ok(()) => (),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => warn!("Failed to delete {}: {e}", path.display()),
+            }
+        }
+    }
+
+    async fn purge_expired(&self, uploads: &mut HashMap<Uuid, Upload>) {
+        let expired: Vec<_> = uploads
+            .iter()
+            .filter(|(_, upload)| upload.last_activity.elapsed() >= self.expiry)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            info!("Deleting expired upload {id}");
+            uploads.remove(&id);
+            self.remove_files(id).await;
+        }
+    }
+
+    async fn delete_workload_files(&self, workload_id: Uuid) {
+        let path = self.workload_files_path(workload_id);
+        match fs::remove_dir_all(&path).await {
+            Ok(()) => info!("Deleted files stored for workload {workload_id}"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => warn!("Failed to delete {}: {e}", path.display()),
+        }
+    }
+}
+
+#[async_trait]
+impl UploadService for DefaultUploadService {
+    async fn create_upload(&self, size: u64, sha256: [u8; 32]) -> Result<Uuid, UploadError> {
+        if size > self.max_file_size {
+            return Err(UploadError::FileTooLarge(self.max_file_size));
+        }
+        let mut uploads = self.uploads.lock().await;
+        self.purge_expired(&mut uploads).await;
+
+        let id = Uuid::new_v4();
+        fs::File::create(self.upload_path(id)).await?;
+        let metadata = serde_json::to_vec(&UploadMetadata { size, sha256 }).map_err(io::Error::other)?;
+        fs::write(self.metadata_path(id), metadata).await?;
+        let upload =
+            Upload { size, sha256, received: 0, hasher: Sha256::new(), complete: false, last_activity: Instant::now() };
+        info!("Starting upload {id} for a {size} bytes file");
+        uploads.insert(id, upload);
+        Ok(id)
+    }
+
+    async fn write_chunk(&self, id: Uuid, offset: u64, chunk: &[u8]) -> Result<UploadStatus, UploadError> {
+        let mut uploads = self.uploads.lock().await;
+        let upload = uploads.get_mut(&id).ok_or(UploadError::UploadNotFound(id))?;
+        if offset != upload.received {
+            return Err(UploadError::OffsetMismatch(upload.received));
+        }
+        if offset + chunk.len() as u64 > upload.size {
+            return Err(UploadError::ChunkTooLarge);
+        }
+
+        let mut file = fs::OpenOptions::new().write(true).open(self.upload_path(id)).await?;
+        // Drop anything a previously failed write may have left behind.
+        file.set_len(offset).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.write_all(chunk).await?;
+        file.flush().await?;
+
+        upload.hasher.update(chunk);
+        upload.received += chunk.len() as u64;
+        upload.last_activity = Instant::now();
+        if upload.received == upload.size {
+            let sha256: [u8; 32] = upload.hasher.clone().finalize().into();
+            if sha256 != upload.sha256 {
+                warn!("Upload {id} doesn't match its expected hash, discarding it");
+                uploads.remove(&id);
+                self.remove_files(id).await;
+                return Err(UploadError::HashMismatch);
+            }
+            info!("Upload {id} is complete");
+            upload.complete = true;
+        }
+        Ok(upload.status())
+    }
+
+    async fn upload_status(&self, id: Uuid) -> Result<UploadStatus, UploadError> {
+        let uploads = self.uploads.lock().await;
+        let upload = uploads.get(&id).ok_or(UploadError::UploadNotFound(id))?;
+        Ok(upload.status())
+    }
+
+    async fn store_upload(&self, id: Uuid, workload_id: Uuid) -> Result<StoredFile, UploadError> {
+        let uploads = self.uploads.lock().await;
+        let upload = uploads.get(&id).ok_or(UploadError::UploadNotFound(id))?;
+        if !upload.complete {
+            return Err(UploadError::Incomplete(id));
+        }
+        let directory = self.workload_files_path(workload_id);
+        fs::create_dir_all(&directory).await?;
+        // Files are named after their hash so the same upload can back more than one of the workload's files.
+        let path = directory.join(hex::encode(upload.sha256));
+        match fs::hard_link(self.upload_path(id), &path).await {
+            Ok(()) => info!("Stored upload {id} at {}", path.display()),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(StoredFile { path, sha256: upload.sha256, size: upload.size })
+    }
+
+    async fn delete_upload(&self, id: Uuid) {
+        if self.uploads.lock().await.remove(&id).is_some() {
+            info!("Deleting upload {id}");
+            self.remove_files(id).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{TempDir, tempdir};
+
+    async fn make_service(max_file_size: u64, expiry: Duration) -> (DefaultUploadService, TempDir) {
+        let dir = tempdir().expect("failed to create tempdir");
+        let args = DefaultUploadServiceArgs {
+            directory: dir.path().join("uploads"),
+            vm_store: dir.path().into(),
+            max_file_size,
+            expiry,
+        };
+        let service = DefaultUploadService::new(args).await.expect("failed to create service");
+        (service, dir)
+    }
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+
+    #[tokio::test]
+    async fn chunked_upload() {
+        let (service, _dir) = make_service(1024, Duration::from_secs(60)).await;
+        let contents = b"hello world";
+        let id = service.create_upload(contents.len() as u64, sha256(contents)).await.expect("failed to create");
+
+        let status = service.write_chunk(id, 0, &contents[..5]).await.expect("failed to write");
+        assert_eq!(status, UploadStatus { size: 11, received: 5, complete: false });
+        let workload_id = Uuid::new_v4();
+        service.store_upload(id, workload_id).await.expect_err("stored incomplete upload");
+
+        let err = service.write_chunk(id, 0, &contents[5..]).await.expect_err("wrote at wrong offset");
+        assert!(matches!(err, UploadError::OffsetMismatch(5)), "{err:?}");
+
+        let status = service.write_chunk(id, 5, &contents[5..]).await.expect("failed to write");
+        assert!(status.complete);
+        let file = service.store_upload(id, workload_id).await.expect("failed to store");
+        assert_eq!(file.sha256, sha256(contents));
+        assert_eq!(file.size, 11);
+        assert_eq!(file.path, service.workload_files_path(workload_id).join(hex::encode(file.sha256)));
+        // storing it again is a no-op
+        assert_eq!(service.store_upload(id, workload_id).await.expect("failed to store"), file);
+
+        service.delete_upload(id).await;
+        let err = service.upload_status(id).await.expect_err("upload still exists");
+        assert!(matches!(err, UploadError::UploadNotFound(_)), "{err:?}");
+        assert_eq!(std::fs::read(&file.path).expect("stored file deleted"), contents);
+
+        service.delete_workload_files(workload_id).await;
+        assert!(!file.path.exists());
+    }
+
+    #[tokio::test]
+    async fn restored_uploads() {
+        let (service, dir) = make_service(1024, Duration::from_secs(60)).await;
+        let contents = b"hello world";
+        let partial = service.create_upload(contents.len() as u64, sha256(contents)).await.expect("failed to create");
+        service.write_chunk(partial, 0, &contents[..5]).await.expect("failed to write");
+        let complete = service.create_upload(3, sha256(b"foo")).await.expect("failed to create");
+        service.write_chunk(complete, 0, b"foo").await.expect("failed to write");
+        drop(service);
+
+        let args = DefaultUploadServiceArgs {
+            directory: dir.path().join("uploads"),
+            vm_store: dir.path().into(),
+            max_file_size: 1024,
+            expiry: Duration::from_secs(60),
+        };
+        let service = DefaultUploadService::new(args).await.expect("failed to create service");
+        let status = service.upload_status(complete).await.expect("upload not restored");
+        assert_eq!(status, UploadStatus { size: 3, received: 3, complete: true });
+
+        let status = service.write_chunk(partial, 5, &contents[5..]).await.expect("failed to write");
+        assert!(status.complete);
+    }
+
+    #[tokio::test]
+    async fn hash_mismatch() {
+        let (service, _dir) = make_service(1024, Duration::from_secs(60)).await;
+        let id = service.create_upload(3, sha256(b"foo")).await.expect("failed to create");
+        let err = service.write_chunk(id, 0, b"bar").await.expect_err("hash matched");
+        assert!(matches!(err, UploadError::HashMismatch), "{err:?}");
+        assert!(!service.upload_path(id).exists());
+        assert!(!service.metadata_path(id).exists());
+    }
+
+    #[tokio::test]
+    async fn size_limits() {
+        let (service, _dir) = make_service(4, Duration::from_secs(60)).await;
+        let err = service.create_upload(5, sha256(b"hello")).await.expect_err("upload created");
+        assert!(matches!(err, UploadError::FileTooLarge(4)), "{err:?}");
+
+        let id = service.create_upload(3, sha256(b"foo")).await.expect("failed to create");
+        let err = service.write_chunk(id, 0, b"food").await.expect_err("chunk written");
+        assert!(matches!(err, UploadError::ChunkTooLarge), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn expired_uploads() {
+        let (service, _dir) = make_service(1024, Duration::ZERO).await;
+        let id = service.create_upload(3, sha256(b"foo")).await.expect("failed to create");
+        service.create_upload(3, sha256(b"bar")).await.expect("failed to create");
+        service.upload_status(id).await.expect_err("upload didn't expire");
+        assert!(!service.upload_path(id).exists());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::repositories::{
+    sqlite::{ProviderError, RepositoryProvider},
+    usage::{UsageRepositoryError, UsageSample},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Provides access to the resources workloads were allocated over time.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait UsageService: Send + Sync {
+    /// Get the aggregated usage for a workload within a time range.
+    async fn workload_usage(
+        &self,
+        id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<WorkloadUsage, UsageServiceError>;
+
+    /// Get the raw usage samples for a workload within a time range.
+    async fn workload_samples(
+        &self,
+        id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSample>, UsageServiceError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UsageServiceError {
+    #[error("internal: {0}")]
+    Internal(String),
+}
+
+impl From<ProviderError> for UsageServiceError {
+    fn from(e: ProviderError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<UsageRepositoryError> for UsageServiceError {
+    fn from(e: UsageRepositoryError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+/// The aggregated resources a workload was allocated.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorkloadUsage {
+    pub first_sample_at: Option<DateTime<Utc>>,
+    pub last_sample_at: Option<DateTime<Utc>>,
+    pub running_seconds: u64,
+    pub cpu_seconds: u64,
+    pub gpu_seconds: u64,
+    pub memory_mb_seconds: u64,
+    pub disk_gb_seconds: u64,
+}
+
+impl WorkloadUsage {
+    fn aggregate(samples: &[UsageSample]) -> Self {
+        let mut usage = Self {
+            first_sample_at: samples.first().map(|s| s.sampled_at),
+            last_sample_at: samples.last().map(|s| s.sampled_at),
+            ..Default::default()
+        };
+        for sample in samples {
+            let duration = u64::from(sample.duration_seconds);
+            usage.running_seconds += duration;
+            usage.cpu_seconds += u64::from(sample.cpus) * duration;
+            usage.gpu_seconds += u64::from(sample.gpus) * duration;
+            usage.memory_mb_seconds += u64::from(sample.memory_mb) * duration;
+            usage.disk_gb_seconds += u64::from(sample.disk_space_gb) * duration;
+        }
+        usage
+    }
+}
+
+pub struct DefaultUsageService {
+    repository_provider: Arc<dyn RepositoryProvider>,
+}
+
+impl DefaultUsageService {
+    pub fn new(repository_provider: Arc<dyn RepositoryProvider>) -> Self {
+        Self { repository_provider }
+    }
+}
+
+#[async_trait]
+impl UsageService for DefaultUsageService {
+    async fn workload_usage(
+        &self,
+        id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<WorkloadUsage, UsageServiceError> {
+        let samples = self.workload_samples(id, from, to).await?;
+        Ok(WorkloadUsage::aggregate(&samples))
+    }
+
+    async fn workload_samples(
+        &self,
+        id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageSample>, UsageServiceError> {
+        let mut repo = self.repository_provider.usage(Default::default()).await?;
+        let samples = repo.list(id, from, to).await?;
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn aggregate() {
+        let workload_id = Uuid::new_v4();
+        let now = Utc::now();
+        let samples = [
+            UsageSample {
+                workload_id,
+                sampled_at: now,
+                duration_seconds: 60,
+                cpus: 2,
+                gpus: 1,
+                memory_mb: 1024,
+                disk_space_gb: 10,
+            },
+            UsageSample {
+                workload_id,
+                sampled_at: now + TimeDelta::seconds(30),
+                duration_seconds: 30,
+                cpus: 4,
+                gpus: 0,
+                memory_mb: 2048,
+                disk_space_gb: 10,
+            },
+        ];
+        let usage = WorkloadUsage::aggregate(&samples);
+        let expected = WorkloadUsage {
+            first_sample_at: Some(now),
+            last_sample_at: Some(now + TimeDelta::seconds(30)),
+            running_seconds: 90,
+            cpu_seconds: 240,
+            gpu_seconds: 60,
+            memory_mb_seconds: 1024 * 60 + 2048 * 30,
+            disk_gb_seconds: 900,
+        };
+        assert_eq!(usage, expected);
+    }
+
+    #[test]
+    fn aggregate_empty() {
+        assert_eq!(WorkloadUsage::aggregate(&[]), WorkloadUsage::default());
+    }
+}
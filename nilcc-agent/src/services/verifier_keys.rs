@@ -0,0 +1,419 @@
+use crate::{
+    clients::cvm_agent::CvmAgentClient,
+    heartbeat_verifier::{KeyLookupError, PublicKey, VerifierKeys},
+    repositories::{
+        sqlite::{ProviderError, ProviderMode, RepositoryProvider},
+        verifier_keys::VerifierKeyRepositoryError,
+        workload::{Workload, WorkloadRepository, WorkloadRepositoryError},
+    },
+    services::vm::VmService,
+};
+use async_trait::async_trait;
+use cvm_agent_models::heartbeat::HeartbeatStatusResponse;
+use std::sync::Arc;
+use strum::EnumDiscriminants;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Manages the lifecycle of the keys workloads submit verifier heartbeats with.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait VerifierKeyService: Send + Sync {
+    /// List every key along with the workload it's assigned to, if any.
+    async fn list_keys(&self) -> Result<Vec<VerifierKeyStatus>, VerifierKeyServiceError>;
+
+    /// Move the workload using a key over to a fresh key and retire the previous one.
+    async fn rotate_key(&self, public_key: &[u8]) -> Result<RotatedKey, VerifierKeyServiceError>;
+
+    /// Retire a key that isn't being used so it's never handed out again.
+    async fn retire_key(&self, public_key: &[u8]) -> Result<(), VerifierKeyServiceError>;
+}
+
+#[derive(Debug, thiserror::Error, EnumDiscriminants)]
+pub enum VerifierKeyServiceError {
+    #[error("key not found")]
+    KeyNotFound,
+
+    #[error("key is not assigned to any workload")]
+    KeyNotAssigned,
+
+    #[error("key is in use by workload {0}, rotate it instead")]
+    KeyInUse(Uuid),
+
+    #[error("key is in use")]
+    KeyHeld,
+
+    #[error("key is retired")]
+    KeyRetired,
+
+    #[error("no more verifier keys available")]
+    NoMoreKeys,
+
+    #[error("internal: {0}")]
+    Internal(String),
+}
+
+impl From<ProviderError> for VerifierKeyServiceError {
+    fn from(e: ProviderError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<WorkloadRepositoryError> for VerifierKeyServiceError {
+    fn from(e: WorkloadRepositoryError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<VerifierKeyRepositoryError> for VerifierKeyServiceError {
+    fn from(e: VerifierKeyRepositoryError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<KeyLookupError> for VerifierKeyServiceError {
+    fn from(e: KeyLookupError) -> Self {
+        match e {
+            KeyLookupError::NotFound => Self::KeyNotFound,
+            KeyLookupError::AlreadyInUse => Self::KeyHeld,
+            KeyLookupError::Retired => Self::KeyRetired,
+        }
+    }
+}
+
+/// A verifier key along with what it's being used for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifierKeyStatus {
+    pub key: PublicKey,
+    pub workload_id: Option<Uuid>,
+
+    /// The heartbeat status reported by the CVM using this key, if it could be fetched.
+    pub heartbeat: Option<HeartbeatStatusResponse>,
+}
+
+/// The outcome of rotating a key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RotatedKey {
+    pub workload_id: Uuid,
+    pub key: PublicKey,
+}
+
+pub struct VerifierKeyServiceArgs {
+    pub verifier_keys: VerifierKeys,
+    pub repository_provider: Arc<dyn RepositoryProvider>,
+    pub vm_service: Arc<dyn VmService>,
+    pub cvm_agent_client: Arc<dyn CvmAgentClient>,
+}
+
+pub struct DefaultVerifierKeyService {
+    verifier_keys: VerifierKeys,
+    repository_provider: Arc<dyn RepositoryProvider>,
+    vm_service: Arc<dyn VmService>,
+    cvm_agent_client: Arc<dyn CvmAgentClient>,
+    // Serializes rotations and retirements so two of them can't act on the same key at once.
+    lock: Mutex<()>,
+}
+
+impl DefaultVerifierKeyService {
+    /// Create the service, taking out of the pool any keys that were retired in the past.
+    ///
+    /// This needs to happen before any workloads are started so retired keys are never handed out.
+    pub async fn new(args: VerifierKeyServiceArgs) -> anyhow::Result<Self> {
+        let VerifierKeyServiceArgs { verifier_keys, repository_provider, vm_service, cvm_agent_client } = args;
+        let mut repo = repository_provider.verifier_keys(Default::default()).await?;
+        let retired_keys = repo.list_retired().await?;
+        for key in &retired_keys {
+            if let Err(e) = verifier_keys.retire(&key.public_key) {
+                // This can only happen if the number of keys shrunk since it was retired.
+                warn!("Ignoring retired key {}: {e}", hex::encode(&key.public_key));
+            }
+        }
+        info!("Found {} retired verifier keys", retired_keys.len());
+        Ok(Self { verifier_keys, repository_provider, vm_service, cvm_agent_client, lock: Default::default() })
+    }
+
+    fn assigned_workload<'a>(workloads: &'a [Workload], key: &PublicKey) -> Option<&'a Workload> {
+        workloads
+            .iter()
+            .find(|w| w.heartbeat.as_ref().and_then(|h| h.wallet_public_key.as_deref()) == Some(key.public.as_slice()))
+    }
+
+    async fn assign_new_key(
+        &self,
+        repo: &mut dyn WorkloadRepository,
+        workload: &Workload,
+        key: &PublicKey,
+    ) -> Result<PublicKey, VerifierKeyServiceError> {
+        let new_key = self.verifier_keys.next_key().map_err(|_| VerifierKeyServiceError::NoMoreKeys)?;
+        let new_public_key =
+            self.verifier_keys.find(&new_key.public_key()).ok_or(VerifierKeyServiceError::KeyNotFound)?;
+        let mut heartbeat = workload.heartbeat.clone();
+        if let Some(heartbeat) = &mut heartbeat {
+            heartbeat.wallet_public_key = Some(new_key.public_key().to_vec());
+        }
+        repo.set_heartbeat(workload.id, heartbeat).await?;
+        info!(
+            "Rotating heartbeat key for workload {} from {} to {}",
+            workload.id,
+            hex::encode(key.public),
+            hex::encode(new_public_key.public)
+        );
+        self.vm_service
+            .rotate_heartbeat_key(workload.id, new_key)
+            .await
+            .map_err(|e| VerifierKeyServiceError::Internal(e.to_string()))?;
+        Ok(new_public_key)
+    }
+
+    async fn persist_retirement(&self, key: &PublicKey) -> Result<(), VerifierKeyServiceError> {
+        let mut repo = self.repository_provider.verifier_keys(Default::default()).await?;
+        repo.retire(&key.public).await?;
+        info!("Retired verifier key {}", hex::encode(key.public));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VerifierKeyService for DefaultVerifierKeyService {
+    async fn list_keys(&self) -> Result<Vec<VerifierKeyStatus>, VerifierKeyServiceError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workloads = repo.list().await?;
+        let mut keys = Vec::new();
+        for key in self.verifier_keys.public_keys() {
+            let workload = Self::assigned_workload(&workloads, &key);
+            let heartbeat = match workload {
+                Some(workload) => match self.cvm_agent_client.heartbeat_status(workload.cvm_agent_port()).await {
+                    Ok(status) => Some(status),
+                    Err(e) => {
+                        warn!("Failed to get heartbeat status for workload {}: {e}", workload.id);
+                        None
+                    }
+                },
+                None => None,
+            };
+            keys.push(VerifierKeyStatus { key, workload_id: workload.map(|w| w.id), heartbeat });
+        }
+        Ok(keys)
+    }
+
+    async fn rotate_key(&self, public_key: &[u8]) -> Result<RotatedKey, VerifierKeyServiceError> {
+        let _guard = self.lock.lock().await;
+        let key = self.verifier_keys.find(public_key).ok_or(VerifierKeyServiceError::KeyNotFound)?;
+        if key.retired {
+            return Err(VerifierKeyServiceError::KeyRetired);
+        }
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let workloads = repo.list().await?;
+        let workload = Self::assigned_workload(&workloads, &key).ok_or(VerifierKeyServiceError::KeyNotAssigned)?;
+        let workload_id = workload.id;
+
+        // Retire the previous key before the VM lets go of it so it never makes it back to the pool, nor gets picked
+        // as the new key.
+        self.verifier_keys.retire(&key.public)?;
+        let new_public_key = match self.assign_new_key(repo.as_mut(), workload, &key).await {
+            Ok(new_public_key) => new_public_key,
+            Err(e) => {
+                self.verifier_keys.reinstate(&key.public)?;
+                return Err(e);
+            }
+        };
+        // Only persist anything once the VM has the new key so the workload is never left pointing at a key it
+        // doesn't use.
+        repo.commit().await?;
+        self.persist_retirement(&key).await?;
+        Ok(RotatedKey { workload_id, key: new_public_key })
+    }
+
+    async fn retire_key(&self, public_key: &[u8]) -> Result<(), VerifierKeyServiceError> {
+        let _guard = self.lock.lock().await;
+        let key = self.verifier_keys.find(public_key).ok_or(VerifierKeyServiceError::KeyNotFound)?;
+        if key.retired {
+            info!("Verifier key {} is already retired", hex::encode(key.public));
+            return Ok(());
+        }
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workloads = repo.list().await?;
+        if let Some(workload) = Self::assigned_workload(&workloads, &key) {
+            return Err(VerifierKeyServiceError::KeyInUse(workload.id));
+        }
+        // This fails if the key was just handed out to a workload that isn't stored yet.
+        self.verifier_keys.retire_unused(&key.public)?;
+        self.persist_retirement(&key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clients::cvm_agent::MockCvmAgentClient,
+        repositories::{
+            sqlite::MockRepositoryProvider,
+            verifier_keys::{MockVerifierKeyRepository, RetiredVerifierKey},
+            workload::{MockWorkloadRepository, WorkloadHeartbeat, utils::make_workload},
+        },
+        services::vm::{MockVmService, VmNotManaged},
+    };
+    use chrono::Utc;
+    use mockall::predicate::{always, eq};
+
+    struct Builder {
+        provider: MockRepositoryProvider,
+        vm_service: MockVmService,
+        verifier_keys: VerifierKeys,
+    }
+
+    impl Builder {
+        fn new(retired_keys: Vec<Vec<u8>>) -> Self {
+            let mut provider = MockRepositoryProvider::default();
+            provider.expect_verifier_keys().once().return_once(move |_| {
+                let keys = retired_keys
+                    .into_iter()
+                    .map(|public_key| RetiredVerifierKey { public_key, retired_at: Utc::now() })
+                    .collect();
+                let mut repo = MockVerifierKeyRepository::default();
+                repo.expect_list_retired().return_once(move || Ok(keys));
+                Ok(Box::new(repo))
+            });
+            Self { provider, vm_service: Default::default(), verifier_keys: VerifierKeys::dummy() }
+        }
+
+        async fn build(self) -> DefaultVerifierKeyService {
+            let args = VerifierKeyServiceArgs {
+                verifier_keys: self.verifier_keys,
+                repository_provider: Arc::new(self.provider),
+                vm_service: Arc::new(self.vm_service),
+                cvm_agent_client: Arc::new(MockCvmAgentClient::default()),
+            };
+            DefaultVerifierKeyService::new(args).await.expect("failed to build service")
+        }
+    }
+
+    fn make_heartbeat(wallet_public_key: Vec<u8>) -> Option<WorkloadHeartbeat> {
+        Some(WorkloadHeartbeat {
+            wallet_public_key: Some(wallet_public_key),
+            measurement_hash_url: "https://example.com/measurement".into(),
+            heartbeat_interval: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn retired_keys_are_loaded() {
+        let verifier_keys = VerifierKeys::dummy();
+        let public_key = verifier_keys.public_keys()[0].public.to_vec();
+        let builder = Builder { verifier_keys: verifier_keys.clone(), ..Builder::new(vec![public_key.clone()]) };
+        builder.build().await;
+
+        assert!(verifier_keys.find(&public_key).expect("key not found").retired);
+        let next_key = verifier_keys.next_key().expect("no keys available");
+        assert_ne!(next_key.public_key().as_slice(), public_key);
+    }
+
+    #[tokio::test]
+    async fn rotate_key() {
+        let mut builder = Builder::new(Vec::new());
+        let verifier_keys = builder.verifier_keys.clone();
+        let current_key = verifier_keys.next_key().expect("no keys available");
+        let current_public_key = current_key.public_key();
+        let workload = Workload { heartbeat: make_heartbeat(current_public_key.to_vec()), ..make_workload() };
+        let id = workload.id;
+
+        let mut workloads_repo = MockWorkloadRepository::default();
+        workloads_repo.expect_list().return_once(move || Ok(vec![workload]));
+        workloads_repo.expect_set_heartbeat().with(eq(id), always()).once().return_once(|_, _| Ok(()));
+        workloads_repo.expect_commit().once().return_once(|| Ok(()));
+        builder.provider.expect_workloads().return_once(move |_| Ok(Box::new(workloads_repo)));
+        builder.provider.expect_verifier_keys().once().return_once(move |_| {
+            let mut repo = MockVerifierKeyRepository::default();
+            repo.expect_retire().with(eq(current_public_key.to_vec())).once().return_once(|_| Ok(()));
+            Ok(Box::new(repo))
+        });
+        // The worker drops the previous key once it gets the new one.
+        builder.vm_service.expect_rotate_heartbeat_key().with(eq(id), always()).once().return_once(move |_, _| {
+            drop(current_key);
+            Ok(())
+        });
+
+        let service = builder.build().await;
+        let rotated = service.rotate_key(&current_public_key).await.expect("rotation failed");
+        assert_eq!(rotated.workload_id, id);
+        assert_ne!(rotated.key.public, current_public_key);
+        assert!(matches!(verifier_keys.get(&current_public_key), Err(KeyLookupError::Retired)));
+    }
+
+    #[tokio::test]
+    async fn rotate_key_vm_not_managed() {
+        let mut builder = Builder::new(Vec::new());
+        let verifier_keys = builder.verifier_keys.clone();
+        let public_key = verifier_keys.public_keys()[0].public;
+        let workload = Workload { heartbeat: make_heartbeat(public_key.to_vec()), ..make_workload() };
+        let id = workload.id;
+
+        // Nothing is committed nor persisted since the VM never got the new key.
+        let mut workloads_repo = MockWorkloadRepository::default();
+        workloads_repo.expect_list().return_once(move || Ok(vec![workload]));
+        workloads_repo.expect_set_heartbeat().with(eq(id), always()).once().return_once(|_, _| Ok(()));
+        workloads_repo.expect_commit().never();
+        builder.provider.expect_workloads().return_once(move |_| Ok(Box::new(workloads_repo)));
+        builder
+            .vm_service
+            .expect_rotate_heartbeat_key()
+            .with(eq(id), always())
+            .once()
+            .return_once(|_, _| Err(VmNotManaged));
+
+        let service = builder.build().await;
+        let key_count = verifier_keys.public_keys().len();
+        service.rotate_key(&public_key).await.expect_err("rotation succeeded");
+        assert!(!verifier_keys.find(&public_key).expect("key not found").retired);
+        assert_eq!(verifier_keys.public_keys().len(), key_count);
+    }
+
+    #[tokio::test]
+    async fn rotate_unassigned_key() {
+        let mut builder = Builder::new(Vec::new());
+        let public_key = builder.verifier_keys.public_keys()[0].public;
+        let mut workloads_repo = MockWorkloadRepository::default();
+        workloads_repo.expect_list().return_once(move || Ok(Vec::new()));
+        builder.provider.expect_workloads().return_once(move |_| Ok(Box::new(workloads_repo)));
+
+        let service = builder.build().await;
+        let err = service.rotate_key(&public_key).await.expect_err("rotation succeeded");
+        assert!(matches!(err, VerifierKeyServiceError::KeyNotAssigned), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn retire_key_in_use() {
+        let mut builder = Builder::new(Vec::new());
+        let key = builder.verifier_keys.next_key().expect("no keys available");
+        let workload = Workload { heartbeat: make_heartbeat(key.public_key().to_vec()), ..make_workload() };
+        let id = workload.id;
+        let mut workloads_repo = MockWorkloadRepository::default();
+        workloads_repo.expect_list().return_once(move || Ok(vec![workload]));
+        builder.provider.expect_workloads().return_once(move |_| Ok(Box::new(workloads_repo)));
+
+        let service = builder.build().await;
+        let err = service.retire_key(&key.public_key()).await.expect_err("retirement succeeded");
+        assert!(matches!(err, VerifierKeyServiceError::KeyInUse(workload_id) if workload_id == id), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn retire_unused_key() {
+        let mut builder = Builder::new(Vec::new());
+        let verifier_keys = builder.verifier_keys.clone();
+        let public_key = verifier_keys.public_keys()[0].public;
+        let mut workloads_repo = MockWorkloadRepository::default();
+        workloads_repo.expect_list().return_once(move || Ok(Vec::new()));
+        builder.provider.expect_workloads().return_once(move |_| Ok(Box::new(workloads_repo)));
+        builder.provider.expect_verifier_keys().once().return_once(move |_| {
+            let mut repo = MockVerifierKeyRepository::default();
+            repo.expect_retire().with(eq(public_key.to_vec())).once().return_once(|_| Ok(()));
+            Ok(Box::new(repo))
+        });
+
+        let service = builder.build().await;
+        service.retire_key(&public_key).await.expect("retirement failed");
+        assert!(verifier_keys.find(&public_key).expect("key not found").retired);
+    }
+}
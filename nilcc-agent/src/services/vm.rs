@@ -1,36 +1,58 @@
 use crate::{
     clients::{
-        cvm_agent::CvmAgentClient,
-        qemu::{HardDiskSpec, VmClient, VmSpec},
+        cvm_agent::{CvmAgentAuthKey, CvmAgentClient},
+        qemu::{HardDiskSpec, VmClient, VmDisplayMode, VmSpec},
     },
-    config::{DockerConfig, ZeroSslConfig},
+    config::{DockerConfig, SnpConfig, TimeSyncConfig},
     heartbeat_verifier::VerifierKey,
+    platform_claims::PlatformClaimsSigner,
     repositories::{sqlite::RepositoryProvider, workload::Workload},
-    services::disk::{ApplicationMetadata, ContainerMetadata, DiskService, EnvironmentVariable, ExternalFile, IsoSpec},
+    resources::NumaAllocator,
+    services::{
+        bandwidth::{BandwidthLimiter, LimitedVm},
+        disk::{ApplicationMetadata, ContainerMetadata, DiskService, EnvironmentVariable, ExternalFile, IsoSpec},
+        progress::ProvisioningTracker,
+    },
     workers::{
         events::EventSender,
         vm::{VmWorker, VmWorkerArgs, VmWorkerHandle},
     },
+    zerossl::ZeroSslAccounts,
 };
 use anyhow::Context;
 use async_trait::async_trait;
-use cvm_agent_models::bootstrap::{DockerCredentials, HeartbeatConfig};
+use attestation_report::report_data::WorkloadIdentity;
+use cvm_agent_models::bootstrap::{
+    DockerCredentials, HeartbeatConfig, LogRotationConfig, PrivatePki, RoughtimeServer,
+    TimeSyncConfig as BootstrapTimeSyncConfig,
+};
+use nilcc_agent_models::workloads::{
+    create::StateDisk,
+    progress::{ProvisioningStep, WorkloadProgressResponse},
+};
 use nilcc_artifacts::{
     VmType,
-    metadata::{ArtifactsMetadata, DiskFormat, KernelArgs},
+    metadata::{ArtifactsMetadata, CvmCpu, DiskFormat, GuestPolicy, KernelArgs},
 };
 use sha2::{Digest, Sha256};
 use std::{
     collections::HashMap,
+    io,
     path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
 use tokio::{fs, sync::Mutex};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
-const CVM_AGENT_PORT: u16 = 59666;
+/// The port the cvm-agent listens on inside the CVM.
+pub const CVM_AGENT_PORT: u16 = 59666;
+
+/// The kernel parameters added to debug VMs, which send the kernel's output to the serial console.
+///
+/// These change the VM's measurement, so debug VMs can't be attested.
+pub const DEBUG_KERNEL_ARGS: &str = "console=ttyS0 debug_mode=1";
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
@@ -39,6 +61,30 @@ pub trait VmService: Send + Sync {
     async fn create_workload_spec(&self, workload: &Workload) -> Result<VmSpec, StartVmError>;
     async fn delete_vm(&self, id: Uuid);
     async fn restart_vm(&self, id: Uuid) -> Result<(), VmNotManaged>;
+    async fn update_application(&self, workload: &Workload) -> Result<(), StartVmError>;
+    async fn change_domain(&self, workload: &Workload) -> Result<(), StartVmError>;
+    async fn retire_domain(&self, id: Uuid, domain: String);
+    async fn rotate_heartbeat_key(&self, id: Uuid, key: VerifierKey) -> Result<(), VmNotManaged>;
+
+    /// Push a workload's docker credentials to its CVM, which logs in to its registries again.
+    async fn update_docker_credentials(&self, workload: &Workload) -> Result<(), VmNotManaged>;
+    async fn pause_vm(&self, id: Uuid) -> Result<(), VmNotManaged>;
+    async fn resume_vm(&self, id: Uuid) -> Result<(), VmNotManaged>;
+
+    /// The provisioning progress of a VM since it was created.
+    fn provisioning_progress(&self, id: Uuid) -> WorkloadProgressResponse;
+
+    /// Clean up VMs left behind by a previous agent instance.
+    ///
+    /// VMs that belong to the given workloads are left running so they're adopted when they're created rather than
+    /// started again, any other running VM is killed, and sockets for VMs that aren't running are removed.
+    async fn cleanup_orphaned_vms(&self, workload_ids: &[Uuid]);
+
+    /// The path to the unix socket a debug VM's serial console is exposed on.
+    fn console_socket_path(&self, id: Uuid) -> PathBuf;
+
+    /// The content hash of the files in a VM's application ISO, or `None` if it hasn't been created yet.
+    async fn iso_content_hash(&self, id: Uuid) -> Option<[u8; 32]>;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -46,12 +92,14 @@ pub trait VmService: Send + Sync {
 pub struct VmNotManaged;
 
 pub struct VmServiceArgs {
+    pub agent_id: Uuid,
     pub state_path: PathBuf,
     pub vm_client: Arc<dyn VmClient>,
     pub cvm_agent_client: Arc<dyn CvmAgentClient>,
-    pub disk_service: Box<dyn DiskService>,
+    pub cvm_agent_auth_key: CvmAgentAuthKey,
+    pub disk_service: Arc<dyn DiskService>,
     pub cvm_artifacts_path: PathBuf,
-    pub zerossl_config: ZeroSslConfig,
+    pub zerossl_accounts: ZeroSslAccounts,
     pub docker_config: DockerConfig,
     pub event_sender: EventSender,
     pub repository_provider: Arc<dyn RepositoryProvider>,
@@ -59,16 +107,25 @@ pub struct VmServiceArgs {
     pub verifier_heartbeat_interval: Duration,
     pub verifier_contract_address: String,
     pub token_contract_address: String,
+    pub ipv6: bool,
+    pub time_sync: Option<TimeSyncConfig>,
+    pub private_pki: Option<PrivatePki>,
+    pub platform_claims: Option<PlatformClaimsSigner>,
+    pub snp: SnpConfig,
+    pub numa: Option<NumaAllocator>,
+    pub bandwidth_limiter: Arc<dyn BandwidthLimiter>,
 }
 
 pub struct DefaultVmService {
+    agent_id: Uuid,
     vm_client: Arc<dyn VmClient>,
     cvm_agent_client: Arc<dyn CvmAgentClient>,
-    disk_service: Box<dyn DiskService>,
+    cvm_agent_auth_key: CvmAgentAuthKey,
+    disk_service: Arc<dyn DiskService>,
     workers: Mutex<HashMap<Uuid, VmWorkerHandle>>,
     state_path: PathBuf,
     cvm_artifacts_path: PathBuf,
-    zerossl_config: ZeroSslConfig,
+    zerossl_accounts: ZeroSslAccounts,
     docker_config: DockerConfig,
     event_sender: EventSender,
     repository_provider: Arc<dyn RepositoryProvider>,
@@ -76,17 +133,27 @@ pub struct DefaultVmService {
     verifier_heartbeat_rpc: String,
     verifier_contract_address: String,
     token_contract_address: String,
+    ipv6: bool,
+    time_sync: Option<TimeSyncConfig>,
+    private_pki: Option<PrivatePki>,
+    platform_claims: Option<PlatformClaimsSigner>,
+    snp: SnpConfig,
+    numa: Option<NumaAllocator>,
+    bandwidth_limiter: Arc<dyn BandwidthLimiter>,
+    progress: ProvisioningTracker,
 }
 
 impl DefaultVmService {
     pub async fn new(args: VmServiceArgs) -> anyhow::Result<Self> {
         let VmServiceArgs {
+            agent_id,
             state_path,
             vm_client,
             cvm_agent_client,
+            cvm_agent_auth_key,
             disk_service,
             cvm_artifacts_path,
-            zerossl_config,
+            zerossl_accounts,
             docker_config,
             event_sender,
             repository_provider,
@@ -94,16 +161,25 @@ impl DefaultVmService {
             verifier_heartbeat_rpc,
             verifier_contract_address,
             token_contract_address,
+            ipv6,
+            time_sync,
+            private_pki,
+            platform_claims,
+            snp,
+            numa,
+            bandwidth_limiter,
         } = args;
         fs::create_dir_all(&state_path).await.context("Creating state directory")?;
         Ok(Self {
+            agent_id,
             vm_client,
             cvm_agent_client,
+            cvm_agent_auth_key,
             disk_service,
             workers: Default::default(),
             state_path,
             cvm_artifacts_path,
-            zerossl_config,
+            zerossl_accounts,
             docker_config,
             event_sender,
             repository_provider,
@@ -111,6 +187,14 @@ impl DefaultVmService {
             verifier_heartbeat_rpc,
             verifier_contract_address,
             token_contract_address,
+            ipv6,
+            time_sync,
+            private_pki,
+            platform_claims,
+            snp,
+            numa,
+            bandwidth_limiter,
+            progress: Default::default(),
         })
     }
 
@@ -123,6 +207,13 @@ impl DefaultVmService {
         kernel_args: String,
     ) -> VmSpec {
         let CvmFiles { kernel, base_disk, verity_disk, .. } = cvm_config.vm;
+        let numa = self.numa.as_ref().and_then(|numa| {
+            let placement = numa.allocate(workload.id, workload.cpus, workload.memory_mb);
+            if placement.is_none() {
+                warn!("No NUMA node can fit VM {}, not pinning it", workload.id);
+            }
+            placement
+        });
         VmSpec {
             cpu: workload.cpus,
             ram_mib: workload.memory_mb,
@@ -134,7 +225,7 @@ impl DefaultVmService {
                     read_only: matches!(base_disk.format, DiskFormat::Raw),
                 },
                 HardDiskSpec { path: verity_disk.path, format: DiskFormat::Raw, read_only: true },
-                HardDiskSpec { path: state_disk_path, format: DiskFormat::Raw, read_only: false },
+                HardDiskSpec { path: state_disk_path, format: state_disk_format(workload), read_only: false },
             ],
             cdrom_iso_path: Some(iso_path),
             gpus: workload.gpus.clone(),
@@ -143,24 +234,36 @@ impl DefaultVmService {
                 (workload.https_port(), 443),
                 (workload.cvm_agent_port(), CVM_AGENT_PORT),
             ],
+            ipv6_port_forwarding: self.ipv6,
             bios_path: Some(cvm_config.bios),
             initrd_path: Some(cvm_config.initrd),
             kernel_path: Some(kernel.clone()),
             kernel_args: Some(kernel_args),
-            display: Default::default(),
+            display: if workload.debug {
+                VmDisplayMode::SerialSocket(self.console_socket_path(workload.id))
+            } else {
+                VmDisplayMode::None
+            },
             enable_cvm: true,
+            guest_policy: cvm_config.guest_policy,
+            cvm_cpu: cvm_config.cpu,
+            numa,
+            host_data: Some(
+                WorkloadIdentity { workload_id: workload.id.to_string(), agent_id: self.agent_id.to_string() }.hash(),
+            ),
         }
     }
 
     async fn create_state_disk(&self, workload: &Workload) -> Result<PathBuf, StartVmError> {
-        let disk_name = format!("{}.state.raw", workload.id);
+        let format = state_disk_format(workload);
+        let disk_name = format!("{}.state.{format}", workload.id);
         let disk_path = self.state_path.join(disk_name);
         if disk_path.exists() {
             info!("Not creating state disk because it already exists");
             return Ok(disk_path);
         }
         self.disk_service
-            .create_disk(&disk_path, DiskFormat::Raw, workload.disk_space_gb)
+            .create_disk(&disk_path, format, workload.disk_space_gb)
             .await
             .map_err(|e| StartVmError(format!("failed to create state disk: {e}")))?;
         Ok(disk_path)
@@ -186,33 +289,104 @@ impl DefaultVmService {
     }
 
     async fn create_application_iso(&self, workload: &Workload) -> Result<(PathBuf, String), StartVmError> {
-        let iso_name = format!("{}.iso", workload.id);
-        let iso_path = self.state_path.join(iso_name);
+        let iso_path = self.state_path.join(format!("{}.iso", workload.id));
         let docker_compose_hash = hex::encode(Sha256::digest(&workload.docker_compose));
-        if iso_path.exists() {
-            info!("Not creating ISO because it already exists");
-            return Ok((iso_path, docker_compose_hash));
-        }
-        let environment_variables =
-            workload.env_vars.iter().map(|(name, value)| EnvironmentVariable::new(name, value)).collect();
-        let files = workload.files.iter().map(|(name, contents)| ExternalFile::new(name, contents.clone())).collect();
-        let spec = IsoSpec {
-            docker_compose_yaml: workload.docker_compose.clone(),
-            metadata: ApplicationMetadata {
-                hostname: workload.domain.clone(),
-                api: ContainerMetadata {
-                    container: workload.public_container_name.clone(),
-                    port: workload.public_container_port,
-                },
-            },
-            environment_variables,
-            files,
-        };
-        self.disk_service
-            .create_application_iso(&iso_path, spec)
+        // This is always recreated so the ISO carries the cvm-agent token derived from the current auth key.
+        self.replace_application_iso(workload).await?;
+        Ok((iso_path, docker_compose_hash))
+    }
+
+    async fn replace_application_iso(&self, workload: &Workload) -> Result<(), StartVmError> {
+        let iso_path = self.state_path.join(format!("{}.iso", workload.id));
+        let tmp_path = self.state_path.join(format!("{}.iso.tmp", workload.id));
+        let api_token = self.cvm_agent_auth_key.token(workload.cvm_agent_port());
+        let spec = IsoSpec { api_token: Some(api_token), ..application_iso_spec(workload) };
+        let content_hash = self
+            .disk_service
+            .create_application_iso(&tmp_path, spec)
             .await
             .map_err(|e| StartVmError(format!("failed to create ISO: {e}")))?;
-        Ok((iso_path, docker_compose_hash))
+        // A running VM keeps using the ISO it was started with, the new one is used the next time it starts.
+        fs::rename(&tmp_path, &iso_path).await.map_err(|e| StartVmError(format!("failed to replace ISO: {e}")))?;
+        fs::write(self.iso_content_hash_path(workload.id), hex::encode(content_hash))
+            .await
+            .map_err(|e| StartVmError(format!("failed to write ISO content hash: {e}")))?;
+        Ok(())
+    }
+
+    /// The path to the file that holds the content hash of a workload's application ISO.
+    fn iso_content_hash_path(&self, id: Uuid) -> PathBuf {
+        self.state_path.join(format!("{id}.iso.content-hash"))
+    }
+
+    /// The credentials a workload's CVM logs in to docker registries with, including the agent's own ones.
+    fn docker_credentials(&self, workload: &Workload) -> Vec<DockerCredentials> {
+        let mut docker_credentials: Vec<_> = workload
+            .docker_credentials
+            .iter()
+            .map(|c| DockerCredentials {
+                username: c.username.clone(),
+                password: c.password.clone(),
+                server: Some(c.server.clone()),
+            })
+            .collect();
+        docker_credentials.push(DockerCredentials {
+            username: self.docker_config.username.clone(),
+            password: self.docker_config.password.clone(),
+            server: None,
+        });
+        docker_credentials
+    }
+}
+
+/// Find the QMP sockets of the VMs in a state directory, along with the ids of the workloads they belong to.
+async fn find_vm_sockets(state_path: &Path) -> io::Result<Vec<(Uuid, PathBuf)>> {
+    let mut sockets = Vec::new();
+    let mut entries = fs::read_dir(state_path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let Some(id) = name.to_str().and_then(|name| name.strip_suffix(".sock")).and_then(|id| id.parse().ok()) else {
+            continue;
+        };
+        sockets.push((id, entry.path()));
+    }
+    Ok(sockets)
+}
+
+/// Build the spec for the application ISO of a workload.
+fn application_iso_spec(workload: &Workload) -> IsoSpec {
+    let environment_variables =
+        workload.env_vars.iter().map(|(name, value)| EnvironmentVariable::new(name, value)).collect();
+    let files = workload
+        .files
+        .iter()
+        .map(|(name, contents)| ExternalFile::new(name, contents.clone()))
+        .chain(workload.stored_files.iter().map(|(name, file)| ExternalFile::stored(name, file.clone())))
+        .collect();
+    IsoSpec {
+        docker_compose_yaml: workload.docker_compose.clone(),
+        metadata: ApplicationMetadata {
+            hostname: workload.domain.clone(),
+            api: ContainerMetadata {
+                container: workload.public_container_name.clone(),
+                port: workload.public_container_port,
+            },
+            sealed_state_disk: workload.state_disk == StateDisk::Sealed,
+        },
+        environment_variables,
+        files,
+        api_token: None,
+    }
+}
+
+/// The format of a workload's state disk.
+///
+/// Sealed state disks are qcow2 so they only take up the space that's actually been written to, given they're kept
+/// around across restarts.
+fn state_disk_format(workload: &Workload) -> DiskFormat {
+    match workload.state_disk {
+        StateDisk::Ephemeral => DiskFormat::Raw,
+        StateDisk::Sealed => DiskFormat::Qcow2,
     }
 }
 
@@ -229,18 +403,13 @@ impl VmService for DefaultVmService {
             }
             None => {
                 info!("Creating disks for VM {id}");
+                self.progress.start(id);
                 let spec = self.create_workload_spec(&workload).await?;
+                if let Some(vm) = LimitedVm::new(&workload) {
+                    self.bandwidth_limiter.limit_vm(vm).await;
+                }
                 let cvm_agent_port = workload.cvm_agent_port();
-                let mut docker_credentials: Vec<_> = workload
-                    .docker_credentials
-                    .into_iter()
-                    .map(|c| DockerCredentials { username: c.username, password: c.password, server: Some(c.server) })
-                    .collect();
-                docker_credentials.push(DockerCredentials {
-                    username: self.docker_config.username.clone(),
-                    password: self.docker_config.password.clone(),
-                    server: None,
-                });
+                let docker_credentials = self.docker_credentials(&workload);
                 let verifier_heartbeat = match (&heartbeat_key, &workload.heartbeat) {
                     (Some(key), Some(heartbeat)) => Some(HeartbeatConfig {
                         interval: self.verifier_heartbeat_interval,
@@ -252,20 +421,48 @@ impl VmService for DefaultVmService {
                     }),
                     _ => None,
                 };
+                let time_sync = self.time_sync.as_ref().map(|config| BootstrapTimeSyncConfig {
+                    servers: config
+                        .servers
+                        .iter()
+                        .map(|s| RoughtimeServer { address: s.address.clone(), public_key: s.public_key.clone() })
+                        .collect(),
+                    interval: config.interval_seconds,
+                    max_skew: config.max_skew_ms,
+                });
+
+                let zerossl_account = self.zerossl_accounts.resolve(workload.zerossl_account.as_deref()).clone();
+                if workload.zerossl_account.as_deref().is_some_and(|id| id != zerossl_account.eab_key_id()) {
+                    warn!("ZeroSSL account bound to VM {id} is no longer configured, using the primary account");
+                }
 
                 let args = VmWorkerArgs {
                     workload_id: id,
+                    agent_id: self.agent_id,
                     vm_client: self.vm_client.clone(),
                     cvm_agent_client: self.cvm_agent_client.clone(),
                     cvm_agent_port,
                     spec,
                     socket_path,
-                    zerossl_config: self.zerossl_config.clone(),
+                    zerossl_account,
                     docker_credentials,
                     event_sender: self.event_sender.clone(),
                     domain: workload.domain,
                     verifier_heartbeat,
                     verifier_heartbeat_key: heartbeat_key,
+                    log_rotation: workload.log_rotation.map(|rotation| LogRotationConfig {
+                        max_size_mb: rotation.max_size_mb,
+                        max_files: rotation.max_files,
+                    }),
+                    time_sync,
+                    private_pki: self.private_pki.clone(),
+                    platform_claims: self.platform_claims.as_ref().map(|signer| signer.sign(id)),
+                    registry_mirrors: workload
+                        .registry_mirrors
+                        .unwrap_or_else(|| self.docker_config.registry_mirrors.clone()),
+                    paused: workload.paused,
+                    progress: self.progress.clone(),
+                    disk_service: self.disk_service.clone(),
                 };
                 let worker = VmWorker::spawn(args);
                 workers.insert(id, worker);
@@ -288,10 +485,11 @@ impl VmService for DefaultVmService {
             .metadata;
         let vm_type = if workload.gpus.is_empty() { VmType::Cpu } else { VmType::Gpu };
         let config_path = self.cvm_artifacts_path.join(&workload.artifacts_version);
-        let mut cvm_config = CvmConfig::from_metadata(&config_path, &metadata, vm_type);
+        let mut cvm_config = CvmConfig::from_metadata(&config_path, &metadata, vm_type, &self.snp);
         let (iso_path, docker_compose_hash) = self.create_application_iso(workload).await?;
+        self.progress.record(workload.id, ProvisioningStep::IsoBuilt);
         let state_disk = self.create_state_disk(workload).await?;
-        let kernel_args = metadata
+        let mut kernel_args = metadata
             .cvm
             .cmdline
             .render(KernelArgs {
@@ -299,6 +497,9 @@ impl VmService for DefaultVmService {
                 docker_compose_hash: &docker_compose_hash,
             })
             .map_err(|e| StartVmError(e.to_string()))?;
+        if workload.debug {
+            kernel_args = format!("{kernel_args} {DEBUG_KERNEL_ARGS}");
+        }
         match cvm_config.vm.base_disk.format {
             DiskFormat::Qcow2 => {
                 // Create a snapshot for qcow2 disks.
@@ -307,6 +508,7 @@ impl VmService for DefaultVmService {
             }
             DiskFormat::Raw => (),
         };
+        self.progress.record(workload.id, ProvisioningStep::DisksCreated);
         let spec = self.create_vm_spec(workload, iso_path, state_disk, cvm_config, kernel_args);
         Ok(spec)
     }
@@ -316,6 +518,16 @@ impl VmService for DefaultVmService {
         match workers.remove(&id) {
             Some(worker) => {
                 worker.delete_vm().await;
+                if let Some(numa) = &self.numa {
+                    numa.release(id);
+                }
+                self.bandwidth_limiter.unlimit_vm(id).await;
+                self.progress.remove(id);
+                if let Err(e) = fs::remove_file(self.iso_content_hash_path(id)).await
+                    && e.kind() != io::ErrorKind::NotFound
+                {
+                    warn!("Failed to remove ISO content hash for VM {id}: {e}");
+                }
             }
             None => {
                 error!("VM {id} is not being managed by any worker");
@@ -336,11 +548,131 @@ impl VmService for DefaultVmService {
             }
         }
     }
+
+    async fn update_docker_credentials(&self, workload: &Workload) -> Result<(), VmNotManaged> {
+        let workers = self.workers.lock().await;
+        let worker = workers.get(&workload.id).ok_or(VmNotManaged)?;
+        info!("Updating docker credentials for VM {}", workload.id);
+        worker.update_docker_credentials(self.docker_credentials(workload)).await;
+        Ok(())
+    }
+
+    async fn pause_vm(&self, id: Uuid) -> Result<(), VmNotManaged> {
+        let workers = self.workers.lock().await;
+        let worker = workers.get(&id).ok_or(VmNotManaged)?;
+        worker.pause_vm().await;
+        Ok(())
+    }
+
+    async fn resume_vm(&self, id: Uuid) -> Result<(), VmNotManaged> {
+        let workers = self.workers.lock().await;
+        let worker = workers.get(&id).ok_or(VmNotManaged)?;
+        worker.resume_vm().await;
+        Ok(())
+    }
+
+    async fn cleanup_orphaned_vms(&self, workload_ids: &[Uuid]) {
+        let workers = self.workers.lock().await;
+        let socket_paths = match find_vm_sockets(&self.state_path).await {
+            Ok(paths) => paths,
+            Err(e) => {
+                error!("Failed to find VM sockets: {e}");
+                return;
+            }
+        };
+        for (id, socket_path) in socket_paths {
+            if workers.contains_key(&id) {
+                continue;
+            }
+            let running = self.vm_client.is_vm_running(&socket_path).await;
+            match (running, workload_ids.contains(&id)) {
+                (true, true) => {
+                    info!("Found running VM {id}, it will be adopted rather than started again");
+                    continue;
+                }
+                (true, false) => {
+                    warn!("Killing orphaned VM {id} whose workload no longer exists");
+                    if let Err(e) = self.vm_client.stop_vm(&socket_path, true).await {
+                        error!("Failed to kill orphaned VM {id}: {e}");
+                        continue;
+                    }
+                }
+                (false, _) => info!("Removing stale sockets for VM {id}"),
+            }
+            for path in [socket_path, self.console_socket_path(id)] {
+                if let Err(e) = fs::remove_file(&path).await
+                    && e.kind() != io::ErrorKind::NotFound
+                {
+                    warn!("Failed to remove {}: {e}", path.display());
+                }
+            }
+        }
+    }
+
+    fn console_socket_path(&self, id: Uuid) -> PathBuf {
+        self.state_path.join(format!("{id}.console.sock"))
+    }
+
+    async fn iso_content_hash(&self, id: Uuid) -> Option<[u8; 32]> {
+        let contents = fs::read_to_string(self.iso_content_hash_path(id)).await.ok()?;
+        let mut content_hash = [0; 32];
+        hex::decode_to_slice(contents.trim(), &mut content_hash).ok()?;
+        Some(content_hash)
+    }
+
+    fn provisioning_progress(&self, id: Uuid) -> WorkloadProgressResponse {
+        self.progress.get(id)
+    }
+
+    async fn update_application(&self, workload: &Workload) -> Result<(), StartVmError> {
+        info!("Updating application ISO for VM {}", workload.id);
+        self.replace_application_iso(workload).await
+    }
+
+    async fn change_domain(&self, workload: &Workload) -> Result<(), StartVmError> {
+        let id = workload.id;
+        let workers = self.workers.lock().await;
+        let Some(worker) = workers.get(&id) else {
+            // The ISO will be created using the new domain when the VM is started.
+            info!("VM {id} is not running, not updating its domain");
+            return Ok(());
+        };
+        info!("Changing domain for VM {id} to {}", workload.domain);
+        self.replace_application_iso(workload).await?;
+        worker.change_domain(workload.domain.clone()).await;
+        Ok(())
+    }
+
+    async fn retire_domain(&self, id: Uuid, domain: String) {
+        let workers = self.workers.lock().await;
+        if let Some(worker) = workers.get(&id) {
+            worker.retire_domain(domain).await;
+        }
+    }
+
+    async fn rotate_heartbeat_key(&self, id: Uuid, key: VerifierKey) -> Result<(), VmNotManaged> {
+        let workers = self.workers.lock().await;
+        match workers.get(&id) {
+            Some(worker) => {
+                worker.rotate_heartbeat_key(key).await;
+                Ok(())
+            }
+            None => {
+                error!("VM {id} is not being managed by any worker");
+                Err(VmNotManaged)
+            }
+        }
+    }
 }
 
 impl CvmConfig {
-    pub fn from_metadata(base_path: &Path, meta: &ArtifactsMetadata, vm_type: VmType) -> Self {
+    pub fn from_metadata(base_path: &Path, meta: &ArtifactsMetadata, vm_type: VmType, snp: &SnpConfig) -> Self {
         let vm = meta.cvm.images.resolve(vm_type);
+        let cpu = CvmCpu {
+            model: meta.cpu.model,
+            cbitpos: snp.cbitpos.unwrap_or(meta.cpu.cbitpos),
+            reduced_phys_bits: snp.reduced_phys_bits.unwrap_or(meta.cpu.reduced_phys_bits),
+        };
         Self {
             initrd: base_path.join(&meta.initrd.path),
             bios: base_path.join(&meta.ovmf.path),
@@ -349,6 +681,8 @@ impl CvmConfig {
                 base_disk: Disk { path: base_path.join(&vm.disk.artifact.path), format: vm.disk.format },
                 verity_disk: Disk { path: base_path.join(&vm.verity.disk.path), format: vm.disk.format },
             },
+            guest_policy: meta.guest_policy,
+            cpu,
         }
     }
 }
@@ -358,6 +692,8 @@ struct CvmConfig {
     initrd: PathBuf,
     bios: PathBuf,
     vm: CvmFiles,
+    guest_policy: GuestPolicy,
+    cpu: CvmCpu,
 }
 
 #[derive(Clone, Debug)]
@@ -375,21 +711,23 @@ struct Disk {
 
 #[derive(Debug, thiserror::Error)]
 #[error("internal: {0}")]
-pub struct StartVmError(String);
+pub struct StartVmError(pub(crate) String);
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         clients::{cvm_agent::MockCvmAgentClient, qemu::MockVmClient},
+        config::ZeroSslConfig,
         repositories::{
             artifacts::{Artifacts, MockArtifactsRepository, utils::make_artifacts_metadata},
             sqlite::MockRepositoryProvider,
             workload::WorkloadHeartbeat,
         },
-        services::disk::MockDiskService,
+        services::{bandwidth::MockBandwidthLimiter, disk::MockDiskService},
     };
     use mockall::predicate::eq;
+    use rstest::rstest;
     use tempfile::{TempDir, tempdir};
     use tokio::sync::mpsc::channel;
 
@@ -405,7 +743,7 @@ mod tests {
         cvm_agent_client: MockCvmAgentClient,
         disk_service: MockDiskService,
         cvm_artifacts_path: PathBuf,
-        zerossl_config: ZeroSslConfig,
+        zerossl_accounts: ZeroSslAccounts,
         docker_config: DockerConfig,
         repository_provider: MockRepositoryProvider,
     }
@@ -418,17 +756,19 @@ mod tests {
                 cvm_agent_client,
                 disk_service,
                 cvm_artifacts_path,
-                zerossl_config,
+                zerossl_accounts,
                 docker_config,
                 repository_provider,
             } = self;
             let args = VmServiceArgs {
+                agent_id: Uuid::new_v4(),
                 state_path: state_path.path().into(),
                 vm_client: Arc::new(vm_client),
                 cvm_agent_client: Arc::new(cvm_agent_client),
-                disk_service: Box::new(disk_service),
+                cvm_agent_auth_key: CvmAgentAuthKey([0; 32]),
+                disk_service: Arc::new(disk_service),
                 cvm_artifacts_path,
-                zerossl_config,
+                zerossl_accounts,
                 docker_config,
                 event_sender: EventSender(channel(1).0),
                 repository_provider: Arc::new(repository_provider),
@@ -436,6 +776,13 @@ mod tests {
                 verifier_heartbeat_rpc: "".into(),
                 verifier_contract_address: "".into(),
                 token_contract_address: "".into(),
+                ipv6: false,
+                time_sync: None,
+                private_pki: None,
+                platform_claims: None,
+                snp: Default::default(),
+                numa: None,
+                bandwidth_limiter: Arc::new(MockBandwidthLimiter::default()),
             };
             let service = DefaultVmService::new(args).await.expect("failed to build");
             Context { service, state_path }
@@ -459,22 +806,35 @@ mod tests {
                 cvm_agent_client: Default::default(),
                 disk_service: Default::default(),
                 cvm_artifacts_path: base_path.join("artifacts"),
-                zerossl_config: ZeroSslConfig { eab_key_id: "key".into(), eab_mac_key: "mac".into() },
-                docker_config: DockerConfig { username: "user".into(), password: "pass".into() },
+                zerossl_accounts: ZeroSslAccounts::new(ZeroSslConfig {
+                    eab_key_id: "key".into(),
+                    eab_mac_key: "mac".into(),
+                    pool: Vec::new(),
+                }),
+                docker_config: DockerConfig {
+                    username: "user".into(),
+                    password: "pass".into(),
+                    registry_mirrors: Vec::new(),
+                },
                 repository_provider: Default::default(),
             }
         }
     }
 
+    #[rstest]
+    #[case::ephemeral(StateDisk::Ephemeral, DiskFormat::Raw)]
+    #[case::sealed(StateDisk::Sealed, DiskFormat::Qcow2)]
     #[tokio::test]
-    async fn start_vm() {
+    async fn start_vm(#[case] state_disk: StateDisk, #[case] state_disk_format: DiskFormat) {
         let heartbeat_key = VerifierKey::dummy();
         let workload = Workload {
             id: Uuid::new_v4(),
             docker_compose: "compose".into(),
             artifacts_version: "default".into(),
             env_vars: Default::default(),
+            env_groups: Default::default(),
             files: Default::default(),
+            stored_files: Default::default(),
             docker_credentials: Default::default(),
             public_container_name: "api".into(),
             public_container_port: 80,
@@ -491,6 +851,22 @@ mod tests {
                 wallet_public_key: Some(heartbeat_key.public_key().into()),
                 heartbeat_interval: Some(Duration::from_secs(1337)),
             }),
+            priority: Default::default(),
+            preempted: false,
+            log_encryption_key: None,
+            upgrade_channel: Default::default(),
+            log_rotation: None,
+            state_disk,
+            zerossl_account: None,
+            env_vars_restart_pending: false,
+            registry_mirrors: None,
+            labels: Default::default(),
+            paused: false,
+            debug: false,
+            bandwidth_limits: None,
+            proxy_timeouts: None,
+            owner: None,
+            isolated: false,
         };
         let mut builder = Builder::default();
         let base_disk_contents = b"totally a disk";
@@ -501,14 +877,20 @@ mod tests {
 
         let id = workload.id;
         let state_path = builder.state_path.path();
-        let state_disk_path = state_path.join(format!("{id}.state.raw"));
+        let state_disk_path = state_path.join(format!("{id}.state.{state_disk_format}"));
         let base_disk_path = state_path.join(format!("{id}.base.qcow2"));
 
-        builder.disk_service.expect_create_application_iso().return_once(move |_, _| Ok(()));
+        let api_token = CvmAgentAuthKey([0; 32]).token(workload.cvm_agent_port());
+        builder.disk_service.expect_create_application_iso().return_once(move |path, spec| {
+            assert_eq!(spec.api_token, Some(api_token));
+            // The ISO is created in a temporary path and then moved into place.
+            std::fs::write(path, b"").expect("failed to write ISO");
+            Ok([1; 32])
+        });
         builder
             .disk_service
             .expect_create_disk()
-            .with(eq(state_disk_path), eq(DiskFormat::Raw), eq(1))
+            .with(eq(state_disk_path), eq(state_disk_format), eq(1))
             .return_once(move |_, _, _| Ok(()));
         builder
             .disk_service
@@ -527,5 +909,32 @@ mod tests {
 
         let ctx = builder.build().await;
         ctx.service.create_vm(workload, Some(heartbeat_key)).await.expect("failed to start");
+        assert_eq!(ctx.service.iso_content_hash(id).await, Some([1; 32]));
+    }
+
+    #[tokio::test]
+    async fn cleanup_orphaned_vms() {
+        let mut builder = Builder::default();
+        let [adopted, orphaned, stale, managed] = [(); 4].map(|_| Uuid::new_v4());
+        let socket_path = |id: Uuid| builder.state_path.path().join(format!("{id}.sock"));
+        for id in [adopted, orphaned, stale] {
+            fs::write(socket_path(id), b"").await.expect("failed to write socket");
+        }
+        fs::write(builder.state_path.path().join(format!("{orphaned}.console.sock")), b"").await.expect("write failed");
+
+        let (adopted_path, orphaned_path, stale_path) =
+            (socket_path(adopted), socket_path(orphaned), socket_path(stale));
+        for path in [adopted_path.clone(), orphaned_path.clone()] {
+            builder.vm_client.expect_is_vm_running().with(eq(path)).return_once(|_| true);
+        }
+        builder.vm_client.expect_is_vm_running().with(eq(stale_path.clone())).return_once(|_| false);
+        builder.vm_client.expect_stop_vm().with(eq(orphaned_path.clone()), eq(true)).once().return_once(|_, _| Ok(()));
+
+        let ctx = builder.build().await;
+        ctx.service.cleanup_orphaned_vms(&[adopted, stale, managed]).await;
+        assert!(adopted_path.exists());
+        assert!(!orphaned_path.exists());
+        assert!(!ctx.service.console_socket_path(orphaned).exists());
+        assert!(!stale_path.exists());
     }
 }
@@ -1,48 +1,144 @@
 use crate::{
+    clients::nilcc_api::VmEvent,
+    compose::validate_docker_compose,
     heartbeat_verifier::{VerifierKey, VerifierKeys},
     repositories::{
         artifacts::ArtifactsRepositoryError,
         sqlite::{ProviderError, ProviderMode, RepositoryProvider},
-        workload::{Workload, WorkloadHeartbeat, WorkloadRepositoryError},
+        workload::{
+            RetiringDomain, StoredFile, Workload, WorkloadHeartbeat, WorkloadRepository, WorkloadRepositoryError,
+        },
     },
-    resources::{GpuAddress, SystemResources},
+    resources::{GpuAddress, HostReservation, SystemResources},
     services::{
+        dns::WorkloadDnsService,
+        env_groups::{EnvGroupError, EnvGroupService},
         proxy::{ProxiedVm, ProxyService},
         vm::{StartVmError, VmService},
     },
+    workers::{disk_watchdog::DiskSpaceStatus, events::EventSender},
+    zerossl::ZeroSslAccounts,
 };
 use anyhow::Context;
 use async_trait::async_trait;
-use nilcc_agent_models::workloads::create::CreateWorkloadRequest;
+use chrono::Utc;
+use nilcc_agent_models::{
+    system::{FreeResources, SystemCapacityResponse, WorkloadShape},
+    workloads::{
+        create::{
+            CreateWorkloadRequest, DockerCredentials, ProxyTimeouts, StateDisk, WorkloadAdmission, WorkloadPriority,
+        },
+        env_vars::{EnvVarsUpdateMode, UpdateEnvVarsRequest, UpdateEnvVarsResponse},
+        files::{UpdateFilesRequest, UpdateFilesResponse},
+        progress::WorkloadProgressResponse,
+    },
+};
 use std::{
+    cmp::Reverse,
     collections::{BTreeSet, HashMap},
-    io,
+    io, mem,
     ops::Range,
+    path::PathBuf,
     sync::Arc,
     time::Duration,
 };
 use strum::EnumDiscriminants;
 use tokio::sync::Mutex;
-use tracing::info;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 const TOTAL_PORTS: usize = 3;
 
+/// The smallest memory and disk space a workload can request.
+const MIN_WORKLOAD_MEMORY_MB: u32 = 512;
+const MIN_WORKLOAD_DISK_SPACE_GB: u32 = 2;
+
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait WorkloadService: Send + Sync {
     async fn bootstrap(&self) -> anyhow::Result<()>;
-    async fn create_workload(&self, request: CreateWorkloadRequest) -> Result<(), CreateWorkloadError>;
+
+    /// Create a workload, owned by the API token with the given name, if any.
+    /// Create a workload, along with the files that were uploaded for it separately.
+    async fn create_workload(
+        &self,
+        request: CreateWorkloadRequest,
+        stored_files: HashMap<String, StoredFile>,
+        owner: Option<String>,
+    ) -> Result<(), CreateWorkloadError>;
+    async fn preview_workload(&self, request: &CreateWorkloadRequest)
+    -> Result<WorkloadAdmission, CreateWorkloadError>;
     async fn list_workloads(&self) -> Result<Vec<Workload>, WorkloadLookupError>;
+    async fn find_workload(&self, id: Uuid) -> Result<Workload, WorkloadLookupError>;
+
+    /// The content hash of the files in a workload's application ISO, or `None` if it hasn't been created yet.
+    async fn iso_content_hash(&self, id: Uuid) -> Option<[u8; 32]>;
     async fn delete_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError>;
     async fn restart_workload(
         &self,
         id: Uuid,
         env_vars: Option<HashMap<String, String>>,
     ) -> Result<(), WorkloadLookupError>;
+    async fn update_env_vars(
+        &self,
+        id: Uuid,
+        request: UpdateEnvVarsRequest,
+    ) -> Result<UpdateEnvVarsResponse, WorkloadLookupError>;
+    async fn update_files(
+        &self,
+        id: Uuid,
+        request: UpdateFilesRequest,
+    ) -> Result<UpdateFilesResponse, UpdateFilesError>;
+
+    /// Replace the credentials a workload's images are pulled with, pushing them to its CVM if it's running.
+    async fn update_docker_credentials(
+        &self,
+        id: Uuid,
+        credentials: Vec<DockerCredentials>,
+    ) -> Result<(), WorkloadLookupError>;
+
+    /// Change the overrides for the timeouts the SNI proxy uses for a workload's traffic.
+    async fn update_proxy_timeouts(&self, id: Uuid, timeouts: Option<ProxyTimeouts>)
+    -> Result<(), WorkloadLookupError>;
     async fn stop_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError>;
     async fn start_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError>;
+
+    /// Freeze a running workload's VM without releasing any of its resources.
+    async fn pause_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError>;
+
+    /// Resume a paused workload's VM.
+    async fn resume_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError>;
+    async fn workload_paused(&self, workload_id: Uuid) -> Result<bool, WorkloadLookupError>;
+
+    /// The name of the API token that owns a workload, if any.
+    async fn workload_owner(&self, workload_id: Uuid) -> Result<Option<String>, WorkloadLookupError>;
+
+    /// The provisioning steps a workload reached since it was created or last restarted.
+    async fn workload_progress(&self, workload_id: Uuid) -> Result<WorkloadProgressResponse, WorkloadLookupError>;
+
+    /// The path to the unix socket a workload's serial console is exposed on, or `None` if it's not a debug workload.
+    async fn debug_console_path(&self, workload_id: Uuid) -> Result<Option<PathBuf>, WorkloadLookupError>;
     async fn cvm_agent_port(&self, workload_id: Uuid) -> Result<u16, WorkloadLookupError>;
+    async fn change_domain(&self, id: Uuid, domain: String) -> Result<(), ChangeDomainError>;
+    async fn upgrade_artifacts(&self, id: Uuid, version: String) -> Result<(), WorkloadLookupError>;
+
+    /// Change the CPUs and memory reserved for the host, returning the reservation that's now in effect.
+    ///
+    /// A reservation can only grow into resources that aren't used by any workload.
+    async fn set_host_reservation(&self, reservation: HostReservation) -> HostReservation;
+
+    /// The resources available for new workloads and the largest workloads that fit in them.
+    async fn capacity(&self) -> Result<SystemCapacityResponse, WorkloadLookupError>;
+
+    /// Preempt the largest low priority workload to relieve memory or disk pressure on the host.
+    ///
+    /// Returns the workload that was preempted, or `None` if there's no low priority workload to preempt.
+    async fn preempt_for_pressure(&self) -> Result<Option<Uuid>, WorkloadLookupError>;
+
+    /// Stop serving the domains workloads stopped using once their grace period is over.
+    ///
+    /// Returns the domains that were retired.
+    async fn retire_domains(&self) -> Result<Vec<String>, WorkloadLookupError>;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -64,6 +160,30 @@ pub enum CreateWorkloadError {
 
     #[error("not enough verifier keys")]
     NotEnoughKeys,
+
+    #[error("env group '{0}' is not available: {1}")]
+    EnvGroupUnavailable(String, String),
+
+    #[error("GPU model '{0}' is not available")]
+    GpuModelUnavailable(String),
+
+    #[error("GPUs in this agent don't support confidential computing")]
+    NonConfidentialGpus,
+
+    #[error("isolated workloads can only be created in agents that don't have any other workloads")]
+    HostNotEmpty,
+
+    #[error("agent is reserved by isolated workload {0}")]
+    HostIsolated(Uuid),
+}
+
+impl From<EnvGroupError> for CreateWorkloadError {
+    fn from(e: EnvGroupError) -> Self {
+        match e {
+            EnvGroupError::Unavailable(name, reason) => Self::EnvGroupUnavailable(name, reason),
+            EnvGroupError::Internal(e) => Self::Internal(e),
+        }
+    }
 }
 
 impl From<ArtifactsRepositoryError> for CreateWorkloadError {
@@ -106,6 +226,111 @@ pub enum WorkloadLookupError {
 
     #[error("internal: {0}")]
     Internal(String),
+
+    #[error("not enough {0} available")]
+    InsufficientResources(&'static str),
+
+    #[error("env group '{0}' is not available: {1}")]
+    EnvGroupUnavailable(String, String),
+
+    #[error("workload is not running")]
+    WorkloadNotRunning,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChangeDomainError {
+    #[error("workload not found")]
+    WorkloadNotFound,
+
+    #[error("domain is already managed by another workload")]
+    DomainExists,
+
+    #[error("env group '{0}' is not available: {1}")]
+    EnvGroupUnavailable(String, String),
+
+    #[error("internal: {0}")]
+    Internal(String),
+}
+
+impl From<ProviderError> for ChangeDomainError {
+    fn from(e: ProviderError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<StartVmError> for ChangeDomainError {
+    fn from(e: StartVmError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<EnvGroupError> for ChangeDomainError {
+    fn from(e: EnvGroupError) -> Self {
+        match e {
+            EnvGroupError::Unavailable(name, reason) => Self::EnvGroupUnavailable(name, reason),
+            EnvGroupError::Internal(e) => Self::Internal(e),
+        }
+    }
+}
+
+impl From<WorkloadRepositoryError> for ChangeDomainError {
+    fn from(e: WorkloadRepositoryError) -> Self {
+        match e {
+            WorkloadRepositoryError::WorkloadNotFound => Self::WorkloadNotFound,
+            WorkloadRepositoryError::DuplicateDomain => Self::DomainExists,
+            WorkloadRepositoryError::DuplicateWorkload | WorkloadRepositoryError::Database(_) => {
+                Self::Internal(e.to_string())
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UpdateFilesError {
+    #[error("workload not found")]
+    WorkloadNotFound,
+
+    #[error("invalid docker compose: {0}")]
+    DockerCompose(String),
+
+    #[error("file '{0}' was uploaded when the workload was created and can't be changed")]
+    StoredFile(String),
+
+    #[error("env group '{0}' is not available: {1}")]
+    EnvGroupUnavailable(String, String),
+
+    #[error("internal: {0}")]
+    Internal(String),
+}
+
+impl From<ProviderError> for UpdateFilesError {
+    fn from(e: ProviderError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<StartVmError> for UpdateFilesError {
+    fn from(e: StartVmError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<EnvGroupError> for UpdateFilesError {
+    fn from(e: EnvGroupError) -> Self {
+        match e {
+            EnvGroupError::Unavailable(name, reason) => Self::EnvGroupUnavailable(name, reason),
+            EnvGroupError::Internal(e) => Self::Internal(e),
+        }
+    }
+}
+
+impl From<WorkloadRepositoryError> for UpdateFilesError {
+    fn from(e: WorkloadRepositoryError) -> Self {
+        match e {
+            WorkloadRepositoryError::WorkloadNotFound => Self::WorkloadNotFound,
+            e => Self::Internal(e.to_string()),
+        }
+    }
 }
 
 impl From<ProviderError> for WorkloadLookupError {
@@ -114,6 +339,15 @@ impl From<ProviderError> for WorkloadLookupError {
     }
 }
 
+impl From<EnvGroupError> for WorkloadLookupError {
+    fn from(e: EnvGroupError) -> Self {
+        match e {
+            EnvGroupError::Unavailable(name, reason) => Self::EnvGroupUnavailable(name, reason),
+            EnvGroupError::Internal(e) => Self::Internal(e),
+        }
+    }
+}
+
 impl From<WorkloadRepositoryError> for WorkloadLookupError {
     fn from(e: WorkloadRepositoryError) -> Self {
         match e {
@@ -124,21 +358,113 @@ impl From<WorkloadRepositoryError> for WorkloadLookupError {
 }
 
 pub struct WorkloadServiceArgs {
-    pub vm_service: Box<dyn VmService>,
+    pub vm_service: Arc<dyn VmService>,
     pub repository_provider: Arc<dyn RepositoryProvider>,
-    pub proxy_service: Box<dyn ProxyService>,
+    pub proxy_service: Arc<dyn ProxyService>,
+    pub env_group_service: Arc<dyn EnvGroupService>,
     pub resources: SystemResources,
     pub open_ports: Range<u16>,
     pub verifier_keys: VerifierKeys,
     pub verifier_heartbeat_interval: Duration,
+    pub event_sender: EventSender,
+    pub domain_grace_period: Duration,
+    pub default_state_disk: StateDisk,
+    pub zerossl_accounts: ZeroSslAccounts,
+    pub disk_space: DiskSpaceStatus,
+    pub dns_service: Arc<dyn WorkloadDnsService>,
 }
 
+#[derive(Clone)]
 struct AvailableResources {
     cpus: u32,
     gpus: Vec<GpuAddress>,
     memory_mb: u32,
     disk_space_gb: u32,
     ports: Vec<u16>,
+    reserved: HostReservation,
+}
+
+impl AvailableResources {
+    fn ensure_fits(&self, cpus: u32, gpus: usize, memory_mb: u32, disk_space_gb: u32) -> Result<(), &'static str> {
+        if self.cpus < cpus {
+            return Err("CPUs");
+        }
+        if self.gpus.len() < gpus {
+            return Err("GPUs");
+        }
+        if self.memory_mb < memory_mb {
+            return Err("memory");
+        }
+        if self.disk_space_gb < disk_space_gb {
+            return Err("disk space");
+        }
+        Ok(())
+    }
+
+    /// The largest workload that fits, or the resource that's too scarce to fit the smallest one.
+    fn largest_workload(&self) -> Result<WorkloadShape, &'static str> {
+        if self.ports.len() < TOTAL_PORTS {
+            return Err("open ports");
+        }
+        self.ensure_fits(1, 0, MIN_WORKLOAD_MEMORY_MB, MIN_WORKLOAD_DISK_SPACE_GB)?;
+        Ok(WorkloadShape {
+            cpus: self.cpus,
+            memory_mb: self.memory_mb,
+            disk_space_gb: self.disk_space_gb,
+            gpus: self.gpus.len() as u32,
+        })
+    }
+
+    fn free(&self) -> FreeResources {
+        FreeResources {
+            cpus: self.cpus,
+            memory_mb: self.memory_mb,
+            disk_space_gb: self.disk_space_gb,
+            gpus: self.gpus.len() as u32,
+            ports: self.ports.len() as u32,
+        }
+    }
+
+    /// Assigns a new set of GPUs to a preempted workload, making sure it fits.
+    fn assign(&self, workload: &mut Workload) -> Result<(), &'static str> {
+        let gpus = workload.gpus.len();
+        self.ensure_fits(workload.cpus, gpus, workload.memory_mb, workload.disk_space_gb)?;
+        workload.gpus = self.gpus.iter().take(gpus).cloned().collect();
+        Ok(())
+    }
+
+    /// Marks every resource other than ports used by a workload as in use.
+    fn claim(&mut self, workload: &Workload) {
+        self.cpus -= workload.cpus;
+        self.gpus.retain(|gpu| !workload.gpus.contains(gpu));
+        self.memory_mb -= workload.memory_mb;
+        self.disk_space_gb -= workload.disk_space_gb;
+    }
+
+    /// Releases every resource other than ports used by a workload.
+    fn release(&mut self, workload: &Workload) {
+        self.cpus += workload.cpus;
+        self.gpus.extend(workload.gpus.iter().cloned());
+        self.memory_mb += workload.memory_mb;
+        self.disk_space_gb += workload.disk_space_gb;
+    }
+
+    /// Moves resources between the host's reservation and the available ones.
+    fn reserve(&mut self, reservation: HostReservation) -> HostReservation {
+        fn adjust(available: &mut u32, reserved: &mut u32, target: u32) {
+            if target > *reserved {
+                let delta = (target - *reserved).min(*available);
+                *available -= delta;
+                *reserved += delta;
+            } else {
+                *available += *reserved - target;
+                *reserved = target;
+            }
+        }
+        adjust(&mut self.cpus, &mut self.reserved.cpus, reservation.cpus);
+        adjust(&mut self.memory_mb, &mut self.reserved.memory_mb, reservation.memory_mb);
+        self.reserved
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -173,11 +499,20 @@ pub enum CreateServiceError {
 
 pub struct DefaultWorkloadService {
     repository_provider: Arc<dyn RepositoryProvider>,
-    vm_service: Box<dyn VmService>,
-    proxy_service: Box<dyn ProxyService>,
+    vm_service: Arc<dyn VmService>,
+    proxy_service: Arc<dyn ProxyService>,
+    env_group_service: Arc<dyn EnvGroupService>,
     resources: Mutex<AvailableResources>,
+    gpu_model: Option<String>,
+    gpus_confidential: bool,
     verifier_keys: VerifierKeys,
     verifier_heartbeat_interval: Duration,
+    event_sender: EventSender,
+    domain_grace_period: Duration,
+    default_state_disk: StateDisk,
+    zerossl_accounts: ZeroSslAccounts,
+    disk_space: DiskSpaceStatus,
+    dns_service: Arc<dyn WorkloadDnsService>,
 }
 
 impl DefaultWorkloadService {
@@ -186,14 +521,23 @@ impl DefaultWorkloadService {
             vm_service,
             repository_provider,
             proxy_service,
+            env_group_service,
             resources,
             open_ports,
             verifier_keys,
             verifier_heartbeat_interval,
+            event_sender,
+            domain_grace_period,
+            default_state_disk,
+            zerossl_accounts,
+            disk_space,
+            dns_service,
         } = args;
 
         let mut repo = repository_provider.workloads(Default::default()).await?;
         let workloads = repo.list().await?;
+        let gpu_model = resources.gpus.as_ref().map(|g| g.model.clone());
+        let gpus_confidential = resources.gpus.as_ref().is_none_or(|g| g.confidential_computing());
         let mut gpus: BTreeSet<_> = resources.gpus.iter().flat_map(|g| g.addresses.iter().cloned()).collect();
         let mut ports: BTreeSet<_> = open_ports.collect();
         let mut cpus = resources.available_cpus();
@@ -201,16 +545,20 @@ impl DefaultWorkloadService {
         let mut disk_space_gb = resources.available_disk_space_gb();
         for workload in workloads {
             let workload_id = workload.id;
-            for gpu in &workload.gpus {
-                if !gpus.remove(gpu) {
-                    return Err(CreateServiceError::CommittedGpuMissing(workload_id, gpu.clone()));
-                }
-            }
             for port in workload.ports {
                 if !ports.remove(&port) {
                     return Err(CreateServiceError::PortOutOfRange(port));
                 }
             }
+            if workload.preempted {
+                // Preempted workloads only hold on to their ports.
+                continue;
+            }
+            for gpu in &workload.gpus {
+                if !gpus.remove(gpu) {
+                    return Err(CreateServiceError::CommittedGpuMissing(workload_id, gpu.clone()));
+                }
+            }
             cpus = cpus.checked_sub(workload.cpus).ok_or(CreateServiceError::OvercommittedCpus)?;
             memory_mb = memory_mb.checked_sub(workload.memory_mb).ok_or(CreateServiceError::OvercommittedMemory)?;
             disk_space_gb =
@@ -222,29 +570,71 @@ impl DefaultWorkloadService {
         info!(
             "Starting with available cpus = {cpus}, gpus = {gpu_count}, memory = {memory_mb}MB, disk = {disk_space_gb}GB"
         );
-        let resources = AvailableResources { cpus, gpus, ports, memory_mb, disk_space_gb }.into();
+        let reserved = HostReservation { cpus: resources.reserved_cpus, memory_mb: resources.reserved_memory_mb };
+        let resources = AvailableResources { cpus, gpus, ports, memory_mb, disk_space_gb, reserved }.into();
         Ok(Self {
             vm_service,
             repository_provider,
             proxy_service,
+            env_group_service,
             resources,
+            gpu_model,
+            gpus_confidential,
             verifier_keys,
             verifier_heartbeat_interval,
+            event_sender,
+            domain_grace_period,
+            default_state_disk,
+            zerossl_accounts,
+            disk_space,
+            dns_service,
         })
     }
 
+    /// Make sure this agent's GPUs are of the model a workload requires, if it requires one, and that the workload
+    /// opted into using them if they don't support confidential computing.
+    fn ensure_gpu_model(&self, request: &CreateWorkloadRequest) -> Result<(), CreateWorkloadError> {
+        match &request.gpu_model {
+            Some(model) if !self.gpu_model.as_ref().is_some_and(|m| m.eq_ignore_ascii_case(model)) => {
+                return Err(CreateWorkloadError::GpuModelUnavailable(model.clone()));
+            }
+            _ => (),
+        };
+        if request.gpus > 0 && !self.gpus_confidential && !request.allow_non_confidential_gpus {
+            return Err(CreateWorkloadError::NonConfidentialGpus);
+        }
+        Ok(())
+    }
+
+    /// Make sure a workload can share this agent with the existing ones.
+    ///
+    /// Isolated workloads need the agent to themselves, so nothing else can be created while one exists, even if it's
+    /// stopped, since it would share the host once it's started again.
+    fn ensure_isolation(workloads: &[Workload], request: &CreateWorkloadRequest) -> Result<(), CreateWorkloadError> {
+        if let Some(workload) = workloads.iter().find(|w| w.isolated) {
+            return Err(CreateWorkloadError::HostIsolated(workload.id));
+        }
+        if request.isolated && !workloads.is_empty() {
+            return Err(CreateWorkloadError::HostNotEmpty);
+        }
+        Ok(())
+    }
+
     fn build_workload(
         &self,
         request: CreateWorkloadRequest,
         resources: &AvailableResources,
         artifacts_version: String,
         heartbeat: Option<WorkloadHeartbeat>,
+        owner: Option<String>,
     ) -> Workload {
         let CreateWorkloadRequest {
             id,
             docker_compose,
             env_vars,
+            env_groups,
             files,
+            stored_files: Default::default(),
             docker_credentials,
             public_container_name,
             public_container_port,
@@ -253,6 +643,17 @@ impl DefaultWorkloadService {
             gpus,
             disk_space_gb,
             domain,
+            priority,
+            log_encryption_key,
+            upgrade_channel,
+            log_rotation,
+            state_disk,
+            registry_mirrors,
+            labels,
+            debug,
+            bandwidth_limits,
+            proxy_timeouts,
+            isolated,
             ..
         } = request;
 
@@ -264,6 +665,7 @@ impl DefaultWorkloadService {
             docker_compose,
             artifacts_version,
             env_vars,
+            env_groups,
             files,
             docker_credentials,
             public_container_name,
@@ -277,7 +679,37 @@ impl DefaultWorkloadService {
             last_reported_event: None,
             enabled: true,
             heartbeat,
+            priority,
+            preempted: false,
+            log_encryption_key,
+            upgrade_channel,
+            log_rotation,
+            state_disk: state_disk.unwrap_or(self.default_state_disk),
+            zerossl_account: Some(self.zerossl_accounts.assign()),
+            env_vars_restart_pending: false,
+            registry_mirrors,
+            labels,
+            paused: false,
+            debug,
+            bandwidth_limits,
+            proxy_timeouts,
+            owner,
+            isolated,
+        }
+    }
+
+    /// Merges the environment variables in a workload's env groups into its own.
+    ///
+    /// The workload's own environment variables take precedence over the ones defined in its groups.
+    async fn resolve_env_groups(&self, mut workload: Workload) -> Result<Workload, EnvGroupError> {
+        if workload.env_groups.is_empty() {
+            return Ok(workload);
         }
+        info!("Resolving env groups {:?} for workload {}", workload.env_groups, workload.id);
+        let mut env_vars = self.env_group_service.resolve(&workload.env_groups).await?;
+        env_vars.extend(mem::take(&mut workload.env_vars));
+        workload.env_vars = env_vars;
+        Ok(workload)
     }
 
     fn workload_key(&self, workload: &Workload) -> anyhow::Result<Option<VerifierKey>> {
@@ -304,6 +736,158 @@ impl DefaultWorkloadService {
         };
         Ok(key)
     }
+
+    /// Create a workload's VM from scratch.
+    async fn recreate_vm(&self, workload: Workload) -> Result<(), WorkloadLookupError> {
+        let key = self.workload_key(&workload).map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+        let workload = self.resolve_env_groups(workload).await?;
+        self.vm_service.create_vm(workload, key).await.map_err(|e| WorkloadLookupError::Internal(e.to_string()))
+    }
+
+    /// Stops enough low priority workloads to make room for the given request.
+    ///
+    /// Returns `false` without stopping anything if preempting every low priority workload would still not
+    /// free up enough resources.
+    async fn preempt_workloads(
+        &self,
+        resources: &mut AvailableResources,
+        request: &CreateWorkloadRequest,
+    ) -> Result<bool, CreateWorkloadError> {
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let Some(preempted) = Self::preemption_candidates(repo.list().await?, resources, request) else {
+            return Ok(false);
+        };
+        info!("Preempting {} workloads to make room for workload {}", preempted.len(), request.id);
+        self.preempt(repo, resources, preempted).await?;
+        Ok(true)
+    }
+
+    /// Stops the given workloads and flags them so they're restarted once resources are available again.
+    async fn preempt(
+        &self,
+        mut repo: Box<dyn WorkloadRepository>,
+        resources: &mut AvailableResources,
+        preempted: Vec<Workload>,
+    ) -> Result<(), WorkloadRepositoryError> {
+        for workload in &preempted {
+            let id = workload.id;
+            info!("Preempting workload {id}");
+            // The workload key is going back to the pool
+            let mut heartbeat = workload.heartbeat.clone();
+            if let Some(config) = &mut heartbeat {
+                config.wallet_public_key = None;
+            }
+            repo.set_enabled(id, false).await?;
+            repo.set_preempted(id, true).await?;
+            repo.set_heartbeat(id, heartbeat).await?;
+        }
+        repo.commit().await?;
+        for workload in preempted {
+            self.vm_service.delete_vm(workload.id).await;
+            self.event_sender.send_event(workload.id, VmEvent::Preempted, Utc::now()).await;
+            resources.release(&workload);
+        }
+        Ok(())
+    }
+
+    /// Finds the low priority workloads that would need to be preempted to make room for the given request.
+    ///
+    /// Returns `None` if preempting every low priority workload would still not free up enough resources.
+    fn preemption_candidates(
+        workloads: Vec<Workload>,
+        resources: &AvailableResources,
+        request: &CreateWorkloadRequest,
+    ) -> Option<Vec<Workload>> {
+        let candidates = Self::preemptible_workloads(workloads);
+        let fits = |resources: &AvailableResources| {
+            resources.ensure_fits(request.cpus, request.gpus as usize, request.memory_mb, request.disk_space_gb).is_ok()
+        };
+        let mut projected = resources.clone();
+        let mut preempted = Vec::new();
+        for workload in candidates {
+            if fits(&projected) {
+                break;
+            }
+            projected.release(&workload);
+            preempted.push(workload);
+        }
+        fits(&projected).then_some(preempted)
+    }
+
+    /// The workloads that can be preempted, from largest to smallest so we preempt as few workloads as possible.
+    fn preemptible_workloads(workloads: Vec<Workload>) -> Vec<Workload> {
+        let mut candidates: Vec<_> =
+            workloads.into_iter().filter(|w| w.enabled && w.priority == WorkloadPriority::Low).collect();
+        candidates.sort_by_key(|w| Reverse((w.gpus.len(), w.cpus, w.memory_mb, w.disk_space_gb)));
+        candidates
+    }
+
+    /// Enables a workload and starts its VM, re-assigning resources to it if it was preempted.
+    async fn enable_workload(
+        &self,
+        resources: &mut AvailableResources,
+        mut workload: Workload,
+    ) -> Result<(), WorkloadLookupError> {
+        let id = workload.id;
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let preempted = workload.preempted;
+        if preempted {
+            resources.assign(&mut workload).map_err(WorkloadLookupError::InsufficientResources)?;
+            workload.preempted = false;
+            repo.set_gpus(id, &workload.gpus).await?;
+            repo.set_preempted(id, false).await?;
+        }
+        let key = self.verifier_keys.next_key().map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+        if let Some(config) = &mut workload.heartbeat {
+            config.wallet_public_key = Some(key.public_key().to_vec());
+        }
+        info!("Starting workload {id} using wallet key {}", hex::encode(key.public_key()));
+        if workload.env_vars_restart_pending {
+            // The VM is created from scratch so it picks up the latest environment variables.
+            workload.env_vars_restart_pending = false;
+            repo.set_env_vars_restart_pending(id, false).await?;
+        }
+        repo.set_enabled(id, true).await?;
+        repo.set_heartbeat(id, workload.heartbeat.clone()).await?;
+        repo.commit().await?;
+        if preempted {
+            resources.claim(&workload);
+        }
+        let workload = self.resolve_env_groups(workload).await?;
+        self.vm_service
+            .create_vm(workload, Some(key))
+            .await
+            .map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Attempts to start any preempted workloads that fit in the currently available resources.
+    async fn resume_preempted_workloads(&self, resources: &mut AvailableResources) -> Result<(), WorkloadLookupError> {
+        // Workloads may have been preempted because of it so don't start them only to have them preempted again.
+        if self.disk_space.is_low() {
+            info!("Not resuming preempted workloads since disk space is low");
+            return Ok(());
+        }
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workloads = repo.list().await?.into_iter().filter(|w| w.preempted);
+        for workload in workloads {
+            let id = workload.id;
+            match self.enable_workload(resources, workload).await {
+                Ok(()) => info!("Resumed preempted workload {id}"),
+                Err(WorkloadLookupError::InsufficientResources(resource)) => {
+                    info!("Not enough {resource} to resume preempted workload {id}")
+                }
+                Err(e) => warn!("Failed to resume preempted workload {id}: {e}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the sorted names of the environment variables or files that were added, changed, or removed.
+fn changed_entries<T: PartialEq>(current: &HashMap<String, T>, updated: &HashMap<String, T>) -> Vec<String> {
+    let names: BTreeSet<_> = current.keys().chain(updated.keys()).collect();
+    names.into_iter().filter(|name| current.get(*name) != updated.get(*name)).cloned().collect()
 }
 
 #[async_trait]
@@ -311,10 +895,13 @@ impl WorkloadService for DefaultWorkloadService {
     async fn bootstrap(&self) -> anyhow::Result<()> {
         let mut repo = self.repository_provider.workloads(Default::default()).await?;
         let workloads = repo.list().await?;
+        let enabled: Vec<_> = workloads.iter().filter(|w| w.enabled).map(|w| w.id).collect();
+        self.vm_service.cleanup_orphaned_vms(&enabled).await;
         for workload in workloads {
             let id = workload.id;
             if workload.enabled {
                 let key = self.workload_key(&workload)?;
+                let workload = self.resolve_env_groups(workload).await?;
                 info!("Starting existing workload {id}");
                 self.vm_service.create_vm(workload, key).await?;
             } else {
@@ -322,11 +909,19 @@ impl WorkloadService for DefaultWorkloadService {
                 continue;
             }
         }
+        let mut resources = self.resources.lock().await;
+        self.resume_preempted_workloads(&mut resources).await?;
         Ok(())
     }
 
-    async fn create_workload(&self, request: CreateWorkloadRequest) -> Result<(), CreateWorkloadError> {
+    async fn create_workload(
+        &self,
+        request: CreateWorkloadRequest,
+        stored_files: HashMap<String, StoredFile>,
+        owner: Option<String>,
+    ) -> Result<(), CreateWorkloadError> {
         use CreateWorkloadError::*;
+        self.ensure_gpu_model(&request)?;
         let mut artifacts_repo = self.repository_provider.artifacts(Default::default()).await?;
         let artifacts = artifacts_repo.find(&request.artifacts_version).await?.ok_or(ArtifactVersionMissing)?;
         let mut resources = self.resources.lock().await;
@@ -334,21 +929,23 @@ impl WorkloadService for DefaultWorkloadService {
         let gpus = request.gpus as usize;
         let disk_space_gb = request.disk_space_gb;
         let memory_mb = request.memory_mb;
-        if resources.cpus < cpus {
-            return Err(InsufficientResources("CPUs"));
-        }
-        if resources.gpus.len() < gpus {
-            return Err(InsufficientResources("GPUs"));
-        }
-        if resources.memory_mb < memory_mb {
-            return Err(InsufficientResources("memory"));
-        }
-        if resources.disk_space_gb < disk_space_gb {
-            return Err(InsufficientResources("disk space"));
-        }
         if resources.ports.len() < TOTAL_PORTS {
             return Err(InsufficientResources("open ports"));
         }
+        // Refuse upfront rather than failing halfway through creating the workload's disks.
+        if self.disk_space.is_low() {
+            return Err(InsufficientResources("host disk"));
+        }
+        // This happens while holding the resources lock so concurrent requests can't both get past it.
+        let workloads = self.repository_provider.workloads(Default::default()).await?.list().await?;
+        Self::ensure_isolation(&workloads, &request)?;
+        if let Err(resource) = resources.ensure_fits(cpus, gpus, memory_mb, disk_space_gb) {
+            let preempted =
+                request.priority == WorkloadPriority::High && self.preempt_workloads(&mut resources, &request).await?;
+            if !preempted {
+                return Err(InsufficientResources(resource));
+            }
+        }
 
         let (heartbeat, wallet_key) = match &request.heartbeat {
             Some(config) => {
@@ -365,8 +962,11 @@ impl WorkloadService for DefaultWorkloadService {
             }
             None => (None, None),
         };
-        let workload = self.build_workload(request, &resources, artifacts.version.clone(), heartbeat);
+        let workload = self.build_workload(request, &resources, artifacts.version.clone(), heartbeat, owner);
+        let workload = Workload { stored_files, ..workload };
         let id = workload.id;
+        // Resolve env groups before storing anything so we don't create workloads that can't be started.
+        let resolved_workload = self.resolve_env_groups(workload.clone()).await?;
         info!("Storing workload {id} in database");
         let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
         repo.create(&workload).await?;
@@ -377,9 +977,10 @@ impl WorkloadService for DefaultWorkloadService {
             wallet_key.as_ref().map(|w| hex::encode(w.public_key()))
         );
         let proxied_vm = ProxiedVm::from(&workload);
-        self.vm_service.create_vm(workload, wallet_key).await?;
+        self.vm_service.create_vm(resolved_workload, wallet_key).await?;
         self.proxy_service.start_vm_proxy(proxied_vm).await;
         repo.commit().await?;
+        self.dns_service.add_domain(&workload.domain).await;
 
         resources.cpus -= cpus;
         resources.gpus.drain(0..gpus);
@@ -389,11 +990,71 @@ impl WorkloadService for DefaultWorkloadService {
         Ok(())
     }
 
+    async fn preview_workload(
+        &self,
+        request: &CreateWorkloadRequest,
+    ) -> Result<WorkloadAdmission, CreateWorkloadError> {
+        use CreateWorkloadError::*;
+        self.ensure_gpu_model(request)?;
+        let mut artifacts_repo = self.repository_provider.artifacts(Default::default()).await?;
+        let artifacts = artifacts_repo.find(&request.artifacts_version).await?.ok_or(ArtifactVersionMissing)?;
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workloads = repo.list().await?;
+        if workloads.iter().any(|w| w.id == request.id) {
+            return Err(AlreadyExists);
+        }
+        if workloads.iter().any(|w| w.domain == request.domain) {
+            return Err(DomainExists);
+        }
+        Self::ensure_isolation(&workloads, request)?;
+        if !request.env_groups.is_empty() {
+            self.env_group_service.resolve(&request.env_groups).await?;
+        }
+
+        let resources = self.resources.lock().await;
+        if resources.ports.len() < TOTAL_PORTS {
+            return Err(InsufficientResources("open ports"));
+        }
+        let mut projected = resources.clone();
+        let mut preempted_workloads = Vec::new();
+        if let Err(resource) =
+            resources.ensure_fits(request.cpus, request.gpus as usize, request.memory_mb, request.disk_space_gb)
+        {
+            let preempted = match request.priority {
+                WorkloadPriority::High => Self::preemption_candidates(workloads, &resources, request),
+                _ => None,
+            };
+            for workload in preempted.ok_or(InsufficientResources(resource))? {
+                projected.release(&workload);
+                preempted_workloads.push(workload.id);
+            }
+        }
+        Ok(WorkloadAdmission {
+            artifacts_version: artifacts.version,
+            cpus: request.cpus,
+            memory_mb: request.memory_mb,
+            disk_space_gb: request.disk_space_gb,
+            gpus: projected.gpus.iter().take(request.gpus as usize).map(ToString::to_string).collect(),
+            ports: projected.ports.iter().take(TOTAL_PORTS).copied().collect(),
+            state_disk: request.state_disk.unwrap_or(self.default_state_disk),
+            preempted_workloads,
+        })
+    }
+
     async fn list_workloads(&self) -> Result<Vec<Workload>, WorkloadLookupError> {
         let mut repo = self.repository_provider.workloads(Default::default()).await?;
         Ok(repo.list().await?)
     }
 
+    async fn find_workload(&self, id: Uuid) -> Result<Workload, WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        Ok(repo.find(id).await?)
+    }
+
+    async fn iso_content_hash(&self, id: Uuid) -> Option<[u8; 32]> {
+        self.vm_service.iso_content_hash(id).await
+    }
+
     async fn delete_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError> {
         // Make sure it exists first
         let mut repo = self.repository_provider.workloads(Default::default()).await?;
@@ -403,13 +1064,16 @@ impl WorkloadService for DefaultWorkloadService {
         repo.delete(id).await?;
         self.proxy_service.stop_vm_proxy(id).await;
         self.vm_service.delete_vm(id).await;
+        self.dns_service.remove_domain(&workload.domain).await;
 
         let mut resources = self.resources.lock().await;
-        resources.cpus += workload.cpus;
-        resources.gpus.extend(workload.gpus);
-        resources.memory_mb += workload.memory_mb;
-        resources.disk_space_gb += workload.disk_space_gb;
+        if !workload.preempted {
+            resources.release(&workload);
+        }
         resources.ports.extend(workload.ports);
+        if let Err(e) = self.resume_preempted_workloads(&mut resources).await {
+            warn!("Failed to resume preempted workloads: {e}");
+        }
         Ok(())
     }
 
@@ -420,16 +1084,35 @@ impl WorkloadService for DefaultWorkloadService {
     ) -> Result<(), WorkloadLookupError> {
         // Make sure it exists first
         let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
-        let workload = repo.find(id).await?;
+        let mut workload = repo.find(id).await?;
+        let env_vars_changed = env_vars.is_some();
         if let Some(env_vars) = env_vars {
-            repo.set_env_vars(id, env_vars).await.map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+            repo.set_env_vars(id, env_vars.clone()).await.map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+            workload.env_vars = env_vars;
+        }
+        if workload.preempted {
+            // Preempted workloads need their resources re-assigned so go through the regular start path.
+            repo.commit().await.map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+            return self.start_workload(id).await;
+        }
+        if workload.env_vars_restart_pending {
+            repo.set_env_vars_restart_pending(id, false).await?;
         }
         if workload.enabled {
+            if env_vars_changed || workload.env_vars_restart_pending || !workload.env_groups.is_empty() {
+                // Regenerate the application ISO so the VM picks up the latest environment when it boots again.
+                let workload = self.resolve_env_groups(workload).await?;
+                self.vm_service
+                    .update_application(&workload)
+                    .await
+                    .map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+            }
             info!("Restarting workload {id}");
             self.vm_service.restart_vm(id).await.map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
         } else {
             info!("Enabling workload {id}");
             let key = self.workload_key(&workload).map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+            let workload = self.resolve_env_groups(workload).await?;
             self.vm_service.create_vm(workload, key).await.map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
             repo.set_enabled(id, true).await?;
         }
@@ -437,6 +1120,122 @@ impl WorkloadService for DefaultWorkloadService {
         Ok(())
     }
 
+    async fn update_env_vars(
+        &self,
+        id: Uuid,
+        request: UpdateEnvVarsRequest,
+    ) -> Result<UpdateEnvVarsResponse, WorkloadLookupError> {
+        let UpdateEnvVarsRequest { mode, env_vars, remove, restart } = request;
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let workload = repo.find(id).await?;
+        let mut updated_env_vars = match mode {
+            EnvVarsUpdateMode::Replace => env_vars,
+            EnvVarsUpdateMode::Patch => workload.env_vars.clone().into_iter().chain(env_vars).collect(),
+        };
+        for name in &remove {
+            updated_env_vars.remove(name);
+        }
+        let changed = changed_entries(&workload.env_vars, &updated_env_vars);
+        // A workload that isn't running will pick up the changes whenever it's started.
+        let mut restart_pending = workload.env_vars_restart_pending || (workload.enabled && !changed.is_empty());
+        if changed.is_empty() {
+            info!("Environment variables for workload {id} are unchanged");
+            // Restarting needs its own transaction
+            drop(repo);
+        } else {
+            info!("Rotating environment variables {changed:?} for workload {id}");
+            repo.set_env_vars(id, updated_env_vars).await?;
+            repo.set_env_vars_restart_pending(id, restart_pending).await?;
+            repo.commit().await?;
+            self.event_sender.send_event(id, VmEvent::EnvVarsRotated { keys: changed.clone() }, Utc::now()).await;
+        }
+        if restart && restart_pending {
+            self.restart_workload(id, None).await?;
+            restart_pending = false;
+        }
+        Ok(UpdateEnvVarsResponse { changed, restart_pending })
+    }
+
+    async fn update_files(
+        &self,
+        id: Uuid,
+        request: UpdateFilesRequest,
+    ) -> Result<UpdateFilesResponse, UpdateFilesError> {
+        let UpdateFilesRequest { files, remove } = request;
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let mut workload = repo.find(id).await?;
+        if let Some(name) = files.keys().chain(&remove).find(|name| workload.stored_files.contains_key(*name)) {
+            return Err(UpdateFilesError::StoredFile(name.clone()));
+        }
+        let mut updated_files = workload.files.clone();
+        updated_files.extend(files);
+        for name in &remove {
+            updated_files.remove(name);
+        }
+        let changed = changed_entries(&workload.files, &updated_files);
+        if changed.is_empty() {
+            info!("Files for workload {id} are unchanged");
+            return Ok(UpdateFilesResponse { changed, restarted: false });
+        }
+        // Make sure every file the docker compose mounts is still there.
+        let file_names = updated_files.keys().chain(workload.stored_files.keys()).map(String::as_str).collect();
+        validate_docker_compose(&workload.docker_compose, &workload.public_container_name, &file_names)
+            .map_err(|e| UpdateFilesError::DockerCompose(e.to_string()))?;
+
+        info!("Updating files {changed:?} for workload {id}");
+        repo.set_files(id, updated_files.clone()).await?;
+        workload.files = updated_files;
+        // Preempted and stopped workloads will pick up the changes whenever they're started.
+        let restarted = workload.enabled && !workload.preempted;
+        if restarted {
+            if workload.env_vars_restart_pending {
+                // The regenerated ISO contains the latest environment variables as well.
+                repo.set_env_vars_restart_pending(id, false).await?;
+            }
+            let workload = self.resolve_env_groups(workload).await?;
+            self.vm_service.update_application(&workload).await?;
+            info!("Restarting workload {id} to apply file changes");
+            self.vm_service.restart_vm(id).await.map_err(|e| UpdateFilesError::Internal(e.to_string()))?;
+        }
+        repo.commit().await?;
+        Ok(UpdateFilesResponse { changed, restarted })
+    }
+
+    async fn update_docker_credentials(
+        &self,
+        id: Uuid,
+        credentials: Vec<DockerCredentials>,
+    ) -> Result<(), WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let mut workload = repo.find(id).await?;
+        info!("Updating docker credentials for workload {id}");
+        repo.set_docker_credentials(id, &credentials).await?;
+        workload.docker_credentials = credentials;
+        // Preempted and stopped workloads will pick up the changes whenever they're started.
+        if workload.enabled && !workload.preempted {
+            self.vm_service
+                .update_docker_credentials(&workload)
+                .await
+                .map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+        }
+        repo.commit().await?;
+        Ok(())
+    }
+
+    async fn update_proxy_timeouts(
+        &self,
+        id: Uuid,
+        timeouts: Option<ProxyTimeouts>,
+    ) -> Result<(), WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        repo.find(id).await?;
+        info!("Updating proxy timeouts for workload {id} to {timeouts:?}");
+        repo.set_proxy_timeouts(id, timeouts).await?;
+        repo.commit().await?;
+        self.proxy_service.set_vm_timeouts(id, timeouts).await;
+        Ok(())
+    }
+
     async fn stop_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError> {
         let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
         let workload = repo.find(id).await?;
@@ -452,38 +1251,212 @@ impl WorkloadService for DefaultWorkloadService {
         info!("Disabling workload {id}");
         repo.set_enabled(id, false).await?;
         repo.set_heartbeat(id, heartbeats).await?;
+        if workload.paused {
+            // Stopping a workload tears its VM down so there's nothing left paused.
+            repo.set_paused(id, false).await?;
+        }
         repo.commit().await?;
         self.vm_service.delete_vm(id).await;
         Ok(())
     }
 
     async fn start_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError> {
-        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
-        let mut workload = repo.find(id).await?;
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workload = repo.find(id).await?;
         if workload.enabled {
             info!("Workload {id} is already enabled");
             return Ok(());
         }
-        let key = self.verifier_keys.next_key().map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
-        if let Some(config) = &mut workload.heartbeat {
-            config.wallet_public_key = Some(key.public_key().to_vec());
+        let mut resources = self.resources.lock().await;
+        self.enable_workload(&mut resources, workload).await
+    }
+
+    async fn pause_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let workload = repo.find(id).await?;
+        if workload.paused {
+            info!("Workload {id} is already paused");
+            return Ok(());
         }
-        info!("Starting workload {id} using wallet key {}", hex::encode(key.public_key()));
-        repo.set_enabled(id, true).await?;
-        repo.set_heartbeat(id, workload.heartbeat.clone()).await?;
+        if !workload.enabled || workload.preempted {
+            return Err(WorkloadLookupError::WorkloadNotRunning);
+        }
+        info!("Pausing workload {id}");
+        repo.set_paused(id, true).await?;
+        self.vm_service.pause_vm(id).await.map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
         repo.commit().await?;
-        self.vm_service
-            .create_vm(workload, Some(key))
-            .await
-            .map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
         Ok(())
     }
 
+    async fn resume_workload(&self, id: Uuid) -> Result<(), WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let workload = repo.find(id).await?;
+        if !workload.paused {
+            info!("Workload {id} is not paused");
+            return Ok(());
+        }
+        info!("Resuming workload {id}");
+        repo.set_paused(id, false).await?;
+        // Preempted workloads don't have a VM, they'll start unpaused whenever they're resumed.
+        if workload.enabled && !workload.preempted {
+            self.vm_service.resume_vm(id).await.map_err(|e| WorkloadLookupError::Internal(e.to_string()))?;
+        }
+        repo.commit().await?;
+        Ok(())
+    }
+
+    async fn workload_paused(&self, workload_id: Uuid) -> Result<bool, WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workload = repo.find(workload_id).await?;
+        Ok(workload.paused)
+    }
+
+    async fn workload_owner(&self, workload_id: Uuid) -> Result<Option<String>, WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workload = repo.find(workload_id).await?;
+        Ok(workload.owner)
+    }
+
+    async fn workload_progress(&self, workload_id: Uuid) -> Result<WorkloadProgressResponse, WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        // Make sure the workload exists.
+        repo.find(workload_id).await?;
+        Ok(self.vm_service.provisioning_progress(workload_id))
+    }
+
+    async fn debug_console_path(&self, workload_id: Uuid) -> Result<Option<PathBuf>, WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workload = repo.find(workload_id).await?;
+        Ok(workload.debug.then(|| self.vm_service.console_socket_path(workload_id)))
+    }
+
     async fn cvm_agent_port(&self, workload_id: Uuid) -> Result<u16, WorkloadLookupError> {
         let mut repo = self.repository_provider.workloads(Default::default()).await?;
         let workload = repo.find(workload_id).await?;
         Ok(workload.cvm_agent_port())
     }
+
+    async fn change_domain(&self, id: Uuid, domain: String) -> Result<(), ChangeDomainError> {
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let workload = repo.find(id).await?;
+        if workload.domain == domain {
+            info!("Workload {id} is already using domain {domain}");
+            return Ok(());
+        }
+        repo.set_domain(id, &domain).await?;
+        let previous_domain = workload.domain;
+        // Keep serving the previous domain for a while so clients can move over to the new one while the CVM gets a
+        // certificate for it.
+        let retire_at = Utc::now() + self.domain_grace_period;
+        let retiring = RetiringDomain { domain: previous_domain.clone(), workload_id: id, retire_at };
+        repo.add_retiring_domain(&retiring).await?;
+        let workload = self.resolve_env_groups(Workload { domain: domain.clone(), ..workload }).await?;
+        self.vm_service.change_domain(&workload).await?;
+        repo.commit().await?;
+
+        info!("Changed domain for workload {id} from {previous_domain} to {domain}");
+        self.dns_service.add_domain(&domain).await;
+        self.proxy_service.change_vm_domain(id, domain).await;
+        Ok(())
+    }
+
+    async fn upgrade_artifacts(&self, id: Uuid, version: String) -> Result<(), WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let mut workload = repo.find(id).await?;
+        if workload.artifacts_version == version {
+            info!("Workload {id} is already using artifacts version {version}");
+            return Ok(());
+        }
+        info!("Upgrading workload {id} from artifacts version {} to {version}", workload.artifacts_version);
+        repo.set_artifacts_version(id, &version).await?;
+        let previous_workload = workload.clone();
+        if workload.enabled && workload.env_vars_restart_pending {
+            workload.env_vars_restart_pending = false;
+            repo.set_env_vars_restart_pending(id, false).await?;
+        }
+        workload.artifacts_version = version.clone();
+        if workload.enabled {
+            // The VM's disks are derived from the artifacts it was created with so it needs to be recreated. The
+            // upgrade is only committed once that works, otherwise the VM is recreated using the previous artifacts.
+            self.vm_service.delete_vm(id).await;
+            if let Err(e) = self.recreate_vm(workload).await {
+                let previous_version = &previous_workload.artifacts_version;
+                warn!("Failed to upgrade workload {id}, going back to artifacts version {previous_version}: {e}");
+                self.vm_service.delete_vm(id).await;
+                if let Err(e) = self.recreate_vm(previous_workload).await {
+                    error!("Failed to recreate workload {id} using artifacts version {previous_version}: {e}");
+                }
+                return Err(e);
+            }
+        }
+        repo.commit().await?;
+        self.event_sender.send_event(id, VmEvent::ArtifactsUpgraded { version }, Utc::now()).await;
+        Ok(())
+    }
+
+    async fn set_host_reservation(&self, reservation: HostReservation) -> HostReservation {
+        self.resources.lock().await.reserve(reservation)
+    }
+
+    async fn capacity(&self) -> Result<SystemCapacityResponse, WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let workloads = repo.list().await?;
+        let resources = self.resources.lock().await;
+        // Preempted workloads keep their ports so only the other resources are freed up.
+        let mut preemptible = resources.clone();
+        for workload in workloads.iter().filter(|w| w.enabled && w.priority == WorkloadPriority::Low) {
+            preemptible.release(workload);
+        }
+        let disk_space_low = self.disk_space.is_low();
+        let isolated = workloads.iter().any(|w| w.isolated);
+        let largest_workload = |resources: &AvailableResources| {
+            if isolated {
+                Err("isolation")
+            } else if disk_space_low {
+                Err("host disk")
+            } else {
+                resources.largest_workload()
+            }
+        };
+        let largest = largest_workload(&resources);
+        Ok(SystemCapacityResponse {
+            free: resources.free(),
+            largest_preempting_workload: largest_workload(&preemptible).ok(),
+            workload_slots: (resources.ports.len() / TOTAL_PORTS) as u32,
+            exhausted_resource: largest.as_ref().err().map(ToString::to_string),
+            largest_workload: largest.ok(),
+        })
+    }
+
+    async fn preempt_for_pressure(&self) -> Result<Option<Uuid>, WorkloadLookupError> {
+        let mut resources = self.resources.lock().await;
+        let mut repo = self.repository_provider.workloads(ProviderMode::Transactional).await?;
+        let Some(workload) = Self::preemptible_workloads(repo.list().await?).into_iter().next() else {
+            return Ok(None);
+        };
+        let id = workload.id;
+        self.preempt(repo, &mut resources, vec![workload]).await?;
+        Ok(Some(id))
+    }
+
+    async fn retire_domains(&self) -> Result<Vec<String>, WorkloadLookupError> {
+        let mut repo = self.repository_provider.workloads(Default::default()).await?;
+        let now = Utc::now();
+        let mut retired = Vec::new();
+        for RetiringDomain { domain, workload_id: id, retire_at } in repo.list_retiring_domains().await? {
+            if retire_at > now {
+                break;
+            }
+            info!("Retiring domain {domain} for workload {id}");
+            self.proxy_service.retire_vm_domain(id, domain.clone()).await;
+            self.dns_service.remove_domain(&domain).await;
+            self.vm_service.retire_domain(id, domain.clone()).await;
+            // This is only forgotten once it's retired so it's retired again if the agent restarts in between.
+            repo.remove_retiring_domain(&domain).await?;
+            retired.push(domain);
+        }
+        Ok(retired)
+    }
 }
 
 #[cfg(test)]
@@ -492,13 +1465,17 @@ mod tests {
 
     use super::*;
     use crate::{
+        accelerators::AcceleratorVendor,
+        config::ZeroSslConfig,
         repositories::{
             artifacts::{Artifacts, MockArtifactsRepository, utils::make_artifacts_metadata},
             sqlite::MockRepositoryProvider,
-            workload::MockWorkloadRepository,
+            workload::{MockWorkloadRepository, utils::make_workload},
         },
         resources::Gpus,
         services::{
+            dns::MockWorkloadDnsService,
+            env_groups::MockEnvGroupService,
             proxy::{MockProxyService, ProxiedVm},
             vm::MockVmService,
         },
@@ -506,16 +1483,22 @@ mod tests {
     use mockall::predicate::{always, eq};
     use nilcc_agent_models::workloads::create::CreateWorkloadHeartbeat;
     use rstest::rstest;
+    use tokio::sync::mpsc::channel;
     use uuid::Uuid;
 
     struct Builder {
         vm_service: MockVmService,
         workloads_repository: MockWorkloadRepository,
+        listed_workloads: Option<Vec<Workload>>,
+        preemption_repository: Option<MockWorkloadRepository>,
         artifacts_repository: MockArtifactsRepository,
         proxy_service: MockProxyService,
+        env_group_service: MockEnvGroupService,
         resources: SystemResources,
         open_ports: Range<u16>,
         existing_workloads: Vec<Workload>,
+        disk_space: DiskSpaceStatus,
+        dns_service: MockWorkloadDnsService,
     }
 
     impl Builder {
@@ -531,11 +1514,16 @@ mod tests {
             let Self {
                 vm_service,
                 workloads_repository,
+                listed_workloads,
+                preemption_repository,
                 artifacts_repository,
                 proxy_service,
+                env_group_service,
                 resources,
                 open_ports,
                 existing_workloads,
+                disk_space,
+                dns_service,
             } = self;
 
             let mut provider = MockRepositoryProvider::default();
@@ -544,17 +1532,39 @@ mod tests {
                 repo.expect_list().return_once(move || Ok(existing_workloads));
                 Ok(Box::new(repo))
             });
+            // The workloads listed when checking whether a new one is isolated from the existing ones.
+            if let Some(workloads) = listed_workloads {
+                provider.expect_workloads().once().return_once(|_| {
+                    let mut repo = MockWorkloadRepository::default();
+                    repo.expect_list().return_once(move || Ok(workloads));
+                    Ok(Box::new(repo))
+                });
+            }
+            if let Some(repo) = preemption_repository {
+                provider.expect_workloads().once().return_once(move |_| Ok(Box::new(repo)));
+            }
             provider.expect_workloads().return_once(move |_| Ok(Box::new(workloads_repository)));
             provider.expect_artifacts().return_once(move |_| Ok(Box::new(artifacts_repository)));
 
             let args = WorkloadServiceArgs {
-                vm_service: Box::new(vm_service),
+                vm_service: Arc::new(vm_service),
                 repository_provider: Arc::new(provider),
-                proxy_service: Box::new(proxy_service),
+                proxy_service: Arc::new(proxy_service),
+                env_group_service: Arc::new(env_group_service),
                 resources,
                 open_ports,
                 verifier_keys: VerifierKeys::dummy(),
                 verifier_heartbeat_interval: Duration::from_secs(42),
+                event_sender: EventSender(channel(1).0),
+                domain_grace_period: Duration::from_secs(3600),
+                default_state_disk: StateDisk::Ephemeral,
+                zerossl_accounts: ZeroSslAccounts::new(ZeroSslConfig {
+                    eab_key_id: "key".into(),
+                    eab_mac_key: "mac".into(),
+                    pool: Vec::new(),
+                }),
+                disk_space,
+                dns_service: Arc::new(dns_service),
             };
             DefaultWorkloadService::new(args).await
         }
@@ -565,8 +1575,11 @@ mod tests {
             Self {
                 vm_service: Default::default(),
                 workloads_repository: Default::default(),
+                listed_workloads: Default::default(),
+                preemption_repository: Default::default(),
                 artifacts_repository: Default::default(),
                 proxy_service: Default::default(),
+                env_group_service: Default::default(),
                 resources: SystemResources {
                     hostname: "foo".into(),
                     memory_mb: 65536,
@@ -576,35 +1589,16 @@ mod tests {
                     cpus: 8,
                     reserved_cpus: 2,
                     gpus: None,
+                    numa: None,
                 },
                 open_ports: 100..200,
                 existing_workloads: Default::default(),
+                disk_space: Default::default(),
+                dns_service: Default::default(),
             }
         }
     }
 
-    fn make_workload() -> Workload {
-        Workload {
-            id: Uuid::new_v4(),
-            docker_compose: Default::default(),
-            artifacts_version: "default".into(),
-            env_vars: Default::default(),
-            files: Default::default(),
-            docker_credentials: Default::default(),
-            public_container_name: Default::default(),
-            public_container_port: Default::default(),
-            memory_mb: Default::default(),
-            cpus: 1.try_into().unwrap(),
-            disk_space_gb: 1.try_into().unwrap(),
-            gpus: Default::default(),
-            ports: [150, 151, 152],
-            domain: "example.com".into(),
-            last_reported_event: None,
-            enabled: true,
-            heartbeat: None,
-        }
-    }
-
     #[rstest]
     #[case::cpu(
         Workload { cpus: 2.try_into().unwrap(), ..make_workload() },
@@ -643,7 +1637,7 @@ mod tests {
         builder.resources.reserved_memory_mb = 1;
         builder.resources.disk_space_gb = 2;
         builder.resources.reserved_disk_space_gb = 1;
-        builder.resources.gpus = Some(Gpus::new("H100", &["addr1".into()]));
+        builder.resources.gpus = Some(Gpus::new(AcceleratorVendor::Nvidia, "H100", &["addr1".into()]));
         builder.open_ports = 100..200;
         builder.existing_workloads = vec![workload];
         assert_eq!(builder.build_invalid().await.to_string(), error.to_string());
@@ -658,7 +1652,7 @@ mod tests {
         builder.resources.reserved_memory_mb = 2048;
         builder.resources.disk_space_gb = 100;
         builder.resources.reserved_disk_space_gb = 20;
-        builder.resources.gpus = Some(Gpus::new("H100", &["addr1".into(), "addr2".into()]));
+        builder.resources.gpus = Some(Gpus::new(AcceleratorVendor::Nvidia, "H100", &["addr1".into(), "addr2".into()]));
         builder.open_ports = 1000..2000;
 
         let workload = Workload {
@@ -686,6 +1680,28 @@ mod tests {
         assert_eq!(resources.gpus, vec!["addr2".into()]);
     }
 
+    #[tokio::test]
+    async fn host_reservation() {
+        let service = Builder::default().build().await;
+        let reservation = service.set_host_reservation(HostReservation { cpus: 4, memory_mb: 4096 }).await;
+        assert_eq!(reservation, HostReservation { cpus: 4, memory_mb: 4096 });
+        {
+            let resources = service.resources.lock().await;
+            assert_eq!(resources.cpus, 4);
+            assert_eq!(resources.memory_mb, 61440);
+        }
+
+        // The reservation can't take more than what's available.
+        let reservation = service.set_host_reservation(HostReservation { cpus: 100, memory_mb: 1_000_000 }).await;
+        assert_eq!(reservation, HostReservation { cpus: 8, memory_mb: 65536 });
+
+        let reservation = service.set_host_reservation(HostReservation { cpus: 1, memory_mb: 1024 }).await;
+        assert_eq!(reservation, HostReservation { cpus: 1, memory_mb: 1024 });
+        let resources = service.resources.lock().await;
+        assert_eq!(resources.cpus, 7);
+        assert_eq!(resources.memory_mb, 64512);
+    }
+
     #[tokio::test]
     async fn create_success() {
         let request = CreateWorkloadRequest {
@@ -693,16 +1709,32 @@ mod tests {
             artifacts_version: "default".into(),
             docker_compose: "compose".into(),
             env_vars: Default::default(),
+            env_groups: Default::default(),
             files: Default::default(),
+            uploaded_files: Default::default(),
             docker_credentials: Default::default(),
             public_container_name: "api".into(),
             public_container_port: 80,
             memory_mb: 1024,
             cpus: 1.try_into().unwrap(),
             gpus: 1,
+            gpu_model: Some("H100".into()),
+            allow_non_confidential_gpus: false,
             disk_space_gb: 1.try_into().unwrap(),
             domain: "example.com".into(),
             heartbeat: Some(CreateWorkloadHeartbeat { measurement_hash_url: "url".into() }),
+            priority: Default::default(),
+            log_encryption_key: None,
+            upgrade_channel: Default::default(),
+            log_rotation: None,
+            image_policy: None,
+            state_disk: None,
+            registry_mirrors: None,
+            labels: Default::default(),
+            debug: false,
+            bandwidth_limits: None,
+            proxy_timeouts: None,
+            isolated: false,
         };
         let expected_key = VerifierKeys::dummy().next_key().unwrap().public_key().to_vec();
         let workload = Workload {
@@ -710,7 +1742,9 @@ mod tests {
             docker_compose: request.docker_compose.clone(),
             artifacts_version: "default".into(),
             env_vars: request.env_vars.clone(),
+            env_groups: request.env_groups.clone(),
             files: request.files.clone(),
+            stored_files: Default::default(),
             docker_credentials: request.docker_credentials.clone(),
             public_container_name: request.public_container_name.clone(),
             public_container_port: request.public_container_port,
@@ -727,8 +1761,25 @@ mod tests {
                 measurement_hash_url: "url".into(),
                 heartbeat_interval: Some(Duration::from_secs(42)),
             }),
+            priority: Default::default(),
+            preempted: false,
+            log_encryption_key: None,
+            upgrade_channel: Default::default(),
+            log_rotation: None,
+            state_disk: Default::default(),
+            zerossl_account: Some("key".into()),
+            env_vars_restart_pending: false,
+            registry_mirrors: None,
+            labels: Default::default(),
+            paused: false,
+            debug: false,
+            bandwidth_limits: None,
+            proxy_timeouts: None,
+            owner: Some("team-a".into()),
+            isolated: false,
         };
         let mut builder = Builder::default();
+        builder.listed_workloads = Some(Vec::new());
         let id = workload.id;
 
         let expected_cpus = builder.resources.available_cpus() - request.cpus as u32;
@@ -737,7 +1788,7 @@ mod tests {
         let metadata = make_artifacts_metadata();
 
         builder.open_ports = 100..200;
-        builder.resources.gpus = Some(Gpus::new("H100", ["addr1".into()]));
+        builder.resources.gpus = Some(Gpus::new(AcceleratorVendor::Nvidia, "H100", ["addr1".into()]));
         builder
             .artifacts_repository
             .expect_find()
@@ -749,11 +1800,18 @@ mod tests {
         builder
             .proxy_service
             .expect_start_vm_proxy()
-            .with(eq(ProxiedVm { id, domain: "example.com".into(), http_port: 100, https_port: 101 }))
+            .with(eq(ProxiedVm {
+                id,
+                domains: vec!["example.com".into()],
+                http_port: 100,
+                https_port: 101,
+                timeouts: None,
+            }))
             .return_once(move |_| ());
+        builder.dns_service.expect_add_domain().with(eq("example.com")).once().return_once(|_| ());
 
         let service = builder.build().await;
-        service.create_workload(request).await.expect("failed to create");
+        service.create_workload(request, Default::default(), Some("team-a".into())).await.expect("failed to create");
 
         // Make sure the allocated resources are successfully tracked.
         let resources = service.resources.lock().await;
@@ -762,4 +1820,781 @@ mod tests {
         assert_eq!(resources.disk_space_gb, expected_disk_space);
         assert_eq!(resources.gpus, vec![]);
     }
+
+    fn make_request(cpus: u32, priority: WorkloadPriority) -> CreateWorkloadRequest {
+        CreateWorkloadRequest {
+            id: Uuid::new_v4(),
+            artifacts_version: "default".into(),
+            docker_compose: "compose".into(),
+            env_vars: Default::default(),
+            env_groups: Default::default(),
+            files: Default::default(),
+            uploaded_files: Default::default(),
+            docker_credentials: Default::default(),
+            public_container_name: "api".into(),
+            public_container_port: 80,
+            memory_mb: 1024,
+            cpus,
+            gpus: 0,
+            gpu_model: None,
+            allow_non_confidential_gpus: false,
+            disk_space_gb: 1,
+            domain: "example.com".into(),
+            heartbeat: None,
+            priority,
+            log_encryption_key: None,
+            upgrade_channel: Default::default(),
+            log_rotation: None,
+            image_policy: None,
+            state_disk: None,
+            registry_mirrors: None,
+            labels: Default::default(),
+            debug: false,
+            bandwidth_limits: None,
+            proxy_timeouts: None,
+            owner: None,
+            isolated: false,
+        }
+    }
+
+    #[rstest]
+    #[case::low(WorkloadPriority::Low)]
+    #[case::normal(WorkloadPriority::Normal)]
+    #[tokio::test]
+    async fn create_without_preemption(#[case] priority: WorkloadPriority) {
+        let mut builder = Builder::default();
+        let existing = Workload { cpus: 4, priority: WorkloadPriority::Low, ..make_workload() };
+        builder.existing_workloads = vec![existing.clone()];
+        builder.listed_workloads = Some(vec![existing]);
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+
+        let service = builder.build().await;
+        let err = service
+            .create_workload(make_request(4, priority), Default::default(), None)
+            .await
+            .expect_err("creation succeeded");
+        assert!(matches!(err, CreateWorkloadError::InsufficientResources("CPUs")), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn capacity() {
+        let mut builder = Builder::default();
+        let high = Workload { cpus: 1, priority: WorkloadPriority::High, ports: [160, 161, 162], ..make_workload() };
+        let low = Workload { cpus: 4, memory_mb: 8192, priority: WorkloadPriority::Low, ..make_workload() };
+        builder.existing_workloads = vec![high.clone(), low.clone()];
+        builder.workloads_repository.expect_list().return_once(move || Ok(vec![high, low]));
+
+        let service = builder.build().await;
+        let capacity = service.capacity().await.expect("failed to get capacity");
+        assert_eq!(capacity.free, FreeResources { cpus: 1, memory_mb: 55296, disk_space_gb: 96, gpus: 0, ports: 94 });
+        assert_eq!(
+            capacity.largest_workload,
+            Some(WorkloadShape { cpus: 1, memory_mb: 55296, disk_space_gb: 96, gpus: 0 })
+        );
+        assert_eq!(
+            capacity.largest_preempting_workload,
+            Some(WorkloadShape { cpus: 5, memory_mb: 63488, disk_space_gb: 97, gpus: 0 })
+        );
+        assert_eq!(capacity.workload_slots, 31);
+        assert_eq!(capacity.exhausted_resource, None);
+    }
+
+    #[tokio::test]
+    async fn capacity_exhausted() {
+        let mut builder = Builder::default();
+        let low = Workload { cpus: 6, priority: WorkloadPriority::Low, ..make_workload() };
+        builder.existing_workloads = vec![low.clone()];
+        builder.workloads_repository.expect_list().return_once(move || Ok(vec![low]));
+
+        let service = builder.build().await;
+        let capacity = service.capacity().await.expect("failed to get capacity");
+        assert_eq!(capacity.largest_workload, None);
+        assert_eq!(capacity.exhausted_resource.as_deref(), Some("CPUs"));
+        assert_eq!(capacity.largest_preempting_workload.map(|shape| shape.cpus), Some(6));
+    }
+
+    #[tokio::test]
+    async fn create_with_low_host_disk() {
+        let mut builder = Builder::default();
+        builder.disk_space.set_low(true);
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+
+        let service = builder.build().await;
+        let request = make_request(1, Default::default());
+        let err = service.create_workload(request, Default::default(), None).await.expect_err("creation succeeded");
+        assert!(matches!(err, CreateWorkloadError::InsufficientResources("host disk")), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn create_preempts_low_priority() {
+        let mut builder = Builder::default();
+        let high = Workload { cpus: 1, priority: WorkloadPriority::High, ports: [160, 161, 162], ..make_workload() };
+        let low = Workload { cpus: 4, priority: WorkloadPriority::Low, ..make_workload() };
+        let low_id = low.id;
+        builder.existing_workloads = vec![high.clone(), low.clone()];
+        builder.listed_workloads = Some(vec![high.clone(), low.clone()]);
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+
+        let mut repo = MockWorkloadRepository::default();
+        repo.expect_list().return_once(move || Ok(vec![high, low]));
+        repo.expect_set_enabled().with(eq(low_id), eq(false)).once().return_once(|_, _| Ok(()));
+        repo.expect_set_preempted().with(eq(low_id), eq(true)).once().return_once(|_, _| Ok(()));
+        repo.expect_set_heartbeat().with(eq(low_id), eq(None)).once().return_once(|_, _| Ok(()));
+        repo.expect_commit().once().return_once(|| Ok(()));
+        builder.preemption_repository = Some(repo);
+        builder.vm_service.expect_delete_vm().with(eq(low_id)).once().return_once(|_| ());
+
+        builder.workloads_repository.expect_create().once().return_once(|_| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder.vm_service.expect_create_vm().once().return_once(|_, _| Ok(()));
+        builder.proxy_service.expect_start_vm_proxy().return_once(|_| ());
+        builder.dns_service.expect_add_domain().return_once(|_| ());
+
+        let service = builder.build().await;
+        service
+            .create_workload(make_request(4, WorkloadPriority::High), Default::default(), None)
+            .await
+            .expect("failed to create");
+
+        // 8 total, 2 reserved, 1 used by the high priority workload, 4 used by the new one
+        let resources = service.resources.lock().await;
+        assert_eq!(resources.cpus, 1);
+    }
+
+    #[tokio::test]
+    async fn preempt_for_pressure() {
+        let mut builder = Builder::default();
+        let normal = Workload { cpus: 2, ports: [160, 161, 162], ..make_workload() };
+        let small = Workload { priority: WorkloadPriority::Low, ports: [170, 171, 172], ..make_workload() };
+        let large = Workload { cpus: 2, priority: WorkloadPriority::Low, ..make_workload() };
+        let large_id = large.id;
+        builder.existing_workloads = vec![normal.clone(), small.clone(), large.clone()];
+        let repo = &mut builder.workloads_repository;
+        repo.expect_list().return_once(move || Ok(vec![normal, small, large]));
+        repo.expect_set_enabled().with(eq(large_id), eq(false)).once().return_once(|_, _| Ok(()));
+        repo.expect_set_preempted().with(eq(large_id), eq(true)).once().return_once(|_, _| Ok(()));
+        repo.expect_set_heartbeat().with(eq(large_id), eq(None)).once().return_once(|_, _| Ok(()));
+        repo.expect_commit().once().return_once(|| Ok(()));
+        builder.vm_service.expect_delete_vm().with(eq(large_id)).once().return_once(|_| ());
+
+        let service = builder.build().await;
+        let preempted = service.preempt_for_pressure().await.expect("failed to preempt");
+        assert_eq!(preempted, Some(large_id));
+
+        // 8 total, 2 reserved, 3 used by the remaining workloads
+        let resources = service.resources.lock().await;
+        assert_eq!(resources.cpus, 3);
+    }
+
+    #[tokio::test]
+    async fn preempt_for_pressure_without_low_priority() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        builder.existing_workloads = vec![workload.clone()];
+        builder.workloads_repository.expect_list().return_once(move || Ok(vec![workload]));
+
+        let service = builder.build().await;
+        let preempted = service.preempt_for_pressure().await.expect("failed to preempt");
+        assert_eq!(preempted, None);
+    }
+
+    #[tokio::test]
+    async fn preview_preempts_low_priority() {
+        let mut builder = Builder::default();
+        let high = Workload {
+            cpus: 1,
+            priority: WorkloadPriority::High,
+            ports: [160, 161, 162],
+            domain: "high.com".into(),
+            ..make_workload()
+        };
+        let low = Workload { cpus: 4, priority: WorkloadPriority::Low, domain: "low.com".into(), ..make_workload() };
+        let low_id = low.id;
+        builder.existing_workloads = vec![high.clone(), low.clone()];
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+        builder.workloads_repository.expect_list().return_once(move || Ok(vec![high, low]));
+
+        let service = builder.build().await;
+        let request = make_request(4, WorkloadPriority::High);
+        let admission = service.preview_workload(&request).await.expect("preview failed");
+        let expected = WorkloadAdmission {
+            artifacts_version: "default".into(),
+            cpus: 4,
+            memory_mb: 1024,
+            disk_space_gb: 1,
+            gpus: vec![],
+            ports: vec![100, 101, 102],
+            state_disk: StateDisk::Ephemeral,
+            preempted_workloads: vec![low_id],
+        };
+        assert_eq!(admission, expected);
+
+        // Nothing was actually claimed.
+        let resources = service.resources.lock().await;
+        assert_eq!(resources.cpus, 1);
+        assert_eq!(resources.ports.len(), 94);
+    }
+
+    #[tokio::test]
+    async fn preview_domain_exists() {
+        let mut builder = Builder::default();
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+        builder.workloads_repository.expect_list().return_once(|| Ok(vec![make_workload()]));
+
+        let service = builder.build().await;
+        let err = service.preview_workload(&make_request(1, Default::default())).await.expect_err("preview succeeded");
+        assert!(matches!(err, CreateWorkloadError::DomainExists), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn create_isolated_in_shared_host() {
+        let mut builder = Builder::default();
+        let existing = make_workload();
+        builder.existing_workloads = vec![existing.clone()];
+        builder.listed_workloads = Some(vec![existing]);
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+        let request = CreateWorkloadRequest { isolated: true, ..make_request(1, Default::default()) };
+
+        let service = builder.build().await;
+        let err = service.create_workload(request, Default::default(), None).await.expect_err("creation succeeded");
+        assert!(matches!(err, CreateWorkloadError::HostNotEmpty), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn create_in_isolated_host() {
+        let mut builder = Builder::default();
+        // Stopped isolated workloads still keep the host to themselves.
+        let isolated = Workload { isolated: true, enabled: false, ..make_workload() };
+        let isolated_id = isolated.id;
+        builder.existing_workloads = vec![isolated.clone()];
+        builder.listed_workloads = Some(vec![isolated.clone()]);
+        builder.workloads_repository.expect_list().return_once(move || Ok(vec![isolated]));
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+
+        let service = builder.build().await;
+        let err = service
+            .create_workload(make_request(1, Default::default()), Default::default(), None)
+            .await
+            .expect_err("created");
+        assert!(matches!(err, CreateWorkloadError::HostIsolated(id) if id == isolated_id), "{err:?}");
+        let capacity = service.capacity().await.expect("failed to get capacity");
+        assert_eq!(capacity.largest_workload, None);
+        assert_eq!(capacity.largest_preempting_workload, None);
+        assert_eq!(capacity.exhausted_resource.as_deref(), Some("isolation"));
+    }
+
+    #[rstest]
+    #[case::other_model(Some(Gpus::new(AcceleratorVendor::Nvidia, "H100", ["addr1".into()])))]
+    #[case::no_gpus(None)]
+    #[tokio::test]
+    async fn create_gpu_model_unavailable(#[case] gpus: Option<Gpus>) {
+        let mut builder = Builder::default();
+        builder.resources.gpus = gpus;
+        let request =
+            CreateWorkloadRequest { gpus: 1, gpu_model: Some("A100".into()), ..make_request(1, Default::default()) };
+
+        let service = builder.build().await;
+        let err = service.preview_workload(&request).await.expect_err("preview succeeded");
+        assert!(matches!(err, CreateWorkloadError::GpuModelUnavailable(_)), "{err:?}");
+        let err = service.create_workload(request, Default::default(), None).await.expect_err("create succeeded");
+        assert!(matches!(err, CreateWorkloadError::GpuModelUnavailable(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn create_non_confidential_gpus() {
+        let mut builder = Builder::default();
+        builder.resources.gpus = Some(Gpus::new(AcceleratorVendor::Amd, "MI300X", ["addr1".into()]));
+        let request = CreateWorkloadRequest { gpus: 1, ..make_request(1, Default::default()) };
+
+        let service = builder.build().await;
+        let err = service.preview_workload(&request).await.expect_err("preview succeeded");
+        assert!(matches!(err, CreateWorkloadError::NonConfidentialGpus), "{err:?}");
+
+        let request = CreateWorkloadRequest { allow_non_confidential_gpus: true, ..request };
+        service.ensure_gpu_model(&request).expect("opted in workload rejected");
+    }
+
+    #[tokio::test]
+    async fn create_with_env_groups() {
+        let mut builder = Builder::default();
+        let request = CreateWorkloadRequest {
+            env_vars: HashMap::from([("FOO".into(), "workload".into())]),
+            env_groups: vec!["shared".into()],
+            ..make_request(1, WorkloadPriority::Normal)
+        };
+        builder.listed_workloads = Some(Vec::new());
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+        builder
+            .env_group_service
+            .expect_resolve()
+            .with(eq(vec!["shared".to_string()]))
+            .once()
+            .return_once(|_| Ok(HashMap::from([("FOO".into(), "group".into()), ("BAR".into(), "group".into())])));
+        // Only the workload's own environment variables are stored.
+        builder
+            .workloads_repository
+            .expect_create()
+            .withf(|workload| workload.env_vars == HashMap::from([("FOO".into(), "workload".into())]))
+            .once()
+            .return_once(|_| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        let expected_env_vars = HashMap::from([("FOO".into(), "workload".into()), ("BAR".into(), "group".into())]);
+        builder
+            .vm_service
+            .expect_create_vm()
+            .withf(move |workload, _| workload.env_vars == expected_env_vars)
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder.proxy_service.expect_start_vm_proxy().return_once(|_| ());
+        builder.dns_service.expect_add_domain().return_once(|_| ());
+
+        let service = builder.build().await;
+        service.create_workload(request, Default::default(), None).await.expect("failed to create");
+    }
+
+    #[tokio::test]
+    async fn create_with_unavailable_env_group() {
+        let mut builder = Builder::default();
+        let request =
+            CreateWorkloadRequest { env_groups: vec!["shared".into()], ..make_request(1, Default::default()) };
+        builder.listed_workloads = Some(Vec::new());
+        builder
+            .artifacts_repository
+            .expect_find()
+            .return_once(|_| Ok(Some(Artifacts { metadata: make_artifacts_metadata(), version: "default".into() })));
+        builder
+            .env_group_service
+            .expect_resolve()
+            .return_once(|_| Err(EnvGroupError::Unavailable("shared".into(), "not found".into())));
+
+        let service = builder.build().await;
+        let err = service.create_workload(request, Default::default(), None).await.expect_err("creation succeeded");
+        assert!(matches!(err, CreateWorkloadError::EnvGroupUnavailable(..)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn restart_with_env_groups() {
+        let mut builder = Builder::default();
+        let workload = Workload { env_groups: vec!["shared".into()], ..make_workload() };
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder
+            .env_group_service
+            .expect_resolve()
+            .once()
+            .return_once(|_| Ok(HashMap::from([("FOO".into(), "rotated".into())])));
+        builder
+            .vm_service
+            .expect_update_application()
+            .withf(|workload| workload.env_vars == HashMap::from([("FOO".into(), "rotated".into())]))
+            .once()
+            .return_once(|_| Ok(()));
+        builder.vm_service.expect_restart_vm().with(eq(id)).once().return_once(|_| Ok(()));
+
+        let service = builder.build().await;
+        service.restart_workload(id, None).await.expect("failed to restart");
+    }
+
+    #[tokio::test]
+    async fn update_env_vars_patch() {
+        let mut builder = Builder::default();
+        let env_vars = HashMap::from([("FOO".into(), "value".into()), ("BAR".into(), "value".into())]);
+        let workload = Workload { env_vars, ..make_workload() };
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder
+            .workloads_repository
+            .expect_set_env_vars()
+            .with(eq(id), eq(HashMap::from([("FOO".into(), "rotated".into()), ("NEW".into(), "value".into())])))
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder
+            .workloads_repository
+            .expect_set_env_vars_restart_pending()
+            .with(eq(id), eq(true))
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder.vm_service.expect_restart_vm().never();
+
+        let service = builder.build().await;
+        let request = UpdateEnvVarsRequest {
+            mode: EnvVarsUpdateMode::Patch,
+            env_vars: HashMap::from([("FOO".into(), "rotated".into()), ("NEW".into(), "value".into())]),
+            remove: vec!["BAR".into()],
+            restart: false,
+        };
+        let response = service.update_env_vars(id, request).await.expect("failed to update");
+        assert_eq!(response.changed, &["BAR", "FOO", "NEW"]);
+        assert!(response.restart_pending);
+    }
+
+    #[tokio::test]
+    async fn update_env_vars_unchanged() {
+        let mut builder = Builder::default();
+        let env_vars = HashMap::from([("FOO".into(), "value".into())]);
+        let workload = Workload { env_vars: env_vars.clone(), ..make_workload() };
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder.workloads_repository.expect_set_env_vars().never();
+        builder.vm_service.expect_restart_vm().never();
+
+        let service = builder.build().await;
+        let request =
+            UpdateEnvVarsRequest { mode: EnvVarsUpdateMode::Replace, env_vars, remove: vec![], restart: true };
+        let response = service.update_env_vars(id, request).await.expect("failed to update");
+        assert!(response.changed.is_empty());
+        assert!(!response.restart_pending);
+    }
+
+    const FILES_DOCKER_COMPOSE: &str = r#"
+services:
+  api:
+    image: caddy:2
+    volumes:
+      - $FILES/config.yaml:/etc/config.yaml
+"#;
+
+    #[tokio::test]
+    async fn update_files() {
+        let mut builder = Builder::default();
+        let workload = Workload {
+            docker_compose: FILES_DOCKER_COMPOSE.into(),
+            public_container_name: "api".into(),
+            files: HashMap::from([("config.yaml".into(), b"old".to_vec()), ("extra".into(), vec![1])]),
+            ..make_workload()
+        };
+        let id = workload.id;
+        let expected_files = HashMap::from([("config.yaml".into(), b"new".to_vec())]);
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder
+            .workloads_repository
+            .expect_set_files()
+            .with(eq(id), eq(expected_files.clone()))
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder
+            .vm_service
+            .expect_update_application()
+            .withf(move |workload| workload.files == expected_files)
+            .once()
+            .return_once(|_| Ok(()));
+        builder.vm_service.expect_restart_vm().with(eq(id)).once().return_once(|_| Ok(()));
+
+        let service = builder.build().await;
+        let request = UpdateFilesRequest {
+            files: HashMap::from([("config.yaml".into(), b"new".to_vec())]),
+            remove: vec!["extra".into()],
+        };
+        let response = service.update_files(id, request).await.expect("failed to update");
+        assert_eq!(response.changed, &["config.yaml", "extra"]);
+        assert!(response.restarted);
+    }
+
+    #[tokio::test]
+    async fn update_docker_credentials() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        let id = workload.id;
+        let credentials = vec![DockerCredentials {
+            server: "registry.example.com".into(),
+            username: "user".into(),
+            password: "rotated".into(),
+        }];
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        let expected_credentials = credentials.clone();
+        builder
+            .workloads_repository
+            .expect_set_docker_credentials()
+            .withf(move |workload_id, credentials| workload_id == &id && credentials == expected_credentials)
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        let expected_credentials = credentials.clone();
+        builder
+            .vm_service
+            .expect_update_docker_credentials()
+            .withf(move |workload| workload.docker_credentials == expected_credentials)
+            .once()
+            .return_once(|_| Ok(()));
+        builder.vm_service.expect_restart_vm().never();
+
+        let service = builder.build().await;
+        service.update_docker_credentials(id, credentials).await.expect("failed to update");
+    }
+
+    #[tokio::test]
+    async fn update_proxy_timeouts() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        let id = workload.id;
+        let timeouts = ProxyTimeouts { connect_ms: None, client_ms: Some(3_600_000), server_ms: Some(3_600_000) };
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder
+            .workloads_repository
+            .expect_set_proxy_timeouts()
+            .with(eq(id), eq(Some(timeouts)))
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder.proxy_service.expect_set_vm_timeouts().with(eq(id), eq(Some(timeouts))).once().return_once(|_, _| ());
+        builder.vm_service.expect_restart_vm().never();
+
+        let service = builder.build().await;
+        service.update_proxy_timeouts(id, Some(timeouts)).await.expect("failed to update");
+    }
+
+    #[tokio::test]
+    async fn update_files_missing_mount() {
+        let mut builder = Builder::default();
+        let workload = Workload {
+            docker_compose: FILES_DOCKER_COMPOSE.into(),
+            public_container_name: "api".into(),
+            files: HashMap::from([("config.yaml".into(), b"old".to_vec()), ("extra".into(), vec![1])]),
+            ..make_workload()
+        };
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder.workloads_repository.expect_set_files().never();
+        builder.vm_service.expect_restart_vm().never();
+
+        let service = builder.build().await;
+        let request = UpdateFilesRequest { files: Default::default(), remove: vec!["config.yaml".into()] };
+        let err = service.update_files(id, request).await.expect_err("update succeeded");
+        assert!(matches!(err, UpdateFilesError::DockerCompose(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn change_domain() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        let id = workload.id;
+        let expected_workload = Workload { domain: "new.example.com".into(), ..workload.clone() };
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder
+            .workloads_repository
+            .expect_set_domain()
+            .with(eq(id), eq("new.example.com"))
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder
+            .workloads_repository
+            .expect_add_retiring_domain()
+            .withf(move |retiring| retiring.domain == "example.com" && retiring.workload_id == id)
+            .once()
+            .return_once(|_| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder.vm_service.expect_change_domain().with(eq(expected_workload)).once().return_once(|_| Ok(()));
+        builder
+            .proxy_service
+            .expect_change_vm_domain()
+            .with(eq(id), eq("new.example.com".to_string()))
+            .once()
+            .return_once(|_, _| ());
+        builder.dns_service.expect_add_domain().with(eq("new.example.com")).once().return_once(|_| ());
+
+        let service = builder.build().await;
+        service.change_domain(id, "new.example.com".into()).await.expect("failed to change domain");
+    }
+
+    #[tokio::test]
+    async fn change_domain_duplicate() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder
+            .workloads_repository
+            .expect_set_domain()
+            .return_once(|_, _| Err(WorkloadRepositoryError::DuplicateDomain));
+
+        let service = builder.build().await;
+        let err = service.change_domain(id, "new.example.com".into()).await.expect_err("change succeeded");
+        assert!(matches!(err, ChangeDomainError::DomainExists), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn retire_domains() {
+        let mut builder = Builder::default();
+        let id = Uuid::new_v4();
+        let make_retiring =
+            |domain: &str, retire_at| RetiringDomain { domain: domain.into(), workload_id: id, retire_at };
+        let domains = vec![
+            make_retiring("old.example.com", Utc::now() - Duration::from_secs(1)),
+            make_retiring("newer.example.com", Utc::now() + Duration::from_secs(3600)),
+        ];
+        builder.workloads_repository.expect_list_retiring_domains().once().return_once(move || Ok(domains));
+        builder
+            .workloads_repository
+            .expect_remove_retiring_domain()
+            .with(eq("old.example.com"))
+            .once()
+            .return_once(|_| Ok(()));
+        builder
+            .proxy_service
+            .expect_retire_vm_domain()
+            .with(eq(id), eq("old.example.com".to_string()))
+            .once()
+            .return_once(|_, _| ());
+        builder.dns_service.expect_remove_domain().with(eq("old.example.com")).once().return_once(|_| ());
+        builder
+            .vm_service
+            .expect_retire_domain()
+            .with(eq(id), eq("old.example.com".to_string()))
+            .once()
+            .return_once(|_, _| ());
+
+        let service = builder.build().await;
+        let retired = service.retire_domains().await.expect("failed to retire domains");
+        assert_eq!(retired, &["old.example.com"]);
+    }
+
+    #[tokio::test]
+    async fn upgrade_artifacts() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        let id = workload.id;
+        let expected_workload = Workload { artifacts_version: "0.3.0".into(), ..workload.clone() };
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder
+            .workloads_repository
+            .expect_set_artifacts_version()
+            .with(eq(id), eq("0.3.0"))
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder.vm_service.expect_delete_vm().with(eq(id)).once().return_once(|_| ());
+        builder.vm_service.expect_create_vm().with(eq(expected_workload), always()).once().return_once(|_, _| Ok(()));
+
+        let service = builder.build().await;
+        service.upgrade_artifacts(id, "0.3.0".into()).await.expect("failed to upgrade");
+    }
+
+    #[tokio::test]
+    async fn upgrade_artifacts_failure() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        let id = workload.id;
+        let upgraded_workload = Workload { artifacts_version: "0.3.0".into(), ..workload.clone() };
+        let previous_workload = workload.clone();
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder.workloads_repository.expect_set_artifacts_version().once().return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().never();
+        builder.vm_service.expect_delete_vm().with(eq(id)).times(2).returning(|_| ());
+        builder
+            .vm_service
+            .expect_create_vm()
+            .with(eq(upgraded_workload), always())
+            .once()
+            .return_once(|_, _| Err(StartVmError("boom".into())));
+        builder.vm_service.expect_create_vm().with(eq(previous_workload), always()).once().return_once(|_, _| Ok(()));
+
+        let service = builder.build().await;
+        service.upgrade_artifacts(id, "0.3.0".into()).await.expect_err("upgrade succeeded");
+    }
+
+    #[tokio::test]
+    async fn upgrade_stopped_workload_artifacts() {
+        let mut builder = Builder::default();
+        let workload = Workload { enabled: false, ..make_workload() };
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder.workloads_repository.expect_set_artifacts_version().once().return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder.vm_service.expect_delete_vm().never();
+        builder.vm_service.expect_create_vm().never();
+
+        let service = builder.build().await;
+        service.upgrade_artifacts(id, "0.3.0".into()).await.expect("failed to upgrade");
+    }
+
+    #[tokio::test]
+    async fn pause_workload() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder.workloads_repository.expect_set_paused().with(eq(id), eq(true)).once().return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder.vm_service.expect_pause_vm().with(eq(id)).once().return_once(|_| Ok(()));
+
+        let service = builder.build().await;
+        service.pause_workload(id).await.expect("failed to pause");
+    }
+
+    #[tokio::test]
+    async fn pause_stopped_workload() {
+        let mut builder = Builder::default();
+        let workload = Workload { enabled: false, ..make_workload() };
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder.workloads_repository.expect_set_paused().never();
+        builder.vm_service.expect_pause_vm().never();
+
+        let service = builder.build().await;
+        let err = service.pause_workload(id).await.expect_err("pause succeeded");
+        assert!(matches!(err, WorkloadLookupError::WorkloadNotRunning), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn resume_workload() {
+        let mut builder = Builder::default();
+        let workload = Workload { paused: true, ..make_workload() };
+        let id = workload.id;
+        builder.workloads_repository.expect_find().with(eq(id)).return_once(move |_| Ok(workload));
+        builder.workloads_repository.expect_set_paused().with(eq(id), eq(false)).once().return_once(|_, _| Ok(()));
+        builder.workloads_repository.expect_commit().once().return_once(|| Ok(()));
+        builder.vm_service.expect_resume_vm().with(eq(id)).once().return_once(|_| Ok(()));
+
+        let service = builder.build().await;
+        service.resume_workload(id).await.expect("failed to resume");
+    }
+
+    #[tokio::test]
+    async fn debug_console_path() {
+        let mut builder = Builder::default();
+        let debug = Workload { debug: true, ..make_workload() };
+        let regular = make_workload();
+        let (debug_id, regular_id) = (debug.id, regular.id);
+        builder.workloads_repository.expect_find().with(eq(debug_id)).return_once(move |_| Ok(debug));
+        builder.workloads_repository.expect_find().with(eq(regular_id)).return_once(move |_| Ok(regular));
+        builder
+            .vm_service
+            .expect_console_socket_path()
+            .with(eq(debug_id))
+            .once()
+            .return_once(|_| PathBuf::from("/tmp/console.sock"));
+
+        let service = builder.build().await;
+        let path = service.debug_console_path(debug_id).await.expect("failed to get path");
+        assert_eq!(path, Some(PathBuf::from("/tmp/console.sock")));
+        let path = service.debug_console_path(regular_id).await.expect("failed to get path");
+        assert_eq!(path, None);
+    }
 }
@@ -1,4 +1,5 @@
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
+use nilcc_agent_models::system::BuildInfo;
 use std::sync::LazyLock;
 
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -6,11 +7,7 @@ const BUILD_GIT_COMMIT_HASH: &str = env!("BUILD_GIT_COMMIT_HASH");
 const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
 
 static AGENT_VERSION: LazyLock<String> = LazyLock::new(|| {
-    let timestamp_secs = BUILD_TIMESTAMP.parse::<i64>().expect("BUILD_TIMESTAMP must be a Unix timestamp.");
-    let build_datetime_str = DateTime::from_timestamp(timestamp_secs, 0)
-        .expect("BUILD_TIMESTAMP must correspond to a valid UTC DateTime.")
-        .format("%Y%m%dT%H%M%SZ")
-        .to_string();
+    let build_datetime_str = build_datetime().format("%Y%m%dT%H%M%SZ").to_string();
 
     format!("{PKG_VERSION}+git.{BUILD_GIT_COMMIT_HASH}.build.{build_datetime_str}")
 });
@@ -20,3 +17,18 @@ static AGENT_VERSION: LazyLock<String> = LazyLock::new(|| {
 pub fn agent_version() -> &'static str {
     &AGENT_VERSION
 }
+
+/// Returns the details of how the agent binary was built.
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: agent_version().into(),
+        package_version: PKG_VERSION.into(),
+        git_commit: BUILD_GIT_COMMIT_HASH.into(),
+        built_at: build_datetime(),
+    }
+}
+
+fn build_datetime() -> DateTime<Utc> {
+    let timestamp_secs = BUILD_TIMESTAMP.parse::<i64>().expect("BUILD_TIMESTAMP must be a Unix timestamp.");
+    DateTime::from_timestamp(timestamp_secs, 0).expect("BUILD_TIMESTAMP must correspond to a valid UTC DateTime.")
+}
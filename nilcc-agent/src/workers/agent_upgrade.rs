@@ -0,0 +1,83 @@
+use crate::services::agent_backup::{AgentBackup, UpgradeRecord};
+use std::{sync::Arc, time::Duration};
+use tokio::{select, sync::Notify, time::sleep};
+use tracing::{error, info};
+
+pub struct AgentUpgradeWatchdogArgs {
+    pub backup: AgentBackup,
+    pub record: UpgradeRecord,
+    pub heartbeat_sent: Arc<Notify>,
+    pub confirmation_timeout: Duration,
+}
+
+/// Confirms a freshly upgraded agent once it sends its first heartbeat, and rolls it back if it doesn't in time.
+pub struct AgentUpgradeWatchdog {
+    backup: AgentBackup,
+    record: UpgradeRecord,
+    heartbeat_sent: Arc<Notify>,
+    confirmation_timeout: Duration,
+}
+
+impl AgentUpgradeWatchdog {
+    pub fn spawn(args: AgentUpgradeWatchdogArgs) {
+        let AgentUpgradeWatchdogArgs { backup, record, heartbeat_sent, confirmation_timeout } = args;
+        tokio::spawn(async move {
+            let worker = Self { backup, record, heartbeat_sent, confirmation_timeout };
+            worker.run().await
+        });
+    }
+
+    async fn run(mut self) {
+        let version = self.record.version.clone();
+        select! {
+            _ = self.heartbeat_sent.notified() => {
+                info!("Upgrade to version {version} confirmed");
+                self.record.confirmed = true;
+                if let Err(e) = self.backup.save_record(&self.record).await {
+                    error!("Failed to confirm upgrade: {e:#}");
+                }
+            }
+            _ = sleep(self.confirmation_timeout) => {
+                error!(
+                    "No heartbeat sent within {:?} of upgrading to {version}, rolling back to {}",
+                    self.confirmation_timeout, self.record.previous_version
+                );
+                if let Err(e) = self.backup.roll_back().await {
+                    error!("Failed to roll back agent: {e:#}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use tokio::fs;
+
+    #[tokio::test]
+    async fn confirm_on_heartbeat() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let agent_path = dir.path().join("nilcc-agent");
+        let config_path = dir.path().join("config.yaml");
+        fs::write(&agent_path, "agent").await.expect("failed to write agent");
+        fs::write(&config_path, "config").await.expect("failed to write config");
+        let backup = AgentBackup::new(agent_path, config_path);
+        backup.create("v1", "v2").await.expect("failed to create backup");
+
+        let record = backup.load_record().await.expect("failed to load").expect("no record");
+        let heartbeat_sent = Arc::new(Notify::new());
+        heartbeat_sent.notify_one();
+        let watchdog = AgentUpgradeWatchdog {
+            backup: backup.clone(),
+            record,
+            heartbeat_sent,
+            confirmation_timeout: Duration::from_secs(3600),
+        };
+        watchdog.run().await;
+
+        let record = backup.load_record().await.expect("failed to load").expect("no record");
+        assert!(record.confirmed);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::services::upgrade::UpgradeService;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+pub struct ArtifactsGcWorkerArgs {
+    pub upgrade_service: Arc<dyn UpgradeService>,
+    pub interval: Duration,
+}
+
+/// Periodically deletes the artifacts versions selected by the garbage collection policy.
+pub struct ArtifactsGcWorker {
+    upgrade_service: Arc<dyn UpgradeService>,
+    interval: Duration,
+}
+
+impl ArtifactsGcWorker {
+    pub fn spawn(args: ArtifactsGcWorkerArgs) {
+        let ArtifactsGcWorkerArgs { upgrade_service, interval } = args;
+        tokio::spawn(async move {
+            let worker = Self { upgrade_service, interval };
+            worker.run().await
+        });
+    }
+
+    async fn run(self) {
+        loop {
+            match self.upgrade_service.collect_artifacts(false).await {
+                Ok(versions) if versions.is_empty() => info!("No artifacts versions to garbage collect"),
+                Ok(versions) => info!("Garbage collected artifacts versions: {}", versions.join(", ")),
+                Err(e) => error!("Failed to garbage collect artifacts: {e}"),
+            }
+            sleep(self.interval).await;
+        }
+    }
+}
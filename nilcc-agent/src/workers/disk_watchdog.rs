@@ -0,0 +1,397 @@
+use crate::{
+    clients::nilcc_api::VmEvent,
+    repositories::{sqlite::RepositoryProvider, workload::Workload},
+    resources::FreeSpaceFinder,
+    services::{disk::DiskService, upgrade::UpgradeService},
+    workers::events::EventSender,
+};
+use anyhow::Context;
+use chrono::Utc;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::{fs, time::sleep};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const BYTES_PER_GB: u64 = 1024 * 1024 * 1024;
+
+// Files are only considered orphaned after this long so we don't race with workloads that are being created.
+const ORPHAN_GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+/// Whether the host is running low on disk space.
+#[derive(Clone, Default)]
+pub struct DiskSpaceStatus(Arc<AtomicBool>);
+
+impl DiskSpaceStatus {
+    /// Whether free disk space is below the configured threshold.
+    pub(crate) fn is_low(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_low(&self, low: bool) {
+        self.0.store(low, Ordering::Relaxed);
+    }
+}
+
+pub struct DiskWatchdogArgs {
+    pub provider: Arc<dyn RepositoryProvider>,
+    pub upgrade_service: Arc<dyn UpgradeService>,
+    pub event_sender: EventSender,
+    pub space_finder: Box<dyn FreeSpaceFinder>,
+    pub disk_service: Arc<dyn DiskService>,
+    pub status: DiskSpaceStatus,
+    pub vm_store: PathBuf,
+    pub artifacts_path: PathBuf,
+    pub min_free_space_gb: u64,
+    pub check_interval: Duration,
+}
+
+/// Periodically deletes files left behind by deleted workloads and keeps track of the free disk space on the host.
+///
+/// When free space drops below the threshold, unused artifacts versions are deleted and, if that isn't enough, new
+/// workloads are refused until space is freed up.
+pub struct DiskWatchdog {
+    provider: Arc<dyn RepositoryProvider>,
+    upgrade_service: Arc<dyn UpgradeService>,
+    event_sender: EventSender,
+    space_finder: Box<dyn FreeSpaceFinder>,
+    disk_service: Arc<dyn DiskService>,
+    status: DiskSpaceStatus,
+    vm_store: PathBuf,
+    artifacts_path: PathBuf,
+    min_free_space_gb: u64,
+    check_interval: Duration,
+    orphan_grace_period: Duration,
+}
+
+impl DiskWatchdog {
+    pub fn spawn(args: DiskWatchdogArgs) {
+        let DiskWatchdogArgs {
+            provider,
+            upgrade_service,
+            event_sender,
+            space_finder,
+            disk_service,
+            status,
+            vm_store,
+            artifacts_path,
+            min_free_space_gb,
+            check_interval,
+        } = args;
+        tokio::spawn(async move {
+            let worker = Self {
+                provider,
+                upgrade_service,
+                event_sender,
+                space_finder,
+                disk_service,
+                status,
+                vm_store,
+                artifacts_path,
+                min_free_space_gb,
+                check_interval,
+                orphan_grace_period: ORPHAN_GRACE_PERIOD,
+            };
+            worker.run().await
+        });
+    }
+
+    async fn run(self) {
+        loop {
+            if let Err(e) = self.run_once().await {
+                error!("Failed to check disk space: {e:#}");
+            }
+            sleep(self.check_interval).await;
+        }
+    }
+
+    async fn run_once(&self) -> anyhow::Result<()> {
+        let workloads = self.provider.workloads(Default::default()).await?.list().await?;
+        self.delete_orphaned_files(&workloads).await?;
+
+        let mut free_space_gb = self.free_space_gb().await?;
+        if free_space_gb < self.min_free_space_gb {
+            warn!("Free disk space is {free_space_gb}GB, deleting unused artifacts versions");
+            match self.upgrade_service.cleanup_artifacts().await {
+                Ok(versions) if !versions.is_empty() => {
+                    info!("Deleted unused artifacts versions: {versions:?}");
+                    free_space_gb = self.free_space_gb().await?;
+                }
+                Ok(_) => info!("No unused artifacts versions to delete"),
+                Err(e) => error!("Failed to delete unused artifacts versions: {e}"),
+            };
+        }
+
+        let low = free_space_gb < self.min_free_space_gb;
+        // Only emit events when the threshold is first crossed so we don't keep overwriting other events.
+        if low && !self.status.is_low() {
+            let message = format!(
+                "Host free disk space is {free_space_gb}GB, below the {}GB threshold, new workloads are refused",
+                self.min_free_space_gb
+            );
+            warn!("{message}");
+            // Only workloads with a running VM can be affected by their disks filling up.
+            for workload in workloads.iter().filter(|w| w.enabled && !w.preempted) {
+                self.event_sender
+                    .send_event(workload.id, VmEvent::Warning { message: message.clone() }, Utc::now())
+                    .await;
+            }
+        } else if !low && self.status.is_low() {
+            info!("Free disk space is {free_space_gb}GB, accepting new workloads again");
+        }
+        self.status.set_low(low);
+        Ok(())
+    }
+
+    async fn free_space_gb(&self) -> anyhow::Result<u64> {
+        let mut free_space = u64::MAX;
+        for path in [&self.vm_store, &self.artifacts_path] {
+            let bytes = self
+                .space_finder
+                .free_space_bytes(path)
+                .with_context(|| format!("Failed to find free space in {}", path.display()))?;
+            free_space = free_space.min(bytes);
+        }
+        // Disks may live in a storage pool rather than the VM store, in which case that can run out of space too.
+        if let Some(pool) = self.disk_service.pool_usage().await.context("Failed to find storage pool usage")? {
+            free_space = free_space.min(pool.free_bytes);
+        }
+        Ok(free_space / BYTES_PER_GB)
+    }
+
+    async fn delete_orphaned_files(&self, workloads: &[Workload]) -> anyhow::Result<()> {
+        let workload_ids: HashSet<_> = workloads.iter().map(|w| w.id).collect();
+        let mut entries = fs::read_dir(&self.vm_store).await.context("Failed to read VM store")?;
+        while let Some(entry) = entries.next_entry().await.context("Failed to read VM store entry")? {
+            let path = entry.path();
+            // Every file and directory in the VM store is prefixed by the ID of the workload it belongs to, e.g.
+            // `{id}.iso`.
+            let Some(id) = workload_id(&path) else {
+                continue;
+            };
+            if workload_ids.contains(&id) || !self.is_stale(&entry).await {
+                continue;
+            }
+            info!("Deleting {} since workload {id} no longer exists", path.display());
+            // Disks go through the disk service since they may be links to volumes that need to be removed as well.
+            let result = match entry.file_type().await {
+                Ok(file_type) if file_type.is_dir() => fs::remove_dir_all(&path).await.map_err(anyhow::Error::from),
+                _ => self.disk_service.delete_disk(&path).await,
+            };
+            if let Err(e) = result {
+                warn!("Failed to delete {}: {e:#}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    async fn is_stale(&self, entry: &fs::DirEntry) -> bool {
+        // This doesn't follow symlinks, so disks linked to volumes are seen as the links themselves.
+        let Ok(metadata) = entry.metadata().await else {
+            return false;
+        };
+        let modified = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+        let file_type = metadata.file_type();
+        (file_type.is_file() || file_type.is_dir() || file_type.is_symlink())
+            && modified.elapsed().unwrap_or_default() >= self.orphan_grace_period
+    }
+}
+
+fn workload_id(path: &Path) -> Option<Uuid> {
+    let name = path.file_name()?.to_str()?;
+    let (id, _) = name.split_once('.')?;
+    id.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        repositories::{
+            sqlite::MockRepositoryProvider,
+            workload::{MockWorkloadRepository, utils::make_workload},
+        },
+        resources::MockFreeSpaceFinder,
+        services::{
+            disk::{DefaultDiskService, MockDiskService, StoragePoolUsage},
+            upgrade::MockUpgradeService,
+        },
+        workers::events::WorkloadEvent,
+    };
+    use tempfile::{TempDir, tempdir};
+    use tokio::sync::mpsc::{Receiver, channel};
+
+    struct Builder {
+        provider: MockRepositoryProvider,
+        upgrade_service: MockUpgradeService,
+        space_finder: MockFreeSpaceFinder,
+        disk_service: Arc<dyn DiskService>,
+        status: DiskSpaceStatus,
+        vm_store: TempDir,
+    }
+
+    impl Default for Builder {
+        fn default() -> Self {
+            Self {
+                provider: Default::default(),
+                upgrade_service: Default::default(),
+                space_finder: Default::default(),
+                disk_service: Arc::new(DefaultDiskService::new("qemu-img".into())),
+                status: Default::default(),
+                vm_store: tempdir().expect("failed to create tempdir"),
+            }
+        }
+    }
+
+    impl Builder {
+        fn build(self) -> (DiskWatchdog, Receiver<WorkloadEvent>, TempDir) {
+            let Self { provider, upgrade_service, space_finder, disk_service, status, vm_store } = self;
+            let (sender, receiver) = channel(16);
+            let worker = DiskWatchdog {
+                provider: Arc::new(provider),
+                upgrade_service: Arc::new(upgrade_service),
+                event_sender: EventSender(sender),
+                space_finder: Box::new(space_finder),
+                disk_service,
+                status,
+                vm_store: vm_store.path().into(),
+                artifacts_path: "/tmp/artifacts".into(),
+                min_free_space_gb: 10,
+                check_interval: Duration::from_secs(1),
+                orphan_grace_period: Duration::ZERO,
+            };
+            (worker, receiver, vm_store)
+        }
+
+        fn set_workloads(&mut self, workloads: Vec<Workload>) {
+            self.provider.expect_workloads().returning(move |_| {
+                let workloads = workloads.clone();
+                let mut repo = MockWorkloadRepository::default();
+                repo.expect_list().return_once(move || Ok(workloads));
+                Ok(Box::new(repo))
+            });
+        }
+
+        fn set_free_space_gb(&mut self, free_space_gb: u64) {
+            self.space_finder.expect_free_space_bytes().returning(move |_| Ok(free_space_gb * BYTES_PER_GB));
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_orphaned_files() {
+        let mut builder = Builder::default();
+        let workload = make_workload();
+        let orphan_id = Uuid::new_v4();
+        let files = [
+            format!("{}.iso", workload.id),
+            format!("{}.state.raw", workload.id),
+            format!("{orphan_id}.iso"),
+            format!("{orphan_id}.state.qcow2"),
+            "unrelated.txt".into(),
+        ];
+        for file in &files {
+            std::fs::write(builder.vm_store.path().join(file), b"").expect("failed to write file");
+        }
+        let directories = [format!("{}.files", workload.id), format!("{orphan_id}.files")];
+        for directory in &directories {
+            let path = builder.vm_store.path().join(directory);
+            std::fs::create_dir(&path).expect("failed to create directory");
+            std::fs::write(path.join("file"), b"").expect("failed to write file");
+        }
+        // Disks backed by volumes are symlinks to the volume's device.
+        let links = [format!("{}.state.qcow2", workload.id), format!("{orphan_id}.base.qcow2")];
+        for link in &links {
+            std::os::unix::fs::symlink("/dev/vg/volume", builder.vm_store.path().join(link))
+                .expect("failed to create symlink");
+        }
+        builder.set_workloads(vec![workload]);
+        builder.set_free_space_gb(100);
+
+        let (worker, _receiver, vm_store) = builder.build();
+        worker.run_once().await.expect("failed to run");
+        let exists: Vec<_> = files.iter().map(|file| vm_store.path().join(file).exists()).collect();
+        assert_eq!(exists, &[true, true, false, false, true]);
+        let exists: Vec<_> = directories.iter().map(|directory| vm_store.path().join(directory).exists()).collect();
+        assert_eq!(exists, &[true, false]);
+        // The links are dangling, so check the links themselves rather than what they point to.
+        let exists: Vec<_> = links.iter().map(|link| vm_store.path().join(link).is_symlink()).collect();
+        assert_eq!(exists, &[true, false]);
+    }
+
+    #[tokio::test]
+    async fn low_pool_space() {
+        let mut builder = Builder::default();
+        builder.set_workloads(vec![make_workload()]);
+        builder.set_free_space_gb(100);
+        let mut disk_service = MockDiskService::default();
+        disk_service
+            .expect_pool_usage()
+            .returning(|| Ok(Some(StoragePoolUsage { size_bytes: 500 * BYTES_PER_GB, free_bytes: 5 * BYTES_PER_GB })));
+        builder.disk_service = Arc::new(disk_service);
+        builder.upgrade_service.expect_cleanup_artifacts().returning(|| Ok(Vec::new()));
+
+        let (worker, mut receiver, _vm_store) = builder.build();
+        worker.run_once().await.expect("failed to run");
+        assert!(worker.status.is_low());
+        assert!(receiver.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn low_disk_space() {
+        let mut builder = Builder::default();
+        let disabled = Workload { enabled: false, ..make_workload() };
+        let preempted = Workload { preempted: true, ..make_workload() };
+        builder.set_workloads(vec![make_workload(), make_workload(), disabled, preempted]);
+        builder.set_free_space_gb(5);
+        builder.upgrade_service.expect_cleanup_artifacts().returning(|| Ok(Vec::new()));
+
+        let (worker, mut receiver, _vm_store) = builder.build();
+        worker.run_once().await.expect("failed to run");
+        assert!(worker.status.is_low());
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_err());
+
+        // The event is only emitted once.
+        worker.run_once().await.expect("failed to run");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn cleanup_frees_space() {
+        let mut builder = Builder::default();
+        builder.set_workloads(vec![make_workload()]);
+        let mut free_space = [5, 5, 50, 50].into_iter();
+        builder
+            .space_finder
+            .expect_free_space_bytes()
+            .times(4)
+            .returning(move |_| Ok(free_space.next().unwrap() * BYTES_PER_GB));
+        builder.upgrade_service.expect_cleanup_artifacts().once().return_once(|| Ok(vec!["old".into()]));
+
+        let (worker, mut receiver, _vm_store) = builder.build();
+        worker.run_once().await.expect("failed to run");
+        assert!(!worker.status.is_low());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn disk_space_recovers() {
+        let mut builder = Builder::default();
+        builder.set_workloads(vec![make_workload()]);
+        builder.set_free_space_gb(50);
+        builder.status.set_low(true);
+
+        let (worker, mut receiver, _vm_store) = builder.build();
+        worker.run_once().await.expect("failed to run");
+        assert!(!worker.status.is_low());
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::services::workload::WorkloadService;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+/// How often domains are checked for whether they need to be retired.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct DomainRetirementWorkerArgs {
+    pub workload_service: Arc<dyn WorkloadService>,
+}
+
+/// Periodically retires the domains workloads stopped using once their grace period is over.
+///
+/// Domains that are being retired are stored in the database so they're still retired if the agent restarts before
+/// their grace period is over.
+pub struct DomainRetirementWorker {
+    workload_service: Arc<dyn WorkloadService>,
+}
+
+impl DomainRetirementWorker {
+    pub fn spawn(args: DomainRetirementWorkerArgs) {
+        let DomainRetirementWorkerArgs { workload_service } = args;
+        tokio::spawn(async move {
+            let worker = Self { workload_service };
+            worker.run().await
+        });
+    }
+
+    async fn run(self) {
+        loop {
+            if let Err(e) = self.run_once().await {
+                error!("Failed to retire domains: {e:#}");
+            }
+            sleep(CHECK_INTERVAL).await;
+        }
+    }
+
+    async fn run_once(&self) -> anyhow::Result<()> {
+        let retired = self.workload_service.retire_domains().await?;
+        if !retired.is_empty() {
+            info!("Retired domains {retired:?}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::workload::MockWorkloadService;
+
+    #[tokio::test]
+    async fn retire_domains() {
+        let mut workload_service = MockWorkloadService::default();
+        workload_service.expect_retire_domains().once().return_once(|| Ok(vec!["old.example.com".into()]));
+        let worker = DomainRetirementWorker { workload_service: Arc::new(workload_service) };
+        worker.run_once().await.expect("failed to retire domains");
+    }
+}
@@ -1,14 +1,33 @@
 use crate::{
-    clients::nilcc_api::{NilccApiClient, NilccApiError, VmEvent, VmEventDiscriminants},
-    repositories::{sqlite::RepositoryProvider, workload::WorkloadRepositoryError},
+    clients::{
+        nilcc_api::{NilccApiClient, NilccApiError, VmEvent, VmEventDiscriminants},
+        webhook::{WebhookClient, WebhookEvent},
+    },
+    config::EventsConfig,
+    repositories::{
+        events::PendingEvent,
+        sqlite::{ProviderMode, RepositoryProvider},
+        workload::WorkloadRepositoryError,
+    },
 };
 use anyhow::Context;
 use chrono::{DateTime, Utc};
+use metrics::{counter, gauge};
+use rate_limit::TokenBucket;
 use reqwest::StatusCode;
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::{
+    fs::OpenOptions,
+    io::AsyncWriteExt,
+    select,
     sync::mpsc::{Receiver, Sender, channel},
-    time::sleep,
+    time::{sleep, sleep_until},
 };
 use tracing::{error, info, warn};
 use uuid::Uuid;
@@ -24,38 +43,172 @@ pub(crate) struct WorkloadEvent {
 pub struct EventWorkerArgs {
     pub api_client: Arc<dyn NilccApiClient>,
     pub repository_provider: Arc<dyn RepositoryProvider>,
+    pub webhooks: Arc<WebhookDispatcher>,
+    pub config: EventsConfig,
 }
 
+/// Forwards workload events to the nilcc API and webhooks.
+///
+/// Events are persisted until they're sent so they survive agent restarts. Every workload is rate limited independently
+/// so a crash looping CVM can't flood the API, and once the queue is full its lowest priority events are dropped.
 pub struct EventWorker {
     client: Arc<dyn NilccApiClient>,
     receiver: Receiver<WorkloadEvent>,
     repository_provider: Arc<dyn RepositoryProvider>,
+    webhooks: Arc<WebhookDispatcher>,
     seen_workloads: HashSet<Uuid>,
+    queue: EventQueue,
+    batch_size: usize,
 }
 
 impl EventWorker {
     pub fn spawn(args: EventWorkerArgs) -> EventSender {
-        let EventWorkerArgs { api_client, repository_provider } = args;
+        let EventWorkerArgs { api_client, repository_provider, webhooks, config } = args;
         let (sender, receiver) = channel(1024);
         tokio::spawn(async move {
-            let worker =
-                EventWorker { client: api_client, repository_provider, receiver, seen_workloads: Default::default() };
+            let worker = EventWorker {
+                client: api_client,
+                repository_provider,
+                receiver,
+                webhooks,
+                seen_workloads: Default::default(),
+                queue: EventQueue::new(&config),
+                batch_size: config.batch_size.max(1),
+            };
             worker.run().await;
         });
         EventSender(sender)
     }
 
     async fn run(mut self) {
-        while let Some(event) = self.receiver.recv().await {
-            while let Err(e) = self.send_event(&event).await {
-                error!("Failed to send event: {e:#}");
-                sleep(RETRY_INTERVAL).await;
+        match self.load_pending().await {
+            Ok(events) => {
+                if !events.is_empty() {
+                    info!("Loaded {} events that weren't sent before restarting", events.len());
+                }
+                for event in events {
+                    self.queue.restore(event);
+                }
+            }
+            Err(e) => error!("Failed to load pending events: {e:#}"),
+        }
+        loop {
+            let now = Instant::now();
+            let first = match self.queue.ready_at(now) {
+                // There's something to send already so only pick up the events that are already waiting.
+                Some(ready_at) if ready_at <= now => None,
+                Some(ready_at) => select! {
+                    Some(event) = self.receiver.recv() => Some(event),
+                    _ = sleep_until(ready_at.into()) => None,
+                },
+                None => match self.receiver.recv().await {
+                    Some(event) => Some(event),
+                    None => break,
+                },
+            };
+            let mut batch: Vec<_> = first.into_iter().collect();
+            while batch.len() < self.batch_size {
+                match self.receiver.try_recv() {
+                    Ok(event) => batch.push(event),
+                    Err(_) => break,
+                }
+            }
+            self.enqueue(batch).await;
+            self.send_ready().await;
+        }
+    }
+
+    async fn load_pending(&self) -> anyhow::Result<Vec<PendingEvent>> {
+        let mut repo =
+            self.repository_provider.pending_events(Default::default()).await.context("Failed to get repository")?;
+        repo.list().await.context("Failed to list events")
+    }
+
+    /// Queue a batch of events, persisting all of them at once.
+    async fn enqueue(&mut self, batch: Vec<WorkloadEvent>) {
+        let mut queued = Vec::new();
+        let mut evicted = Vec::new();
+        for WorkloadEvent { workload_id, event, timestamp } in batch {
+            let event_type = format!("{:?}", VmEventDiscriminants::from(&event));
+            let priority = EventPriority::from(&event);
+            match self.queue.push(workload_id, event, timestamp) {
+                Push::Queued { event, evicted: other } => {
+                    queued.push(event);
+                    if let Some(other) = other {
+                        let other_type = format!("{:?}", VmEventDiscriminants::from(&other.event));
+                        let other_priority = EventPriority::from(&other.event);
+                        warn!("Event queue is full, dropping event {other_type} for workload {}", other.workload_id);
+                        counter!("events_dropped_total", "priority" => other_priority.label()).increment(1);
+                        evicted.push(other.id);
+                    }
+                }
+                Push::Coalesced => {
+                    info!("Event {event_type} for workload {workload_id} is already queued, coalescing it");
+                    counter!("events_coalesced_total").increment(1);
+                }
+                Push::Dropped => {
+                    warn!("Event queue is full, dropping event {event_type} for workload {workload_id}");
+                    counter!("events_dropped_total", "priority" => priority.label()).increment(1);
+                }
+            }
+        }
+        // Events evicted by others in the same batch were never persisted.
+        queued.retain(|event| !evicted.contains(&event.id));
+        if let Err(e) = self.persist(&queued, &evicted).await {
+            error!("Failed to persist queued events: {e:#}");
+        }
+        gauge!("events_queued").set(self.queue.len() as f64);
+    }
+
+    /// Send the events that aren't rate limited, up to a batch's worth of them.
+    async fn send_ready(&mut self) {
+        for _ in 0..self.batch_size {
+            let Some(event) = self.queue.next(Instant::now()) else {
+                break;
+            };
+            match self.send_event(&event).await {
+                Ok(sent) => {
+                    if sent {
+                        self.queue.record_sent(event.workload_id, Instant::now());
+                        self.webhooks.dispatch(&event);
+                    }
+                    self.queue.remove(event.id);
+                    if let Err(e) = self.persist(&[], &[event.id]).await {
+                        error!("Failed to delete sent event: {e:#}");
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to send event: {e:#}");
+                    sleep(RETRY_INTERVAL).await;
+                    break;
+                }
             }
         }
+        gauge!("events_queued").set(self.queue.len() as f64);
     }
 
-    async fn send_event(&mut self, event: &WorkloadEvent) -> anyhow::Result<()> {
-        let WorkloadEvent { workload_id, event, timestamp } = event;
+    async fn persist(&self, queued: &[PendingEvent], removed: &[i64]) -> anyhow::Result<()> {
+        if queued.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+        let mut repo = self
+            .repository_provider
+            .pending_events(ProviderMode::Transactional)
+            .await
+            .context("Failed to get repository")?;
+        for event in queued {
+            repo.insert(event).await.context("Failed to insert event")?;
+        }
+        for id in removed {
+            repo.delete(*id).await.context("Failed to delete event")?;
+        }
+        repo.commit().await.context("Failed to commit")?;
+        Ok(())
+    }
+
+    /// Send an event to the API, returning whether it was a new event for the workload.
+    async fn send_event(&mut self, event: &PendingEvent) -> anyhow::Result<bool> {
+        let PendingEvent { workload_id, event, timestamp, .. } = event;
         let event_type = format!("{:?}", VmEventDiscriminants::from(event));
         let mut repo =
             self.repository_provider.workloads(Default::default()).await.context("Failed to get repository")?;
@@ -64,7 +217,7 @@ impl EventWorker {
                 Ok(workload) => workload,
                 Err(WorkloadRepositoryError::WorkloadNotFound) => {
                     warn!("Ignoring event {event_type} since workload {workload_id} does not exist anymore");
-                    return Ok(());
+                    return Ok(false);
                 }
                 Err(e) => {
                     return Err(e).context("Failed to lookup workload");
@@ -73,7 +226,7 @@ impl EventWorker {
             if workload.last_reported_event.as_ref() == Some(&event_type) {
                 info!("Already reported event {event_type} for workload {workload_id}, ignoring");
                 self.seen_workloads.insert(*workload_id);
-                return Ok(());
+                return Ok(false);
             }
         }
 
@@ -82,7 +235,7 @@ impl EventWorker {
             Ok(_) => (),
             Err(NilccApiError::Api { status, .. }) if status == StatusCode::NOT_FOUND => {
                 warn!("API returned 404 for workload {workload_id} event, ignoring");
-                return Ok(());
+                return Ok(true);
             }
             Err(e) => {
                 return Err(e).context("Failed to send event to API");
@@ -93,7 +246,7 @@ impl EventWorker {
             match repo.set_last_reported_event(*workload_id, event_type.clone()).await {
                 Ok(_) => {
                     self.seen_workloads.insert(*workload_id);
-                    return Ok(());
+                    return Ok(true);
                 }
                 Err(e) => {
                     warn!("Failed to update workload last reported event: {e}");
@@ -104,6 +257,261 @@ impl EventWorker {
     }
 }
 
+/// The priority of an event, which decides which events are sent first and which ones are dropped first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum EventPriority {
+    Warning,
+    Lifecycle,
+    Error,
+}
+
+impl EventPriority {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Warning => "warning",
+            Self::Lifecycle => "lifecycle",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl From<&VmEvent> for EventPriority {
+    fn from(event: &VmEvent) -> Self {
+        match event {
+            VmEvent::FailedToStart { .. } => Self::Error,
+            VmEvent::Warning { .. } => Self::Warning,
+            _ => Self::Lifecycle,
+        }
+    }
+}
+
+/// The outcome of pushing an event into an [`EventQueue`].
+#[derive(Debug, PartialEq)]
+enum Push {
+    /// The event was queued, possibly evicting a lower priority one.
+    Queued { event: PendingEvent, evicted: Option<PendingEvent> },
+
+    /// The event is identical to one that's already queued for the workload.
+    Coalesced,
+
+    /// The queue is full of events with a higher priority.
+    Dropped,
+}
+
+/// A bounded queue of events waiting to be sent, which rate limits every workload.
+///
+/// A workload's events are sent in the order they were received, except for warnings which can be overtaken by any
+/// other event since they don't change the workload's state. Across workloads, errors are sent first and warnings last.
+struct EventQueue {
+    events: Vec<PendingEvent>,
+    // Workloads without a token bucket have all of their tokens available.
+    limiters: HashMap<Uuid, TokenBucket>,
+    max_queued: usize,
+    burst: u32,
+    interval: Duration,
+    next_id: i64,
+}
+
+impl EventQueue {
+    fn new(config: &EventsConfig) -> Self {
+        Self {
+            events: Vec::new(),
+            limiters: HashMap::new(),
+            max_queued: config.max_queued.max(1),
+            burst: config.workload_burst.max(1),
+            interval: config.workload_interval_seconds,
+            next_id: 1,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Add an event that was queued before the agent restarted.
+    fn restore(&mut self, event: PendingEvent) {
+        self.next_id = self.next_id.max(event.id + 1);
+        self.events.push(event);
+    }
+
+    fn push(&mut self, workload_id: Uuid, event: VmEvent, timestamp: DateTime<Utc>) -> Push {
+        let priority = EventPriority::from(&event);
+        let workload_events = || self.events.iter().filter(|queued| queued.workload_id == workload_id);
+        // Coalescing with anything but the workload's last state change would reorder its state changes.
+        let coalesce = match priority {
+            EventPriority::Warning => workload_events().any(|queued| queued.event == event),
+            _ => workload_events()
+                .filter(|queued| EventPriority::from(&queued.event) != EventPriority::Warning)
+                .last()
+                .is_some_and(|queued| queued.event == event),
+        };
+        if coalesce {
+            return Push::Coalesced;
+        }
+
+        let mut evicted = None;
+        if self.events.len() >= self.max_queued {
+            // This picks the oldest of the events with the lowest priority.
+            let (index, lowest) = self
+                .events
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, queued)| EventPriority::from(&queued.event))
+                .expect("queue is empty");
+            if EventPriority::from(&lowest.event) > priority {
+                return Push::Dropped;
+            }
+            evicted = Some(self.events.remove(index));
+        }
+        let event = PendingEvent { id: self.next_id, workload_id, event, timestamp };
+        self.next_id += 1;
+        self.events.push(event.clone());
+        Push::Queued { event, evicted }
+    }
+
+    /// Get the next event to send, unless all queued events are rate limited.
+    fn next(&mut self, now: Instant) -> Option<PendingEvent> {
+        self.refill(now);
+        // The workloads that have an earlier queued event, and the ones where that event isn't a warning.
+        let mut passed = HashSet::new();
+        let mut passed_changes = HashSet::new();
+        let mut next: Option<(&PendingEvent, EventPriority)> = None;
+        for queued in &self.events {
+            let workload_id = queued.workload_id;
+            let priority = EventPriority::from(&queued.event);
+            let limited = self.limiters.get(&workload_id).is_some_and(|limiter| limiter.tokens() == 0);
+            let in_order = match priority {
+                EventPriority::Warning => !passed.contains(&workload_id),
+                _ => !passed_changes.contains(&workload_id),
+            };
+            if !limited && in_order && next.is_none_or(|(_, best)| priority > best) {
+                next = Some((queued, priority));
+            }
+            passed.insert(workload_id);
+            if priority != EventPriority::Warning {
+                passed_changes.insert(workload_id);
+            }
+        }
+        next.map(|(event, _)| event.clone())
+    }
+
+    /// Get the instant at which an event can be sent, or `None` if the queue is empty.
+    fn ready_at(&mut self, now: Instant) -> Option<Instant> {
+        self.refill(now);
+        self.events
+            .iter()
+            .map(|queued| match self.limiters.get(&queued.workload_id) {
+                Some(limiter) if limiter.tokens() == 0 => limiter.next_refill(self.interval),
+                _ => now,
+            })
+            .min()
+    }
+
+    /// Record that an event was sent for a workload.
+    fn record_sent(&mut self, workload_id: Uuid, now: Instant) {
+        self.limiters.entry(workload_id).or_insert_with(|| TokenBucket::full(self.burst, now)).take();
+    }
+
+    fn remove(&mut self, id: i64) {
+        self.events.retain(|queued| queued.id != id);
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let (burst, interval) = (self.burst, self.interval);
+        self.limiters.retain(|_, limiter| {
+            limiter.refill(now, burst, interval);
+            // A full bucket is the same as not having one.
+            limiter.tokens() < burst
+        });
+    }
+}
+
+pub struct WebhookDispatcherArgs {
+    pub agent_id: Uuid,
+    pub clients: Vec<Arc<dyn WebhookClient>>,
+    pub max_attempts: u32,
+    pub retry_interval: Duration,
+    pub dead_letter_path: Option<PathBuf>,
+}
+
+/// Sends workload events to webhooks.
+///
+/// Every event is delivered to each webhook independently, retrying with an exponential backoff. Events that can't be
+/// delivered after all attempts are logged and appended to the dead letter file, if any.
+pub struct WebhookDispatcher {
+    agent_id: Uuid,
+    clients: Vec<Arc<dyn WebhookClient>>,
+    max_attempts: u32,
+    retry_interval: Duration,
+    dead_letter_path: Option<PathBuf>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(args: WebhookDispatcherArgs) -> Self {
+        let WebhookDispatcherArgs { agent_id, clients, max_attempts, retry_interval, dead_letter_path } = args;
+        Self { agent_id, clients, max_attempts: max_attempts.max(1), retry_interval, dead_letter_path }
+    }
+
+    fn dispatch(self: &Arc<Self>, event: &PendingEvent) {
+        let PendingEvent { workload_id, event, timestamp, .. } = event;
+        let event = WebhookEvent {
+            agent_id: self.agent_id,
+            workload_id: *workload_id,
+            event: event.clone(),
+            timestamp: *timestamp,
+        };
+        for client in &self.clients {
+            let dispatcher = self.clone();
+            let client = client.clone();
+            let event = event.clone();
+            tokio::spawn(async move { dispatcher.deliver(client.as_ref(), &event).await });
+        }
+    }
+
+    async fn deliver(&self, client: &dyn WebhookClient, event: &WebhookEvent) {
+        let url = client.url();
+        let workload_id = event.workload_id;
+        let mut retry_interval = self.retry_interval;
+        let mut attempt = 1;
+        let error = loop {
+            match client.send(event).await {
+                Ok(()) => return,
+                Err(e) if attempt == self.max_attempts => break e,
+                Err(e) => {
+                    warn!("Failed to send event for workload {workload_id} to webhook {url} (attempt {attempt}): {e}");
+                    sleep(retry_interval).await;
+                    retry_interval *= 2;
+                    attempt += 1;
+                }
+            }
+        };
+        error!("Giving up on sending event for workload {workload_id} to webhook {url}: {error}");
+        if let Some(path) = &self.dead_letter_path {
+            let letter = DeadLetter { url, event, error: error.to_string() };
+            if let Err(e) = Self::write_dead_letter(path, &letter).await {
+                error!("Failed to write dead letter to {}: {e:#}", path.display());
+            }
+        }
+    }
+
+    async fn write_dead_letter(path: &Path, letter: &DeadLetter<'_>) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(letter).context("Failed to serialize")?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(path).await.context("Failed to open file")?;
+        file.write_all(&line).await.context("Failed to write")?;
+        Ok(())
+    }
+}
+
+/// An event that couldn't be delivered to a webhook.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeadLetter<'a> {
+    url: &'a str,
+    event: &'a WebhookEvent,
+    error: String,
+}
+
 #[derive(Clone)]
 pub struct EventSender(pub(crate) Sender<WorkloadEvent>);
 
@@ -114,3 +522,164 @@ impl EventSender {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::webhook::{MockWebhookClient, WebhookError};
+    use mockall::Sequence;
+    use tempfile::tempdir;
+
+    fn make_dispatcher(dead_letter_path: Option<PathBuf>) -> WebhookDispatcher {
+        WebhookDispatcher::new(WebhookDispatcherArgs {
+            agent_id: Uuid::new_v4(),
+            clients: Vec::new(),
+            max_attempts: 3,
+            retry_interval: Duration::from_millis(1),
+            dead_letter_path,
+        })
+    }
+
+    fn make_event() -> WebhookEvent {
+        WebhookEvent {
+            agent_id: Uuid::new_v4(),
+            workload_id: Uuid::new_v4(),
+            event: VmEvent::FailedToStart { error: "oops".into() },
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn make_client() -> MockWebhookClient {
+        let mut client = MockWebhookClient::default();
+        client.expect_url().return_const("https://example.com/hook".to_string());
+        client
+    }
+
+    fn failure() -> Result<(), WebhookError> {
+        Err(WebhookError::Status { status: StatusCode::INTERNAL_SERVER_ERROR, message: "oops".into() })
+    }
+
+    #[tokio::test]
+    async fn webhook_delivery_retries() {
+        let mut client = make_client();
+        let mut sequence = Sequence::new();
+        client.expect_send().times(2).in_sequence(&mut sequence).returning(|_| failure());
+        client.expect_send().once().in_sequence(&mut sequence).returning(|_| Ok(()));
+
+        let dir = tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("dead-letters.jsonl");
+        make_dispatcher(Some(path.clone())).deliver(&client, &make_event()).await;
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn webhook_dead_letter() {
+        let mut client = make_client();
+        client.expect_send().times(3).returning(|_| failure());
+
+        let dir = tempdir().expect("failed to create tempdir");
+        let path = dir.path().join("dead-letters.jsonl");
+        let dispatcher = make_dispatcher(Some(path.clone()));
+        let event = make_event();
+        dispatcher.deliver(&client, &event).await;
+
+        let contents = std::fs::read_to_string(&path).expect("failed to read dead letters");
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let letter: serde_json::Value = serde_json::from_str(lines[0]).expect("invalid dead letter");
+        assert_eq!(letter["url"], "https://example.com/hook");
+        assert_eq!(letter["event"], serde_json::to_value(&event).unwrap());
+    }
+
+    fn make_queue(max_queued: usize) -> EventQueue {
+        EventQueue::new(&EventsConfig {
+            max_queued,
+            batch_size: 10,
+            workload_burst: 2,
+            workload_interval_seconds: Duration::from_secs(10),
+        })
+    }
+
+    fn push(queue: &mut EventQueue, workload_id: Uuid, event: VmEvent) -> Push {
+        queue.push(workload_id, event, Utc::now())
+    }
+
+    fn warning(message: &str) -> VmEvent {
+        VmEvent::Warning { message: message.into() }
+    }
+
+    #[test]
+    fn queue_coalescing() {
+        let mut queue = make_queue(10);
+        let id = Uuid::new_v4();
+        assert!(matches!(push(&mut queue, id, VmEvent::Starting), Push::Queued { .. }));
+        assert!(matches!(push(&mut queue, id, warning("disk")), Push::Queued { .. }));
+        assert_eq!(push(&mut queue, id, VmEvent::Starting), Push::Coalesced);
+        assert!(matches!(push(&mut queue, id, VmEvent::Running), Push::Queued { .. }));
+        assert_eq!(push(&mut queue, id, warning("disk")), Push::Coalesced);
+
+        // Coalescing this one would reorder the workload's state changes.
+        assert!(matches!(push(&mut queue, id, VmEvent::Starting), Push::Queued { .. }));
+        assert_eq!(queue.len(), 4);
+    }
+
+    #[test]
+    fn queue_priorities() {
+        let mut queue = make_queue(10);
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        push(&mut queue, first, warning("disk"));
+        push(&mut queue, first, VmEvent::Running);
+        push(&mut queue, first, VmEvent::Stopped);
+        push(&mut queue, second, VmEvent::FailedToStart { error: "oops".into() });
+
+        let now = Instant::now();
+        let mut events = Vec::new();
+        while let Some(event) = queue.next(now) {
+            queue.remove(event.id);
+            events.push(event.event);
+        }
+        let expected =
+            [VmEvent::FailedToStart { error: "oops".into() }, VmEvent::Running, VmEvent::Stopped, warning("disk")];
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn queue_full() {
+        let mut queue = make_queue(2);
+        let id = Uuid::new_v4();
+        push(&mut queue, id, warning("disk"));
+        push(&mut queue, Uuid::new_v4(), VmEvent::Running);
+
+        let outcome = push(&mut queue, Uuid::new_v4(), VmEvent::FailedToStart { error: "oops".into() });
+        let Push::Queued { evicted, .. } = outcome else { panic!("event not queued") };
+        assert_eq!(evicted.map(|event| event.event), Some(warning("disk")));
+        assert_eq!(push(&mut queue, id, warning("memory")), Push::Dropped);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn queue_rate_limiting() {
+        let mut queue = make_queue(10);
+        let (limited, other) = (Uuid::new_v4(), Uuid::new_v4());
+        for event in [VmEvent::Starting, VmEvent::Running, VmEvent::Stopped] {
+            push(&mut queue, limited, event);
+        }
+        let now = Instant::now();
+        for _ in 0..2 {
+            let event = queue.next(now).expect("no event");
+            queue.record_sent(limited, now);
+            queue.remove(event.id);
+        }
+        assert!(queue.next(now).is_none());
+        assert_eq!(queue.ready_at(now), Some(now + Duration::from_secs(10)));
+
+        // Other workloads aren't affected.
+        push(&mut queue, other, VmEvent::Running);
+        let event = queue.next(now).expect("no event");
+        assert_eq!(event.workload_id, other);
+        queue.remove(event.id);
+
+        let event = queue.next(now + Duration::from_secs(10)).expect("no event");
+        assert_eq!(event.event, VmEvent::Stopped);
+    }
+}
@@ -1,10 +1,10 @@
 use crate::{
-    clients::nilcc_api::NilccApiClient,
+    clients::nilcc_api::{HeartbeatWorkload, NilccApiClient},
     repositories::{sqlite::RepositoryProvider, workload::Workload},
     services::upgrade::{UpgradeError, UpgradeService},
 };
 use std::{collections::BTreeSet, sync::Arc, time::Duration};
-use tokio::time::sleep;
+use tokio::{sync::Notify, time::sleep};
 use tracing::{debug, error, info, warn};
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
@@ -13,19 +13,23 @@ pub struct HeartbeatWorkerArgs {
     pub api_client: Arc<dyn NilccApiClient>,
     pub provider: Arc<dyn RepositoryProvider>,
     pub upgrader: Arc<dyn UpgradeService>,
+
+    /// Notified every time a heartbeat is successfully sent.
+    pub heartbeat_sent: Arc<Notify>,
 }
 
 pub struct HeartbeatWorker {
     api_client: Arc<dyn NilccApiClient>,
     provider: Arc<dyn RepositoryProvider>,
     upgrader: Arc<dyn UpgradeService>,
+    heartbeat_sent: Arc<Notify>,
 }
 
 impl HeartbeatWorker {
     pub fn spawn(args: HeartbeatWorkerArgs) {
-        let HeartbeatWorkerArgs { api_client, provider, upgrader } = args;
+        let HeartbeatWorkerArgs { api_client, provider, upgrader, heartbeat_sent } = args;
         tokio::spawn(async move {
-            let worker = Self { api_client, provider, upgrader };
+            let worker = Self { api_client, provider, upgrader, heartbeat_sent };
             worker.run().await
         });
     }
@@ -42,18 +46,25 @@ impl HeartbeatWorker {
     }
 
     async fn run_once(&self) -> anyhow::Result<()> {
-        match self.load_available_artifact_versions().await {
-            Ok(available_versions) => match self.api_client.heartbeat(available_versions.clone()).await {
-                Ok(response) => {
-                    self.handle_versions(available_versions, response.expected_artifact_versions).await;
-                    Ok(())
-                }
-                Err(e) => {
-                    warn!("Could not submit heartbeat: {e}");
-                    Ok(())
-                }
-            },
-            Err(e) => Err(e.context("Failed to load available artifact versions")),
+        let available_versions = self
+            .load_available_artifact_versions()
+            .await
+            .map_err(|e| e.context("Failed to load available artifact versions"))?;
+        let workloads = self.load_workloads().await.map_err(|e| e.context("Failed to load workloads"))?;
+        let heartbeat_workloads = workloads
+            .iter()
+            .map(|w| HeartbeatWorkload { workload_id: w.id, labels: w.labels.clone(), isolated: w.isolated })
+            .collect();
+        match self.api_client.heartbeat(available_versions.clone(), heartbeat_workloads).await {
+            Ok(response) => {
+                self.heartbeat_sent.notify_one();
+                self.handle_versions(available_versions, response.expected_artifact_versions, &workloads).await;
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Could not submit heartbeat: {e}");
+                Ok(())
+            }
         }
     }
 
@@ -69,11 +80,16 @@ impl HeartbeatWorker {
         Ok(workloads)
     }
 
-    async fn handle_versions(&self, available_versions: Vec<String>, expected_versions: Vec<String>) {
+    async fn handle_versions(
+        &self,
+        available_versions: Vec<String>,
+        expected_versions: Vec<String>,
+        workloads: &[Workload],
+    ) {
         let available_versions: BTreeSet<_> = available_versions.into_iter().collect();
         let expected_versions: BTreeSet<_> = expected_versions.into_iter().collect();
         self.install_missing_versions(&available_versions, &expected_versions).await;
-        self.uninstall_unused_versions(&available_versions, &expected_versions).await;
+        self.uninstall_unused_versions(&available_versions, &expected_versions, workloads).await;
     }
 
     async fn install_missing_versions(
@@ -102,19 +118,13 @@ impl HeartbeatWorker {
         &self,
         available_versions: &BTreeSet<String>,
         expected_versions: &BTreeSet<String>,
+        workloads: &[Workload],
     ) {
         let redundant_versions: Vec<_> = available_versions.difference(expected_versions).collect();
         if redundant_versions.is_empty() {
             return;
         }
-        let workloads = match self.load_workloads().await {
-            Ok(workloads) => workloads,
-            Err(e) => {
-                error!("Failed to load workloads: {e:#}");
-                return;
-            }
-        };
-        let versions_in_use: BTreeSet<_> = workloads.into_iter().map(|w| w.artifacts_version).collect();
+        let versions_in_use: BTreeSet<_> = workloads.iter().map(|w| &w.artifacts_version).collect();
         info!("Artifact versions {redundant_versions:?} are no longer required, {versions_in_use:?} are in use");
         for version in redundant_versions {
             if versions_in_use.contains(version) {
@@ -155,6 +165,7 @@ mod tests {
                 api_client: Arc::new(api_client),
                 provider: Arc::new(provider),
                 upgrader: Arc::new(upgrader),
+                heartbeat_sent: Default::default(),
             }
         }
 
@@ -185,8 +196,8 @@ mod tests {
         builder
             .api_client
             .expect_heartbeat()
-            .with(eq(existing.into_iter().map(ToString::to_string).collect::<Vec<_>>()))
-            .return_once(move |_| Ok(HeartbeatResponse { expected_artifact_versions: expected }));
+            .with(eq(existing.into_iter().map(ToString::to_string).collect::<Vec<_>>()), eq(vec![]))
+            .return_once(move |_, _| Ok(HeartbeatResponse { expected_artifact_versions: expected }));
         builder.upgrader.expect_install_artifacts().with(eq("c".to_string())).once().return_once(move |_| Ok(()));
         builder
             .upgrader
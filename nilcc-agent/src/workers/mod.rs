@@ -1,3 +1,12 @@
+pub mod agent_upgrade;
+pub mod artifacts_gc;
+pub mod disk_watchdog;
+pub mod domain_retirement;
 pub mod events;
 pub mod heartbeat;
+pub mod pressure;
+pub mod public_ip;
+pub mod reservation;
+pub mod upgrade_channel;
+pub mod usage;
 pub(crate) mod vm;
@@ -0,0 +1,125 @@
+use crate::{
+    resources::AvailableMemoryFinder, services::workload::WorkloadService, workers::disk_watchdog::DiskSpaceStatus,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::{debug, error, warn};
+
+pub struct PressureWatchdogArgs {
+    pub workload_service: Arc<dyn WorkloadService>,
+    pub memory_finder: Box<dyn AvailableMemoryFinder>,
+    pub disk_space: DiskSpaceStatus,
+    pub min_available_memory_mb: u64,
+    pub check_interval: Duration,
+}
+
+/// Periodically checks whether the host is under memory or disk pressure and preempts low priority workloads to
+/// relieve it.
+///
+/// At most one workload is preempted on every check so the host gets a chance to recover before more are stopped.
+pub struct PressureWatchdog {
+    workload_service: Arc<dyn WorkloadService>,
+    memory_finder: Box<dyn AvailableMemoryFinder>,
+    disk_space: DiskSpaceStatus,
+    min_available_memory_mb: u64,
+    check_interval: Duration,
+}
+
+impl PressureWatchdog {
+    pub fn spawn(args: PressureWatchdogArgs) {
+        let PressureWatchdogArgs {
+            workload_service,
+            memory_finder,
+            disk_space,
+            min_available_memory_mb,
+            check_interval,
+        } = args;
+        tokio::spawn(async move {
+            let worker = Self { workload_service, memory_finder, disk_space, min_available_memory_mb, check_interval };
+            worker.run().await
+        });
+    }
+
+    async fn run(self) {
+        loop {
+            sleep(self.check_interval).await;
+            if let Err(e) = self.run_once().await {
+                error!("Failed to relieve host pressure: {e:#}");
+            }
+        }
+    }
+
+    async fn run_once(&self) -> anyhow::Result<Option<String>> {
+        let Some(pressure) = self.pressure() else {
+            return Ok(None);
+        };
+        match self.workload_service.preempt_for_pressure().await? {
+            Some(id) => warn!("Preempted workload {id} since {pressure}"),
+            None => debug!("Host is under pressure since {pressure} but there's no workload to preempt"),
+        }
+        Ok(Some(pressure))
+    }
+
+    /// Describes the pressure the host is under, if any.
+    fn pressure(&self) -> Option<String> {
+        let available_memory_mb = self.memory_finder.available_memory_mb();
+        if available_memory_mb < self.min_available_memory_mb {
+            Some(format!("available memory is {available_memory_mb}MB"))
+        } else if self.disk_space.is_low() {
+            Some("free disk space is low".into())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resources::MockAvailableMemoryFinder, services::workload::MockWorkloadService};
+    use uuid::Uuid;
+
+    fn make_watchdog(
+        workload_service: MockWorkloadService,
+        available_memory_mb: u64,
+        disk_space: DiskSpaceStatus,
+    ) -> PressureWatchdog {
+        let mut memory_finder = MockAvailableMemoryFinder::default();
+        memory_finder.expect_available_memory_mb().return_const(available_memory_mb);
+        PressureWatchdog {
+            workload_service: Arc::new(workload_service),
+            memory_finder: Box::new(memory_finder),
+            disk_space,
+            min_available_memory_mb: 1024,
+            check_interval: Duration::from_secs(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn no_pressure() {
+        let mut workload_service = MockWorkloadService::default();
+        workload_service.expect_preempt_for_pressure().never();
+        let watchdog = make_watchdog(workload_service, 2048, Default::default());
+        assert_eq!(watchdog.run_once().await.expect("check failed"), None);
+    }
+
+    #[tokio::test]
+    async fn memory_pressure() {
+        let mut workload_service = MockWorkloadService::default();
+        workload_service.expect_preempt_for_pressure().once().return_once(|| Ok(Some(Uuid::new_v4())));
+        let watchdog = make_watchdog(workload_service, 512, Default::default());
+        let pressure = watchdog.run_once().await.expect("check failed");
+        assert_eq!(pressure.as_deref(), Some("available memory is 512MB"));
+    }
+
+    #[tokio::test]
+    async fn disk_pressure() {
+        let mut workload_service = MockWorkloadService::default();
+        workload_service.expect_preempt_for_pressure().once().return_once(|| Ok(None));
+        let disk_space = DiskSpaceStatus::default();
+        disk_space.set_low(true);
+        let watchdog = make_watchdog(workload_service, 2048, disk_space);
+        let pressure = watchdog.run_once().await.expect("check failed");
+        assert_eq!(pressure.as_deref(), Some("free disk space is low"));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{
+    clients::nilcc_api::{NilccApiClient, VmEvent},
+    config::ApiConfig,
+    repositories::{sqlite::RepositoryProvider, workload::Workload},
+    resources::{PublicIpFinder, PublicIps, SystemResources},
+    services::dns::DnsUpdates,
+    workers::events::EventSender,
+};
+use anyhow::Context;
+use chrono::Utc;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::{debug, error, info};
+
+pub struct PublicIpWorkerArgs {
+    pub api_client: Arc<dyn NilccApiClient>,
+    pub api_config: ApiConfig,
+    pub resources: SystemResources,
+    pub provider: Arc<dyn RepositoryProvider>,
+    pub event_sender: EventSender,
+    pub ip_finder: Box<dyn PublicIpFinder>,
+    pub dns: Option<DnsUpdates>,
+    pub public_ips: PublicIps,
+    pub check_interval: Duration,
+}
+
+/// Periodically re-detects the public IPs and re-registers with the nilcc API when it changes.
+pub struct PublicIpWorker {
+    api_client: Arc<dyn NilccApiClient>,
+    api_config: ApiConfig,
+    resources: SystemResources,
+    provider: Arc<dyn RepositoryProvider>,
+    event_sender: EventSender,
+    ip_finder: Box<dyn PublicIpFinder>,
+    dns: Option<DnsUpdates>,
+    public_ips: PublicIps,
+    dns_outdated: bool,
+    check_interval: Duration,
+}
+
+impl PublicIpWorker {
+    pub fn spawn(args: PublicIpWorkerArgs) {
+        let PublicIpWorkerArgs {
+            api_client,
+            api_config,
+            resources,
+            provider,
+            event_sender,
+            ip_finder,
+            dns,
+            public_ips,
+            check_interval,
+        } = args;
+        tokio::spawn(async move {
+            let worker = Self {
+                api_client,
+                api_config,
+                resources,
+                provider,
+                event_sender,
+                ip_finder,
+                dns,
+                public_ips,
+                dns_outdated: false,
+                check_interval,
+            };
+            worker.run().await
+        });
+    }
+
+    async fn run(mut self) {
+        loop {
+            sleep(self.check_interval).await;
+            if let Err(e) = self.run_once().await {
+                error!("Failed to process public IP: {e:#}");
+            }
+        }
+    }
+
+    async fn run_once(&mut self) -> anyhow::Result<()> {
+        let public_ips = self.ip_finder.find_public_ips().context("Failed to find public IPs")?;
+        if public_ips == self.public_ips {
+            debug!("Public IPs haven't changed");
+        } else {
+            self.handle_change(public_ips).await?;
+        }
+        if self.dns_outdated {
+            self.update_dns().await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_change(&mut self, public_ips: PublicIps) -> anyhow::Result<()> {
+        let previous_ips = self.public_ips;
+        info!("Public IPs changed from {previous_ips} to {public_ips}, re-registering");
+        // Keep advertising the previous IPs until the API knows about the new ones so this is retried on the next tick.
+        self.api_client
+            .register(&self.api_config, &self.resources, public_ips)
+            .await
+            .context("Failed to re-register with API")?;
+        self.public_ips = public_ips;
+        self.dns_outdated = self.dns.is_some();
+
+        let message = format!("Host public IPs changed from {previous_ips} to {public_ips}");
+        for workload in self.load_workloads().await? {
+            self.event_sender.send_event(workload.id, VmEvent::Warning { message: message.clone() }, Utc::now()).await;
+        }
+        Ok(())
+    }
+
+    async fn update_dns(&mut self) -> anyhow::Result<()> {
+        let Some(dns) = &self.dns else {
+            return Ok(());
+        };
+        let workloads = self.load_workloads().await?;
+        let mut domains = vec![self.api_config.domain.clone()];
+        domains.extend(workloads.into_iter().map(|w| w.domain).filter(|domain| dns.manages(domain)));
+        dns.updater.update_records(&domains, self.public_ips).await.context("Failed to update DNS records")?;
+        info!("Updated DNS records for {} domains", domains.len());
+        self.dns_outdated = false;
+        Ok(())
+    }
+
+    async fn load_workloads(&self) -> anyhow::Result<Vec<Workload>> {
+        let mut repo = self.provider.workloads(Default::default()).await?;
+        let workloads = repo.list().await?;
+        Ok(workloads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clients::nilcc_api::{MockNilccApiClient, NilccApiError},
+        repositories::{
+            sqlite::MockRepositoryProvider,
+            workload::{MockWorkloadRepository, utils::make_workload},
+        },
+        resources::MockPublicIpFinder,
+        services::dns::MockDnsRecordUpdater,
+        workers::events::WorkloadEvent,
+    };
+    use mockall::predicate::{always, eq};
+    use reqwest::StatusCode;
+    use tokio::sync::mpsc::{Receiver, channel};
+
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const CURRENT_IPS: PublicIps = PublicIps { ipv4: Some(Ipv4Addr::new(1, 1, 1, 1)), ipv6: None };
+    const NEW_IPS: PublicIps = PublicIps { ipv4: Some(Ipv4Addr::new(2, 2, 2, 2)), ipv6: None };
+
+    #[derive(Default)]
+    struct Builder {
+        api_client: MockNilccApiClient,
+        provider: MockRepositoryProvider,
+        ip_finder: MockPublicIpFinder,
+        dns_updater: Option<MockDnsRecordUpdater>,
+    }
+
+    impl Builder {
+        fn build(self) -> (PublicIpWorker, Receiver<WorkloadEvent>) {
+            let Self { api_client, provider, ip_finder, dns_updater } = self;
+            let (sender, receiver) = channel(16);
+            let api_config = ApiConfig {
+                bind_endpoint: "127.0.0.1:1337".parse().unwrap(),
+                domain: "agent.nilcc.com".into(),
+                token: "token".into(),
+                tokens: Vec::new(),
+                additional_bind_endpoints: Vec::new(),
+                unix_socket: None,
+                systemd_activation: None,
+                public_attestation: Default::default(),
+                cvm_agent_limits: Default::default(),
+                debug_console: false,
+            };
+            let resources = SystemResources {
+                hostname: "host".into(),
+                memory_mb: 1024,
+                reserved_memory_mb: 0,
+                disk_space_gb: 10,
+                reserved_disk_space_gb: 0,
+                cpus: 1,
+                reserved_cpus: 0,
+                gpus: None,
+                numa: None,
+            };
+            let worker = PublicIpWorker {
+                api_client: Arc::new(api_client),
+                api_config,
+                resources,
+                provider: Arc::new(provider),
+                event_sender: EventSender(sender),
+                ip_finder: Box::new(ip_finder),
+                dns: dns_updater.map(|updater| DnsUpdates { updater: Arc::new(updater), zone: "nilcc.com".into() }),
+                public_ips: CURRENT_IPS,
+                dns_outdated: false,
+                check_interval: Duration::from_secs(1),
+            };
+            (worker, receiver)
+        }
+
+        fn set_workloads(&mut self, workloads: Vec<Workload>) {
+            self.provider.expect_workloads().returning(move |_| {
+                let workloads = workloads.clone();
+                let mut repo = MockWorkloadRepository::default();
+                repo.expect_list().return_once(move || Ok(workloads));
+                Ok(Box::new(repo))
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn unchanged_ip() {
+        let mut builder = Builder::default();
+        builder.ip_finder.expect_find_public_ips().return_once(|| Ok(CURRENT_IPS));
+
+        let (mut worker, mut receiver) = builder.build();
+        worker.run_once().await.expect("failed to run");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn changed_ip() {
+        let mut builder = Builder::default();
+        builder.ip_finder.expect_find_public_ips().return_once(|| Ok(NEW_IPS));
+        builder.api_client.expect_register().with(always(), always(), eq(NEW_IPS)).once().return_once(|_, _, _| Ok(()));
+        let workload = Workload { domain: "foo.workloads.nilcc.com".into(), ..make_workload() };
+        builder.set_workloads(vec![workload, make_workload()]);
+
+        let mut dns_updater = MockDnsRecordUpdater::default();
+        let expected_domains = vec!["agent.nilcc.com".to_string(), "foo.workloads.nilcc.com".to_string()];
+        dns_updater
+            .expect_update_records()
+            .withf(move |domains, ips| domains == expected_domains && *ips == NEW_IPS)
+            .once()
+            .return_once(|_, _| Ok(()));
+        builder.dns_updater = Some(dns_updater);
+
+        let (mut worker, mut receiver) = builder.build();
+        worker.run_once().await.expect("failed to run");
+        assert_eq!(worker.public_ips, NEW_IPS);
+        assert!(!worker.dns_outdated);
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn register_failure() {
+        let mut builder = Builder::default();
+        builder.ip_finder.expect_find_public_ips().return_once(|| Ok(NEW_IPS));
+        builder.api_client.expect_register().return_once(|_, _, _| {
+            Err(NilccApiError::Api { status: StatusCode::BAD_GATEWAY, message: "unavailable".into() })
+        });
+
+        let (mut worker, _receiver) = builder.build();
+        worker.run_once().await.expect_err("run succeeded");
+        assert_eq!(worker.public_ips, CURRENT_IPS);
+    }
+
+    #[tokio::test]
+    async fn added_ipv6() {
+        let new_ips = PublicIps { ipv6: Some(Ipv6Addr::new(0x2a01, 0x4f8, 0, 0, 0, 0, 0, 1)), ..CURRENT_IPS };
+        let mut builder = Builder::default();
+        builder.ip_finder.expect_find_public_ips().return_once(move || Ok(new_ips));
+        builder.api_client.expect_register().with(always(), always(), eq(new_ips)).once().return_once(|_, _, _| Ok(()));
+        builder.set_workloads(vec![Workload { domain: "foo.workloads.nilcc.com".into(), ..make_workload() }]);
+
+        let (mut worker, mut receiver) = builder.build();
+        worker.run_once().await.expect("failed to run");
+        assert_eq!(worker.public_ips, new_ips);
+        assert!(receiver.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    resources::{HostReservation, OverheadSampler, OverheadTracker},
+    services::workload::WorkloadService,
+};
+use metrics::gauge;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::{debug, info};
+
+pub struct ReservationTunerArgs {
+    pub workload_service: Arc<dyn WorkloadService>,
+    pub sampler: OverheadSampler,
+    pub sample_interval: Duration,
+    pub window_samples: usize,
+    pub margin_percent: u32,
+    pub min_reservation: HostReservation,
+    pub max_reservation: HostReservation,
+}
+
+/// Periodically samples the host's overhead and adjusts the resources reserved for it.
+///
+/// The reservation is kept between the configured bounds and is only changed once a full window of samples is taken.
+pub struct ReservationTuner {
+    workload_service: Arc<dyn WorkloadService>,
+    sampler: OverheadSampler,
+    tracker: OverheadTracker,
+    sample_interval: Duration,
+    margin_percent: u32,
+    min_reservation: HostReservation,
+    max_reservation: HostReservation,
+}
+
+impl ReservationTuner {
+    pub fn spawn(args: ReservationTunerArgs) {
+        let ReservationTunerArgs {
+            workload_service,
+            sampler,
+            sample_interval,
+            window_samples,
+            margin_percent,
+            min_reservation,
+            max_reservation,
+        } = args;
+        tokio::spawn(async move {
+            let worker = Self {
+                workload_service,
+                sampler,
+                tracker: OverheadTracker::new(window_samples),
+                sample_interval,
+                margin_percent,
+                min_reservation,
+                max_reservation,
+            };
+            worker.run().await
+        });
+    }
+
+    async fn run(mut self) {
+        let mut current = self.min_reservation;
+        loop {
+            sleep(self.sample_interval).await;
+            let sample = self.sampler.sample();
+            debug!("Host overhead is {:.2} CPUs and {}MB of memory", sample.cpus, sample.memory_mb);
+            self.tracker.observe(sample);
+            if !self.tracker.is_full() {
+                continue;
+            }
+            let target = clamp(self.tracker.recommend(self.margin_percent), self.min_reservation, self.max_reservation);
+            let reservation = self.workload_service.set_host_reservation(target).await;
+            if reservation != current {
+                info!(
+                    "Host reservation changed from {} CPUs and {}MB to {} CPUs and {}MB",
+                    current.cpus, current.memory_mb, reservation.cpus, reservation.memory_mb
+                );
+                current = reservation;
+            }
+            gauge!("host_reserved_cpus").set(reservation.cpus as f64);
+            gauge!("host_reserved_memory_mb").set(reservation.memory_mb as f64);
+        }
+    }
+}
+
+fn clamp(reservation: HostReservation, min: HostReservation, max: HostReservation) -> HostReservation {
+    // `Ord::clamp` panics if min > max so don't use it here in case the bounds are misconfigured.
+    HostReservation {
+        cpus: reservation.cpus.min(max.cpus).max(min.cpus),
+        memory_mb: reservation.memory_mb.min(max.memory_mb).max(min.memory_mb),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_reservation() {
+        let min = HostReservation { cpus: 2, memory_mb: 2048 };
+        let max = HostReservation { cpus: 4, memory_mb: 8192 };
+        let clamped = clamp(HostReservation { cpus: 1, memory_mb: 10000 }, min, max);
+        assert_eq!(clamped, HostReservation { cpus: 2, memory_mb: 8192 });
+
+        let clamped = clamp(HostReservation { cpus: 3, memory_mb: 4000 }, min, max);
+        assert_eq!(clamped, HostReservation { cpus: 3, memory_mb: 4000 });
+    }
+}
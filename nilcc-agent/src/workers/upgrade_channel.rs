@@ -0,0 +1,160 @@
+use crate::{repositories::sqlite::RepositoryProvider, services::workload::WorkloadService};
+use nilcc_agent_models::workloads::create::UpgradeChannel;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+pub struct UpgradeChannelWorkerArgs {
+    pub provider: Arc<dyn RepositoryProvider>,
+    pub workload_service: Arc<dyn WorkloadService>,
+    pub artifacts_installed: Arc<Notify>,
+}
+
+/// Moves workloads that follow the `latest-stable` channel to the newest stable artifacts version.
+///
+/// This runs once on startup and then every time a new artifacts version is installed.
+pub struct UpgradeChannelWorker {
+    provider: Arc<dyn RepositoryProvider>,
+    workload_service: Arc<dyn WorkloadService>,
+    artifacts_installed: Arc<Notify>,
+}
+
+impl UpgradeChannelWorker {
+    pub fn spawn(args: UpgradeChannelWorkerArgs) {
+        let UpgradeChannelWorkerArgs { provider, workload_service, artifacts_installed } = args;
+        tokio::spawn(async move {
+            let worker = Self { provider, workload_service, artifacts_installed };
+            worker.run().await
+        });
+    }
+
+    async fn run(self) {
+        loop {
+            if let Err(e) = self.run_once().await {
+                error!("Failed to upgrade workloads: {e:#}");
+            }
+            self.artifacts_installed.notified().await;
+        }
+    }
+
+    async fn run_once(&self) -> anyhow::Result<()> {
+        let artifacts = self.provider.artifacts(Default::default()).await?.list().await?;
+        let Some((latest, latest_version)) =
+            artifacts.into_iter().filter_map(|a| Some((StableVersion::parse(&a.version)?, a.version))).max()
+        else {
+            info!("No stable artifacts versions installed");
+            return Ok(());
+        };
+        let workloads = self.provider.workloads(Default::default()).await?.list().await?;
+        for workload in workloads {
+            if workload.upgrade_channel != UpgradeChannel::LatestStable {
+                continue;
+            }
+            // Workloads on a version that isn't stable get moved to the latest stable one, but never downgrade.
+            if StableVersion::parse(&workload.artifacts_version).is_some_and(|current| current >= latest) {
+                continue;
+            }
+            let id = workload.id;
+            info!("Upgrading workload {id} to artifacts version {latest_version}");
+            if let Err(e) = self.workload_service.upgrade_artifacts(id, latest_version.clone()).await {
+                error!("Failed to upgrade workload {id} to artifacts version {latest_version}: {e}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A stable artifacts version, e.g. `0.2.1`.
+///
+/// Anything else, like release candidates or development builds, is not considered stable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct StableVersion(u32, u32, u32);
+
+impl StableVersion {
+    fn parse(version: &str) -> Option<Self> {
+        let mut parts = version.split('.').map(|p| p.parse::<u32>().ok());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => Some(Self(major, minor, patch)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        repositories::{
+            artifacts::{Artifacts, MockArtifactsRepository, utils::make_artifacts_metadata},
+            sqlite::MockRepositoryProvider,
+            workload::{MockWorkloadRepository, Workload, utils::make_workload},
+        },
+        services::workload::MockWorkloadService,
+    };
+    use mockall::predicate::eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::stable("0.2.1", Some(StableVersion(0, 2, 1)))]
+    #[case::large("10.20.300", Some(StableVersion(10, 20, 300)))]
+    #[case::release_candidate("0.3.0-rc1", None)]
+    #[case::dev("dev-1700000000", None)]
+    #[case::too_short("0.2", None)]
+    #[case::too_long("0.2.1.4", None)]
+    fn parse_version(#[case] version: &str, #[case] expected: Option<StableVersion>) {
+        assert_eq!(StableVersion::parse(version), expected);
+    }
+
+    #[tokio::test]
+    async fn upgrade_channel_workloads() {
+        let pinned =
+            Workload { artifacts_version: "0.2.0".into(), upgrade_channel: UpgradeChannel::Pinned, ..make_workload() };
+        let outdated = Workload {
+            artifacts_version: "0.2.0".into(),
+            upgrade_channel: UpgradeChannel::LatestStable,
+            ..make_workload()
+        };
+        let dev = Workload {
+            artifacts_version: "dev-1700000000".into(),
+            upgrade_channel: UpgradeChannel::LatestStable,
+            ..make_workload()
+        };
+        let up_to_date = Workload {
+            artifacts_version: "0.10.0".into(),
+            upgrade_channel: UpgradeChannel::LatestStable,
+            ..make_workload()
+        };
+        let (outdated_id, dev_id) = (outdated.id, dev.id);
+
+        let mut provider = MockRepositoryProvider::default();
+        provider.expect_artifacts().return_once(|_| {
+            let mut repo = MockArtifactsRepository::default();
+            let artifacts = ["0.2.0", "0.10.0", "0.11.0-rc1", "dev-1700000000"]
+                .into_iter()
+                .map(|version| Artifacts { version: version.into(), metadata: make_artifacts_metadata() })
+                .collect();
+            repo.expect_list().return_once(move || Ok(artifacts));
+            Ok(Box::new(repo))
+        });
+        provider.expect_workloads().return_once(move |_| {
+            let mut repo = MockWorkloadRepository::default();
+            repo.expect_list().return_once(move || Ok(vec![pinned, outdated, dev, up_to_date]));
+            Ok(Box::new(repo))
+        });
+        let mut workload_service = MockWorkloadService::default();
+        for id in [outdated_id, dev_id] {
+            workload_service
+                .expect_upgrade_artifacts()
+                .with(eq(id), eq("0.10.0".to_string()))
+                .once()
+                .return_once(|_, _| Ok(()));
+        }
+
+        let worker = UpgradeChannelWorker {
+            provider: Arc::new(provider),
+            workload_service: Arc::new(workload_service),
+            artifacts_installed: Default::default(),
+        };
+        worker.run_once().await.expect("failed to run");
+    }
+}
@@ -0,0 +1,103 @@
+use crate::repositories::{sqlite::RepositoryProvider, usage::UsageSample};
+use chrono::{DateTime, Utc};
+use std::{sync::Arc, time::Duration};
+use tokio::time::{Instant, sleep};
+use tracing::{debug, error};
+
+pub struct UsageSamplerArgs {
+    pub provider: Arc<dyn RepositoryProvider>,
+    pub sample_interval: Duration,
+}
+
+/// Periodically records the resources allocated to every running workload.
+pub struct UsageSampler {
+    provider: Arc<dyn RepositoryProvider>,
+    sample_interval: Duration,
+}
+
+impl UsageSampler {
+    pub fn spawn(args: UsageSamplerArgs) {
+        let UsageSamplerArgs { provider, sample_interval } = args;
+        tokio::spawn(async move {
+            let worker = Self { provider, sample_interval };
+            worker.run().await
+        });
+    }
+
+    async fn run(self) {
+        let mut last_sample = Instant::now();
+        loop {
+            sleep(self.sample_interval).await;
+            // Use the actual elapsed time so a slow sample doesn't skew the totals.
+            let now = Instant::now();
+            let elapsed = now - last_sample;
+            last_sample = now;
+            if let Err(e) = self.run_once(Utc::now(), elapsed).await {
+                error!("Failed to sample workload usage: {e:#}");
+            }
+        }
+    }
+
+    async fn run_once(&self, sampled_at: DateTime<Utc>, elapsed: Duration) -> anyhow::Result<()> {
+        let workloads = self.provider.workloads(Default::default()).await?.list().await?;
+        let mut repo = self.provider.usage(Default::default()).await?;
+        let duration_seconds = elapsed.as_secs().try_into().unwrap_or(u32::MAX);
+        for workload in workloads.into_iter().filter(|w| w.enabled) {
+            let sample = UsageSample {
+                workload_id: workload.id,
+                sampled_at,
+                duration_seconds,
+                cpus: workload.cpus,
+                gpus: workload.gpus.len() as u32,
+                memory_mb: workload.memory_mb,
+                disk_space_gb: workload.disk_space_gb,
+            };
+            repo.insert(&sample).await?;
+        }
+        debug!("Sampled workload usage over the last {elapsed:?}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::{
+        sqlite::MockRepositoryProvider,
+        usage::MockUsageRepository,
+        workload::{MockWorkloadRepository, Workload, utils::make_workload},
+    };
+    use mockall::predicate::eq;
+
+    #[tokio::test]
+    async fn sample_running_workloads() {
+        let running =
+            Workload { memory_mb: 2048, cpus: 2, disk_space_gb: 10, gpus: vec!["addr1".into()], ..make_workload() };
+        let stopped = Workload { enabled: false, ..make_workload() };
+        let sampled_at = Utc::now();
+        let expected = UsageSample {
+            workload_id: running.id,
+            sampled_at,
+            duration_seconds: 60,
+            cpus: 2,
+            gpus: 1,
+            memory_mb: 2048,
+            disk_space_gb: 10,
+        };
+
+        let mut provider = MockRepositoryProvider::default();
+        provider.expect_workloads().return_once(move |_| {
+            let mut repo = MockWorkloadRepository::default();
+            repo.expect_list().return_once(move || Ok(vec![running, stopped]));
+            Ok(Box::new(repo))
+        });
+        provider.expect_usage().return_once(move |_| {
+            let mut repo = MockUsageRepository::default();
+            repo.expect_insert().with(eq(expected)).once().return_once(|_| Ok(()));
+            Ok(Box::new(repo))
+        });
+
+        let worker = UsageSampler { provider: Arc::new(provider), sample_interval: Duration::from_secs(60) };
+        worker.run_once(sampled_at, Duration::from_secs(60)).await.expect("failed to sample");
+    }
+}
@@ -1,49 +1,67 @@
 use crate::{
     clients::{
-        cvm_agent::CvmAgentClient,
+        cvm_agent::{CvmAgentClient, CvmAgentRequestError},
         nilcc_api::VmEvent,
         qemu::{QemuClientError, VmClient, VmSpec},
     },
-    config::ZeroSslConfig,
     heartbeat_verifier::VerifierKey,
+    services::{disk::DiskService, progress::ProvisioningTracker},
     workers::events::EventSender,
+    zerossl::ZeroSslAccount,
 };
+use attestation_report::platform_claims::SignedPlatformClaims;
 use chrono::Utc;
 use cvm_agent_models::{
-    bootstrap::{AcmeCredentials, BootstrapRequest, DockerCredentials, HeartbeatConfig},
-    health::{EventKind, LastEvent},
+    bootstrap::{
+        BootstrapRequest, BootstrapStatus, BootstrapStep, DockerCredentials, HeartbeatConfig, LogRotationConfig,
+        PrivatePki, TimeSyncConfig,
+    },
+    config::{DockerCredentialsConfigRequest, DomainsConfigRequest},
+    health::{EventKind, HealthResponse, LastEvent},
 };
 use metrics::{counter, gauge};
+use nilcc_agent_models::workloads::progress::ProvisioningStep;
 use std::{path::PathBuf, sync::Arc, time::Duration};
 use strum::EnumDiscriminants;
 use tokio::{
     fs, select,
     sync::mpsc::{Receiver, Sender, channel},
     task::JoinHandle,
-    time::{MissedTickBehavior, interval},
+    time::{Instant, MissedTickBehavior, interval},
 };
 use tracing::{Instrument, error, info, info_span, warn};
 use uuid::Uuid;
 
 const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+const BOOTSTRAP_RETRY_INTERVAL: Duration = Duration::from_secs(60);
 
 pub(crate) struct VmWorkerArgs {
     pub(crate) workload_id: Uuid,
+    pub(crate) agent_id: Uuid,
     pub(crate) vm_client: Arc<dyn VmClient>,
     pub(crate) cvm_agent_client: Arc<dyn CvmAgentClient>,
     pub(crate) cvm_agent_port: u16,
     pub(crate) spec: VmSpec,
     pub(crate) socket_path: PathBuf,
-    pub(crate) zerossl_config: ZeroSslConfig,
+    pub(crate) zerossl_account: ZeroSslAccount,
     pub(crate) docker_credentials: Vec<DockerCredentials>,
     pub(crate) event_sender: EventSender,
     pub(crate) domain: String,
     pub(crate) verifier_heartbeat: Option<HeartbeatConfig>,
     pub(crate) verifier_heartbeat_key: Option<VerifierKey>,
+    pub(crate) log_rotation: Option<LogRotationConfig>,
+    pub(crate) time_sync: Option<TimeSyncConfig>,
+    pub(crate) private_pki: Option<PrivatePki>,
+    pub(crate) platform_claims: Option<SignedPlatformClaims>,
+    pub(crate) registry_mirrors: Vec<String>,
+    pub(crate) paused: bool,
+    pub(crate) progress: ProvisioningTracker,
+    pub(crate) disk_service: Arc<dyn DiskService>,
 }
 
 pub(crate) struct VmWorker {
     workload_id: Uuid,
+    agent_id: Uuid,
     vm_client: Arc<dyn VmClient>,
     cvm_agent_client: Arc<dyn CvmAgentClient>,
     cvm_agent_port: u16,
@@ -51,36 +69,58 @@ pub(crate) struct VmWorker {
     socket_path: PathBuf,
     receiver: Receiver<WorkerCommand>,
     vm_state: VmState,
-    zerossl_config: ZeroSslConfig,
+    zerossl_account: ZeroSslAccount,
     docker_credentials: Vec<DockerCredentials>,
+    docker_credentials_outdated: bool,
     domain: String,
+    retiring_domains: Vec<String>,
+    domains_outdated: bool,
     event_sender: EventSender,
     verifier_heartbeat: Option<HeartbeatConfig>,
     #[allow(dead_code)] // need to keep it alive so it doesn't go back to the pool
     verifier_heartbeat_key: Option<VerifierKey>,
+    log_rotation: Option<LogRotationConfig>,
+    time_sync: Option<TimeSyncConfig>,
+    private_pki: Option<PrivatePki>,
+    platform_claims: Option<SignedPlatformClaims>,
+    registry_mirrors: Vec<String>,
     last_event_id: Option<u64>,
+    last_bootstrap_attempt: Option<Instant>,
+    paused: bool,
+    progress: ProvisioningTracker,
+    disk_service: Arc<dyn DiskService>,
 }
 
 impl VmWorker {
     pub(crate) fn spawn(args: VmWorkerArgs) -> VmWorkerHandle {
         let VmWorkerArgs {
             workload_id,
+            agent_id,
             vm_client,
             spec,
             socket_path,
             cvm_agent_client,
             cvm_agent_port,
-            zerossl_config,
+            zerossl_account,
             docker_credentials,
             event_sender,
             domain,
             verifier_heartbeat,
             verifier_heartbeat_key,
+            log_rotation,
+            time_sync,
+            private_pki,
+            platform_claims,
+            registry_mirrors,
+            paused,
+            progress,
+            disk_service,
         } = args;
         let (sender, receiver) = channel(64);
         let join_handle = tokio::spawn(async move {
             let worker = VmWorker {
                 workload_id,
+                agent_id,
                 vm_client,
                 cvm_agent_client,
                 cvm_agent_port,
@@ -88,13 +128,25 @@ impl VmWorker {
                 socket_path,
                 receiver,
                 vm_state: Default::default(),
-                zerossl_config,
+                zerossl_account,
                 docker_credentials,
+                docker_credentials_outdated: false,
                 event_sender,
                 domain,
+                retiring_domains: Vec::new(),
+                domains_outdated: false,
                 verifier_heartbeat,
                 verifier_heartbeat_key,
+                log_rotation,
+                time_sync,
+                private_pki,
+                platform_claims,
+                registry_mirrors,
                 last_event_id: None,
+                last_bootstrap_attempt: None,
+                paused,
+                progress,
+                disk_service,
             };
             worker.run().instrument(info_span!("vm_worker", workload_id = workload_id.to_string())).await;
         });
@@ -137,17 +189,24 @@ impl VmWorker {
                 gauge!("vms_running_total").increment(1);
                 self.submit_event(VmEvent::Starting).await;
                 self.vm_state = VmState::Starting;
+                self.progress.record(self.workload_id, ProvisioningStep::VmStarted);
             }
             Err(QemuClientError::VmAlreadyRunning) => {
                 info!("VM was already running, ignoring");
                 self.vm_state = VmState::Starting;
+                self.progress.record(self.workload_id, ProvisioningStep::VmStarted);
             }
             Err(e) => {
                 error!("Failed to start VM: {e}");
                 counter!("vm_start_errors_total").increment(1);
                 self.submit_event(VmEvent::FailedToStart { error: e.to_string() }).await;
+                return;
             }
         }
+        // A paused workload stays paused until it's explicitly resumed, even if its VM is started again.
+        if self.paused {
+            self.pause_vm().await;
+        }
     }
 
     async fn delete_vm(&mut self) {
@@ -168,8 +227,8 @@ impl VmWorker {
         for path in paths {
             let disk_display = path.display();
             info!("Deleting disk {disk_display}");
-            if let Err(e) = fs::remove_file(&path).await {
-                error!("Failed to delete disk {disk_display}: {e}");
+            if let Err(e) = self.disk_service.delete_disk(path).await {
+                error!("Failed to delete disk {disk_display}: {e:#}");
             }
         }
         self.submit_event(VmEvent::Stopped).await;
@@ -197,6 +256,28 @@ impl VmWorker {
         }
     }
 
+    async fn pause_vm(&mut self) {
+        info!("Pausing VM");
+        match self.vm_client.pause_vm(&self.socket_path).await {
+            Ok(()) => self.paused = true,
+            Err(e) => {
+                counter!("vm_action_errors_total", "action" => "pause").increment(1);
+                error!("Failed to pause VM: {e}");
+            }
+        }
+    }
+
+    async fn resume_vm(&mut self) {
+        info!("Resuming VM");
+        match self.vm_client.resume_vm(&self.socket_path).await {
+            Ok(()) => self.paused = false,
+            Err(e) => {
+                counter!("vm_action_errors_total", "action" => "resume").increment(1);
+                error!("Failed to resume VM: {e}");
+            }
+        }
+    }
+
     async fn handle_tick(&mut self) {
         if !self.vm_client.is_vm_running(&self.socket_path).await {
             warn!("VM is no longer running, starting it again");
@@ -204,27 +285,42 @@ impl VmWorker {
             self.start_vm().await;
             return;
         }
+        if self.paused {
+            // The CVM agent can't respond while the VM's vCPUs are frozen.
+            return;
+        }
 
         if !matches!(self.vm_state, VmState::Running) {
             info!("Checking health of CVM agent");
             match self.cvm_agent_client.check_health(self.cvm_agent_port).await {
                 Ok(response) => {
-                    if !response.bootstrapped {
+                    self.track_bootstrap(&response);
+                    if self.needs_bootstrap(&response) {
                         info!("CVM agent is running, bootstrapping it");
+                        self.last_bootstrap_attempt = Some(Instant::now());
                         let request = BootstrapRequest {
-                            acme: AcmeCredentials {
-                                eab_key_id: self.zerossl_config.eab_key_id.clone(),
-                                eab_mac_key: self.zerossl_config.eab_mac_key.clone(),
-                            },
+                            acme: self.zerossl_account.credentials(),
                             docker: self.docker_credentials.clone(),
                             domain: self.domain.clone(),
                             workload_id: Some(self.workload_id),
+                            agent_id: Some(self.agent_id),
                             heartbeat: self.verifier_heartbeat.clone(),
+                            log_rotation: self.log_rotation,
+                            time_sync: self.time_sync.clone(),
+                            private_pki: self.private_pki.clone(),
+                            registry_mirrors: self.registry_mirrors.clone(),
+                            platform_claims: self
+                                .platform_claims
+                                .as_ref()
+                                .map(|claims| serde_json::to_string(claims).expect("failed to serialize")),
                         };
                         if let Err(e) = self.cvm_agent_client.bootstrap(self.cvm_agent_port, &request).await {
                             warn!("Failed to bootstrap agent: {e:#}");
                             return;
                         }
+                        if self.private_pki.is_none() {
+                            self.zerossl_account.record_certificate_request();
+                        }
                         self.submit_event(VmEvent::AwaitingCert).await;
                         info!("CVM agent is bootstrapped");
                     }
@@ -232,6 +328,7 @@ impl VmWorker {
                         info!("CVM's https endpoint is functional");
                         self.vm_state = VmState::Running;
                         self.submit_event(VmEvent::Running).await;
+                        self.progress.record(self.workload_id, ProvisioningStep::CertificateIssued);
                     }
                     if let Some(last_event) = response.last_event {
                         let LastEvent { id, kind, message, timestamp } = last_event;
@@ -252,6 +349,118 @@ impl VmWorker {
                 }
             }
         }
+        if matches!(self.vm_state, VmState::Running)
+            && !self.progress.reached(self.workload_id, ProvisioningStep::Ready)
+        {
+            self.check_ready().await;
+        }
+        if matches!(self.vm_state, VmState::Running) && self.domains_outdated {
+            self.push_domains().await;
+        }
+        if matches!(self.vm_state, VmState::Running) && self.docker_credentials_outdated {
+            self.push_docker_credentials().await;
+        }
+    }
+
+    fn track_bootstrap(&self, response: &HealthResponse) {
+        if response.bootstrapped {
+            self.progress.record(self.workload_id, ProvisioningStep::CvmBootstrapped);
+        }
+        if let Some(BootstrapStatus { step, running: false, error: Some(error) }) = &response.bootstrap {
+            self.progress.fail(self.workload_id, format!("bootstrap failed at step {step:?}: {error}"));
+        }
+    }
+
+    async fn check_ready(&self) {
+        match self.cvm_agent_client.compose_state(self.cvm_agent_port).await {
+            Ok(state) if state.converged => {
+                info!("All services converged, workload is ready");
+                self.progress.record(self.workload_id, ProvisioningStep::Ready);
+            }
+            Ok(_) => info!("Waiting for services to converge"),
+            Err(e) => warn!("Failed to check compose state: {e:#}"),
+        }
+    }
+
+    async fn push_domains(&mut self) {
+        let domains: Vec<_> = [self.domain.clone()].into_iter().chain(self.retiring_domains.iter().cloned()).collect();
+        info!("Updating CVM domains to {domains:?}");
+        let request = DomainsConfigRequest { domains };
+        match self.cvm_agent_client.set_domains_config(self.cvm_agent_port, &request).await {
+            Ok(()) => self.domains_outdated = false,
+            Err(e) => warn!("Failed to update CVM domains: {e:#}"),
+        }
+    }
+
+    async fn push_docker_credentials(&mut self) {
+        info!("Updating CVM docker credentials");
+        let request = DockerCredentialsConfigRequest { credentials: self.docker_credentials.clone() };
+        match self.cvm_agent_client.set_docker_credentials(self.cvm_agent_port, &request).await {
+            Ok(()) => self.docker_credentials_outdated = false,
+            Err(CvmAgentRequestError::Http(e)) if e.status().is_some_and(|s| s.is_client_error()) => {
+                // Retrying won't help if a registry rejected the credentials.
+                warn!("CVM rejected docker credentials: {e}");
+                self.docker_credentials_outdated = false;
+                let message = "CVM failed to log in to docker registries using the updated credentials".into();
+                self.submit_event(VmEvent::Warning { message }).await;
+            }
+            Err(e) => warn!("Failed to update CVM docker credentials: {e:#}"),
+        }
+    }
+
+    fn update_docker_credentials(&mut self, credentials: Vec<DockerCredentials>) {
+        // These are also used if the CVM needs to be bootstrapped again.
+        self.docker_credentials = credentials;
+        self.docker_credentials_outdated = true;
+    }
+
+    fn change_domain(&mut self, domain: String) {
+        if domain == self.domain {
+            return;
+        }
+        // Keep serving the previous domain until it's explicitly retired.
+        let previous_domain = std::mem::replace(&mut self.domain, domain);
+        self.retiring_domains.retain(|d| d != &self.domain);
+        self.retiring_domains.push(previous_domain);
+        self.domains_outdated = true;
+    }
+
+    fn retire_domain(&mut self, domain: String) {
+        let length = self.retiring_domains.len();
+        self.retiring_domains.retain(|d| d != &domain);
+        self.domains_outdated |= self.retiring_domains.len() != length;
+    }
+
+    async fn rotate_heartbeat_key(&mut self, key: VerifierKey) {
+        let Some(heartbeat) = &mut self.verifier_heartbeat else {
+            warn!("Not rotating heartbeat key because heartbeats are not enabled");
+            return;
+        };
+        info!("Rotating heartbeat key to {}", hex::encode(key.public_key()));
+        heartbeat.wallet_private_key = key.secret_key().to_vec();
+        // The previous key is released here. The CVM only picks up the new one when it's bootstrapped again.
+        self.verifier_heartbeat_key = Some(key);
+        self.restart_vm().await;
+    }
+
+    fn needs_bootstrap(&self, response: &HealthResponse) -> bool {
+        // Older cvm-agent versions don't report their bootstrap status and can't resume a failed bootstrap.
+        let Some(status) = &response.bootstrap else {
+            return !response.bootstrapped;
+        };
+        if status.running || status.step == BootstrapStep::Completed {
+            return false;
+        }
+        match &status.error {
+            Some(error) => {
+                let retry = self.last_bootstrap_attempt.is_none_or(|last| last.elapsed() >= BOOTSTRAP_RETRY_INTERVAL);
+                if retry {
+                    info!("CVM bootstrap failed at step {:?} ({error}), resuming it", status.step);
+                }
+                retry
+            }
+            None => true,
+        }
     }
 
     async fn handle_command(&mut self, command: WorkerCommand) {
@@ -260,6 +469,19 @@ impl VmWorker {
         match command {
             WorkerCommand::Delete => self.delete_vm().await,
             WorkerCommand::Restart => self.restart_vm().await,
+            WorkerCommand::ChangeDomain(domain) => self.change_domain(domain),
+            WorkerCommand::RetireDomain(domain) => self.retire_domain(domain),
+            WorkerCommand::RotateHeartbeatKey(key) => self.rotate_heartbeat_key(key).await,
+            WorkerCommand::UpdateDockerCredentials(credentials) => self.update_docker_credentials(credentials),
+            WorkerCommand::Pause => self.pause_vm().await,
+            WorkerCommand::Resume => self.resume_vm().await,
+        }
+        // The CVM agent can't respond while the VM's vCPUs are frozen, so these are pushed once it's resumed.
+        if matches!(self.vm_state, VmState::Running) && !self.paused && self.domains_outdated {
+            self.push_domains().await;
+        }
+        if matches!(self.vm_state, VmState::Running) && !self.paused && self.docker_credentials_outdated {
+            self.push_docker_credentials().await;
         }
     }
 
@@ -293,6 +515,30 @@ impl VmWorkerHandle {
         self.send_command(WorkerCommand::Restart).await;
     }
 
+    pub(crate) async fn change_domain(&self, domain: String) {
+        self.send_command(WorkerCommand::ChangeDomain(domain)).await;
+    }
+
+    pub(crate) async fn retire_domain(&self, domain: String) {
+        self.send_command(WorkerCommand::RetireDomain(domain)).await;
+    }
+
+    pub(crate) async fn rotate_heartbeat_key(&self, key: VerifierKey) {
+        self.send_command(WorkerCommand::RotateHeartbeatKey(key)).await;
+    }
+
+    pub(crate) async fn update_docker_credentials(&self, credentials: Vec<DockerCredentials>) {
+        self.send_command(WorkerCommand::UpdateDockerCredentials(credentials)).await;
+    }
+
+    pub(crate) async fn pause_vm(&self) {
+        self.send_command(WorkerCommand::Pause).await;
+    }
+
+    pub(crate) async fn resume_vm(&self) {
+        self.send_command(WorkerCommand::Resume).await;
+    }
+
     async fn send_command(&self, command: WorkerCommand) {
         if self.sender.send(command).await.is_err() {
             error!("Worker receiver dropped");
@@ -304,4 +550,10 @@ impl VmWorkerHandle {
 enum WorkerCommand {
     Delete,
     Restart,
+    ChangeDomain(String),
+    RetireDomain(String),
+    RotateHeartbeatKey(VerifierKey),
+    UpdateDockerCredentials(Vec<DockerCredentials>),
+    Pause,
+    Resume,
 }
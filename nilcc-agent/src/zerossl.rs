@@ -0,0 +1,127 @@
+use crate::config::{ZeroSslAccountConfig, ZeroSslConfig};
+use cvm_agent_models::bootstrap::AcmeCredentials;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+/// A ZeroSSL account that workloads can be bound to.
+#[derive(Clone, Debug)]
+pub struct ZeroSslAccount {
+    eab_key_id: String,
+    eab_mac_key: String,
+    certificate_requests: Arc<AtomicU64>,
+}
+
+impl ZeroSslAccount {
+    /// The EAB key id that identifies this account.
+    pub fn eab_key_id(&self) -> &str {
+        &self.eab_key_id
+    }
+
+    /// The ACME credentials for this account.
+    pub fn credentials(&self) -> AcmeCredentials {
+        AcmeCredentials { eab_key_id: self.eab_key_id.clone(), eab_mac_key: self.eab_mac_key.clone() }
+    }
+
+    /// Record that a CVM was handed off this account's credentials to request a certificate.
+    pub fn record_certificate_request(&self) {
+        self.certificate_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of certificates requested using this account since the agent started.
+    pub fn certificate_requests(&self) -> u64 {
+        self.certificate_requests.load(Ordering::Relaxed)
+    }
+}
+
+/// The pool of ZeroSSL accounts workloads are assigned to in a round-robin fashion.
+///
+/// The account in the top level of the ZeroSSL config is always the first one in the pool and is used for any workload
+/// that isn't bound to any account, or whose account is no longer configured.
+#[derive(Clone, Debug)]
+pub struct ZeroSslAccounts {
+    accounts: Arc<Vec<ZeroSslAccount>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl ZeroSslAccounts {
+    pub fn new(config: ZeroSslConfig) -> Self {
+        let ZeroSslConfig { eab_key_id, eab_mac_key, pool } = config;
+        let mut accounts: Vec<ZeroSslAccount> = Vec::new();
+        for ZeroSslAccountConfig { eab_key_id, eab_mac_key } in
+            [ZeroSslAccountConfig { eab_key_id, eab_mac_key }].into_iter().chain(pool)
+        {
+            if accounts.iter().any(|a| a.eab_key_id == eab_key_id) {
+                continue;
+            }
+            accounts.push(ZeroSslAccount { eab_key_id, eab_mac_key, certificate_requests: Default::default() });
+        }
+        Self { accounts: accounts.into(), next: Default::default() }
+    }
+
+    /// Assign the next account in the pool, returning its key id.
+    pub fn assign(&self) -> String {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.accounts.len();
+        self.accounts[index].eab_key_id.clone()
+    }
+
+    /// Find the account with the given key id.
+    pub fn find(&self, eab_key_id: &str) -> Option<&ZeroSslAccount> {
+        self.accounts.iter().find(|a| a.eab_key_id == eab_key_id)
+    }
+
+    /// Resolve the account a workload is bound to, falling back to the primary one.
+    pub fn resolve(&self, eab_key_id: Option<&str>) -> &ZeroSslAccount {
+        eab_key_id.and_then(|id| self.find(id)).unwrap_or(self.primary())
+    }
+
+    /// The primary account.
+    pub fn primary(&self) -> &ZeroSslAccount {
+        &self.accounts[0]
+    }
+
+    /// All accounts in the pool.
+    pub fn accounts(&self) -> &[ZeroSslAccount] {
+        &self.accounts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_accounts() -> ZeroSslAccounts {
+        let account = |id: &str| ZeroSslAccountConfig { eab_key_id: id.into(), eab_mac_key: format!("{id}-mac") };
+        ZeroSslAccounts::new(ZeroSslConfig {
+            eab_key_id: "a".into(),
+            eab_mac_key: "a-mac".into(),
+            pool: vec![account("b"), account("a"), account("c")],
+        })
+    }
+
+    #[test]
+    fn round_robin() {
+        let accounts = make_accounts();
+        let assigned: Vec<_> = (0..4).map(|_| accounts.assign()).collect();
+        assert_eq!(assigned, &["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn resolve() {
+        let accounts = make_accounts();
+        assert_eq!(accounts.resolve(Some("c")).eab_key_id(), "c");
+        assert_eq!(accounts.resolve(Some("removed")).eab_key_id(), "a");
+        assert_eq!(accounts.resolve(None).eab_key_id(), "a");
+    }
+
+    #[test]
+    fn certificate_requests() {
+        let accounts = make_accounts();
+        let account = accounts.resolve(Some("b"));
+        assert_eq!(account.credentials().eab_mac_key, "b-mac");
+        account.record_certificate_request();
+        let requests: Vec<_> = accounts.accounts().iter().map(|a| a.certificate_requests()).collect();
+        assert_eq!(requests, &[0, 1, 0]);
+    }
+}
@@ -0,0 +1,27 @@
+import type { MigrationInterface, QueryRunner } from "typeorm";
+
+export class MetalInstanceIpv61776000000000 implements MigrationInterface {
+  name = "MetalInstanceIpv61776000000000";
+
+  public async up(queryRunner: QueryRunner): Promise<void> {
+    await queryRunner.query(`
+      ALTER TABLE metal_instances
+      ALTER COLUMN public_ip DROP NOT NULL
+    `);
+    await queryRunner.query(`
+      ALTER TABLE metal_instances
+      ADD COLUMN public_ipv6 VARCHAR
+    `);
+  }
+
+  public async down(queryRunner: QueryRunner): Promise<void> {
+    await queryRunner.query(`
+      ALTER TABLE metal_instances
+      DROP COLUMN public_ipv6
+    `);
+    await queryRunner.query(`
+      ALTER TABLE metal_instances
+      ALTER COLUMN public_ip SET NOT NULL
+    `);
+  }
+}
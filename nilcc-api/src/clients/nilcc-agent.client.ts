@@ -15,6 +15,7 @@ const ALLOWED_CREATE_WORKLOAD_ERRORS: string[] = [
   "MALFORMED_REQUEST",
   "RESOURCE_LIMIT",
   "RESERVED_ENVIRONMENT_VARIABLE",
+  "VULNERABLE_IMAGE",
 ];
 
 export interface NilccAgentClient {
@@ -78,7 +79,7 @@ export class DefaultNilccAgentClient implements NilccAgentClient {
 
   makeUrl(metalInstance: MetalInstanceEntity, path: string) {
     const host = this.subdomain.endsWith(".localhost")
-      ? metalInstance.publicIp
+      ? (metalInstance.publicIp ?? `[${metalInstance.publicIpv6}]`)
       : `${metalInstance.id}.${this.subdomain}`;
     return `${this.scheme}://${host}:${this.port}${path}`;
   }
@@ -354,7 +355,7 @@ export const SystemLogsRequest = z.object({
       "Whether to get logs from the tail of the log instead of the head.",
   }),
   source: z
-    .enum(["cvm-agent"])
+    .enum(["cvm-agent", "proxy", "kernel", "docker-daemon"])
     .default("cvm-agent")
     .openapi({ description: "The source to get logs from." }),
   maxLines: z
@@ -403,6 +404,63 @@ export const SystemStatsResponse = z.object({
     })
     .openapi({ description: "Disk stats." })
     .array(),
+  logDiskUsage: z
+    .number()
+    .optional()
+    .openapi({
+      description: "The disk space used by container logs, in bytes.",
+    }),
+  clockSkew: z
+    .object({
+      skewMs: z.number().openapi({
+        description:
+          "How far ahead the CVM's clock is, in milliseconds. Negative if it's behind.",
+      }),
+      uncertaintyMs: z.number().openapi({
+        description: "The uncertainty of the measurement, in milliseconds.",
+      }),
+      servers: z.number().openapi({
+        description: "The number of servers that were successfully queried.",
+      }),
+      measuredAt: z.string().openapi({
+        description: "When the skew was measured.",
+      }),
+    })
+    .optional()
+    .openapi({
+      description:
+        "The skew of the CVM's clock against trusted time, if time synchronization is enabled.",
+    }),
+  gpus: z
+    .object({
+      ccMode: z.string().nullish().openapi({
+        description:
+          "The confidential computing mode the GPUs are in, if it could be determined.",
+      }),
+      devices: z
+        .object({
+          index: z.number().openapi({ description: "The GPU index." }),
+          name: z.string().openapi({ description: "The GPU name." }),
+          utilization: z
+            .number()
+            .openapi({ description: "The GPU utilization, as a percentage." }),
+          memoryUsed: z
+            .number()
+            .openapi({ description: "The used memory, in bytes." }),
+          memoryTotal: z
+            .number()
+            .openapi({ description: "The total memory, in bytes." }),
+          temperature: z
+            .number()
+            .openapi({ description: "The temperature, in degrees celsius." }),
+        })
+        .openapi({ description: "GPU stats." })
+        .array(),
+    })
+    .nullish()
+    .openapi({
+      description: "Stats about the GPUs, if the CVM has any.",
+    }),
 });
 export type SystemStatsResponse = z.infer<typeof SystemStatsResponse>;
 
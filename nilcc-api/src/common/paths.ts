@@ -45,6 +45,9 @@ export const PathsV1 = {
     restart: PathSchema.parse("/api/v1/workloads/restart"),
     start: PathSchema.parse("/api/v1/workloads/start"),
     stop: PathSchema.parse("/api/v1/workloads/stop"),
+    listByAccount: PathSchema.parse("/api/v1/workloads/account/:accountId"),
+    placement: PathSchema.parse("/api/v1/workloads/:id/placement"),
+    reschedule: PathSchema.parse("/api/v1/workloads/reschedule"),
   },
   workloadContainers: {
     list: PathSchema.parse("/api/v1/workload-containers/list"),
@@ -29,6 +29,7 @@ import { ApiKeys1772193140110 } from "migrations/1772193140110-ApiKeys";
 import { WalletAuth1773000000000 } from "migrations/1773000000000-WalletAuth";
 import { UsdBasedPricing1774000000000 } from "migrations/1774000000000-UsdBasedPricing";
 import { ApiKeyIdVarchar1775000000000 } from "migrations/1775000000000-ApiKeyIdVarchar";
+import { MetalInstanceIpv61776000000000 } from "migrations/1776000000000-MetalInstanceIpv6";
 import { DataSource } from "typeorm";
 import { ApiKeyEntity } from "#/api-key/api-key.entity";
 import { NonceEntity } from "#/auth/nonce.entity";
@@ -82,6 +83,7 @@ export async function buildDataSource(config: EnvVars): Promise<DataSource> {
       WalletAuth1773000000000,
       UsdBasedPricing1774000000000,
       ApiKeyIdVarchar1775000000000,
+      MetalInstanceIpv61776000000000,
     ],
     synchronize: false,
     logging: false,
@@ -11,7 +11,8 @@ export const RegisterMetalInstanceRequest = z
   .object({
     metalInstanceId: Uuid,
     agentVersion: z.string().min(1, "Agent version is required"),
-    publicIp: z.string().ip({ version: "v4" }),
+    publicIp: z.string().ip({ version: "v4" }).optional(),
+    publicIpv6: z.string().ip({ version: "v6" }).optional(),
     token: z.string(),
     hostname: z.string().min(1, "hostname is required"),
     memoryMb: Resource,
@@ -20,6 +21,9 @@ export const RegisterMetalInstanceRequest = z
     gpus: z.number().nonnegative(),
     gpuModel: z.string().optional(),
   })
+  .refine((v) => v.publicIp !== undefined || v.publicIpv6 !== undefined, {
+    message: "at least one public IP must be provided",
+  })
   .openapi({ ref: "RegisterMetalInstanceRequest" });
 export type RegisterMetalInstanceRequest = z.infer<
   typeof RegisterMetalInstanceRequest
@@ -29,6 +33,14 @@ export const HeartbeatRequest = z
   .object({
     metalInstanceId: Uuid,
     availableArtifactVersions: z.string().array(),
+    workloads: z
+      .object({
+        workloadId: Uuid,
+        labels: z.record(z.string(), z.string()),
+        isolated: z.boolean().optional(),
+      })
+      .array()
+      .optional(),
   })
   .openapi({ ref: "HeartbeatRequest" });
 export type HeartbeatRequest = z.infer<typeof HeartbeatRequest>;
@@ -48,7 +60,8 @@ export const GetMetalInstanceResponse = z
     hostname: z.string(),
     domain: z.string(),
     token: z.string(),
-    publicIp: z.string(),
+    publicIp: z.string().optional(),
+    publicIpv6: z.string().optional(),
     memoryMb: Resource,
     cpus: Resource,
     diskSpaceGb: Resource,
@@ -10,8 +10,11 @@ export class MetalInstanceEntity {
   @Column({ type: "varchar" })
   hostname: string;
 
-  @Column({ type: "varchar" })
-  publicIp: string;
+  @Column({ type: "varchar", nullable: true })
+  publicIp?: string;
+
+  @Column({ type: "varchar", nullable: true })
+  publicIpv6?: string;
 
   @Column({ type: "varchar" })
   token: string;
@@ -11,7 +11,8 @@ export const metalInstanceMapper = {
       hostname: metalInstance.hostname,
       domain: `${metalInstance.id}.${subdomain}`,
       token: metalInstance.token,
-      publicIp: metalInstance.publicIp,
+      publicIp: metalInstance.publicIp ?? undefined,
+      publicIpv6: metalInstance.publicIpv6 ?? undefined,
       memoryMb: {
         total: metalInstance.totalMemory,
         reserved: metalInstance.reservedMemory,
@@ -189,6 +189,8 @@ export class MetalInstanceService {
     currentMetalInstance.agentVersion = metalInstance.agentVersion;
     currentMetalInstance.hostname = metalInstance.hostname;
     currentMetalInstance.token = metalInstance.token;
+    currentMetalInstance.publicIp = metalInstance.publicIp;
+    currentMetalInstance.publicIpv6 = metalInstance.publicIpv6;
 
     currentMetalInstance.totalCpus = metalInstance.cpus.total;
     currentMetalInstance.reservedCpus = metalInstance.cpus.reserved;
@@ -218,6 +220,7 @@ export class MetalInstanceService {
       agentVersion: request.agentVersion,
       token: request.token,
       publicIp: request.publicIp,
+      publicIpv6: request.publicIpv6,
       hostname: request.hostname,
       totalCpus: request.cpus.total,
       reservedCpus: request.cpus.reserved,
@@ -231,11 +234,20 @@ export class MetalInstanceService {
       updatedAt: now,
       lastSeenAt: now,
     });
-    bindings.services.dns.metalInstances.createRecord(
-      request.metalInstanceId,
-      request.publicIp,
-      "A",
-    );
+    if (request.publicIp) {
+      bindings.services.dns.metalInstances.createRecord(
+        request.metalInstanceId,
+        request.publicIp,
+        "A",
+      );
+    }
+    if (request.publicIpv6) {
+      bindings.services.dns.metalInstances.createRecord(
+        request.metalInstanceId,
+        request.publicIpv6,
+        "AAAA",
+      );
+    }
 
     await repository.save(newMetalInstance);
   }
@@ -7,6 +7,9 @@ export const WorkloadEventKind = z.discriminatedUnion("kind", [
   z.object({ kind: z.literal("stopped") }),
   z.object({ kind: z.literal("vmRestarted") }),
   z.object({ kind: z.literal("forcedRestart") }),
+  z.object({ kind: z.literal("preempted") }),
+  z.object({ kind: z.literal("artifactsUpgraded"), version: z.string() }),
+  z.object({ kind: z.literal("envVarsRotated"), keys: z.string().array() }),
   z.object({ kind: z.literal("awaitingCert") }),
   z.object({ kind: z.literal("running") }),
   z.object({ kind: z.literal("failedToStart"), error: z.string() }),
@@ -2,7 +2,7 @@ import { describeRoute } from "hono-openapi";
 import { resolver } from "hono-openapi/zod";
 import z from "zod";
 import { SystemStatsResponse as StatsResponse } from "#/clients/nilcc-agent.client";
-import { userAuthentication } from "#/common/auth";
+import { adminAuthentication, userAuthentication } from "#/common/auth";
 import { EntityNotFound } from "#/common/errors";
 import {
   OpenApiSpecCommonErrorResponses,
@@ -23,13 +23,16 @@ import {
   DeleteWorkloadRequest,
   GetWorkloadResponse,
   ListWorkloadsResponse,
+  RescheduleWorkloadRequest,
   RestartWorkloadRequest,
   StatsRequest,
   WorkloadSystemLogsRequest,
+  WorkloadPlacementResponse,
   WorkloadSystemLogsResponse,
 } from "./workload.dto";
 
 const idParamSchema = z.object({ id: z.string().uuid() });
+const accountIdParamSchema = z.object({ accountId: z.string().uuid() });
 
 export function create(options: ControllerOptions): void {
   const { app, bindings } = options;
@@ -303,3 +306,135 @@ export function stats(options: ControllerOptions) {
     },
   );
 }
+
+export function listByAccount(options: ControllerOptions): void {
+  const { app, bindings } = options;
+
+  app.get(
+    PathsV1.workload.listByAccount,
+    describeRoute({
+      tags: ["workload"],
+      summary: "List the workloads for an account",
+      description:
+        "This endpoint allows admins to list all workloads that belong to an account.",
+      responses: {
+        200: {
+          description: "The workloads",
+          content: {
+            "application/json": {
+              schema: resolver(ListWorkloadsResponse),
+            },
+          },
+        },
+        ...OpenApiSpecCommonErrorResponses,
+      },
+    }),
+    adminAuthentication(bindings),
+    pathValidator(accountIdParamSchema),
+    transactionMiddleware(bindings.dataSource),
+    responseValidator(bindings, ListWorkloadsResponse),
+    async (c) => {
+      const params = c.req.valid("param");
+      const workloads = await bindings.services.workload.listByAccount(
+        bindings,
+        params.accountId,
+        c.get("txQueryRunner"),
+      );
+      return c.json(
+        workloads.map((w) =>
+          workloadMapper.entityToResponse(
+            w,
+            bindings.config.workloadsDnsDomain,
+            bindings.config.metalInstancesDnsDomain,
+          ),
+        ),
+      );
+    },
+  );
+}
+
+export function placement(options: ControllerOptions): void {
+  const { app, bindings } = options;
+
+  app.get(
+    PathsV1.workload.placement,
+    describeRoute({
+      tags: ["workload"],
+      summary: "Get the placement for a workload",
+      description:
+        "This endpoint allows admins to find out which metal instance a workload is scheduled on.",
+      responses: {
+        200: {
+          description: "The workload placement",
+          content: {
+            "application/json": {
+              schema: resolver(WorkloadPlacementResponse),
+            },
+          },
+        },
+        ...OpenApiSpecCommonErrorResponses,
+      },
+    }),
+    adminAuthentication(bindings),
+    pathValidator(idParamSchema),
+    transactionMiddleware(bindings.dataSource),
+    responseValidator(bindings, WorkloadPlacementResponse),
+    async (c) => {
+      const params = c.req.valid("param");
+      const workload = await bindings.services.workload.placement(
+        bindings,
+        params.id,
+        c.get("txQueryRunner"),
+      );
+      return c.json(
+        workloadMapper.entityToPlacementResponse(
+          workload,
+          bindings.config.metalInstancesDnsDomain,
+        ),
+      );
+    },
+  );
+}
+
+export function reschedule(options: ControllerOptions): void {
+  const { app, bindings } = options;
+
+  app.post(
+    PathsV1.workload.reschedule,
+    describeRoute({
+      tags: ["workload"],
+      summary: "Reschedule a workload",
+      description:
+        "This endpoint allows admins to move a workload to a different metal instance. The workload is created in the new metal instance and deleted from the one it was previously scheduled on.",
+      responses: {
+        200: {
+          description: "The new workload placement",
+          content: {
+            "application/json": {
+              schema: resolver(WorkloadPlacementResponse),
+            },
+          },
+        },
+        ...OpenApiSpecCommonErrorResponses,
+      },
+    }),
+    adminAuthentication(bindings),
+    payloadValidator(RescheduleWorkloadRequest),
+    transactionMiddleware(bindings.dataSource),
+    responseValidator(bindings, WorkloadPlacementResponse),
+    async (c) => {
+      const payload = c.req.valid("json");
+      const workload = await bindings.services.workload.reschedule(
+        bindings,
+        payload,
+        c.get("txQueryRunner"),
+      );
+      return c.json(
+        workloadMapper.entityToPlacementResponse(
+          workload,
+          bindings.config.metalInstancesDnsDomain,
+        ),
+      );
+    },
+  );
+}
@@ -1,6 +1,7 @@
 import { z } from "zod";
 import { SystemLogsRequest } from "#/clients/nilcc-agent.client";
 import { Uuid } from "#/common/types";
+import { GetMetalInstanceResponse } from "#/metal-instance/metal-instance.dto";
 
 const FILENAME_REGEX = /^[\w/._-]+$/;
 
@@ -247,3 +248,39 @@ export const WorkloadSystemLogsResponse = z
 export type WorkloadSystemLogsResponse = z.infer<
   typeof WorkloadSystemLogsResponse
 >;
+
+export const WorkloadPlacementResponse = z
+  .object({
+    workloadId: Uuid.openapi({
+      description: "The identifier for the workload.",
+    }),
+    accountId: Uuid.openapi({
+      description: "The account this workload belongs to.",
+    }),
+    status: CreateWorkloadResponse.shape.status,
+    artifactsVersion: z.string().openapi({
+      description: "The artifacts version the workload is running on.",
+    }),
+    metalInstance: GetMetalInstanceResponse.openapi({
+      description: "The metal instance the workload is scheduled on.",
+    }),
+  })
+  .openapi({ ref: "WorkloadPlacementResponse" });
+export type WorkloadPlacementResponse = z.infer<
+  typeof WorkloadPlacementResponse
+>;
+
+export const RescheduleWorkloadRequest = z
+  .object({
+    workloadId: Uuid.openapi({
+      description: "The identifier for the workload to be rescheduled.",
+    }),
+    metalInstanceId: Uuid.optional().openapi({
+      description:
+        "The metal instance to move the workload to. If not set, any other metal instance with enough free resources is used.",
+    }),
+  })
+  .openapi({ ref: "RescheduleWorkloadRequest" });
+export type RescheduleWorkloadRequest = z.infer<
+  typeof RescheduleWorkloadRequest
+>;
@@ -154,6 +154,9 @@ export class WorkloadEventEntity {
     | "stopped"
     | "vmRestarted"
     | "forcedRestart"
+    | "preempted"
+    | "artifactsUpgraded"
+    | "envVarsRotated"
     | "failedToStart"
     | "warning";
 
@@ -1,5 +1,9 @@
 import { microdollarsToUsd } from "#/common/nil";
-import type { CreateWorkloadResponse } from "#/workload/workload.dto";
+import { metalInstanceMapper } from "#/metal-instance/metal-instance.mapper";
+import type {
+  CreateWorkloadResponse,
+  WorkloadPlacementResponse,
+} from "#/workload/workload.dto";
 import type { WorkloadEntity } from "#/workload/workload.entity";
 
 export const workloadMapper = {
@@ -33,4 +37,20 @@ export const workloadMapper = {
       updatedAt: workload.updatedAt.toISOString(),
     };
   },
+
+  entityToPlacementResponse(
+    workload: WorkloadEntity,
+    metalInstancesDomain: string,
+  ): WorkloadPlacementResponse {
+    return {
+      workloadId: workload.id,
+      accountId: workload.account.id,
+      status: workload.status,
+      artifactsVersion: workload.artifactsVersion,
+      metalInstance: metalInstanceMapper.entityToResponse(
+        workload.metalInstance,
+        metalInstancesDomain,
+      ),
+    };
+  },
 };
@@ -9,4 +9,7 @@ export function buildWorkloadRouter(options: ControllerOptions): void {
   WorkloadController.restart(options);
   WorkloadController.systemLogs(options);
   WorkloadController.stats(options);
+  WorkloadController.listByAccount(options);
+  WorkloadController.placement(options);
+  WorkloadController.reschedule(options);
 }
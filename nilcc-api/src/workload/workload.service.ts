@@ -1,6 +1,6 @@
 import type { QueryRunner, Repository } from "typeorm";
 import { v4 as uuidv4 } from "uuid";
-import type { AccountEntity } from "#/account/account.entity";
+import { AccountEntity } from "#/account/account.entity";
 import { ArtifactEntity } from "#/artifact/artifact.entity";
 import type {
   Container,
@@ -28,6 +28,7 @@ import type {
 import { WorkloadTierEntity } from "#/workload-tier/workload-tier.entity";
 import type {
   CreateWorkloadRequest,
+  RescheduleWorkloadRequest,
   RestartWorkloadRequest,
   StatsRequest,
   WorkloadSystemLogsRequest,
@@ -233,6 +234,108 @@ export class WorkloadService {
     );
   }
 
+  async listByAccount(
+    bindings: AppBindings,
+    accountId: string,
+    tx: QueryRunner,
+  ): Promise<WorkloadEntity[]> {
+    const account = await tx.manager
+      .getRepository(AccountEntity)
+      .findOneBy({ id: accountId });
+    if (account === null) {
+      throw new EntityNotFound("account");
+    }
+    const repository = this.getRepository(bindings, tx);
+    return await repository.find({
+      where: { account: { id: accountId } },
+      relations: ["account", "metalInstance"],
+    });
+  }
+
+  async placement(
+    bindings: AppBindings,
+    workloadId: string,
+    tx: QueryRunner,
+  ): Promise<WorkloadEntity> {
+    const repository = this.getRepository(bindings, tx);
+    const workload = await repository.findOne({
+      where: { id: workloadId },
+      relations: ["account", "metalInstance"],
+    });
+    if (workload === null) {
+      throw new EntityNotFound("workload");
+    }
+    return workload;
+  }
+
+  async reschedule(
+    bindings: AppBindings,
+    request: RescheduleWorkloadRequest,
+    tx: QueryRunner,
+  ): Promise<WorkloadEntity> {
+    const repository = this.getRepository(bindings, tx);
+    const workload = await this.placement(bindings, request.workloadId, tx);
+    const previousInstance = workload.metalInstance;
+    const candidates = (
+      await bindings.services.metalInstance.findWithFreeResources(
+        {
+          cpus: workload.cpus,
+          memory: workload.memory,
+          disk: workload.disk,
+          gpus: workload.gpus,
+          artifactsVersion: workload.artifactsVersion,
+        },
+        bindings,
+        tx,
+      )
+    )
+      .filter((instance) => instance.id !== previousInstance.id)
+      .sort((a, b) => a.id.localeCompare(b.id));
+    const metalInstance =
+      request.metalInstanceId === undefined
+        ? candidates[0]
+        : candidates.find((i) => i.id === request.metalInstanceId);
+    if (metalInstance === undefined) {
+      throw new NoInstancesAvailable();
+    }
+
+    bindings.log.info(
+      `Rescheduling workload ${workload.id} from metal instance ${previousInstance.id} to ${metalInstance.id}`,
+    );
+    workload.metalInstance = metalInstance;
+    workload.status = "scheduled";
+    workload.updatedAt = bindings.services.time.getTime();
+    await repository.save(workload);
+
+    const domain =
+      workload.domain || `${workload.id}.${bindings.config.workloadsDnsDomain}`;
+    await bindings.services.nilccAgentClient.createWorkload(
+      metalInstance,
+      workload,
+      domain,
+    );
+    if (!workload.domain) {
+      await this.removeCnameForWorkload(bindings, workload.id);
+      await this.createCnameForWorkload(
+        bindings,
+        workload.id,
+        metalInstance.id,
+      );
+    }
+    // The previous instance is often unreachable, which is usually why the workload is being rescheduled.
+    try {
+      await bindings.services.nilccAgentClient.deleteWorkload(
+        previousInstance,
+        workload.id,
+      );
+    } catch (e) {
+      bindings.log.warn(
+        `Failed to delete workload ${workload.id} from metal instance ${previousInstance.id}: ${e}`,
+      );
+    }
+    return workload;
+  }
+
   async submitEvent(
     bindings: AppBindings,
     request: SubmitEventRequest,
@@ -259,13 +362,16 @@ export class WorkloadService {
         workload.status = "running";
         break;
       case "stopped":
+      case "preempted":
         workload.status = "stopped";
         break;
       case "failedToStart":
         workload.status = "error";
         break;
       case "warning":
-        // We don't want a state change for warnings
+      case "artifactsUpgraded":
+      case "envVarsRotated":
+        // We don't want a state change for warnings, upgrades or rotations
         break;
     }
     let details: string | undefined;
@@ -273,6 +379,10 @@ export class WorkloadService {
       details = request.event.error;
     } else if (request.event.kind === "warning") {
       details = request.event.message;
+    } else if (request.event.kind === "artifactsUpgraded") {
+      details = request.event.version;
+    } else if (request.event.kind === "envVarsRotated") {
+      details = request.event.keys.join(",");
     }
     const event: WorkloadEventEntity = {
       id: uuidv4(),
@@ -307,6 +417,11 @@ export class WorkloadService {
         details = { kind: "failedToStart", error: event.details || "" };
       } else if (event.event === "warning") {
         details = { kind: "warning", message: event.details || "" };
+      } else if (event.event === "artifactsUpgraded") {
+        details = { kind: "artifactsUpgraded", version: event.details || "" };
+      } else if (event.event === "envVarsRotated") {
+        const keys = event.details ? event.details.split(",") : [];
+        details = { kind: "envVarsRotated", keys };
       } else {
         details = { kind: event.event };
       }
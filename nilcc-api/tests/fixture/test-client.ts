@@ -26,6 +26,8 @@ import {
   CreateWorkloadResponse,
   GetWorkloadResponse,
   ListWorkloadsResponse,
+  type RescheduleWorkloadRequest,
+  WorkloadPlacementResponse,
   type WorkloadSystemLogsRequest,
   WorkloadSystemLogsResponse,
 } from "#/workload/workload.dto";
@@ -248,6 +250,34 @@ export class AdminClient extends TestClient {
     });
     return new RequestPromise(promise, z.object({}));
   }
+
+  listAccountWorkloads(
+    accountId: string,
+  ): RequestPromise<ListWorkloadsResponse> {
+    const promise = this.request(
+      PathsV1.workload.listByAccount.replace(":accountId", accountId),
+      { method: "GET" },
+    );
+    return new RequestPromise(promise, ListWorkloadsResponse);
+  }
+
+  workloadPlacement(id: string): RequestPromise<WorkloadPlacementResponse> {
+    const promise = this.request(
+      PathsV1.workload.placement.replace(":id", id),
+      { method: "GET" },
+    );
+    return new RequestPromise(promise, WorkloadPlacementResponse);
+  }
+
+  rescheduleWorkload(
+    request: RescheduleWorkloadRequest,
+  ): RequestPromise<WorkloadPlacementResponse> {
+    const promise = this.request(PathsV1.workload.reschedule, {
+      method: "POST",
+      body: request,
+    });
+    return new RequestPromise(promise, WorkloadPlacementResponse);
+  }
 }
 
 export class UserClient extends TestClient {
@@ -203,4 +203,35 @@ services:
     await clients.user.deleteWorkload(workload.workloadId).submit();
     clients.admin.deleteMetalInstance(instance.metalInstanceId).submit();
   });
+
+  it("should register an IPv6-only metal instance", async ({
+    expect,
+    clients,
+  }) => {
+    const ipv6Instance = {
+      ...myMetalInstance,
+      metalInstanceId: "5f0c4bb0-6c43-4b8e-9d57-8f2ac1ed0b1a",
+      publicIp: undefined,
+      publicIpv6: "::1",
+    };
+    await clients.metalInstance.register(ipv6Instance).submit();
+
+    const instance = await clients.admin
+      .getMetalInstance(ipv6Instance.metalInstanceId)
+      .submit();
+    expect(instance.publicIp).toBeUndefined();
+    expect(instance.publicIpv6).toEqual("::1");
+  });
+
+  it("should reject a metal instance without public IPs", async ({
+    expect,
+    clients,
+  }) => {
+    const request = {
+      ...myMetalInstance,
+      metalInstanceId: "0a4f6c1e-3b9d-4d7e-8c2f-6e5b1a9d3c7f",
+      publicIp: undefined,
+    };
+    expect(await clients.metalInstance.register(request).status()).toBe(400);
+  });
 });
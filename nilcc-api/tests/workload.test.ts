@@ -389,6 +389,51 @@ services:
     await ownerB.deleteWorkload(workloadB.workloadId).submit();
   });
 
+  it("should allow admins to inspect and reschedule workloads", async ({
+    expect,
+    clients,
+  }) => {
+    const otherMetalInstance: RegisterMetalInstanceRequest = {
+      ...myMetalInstance,
+      metalInstanceId: "fd3c86e4-c7e5-4bb3-a5f5-45945b5593e4",
+      hostname: "my-other-metal-instance",
+    };
+    await clients.metalInstance.register(otherMetalInstance).submit();
+    await clients.metalInstance
+      .heartbeat(otherMetalInstance.metalInstanceId, ["aaa"])
+      .submit();
+
+    const workload = await clients.user
+      .createWorkload(createWorkloadRequest)
+      .submit();
+    const workloads = await clients.admin
+      .listAccountWorkloads(workload.accountId)
+      .submit();
+    expect(workloads.map((w) => w.workloadId)).toContain(workload.workloadId);
+
+    const placement = await clients.admin
+      .workloadPlacement(workload.workloadId)
+      .submit();
+    expect(placement.accountId).toBe(workload.accountId);
+    const previousInstanceId = placement.metalInstance.metalInstanceId;
+
+    const newPlacement = await clients.admin
+      .rescheduleWorkload({ workloadId: workload.workloadId })
+      .submit();
+    expect(newPlacement.status).toBe("scheduled");
+    expect(newPlacement.metalInstance.metalInstanceId).not.toBe(
+      previousInstanceId,
+    );
+    const updated = await clients.admin
+      .workloadPlacement(workload.workloadId)
+      .submit();
+    expect(updated.metalInstance.metalInstanceId).toBe(
+      newPlacement.metalInstance.metalInstanceId,
+    );
+
+    await clients.user.deleteWorkload(workload.workloadId).submit();
+  });
+
   it("should allow performing workload actions", async ({
     expect,
     clients,
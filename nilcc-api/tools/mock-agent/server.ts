@@ -202,6 +202,8 @@ authenticated.post("/api/v1/system/artifacts/cleanup", (c) =>
 
 authenticated.get("/api/v1/system/verifier/keys", (c) => c.json([]));
 
+authenticated.get("/api/v1/system/zerossl/accounts", (c) => c.json([]));
+
 authenticated.post("/api/v1/system/agent/upgrade", (c) => c.json({}));
 
 // ── Workloads ────────────────────────────────────────────────────────────────
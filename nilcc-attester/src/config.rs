@@ -1,4 +1,5 @@
 use anyhow::Context;
+use attestation_report::{platform_claims::SignedPlatformClaims, report_data::WorkloadIdentity};
 use serde::{Deserialize, Serialize};
 use std::{
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
@@ -16,6 +17,16 @@ pub struct Config {
     #[serde(default = "default_proxy_endpoint")]
     pub proxy_endpoint: String,
     pub attestation_domain: String,
+    #[serde(default)]
+    pub workload_id: Option<String>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default = "default_boot_log_path")]
+    pub boot_log_path: PathBuf,
+    #[serde(default)]
+    pub token_public_key: Option<String>,
+    #[serde(default)]
+    pub platform_claims: Option<String>,
 }
 
 impl Config {
@@ -27,6 +38,33 @@ impl Config {
         let settings = builder.build().context("parsing config")?;
         settings.try_deserialize().context("deserializing config")
     }
+
+    /// The identity of the workload running in this CVM, if the agent provided one.
+    pub fn workload_identity(&self) -> Option<WorkloadIdentity> {
+        // These come from docker compose variables so they're empty strings rather than missing when unset.
+        let workload_id = self.workload_id.clone().filter(|id| !id.is_empty())?;
+        let agent_id = self.agent_id.clone().filter(|id| !id.is_empty())?;
+        Some(WorkloadIdentity { workload_id, agent_id })
+    }
+
+    /// The public key the CVM signs identity tokens with, if the agent provided one.
+    pub fn token_key(&self) -> anyhow::Result<Option<[u8; 32]>> {
+        let Some(key) = self.token_public_key.as_ref().filter(|key| !key.is_empty()) else {
+            return Ok(None);
+        };
+        let mut token_key = [0; 32];
+        hex::decode_to_slice(key, &mut token_key).context("invalid token public key")?;
+        Ok(Some(token_key))
+    }
+
+    /// The platform claims signed by the agent, if it provided any.
+    pub fn platform_claims(&self) -> anyhow::Result<Option<SignedPlatformClaims>> {
+        let Some(claims) = self.platform_claims.as_ref().filter(|claims| !claims.is_empty()) else {
+            return Ok(None);
+        };
+        let claims = serde_json::from_str(claims).context("invalid platform claims")?;
+        Ok(Some(claims))
+    }
 }
 
 #[derive(Deserialize)]
@@ -57,6 +95,10 @@ fn default_gpu_attester_path() -> PathBuf {
     "/opt/nillion/gpu-attester/main.py".into()
 }
 
+fn default_boot_log_path() -> PathBuf {
+    "/var/lib/nilcc-boot/boot-log.json".into()
+}
+
 fn default_proxy_endpoint() -> String {
     "cvm-nilcc-proxy-1:443".to_string()
 }
@@ -1,4 +1,5 @@
 use anyhow::bail;
+use attestation_report::report_data::WorkloadIdentity;
 use axum::http;
 use clap::Parser;
 use nilcc_attester::{
@@ -7,7 +8,7 @@ use nilcc_attester::{
     report::{GpuReportConfig, HardwareReporter},
     routes::{AppState, build_router},
 };
-use std::{process::exit, sync::Arc, time::Duration};
+use std::{fs, process::exit, sync::Arc, time::Duration};
 use tokio::{net::TcpListener, signal, time::sleep};
 use tower_http::cors::CorsLayer;
 use tracing::{error, info, warn};
@@ -41,9 +42,17 @@ async fn shutdown_signal() {
     info!("Received shutdown signal");
 }
 
-async fn build_reporter(gpu_config: GpuReportConfig, fetcher: CertFetcher) -> anyhow::Result<HardwareReporter> {
+async fn build_reporter(
+    gpu_config: GpuReportConfig,
+    fetcher: CertFetcher,
+    identity: Option<WorkloadIdentity>,
+    boot_log: Option<Arc<String>>,
+    token_key: Option<[u8; 32]>,
+) -> anyhow::Result<HardwareReporter> {
     for _ in 0..MAX_REPORTER_RETRIES {
-        match HardwareReporter::new(gpu_config.clone(), fetcher.clone()).await {
+        let reporter =
+            HardwareReporter::new(gpu_config.clone(), fetcher.clone(), identity.clone(), boot_log.clone(), token_key);
+        match reporter.await {
             Ok(reporter) => return Ok(reporter),
             Err(e) => {
                 warn!("Failed to build hardware reporter: {e:#}");
@@ -73,11 +82,58 @@ async fn main() {
         VmType::Cpu => GpuReportConfig::Disabled,
         VmType::Gpu => GpuReportConfig::Enabled { attester_path: config.gpu_attester_path },
     };
+    let identity = config.workload_identity();
+    match &identity {
+        Some(WorkloadIdentity { workload_id, agent_id }) => {
+            info!("Binding reports to workload {workload_id} running on agent {agent_id}")
+        }
+        None => warn!("No workload identity provided, reports won't be bound to a workload"),
+    };
+    let boot_log = match fs::read_to_string(&config.boot_log_path) {
+        Ok(boot_log) => {
+            info!("Binding boot log to reports that request it");
+            Some(Arc::new(boot_log))
+        }
+        Err(e) => {
+            warn!("Could not read boot log from {}, reports won't include it: {e}", config.boot_log_path.display());
+            None
+        }
+    };
+    let token_key = match config.token_key() {
+        Ok(Some(token_key)) => {
+            info!("Binding identity token key {} to reports that request it", hex::encode(token_key));
+            Some(token_key)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Failed to load token key: {e:#}");
+            exit(1);
+        }
+    };
+    let platform_claims = match config.platform_claims() {
+        Ok(Some(claims)) => {
+            info!("Exposing platform claims signed by agent key {}", hex::encode(&claims.public_key));
+            Some(Arc::new(claims))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Failed to load platform claims: {e:#}");
+            exit(1);
+        }
+    };
     let fetcher = CertFetcher { proxy_endpoint: config.proxy_endpoint, server_name: config.attestation_domain };
-    let reporter = build_reporter(gpu_config, fetcher).await.expect("Failed to initialize hardware reporter");
+    let reporter = build_reporter(gpu_config, fetcher, identity.clone(), boot_log, token_key)
+        .await
+        .expect("Failed to initialize hardware reporter");
     let reporter = Arc::new(reporter);
-    let state =
-        AppState { nilcc_version: config.nilcc_version, vm_type: config.vm_type, cpu_count: num_cpus::get(), reporter };
+    let state = AppState {
+        nilcc_version: config.nilcc_version,
+        vm_type: config.vm_type,
+        cpu_count: num_cpus::get(),
+        identity,
+        platform_claims,
+        reporter,
+    };
     let listener = TcpListener::bind(bind_endpoint).await.expect("failed to bind");
     let cors = CorsLayer::new()
         .allow_methods([http::Method::GET, http::Method::POST])
@@ -1,5 +1,9 @@
 use crate::cert::CertFetcher;
 use anyhow::{Context, bail};
+use attestation_report::{
+    boot_log::BootLog,
+    report_data::{ReportData, WorkloadIdentity},
+};
 use sev::{
     firmware::guest::{AttestationReport, Firmware},
     parser::ByteParser,
@@ -16,6 +20,25 @@ pub struct Reports {
     pub attestation: Arc<attestation_report::v2::AttestationReport>,
     pub raw_attestation: Vec<u8>,
     pub gpu_token: Option<String>,
+    pub boot_log: Option<BootLogReport>,
+    pub token_key: Option<TokenKeyReport>,
+}
+
+/// A report that additionally binds the CVM's boot log.
+#[derive(Clone)]
+pub struct BootLogReport {
+    pub attestation: Arc<attestation_report::v2::AttestationReport>,
+    pub raw_attestation: Vec<u8>,
+    pub boot_log: Arc<String>,
+}
+
+/// A report that additionally binds the key identity tokens are signed with, along with the boot log if there's one.
+#[derive(Clone)]
+pub struct TokenKeyReport {
+    pub attestation: Arc<attestation_report::v2::AttestationReport>,
+    pub raw_attestation: Vec<u8>,
+    pub public_key: [u8; 32],
+    pub boot_log: Option<Arc<String>>,
 }
 
 pub struct HardwareReporter {
@@ -23,17 +46,18 @@ pub struct HardwareReporter {
 }
 
 impl HardwareReporter {
-    pub async fn new(gpu: GpuReportConfig, cert_fetcher: CertFetcher) -> anyhow::Result<Self> {
+    pub async fn new(
+        gpu: GpuReportConfig,
+        cert_fetcher: CertFetcher,
+        identity: Option<WorkloadIdentity>,
+        boot_log: Option<Arc<String>>,
+        token_key: Option<[u8; 32]>,
+    ) -> anyhow::Result<Self> {
         let fingerprint = cert_fetcher.fetch_fingerprint().await.context("Failed to fetch cert fingerpring")?;
-        let hardware_report = Self::fetch_hardware_report(&fingerprint).context("Failed to fetch hardware report")?;
-        let raw_attestation = hardware_report.to_bytes()?.into();
-        let reports = Reports {
-            attestation: Arc::new(hardware_report.into()),
-            raw_attestation,
-            gpu_token: Self::fetch_gpu_report(&fingerprint, &gpu).await.context("Failed to fetch GPU report")?,
-        };
+        let reports =
+            Self::generate_reports(&fingerprint, identity.as_ref(), boot_log.as_ref(), token_key, &gpu).await?;
         let reports = Arc::new(Mutex::new(reports));
-        Worker::spawn(gpu, cert_fetcher, fingerprint, reports.clone());
+        Worker::spawn(gpu, cert_fetcher, identity, boot_log, token_key, fingerprint, reports.clone());
         Ok(Self { reports })
     }
 
@@ -42,12 +66,73 @@ impl HardwareReporter {
         (*reports).clone()
     }
 
-    fn fetch_hardware_report(fingerprint: &[u8; 32]) -> anyhow::Result<AttestationReport> {
-        let mut data: [u8; 64] = [0; 64];
-        // Version, bump if changed
-        data[0] = 0;
-        // Copy over the cert fingerprint
-        data[1..33].copy_from_slice(fingerprint);
+    async fn generate_reports(
+        fingerprint: &[u8; 32],
+        identity: Option<&WorkloadIdentity>,
+        boot_log: Option<&Arc<String>>,
+        token_key: Option<[u8; 32]>,
+        gpu: &GpuReportConfig,
+    ) -> anyhow::Result<Reports> {
+        let hardware_report = Self::fetch_hardware_report(fingerprint, identity, None, None)
+            .context("Failed to fetch hardware report")?;
+        // The identity is provided by the host at bootstrap so only bind it if the CVM was launched with it.
+        if let Some(identity) = identity
+            && hardware_report.host_data != identity.hash()
+        {
+            bail!(
+                "Workload identity hash {} doesn't match the host data {} the CVM was launched with",
+                hex::encode(identity.hash()),
+                hex::encode(hardware_report.host_data)
+            );
+        }
+        let raw_attestation = hardware_report.to_bytes()?.into();
+        let boot_log = match boot_log {
+            Some(boot_log) => {
+                let boot_log_hash = BootLog::hash(boot_log.as_bytes());
+                let hardware_report = Self::fetch_hardware_report(fingerprint, identity, Some(boot_log_hash), None)
+                    .context("Failed to fetch boot log hardware report")?;
+                let raw_attestation = hardware_report.to_bytes()?.into();
+                Some(BootLogReport {
+                    attestation: Arc::new(hardware_report.into()),
+                    raw_attestation,
+                    boot_log: boot_log.clone(),
+                })
+            }
+            None => None,
+        };
+        let token_key = match token_key {
+            Some(public_key) => {
+                let boot_log_hash = boot_log.map(|boot_log| BootLog::hash(boot_log.as_bytes()));
+                let hardware_report =
+                    Self::fetch_hardware_report(fingerprint, identity, boot_log_hash, Some(public_key))
+                        .context("Failed to fetch token key hardware report")?;
+                let raw_attestation = hardware_report.to_bytes()?.into();
+                Some(TokenKeyReport {
+                    attestation: Arc::new(hardware_report.into()),
+                    raw_attestation,
+                    public_key,
+                    boot_log: boot_log.cloned(),
+                })
+            }
+            None => None,
+        };
+        Ok(Reports {
+            attestation: Arc::new(hardware_report.into()),
+            raw_attestation,
+            gpu_token: Self::fetch_gpu_report(fingerprint, gpu).await.context("Failed to fetch GPU report")?,
+            boot_log,
+            token_key,
+        })
+    }
+
+    fn fetch_hardware_report(
+        fingerprint: &[u8; 32],
+        identity: Option<&WorkloadIdentity>,
+        boot_log_hash: Option<[u8; 32]>,
+        token_key: Option<[u8; 32]>,
+    ) -> anyhow::Result<AttestationReport> {
+        let data = ReportData { tls_fingerprint: *fingerprint, identity: identity.cloned(), boot_log_hash, token_key }
+            .encode();
 
         info!("Generating hardware report using nonce {}", hex::encode(data));
         let mut fw = Firmware::open().context("unable to open /dev/sev-guest")?;
@@ -90,13 +175,24 @@ pub enum GpuReportConfig {
 struct Worker {
     gpu: GpuReportConfig,
     cert_fetcher: CertFetcher,
+    identity: Option<WorkloadIdentity>,
+    boot_log: Option<Arc<String>>,
+    token_key: Option<[u8; 32]>,
     fingerprint: [u8; 32],
     reports: Arc<Mutex<Reports>>,
 }
 
 impl Worker {
-    fn spawn(gpu: GpuReportConfig, cert_fetcher: CertFetcher, fingerprint: [u8; 32], reports: Arc<Mutex<Reports>>) {
-        let worker = Self { gpu, cert_fetcher, fingerprint, reports };
+    fn spawn(
+        gpu: GpuReportConfig,
+        cert_fetcher: CertFetcher,
+        identity: Option<WorkloadIdentity>,
+        boot_log: Option<Arc<String>>,
+        token_key: Option<[u8; 32]>,
+        fingerprint: [u8; 32],
+        reports: Arc<Mutex<Reports>>,
+    ) {
+        let worker = Self { gpu, cert_fetcher, identity, boot_log, token_key, fingerprint, reports };
         tokio::spawn(async move {
             worker.run().await;
         });
@@ -126,14 +222,16 @@ impl Worker {
             hex::encode(self.fingerprint),
             hex::encode(fingerprint)
         );
-        let hardware_report =
-            HardwareReporter::fetch_hardware_report(&fingerprint).context("Failed to fetch hardware report")?;
-        let raw_attestation = hardware_report.to_bytes()?.into();
-        let gpu_token =
-            HardwareReporter::fetch_gpu_report(&fingerprint, &self.gpu).await.context("Failed to fetch GPU report")?;
+        let reports = HardwareReporter::generate_reports(
+            &fingerprint,
+            self.identity.as_ref(),
+            self.boot_log.as_ref(),
+            self.token_key,
+            &self.gpu,
+        )
+        .await?;
         self.fingerprint = fingerprint;
-        *self.reports.lock().await =
-            Reports { attestation: Arc::new(hardware_report.into()), raw_attestation, gpu_token };
+        *self.reports.lock().await = reports;
         Ok(())
     }
 }
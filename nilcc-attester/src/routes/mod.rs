@@ -1,4 +1,5 @@
 use crate::{config::VmType, report::HardwareReporter};
+use attestation_report::{platform_claims::SignedPlatformClaims, report_data::WorkloadIdentity};
 use axum::{Router, routing::get};
 use std::sync::Arc;
 
@@ -22,5 +23,7 @@ pub struct AppState {
     pub nilcc_version: String,
     pub vm_type: VmType,
     pub cpu_count: usize,
+    pub identity: Option<WorkloadIdentity>,
+    pub platform_claims: Option<Arc<SignedPlatformClaims>>,
     pub reporter: Arc<HardwareReporter>,
 }
@@ -1,5 +1,15 @@
-use crate::{config::VmType, report::Reports, routes::AppState};
-use axum::{Json, extract::State, http::StatusCode};
+use crate::{
+    config::VmType,
+    report::{BootLogReport, Reports, TokenKeyReport},
+    routes::AppState,
+};
+use attestation_report::{platform_claims::SignedPlatformClaims, report_data::WorkloadIdentity};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
 use serde::Serialize;
 use serde_with::hex::Hex;
 use serde_with::serde_as;
@@ -13,6 +23,24 @@ pub(crate) struct Response {
     raw_report: Vec<u8>,
     gpu_token: Option<String>,
     environment: EnvironmentSpec,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    boot_log: Option<Arc<String>>,
+    #[serde_as(as = "Option<Hex>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_public_key: Option<[u8; 32]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform_claims: Option<Arc<SignedPlatformClaims>>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ReportQuery {
+    /// Return a report that binds the CVM's boot log along with the boot log itself.
+    #[serde(default)]
+    include_boot_log: bool,
+
+    /// Return a report that binds the key identity tokens are signed with, along with the boot log if there's one.
+    #[serde(default)]
+    include_token_key: bool,
 }
 
 #[derive(Serialize)]
@@ -20,11 +48,41 @@ pub(crate) struct EnvironmentSpec {
     nilcc_version: String,
     vm_type: VmType,
     cpu_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workload_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agent_id: Option<String>,
 }
 
-pub(crate) async fn handler(state: State<AppState>) -> Result<Json<Response>, StatusCode> {
-    let AppState { nilcc_version, vm_type, cpu_count, reporter } = state.0;
-    let Reports { attestation, raw_attestation, gpu_token, .. } = reporter.reports().await;
-    let environment = EnvironmentSpec { nilcc_version, vm_type, cpu_count };
-    Ok(Json(Response { report: attestation, raw_report: raw_attestation, environment, gpu_token }))
+pub(crate) async fn handler(state: State<AppState>, query: Query<ReportQuery>) -> Result<Json<Response>, StatusCode> {
+    let AppState { nilcc_version, vm_type, cpu_count, identity, platform_claims, reporter } = state.0;
+    let Reports { attestation, raw_attestation, gpu_token, boot_log, token_key } = reporter.reports().await;
+    let (attestation, raw_attestation, boot_log, token_public_key) = if query.include_token_key {
+        let Some(TokenKeyReport { attestation, raw_attestation, public_key, boot_log }) = token_key else {
+            return Err(StatusCode::NOT_FOUND);
+        };
+        (attestation, raw_attestation, boot_log, Some(public_key))
+    } else {
+        match (query.include_boot_log, boot_log) {
+            (true, Some(BootLogReport { attestation, raw_attestation, boot_log })) => {
+                (attestation, raw_attestation, Some(boot_log), None)
+            }
+            (true, None) => return Err(StatusCode::NOT_FOUND),
+            (false, _) => (attestation, raw_attestation, None, None),
+        }
+    };
+    let (workload_id, agent_id) = match identity {
+        Some(WorkloadIdentity { workload_id, agent_id }) => (Some(workload_id), Some(agent_id)),
+        None => (None, None),
+    };
+    let environment = EnvironmentSpec { nilcc_version, vm_type, cpu_count, workload_id, agent_id };
+    Ok(Json(Response {
+        report: attestation,
+        raw_report: raw_attestation,
+        environment,
+        gpu_token,
+        boot_log,
+        token_public_key,
+        platform_claims,
+    }))
 }
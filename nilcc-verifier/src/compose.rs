@@ -0,0 +1,80 @@
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// The hash of a docker compose file, as measured by the CVM that runs it.
+pub(crate) struct ComposeHash {
+    pub(crate) hash: [u8; 32],
+    pub(crate) warnings: Vec<ComposeWarning>,
+}
+
+impl ComposeHash {
+    /// Hash a docker compose file.
+    ///
+    /// The agent and the CVM hash the docker compose file's exact bytes: there's no normalization of line endings,
+    /// whitespace, or YAML structure. The only requirement is that it's valid UTF-8, since it's submitted as a string.
+    pub(crate) fn new(contents: &[u8]) -> Result<Self, NotUtf8> {
+        let contents = std::str::from_utf8(contents).map_err(|_| NotUtf8)?;
+        let hash = Sha256::digest(contents).into();
+        let mut warnings = Vec::new();
+        if contents.starts_with('\u{feff}') {
+            warnings.push(ComposeWarning::ByteOrderMark);
+        }
+        if contents.contains("\r\n") {
+            warnings.push(ComposeWarning::CrlfLineEndings);
+        }
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            warnings.push(ComposeWarning::MissingTrailingNewline);
+        }
+        Ok(Self { hash, warnings })
+    }
+}
+
+/// Something that's part of the hash but is commonly lost or added when a docker compose file is copied around.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ComposeWarning {
+    ByteOrderMark,
+    CrlfLineEndings,
+    MissingTrailingNewline,
+}
+
+impl fmt::Display for ComposeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Self::ByteOrderMark => "file starts with a UTF-8 byte order mark",
+            Self::CrlfLineEndings => "file uses CRLF line endings",
+            Self::MissingTrailingNewline => "file doesn't end with a newline",
+        };
+        write!(f, "{message}, make sure the submitted file contains it as well")
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("docker compose file is not valid UTF-8")]
+pub(crate) struct NotUtf8;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash() {
+        let compose = b"services:\n  api:\n    image: caddy\n";
+        let hash = ComposeHash::new(compose).expect("failed to hash");
+        assert_eq!(hash.hash, <[u8; 32]>::from(Sha256::digest(compose)));
+        assert!(hash.warnings.is_empty());
+    }
+
+    #[test]
+    fn warnings() {
+        let hash = ComposeHash::new(b"\xef\xbb\xbfservices:\r\n  api: {}").expect("failed to hash");
+        assert_eq!(
+            hash.warnings,
+            &[ComposeWarning::ByteOrderMark, ComposeWarning::CrlfLineEndings, ComposeWarning::MissingTrailingNewline]
+        );
+    }
+
+    #[test]
+    fn not_utf8() {
+        assert!(ComposeHash::new(b"services: \xff\n").is_err());
+    }
+}
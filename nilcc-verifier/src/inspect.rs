@@ -0,0 +1,100 @@
+use attestation_report::{boot_log::BootLog, platform_claims::SignedPlatformClaims, report_data::WorkloadIdentity};
+use attestation_verification::{ReportBundle, VmType};
+use nilcc_artifacts::metadata::{CpuModel, KernelArgs, KernelCommandLine, MissingCommandLineParameter};
+use serde::Serialize;
+
+/// The placeholder left in the kernel command line in place of the docker compose hash.
+const DOCKER_COMPOSE_HASH_PLACEHOLDER: &str = "{DOCKER_COMPOSE_HASH}";
+
+/// A breakdown of the environment a CVM attests to.
+#[derive(Serialize)]
+pub(crate) struct InspectReport {
+    artifacts: InspectArtifacts,
+    vm_type: VmType,
+    vcpus: u32,
+    cpu_model: CpuModel,
+    kernel_cmdline: String,
+    filesystem_root_hash: String,
+    measurement: String,
+    tls_fingerprint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workload: Option<WorkloadIdentity>,
+    gpu_evidence: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    boot_log: Option<BootLog>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform_claims: Option<SignedPlatformClaims>,
+}
+
+#[derive(Serialize)]
+struct InspectArtifacts {
+    version: String,
+    metadata_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    github_actions_build_url: Option<String>,
+}
+
+impl InspectReport {
+    pub(crate) fn new(bundle: &ReportBundle) -> Result<Self, MissingCommandLineParameter> {
+        let ReportBundle {
+            report,
+            metadata,
+            cpu_count,
+            metadata_hash,
+            metadata_signer,
+            tls_fingerprint,
+            nilcc_version,
+            vm_type,
+            identity,
+            gpu_token,
+            boot_log,
+            platform_claims,
+        } = bundle;
+        let filesystem_root_hash = metadata.cvm.images.resolve((*vm_type).into()).verity.root_hash;
+        let github_actions_build_url = metadata.build.as_ref().map(|b| {
+            let id = b.github_action_run_id;
+            format!("https://github.com/NillionNetwork/nilcc/actions/runs/{id}")
+        });
+        Ok(Self {
+            artifacts: InspectArtifacts {
+                version: nilcc_version.clone(),
+                metadata_hash: hex::encode(metadata_hash),
+                signer: metadata_signer.clone(),
+                github_actions_build_url,
+            },
+            vm_type: *vm_type,
+            vcpus: *cpu_count,
+            cpu_model: metadata.cpu.model,
+            kernel_cmdline: kernel_command_line(&metadata.cvm.cmdline, &filesystem_root_hash)?,
+            filesystem_root_hash: hex::encode(filesystem_root_hash),
+            measurement: hex::encode(report.measurement),
+            tls_fingerprint: tls_fingerprint.clone(),
+            workload: identity.clone(),
+            gpu_evidence: gpu_token.is_some(),
+            boot_log: boot_log.clone(),
+            platform_claims: platform_claims.clone(),
+        })
+    }
+}
+
+/// Render the kernel command line, leaving a placeholder in place of the docker compose hash since we don't know it.
+fn kernel_command_line(
+    cmdline: &KernelCommandLine,
+    filesystem_root_hash: &[u8; 32],
+) -> Result<String, MissingCommandLineParameter> {
+    cmdline.render(KernelArgs { docker_compose_hash: DOCKER_COMPOSE_HASH_PLACEHOLDER, filesystem_root_hash })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_command_line_placeholder() {
+        let cmdline = KernelCommandLine("root={VERITY_ROOT_HASH} compose={DOCKER_COMPOSE_HASH}".into());
+        let cmdline = kernel_command_line(&cmdline, &[0xaa; 32]).expect("failed to render");
+        assert_eq!(cmdline, format!("root={} compose={{DOCKER_COMPOSE_HASH}}", "aa".repeat(32)));
+    }
+}
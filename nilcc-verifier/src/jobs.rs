@@ -0,0 +1,169 @@
+use crate::routes::v1::attestations::verify_batch::VerifyResult;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// How long a completed job's results are kept around for.
+const JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// How long a job can run for before it's abandoned.
+pub(crate) const JOB_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The status of a verification job.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    /// The job's reports are still being verified.
+    Running,
+
+    /// All of the job's reports were verified.
+    Completed {
+        /// When the job completed.
+        completed_at: DateTime<Utc>,
+
+        /// The result for every report, in the same order they were submitted in.
+        results: Vec<VerifyResult>,
+    },
+
+    /// The job didn't finish verifying its reports in time.
+    TimedOut {
+        /// When the job was abandoned.
+        completed_at: DateTime<Utc>,
+    },
+}
+
+/// A job that verifies a batch of reports in the background.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct Job {
+    /// When the job was created.
+    pub(crate) created_at: DateTime<Utc>,
+
+    /// The number of reports in this job.
+    pub(crate) total: usize,
+
+    /// The job's status.
+    #[serde(flatten)]
+    pub(crate) status: JobStatus,
+}
+
+/// The verification jobs that were submitted to this verifier.
+#[derive(Clone, Default)]
+pub(crate) struct VerificationJobs {
+    jobs: Arc<Mutex<HashMap<Uuid, Job>>>,
+}
+
+impl VerificationJobs {
+    /// Register a new running job with the given number of reports.
+    pub(crate) fn create(&self, total: usize) -> Uuid {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+        let mut jobs = self.jobs.lock().expect("lock poisoned");
+        // Results are kept in memory so expire old ones as new jobs come in.
+        let retention = chrono::Duration::from_std(JOB_RETENTION).expect("invalid retention");
+        let timeout = chrono::Duration::from_std(JOB_TIMEOUT).expect("invalid timeout");
+        jobs.retain(|_, job| match &job.status {
+            // Jobs time out on their own, this only catches the ones whose task died without reporting back.
+            JobStatus::Running => now - job.created_at < timeout + retention,
+            JobStatus::Completed { completed_at, .. } | JobStatus::TimedOut { completed_at } => {
+                now - *completed_at < retention
+            }
+        });
+        jobs.insert(id, Job { created_at: now, total, status: JobStatus::Running });
+        id
+    }
+
+    /// Mark a job as completed.
+    pub(crate) fn complete(&self, id: Uuid, results: Vec<VerifyResult>) {
+        let mut jobs = self.jobs.lock().expect("lock poisoned");
+        if let Some(job) = jobs.get_mut(&id) {
+            job.status = JobStatus::Completed { completed_at: Utc::now(), results };
+        }
+    }
+
+    /// Mark a job as timed out.
+    pub(crate) fn time_out(&self, id: Uuid) {
+        let mut jobs = self.jobs.lock().expect("lock poisoned");
+        if let Some(job) = jobs.get_mut(&id) {
+            job.status = JobStatus::TimedOut { completed_at: Utc::now() };
+        }
+    }
+
+    /// Get a job.
+    pub(crate) fn get(&self, id: Uuid) -> Option<Job> {
+        self.jobs.lock().expect("lock poisoned").get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lifecycle() {
+        let jobs = VerificationJobs::default();
+        let id = jobs.create(2);
+        let job = jobs.get(id).expect("job not found");
+        assert_eq!(job.total, 2);
+        assert_eq!(job.status, JobStatus::Running);
+
+        let results = vec![
+            VerifyResult::Success,
+            VerifyResult::Failure { message: "oops".into(), error_code: "MALFORMED_REPORT".into() },
+        ];
+        jobs.complete(id, results.clone());
+        let job = jobs.get(id).expect("job not found");
+        let JobStatus::Completed { results: job_results, .. } = job.status else { panic!("job not completed") };
+        assert_eq!(job_results, results);
+    }
+
+    #[test]
+    fn expiry() {
+        let jobs = VerificationJobs::default();
+        let expired = jobs.create(1);
+        let running = jobs.create(1);
+        jobs.complete(expired, vec![]);
+        {
+            let mut inner = jobs.jobs.lock().expect("lock poisoned");
+            let JobStatus::Completed { completed_at, .. } = &mut inner.get_mut(&expired).expect("job not found").status
+            else {
+                panic!("job not completed")
+            };
+            *completed_at -= chrono::Duration::from_std(JOB_RETENTION).expect("invalid retention");
+        }
+
+        let recent = jobs.create(1);
+        assert!(jobs.get(expired).is_none());
+        assert!(jobs.get(running).is_some());
+        assert!(jobs.get(recent).is_some());
+    }
+
+    #[test]
+    fn timed_out() {
+        let jobs = VerificationJobs::default();
+        let timed_out = jobs.create(1);
+        let stuck = jobs.create(1);
+        jobs.time_out(timed_out);
+        let job = jobs.get(timed_out).expect("job not found");
+        assert!(matches!(job.status, JobStatus::TimedOut { .. }));
+        {
+            let mut inner = jobs.jobs.lock().expect("lock poisoned");
+            let job = inner.get_mut(&stuck).expect("job not found");
+            job.created_at -= chrono::Duration::from_std(JOB_TIMEOUT + JOB_RETENTION).expect("invalid duration");
+        }
+
+        jobs.create(1);
+        assert!(jobs.get(timed_out).is_some());
+        assert!(jobs.get(stuck).is_none());
+    }
+
+    #[test]
+    fn unknown_job() {
+        let jobs = VerificationJobs::default();
+        assert!(jobs.get(Uuid::new_v4()).is_none());
+    }
+}
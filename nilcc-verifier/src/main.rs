@@ -1,15 +1,29 @@
-use crate::routes::build_router;
+use crate::{
+    compose::ComposeHash,
+    inspect::InspectReport,
+    monitor::{Monitor, MonitorArgs, MonitorConfig},
+    routes::build_router,
+};
 use anyhow::Context;
+use attestation_report::{boot_log::BootLog, platform_claims::PlatformClaims, report_data::WorkloadIdentity};
 use attestation_verification::{
-    DefaultCertificateFetcher, ErrorCode, MeasurementGenerator, ReportBundle, ReportFetcher, ReportResponse,
-    ReportVerifier, ValidateError, VmType, report::DefaultReportArtifactsDownloader,
+    ArtifactCache, BootLogVerifier, DefaultCertificateFetcher, ErrorCode, MeasurementExplainer, MeasurementGenerator,
+    PlatformClaimsVerifier, ProofBundle, RecordingCertificateFetcher, ReportBundle, ReportFetcher, ReportResponse,
+    ReportVerifier, ValidateError, VerificationError, VmType, report::DefaultReportArtifactsDownloader,
 };
 use clap::{Args, CommandFactory, Parser, Subcommand, error::ErrorKind};
-use nilcc_artifacts::{Artifacts, downloader::ArtifactsDownloader, metadata::ArtifactsMetadata};
+use nilcc_artifacts::{
+    Artifacts,
+    downloader::ArtifactsDownloader,
+    metadata::{ArtifactsMetadata, GuestPolicy},
+    signature::SigningKey,
+};
 use serde::Serialize;
 use std::{
     fs,
+    io::{self, Read},
     net::SocketAddr,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     process::exit,
     sync::Arc,
@@ -18,8 +32,12 @@ use tokio::{
     net::TcpListener,
     signal::{self, unix::SignalKind},
 };
-use tracing::{error, info, level_filters::LevelFilter};
+use tracing::{error, info, level_filters::LevelFilter, warn};
 
+mod compose;
+mod inspect;
+mod jobs;
+mod monitor;
 mod routes;
 
 #[derive(Parser)]
@@ -44,6 +62,54 @@ enum Command {
 
     /// Start an HTTP API that allows validating attestations.
     Serve(ServeArgs),
+
+    /// Validate a workload and export a proof bundle that can be re-verified offline later.
+    ExportProof(ExportProofArgs),
+
+    /// Verify a proof bundle created via `export-proof` without any network access.
+    VerifyProof(VerifyProofArgs),
+
+    /// Continuously validate a set of workloads, exporting metrics and sending alerts on failures.
+    Monitor(MonitorCommandArgs),
+
+    /// Print a breakdown of the environment a workload attests to, without validating its measurement.
+    Inspect(InspectArgs),
+
+    /// Compute the hash of a docker compose file, as measured by the CVM that runs it.
+    ComposeHash(ComposeHashArgs),
+
+    /// Manage the artifacts cache.
+    #[clap(subcommand)]
+    Cache(CacheCommand),
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove every cached artifacts version that isn't being downloaded.
+    Purge(CachePurgeArgs),
+}
+
+#[derive(Args, Clone)]
+struct ArtifactCacheArgs {
+    /// The path where artifacts will be cached.
+    #[clap(short, long, default_value = default_artifact_cache_path().into_os_string())]
+    artifact_cache: PathBuf,
+
+    /// The maximum size of the artifacts cache, in MB.
+    ///
+    /// Once the cache grows past it, the least recently used artifacts versions are evicted.
+    #[clap(long, env = "NILCC_VERIFIER_ARTIFACT_CACHE_MAX_SIZE_MB")]
+    artifact_cache_max_size_mb: Option<u64>,
+}
+
+impl ArtifactCacheArgs {
+    fn build(&self) -> ArtifactCache {
+        let cache = ArtifactCache::new(self.artifact_cache.clone());
+        match self.artifact_cache_max_size_mb {
+            Some(max_size_mb) => cache.with_max_size(max_size_mb.saturating_mul(1024 * 1024)),
+            None => cache,
+        }
+    }
 }
 
 #[derive(Args)]
@@ -85,9 +151,8 @@ struct ValidateArgs {
     /// The public endpoint for the CVM, e.g. `https://example.com`
     endpoint: String,
 
-    /// The path where artifacts will be cached.
-    #[clap(short, long, default_value = default_artifact_cache_path().into_os_string())]
-    artifact_cache: PathBuf,
+    #[clap(flatten)]
+    artifact_cache: ArtifactCacheArgs,
 
     /// The path where certificates will be cached.
     #[clap(short, long, default_value = default_cert_cache_path().into_os_string())]
@@ -103,6 +168,48 @@ struct ValidateArgs {
     /// The domain where the processor's VCEK cert should be fetched from.
     #[clap(long)]
     processor_cert_domain: Option<String>,
+
+    /// The URL of a KDS mirror to fetch VCEK certificates from before falling back to AMD's KDS.
+    #[clap(long)]
+    kds_mirror_url: Option<String>,
+
+    /// The id of the workload the report is expected to belong to.
+    #[clap(long)]
+    workload_id: Option<String>,
+
+    /// Accept reports that aren't bound to a workload identity.
+    #[clap(long, conflicts_with = "workload_id")]
+    allow_unbound: bool,
+
+    /// Print a breakdown of the measurement's inputs to stderr if it doesn't match the one in the report.
+    #[clap(long, conflicts_with = "ignore_measurement_hash")]
+    explain: bool,
+
+    /// Request a report that binds the CVM's boot log and check that the log is consistent with the measurement.
+    #[clap(long, conflicts_with = "ignore_measurement_hash")]
+    include_boot_log: bool,
+
+    /// A key trusted to sign artifacts metadata, as `<name>:<hex ed25519 public key>`.
+    ///
+    /// If any are set, the artifacts metadata must be signed by one of these keys.
+    #[clap(long = "signing-key")]
+    signing_keys: Vec<SigningKey>,
+
+    /// A hex encoded secp256k1 public key trusted to sign the platform claims CVMs expose.
+    ///
+    /// If any are set, the CVM must expose platform claims signed by one of these keys for its workload.
+    #[clap(long = "trusted-agent-key", value_parser = parse_agent_key)]
+    trusted_agent_keys: Vec<AgentKey>,
+}
+
+/// A compressed secp256k1 public key an agent signs platform claims with.
+#[derive(Clone)]
+struct AgentKey(Vec<u8>);
+
+fn parse_agent_key(input: &str) -> Result<AgentKey, String> {
+    let mut key = [0; 33];
+    hex::decode_to_slice(input, &mut key).map_err(|_| "expected a hex encoded 33 byte compressed secp256k1 key")?;
+    Ok(AgentKey(key.to_vec()))
 }
 
 #[derive(Args)]
@@ -119,9 +226,8 @@ struct Measurement {
 
 #[derive(Args)]
 struct MeasurementHashArgs {
-    /// The path where artifacts will be cached.
-    #[clap(short, long, default_value = default_artifact_cache_path().into_os_string())]
-    artifact_cache: PathBuf,
+    #[clap(flatten)]
+    artifact_cache: ArtifactCacheArgs,
 
     /// The base url from which artifacts should be fetched.
     #[clap(long, default_value = default_artifacts_url())]
@@ -140,6 +246,12 @@ struct MeasurementHashArgs {
 
     /// The nilcc artifacts version that's being used.
     nilcc_version: String,
+
+    /// A key trusted to sign artifacts metadata, as `<name>:<hex ed25519 public key>`.
+    ///
+    /// If any are set, the artifacts metadata must be signed by one of these keys.
+    #[clap(long = "signing-key")]
+    signing_keys: Vec<SigningKey>,
 }
 
 #[derive(Args)]
@@ -159,6 +271,12 @@ struct DownloadArtifactsArgs {
     /// The base url from which artifacts should be fetched.
     #[clap(long, default_value = default_artifacts_url())]
     artifacts_url: String,
+
+    /// A key trusted to sign artifacts metadata, as `<name>:<hex ed25519 public key>`.
+    ///
+    /// If any are set, the artifacts metadata must be signed by one of these keys.
+    #[clap(long = "signing-key")]
+    signing_keys: Vec<SigningKey>,
 }
 
 #[derive(Args)]
@@ -167,13 +285,161 @@ struct ServeArgs {
     #[clap(short, long, default_value = "0.0.0.0:8080")]
     bind_endpoint: SocketAddr,
 
-    /// The path where artifacts will be cached.
-    #[clap(short, long, default_value = default_artifact_cache_path().into_os_string())]
-    artifact_cache: PathBuf,
+    #[clap(flatten)]
+    artifact_cache: ArtifactCacheArgs,
 
     /// The path where certificates will be cached.
     #[clap(short, long, default_value = default_cert_cache_path().into_os_string())]
     cert_cache: PathBuf,
+
+    /// The maximum number of reports verified concurrently by each batch or job.
+    #[clap(long, default_value = "8")]
+    max_concurrency: NonZeroUsize,
+
+    /// The maximum number of reports verified concurrently across every batch and job.
+    #[clap(long, default_value = "32")]
+    max_total_concurrency: NonZeroUsize,
+
+    /// The URL of a KDS mirror to fetch VCEK certificates from before falling back to AMD's KDS.
+    #[clap(long)]
+    kds_mirror_url: Option<String>,
+}
+
+#[derive(Args)]
+struct ExportProofArgs {
+    /// The public endpoint for the CVM, e.g. `https://example.com`
+    endpoint: String,
+
+    /// The path where the proof bundle will be written to.
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// The docker compose hash that the CVM executes.
+    #[clap(long)]
+    docker_compose_hash: String,
+
+    #[clap(flatten)]
+    artifact_cache: ArtifactCacheArgs,
+
+    /// The path where certificates will be cached.
+    #[clap(short, long, default_value = default_cert_cache_path().into_os_string())]
+    cert_cache: PathBuf,
+
+    /// The base url from which artifacts should be fetched.
+    #[clap(long, default_value = default_artifacts_url())]
+    artifacts_url: String,
+
+    /// The domain where the processor's VCEK cert should be fetched from.
+    #[clap(long)]
+    processor_cert_domain: Option<String>,
+
+    /// The URL of a KDS mirror to fetch VCEK certificates from before falling back to AMD's KDS.
+    #[clap(long)]
+    kds_mirror_url: Option<String>,
+
+    /// A key trusted to sign artifacts metadata, as `<name>:<hex ed25519 public key>`.
+    ///
+    /// If any are set, the artifacts metadata must be signed by one of these keys.
+    #[clap(long = "signing-key")]
+    signing_keys: Vec<SigningKey>,
+}
+
+#[derive(Args)]
+struct VerifyProofArgs {
+    /// The path to the proof bundle.
+    proof: PathBuf,
+
+    /// The path to the artifacts the CVM was booted with, e.g. as downloaded via `download-artifacts`.
+    ///
+    /// If set, the measurement is regenerated from these artifacts rather than using the one in the bundle.
+    #[clap(long)]
+    artifacts_path: Option<PathBuf>,
+
+    /// Reject proofs for CVMs that were launched with a policy that allows SMT.
+    ///
+    /// The guest policy in the bundle is not trusted, the report is always checked against the one set here.
+    #[clap(long)]
+    disallow_smt: bool,
+}
+
+#[derive(Args)]
+struct MonitorCommandArgs {
+    /// The path to the monitor's configuration file.
+    config: PathBuf,
+
+    #[clap(flatten)]
+    artifact_cache: ArtifactCacheArgs,
+
+    /// The path where certificates will be cached.
+    #[clap(short, long, default_value = default_cert_cache_path().into_os_string())]
+    cert_cache: PathBuf,
+
+    /// The base url from which artifacts should be fetched.
+    #[clap(long, default_value = default_artifacts_url())]
+    artifacts_url: String,
+
+    /// The domain where the processor's VCEK cert should be fetched from.
+    #[clap(long)]
+    processor_cert_domain: Option<String>,
+
+    /// The URL of a KDS mirror to fetch VCEK certificates from before falling back to AMD's KDS.
+    #[clap(long)]
+    kds_mirror_url: Option<String>,
+}
+
+#[derive(Args)]
+struct InspectArgs {
+    /// The public endpoint for the CVM, e.g. `https://example.com`
+    endpoint: String,
+
+    /// Request a report that binds the CVM's boot log and include the log in the output.
+    #[clap(long)]
+    include_boot_log: bool,
+
+    #[clap(flatten)]
+    artifact_cache: ArtifactCacheArgs,
+
+    /// The path where certificates will be cached.
+    #[clap(short, long, default_value = default_cert_cache_path().into_os_string())]
+    cert_cache: PathBuf,
+
+    /// The base url from which artifacts should be fetched.
+    #[clap(long, default_value = default_artifacts_url())]
+    artifacts_url: String,
+
+    /// The domain where the processor's VCEK cert should be fetched from.
+    #[clap(long)]
+    processor_cert_domain: Option<String>,
+
+    /// The URL of a KDS mirror to fetch VCEK certificates from before falling back to AMD's KDS.
+    #[clap(long)]
+    kds_mirror_url: Option<String>,
+
+    /// A key trusted to sign artifacts metadata, as `<name>:<hex ed25519 public key>`.
+    ///
+    /// If any are set, the artifacts metadata must be signed by one of these keys.
+    #[clap(long = "signing-key")]
+    signing_keys: Vec<SigningKey>,
+}
+
+#[derive(Args)]
+struct CachePurgeArgs {
+    /// The path where artifacts are cached.
+    #[clap(short, long, default_value = default_artifact_cache_path().into_os_string())]
+    artifact_cache: PathBuf,
+}
+
+#[derive(Args)]
+struct ComposeHashArgs {
+    /// The path to the docker compose file or '-' for stdin.
+    path: String,
+
+    /// Compare the hash against the one reported in the boot log of the CVM at this public endpoint, e.g.
+    /// `https://example.com`.
+    ///
+    /// This doesn't validate the CVM's attestation report, use `validate` for that.
+    #[clap(long)]
+    check: Option<String>,
 }
 
 fn default_cache_path() -> PathBuf {
@@ -192,6 +458,32 @@ fn default_artifacts_url() -> String {
     "https://nilcc.s3.eu-west-1.amazonaws.com".into()
 }
 
+fn build_cert_fetcher(
+    cert_cache: PathBuf,
+    processor_cert_domain: Option<String>,
+    kds_mirror_url: Option<String>,
+) -> Result<DefaultCertificateFetcher, ValidateError> {
+    let mut fetcher = DefaultCertificateFetcher::new(cert_cache).map_err(ValidateError::CertCacheDirectories)?;
+    if let Some(domain) = processor_cert_domain {
+        fetcher = fetcher.with_processor_cert_domain(domain);
+    }
+    if let Some(url) = kds_mirror_url {
+        fetcher = fetcher.with_mirror_url(url);
+    }
+    Ok(fetcher)
+}
+
+fn build_artifacts_downloader(signing_keys: Vec<SigningKey>) -> DefaultReportArtifactsDownloader {
+    warn_if_no_signing_keys(&signing_keys);
+    DefaultReportArtifactsDownloader::new(signing_keys)
+}
+
+fn warn_if_no_signing_keys(signing_keys: &[SigningKey]) {
+    if signing_keys.is_empty() {
+        warn!("No artifacts signing keys were given, the artifacts metadata signature won't be verified");
+    }
+}
+
 fn decode_compose_hash(input: &str) -> Result<[u8; 32], ValidateError> {
     let mut hash: [u8; 32] = [0; 32];
     hex::decode_to_slice(input, &mut hash).map_err(|_| ValidateError::DockerComposeHash)?;
@@ -199,27 +491,91 @@ fn decode_compose_hash(input: &str) -> Result<[u8; 32], ValidateError> {
 }
 
 async fn validate(args: ValidateArgs) -> Result<ReportMetadata, ValidateError> {
-    let ValidateArgs { endpoint, artifact_cache, cert_cache, measurement, artifacts_url, processor_cert_domain } = args;
-    let fetcher = ReportFetcher::new(artifact_cache.clone(), artifacts_url, Box::new(DefaultReportArtifactsDownloader));
+    let ValidateArgs {
+        endpoint,
+        artifact_cache,
+        cert_cache,
+        measurement,
+        artifacts_url,
+        processor_cert_domain,
+        kds_mirror_url,
+        workload_id,
+        allow_unbound,
+        explain,
+        include_boot_log,
+        signing_keys,
+        trusted_agent_keys,
+    } = args;
+    let artifact_cache = artifact_cache.build();
+    let downloader = build_artifacts_downloader(signing_keys);
+    let mut fetcher = ReportFetcher::new(artifact_cache.clone(), artifacts_url, Box::new(downloader));
+    if include_boot_log {
+        fetcher = fetcher.with_boot_log();
+    }
     let bundle = fetcher.fetch_report(&endpoint).await?;
-    let ReportBundle { cpu_count, metadata_hash, tls_fingerprint, nilcc_version, metadata, vm_type, .. } = bundle;
+    let ReportBundle {
+        cpu_count,
+        metadata_hash,
+        metadata_signer,
+        tls_fingerprint,
+        nilcc_version,
+        metadata,
+        vm_type,
+        identity,
+        boot_log,
+        platform_claims,
+        ..
+    } = bundle;
+    match (&identity, workload_id) {
+        (Some(identity), Some(expected)) if identity.workload_id != expected => {
+            return Err(ValidateError::WorkloadMismatch { expected, actual: identity.workload_id.clone() });
+        }
+        (None, _) if !allow_unbound => return Err(ValidateError::UnboundWorkload),
+        _ => (),
+    };
 
-    let artifacts_path = artifact_cache.join(&nilcc_version);
-    let measurement = match measurement.ignore_measurement_hash {
-        true => bundle.report.measurement.to_vec(),
+    let artifacts_path = artifact_cache.version_path(&nilcc_version);
+    let (measurement, generator) = match measurement.ignore_measurement_hash {
+        true => (bundle.report.measurement.to_vec(), None),
         false => {
             let docker_compose_hash = measurement.docker_compose_hash.expect("no docker compose hash");
             let docker_compose_hash = decode_compose_hash(&docker_compose_hash)?;
-            MeasurementGenerator::new(docker_compose_hash, cpu_count, vm_type.into(), &metadata, &artifacts_path)
-                .generate()?
+            let generator =
+                MeasurementGenerator::new(docker_compose_hash, cpu_count, vm_type.into(), &metadata, &artifacts_path);
+            (generator.clone().generate()?, Some(generator))
         }
     };
-    let mut fetcher = DefaultCertificateFetcher::new(cert_cache).map_err(ValidateError::CertCacheDirectories)?;
-    if let Some(domain) = processor_cert_domain {
-        fetcher = fetcher.with_processor_cert_domain(domain);
-    }
+    let fetcher = build_cert_fetcher(cert_cache, processor_cert_domain, kds_mirror_url)?;
     let verifier = ReportVerifier::new(Arc::new(fetcher));
-    verifier.verify_report(&bundle.report, &measurement).await?;
+    let result = verifier.verify_report(&bundle.report, &measurement, &metadata.guest_policy).await;
+    if explain
+        && let Err(VerificationError::InvalidMeasurement { .. }) = &result
+        && let Some(generator) = &generator
+    {
+        let explainer =
+            MeasurementExplainer { generator: generator.clone(), metadata: &metadata, vm_type: vm_type.into() };
+        // This is best effort, failing to explain the mismatch must not hide it.
+        match explainer.explain(&measurement, &bundle.report.measurement) {
+            Ok(explanation) => eprintln!("{explanation}"),
+            Err(e) => warn!("Failed to explain measurement mismatch: {e}"),
+        }
+    }
+    result?;
+    if let (Some(boot_log), Some(generator)) = (&boot_log, &generator) {
+        BootLogVerifier { generator }.verify(boot_log)?;
+        info!("Boot log is consistent with the measurement");
+    }
+    let platform = match (trusted_agent_keys.is_empty(), &platform_claims) {
+        (true, _) => None,
+        (false, None) => return Err(ValidateError::MissingPlatformClaims),
+        (false, Some(signed)) => {
+            let trusted_keys: Vec<_> = trusted_agent_keys.into_iter().map(|key| key.0).collect();
+            let verifier = PlatformClaimsVerifier { trusted_keys: &trusted_keys, identity: identity.as_ref() };
+            let claims = verifier.verify(signed)?;
+            info!("Platform claims are signed by trusted agent key {}", hex::encode(&signed.public_key));
+            Some(claims.clone())
+        }
+    };
 
     let github_actions_build_url = metadata.build.as_ref().map(|b| {
         let id = b.github_action_run_id;
@@ -231,20 +587,36 @@ async fn validate(args: ValidateArgs) -> Result<ReportMetadata, ValidateError> {
         measurement_hash: hex::encode(measurement),
         metadata_hash,
         tls_fingerprint,
-        artifacts: ReportArtifacts { version: nilcc_version, metadata },
+        workload: identity,
+        artifacts: ReportArtifacts { version: nilcc_version, signer: metadata_signer, metadata },
+        boot_log,
+        platform,
     };
     Ok(meta)
 }
 
 async fn compute_measurement_hash(args: MeasurementHashArgs) -> anyhow::Result<()> {
-    let MeasurementHashArgs { artifact_cache, artifacts_url, vm_type, cpus, docker_compose_hash, nilcc_version } = args;
-    let download_path = artifact_cache.join(&nilcc_version);
+    let MeasurementHashArgs {
+        artifact_cache,
+        artifacts_url,
+        vm_type,
+        cpus,
+        docker_compose_hash,
+        nilcc_version,
+        signing_keys,
+    } = args;
+    let artifact_cache = artifact_cache.build();
     let docker_compose_hash = decode_compose_hash(&docker_compose_hash)?;
+    let cached = artifact_cache.lock(&nilcc_version).await.context("Failed to lock cached artifacts")?;
+    let download_path = cached.path();
+    warn_if_no_signing_keys(&signing_keys);
     let downloader = ArtifactsDownloader::new(nilcc_version.clone(), vec![vm_type.into()])
         .without_disk_images()
         .without_artifact_overwrite()
-        .with_artifacts_url(artifacts_url);
-    let artifacts = downloader.download(&download_path).await?;
+        .with_artifacts_url(artifacts_url)
+        .with_signing_keys(signing_keys);
+    let artifacts = downloader.download(download_path).await?;
+    artifact_cache.evict().context("Failed to evict cached artifacts")?;
     let Artifacts { metadata, .. } = artifacts;
     let vm_type_metadata = metadata.cvm.images.resolve(vm_type.into());
     let filesystem_root_hash = vm_type_metadata.verity.root_hash;
@@ -259,6 +631,7 @@ async fn compute_measurement_hash(args: MeasurementHashArgs) -> anyhow::Result<(
         docker_compose_hash,
         filesystem_root_hash,
         kernel_args: metadata.cvm.cmdline.clone(),
+        cpu_model: metadata.cpu.model,
     }
     .generate()?;
     let measurement = hex::encode(&measurement);
@@ -268,7 +641,7 @@ async fn compute_measurement_hash(args: MeasurementHashArgs) -> anyhow::Result<(
 }
 
 async fn download_artifacts(args: DownloadArtifactsArgs) -> anyhow::Result<()> {
-    let DownloadArtifactsArgs { workload_url, artifacts_version, output_directory, artifacts_url } = args;
+    let DownloadArtifactsArgs { workload_url, artifacts_version, output_directory, artifacts_url, signing_keys } = args;
     let version = match (workload_url, artifacts_version) {
         (Some(workload_url), None) => {
             let report_url = format!("{workload_url}/nilcc/api/v2/report");
@@ -293,24 +666,184 @@ async fn download_artifacts(args: DownloadArtifactsArgs) -> anyhow::Result<()> {
         None => format!("nilcc-{version}"),
     };
     fs::create_dir_all(&output_directory).context("Failed to create output directory")?;
+    warn_if_no_signing_keys(&signing_keys);
     let downloader = ArtifactsDownloader::new(version, vec![VmType::Cpu.into(), VmType::Gpu.into()])
-        .with_artifacts_url(artifacts_url);
+        .with_artifacts_url(artifacts_url)
+        .with_signing_keys(signing_keys);
     println!("Downloading artifacts...");
     let artifacts = downloader.download(Path::new(&output_directory)).await?;
     let metadata_hash = hex::encode(artifacts.metadata_hash);
     println!("Artifacts downloaded at {output_directory}, metadata hash = {metadata_hash}");
+    if let Some(signer) = artifacts.signer {
+        println!("Artifacts metadata signed by {signer}");
+    }
     Ok(())
 }
 
 async fn serve(args: ServeArgs) -> anyhow::Result<()> {
-    let ServeArgs { bind_endpoint, artifact_cache, cert_cache } = args;
-    let router = build_router(cert_cache, artifact_cache).context("building HTTP router")?;
+    let ServeArgs { bind_endpoint, artifact_cache, cert_cache, max_concurrency, max_total_concurrency, kds_mirror_url } =
+        args;
+    let router = build_router(
+        cert_cache,
+        artifact_cache.build(),
+        max_concurrency.get(),
+        max_total_concurrency.get(),
+        kds_mirror_url,
+    )
+    .context("building HTTP router")?;
     let listener = TcpListener::bind(bind_endpoint).await.expect("failed to bind");
     info!("Launching server in {bind_endpoint}");
     axum::serve(listener, router).with_graceful_shutdown(shutdown_signal()).await?;
     Ok(())
 }
 
+async fn export_proof(args: ExportProofArgs) -> anyhow::Result<()> {
+    let ExportProofArgs {
+        endpoint,
+        output,
+        docker_compose_hash,
+        artifact_cache,
+        cert_cache,
+        artifacts_url,
+        processor_cert_domain,
+        kds_mirror_url,
+        signing_keys,
+    } = args;
+    let docker_compose_hash = decode_compose_hash(&docker_compose_hash)?;
+    let artifact_cache = artifact_cache.build();
+    let downloader = build_artifacts_downloader(signing_keys);
+    let fetcher = ReportFetcher::new(artifact_cache.clone(), artifacts_url, Box::new(downloader));
+    let bundle = fetcher.fetch_report(&endpoint).await?;
+
+    let artifacts_path = artifact_cache.version_path(&bundle.nilcc_version);
+    let generator = MeasurementGenerator::new(
+        docker_compose_hash,
+        bundle.cpu_count,
+        bundle.vm_type.into(),
+        &bundle.metadata,
+        &artifacts_path,
+    );
+    let measurement = generator.clone().generate()?;
+    let cert_fetcher = build_cert_fetcher(cert_cache, processor_cert_domain, kds_mirror_url)?;
+    let cert_fetcher = Arc::new(RecordingCertificateFetcher::new(Arc::new(cert_fetcher)));
+    let verifier = ReportVerifier::new(cert_fetcher.clone());
+    verifier.verify_report(&bundle.report, &measurement, &bundle.metadata.guest_policy).await?;
+    let certs = cert_fetcher.take_certs().context("No certificates were fetched")?;
+
+    let proof = ProofBundle::new(endpoint, bundle, certs, &generator, measurement)?;
+    let proof = serde_json::to_string_pretty(&proof).context("Failed to serialize proof")?;
+    fs::write(&output, proof).context("Failed to write proof")?;
+    println!("Proof written to {}", output.display());
+    Ok(())
+}
+
+async fn verify_proof(args: VerifyProofArgs) -> anyhow::Result<()> {
+    let VerifyProofArgs { proof, artifacts_path, disallow_smt } = args;
+    let proof = fs::read_to_string(&proof).context("Failed to read proof")?;
+    let proof: ProofBundle = serde_json::from_str(&proof).context("Malformed proof")?;
+    let required_policy = GuestPolicy { smt: !disallow_smt, ..Default::default() };
+    proof.verify(artifacts_path.as_deref(), &required_policy).await?;
+    let measurement = hex::encode(&proof.measurement.measurement);
+    println!("Proof for {} is valid, measurement = {measurement}", proof.endpoint);
+    Ok(())
+}
+
+async fn monitor(args: MonitorCommandArgs) -> anyhow::Result<()> {
+    let MonitorCommandArgs { config, artifact_cache, cert_cache, artifacts_url, processor_cert_domain, kds_mirror_url } =
+        args;
+    let config = fs::read(&config).context("Failed to read config file")?;
+    let config: MonitorConfig = serde_yaml::from_slice(&config).context("Failed to deserialize config file")?;
+    let monitor = Monitor::new(MonitorArgs {
+        config,
+        artifact_cache,
+        cert_cache,
+        artifacts_url,
+        processor_cert_domain,
+        kds_mirror_url,
+    })?;
+    tokio::select! {
+        _ = monitor.run() => (),
+        _ = shutdown_signal() => (),
+    }
+    Ok(())
+}
+
+async fn inspect(args: InspectArgs) -> anyhow::Result<()> {
+    let InspectArgs {
+        endpoint,
+        include_boot_log,
+        artifact_cache,
+        cert_cache,
+        artifacts_url,
+        processor_cert_domain,
+        kds_mirror_url,
+        signing_keys,
+    } = args;
+    let downloader = build_artifacts_downloader(signing_keys);
+    let mut fetcher = ReportFetcher::new(artifact_cache.build(), artifacts_url, Box::new(downloader));
+    if include_boot_log {
+        fetcher = fetcher.with_boot_log();
+    }
+    let bundle = fetcher.fetch_report(&endpoint).await?;
+
+    // We don't know the expected measurement so only check that the report was signed by an AMD CPU.
+    let cert_fetcher = build_cert_fetcher(cert_cache, processor_cert_domain, kds_mirror_url)?;
+    let verifier = ReportVerifier::new(Arc::new(cert_fetcher));
+    verifier.verify_report(&bundle.report, &bundle.report.measurement, &bundle.metadata.guest_policy).await?;
+
+    let report = InspectReport::new(&bundle)?;
+    println!("{}", serde_json::to_string_pretty(&report).context("Failed to serialize report")?);
+    Ok(())
+}
+
+fn purge_cache(args: CachePurgeArgs) -> anyhow::Result<()> {
+    let CachePurgeArgs { artifact_cache } = args;
+    let purged = ArtifactCache::new(artifact_cache).purge().context("Failed to purge artifacts cache")?;
+    for version in &purged {
+        println!("Removed artifacts version {version}");
+    }
+    println!("Removed {} cached artifacts versions", purged.len());
+    Ok(())
+}
+
+async fn compose_hash(args: ComposeHashArgs) -> anyhow::Result<bool> {
+    let ComposeHashArgs { path, check } = args;
+    let contents = match path.as_str() {
+        "-" => {
+            let mut contents = Vec::new();
+            io::stdin().read_to_end(&mut contents).context("Failed to read stdin")?;
+            contents
+        }
+        path => fs::read(path).context("Failed to read docker compose file")?,
+    };
+    let ComposeHash { hash, warnings } = ComposeHash::new(&contents)?;
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    let hash = hex::encode(hash);
+    println!("{hash}");
+
+    let Some(endpoint) = check else {
+        return Ok(true);
+    };
+    let report_url = format!("{endpoint}/nilcc/api/v2/report?include_boot_log=true");
+    let report: ReportResponse = reqwest::get(report_url)
+        .await
+        .context("Failed to fetch attestation report")?
+        .json()
+        .await
+        .context("Malformed attestation report")?;
+    let boot_log = report.boot_log.context("CVM did not return a boot log")?;
+    let boot_log: BootLog = serde_json::from_str(&boot_log).context("Malformed boot log")?;
+    if boot_log.docker_compose_hash == hash {
+        println!("Hash matches the one reported by the CVM");
+        Ok(true)
+    } else {
+        println!("Hash does not match the one reported by the CVM: {}", boot_log.docker_compose_hash);
+        Ok(false)
+    }
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c().await.expect("failed to install ctrl-c handler");
@@ -337,12 +870,20 @@ struct ReportMetadata {
     metadata_hash: String,
     measurement_hash: String,
     tls_fingerprint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workload: Option<WorkloadIdentity>,
     artifacts: ReportArtifacts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    boot_log: Option<BootLog>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<PlatformClaims>,
 }
 
 #[derive(Serialize)]
 struct ReportArtifacts {
     version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signer: Option<String>,
     #[serde(flatten)]
     metadata: ArtifactsMetadata,
 }
@@ -400,5 +941,43 @@ async fn main() {
                 exit(1);
             }
         }
+        Command::ExportProof(args) => {
+            if let Err(e) = export_proof(args).await {
+                error!("Failed to export proof: {e:#}");
+                exit(1);
+            }
+        }
+        Command::VerifyProof(args) => {
+            if let Err(e) = verify_proof(args).await {
+                error!("Failed to verify proof: {e:#}");
+                exit(1);
+            }
+        }
+        Command::Monitor(args) => {
+            if let Err(e) = monitor(args).await {
+                error!("Failed to run monitor: {e:#}");
+                exit(1);
+            }
+        }
+        Command::Inspect(args) => {
+            if let Err(e) = inspect(args).await {
+                error!("Failed to inspect workload: {e:#}");
+                exit(1);
+            }
+        }
+        Command::Cache(CacheCommand::Purge(args)) => {
+            if let Err(e) = purge_cache(args) {
+                error!("Failed to purge cache: {e:#}");
+                exit(1);
+            }
+        }
+        Command::ComposeHash(args) => match compose_hash(args).await {
+            Ok(true) => (),
+            Ok(false) => exit(1),
+            Err(e) => {
+                error!("Failed to compute compose hash: {e:#}");
+                exit(1);
+            }
+        },
     }
 }
@@ -0,0 +1,382 @@
+use crate::{ArtifactCacheArgs, Measurement, ReportMetadata, ValidateArgs, validate};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use metrics::{counter, gauge};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use reqwest::{Client, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_with::{DurationSeconds, serde_as};
+use sha2::Sha256;
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, time::Duration};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+/// The header that contains the signature of a webhook request's body.
+const SIGNATURE_HEADER: &str = "x-nilcc-signature";
+
+/// The configuration for the monitor.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+pub(crate) struct MonitorConfig {
+    /// The workloads to monitor.
+    pub(crate) targets: Vec<TargetConfig>,
+
+    /// How often every workload is validated.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_interval")]
+    pub(crate) interval_seconds: Duration,
+
+    /// The endpoint where Prometheus metrics are exported.
+    #[serde(default = "default_metrics_bind_endpoint")]
+    pub(crate) metrics_bind_endpoint: SocketAddr,
+
+    /// The webhooks alerts are sent to.
+    #[serde(default)]
+    pub(crate) webhooks: Vec<WebhookConfig>,
+
+    /// The timeout for a single webhook request.
+    #[serde_as(as = "DurationSeconds")]
+    #[serde(default = "default_webhook_timeout")]
+    pub(crate) webhook_timeout_seconds: Duration,
+}
+
+/// A workload to monitor.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct TargetConfig {
+    /// The name used to identify this workload in metrics and alerts.
+    pub(crate) name: String,
+
+    /// The public endpoint for the CVM, e.g. `https://example.com`.
+    pub(crate) endpoint: String,
+
+    /// The docker compose hash that the CVM is expected to execute.
+    pub(crate) docker_compose_hash: String,
+
+    /// The id of the workload the report is expected to belong to.
+    #[serde(default)]
+    pub(crate) workload_id: Option<String>,
+
+    /// Whether to accept reports that aren't bound to a workload identity.
+    #[serde(default)]
+    pub(crate) allow_unbound: bool,
+}
+
+/// A webhook alerts are sent to.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct WebhookConfig {
+    /// The URL to POST alerts to.
+    pub(crate) url: String,
+
+    /// The secret used to sign every request using HMAC-SHA256.
+    pub(crate) secret: String,
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_metrics_bind_endpoint() -> SocketAddr {
+    ([0, 0, 0, 0], 9091).into()
+}
+
+fn default_webhook_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+pub(crate) struct MonitorArgs {
+    pub(crate) config: MonitorConfig,
+    pub(crate) artifact_cache: ArtifactCacheArgs,
+    pub(crate) cert_cache: PathBuf,
+    pub(crate) artifacts_url: String,
+    pub(crate) processor_cert_domain: Option<String>,
+    pub(crate) kds_mirror_url: Option<String>,
+}
+
+/// Continuously validates a set of workloads, exporting metrics and sending alerts when anything changes.
+pub(crate) struct Monitor {
+    targets: Vec<TargetConfig>,
+    interval: Duration,
+    webhooks: Vec<WebhookConfig>,
+    client: Client,
+    artifact_cache: ArtifactCacheArgs,
+    cert_cache: PathBuf,
+    artifacts_url: String,
+    processor_cert_domain: Option<String>,
+    kds_mirror_url: Option<String>,
+    states: HashMap<String, TargetState>,
+}
+
+impl Monitor {
+    pub(crate) fn new(args: MonitorArgs) -> anyhow::Result<Self> {
+        let MonitorArgs { config, artifact_cache, cert_cache, artifacts_url, processor_cert_domain, kds_mirror_url } =
+            args;
+        let MonitorConfig { targets, interval_seconds, metrics_bind_endpoint, webhooks, webhook_timeout_seconds } =
+            config;
+        PrometheusBuilder::default()
+            .with_http_listener(metrics_bind_endpoint)
+            .install()
+            .context("Failed to start metrics exporter")?;
+        let client =
+            Client::builder().timeout(webhook_timeout_seconds).build().context("Failed to build reqwest client")?;
+        Ok(Self {
+            targets,
+            interval: interval_seconds,
+            webhooks,
+            client,
+            artifact_cache,
+            cert_cache,
+            artifacts_url,
+            processor_cert_domain,
+            kds_mirror_url,
+            states: Default::default(),
+        })
+    }
+
+    pub(crate) async fn run(mut self) {
+        info!("Monitoring {} workloads every {:?}", self.targets.len(), self.interval);
+        loop {
+            for target in self.targets.clone() {
+                self.check(&target).await;
+            }
+            sleep(self.interval).await;
+        }
+    }
+
+    async fn check(&mut self, target: &TargetConfig) {
+        let TargetConfig { name, endpoint, docker_compose_hash, workload_id, allow_unbound } = target;
+        info!("Validating workload {name} at {endpoint}");
+        let args = ValidateArgs {
+            endpoint: endpoint.clone(),
+            artifact_cache: self.artifact_cache.clone(),
+            cert_cache: self.cert_cache.clone(),
+            measurement: Measurement {
+                docker_compose_hash: Some(docker_compose_hash.clone()),
+                ignore_measurement_hash: false,
+            },
+            artifacts_url: self.artifacts_url.clone(),
+            processor_cert_domain: self.processor_cert_domain.clone(),
+            kds_mirror_url: self.kds_mirror_url.clone(),
+            workload_id: workload_id.clone(),
+            allow_unbound: *allow_unbound,
+            explain: false,
+            include_boot_log: false,
+            signing_keys: Vec::new(),
+            trusted_agent_keys: Vec::new(),
+        };
+        let outcome = match validate(args).await {
+            Ok(metadata) => {
+                let ReportMetadata { measurement_hash, tls_fingerprint, .. } = metadata;
+                Outcome::Valid { measurement_hash, tls_fingerprint }
+            }
+            Err(e) => {
+                warn!("Validation for workload {name} failed: {e}");
+                Outcome::Invalid { error: e.to_string() }
+            }
+        };
+        record_metrics(name, &outcome);
+        let state = self.states.entry(name.clone()).or_default();
+        for kind in state.observe(&outcome) {
+            match &kind {
+                AlertKind::MeasurementChanged { previous, .. } => {
+                    counter!("verifier_measurement_hash_changes_total", "target" => name.clone()).increment(1);
+                    gauge!(
+                        "verifier_measurement_info",
+                        "target" => name.clone(),
+                        "measurement_hash" => previous.clone()
+                    )
+                    .set(0);
+                }
+                AlertKind::TlsFingerprintChanged { .. } => {
+                    counter!("verifier_tls_fingerprint_changes_total", "target" => name.clone()).increment(1);
+                }
+                AlertKind::ValidationFailed { .. } | AlertKind::Recovered => (),
+            }
+            let alert = Alert { target: name.clone(), endpoint: endpoint.clone(), kind, timestamp: Utc::now() };
+            self.send_alert(&alert).await;
+        }
+    }
+
+    async fn send_alert(&self, alert: &Alert) {
+        let body = serde_json::to_vec(alert).expect("failed to serialize alert");
+        for webhook in &self.webhooks {
+            let signature = sign(&webhook.secret, &body);
+            let result = self
+                .client
+                .post(&webhook.url)
+                .header(CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, format!("sha256={signature}"))
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => info!("Sent alert for workload {} to {}", alert.target, webhook.url),
+                Err(e) => error!("Failed to send alert to {}: {e}", webhook.url),
+            }
+        }
+    }
+}
+
+fn record_metrics(name: &str, outcome: &Outcome) {
+    let result = match outcome {
+        Outcome::Valid { .. } => "success",
+        Outcome::Invalid { .. } => "failure",
+    };
+    counter!("verifier_validations_total", "target" => name.to_string(), "result" => result).increment(1);
+    match outcome {
+        Outcome::Valid { measurement_hash, .. } => {
+            gauge!("verifier_validation_success", "target" => name.to_string()).set(1);
+            gauge!("verifier_last_success_timestamp_seconds", "target" => name.to_string())
+                .set(Utc::now().timestamp() as f64);
+            gauge!(
+                "verifier_measurement_info",
+                "target" => name.to_string(),
+                "measurement_hash" => measurement_hash.clone()
+            )
+            .set(1);
+        }
+        Outcome::Invalid { .. } => gauge!("verifier_validation_success", "target" => name.to_string()).set(0),
+    }
+}
+
+/// Sign a webhook request's body, returning the hex encoded HMAC-SHA256 of it.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// The outcome of validating a workload.
+enum Outcome {
+    Valid { measurement_hash: String, tls_fingerprint: String },
+    Invalid { error: String },
+}
+
+/// What we know about a workload from previous validations.
+#[derive(Default)]
+struct TargetState {
+    healthy: Option<bool>,
+    measurement_hash: Option<String>,
+    tls_fingerprint: Option<String>,
+}
+
+impl TargetState {
+    /// Update the state using a validation outcome, returning the alerts that should be fired.
+    fn observe(&mut self, outcome: &Outcome) -> Vec<AlertKind> {
+        let mut alerts = Vec::new();
+        match outcome {
+            Outcome::Valid { measurement_hash, tls_fingerprint } => {
+                if self.healthy == Some(false) {
+                    alerts.push(AlertKind::Recovered);
+                }
+                self.healthy = Some(true);
+                if let Some(previous) = self.measurement_hash.replace(measurement_hash.clone())
+                    && &previous != measurement_hash
+                {
+                    alerts.push(AlertKind::MeasurementChanged { previous, current: measurement_hash.clone() });
+                }
+                if let Some(previous) = self.tls_fingerprint.replace(tls_fingerprint.clone())
+                    && &previous != tls_fingerprint
+                {
+                    alerts.push(AlertKind::TlsFingerprintChanged { previous, current: tls_fingerprint.clone() });
+                }
+            }
+            Outcome::Invalid { error } => {
+                // Only alert when a workload starts failing so a broken workload doesn't spam webhooks.
+                if self.healthy != Some(false) {
+                    alerts.push(AlertKind::ValidationFailed { error: error.clone() });
+                }
+                self.healthy = Some(false);
+            }
+        }
+        alerts
+    }
+}
+
+/// An alert sent to webhooks.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Alert {
+    target: String,
+    endpoint: String,
+    #[serde(flatten)]
+    kind: AlertKind,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum AlertKind {
+    ValidationFailed { error: String },
+    Recovered,
+    MeasurementChanged { previous: String, current: String },
+    TlsFingerprintChanged { previous: String, current: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid(measurement_hash: &str, tls_fingerprint: &str) -> Outcome {
+        Outcome::Valid { measurement_hash: measurement_hash.into(), tls_fingerprint: tls_fingerprint.into() }
+    }
+
+    fn invalid() -> Outcome {
+        Outcome::Invalid { error: "oops".into() }
+    }
+
+    #[test]
+    fn failures_alert_once() {
+        let mut state = TargetState::default();
+        assert_eq!(state.observe(&valid("a", "x")), &[]);
+        assert_eq!(state.observe(&invalid()), &[AlertKind::ValidationFailed { error: "oops".into() }]);
+        assert_eq!(state.observe(&invalid()), &[]);
+        assert_eq!(state.observe(&valid("a", "x")), &[AlertKind::Recovered]);
+    }
+
+    #[test]
+    fn first_validation_failure() {
+        let mut state = TargetState::default();
+        assert_eq!(state.observe(&invalid()), &[AlertKind::ValidationFailed { error: "oops".into() }]);
+    }
+
+    #[test]
+    fn changes() {
+        let mut state = TargetState::default();
+        state.observe(&valid("a", "x"));
+        assert_eq!(
+            state.observe(&valid("b", "y")),
+            &[
+                AlertKind::MeasurementChanged { previous: "a".into(), current: "b".into() },
+                AlertKind::TlsFingerprintChanged { previous: "x".into(), current: "y".into() },
+            ]
+        );
+        // A failure in between doesn't reset what we last saw.
+        state.observe(&invalid());
+        assert_eq!(
+            state.observe(&valid("b", "z")),
+            &[AlertKind::Recovered, AlertKind::TlsFingerprintChanged { previous: "y".into(), current: "z".into() }]
+        );
+    }
+
+    #[test]
+    fn alert_serialization() {
+        let alert = Alert {
+            target: "app".into(),
+            endpoint: "https://example.com".into(),
+            kind: AlertKind::MeasurementChanged { previous: "a".into(), current: "b".into() },
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+        };
+        let value = serde_json::to_value(&alert).expect("failed to serialize");
+        let expected = serde_json::json!({
+            "target": "app",
+            "endpoint": "https://example.com",
+            "kind": "measurementChanged",
+            "previous": "a",
+            "current": "b",
+            "timestamp": "1970-01-01T00:00:00Z",
+        });
+        assert_eq!(value, expected);
+    }
+}
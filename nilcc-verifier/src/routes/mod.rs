@@ -1,22 +1,43 @@
-use attestation_verification::{DefaultCertificateFetcher, ReportVerifier};
+use crate::jobs::VerificationJobs;
+use attestation_verification::{ArtifactCache, DefaultCertificateFetcher, ReportVerifier};
 use axum::Router;
 use axum::routing::{get, post};
 use axum::{Json, http::StatusCode, response::IntoResponse};
 use convert_case::{Case, Casing};
 use serde::Serialize;
 use std::{path::PathBuf, sync::Arc};
+use tokio::sync::Semaphore;
 
-mod v1;
+pub(crate) mod v1;
 
-pub(crate) fn build_router(cert_cache: PathBuf, artifacts_path: PathBuf) -> anyhow::Result<Router> {
-    let cert_fetcher = Arc::new(DefaultCertificateFetcher::new(cert_cache)?);
+pub(crate) fn build_router(
+    cert_cache: PathBuf,
+    artifact_cache: ArtifactCache,
+    max_concurrency: usize,
+    max_total_concurrency: usize,
+    kds_mirror_url: Option<String>,
+) -> anyhow::Result<Router> {
+    let mut cert_fetcher = DefaultCertificateFetcher::new(cert_cache)?;
+    if let Some(url) = kds_mirror_url {
+        cert_fetcher = cert_fetcher.with_mirror_url(url);
+    }
+    let cert_fetcher = Arc::new(cert_fetcher);
     let report_verifier = ReportVerifier::new(cert_fetcher);
-    let state = VerifyState { report_verifier, artifacts_path };
+    let state = VerifyState {
+        report_verifier,
+        artifact_cache,
+        jobs: Default::default(),
+        max_concurrency,
+        verification_permits: Arc::new(Semaphore::new(max_total_concurrency)),
+    };
     let router = Router::new().route("/health", get(|| async { StatusCode::OK })).nest(
         "/v1",
         Router::new()
             .route("/attestations/verify", post(v1::attestations::verify::handler))
             .route("/attestations/verify-amd", post(v1::attestations::verify_amd::handler))
+            .route("/attestations/verify-batch", post(v1::attestations::verify_batch::handler))
+            .route("/attestations/jobs", post(v1::attestations::jobs::create::handler))
+            .route("/attestations/jobs/{id}", get(v1::attestations::jobs::get::handler))
             .with_state(state),
     );
     Ok(router)
@@ -25,7 +46,10 @@ pub(crate) fn build_router(cert_cache: PathBuf, artifacts_path: PathBuf) -> anyh
 #[derive(Clone)]
 pub(crate) struct VerifyState {
     pub(crate) report_verifier: ReportVerifier,
-    pub(crate) artifacts_path: PathBuf,
+    pub(crate) artifact_cache: ArtifactCache,
+    pub(crate) jobs: VerificationJobs,
+    pub(crate) max_concurrency: usize,
+    pub(crate) verification_permits: Arc<Semaphore>,
 }
 
 /// An error when handling a request.
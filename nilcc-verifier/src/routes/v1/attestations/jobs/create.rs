@@ -0,0 +1,50 @@
+use crate::{
+    jobs::JOB_TIMEOUT,
+    routes::{
+        RequestHandlerError, VerifyState,
+        v1::attestations::verify_batch::{VerifyBatchRequest, verify_all},
+    },
+};
+use axum::{Json, extract::State, http::StatusCode};
+use serde::Serialize;
+use tokio::time::timeout;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// The maximum number of reports a single job can verify.
+const MAX_JOB_SIZE: usize = 10_000;
+
+#[derive(Serialize)]
+pub(crate) struct CreateJobResponse {
+    job_id: Uuid,
+}
+
+pub(crate) async fn handler(
+    state: State<VerifyState>,
+    request: Json<VerifyBatchRequest>,
+) -> Result<(StatusCode, Json<CreateJobResponse>), RequestHandlerError> {
+    let VerifyBatchRequest { reports } = request.0;
+    if reports.len() > MAX_JOB_SIZE {
+        return Err(RequestHandlerError::new(
+            StatusCode::BAD_REQUEST,
+            format!("at most {MAX_JOB_SIZE} reports can be verified in a job"),
+            "TOO_MANY_REPORTS",
+        ));
+    }
+    let job_id = state.jobs.create(reports.len());
+    info!("Verifying {} reports in job {job_id}", reports.len());
+    let state = state.0.clone();
+    tokio::spawn(async move {
+        match timeout(JOB_TIMEOUT, verify_all(&state, reports)).await {
+            Ok(results) => {
+                info!("Job {job_id} completed");
+                state.jobs.complete(job_id, results);
+            }
+            Err(_) => {
+                warn!("Job {job_id} timed out");
+                state.jobs.time_out(job_id);
+            }
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(CreateJobResponse { job_id })))
+}
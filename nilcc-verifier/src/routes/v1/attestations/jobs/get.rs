@@ -0,0 +1,18 @@
+use crate::{
+    jobs::Job,
+    routes::{RequestHandlerError, VerifyState},
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use uuid::Uuid;
+
+pub(crate) async fn handler(state: State<VerifyState>, id: Path<Uuid>) -> Result<Json<Job>, RequestHandlerError> {
+    let job = state
+        .jobs
+        .get(id.0)
+        .ok_or_else(|| RequestHandlerError::new(StatusCode::NOT_FOUND, "job not found", "JOB_NOT_FOUND"))?;
+    Ok(Json(job))
+}
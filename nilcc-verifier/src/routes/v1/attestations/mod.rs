@@ -1,2 +1,4 @@
+pub(crate) mod jobs;
 pub(crate) mod verify;
 pub(crate) mod verify_amd;
+pub(crate) mod verify_batch;
@@ -12,7 +12,7 @@ use sev::parser::ByteParser;
 use tracing::{error, warn};
 
 #[serde_as]
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub(crate) struct VerifyRequest {
     #[serde_as(as = "Hex")]
     report: Vec<u8>,
@@ -32,38 +32,54 @@ pub(crate) async fn handler(
     state: State<VerifyState>,
     request: Json<VerifyRequest>,
 ) -> Result<Json<VerifyResponse>, RequestHandlerError> {
-    let VerifyRequest { report, docker_compose_hash, nilcc_version, vcpus, vm_type } = request.0;
+    let response = verify(&state, request.0).await?;
+    Ok(Json(response))
+}
+
+/// Verify a single report.
+pub(crate) async fn verify(state: &VerifyState, request: VerifyRequest) -> Result<VerifyResponse, RequestHandlerError> {
+    let VerifyRequest { report, docker_compose_hash, nilcc_version, vcpus, vm_type } = request;
     let vm_type = vm_type.into();
     let report = AttestationReport::from_bytes(&report).map_err(|_| {
         RequestHandlerError::new(StatusCode::BAD_REQUEST, "malformed attestation report", "MALFORMED_REPORT")
     })?;
-    let artifacts_path = state.artifacts_path.join(&nilcc_version);
+    // Other requests and processes sharing the cache would otherwise see each other's partially downloaded artifacts.
+    let cached = state.artifact_cache.lock(&nilcc_version).await.map_err(|e| {
+        error!("Failed to lock cached artifacts: {e}");
+        RequestHandlerError::internal()
+    })?;
+    let artifacts_path = cached.path();
     let artifacts = ArtifactsDownloader::new(nilcc_version.clone(), vec![vm_type])
         .without_disk_images()
         .without_artifact_overwrite()
-        .download(&artifacts_path)
+        .download(artifacts_path)
         .await
         .map_err(|e| {
             warn!("Failed to download artifact version '{nilcc_version}': {e:#}");
             RequestHandlerError::internal()
         })?;
     let measurement_hash =
-        MeasurementGenerator::new(docker_compose_hash, vcpus, vm_type, &artifacts.metadata, &artifacts_path)
+        MeasurementGenerator::new(docker_compose_hash, vcpus, vm_type, &artifacts.metadata, artifacts_path)
             .generate()
             .map_err(|e| {
                 error!("Failed to generate measurement hash: {e:#}");
                 RequestHandlerError::internal()
             })?;
-    state.report_verifier.verify_report(&report, &measurement_hash).await.map_err(|e| {
-        warn!("Failed to verify report: {e:#}");
-        let error_code = ErrorCode::from(ValidateError::VerifyReports(e));
-        RequestHandlerError::new(
-            StatusCode::PRECONDITION_FAILED,
-            "report verification failed",
-            format!("{error_code:?}"),
-        )
-    })?;
+    drop(cached);
+    if let Err(e) = state.artifact_cache.evict() {
+        warn!("Failed to evict cached artifacts: {e}");
+    }
+    state.report_verifier.verify_report(&report, &measurement_hash, &artifacts.metadata.guest_policy).await.map_err(
+        |e| {
+            warn!("Failed to verify report: {e:#}");
+            let error_code = ErrorCode::from(ValidateError::VerifyReports(e));
+            RequestHandlerError::new(
+                StatusCode::PRECONDITION_FAILED,
+                "report verification failed",
+                format!("{error_code:?}"),
+            )
+        },
+    )?;
 
-    let response = VerifyResponse {};
-    Ok(Json(response))
+    Ok(VerifyResponse {})
 }
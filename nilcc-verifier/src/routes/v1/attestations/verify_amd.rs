@@ -1,6 +1,7 @@
 use crate::routes::{RequestHandlerError, VerifyState};
 use attestation_verification::{ErrorCode, ValidateError};
 use axum::{Json, extract::State, http::StatusCode};
+use nilcc_artifacts::metadata::GuestPolicy;
 use serde::{Deserialize, Serialize};
 use serde_with::hex::Hex;
 use serde_with::serde_as;
@@ -30,8 +31,10 @@ pub(crate) async fn handler(
     let report = AttestationReport::from_bytes(&report).map_err(|_| {
         RequestHandlerError::new(StatusCode::BAD_REQUEST, "malformed attestation report", "MALFORMED_REPORT")
     })?;
-    // Verify the report and pass in its own measurement hash since we don't care about its value.
-    state.report_verifier.verify_report(&report, &report.measurement).await.map_err(|e| {
+    // Verify the report and pass in its own measurement hash since we don't care about its value. There's no artifacts
+    // version to take the required guest policy from so use the default one.
+    let policy = GuestPolicy::default();
+    state.report_verifier.verify_report(&report, &report.measurement, &policy).await.map_err(|e| {
         warn!("Failed to verify report: {e:#}");
         let error_code = ErrorCode::from(ValidateError::VerifyReports(e));
         RequestHandlerError::new(
@@ -0,0 +1,148 @@
+use crate::routes::{
+    RequestHandlerError, VerifyState,
+    v1::attestations::verify::{VerifyRequest, verify},
+};
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use tokio::task::JoinSet;
+
+/// The maximum number of reports that can be verified in a single synchronous batch.
+///
+/// Larger batches should be submitted as a job instead.
+pub(crate) const MAX_BATCH_SIZE: usize = 100;
+
+#[derive(Deserialize)]
+pub(crate) struct VerifyBatchRequest {
+    pub(crate) reports: Vec<VerifyRequest>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct VerifyBatchResponse {
+    results: Vec<VerifyResult>,
+}
+
+/// The result of verifying a single report in a batch.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum VerifyResult {
+    /// The report was verified successfully.
+    Success,
+
+    /// The report failed verification.
+    Failure { message: String, error_code: String },
+}
+
+impl From<Result<(), RequestHandlerError>> for VerifyResult {
+    fn from(result: Result<(), RequestHandlerError>) -> Self {
+        match result {
+            Ok(()) => Self::Success,
+            Err(e) => Self::Failure { message: e.message, error_code: e.error_code },
+        }
+    }
+}
+
+pub(crate) async fn handler(
+    state: State<VerifyState>,
+    request: Json<VerifyBatchRequest>,
+) -> Result<Json<VerifyBatchResponse>, RequestHandlerError> {
+    let VerifyBatchRequest { reports } = request.0;
+    if reports.len() > MAX_BATCH_SIZE {
+        return Err(RequestHandlerError::new(
+            StatusCode::BAD_REQUEST,
+            format!("at most {MAX_BATCH_SIZE} reports can be verified in a batch, submit a job instead"),
+            "TOO_MANY_REPORTS",
+        ));
+    }
+    let results = verify_all(&state, reports).await;
+    Ok(Json(VerifyBatchResponse { results }))
+}
+
+/// Verify a set of reports concurrently, returning their results in the same order.
+///
+/// Reports are put in a queue that `max_concurrency` workers pull from, and every report needs a permit from the
+/// state's semaphore to be verified so the total across every batch and job is bounded as well.
+pub(crate) async fn verify_all(state: &VerifyState, reports: Vec<VerifyRequest>) -> Vec<VerifyResult> {
+    let total = reports.len();
+    let reports = Arc::new(reports);
+    let next = Arc::new(AtomicUsize::new(0));
+    let mut workers = JoinSet::new();
+    for _ in 0..state.max_concurrency.min(total) {
+        let state = state.clone();
+        let reports = reports.clone();
+        let next = next.clone();
+        workers.spawn(async move {
+            let mut results = Vec::new();
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(report) = reports.get(index) else {
+                    break;
+                };
+                let _permit = state.verification_permits.acquire().await.expect("semaphore closed");
+                let result = verify(&state, report.clone()).await.map(|_| ());
+                results.push((index, VerifyResult::from(result)));
+            }
+            results
+        });
+    }
+    let mut results = vec![VerifyResult::Success; total];
+    for (index, result) in workers.join_all().await.into_iter().flatten() {
+        results[index] = result;
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use attestation_verification::{ArtifactCache, DefaultCertificateFetcher, ReportVerifier};
+    use std::path::Path;
+    use tempfile::tempdir;
+    use tokio::sync::Semaphore;
+
+    fn make_state(dir: &Path) -> VerifyState {
+        let cert_fetcher = DefaultCertificateFetcher::new(dir.join("certs")).expect("failed to create fetcher");
+        VerifyState {
+            report_verifier: ReportVerifier::new(Arc::new(cert_fetcher)),
+            artifact_cache: ArtifactCache::new(dir.join("artifacts")),
+            jobs: Default::default(),
+            max_concurrency: 2,
+            verification_permits: Arc::new(Semaphore::new(1)),
+        }
+    }
+
+    fn make_request(report: &str) -> VerifyRequest {
+        let request = serde_json::json!({
+            "report": report,
+            "docker_compose_hash": "00".repeat(32),
+            "nilcc_version": "1.0.0",
+            "vcpus": 1,
+            "vm_type": "cpu",
+        });
+        serde_json::from_value(request).expect("invalid request")
+    }
+
+    #[tokio::test]
+    async fn malformed_reports() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let state = make_state(dir.path());
+        // These fail before anything is downloaded.
+        let reports = ["", "aa", "bbbb", "cc", "dd"].into_iter().map(make_request).collect();
+        let results = verify_all(&state, reports).await;
+        assert_eq!(results.len(), 5);
+        for result in results {
+            let VerifyResult::Failure { error_code, .. } = result else { panic!("unexpected result: {result:?}") };
+            assert_eq!(error_code, "MALFORMED_REPORT");
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_batch() {
+        let dir = tempdir().expect("failed to create tempdir");
+        let state = make_state(dir.path());
+        assert_eq!(verify_all(&state, Vec::new()).await, vec![]);
+    }
+}